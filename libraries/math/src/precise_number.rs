@@ -45,6 +45,14 @@ impl PreciseNumber {
         Self { value: zero() }
     }
 
+    /// The natural logarithm of 2, used to reduce the range of inputs to
+    /// checked_ln and checked_exp.
+    fn ln_2() -> Self {
+        Self {
+            value: InnerUint::from(693_147_180_560u128),
+        }
+    }
+
     fn one() -> Self {
         Self { value: one() }
     }
@@ -274,12 +282,13 @@ impl PreciseNumber {
 
     /// Get the power of a number, where the exponent is expressed as a fraction
     /// (numerator / denominator)
-    /// NOTE: this function is private because its accurate range and precision
-    /// have not been estbalished.
-    #[allow(dead_code)]
-    fn checked_pow_fraction(&self, exponent: &Self) -> Option<Self> {
-        assert!(self.value >= Self::min_pow_base());
-        assert!(self.value <= Self::max_pow_base());
+    /// The fractional part of the exponent uses a Taylor Series around 1, so
+    /// the base must be between 0 and 2, and results get less accurate as the
+    /// base gets further from 1.  Returns None for bases outside that range.
+    pub fn checked_pow_fraction(&self, exponent: &Self) -> Option<Self> {
+        if self.value < Self::min_pow_base() || self.value > Self::max_pow_base() {
+            return None;
+        }
         let whole_exponent = exponent.floor()?;
         let precise_whole = self.checked_pow(whole_exponent.to_imprecise()?)?;
         let (remainder_exponent, negative) = exponent.unsigned_sub(&whole_exponent);
@@ -292,6 +301,75 @@ impl PreciseNumber {
         precise_whole.checked_mul(&precise_remainder)
     }
 
+    /// Natural logarithm of a number greater than or equal to one, the only
+    /// range where the result can be represented without a sign.
+    ///
+    /// The number is first reduced to `m * 2^k` with `1 <= m < 2`, giving
+    /// `ln(x) = k * ln(2) + ln(m)`, and `ln(m)` is approximated through the
+    /// series `2 * (z + z^3 / 3 + z^5 / 5 + ...)` where `z = (m - 1) / (m + 1)`,
+    /// which converges quickly since `0 <= z < 1/3`.  Terms are added until
+    /// they vanish at the available precision.
+    pub fn checked_ln(&self) -> Option<Self> {
+        let one = Self::one();
+        if self.less_than(&one) {
+            return None;
+        }
+        let two = one.value.checked_mul(InnerUint::from(2))?;
+        let mut mantissa = self.clone();
+        let mut exponent = 0u128;
+        while mantissa.value >= two {
+            mantissa.value = mantissa.value.checked_div(InnerUint::from(2))?;
+            exponent = exponent.checked_add(1)?;
+        }
+
+        let z = mantissa
+            .checked_sub(&one)?
+            .checked_div(&mantissa.checked_add(&one)?)?;
+        let z_squared = z.checked_mul(&z)?;
+        let mut power = z.clone();
+        let mut series = z;
+        for k in 1..Self::MAX_APPROXIMATION_ITERATIONS {
+            power = power.checked_mul(&z_squared)?;
+            let term = power.checked_div(&Self::new(k.checked_mul(2)?.checked_add(1)?)?)?;
+            if term == Self::zero() {
+                break;
+            }
+            series = series.checked_add(&term)?;
+        }
+        let ln_mantissa = series.checked_add(&series)?;
+        Self::ln_2()
+            .checked_mul(&Self::new(exponent)?)?
+            .checked_add(&ln_mantissa)
+    }
+
+    /// Exponential function, `e^x`, for a non-negative number.
+    ///
+    /// The exponent is first reduced to `k * ln(2) + r` with `0 <= r < ln(2)`,
+    /// giving `e^x = 2^k * e^r`, and `e^r` is approximated using its Taylor
+    /// Series around 0, adding terms until they vanish at the available
+    /// precision.
+    pub fn checked_exp(&self) -> Option<Self> {
+        let ln_2 = Self::ln_2();
+        let exponent = self.value.checked_div(ln_2.value)?;
+        let remainder = Self {
+            value: self.value.checked_sub(ln_2.value.checked_mul(exponent)?)?,
+        };
+
+        let mut term = Self::one();
+        let mut series = Self::one();
+        for k in 1..Self::MAX_APPROXIMATION_ITERATIONS {
+            term = term.checked_mul(&remainder)?.checked_div(&Self::new(k)?)?;
+            if term == Self::zero() {
+                break;
+            }
+            series = series.checked_add(&term)?;
+        }
+        let value = series
+            .value
+            .checked_mul(InnerUint::from(2).checked_pow(exponent)?)?;
+        Some(Self { value })
+    }
+
     /// Approximate the nth root of a number using Newton's method
     /// https://en.wikipedia.org/wiki/Newton%27s_method
     /// NOTE: this function is private because its accurate range and precision
@@ -443,6 +521,89 @@ mod tests {
         ); // 8.629769290
     }
 
+    fn check_ln(number: PreciseNumber, expected: InnerUint) {
+        let precision = InnerUint::from(100); // correct to at least 10 decimal places
+        let ln = number.checked_ln().unwrap();
+        let expected = PreciseNumber { value: expected };
+        assert!(ln.almost_eq(&expected, precision));
+    }
+
+    #[test]
+    fn test_ln() {
+        check_ln(PreciseNumber::new(1).unwrap(), InnerUint::from(0));
+        check_ln(
+            PreciseNumber::new(2).unwrap(),
+            InnerUint::from(693147180560u128),
+        ); // 0.69314718056
+        check_ln(
+            PreciseNumber::new(3).unwrap(),
+            InnerUint::from(1_098612288668u128),
+        ); // 1.098612288668
+        check_ln(
+            PreciseNumber::new(1_000_000).unwrap(),
+            InnerUint::from(13_815510557964u128),
+        ); // 13.815510557964
+        check_ln(
+            PreciseNumber::new(u64::MAX as u128).unwrap(),
+            InnerUint::from(44_361419555836u128),
+        ); // 44.361419555836
+
+        // no negative results
+        let half = PreciseNumber { value: one() / 2 };
+        assert!(half.checked_ln().is_none());
+    }
+
+    fn check_exp(exponent: InnerUint, expected: PreciseNumber, precision: InnerUint) {
+        let exponent = PreciseNumber { value: exponent };
+        let exp = exponent.checked_exp().unwrap();
+        assert!(exp.almost_eq(&expected, precision));
+    }
+
+    #[test]
+    fn test_exp() {
+        let one = one();
+        let precision = InnerUint::from(100); // correct to at least 10 decimal places
+        check_exp(
+            InnerUint::from(0),
+            PreciseNumber::new(1).unwrap(),
+            precision,
+        );
+        check_exp(
+            one,
+            PreciseNumber {
+                value: InnerUint::from(2_718281828459u128),
+            },
+            precision,
+        ); // 2.718281828459
+        check_exp(
+            one / 10,
+            PreciseNumber {
+                value: InnerUint::from(1_105170918076u128),
+            },
+            precision,
+        ); // 1.105170918076
+           // large results are only accurate to about 11 significant digits
+        check_exp(
+            one * 20,
+            PreciseNumber {
+                value: InnerUint::from(485165195_409790277970u128),
+            },
+            InnerUint::from(5_000_000_000u128), // 485165195.40979
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_exp_ln_inverse(a in 1..u64::MAX) {
+            let a = PreciseNumber::new(a as u128).unwrap();
+            let round_trip = a.checked_ln().unwrap().checked_exp().unwrap();
+            // ln is accurate to about 11 decimal places, which exp turns into a
+            // relative error
+            let precision = a.value / InnerUint::from(10_000_000_000u128) + InnerUint::from(100);
+            assert!(round_trip.almost_eq(&a, precision));
+        }
+    }
+
     #[test]
     fn test_newtonian_approximation() {
         // square root
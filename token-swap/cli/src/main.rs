@@ -0,0 +1,1717 @@
+use clap::{
+    crate_description, crate_name, crate_version, value_t, value_t_or_exit, App, AppSettings, Arg,
+    ArgMatches, SubCommand,
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_clap_utils::{
+    input_parsers::pubkey_of,
+    input_validators::{is_amount, is_keypair, is_parsable, is_pubkey, is_url},
+    keypair::signer_from_path,
+};
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::RpcAccountInfoConfig,
+    rpc_config::RpcProgramAccountsConfig,
+    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+};
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{
+    account::from_account,
+    clock::Clock,
+    commitment_config::CommitmentConfig,
+    native_token::Sol,
+    signature::{Keypair, Signer},
+    system_instruction, sysvar,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+use spl_token::{
+    self, amount_to_ui_amount,
+    instruction::{initialize_account, initialize_mint, transfer},
+    native_mint,
+    state::Account as TokenAccount,
+    state::Mint as TokenMint,
+    ui_amount_to_amount,
+};
+use spl_token_swap::{
+    curve::{
+        base::{CurveType, SwapCurve},
+        calculator::TradeDirection,
+        constant_price::ConstantPriceCurve,
+        constant_product::ConstantProductCurve,
+        fees::Fees,
+        offset::OffsetCurve,
+        stable::StableCurve,
+        weighted::WeightedCurve,
+    },
+    instruction::{
+        deposit_all_token_types, deposit_single_token_type_exact_amount_in,
+        initialize as initialize_swap, swap, with_allowlist_membership, withdraw_all_token_types,
+        withdraw_single_token_type_exact_amount_out, DepositAllTokenTypes,
+        DepositSingleTokenTypeExactAmountIn, Swap, WithdrawAllTokenTypes,
+        WithdrawSingleTokenTypeExactAmountOut,
+    },
+    processor::Processor as SwapProcessor,
+    quote::{self, PoolReserves},
+    state::{AllowlistMember, SwapAllowlist, SwapV1, SwapVersion},
+};
+use std::process::exit;
+
+struct Config {
+    rpc_client: RpcClient,
+    verbose: bool,
+    owner: Box<dyn Signer>,
+    fee_payer: Box<dyn Signer>,
+    dry_run: bool,
+}
+
+type Error = Box<dyn std::error::Error>;
+type CommandResult = Result<Vec<Transaction>, Error>;
+
+// Offsets of the token mints in a two-token swap account, after the version,
+// is_initialized, nonce, token program, token A and B accounts, and pool mint
+const TOKEN_A_MINT_OFFSET: usize = 131;
+const TOKEN_B_MINT_OFFSET: usize = 163;
+const SWAP_V1_VERSION: u8 = 1;
+const SLIPPAGE_BASIS_POINTS: u128 = 10_000;
+
+macro_rules! unique_signers {
+    ($vec:ident) => {
+        $vec.sort_by_key(|l| l.pubkey());
+        $vec.dedup();
+    };
+}
+
+fn check_fee_payer_balance(config: &Config, required_balance: u64) -> Result<(), Error> {
+    let balance = config.rpc_client.get_balance(&config.fee_payer.pubkey())?;
+    if balance < required_balance {
+        Err(format!(
+            "Fee payer, {}, has insufficient balance: {} required, {} available",
+            config.fee_payer.pubkey(),
+            Sol(required_balance),
+            Sol(balance)
+        )
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn is_fraction(string: String) -> Result<(), String> {
+    parse_fraction(&string)
+        .map(|_| ())
+        .ok_or_else(|| format!("Unable to parse fraction, provided: {}", string))
+}
+
+fn parse_fraction(string: &str) -> Option<(u64, u64)> {
+    let mut parts = string.splitn(2, '/');
+    let numerator = parts.next()?.parse::<u64>().ok()?;
+    let denominator = parts.next()?.parse::<u64>().ok()?;
+    Some((numerator, denominator))
+}
+
+fn fraction_of(matches: &ArgMatches<'_>, name: &str) -> (u64, u64) {
+    matches
+        .value_of(name)
+        .and_then(parse_fraction)
+        .unwrap_or((0, 0))
+}
+
+fn is_slippage(string: String) -> Result<(), String> {
+    match string.parse::<f64>() {
+        Ok(slippage) if (0.0..=100.0).contains(&slippage) => Ok(()),
+        _ => Err(format!(
+            "Slippage must be a percentage between 0 and 100, provided: {}",
+            string
+        )),
+    }
+}
+
+/// Applies the slippage, in percent, to an expected amount, lowering it for
+/// minimum amounts and raising it for maximum amounts
+fn with_slippage(amount: u128, slippage: f64, raise: bool) -> Result<u64, Error> {
+    let basis_points = (slippage * 100.0).round() as u128;
+    let amount = if raise {
+        amount
+            .checked_mul(SLIPPAGE_BASIS_POINTS + basis_points)
+            .map(|amount| (amount + SLIPPAGE_BASIS_POINTS - 1) / SLIPPAGE_BASIS_POINTS)
+    } else {
+        amount
+            .checked_mul(SLIPPAGE_BASIS_POINTS.saturating_sub(basis_points))
+            .map(|amount| amount / SLIPPAGE_BASIS_POINTS)
+    };
+    amount
+        .filter(|amount| *amount <= u64::MAX as u128)
+        .map(|amount| amount as u64)
+        .ok_or_else(|| "Amount overflow".into())
+}
+
+fn get_token_account(config: &Config, account: &Pubkey) -> Result<TokenAccount, Error> {
+    let account_data = config.rpc_client.get_account_data(account)?;
+    TokenAccount::unpack(&account_data)
+        .map_err(|_| format!("{} is not a token account", account).into())
+}
+
+fn get_mint(config: &Config, mint: &Pubkey) -> Result<TokenMint, Error> {
+    let mint_data = config.rpc_client.get_account_data(mint)?;
+    TokenMint::unpack(&mint_data).map_err(|_| format!("{} is not a token mint", mint).into())
+}
+
+/// Returns the given token account, or the owner's associated token account
+/// for the mint, creating it if needed
+fn unwrap_create_token_account(
+    config: &Config,
+    token_optional: &Option<Pubkey>,
+    mint: &Pubkey,
+    instructions: &mut Vec<Instruction>,
+    total_rent_free_balances: &mut u64,
+) -> Result<Pubkey, Error> {
+    if let Some(account) = token_optional {
+        return Ok(*account);
+    }
+    let account = get_associated_token_address(&config.owner.pubkey(), mint);
+    if config
+        .rpc_client
+        .get_account_with_commitment(&account, config.rpc_client.commitment())?
+        .value
+        .is_none()
+    {
+        println!("Creating account to receive tokens {}", account);
+        instructions.push(create_associated_token_account(
+            &config.fee_payer.pubkey(),
+            &config.owner.pubkey(),
+            mint,
+        ));
+        *total_rent_free_balances += config
+            .rpc_client
+            .get_minimum_balance_for_rent_exemption(TokenAccount::LEN)?;
+    }
+    Ok(account)
+}
+
+/// Returns the given token account, or the owner's associated token account
+/// for the mint
+fn unwrap_token_account(config: &Config, token_optional: &Option<Pubkey>, mint: &Pubkey) -> Pubkey {
+    token_optional.unwrap_or_else(|| get_associated_token_address(&config.owner.pubkey(), mint))
+}
+
+fn sign_transaction(
+    config: &Config,
+    instructions: &[Instruction],
+    mut signers: Vec<&dyn Signer>,
+    total_rent_free_balances: u64,
+) -> CommandResult {
+    let mut transaction =
+        Transaction::new_with_payer(instructions, Some(&config.fee_payer.pubkey()));
+
+    let (recent_blockhash, fee_calculator) = config.rpc_client.get_recent_blockhash()?;
+    check_fee_payer_balance(
+        config,
+        total_rent_free_balances + fee_calculator.calculate_fee(&transaction.message()),
+    )?;
+    unique_signers!(signers);
+    transaction.sign(&signers, recent_blockhash);
+    Ok(vec![transaction])
+}
+
+/// A two-token swap, with the balances of its accounts
+struct Pool {
+    swap: SwapV1,
+    authority: Pubkey,
+    /// Fees charged at the current slot, with the dynamic trade fee if any
+    fees: Fees,
+    /// Allowlist of the pool, if deposits require a membership
+    allowlist: Option<SwapAllowlist>,
+    reserves: PoolReserves,
+    token_a_decimals: u8,
+    token_b_decimals: u8,
+    pool_mint_decimals: u8,
+}
+
+impl Pool {
+    /// Direction of a trade providing the given mint to the swap
+    fn trade_direction(&self, mint: &Pubkey) -> Result<TradeDirection, Error> {
+        if *mint == self.swap.token_a_mint {
+            Ok(TradeDirection::AtoB)
+        } else if *mint == self.swap.token_b_mint {
+            Ok(TradeDirection::BtoA)
+        } else {
+            Err(format!("Mint {} is not traded by the pool", mint).into())
+        }
+    }
+
+    /// Swap token account, mint and decimals of the side of the pool given
+    /// by the trade direction, token A for `AtoB`
+    fn source_side(&self, trade_direction: TradeDirection) -> (Pubkey, Pubkey, u8) {
+        match trade_direction {
+            TradeDirection::AtoB => (
+                self.swap.token_a,
+                self.swap.token_a_mint,
+                self.token_a_decimals,
+            ),
+            TradeDirection::BtoA => (
+                self.swap.token_b,
+                self.swap.token_b_mint,
+                self.token_b_decimals,
+            ),
+        }
+    }
+
+    /// Adds the allowlist membership of the owner to a deposit, or to a swap
+    /// if the pool gates swaps
+    fn with_membership(
+        &self,
+        config: &Config,
+        pool: &Pubkey,
+        instruction: Instruction,
+        is_swap: bool,
+    ) -> Result<Instruction, Error> {
+        match self.allowlist {
+            Some(allowlist) if !is_swap || allowlist.gate_swaps => {
+                let membership = get_allowlist_membership(config, pool, &config.owner.pubkey())?;
+                Ok(with_allowlist_membership(instruction, &membership))
+            }
+            _ => Ok(instruction),
+        }
+    }
+}
+
+/// Finds the account holding the membership of a user in the allowlist of a
+/// pool
+fn get_allowlist_membership(
+    config: &Config,
+    pool: &Pubkey,
+    member: &Pubkey,
+) -> Result<Pubkey, Error> {
+    let memberships = config.rpc_client.get_program_accounts_with_config(
+        &spl_token_swap::id(),
+        RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(AllowlistMember::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 0,
+                    bytes: MemcmpEncodedBytes::Binary(bs58::encode([1]).into_string()),
+                    encoding: None,
+                }),
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 1,
+                    bytes: MemcmpEncodedBytes::Binary(bs58::encode(pool.to_bytes()).into_string()),
+                    encoding: None,
+                }),
+                RpcFilterType::Memcmp(Memcmp {
+                    offset: 33,
+                    bytes: MemcmpEncodedBytes::Binary(
+                        bs58::encode(member.to_bytes()).into_string(),
+                    ),
+                    encoding: None,
+                }),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+        },
+    )?;
+    memberships
+        .first()
+        .map(|(pubkey, _)| *pubkey)
+        .ok_or_else(|| format!("{} is not on the allowlist of pool {}", member, pool).into())
+}
+
+fn get_pool(config: &Config, pool: &Pubkey) -> Result<Pool, Error> {
+    let pool_data = config.rpc_client.get_account_data(pool)?;
+    let mut swap = SwapVersion::unpack_swap_v1(&pool_data)
+        .map_err(|_| format!("{} is not a two-token swap", pool))?;
+    // quotes use the amplifier of a ramping stable curve at the cluster time
+    if swap.swap_curve.curve_type == CurveType::Stable {
+        let clock: Clock = from_account(&config.rpc_client.get_account(&sysvar::clock::id())?)
+            .ok_or("Clock sysvar could not be read")?;
+        swap.swap_curve.fix_at(clock.unix_timestamp)?;
+    }
+    let authority = SwapProcessor::authority_id(&spl_token_swap::id(), pool, swap.nonce)?;
+
+    let token_a = get_token_account(config, &swap.token_a)?;
+    let token_b = get_token_account(config, &swap.token_b)?;
+    let token_a_mint = get_mint(config, &swap.token_a_mint)?;
+    let token_b_mint = get_mint(config, &swap.token_b_mint)?;
+    let pool_mint = get_mint(config, &swap.pool_mint)?;
+    let fees = match SwapVersion::unpack_dynamic_fee(&pool_data)? {
+        Some(dynamic_fee) if dynamic_fee.is_enabled() => dynamic_fee
+            .decay(config.rpc_client.get_slot()?)
+            .fees(&swap.fees),
+        _ => swap.fees.clone(),
+    };
+    let allowlist =
+        SwapVersion::unpack_allowlist(&pool_data)?.filter(|allowlist| allowlist.is_enabled());
+    Ok(Pool {
+        authority,
+        fees,
+        allowlist,
+        reserves: PoolReserves {
+            token_a_amount: token_a.amount as u128,
+            token_b_amount: token_b.amount as u128,
+            pool_supply: pool_mint.supply as u128,
+        },
+        token_a_decimals: token_a_mint.decimals,
+        token_b_decimals: token_b_mint.decimals,
+        pool_mint_decimals: pool_mint.decimals,
+        swap,
+    })
+}
+
+struct CreatePoolArgs {
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    token_a_amount: f64,
+    token_b_amount: f64,
+    token_a_source: Option<Pubkey>,
+    token_b_source: Option<Pubkey>,
+    fees: Fees,
+    swap_curve: SwapCurve,
+    fee_owner: Option<Pubkey>,
+    admin: Option<Box<dyn Signer>>,
+}
+
+fn command_create_pool(config: &Config, args: CreatePoolArgs) -> CommandResult {
+    let swap_account = Keypair::new();
+    println!("Creating swap {}", swap_account.pubkey());
+
+    let pool_mint = Keypair::new();
+    println!("Creating pool mint {}", pool_mint.pubkey());
+
+    let token_a = Keypair::new();
+    println!("Creating token A account {}", token_a.pubkey());
+
+    let token_b = Keypair::new();
+    println!("Creating token B account {}", token_b.pubkey());
+
+    let pool_fee_account = Keypair::new();
+    println!(
+        "Creating pool fee collection account {}",
+        pool_fee_account.pubkey()
+    );
+
+    // Calculate the swap authority, which owns the swap token accounts and
+    // mints pool tokens
+    let (authority, nonce) = Pubkey::find_program_address(
+        &[&swap_account.pubkey().to_bytes()[..]],
+        &spl_token_swap::id(),
+    );
+    if config.verbose {
+        println!("Swap authority {}", authority);
+    }
+
+    let token_a_decimals = get_mint(config, &args.token_a_mint)?.decimals;
+    let token_b_decimals = get_mint(config, &args.token_b_mint)?.decimals;
+    let token_a_source = unwrap_token_account(config, &args.token_a_source, &args.token_a_mint);
+    let token_b_source = unwrap_token_account(config, &args.token_b_source, &args.token_b_mint);
+    let destination = get_associated_token_address(&config.owner.pubkey(), &pool_mint.pubkey());
+    let fee_owner = args.fee_owner.unwrap_or_else(|| config.owner.pubkey());
+    let admin = args.admin.as_ref().map(|admin| admin.pubkey());
+
+    let mint_balance = config
+        .rpc_client
+        .get_minimum_balance_for_rent_exemption(TokenMint::LEN)?;
+    let token_account_balance = config
+        .rpc_client
+        .get_minimum_balance_for_rent_exemption(TokenAccount::LEN)?;
+    let swap_balance = config
+        .rpc_client
+        .get_minimum_balance_for_rent_exemption(SwapVersion::LATEST_LEN)?;
+    let total_rent_free_balances = mint_balance + 4 * token_account_balance + swap_balance;
+
+    // The accounts of the swap do not fit in a single transaction, so the pool
+    // mint and token accounts are set up first.  The initial liquidity is only
+    // transferred along with the initialization of the swap, so that it stays
+    // with the owner if the swap cannot be initialized.
+    let mut setup_transaction = Transaction::new_with_payer(
+        &[
+            // Account for the pool mint
+            system_instruction::create_account(
+                &config.fee_payer.pubkey(),
+                &pool_mint.pubkey(),
+                mint_balance,
+                TokenMint::LEN as u64,
+                &spl_token::id(),
+            ),
+            // Initialize the pool mint, minted by the swap authority
+            initialize_mint(
+                &spl_token::id(),
+                &pool_mint.pubkey(),
+                &authority,
+                None,
+                native_mint::DECIMALS,
+            )?,
+            // Accounts for the tokens held by the swap
+            system_instruction::create_account(
+                &config.fee_payer.pubkey(),
+                &token_a.pubkey(),
+                token_account_balance,
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_account(
+                &spl_token::id(),
+                &token_a.pubkey(),
+                &args.token_a_mint,
+                &authority,
+            )?,
+            system_instruction::create_account(
+                &config.fee_payer.pubkey(),
+                &token_b.pubkey(),
+                token_account_balance,
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_account(
+                &spl_token::id(),
+                &token_b.pubkey(),
+                &args.token_b_mint,
+                &authority,
+            )?,
+            // Account for the pool fee accumulation
+            system_instruction::create_account(
+                &config.fee_payer.pubkey(),
+                &pool_fee_account.pubkey(),
+                token_account_balance,
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_account(
+                &spl_token::id(),
+                &pool_fee_account.pubkey(),
+                &pool_mint.pubkey(),
+                &fee_owner,
+            )?,
+        ],
+        Some(&config.fee_payer.pubkey()),
+    );
+
+    let mut initialize_transaction = Transaction::new_with_payer(
+        &[
+            // Account to receive the initial pool tokens
+            create_associated_token_account(
+                &config.fee_payer.pubkey(),
+                &config.owner.pubkey(),
+                &pool_mint.pubkey(),
+            ),
+            // Account for the swap
+            system_instruction::create_account(
+                &config.fee_payer.pubkey(),
+                &swap_account.pubkey(),
+                swap_balance,
+                SwapVersion::LATEST_LEN as u64,
+                &spl_token_swap::id(),
+            ),
+            // Initial liquidity
+            transfer(
+                &spl_token::id(),
+                &token_a_source,
+                &token_a.pubkey(),
+                &config.owner.pubkey(),
+                &[],
+                ui_amount_to_amount(args.token_a_amount, token_a_decimals),
+            )?,
+            transfer(
+                &spl_token::id(),
+                &token_b_source,
+                &token_b.pubkey(),
+                &config.owner.pubkey(),
+                &[],
+                ui_amount_to_amount(args.token_b_amount, token_b_decimals),
+            )?,
+            initialize_swap(
+                &spl_token_swap::id(),
+                &spl_token::id(),
+                &swap_account.pubkey(),
+                &authority,
+                &token_a.pubkey(),
+                &token_b.pubkey(),
+                &pool_mint.pubkey(),
+                &pool_fee_account.pubkey(),
+                &destination,
+                admin.as_ref(),
+                nonce,
+                args.fees,
+                args.swap_curve,
+            )?,
+        ],
+        Some(&config.fee_payer.pubkey()),
+    );
+
+    let (recent_blockhash, fee_calculator) = config.rpc_client.get_recent_blockhash()?;
+    check_fee_payer_balance(
+        config,
+        total_rent_free_balances
+            + fee_calculator.calculate_fee(&setup_transaction.message())
+            + fee_calculator.calculate_fee(&initialize_transaction.message()),
+    )?;
+    let setup_signers = vec![
+        config.fee_payer.as_ref(),
+        &pool_mint as &dyn Signer,
+        &token_a as &dyn Signer,
+        &token_b as &dyn Signer,
+        &pool_fee_account as &dyn Signer,
+    ];
+    setup_transaction.sign(&setup_signers, recent_blockhash);
+    let mut initialize_signers = vec![
+        config.fee_payer.as_ref(),
+        config.owner.as_ref(),
+        &swap_account as &dyn Signer,
+    ];
+    if let Some(admin) = args.admin.as_ref() {
+        initialize_signers.push(admin.as_ref());
+    }
+    unique_signers!(initialize_signers);
+    initialize_transaction.sign(&initialize_signers, recent_blockhash);
+    Ok(vec![setup_transaction, initialize_transaction])
+}
+
+fn command_swap(
+    config: &Config,
+    pool: &Pubkey,
+    input_mint: &Pubkey,
+    ui_amount: f64,
+    slippage: f64,
+    source: &Option<Pubkey>,
+    destination: &Option<Pubkey>,
+) -> CommandResult {
+    let pool_data = get_pool(config, pool)?;
+    let trade_direction = pool_data.trade_direction(input_mint)?;
+    let (swap_source, _, source_decimals) = pool_data.source_side(trade_direction);
+    let (swap_destination, destination_mint, destination_decimals) =
+        pool_data.source_side(trade_direction.opposite());
+
+    let amount_in = ui_amount_to_amount(ui_amount, source_decimals);
+    let quote = quote::swap(
+        &pool_data.swap.swap_curve,
+        &pool_data.fees,
+        &pool_data.reserves,
+        amount_in as u128,
+        trade_direction,
+    )
+    .ok_or("Unable to swap this amount")?;
+    let amount_out = match trade_direction {
+        TradeDirection::AtoB => quote.token_b_amount,
+        TradeDirection::BtoA => quote.token_a_amount,
+    };
+    let minimum_amount_out = with_slippage(amount_out, slippage, false)?;
+    println!(
+        "Swapping {} for at least {} of {}",
+        ui_amount,
+        amount_to_ui_amount(minimum_amount_out, destination_decimals),
+        destination_mint
+    );
+    if config.verbose {
+        println!(
+            "Expected amount out: {}, trade fee: {}, owner fee: {}",
+            amount_out, quote.trade_fee, quote.owner_fee
+        );
+    }
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut total_rent_free_balances: u64 = 0;
+    let source = unwrap_token_account(config, source, input_mint);
+    let destination = unwrap_create_token_account(
+        config,
+        destination,
+        &destination_mint,
+        &mut instructions,
+        &mut total_rent_free_balances,
+    )?;
+    instructions.push(pool_data.with_membership(
+        config,
+        pool,
+        swap(
+            &spl_token_swap::id(),
+            &spl_token::id(),
+            pool,
+            &pool_data.authority,
+            &config.owner.pubkey(),
+            &source,
+            &swap_source,
+            &swap_destination,
+            &destination,
+            &pool_data.swap.pool_mint,
+            &pool_data.swap.pool_fee_account,
+            None,
+            Swap {
+                amount_in,
+                minimum_amount_out,
+            },
+        )?,
+        true,
+    )?);
+
+    sign_transaction(
+        config,
+        &instructions,
+        vec![config.fee_payer.as_ref(), config.owner.as_ref()],
+        total_rent_free_balances,
+    )
+}
+
+fn command_deposit(
+    config: &Config,
+    pool: &Pubkey,
+    ui_pool_token_amount: f64,
+    slippage: f64,
+    token_a: &Option<Pubkey>,
+    token_b: &Option<Pubkey>,
+    token_receiver: &Option<Pubkey>,
+) -> CommandResult {
+    let pool_data = get_pool(config, pool)?;
+    let pool_token_amount = ui_amount_to_amount(ui_pool_token_amount, pool_data.pool_mint_decimals);
+    let quote = quote::deposit_all_token_types(
+        &pool_data.swap.swap_curve,
+        &pool_data.reserves,
+        pool_token_amount as u128,
+    )
+    .ok_or("Unable to deposit this amount")?;
+    let maximum_token_a_amount = with_slippage(quote.token_a_amount, slippage, true)?;
+    let maximum_token_b_amount = with_slippage(quote.token_b_amount, slippage, true)?;
+    println!(
+        "Depositing at most {} of token A and {} of token B for {} pool tokens",
+        amount_to_ui_amount(maximum_token_a_amount, pool_data.token_a_decimals),
+        amount_to_ui_amount(maximum_token_b_amount, pool_data.token_b_decimals),
+        ui_pool_token_amount
+    );
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut total_rent_free_balances: u64 = 0;
+    let token_a = unwrap_token_account(config, token_a, &pool_data.swap.token_a_mint);
+    let token_b = unwrap_token_account(config, token_b, &pool_data.swap.token_b_mint);
+    let token_receiver = unwrap_create_token_account(
+        config,
+        token_receiver,
+        &pool_data.swap.pool_mint,
+        &mut instructions,
+        &mut total_rent_free_balances,
+    )?;
+    instructions.push(pool_data.with_membership(
+        config,
+        pool,
+        deposit_all_token_types(
+            &spl_token_swap::id(),
+            &spl_token::id(),
+            pool,
+            &pool_data.authority,
+            &config.owner.pubkey(),
+            &token_a,
+            &token_b,
+            &pool_data.swap.token_a,
+            &pool_data.swap.token_b,
+            &pool_data.swap.pool_mint,
+            &token_receiver,
+            DepositAllTokenTypes {
+                pool_token_amount,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            },
+        )?,
+        false,
+    )?);
+
+    sign_transaction(
+        config,
+        &instructions,
+        vec![config.fee_payer.as_ref(), config.owner.as_ref()],
+        total_rent_free_balances,
+    )
+}
+
+fn command_deposit_single(
+    config: &Config,
+    pool: &Pubkey,
+    input_mint: &Pubkey,
+    ui_amount: f64,
+    slippage: f64,
+    source: &Option<Pubkey>,
+    token_receiver: &Option<Pubkey>,
+) -> CommandResult {
+    let pool_data = get_pool(config, pool)?;
+    let trade_direction = pool_data.trade_direction(input_mint)?;
+    let (_, _, source_decimals) = pool_data.source_side(trade_direction);
+    let source_token_amount = ui_amount_to_amount(ui_amount, source_decimals);
+    let quote = quote::deposit_single_token_type_exact_amount_in(
+        &pool_data.swap.swap_curve,
+        &pool_data.fees,
+        &pool_data.reserves,
+        source_token_amount as u128,
+        trade_direction,
+    )
+    .ok_or("Unable to deposit this amount")?;
+    let minimum_pool_token_amount = with_slippage(quote.pool_token_amount, slippage, false)?;
+    println!(
+        "Depositing {} for at least {} pool tokens",
+        ui_amount,
+        amount_to_ui_amount(minimum_pool_token_amount, pool_data.pool_mint_decimals)
+    );
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut total_rent_free_balances: u64 = 0;
+    let source = unwrap_token_account(config, source, input_mint);
+    let token_receiver = unwrap_create_token_account(
+        config,
+        token_receiver,
+        &pool_data.swap.pool_mint,
+        &mut instructions,
+        &mut total_rent_free_balances,
+    )?;
+    instructions.push(pool_data.with_membership(
+        config,
+        pool,
+        deposit_single_token_type_exact_amount_in(
+            &spl_token_swap::id(),
+            &spl_token::id(),
+            pool,
+            &pool_data.authority,
+            &config.owner.pubkey(),
+            &source,
+            &pool_data.swap.token_a,
+            &pool_data.swap.token_b,
+            &pool_data.swap.pool_mint,
+            &token_receiver,
+            DepositSingleTokenTypeExactAmountIn {
+                source_token_amount,
+                minimum_pool_token_amount,
+            },
+        )?,
+        false,
+    )?);
+
+    sign_transaction(
+        config,
+        &instructions,
+        vec![config.fee_payer.as_ref(), config.owner.as_ref()],
+        total_rent_free_balances,
+    )
+}
+
+fn command_withdraw(
+    config: &Config,
+    pool: &Pubkey,
+    ui_pool_token_amount: f64,
+    slippage: f64,
+    withdraw_from: &Option<Pubkey>,
+    token_a: &Option<Pubkey>,
+    token_b: &Option<Pubkey>,
+) -> CommandResult {
+    let pool_data = get_pool(config, pool)?;
+    let pool_token_amount = ui_amount_to_amount(ui_pool_token_amount, pool_data.pool_mint_decimals);
+    let quote = quote::withdraw_all_token_types(
+        &pool_data.swap.swap_curve,
+        &pool_data.fees,
+        &pool_data.reserves,
+        pool_token_amount as u128,
+    )
+    .ok_or("Unable to withdraw this amount")?;
+    let minimum_token_a_amount = with_slippage(quote.token_a_amount, slippage, false)?;
+    let minimum_token_b_amount = with_slippage(quote.token_b_amount, slippage, false)?;
+    println!(
+        "Withdrawing {} pool tokens for at least {} of token A and {} of token B",
+        ui_pool_token_amount,
+        amount_to_ui_amount(minimum_token_a_amount, pool_data.token_a_decimals),
+        amount_to_ui_amount(minimum_token_b_amount, pool_data.token_b_decimals)
+    );
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut total_rent_free_balances: u64 = 0;
+    let withdraw_from = unwrap_token_account(config, withdraw_from, &pool_data.swap.pool_mint);
+    let token_a = unwrap_create_token_account(
+        config,
+        token_a,
+        &pool_data.swap.token_a_mint,
+        &mut instructions,
+        &mut total_rent_free_balances,
+    )?;
+    let token_b = unwrap_create_token_account(
+        config,
+        token_b,
+        &pool_data.swap.token_b_mint,
+        &mut instructions,
+        &mut total_rent_free_balances,
+    )?;
+    instructions.push(withdraw_all_token_types(
+        &spl_token_swap::id(),
+        &spl_token::id(),
+        pool,
+        &pool_data.authority,
+        &config.owner.pubkey(),
+        &pool_data.swap.pool_mint,
+        &pool_data.swap.pool_fee_account,
+        &withdraw_from,
+        &pool_data.swap.token_a,
+        &pool_data.swap.token_b,
+        &token_a,
+        &token_b,
+        WithdrawAllTokenTypes {
+            pool_token_amount,
+            minimum_token_a_amount,
+            minimum_token_b_amount,
+        },
+    )?);
+
+    sign_transaction(
+        config,
+        &instructions,
+        vec![config.fee_payer.as_ref(), config.owner.as_ref()],
+        total_rent_free_balances,
+    )
+}
+
+fn command_withdraw_single(
+    config: &Config,
+    pool: &Pubkey,
+    output_mint: &Pubkey,
+    ui_amount: f64,
+    slippage: f64,
+    withdraw_from: &Option<Pubkey>,
+    destination: &Option<Pubkey>,
+) -> CommandResult {
+    let pool_data = get_pool(config, pool)?;
+    let trade_direction = pool_data.trade_direction(output_mint)?;
+    let (_, _, destination_decimals) = pool_data.source_side(trade_direction);
+    let destination_token_amount = ui_amount_to_amount(ui_amount, destination_decimals);
+    let quote = quote::withdraw_single_token_type_exact_amount_out(
+        &pool_data.swap.swap_curve,
+        &pool_data.fees,
+        &pool_data.reserves,
+        destination_token_amount as u128,
+        trade_direction,
+    )
+    .ok_or("Unable to withdraw this amount")?;
+    let maximum_pool_token_amount = with_slippage(quote.pool_token_amount, slippage, true)?;
+    println!(
+        "Withdrawing {} for at most {} pool tokens",
+        ui_amount,
+        amount_to_ui_amount(maximum_pool_token_amount, pool_data.pool_mint_decimals)
+    );
+
+    let mut instructions: Vec<Instruction> = vec![];
+    let mut total_rent_free_balances: u64 = 0;
+    let withdraw_from = unwrap_token_account(config, withdraw_from, &pool_data.swap.pool_mint);
+    let destination = unwrap_create_token_account(
+        config,
+        destination,
+        output_mint,
+        &mut instructions,
+        &mut total_rent_free_balances,
+    )?;
+    instructions.push(withdraw_single_token_type_exact_amount_out(
+        &spl_token_swap::id(),
+        &spl_token::id(),
+        pool,
+        &pool_data.authority,
+        &config.owner.pubkey(),
+        &pool_data.swap.pool_mint,
+        &pool_data.swap.pool_fee_account,
+        &withdraw_from,
+        &pool_data.swap.token_a,
+        &pool_data.swap.token_b,
+        &destination,
+        WithdrawSingleTokenTypeExactAmountOut {
+            destination_token_amount,
+            maximum_pool_token_amount,
+        },
+    )?);
+
+    sign_transaction(
+        config,
+        &instructions,
+        vec![config.fee_payer.as_ref(), config.owner.as_ref()],
+        total_rent_free_balances,
+    )
+}
+
+fn command_list(config: &Config, mint: &Pubkey) -> CommandResult {
+    let mut pools = vec![];
+    for offset in [TOKEN_A_MINT_OFFSET, TOKEN_B_MINT_OFFSET].iter() {
+        pools.extend(config.rpc_client.get_program_accounts_with_config(
+            &spl_token_swap::id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::Memcmp(Memcmp {
+                        offset: 0,
+                        bytes: MemcmpEncodedBytes::Binary(
+                            bs58::encode([SWAP_V1_VERSION]).into_string(),
+                        ),
+                        encoding: None,
+                    }),
+                    RpcFilterType::Memcmp(Memcmp {
+                        offset: *offset,
+                        bytes: MemcmpEncodedBytes::Binary(
+                            bs58::encode(mint.to_bytes()).into_string(),
+                        ),
+                        encoding: None,
+                    }),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+            },
+        )?);
+    }
+
+    if pools.is_empty() {
+        return Err(format!("No pools found for mint {}.", mint).into());
+    }
+
+    for (pubkey, account) in pools {
+        let swap = SwapVersion::unpack_swap_v1(&account.data)?;
+        println!(
+            "Pool: {}\tToken A: {}\tToken B: {}\tCurve: {:?}",
+            pubkey, swap.token_a_mint, swap.token_b_mint, swap.swap_curve.curve_type
+        );
+    }
+
+    Ok(vec![])
+}
+
+fn command_show(config: &Config, pool: &Pubkey) -> CommandResult {
+    let pool_data = get_pool(config, pool)?;
+    let swap = &pool_data.swap;
+    println!("Swap: {}", pool);
+    println!("Authority: {}", pool_data.authority);
+    println!("Token program: {}", swap.token_program_id);
+    println!(
+        "Token A: {}\tMint: {}\tBalance: {}",
+        swap.token_a,
+        swap.token_a_mint,
+        amount_to_ui_amount(
+            pool_data.reserves.token_a_amount as u64,
+            pool_data.token_a_decimals
+        )
+    );
+    println!(
+        "Token B: {}\tMint: {}\tBalance: {}",
+        swap.token_b,
+        swap.token_b_mint,
+        amount_to_ui_amount(
+            pool_data.reserves.token_b_amount as u64,
+            pool_data.token_b_decimals
+        )
+    );
+    println!(
+        "Pool mint: {}\tSupply: {}",
+        swap.pool_mint,
+        amount_to_ui_amount(
+            pool_data.reserves.pool_supply as u64,
+            pool_data.pool_mint_decimals
+        )
+    );
+    println!("Pool fee account: {}", swap.pool_fee_account);
+    let fees = &swap.fees;
+    println!(
+        "Fees: trade {}/{}, owner trade {}/{}, owner withdraw {}/{}, host {}/{}",
+        fees.trade_fee_numerator,
+        fees.trade_fee_denominator,
+        fees.owner_trade_fee_numerator,
+        fees.owner_trade_fee_denominator,
+        fees.owner_withdraw_fee_numerator,
+        fees.owner_withdraw_fee_denominator,
+        fees.host_fee_numerator,
+        fees.host_fee_denominator
+    );
+    println!("Curve: {:?}", swap.swap_curve.calculator);
+
+    let pool_account_data = config.rpc_client.get_account_data(pool)?;
+    if let Some(admin) = SwapVersion::unpack_admin(&pool_account_data)? {
+        println!("Admin: {}", admin.admin_authority);
+    }
+    if let Some(status) = SwapVersion::unpack_pool_status(&pool_account_data)? {
+        println!("Status: {:?}", status);
+    }
+    if let Some(dynamic_fee) = SwapVersion::unpack_dynamic_fee(&pool_account_data)? {
+        if dynamic_fee.is_enabled() {
+            println!(
+                "Dynamic trade fee: {} to {}/{}, maximum at {} bps of volatility, decaying over {} slots",
+                dynamic_fee.min_trade_fee_numerator,
+                dynamic_fee.max_trade_fee_numerator,
+                fees.trade_fee_denominator,
+                dynamic_fee.max_volatility,
+                dynamic_fee.volatility_period
+            );
+            println!(
+                "Current trade fee: {}/{}",
+                pool_data.fees.trade_fee_numerator, pool_data.fees.trade_fee_denominator
+            );
+        }
+    }
+    if let Some(allowlist) = pool_data.allowlist {
+        println!(
+            "Allowlist authority: {}, gating {}",
+            allowlist.allowlist_authority,
+            if allowlist.gate_swaps {
+                "deposits and swaps"
+            } else {
+                "deposits"
+            }
+        );
+    }
+    if config.verbose {
+        if let Some(accumulator) = SwapVersion::unpack_price_accumulator(&pool_account_data)? {
+            println!(
+                "Price A cumulative: {}\tPrice B cumulative: {}\tLast update slot: {}",
+                accumulator.price_a_cumulative,
+                accumulator.price_b_cumulative,
+                accumulator.last_update_slot
+            );
+        }
+    }
+
+    Ok(vec![])
+}
+
+fn swap_curve_of(matches: &ArgMatches<'_>) -> SwapCurve {
+    match matches.value_of("curve_type").unwrap() {
+        "constant-price" => SwapCurve {
+            curve_type: CurveType::ConstantPrice,
+            calculator: Box::new(ConstantPriceCurve {
+                token_b_price: value_t_or_exit!(matches, "token_b_price", u64),
+            }),
+        },
+        "stable" => SwapCurve {
+            curve_type: CurveType::Stable,
+            calculator: Box::new(StableCurve::new(value_t_or_exit!(matches, "amp", u64))),
+        },
+        "offset" => SwapCurve {
+            curve_type: CurveType::Offset,
+            calculator: Box::new(OffsetCurve {
+                token_b_offset: value_t_or_exit!(matches, "token_b_offset", u64),
+            }),
+        },
+        "weighted" => SwapCurve {
+            curve_type: CurveType::Weighted,
+            calculator: Box::new(WeightedCurve {
+                token_a_weight: value_t_or_exit!(matches, "token_a_weight", u64),
+                token_b_weight: value_t_or_exit!(matches, "token_b_weight", u64),
+            }),
+        },
+        _ => SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        },
+    }
+}
+
+fn main() {
+    let pool_arg = Arg::with_name("pool")
+        .long("pool")
+        .validator(is_pubkey)
+        .value_name("ADDRESS")
+        .takes_value(true)
+        .required(true)
+        .help("Swap address");
+    let slippage_arg = Arg::with_name("slippage")
+        .long("slippage")
+        .validator(is_slippage)
+        .value_name("PERCENT")
+        .takes_value(true)
+        .default_value("1")
+        .help("Largest change from the quoted amounts allowed, in percent.");
+    let fee_arg = |name: &'static str, long: &'static str, help: &'static str| {
+        Arg::with_name(name)
+            .long(long)
+            .validator(is_fraction)
+            .value_name("NUMERATOR/DENOMINATOR")
+            .takes_value(true)
+            .help(help)
+    };
+
+    let matches = App::new(crate_name!())
+        .about(crate_description!())
+        .version(crate_version!())
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg({
+            let arg = Arg::with_name("config_file")
+                .short("C")
+                .long("config")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .help("Configuration file to use");
+            if let Some(ref config_file) = *solana_cli_config::CONFIG_FILE {
+                arg.default_value(&config_file)
+            } else {
+                arg
+            }
+        })
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .takes_value(false)
+                .global(true)
+                .help("Show additional information"),
+        )
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .takes_value(false)
+                .global(true)
+                .help("Simluate transaction instead of executing"),
+        )
+        .arg(
+            Arg::with_name("json_rpc_url")
+                .long("url")
+                .value_name("URL")
+                .takes_value(true)
+                .validator(is_url)
+                .help("JSON RPC URL for the cluster.  Default from the configuration file."),
+        )
+        .arg(
+            Arg::with_name("owner")
+                .long("owner")
+                .value_name("KEYPAIR")
+                .validator(is_keypair)
+                .takes_value(true)
+                .help(
+                    "Specify the owner of the token accounts used. \
+                     This may be a keypair file, the ASK keyword. \
+                     Defaults to the client keypair.",
+                ),
+        )
+        .arg(
+            Arg::with_name("fee_payer")
+                .long("fee-payer")
+                .value_name("KEYPAIR")
+                .validator(is_keypair)
+                .takes_value(true)
+                .help(
+                    "Specify the fee-payer account. \
+                     This may be a keypair file, the ASK keyword. \
+                     Defaults to the client keypair.",
+                ),
+        )
+        .subcommand(SubCommand::with_name("create-pool").about("Create a new token swap pool, funded from the owner's token accounts")
+            .arg(
+                Arg::with_name("token_a_mint")
+                    .long("token-a-mint")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Mint of token A"),
+            )
+            .arg(
+                Arg::with_name("token_b_mint")
+                    .long("token-b-mint")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Mint of token B"),
+            )
+            .arg(
+                Arg::with_name("token_a_amount")
+                    .long("token-a-amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Initial amount of token A in the pool"),
+            )
+            .arg(
+                Arg::with_name("token_b_amount")
+                    .long("token-b-amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Initial amount of token B in the pool"),
+            )
+            .arg(
+                Arg::with_name("token_a_source")
+                    .long("token-a-source")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to transfer the initial token A from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("token_b_source")
+                    .long("token-b-source")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to transfer the initial token B from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("curve_type")
+                    .long("curve-type")
+                    .value_name("CURVE")
+                    .takes_value(true)
+                    .possible_values(&["constant-product", "constant-price", "stable", "offset", "weighted"])
+                    .default_value("constant-product")
+                    .help("Curve used to price swaps, deposits and withdrawals"),
+            )
+            .arg(
+                Arg::with_name("token_b_price")
+                    .long("token-b-price")
+                    .validator(is_parsable::<u64>)
+                    .value_name("PRICE")
+                    .takes_value(true)
+                    .required_if("curve_type", "constant-price")
+                    .help("Amount of token A for one token B, for the constant price curve"),
+            )
+            .arg(
+                Arg::with_name("amp")
+                    .long("amp")
+                    .validator(is_parsable::<u64>)
+                    .value_name("AMPLIFIER")
+                    .takes_value(true)
+                    .required_if("curve_type", "stable")
+                    .help("Amplifier of the stable curve"),
+            )
+            .arg(
+                Arg::with_name("token_b_offset")
+                    .long("token-b-offset")
+                    .validator(is_parsable::<u64>)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required_if("curve_type", "offset")
+                    .help("Amount of token B added to the pool's balance, for the offset curve"),
+            )
+            .arg(
+                Arg::with_name("token_a_weight")
+                    .long("token-a-weight")
+                    .validator(is_parsable::<u64>)
+                    .value_name("WEIGHT")
+                    .takes_value(true)
+                    .required_if("curve_type", "weighted")
+                    .help("Weight of token A, for the weighted curve"),
+            )
+            .arg(
+                Arg::with_name("token_b_weight")
+                    .long("token-b-weight")
+                    .validator(is_parsable::<u64>)
+                    .value_name("WEIGHT")
+                    .takes_value(true)
+                    .required_if("curve_type", "weighted")
+                    .help("Weight of token B, for the weighted curve"),
+            )
+            .arg(fee_arg("trade_fee", "trade-fee", "Trading fee kept in the pool, for example 25/10000. Defaults to no fee."))
+            .arg(fee_arg("owner_trade_fee", "owner-trade-fee", "Trading fee paid to the fee account in pool tokens. Defaults to no fee."))
+            .arg(fee_arg("owner_withdraw_fee", "owner-withdraw-fee", "Withdrawal fee paid to the fee account in pool tokens. Defaults to no fee."))
+            .arg(fee_arg("host_fee", "host-fee", "Part of the owner trading fee paid to hosts of swaps. Defaults to no fee."))
+            .arg(
+                Arg::with_name("fee_owner")
+                    .long("fee-owner")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Owner of the pool fee account. Defaults to the owner."),
+            )
+            .arg(
+                Arg::with_name("admin")
+                    .long("admin")
+                    .validator(is_keypair)
+                    .value_name("KEYPAIR")
+                    .takes_value(true)
+                    .help("Admin authority allowed to update the fees and status of the pool, signing to accept the role. Defaults to no admin."),
+            )
+        )
+        .subcommand(SubCommand::with_name("swap").about("Swap tokens through a pool")
+            .arg(pool_arg.clone())
+            .arg(
+                Arg::with_name("input_mint")
+                    .long("input-mint")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Mint of the token given to the pool"),
+            )
+            .arg(
+                Arg::with_name("amount")
+                    .long("amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Amount of the input token to swap"),
+            )
+            .arg(slippage_arg.clone())
+            .arg(
+                Arg::with_name("source")
+                    .long("source")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to swap tokens from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("destination")
+                    .long("destination")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to receive the swapped tokens. Defaults to the owner's associated token account."),
+            )
+        )
+        .subcommand(SubCommand::with_name("deposit").about("Deposit both tokens into a pool for pool tokens")
+            .arg(pool_arg.clone())
+            .arg(
+                Arg::with_name("pool_token_amount")
+                    .long("pool-token-amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Amount of pool tokens to receive"),
+            )
+            .arg(slippage_arg.clone())
+            .arg(
+                Arg::with_name("token_a")
+                    .long("token-a")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to deposit token A from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("token_b")
+                    .long("token-b")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to deposit token B from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("token_receiver")
+                    .long("token-receiver")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to receive pool tokens. Defaults to the owner's associated token account."),
+            )
+        )
+        .subcommand(SubCommand::with_name("deposit-single").about("Deposit one token into a pool for pool tokens")
+            .arg(pool_arg.clone())
+            .arg(
+                Arg::with_name("input_mint")
+                    .long("input-mint")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Mint of the token to deposit"),
+            )
+            .arg(
+                Arg::with_name("amount")
+                    .long("amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Amount of the token to deposit"),
+            )
+            .arg(slippage_arg.clone())
+            .arg(
+                Arg::with_name("source")
+                    .long("source")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to deposit tokens from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("token_receiver")
+                    .long("token-receiver")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to receive pool tokens. Defaults to the owner's associated token account."),
+            )
+        )
+        .subcommand(SubCommand::with_name("withdraw").about("Withdraw both tokens from a pool for pool tokens")
+            .arg(pool_arg.clone())
+            .arg(
+                Arg::with_name("pool_token_amount")
+                    .long("pool-token-amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Amount of pool tokens to withdraw"),
+            )
+            .arg(slippage_arg.clone())
+            .arg(
+                Arg::with_name("withdraw_from")
+                    .long("withdraw-from")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to withdraw pool tokens from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("token_a")
+                    .long("token-a")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to receive token A. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("token_b")
+                    .long("token-b")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to receive token B. Defaults to the owner's associated token account."),
+            )
+        )
+        .subcommand(SubCommand::with_name("withdraw-single").about("Withdraw one token from a pool for pool tokens")
+            .arg(pool_arg.clone())
+            .arg(
+                Arg::with_name("output_mint")
+                    .long("output-mint")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Mint of the token to withdraw"),
+            )
+            .arg(
+                Arg::with_name("amount")
+                    .long("amount")
+                    .validator(is_amount)
+                    .value_name("AMOUNT")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Amount of the token to withdraw"),
+            )
+            .arg(slippage_arg)
+            .arg(
+                Arg::with_name("withdraw_from")
+                    .long("withdraw-from")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to withdraw pool tokens from. Defaults to the owner's associated token account."),
+            )
+            .arg(
+                Arg::with_name("destination")
+                    .long("destination")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .help("Account to receive the withdrawn tokens. Defaults to the owner's associated token account."),
+            )
+        )
+        .subcommand(SubCommand::with_name("list").about("List the pools trading a mint")
+            .arg(
+                Arg::with_name("mint")
+                    .long("mint")
+                    .validator(is_pubkey)
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Mint traded by the pools"),
+            )
+        )
+        .subcommand(SubCommand::with_name("show").about("Show the state of a pool")
+            .arg(pool_arg)
+        )
+        .get_matches();
+
+    let mut wallet_manager = None;
+    let config = {
+        let cli_config = if let Some(config_file) = matches.value_of("config_file") {
+            solana_cli_config::Config::load(config_file).unwrap_or_default()
+        } else {
+            solana_cli_config::Config::default()
+        };
+        let json_rpc_url = value_t!(matches, "json_rpc_url", String)
+            .unwrap_or_else(|_| cli_config.json_rpc_url.clone());
+
+        let owner = signer_from_path(
+            &matches,
+            &cli_config.keypair_path,
+            "owner",
+            &mut wallet_manager,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            exit(1);
+        });
+        let fee_payer = signer_from_path(
+            &matches,
+            &cli_config.keypair_path,
+            "fee_payer",
+            &mut wallet_manager,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            exit(1);
+        });
+        let verbose = matches.is_present("verbose");
+        let dry_run = matches.is_present("dry_run");
+
+        Config {
+            rpc_client: RpcClient::new_with_commitment(json_rpc_url, CommitmentConfig::confirmed()),
+            verbose,
+            owner,
+            fee_payer,
+            dry_run,
+        }
+    };
+
+    solana_logger::setup_with_default("solana=info");
+
+    let _ = match matches.subcommand() {
+        ("create-pool", Some(arg_matches)) => {
+            let (trade_fee_numerator, trade_fee_denominator) =
+                fraction_of(arg_matches, "trade_fee");
+            let (owner_trade_fee_numerator, owner_trade_fee_denominator) =
+                fraction_of(arg_matches, "owner_trade_fee");
+            let (owner_withdraw_fee_numerator, owner_withdraw_fee_denominator) =
+                fraction_of(arg_matches, "owner_withdraw_fee");
+            let (host_fee_numerator, host_fee_denominator) = fraction_of(arg_matches, "host_fee");
+            command_create_pool(
+                &config,
+                CreatePoolArgs {
+                    token_a_mint: pubkey_of(arg_matches, "token_a_mint").unwrap(),
+                    token_b_mint: pubkey_of(arg_matches, "token_b_mint").unwrap(),
+                    token_a_amount: value_t_or_exit!(arg_matches, "token_a_amount", f64),
+                    token_b_amount: value_t_or_exit!(arg_matches, "token_b_amount", f64),
+                    token_a_source: pubkey_of(arg_matches, "token_a_source"),
+                    token_b_source: pubkey_of(arg_matches, "token_b_source"),
+                    fees: Fees {
+                        trade_fee_numerator,
+                        trade_fee_denominator,
+                        owner_trade_fee_numerator,
+                        owner_trade_fee_denominator,
+                        owner_withdraw_fee_numerator,
+                        owner_withdraw_fee_denominator,
+                        host_fee_numerator,
+                        host_fee_denominator,
+                    },
+                    swap_curve: swap_curve_of(arg_matches),
+                    fee_owner: pubkey_of(arg_matches, "fee_owner"),
+                    admin: arg_matches.value_of("admin").map(|path| {
+                        signer_from_path(arg_matches, path, "admin", &mut wallet_manager)
+                            .unwrap_or_else(|e| {
+                                eprintln!("error: {}", e);
+                                exit(1);
+                            })
+                    }),
+                },
+            )
+        }
+        ("swap", Some(arg_matches)) => {
+            let pool: Pubkey = pubkey_of(arg_matches, "pool").unwrap();
+            let input_mint: Pubkey = pubkey_of(arg_matches, "input_mint").unwrap();
+            let amount = value_t_or_exit!(arg_matches, "amount", f64);
+            let slippage = value_t_or_exit!(arg_matches, "slippage", f64);
+            let source: Option<Pubkey> = pubkey_of(arg_matches, "source");
+            let destination: Option<Pubkey> = pubkey_of(arg_matches, "destination");
+            command_swap(
+                &config,
+                &pool,
+                &input_mint,
+                amount,
+                slippage,
+                &source,
+                &destination,
+            )
+        }
+        ("deposit", Some(arg_matches)) => {
+            let pool: Pubkey = pubkey_of(arg_matches, "pool").unwrap();
+            let pool_token_amount = value_t_or_exit!(arg_matches, "pool_token_amount", f64);
+            let slippage = value_t_or_exit!(arg_matches, "slippage", f64);
+            let token_a: Option<Pubkey> = pubkey_of(arg_matches, "token_a");
+            let token_b: Option<Pubkey> = pubkey_of(arg_matches, "token_b");
+            let token_receiver: Option<Pubkey> = pubkey_of(arg_matches, "token_receiver");
+            command_deposit(
+                &config,
+                &pool,
+                pool_token_amount,
+                slippage,
+                &token_a,
+                &token_b,
+                &token_receiver,
+            )
+        }
+        ("deposit-single", Some(arg_matches)) => {
+            let pool: Pubkey = pubkey_of(arg_matches, "pool").unwrap();
+            let input_mint: Pubkey = pubkey_of(arg_matches, "input_mint").unwrap();
+            let amount = value_t_or_exit!(arg_matches, "amount", f64);
+            let slippage = value_t_or_exit!(arg_matches, "slippage", f64);
+            let source: Option<Pubkey> = pubkey_of(arg_matches, "source");
+            let token_receiver: Option<Pubkey> = pubkey_of(arg_matches, "token_receiver");
+            command_deposit_single(
+                &config,
+                &pool,
+                &input_mint,
+                amount,
+                slippage,
+                &source,
+                &token_receiver,
+            )
+        }
+        ("withdraw", Some(arg_matches)) => {
+            let pool: Pubkey = pubkey_of(arg_matches, "pool").unwrap();
+            let pool_token_amount = value_t_or_exit!(arg_matches, "pool_token_amount", f64);
+            let slippage = value_t_or_exit!(arg_matches, "slippage", f64);
+            let withdraw_from: Option<Pubkey> = pubkey_of(arg_matches, "withdraw_from");
+            let token_a: Option<Pubkey> = pubkey_of(arg_matches, "token_a");
+            let token_b: Option<Pubkey> = pubkey_of(arg_matches, "token_b");
+            command_withdraw(
+                &config,
+                &pool,
+                pool_token_amount,
+                slippage,
+                &withdraw_from,
+                &token_a,
+                &token_b,
+            )
+        }
+        ("withdraw-single", Some(arg_matches)) => {
+            let pool: Pubkey = pubkey_of(arg_matches, "pool").unwrap();
+            let output_mint: Pubkey = pubkey_of(arg_matches, "output_mint").unwrap();
+            let amount = value_t_or_exit!(arg_matches, "amount", f64);
+            let slippage = value_t_or_exit!(arg_matches, "slippage", f64);
+            let withdraw_from: Option<Pubkey> = pubkey_of(arg_matches, "withdraw_from");
+            let destination: Option<Pubkey> = pubkey_of(arg_matches, "destination");
+            command_withdraw_single(
+                &config,
+                &pool,
+                &output_mint,
+                amount,
+                slippage,
+                &withdraw_from,
+                &destination,
+            )
+        }
+        ("list", Some(arg_matches)) => {
+            let mint: Pubkey = pubkey_of(arg_matches, "mint").unwrap();
+            command_list(&config, &mint)
+        }
+        ("show", Some(arg_matches)) => {
+            let pool: Pubkey = pubkey_of(arg_matches, "pool").unwrap();
+            command_show(&config, &pool)
+        }
+        _ => unreachable!(),
+    }
+    .and_then(|transactions| {
+        for transaction in transactions {
+            if config.dry_run {
+                let result = config.rpc_client.simulate_transaction(&transaction)?;
+                println!("Simulate result: {:?}", result);
+            } else {
+                let signature = config
+                    .rpc_client
+                    .send_and_confirm_transaction_with_spinner(&transaction)?;
+                println!("Signature: {}", signature);
+            }
+        }
+        Ok(())
+    })
+    .map_err(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+}
@@ -1,6 +1,6 @@
 use spl_token_swap_fuzz::{
     native_account_data::NativeAccountData,
-    native_token::{get_token_balance, transfer},
+    native_token::{get_mint_supply, get_token_balance, transfer},
     native_token_swap::NativeTokenSwap,
 };
 
@@ -8,8 +8,12 @@ use spl_token_swap::{
     curve::{
         base::{CurveType, SwapCurve},
         calculator::CurveCalculator,
+        constant_price::ConstantPriceCurve,
         constant_product::ConstantProductCurve,
         fees::Fees,
+        offset::OffsetCurve,
+        stable::{StableCurve, MAX_AMP, MIN_AMP},
+        weighted::{WeightedCurve, MAX_WEIGHT_RATIO},
     },
     error::SwapError,
     instruction::{
@@ -21,17 +25,57 @@ use spl_token_swap::{
 use spl_math::precise_number::PreciseNumber;
 use spl_token::error::TokenError;
 
+use solana_program::entrypoint::ProgramResult;
+
 use honggfuzz::fuzz;
 
 use arbitrary::Arbitrary;
 use std::collections::{HashMap, HashSet};
 
+#[derive(Debug, Arbitrary, Clone)]
+struct FuzzData {
+    curve: FuzzCurve,
+    fees: FuzzFees,
+    instructions: Vec<FuzzInstruction>,
+}
+
+/// Curve of the pool, with parameters brought into the range accepted by
+/// each curve when the pool is created.
+#[derive(Debug, Arbitrary, Clone)]
+enum FuzzCurve {
+    ConstantProduct,
+    ConstantPrice {
+        token_b_price: u64,
+    },
+    Stable {
+        amp: u64,
+    },
+    Offset {
+        token_b_offset: u64,
+    },
+    Weighted {
+        token_a_weight: u64,
+        token_b_weight: u64,
+    },
+}
+
+/// Fees of the pool in basis points.  Any of them can be zero, to cover the
+/// paths with and without each fee.
+#[derive(Debug, Arbitrary, Clone)]
+struct FuzzFees {
+    trade_fee: u16,
+    owner_trade_fee: u16,
+    owner_withdraw_fee: u16,
+    host_fee: u16,
+}
+
 #[derive(Debug, Arbitrary, Clone)]
 enum FuzzInstruction {
     Swap {
         token_a_id: AccountId,
         token_b_id: AccountId,
         trade_direction: TradeDirection,
+        host_fee: bool,
         instruction: Swap,
     },
     DepositAllTokenTypes {
@@ -58,6 +102,11 @@ enum FuzzInstruction {
         pool_token_id: AccountId,
         instruction: WithdrawSingleTokenTypeExactAmountOut,
     },
+    WithdrawOwnerFees {
+        token_a_id: AccountId,
+        token_b_id: AccountId,
+        instruction: WithdrawAllTokenTypes,
+    },
 }
 
 /// Helper enum to tell which direction a swap is meant to go.
@@ -77,41 +126,132 @@ const INITIAL_SWAP_TOKEN_B_AMOUNT: u64 = 300_000_000_000;
 const INITIAL_USER_TOKEN_A_AMOUNT: u64 = 1_000_000_000;
 const INITIAL_USER_TOKEN_B_AMOUNT: u64 = 3_000_000_000;
 
+const MAX_TOKEN_B_PRICE: u64 = 1_000_000;
+
+const FEE_DENOMINATOR: u64 = 10_000;
+const MAX_FEE_NUMERATOR: u64 = 1_000;
+
+/// Error allowed on the normalized value of the pool, in tokens.  The
+/// constant price value is rounded down to the token, and the square root in
+/// the constant product and offset values is more precise than that.
+const VALUE_TOLERANCE: u128 = 1;
+
+/// Error allowed on the normalized value of weighted pools, in tokens.  The
+/// power approximations are accurate to about 11 significant digits, which
+/// for the largest pools that the fuzzed accounts can fill is under 100
+/// tokens.
+const WEIGHTED_VALUE_TOLERANCE: u128 = 100;
+
+impl FuzzCurve {
+    fn swap_curve(&self) -> SwapCurve {
+        match *self {
+            FuzzCurve::ConstantProduct => SwapCurve {
+                curve_type: CurveType::ConstantProduct,
+                calculator: Box::new(ConstantProductCurve {}),
+            },
+            FuzzCurve::ConstantPrice { token_b_price } => SwapCurve {
+                curve_type: CurveType::ConstantPrice,
+                calculator: Box::new(ConstantPriceCurve {
+                    token_b_price: token_b_price % MAX_TOKEN_B_PRICE + 1,
+                }),
+            },
+            FuzzCurve::Stable { .. } => SwapCurve {
+                curve_type: CurveType::Stable,
+                calculator: Box::new(self.stable_curve().unwrap()),
+            },
+            FuzzCurve::Offset { token_b_offset } => SwapCurve {
+                curve_type: CurveType::Offset,
+                calculator: Box::new(OffsetCurve {
+                    token_b_offset: token_b_offset.max(1),
+                }),
+            },
+            FuzzCurve::Weighted {
+                token_a_weight,
+                token_b_weight,
+            } => SwapCurve {
+                curve_type: CurveType::Weighted,
+                calculator: Box::new(WeightedCurve {
+                    token_a_weight: token_a_weight % MAX_WEIGHT_RATIO + 1,
+                    token_b_weight: token_b_weight % MAX_WEIGHT_RATIO + 1,
+                }),
+            },
+        }
+    }
+
+    /// Stable curve of the pool, whose swaps keep its invariant rather than
+    /// the normalized value
+    fn stable_curve(&self) -> Option<StableCurve> {
+        match *self {
+            FuzzCurve::Stable { amp } => {
+                Some(StableCurve::new(MIN_AMP + amp % (MAX_AMP - MIN_AMP + 1)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FuzzFees {
+    fn fees(&self) -> Fees {
+        let numerator = |fee: u16, max: u64| fee as u64 % (max + 1);
+        Fees {
+            trade_fee_numerator: numerator(self.trade_fee, MAX_FEE_NUMERATOR),
+            trade_fee_denominator: FEE_DENOMINATOR,
+            owner_trade_fee_numerator: numerator(self.owner_trade_fee, MAX_FEE_NUMERATOR),
+            owner_trade_fee_denominator: FEE_DENOMINATOR,
+            owner_withdraw_fee_numerator: numerator(self.owner_withdraw_fee, MAX_FEE_NUMERATOR),
+            owner_withdraw_fee_denominator: FEE_DENOMINATOR,
+            host_fee_numerator: numerator(self.host_fee, FEE_DENOMINATOR),
+            host_fee_denominator: FEE_DENOMINATOR,
+        }
+    }
+}
+
+impl TradeDirection {
+    fn opposite(&self) -> Self {
+        match self {
+            TradeDirection::AtoB => TradeDirection::BtoA,
+            TradeDirection::BtoA => TradeDirection::AtoB,
+        }
+    }
+}
+
+/// Balances of the pool, taken before and after each instruction
+struct PoolSnapshot {
+    token_a_amount: u128,
+    token_b_amount: u128,
+    pool_token_supply: u128,
+    fee_pool_token_amount: u128,
+}
+
+impl PoolSnapshot {
+    fn new(token_swap: &NativeTokenSwap) -> Self {
+        Self {
+            token_a_amount: get_token_balance(&token_swap.token_a_account) as u128,
+            token_b_amount: get_token_balance(&token_swap.token_b_account) as u128,
+            pool_token_supply: get_mint_supply(&token_swap.pool_mint_account) as u128,
+            // swaps pay host fees to the pool token account
+            fee_pool_token_amount: (get_token_balance(&token_swap.pool_fee_account)
+                + get_token_balance(&token_swap.pool_token_account))
+                as u128,
+        }
+    }
+}
+
 fn main() {
     loop {
-        fuzz!(|fuzz_instructions: Vec<FuzzInstruction>| {
-            run_fuzz_instructions(fuzz_instructions)
-        });
+        fuzz!(|fuzz_data: FuzzData| { run_fuzz_instructions(fuzz_data) });
     }
 }
 
-fn run_fuzz_instructions(fuzz_instructions: Vec<FuzzInstruction>) {
-    let trade_fee_numerator = 25;
-    let trade_fee_denominator = 10000;
-    let owner_trade_fee_numerator = 5;
-    let owner_trade_fee_denominator = 10000;
-    let owner_withdraw_fee_numerator = 30;
-    let owner_withdraw_fee_denominator = 10000;
-    let host_fee_numerator = 1;
-    let host_fee_denominator = 5;
-    let fees = Fees {
-        trade_fee_numerator,
-        trade_fee_denominator,
-        owner_trade_fee_numerator,
-        owner_trade_fee_denominator,
-        owner_withdraw_fee_numerator,
-        owner_withdraw_fee_denominator,
-        host_fee_numerator,
-        host_fee_denominator,
-    };
-    let curve = ConstantProductCurve {};
-    let swap_curve = SwapCurve {
-        curve_type: CurveType::ConstantProduct,
-        calculator: Box::new(curve.clone()),
-    };
-    let mut token_swap = NativeTokenSwap::new(
+fn run_fuzz_instructions(fuzz_data: FuzzData) {
+    let FuzzData {
+        curve,
         fees,
-        swap_curve,
+        instructions: fuzz_instructions,
+    } = fuzz_data;
+    let mut token_swap = NativeTokenSwap::new(
+        fees.fees(),
+        curve.swap_curve(),
         INITIAL_SWAP_TOKEN_A_AMOUNT,
         INITIAL_SWAP_TOKEN_B_AMOUNT,
     );
@@ -163,6 +303,12 @@ fn run_fuzz_instructions(fuzz_instructions: Vec<FuzzInstruction>) {
                 TradeDirection::AtoB => (Some(token_account_id), None, Some(pool_token_id)),
                 TradeDirection::BtoA => (None, Some(token_account_id), Some(pool_token_id)),
             },
+
+            FuzzInstruction::WithdrawOwnerFees {
+                token_a_id,
+                token_b_id,
+                ..
+            } => (Some(token_a_id), Some(token_b_id), None),
         };
         if let Some(token_a_id) = token_a_id {
             token_a_accounts
@@ -181,22 +327,21 @@ fn run_fuzz_instructions(fuzz_instructions: Vec<FuzzInstruction>) {
         }
     }
 
-    let pool_tokens = [&token_swap.pool_token_account, &token_swap.pool_fee_account]
-        .iter()
-        .map(|&x| get_token_balance(x))
-        .sum::<u64>() as u128;
-    let initial_pool_token_amount =
-        pool_tokens + pool_accounts.values().map(get_token_balance).sum::<u64>() as u128;
-    let initial_swap_token_a_amount = get_token_balance(&token_swap.token_a_account) as u128;
-    let initial_swap_token_b_amount = get_token_balance(&token_swap.token_b_account) as u128;
-
     // to ensure that we never create or remove base tokens
     let before_total_token_a =
         INITIAL_SWAP_TOKEN_A_AMOUNT + get_total_token_a_amount(&fuzz_instructions);
     let before_total_token_b =
         INITIAL_SWAP_TOKEN_B_AMOUNT + get_total_token_b_amount(&fuzz_instructions);
 
+    // to ensure that pool tokens never lose value over the whole run, and
+    // not only within the tolerance of each instruction
+    let stable_curve = curve.stable_curve();
+    let mut min_value_per_pool_token =
+        min_pool_token_value(&token_swap.swap_curve, &PoolSnapshot::new(&token_swap));
+
     for fuzz_instruction in fuzz_instructions {
+        let is_swap = matches!(fuzz_instruction, FuzzInstruction::Swap { .. });
+        let before = PoolSnapshot::new(&token_swap);
         run_fuzz_instruction(
             fuzz_instruction,
             &mut token_swap,
@@ -204,45 +349,32 @@ fn run_fuzz_instructions(fuzz_instructions: Vec<FuzzInstruction>) {
             &mut token_b_accounts,
             &mut pool_accounts,
         );
-    }
-
-    // Omit fees intentionally, because fees in the form of pool tokens can
-    // dilute the value of the pool.  For example, if we perform a small swap
-    // whose fee is worth less than 1 pool token, we may round up to 1 pool
-    // token and mint it as the fee.  Depending on the size of the pool, this
-    // fee can actually reduce the value of pool tokens.
-    let pool_token_amount =
-        pool_tokens + pool_accounts.values().map(get_token_balance).sum::<u64>() as u128;
-    let swap_token_a_amount = get_token_balance(&token_swap.token_a_account) as u128;
-    let swap_token_b_amount = get_token_balance(&token_swap.token_b_account) as u128;
-
-    let initial_pool_value = curve
-        .normalized_value(initial_swap_token_a_amount, initial_swap_token_b_amount)
-        .unwrap();
-    let pool_value = curve
-        .normalized_value(swap_token_a_amount, swap_token_b_amount)
-        .unwrap();
-
-    let pool_token_amount = PreciseNumber::new(pool_token_amount).unwrap();
-    let initial_pool_token_amount = PreciseNumber::new(initial_pool_token_amount).unwrap();
-    assert!(initial_pool_value
-        .checked_div(&initial_pool_token_amount)
-        .unwrap()
-        .less_than_or_equal(&pool_value.checked_div(&pool_token_amount).unwrap()));
+        let after = PoolSnapshot::new(&token_swap);
 
-    // check total token a and b amounts
-    let after_total_token_a = token_a_accounts
-        .values()
-        .map(get_token_balance)
-        .sum::<u64>()
-        + get_token_balance(&token_swap.token_a_account);
-    assert_eq!(before_total_token_a, after_total_token_a);
-    let after_total_token_b = token_b_accounts
-        .values()
-        .map(get_token_balance)
-        .sum::<u64>()
-        + get_token_balance(&token_swap.token_b_account);
-    assert_eq!(before_total_token_b, after_total_token_b);
+        match stable_curve {
+            // The stable curve reports the constant product value, which its
+            // swaps don't keep, so they are checked against its invariant and
+            // the value starts over from the new balances
+            Some(ref stable_curve) if is_swap => {
+                check_stable_invariant(stable_curve, &before, &after);
+                min_value_per_pool_token = min_pool_token_value(&token_swap.swap_curve, &after);
+            }
+            _ => check_pool_value(
+                &token_swap.swap_curve,
+                &mut min_value_per_pool_token,
+                &before,
+                &after,
+            ),
+        }
+        check_pool_token_supply(&token_swap, &pool_accounts);
+        check_token_totals(
+            &token_swap,
+            &token_a_accounts,
+            &token_b_accounts,
+            before_total_token_a,
+            before_total_token_b,
+        );
+    }
 
     // Final check to make sure that withdrawing everything works
     //
@@ -298,17 +430,25 @@ fn run_fuzz_instruction(
             token_a_id,
             token_b_id,
             trade_direction,
+            host_fee,
             instruction,
         } => {
+            check_swap_round_trip(token_swap, &trade_direction, host_fee, instruction.clone());
             let mut token_a_account = token_a_accounts.get_mut(&token_a_id).unwrap();
             let mut token_b_account = token_b_accounts.get_mut(&token_b_id).unwrap();
             match trade_direction {
-                TradeDirection::AtoB => {
-                    token_swap.swap_a_to_b(&mut token_a_account, &mut token_b_account, instruction)
-                }
-                TradeDirection::BtoA => {
-                    token_swap.swap_b_to_a(&mut token_b_account, &mut token_a_account, instruction)
-                }
+                TradeDirection::AtoB => token_swap.swap_a_to_b(
+                    &mut token_a_account,
+                    &mut token_b_account,
+                    host_fee,
+                    instruction,
+                ),
+                TradeDirection::BtoA => token_swap.swap_b_to_a(
+                    &mut token_b_account,
+                    &mut token_a_account,
+                    host_fee,
+                    instruction,
+                ),
             }
         }
         FuzzInstruction::DepositAllTokenTypes {
@@ -317,6 +457,7 @@ fn run_fuzz_instruction(
             pool_token_id,
             instruction,
         } => {
+            check_deposit_all_round_trip(token_swap, instruction.clone());
             let mut token_a_account = token_a_accounts.get_mut(&token_a_id).unwrap();
             let mut token_b_account = token_b_accounts.get_mut(&token_b_id).unwrap();
             let mut pool_account = pool_accounts.get_mut(&pool_token_id).unwrap();
@@ -349,6 +490,7 @@ fn run_fuzz_instruction(
             pool_token_id,
             instruction,
         } => {
+            check_deposit_single_round_trip(token_swap, &trade_direction, instruction.clone());
             let mut source_token_account = match trade_direction {
                 TradeDirection::AtoB => token_a_accounts.get_mut(&token_account_id).unwrap(),
                 TradeDirection::BtoA => token_b_accounts.get_mut(&token_account_id).unwrap(),
@@ -377,7 +519,22 @@ fn run_fuzz_instruction(
                 instruction,
             )
         }
+        FuzzInstruction::WithdrawOwnerFees {
+            token_a_id,
+            token_b_id,
+            instruction,
+        } => {
+            let mut token_a_account = token_a_accounts.get_mut(&token_a_id).unwrap();
+            let mut token_b_account = token_b_accounts.get_mut(&token_b_id).unwrap();
+            token_swap.withdraw_owner_fees(&mut token_a_account, &mut token_b_account, instruction)
+        }
     };
+    succeeded(result);
+}
+
+/// Fails on any error that fuzzed amounts should not be able to cause, and
+/// tells whether the instruction succeeded
+fn succeeded(result: ProgramResult) -> bool {
     result
         .map_err(|e| {
             if !(e == SwapError::CalculationFailure.into()
@@ -385,12 +542,270 @@ fn run_fuzz_instruction(
                 || e == SwapError::FeeCalculationFailure.into()
                 || e == SwapError::ExceededSlippage.into()
                 || e == SwapError::ZeroTradingTokens.into()
+                || e == SwapError::UnsupportedCurveOperation.into()
                 || e == TokenError::InsufficientFunds.into())
             {
                 Err(e).unwrap()
             }
         })
-        .ok();
+        .is_ok()
+}
+
+/// Swaps on a copy of the pool and swaps the proceeds straight back, which
+/// must not return more than was swapped in
+fn check_swap_round_trip(
+    token_swap: &NativeTokenSwap,
+    trade_direction: &TradeDirection,
+    host_fee: bool,
+    instruction: Swap,
+) {
+    let mut token_swap = token_swap.clone();
+    let mut token_a_account = token_swap.create_token_a_account(INITIAL_USER_TOKEN_A_AMOUNT);
+    let mut token_b_account = token_swap.create_token_b_account(INITIAL_USER_TOKEN_B_AMOUNT);
+    let (source_account, destination_account) = match trade_direction {
+        TradeDirection::AtoB => (&mut token_a_account, &mut token_b_account),
+        TradeDirection::BtoA => (&mut token_b_account, &mut token_a_account),
+    };
+
+    let source_amount = get_token_balance(source_account);
+    let destination_amount = get_token_balance(destination_account);
+    if !succeeded(swap(
+        &mut token_swap,
+        trade_direction,
+        source_account,
+        destination_account,
+        host_fee,
+        instruction,
+    )) {
+        return;
+    }
+    let amount_in = source_amount - get_token_balance(source_account);
+    let amount_out = get_token_balance(destination_account) - destination_amount;
+
+    let source_amount = get_token_balance(source_account);
+    if !succeeded(swap(
+        &mut token_swap,
+        &trade_direction.opposite(),
+        destination_account,
+        source_account,
+        host_fee,
+        Swap {
+            amount_in: amount_out,
+            minimum_amount_out: 0,
+        },
+    )) {
+        return;
+    }
+    assert!(get_token_balance(source_account) - source_amount <= amount_in);
+}
+
+/// Deposits both tokens into a copy of the pool and withdraws the pool
+/// tokens received straight away, which must not return more of either token
+/// than was deposited
+fn check_deposit_all_round_trip(token_swap: &NativeTokenSwap, instruction: DepositAllTokenTypes) {
+    let mut token_swap = token_swap.clone();
+    let mut token_a_account = token_swap.create_token_a_account(INITIAL_USER_TOKEN_A_AMOUNT);
+    let mut token_b_account = token_swap.create_token_b_account(INITIAL_USER_TOKEN_B_AMOUNT);
+    let mut pool_account = token_swap.create_pool_account();
+    if !succeeded(token_swap.deposit_all_token_types(
+        &mut token_a_account,
+        &mut token_b_account,
+        &mut pool_account,
+        instruction,
+    )) {
+        return;
+    }
+    let deposited_token_a_amount =
+        INITIAL_USER_TOKEN_A_AMOUNT - get_token_balance(&token_a_account);
+    let deposited_token_b_amount =
+        INITIAL_USER_TOKEN_B_AMOUNT - get_token_balance(&token_b_account);
+
+    let mut withdrawn_token_a_account = token_swap.create_token_a_account(0);
+    let mut withdrawn_token_b_account = token_swap.create_token_b_account(0);
+    if !succeeded(token_swap.withdraw_all(
+        &mut pool_account,
+        &mut withdrawn_token_a_account,
+        &mut withdrawn_token_b_account,
+    )) {
+        return;
+    }
+    assert!(get_token_balance(&withdrawn_token_a_account) <= deposited_token_a_amount);
+    assert!(get_token_balance(&withdrawn_token_b_account) <= deposited_token_b_amount);
+}
+
+/// Deposits one token into a copy of the pool, after which the pool tokens
+/// received must not be able to withdraw more of that token than was
+/// deposited
+fn check_deposit_single_round_trip(
+    token_swap: &NativeTokenSwap,
+    trade_direction: &TradeDirection,
+    instruction: DepositSingleTokenTypeExactAmountIn,
+) {
+    let mut token_swap = token_swap.clone();
+    let (mut token_account, initial_amount) = match trade_direction {
+        TradeDirection::AtoB => (
+            token_swap.create_token_a_account(INITIAL_USER_TOKEN_A_AMOUNT),
+            INITIAL_USER_TOKEN_A_AMOUNT,
+        ),
+        TradeDirection::BtoA => (
+            token_swap.create_token_b_account(INITIAL_USER_TOKEN_B_AMOUNT),
+            INITIAL_USER_TOKEN_B_AMOUNT,
+        ),
+    };
+    let mut pool_account = token_swap.create_pool_account();
+    if !succeeded(token_swap.deposit_single_token_type_exact_amount_in(
+        &mut token_account,
+        &mut pool_account,
+        instruction,
+    )) {
+        return;
+    }
+    let deposited_amount = initial_amount - get_token_balance(&token_account);
+    let pool_token_amount = get_token_balance(&pool_account);
+
+    assert!(!succeeded(
+        token_swap.withdraw_single_token_type_exact_amount_out(
+            &mut pool_account,
+            &mut token_account,
+            WithdrawSingleTokenTypeExactAmountOut {
+                destination_token_amount: deposited_amount + 1,
+                maximum_pool_token_amount: pool_token_amount,
+            },
+        )
+    ));
+}
+
+fn swap(
+    token_swap: &mut NativeTokenSwap,
+    trade_direction: &TradeDirection,
+    source_account: &mut NativeAccountData,
+    destination_account: &mut NativeAccountData,
+    host_fee: bool,
+    instruction: Swap,
+) -> ProgramResult {
+    match trade_direction {
+        TradeDirection::AtoB => {
+            token_swap.swap_a_to_b(source_account, destination_account, host_fee, instruction)
+        }
+        TradeDirection::BtoA => {
+            token_swap.swap_b_to_a(source_account, destination_account, host_fee, instruction)
+        }
+    }
+}
+
+/// Error allowed on the normalized value of the pool
+fn value_tolerance(swap_curve: &SwapCurve) -> PreciseNumber {
+    let tolerance = match swap_curve.curve_type {
+        CurveType::Weighted => WEIGHTED_VALUE_TOLERANCE,
+        _ => VALUE_TOLERANCE,
+    };
+    PreciseNumber::new(tolerance).unwrap()
+}
+
+/// Lowest value that each pool token can have, given the error allowed on
+/// the normalized value of the pool
+fn min_pool_token_value(swap_curve: &SwapCurve, snapshot: &PoolSnapshot) -> PreciseNumber {
+    swap_curve
+        .calculator
+        .normalized_value(snapshot.token_a_amount, snapshot.token_b_amount)
+        .unwrap()
+        .checked_sub(&value_tolerance(swap_curve))
+        .unwrap_or_else(|| PreciseNumber::new(0).unwrap())
+        .checked_div(&PreciseNumber::new(snapshot.pool_token_supply).unwrap())
+        .unwrap()
+}
+
+/// No instruction can take value out of the pool, so the value of each pool
+/// token must not go below the lowest value it had at the start.  The error
+/// allowed on the normalized value only applies once, so that small losses
+/// cannot add up over the run.
+///
+/// Pool tokens minted as fees are left out, because fees in the form of pool
+/// tokens can dilute the value of the pool.  For example, if we perform a
+/// small swap whose fee is worth less than 1 pool token, we may round up to 1
+/// pool token and mint it as the fee.  Depending on the size of the pool, this
+/// fee can actually reduce the value of pool tokens.
+fn check_pool_value(
+    swap_curve: &SwapCurve,
+    min_value_per_pool_token: &mut PreciseNumber,
+    before: &PoolSnapshot,
+    after: &PoolSnapshot,
+) {
+    let minted_fee_amount = after
+        .fee_pool_token_amount
+        .saturating_sub(before.fee_pool_token_amount)
+        .min(
+            after
+                .pool_token_supply
+                .saturating_sub(before.pool_token_supply),
+        );
+    let pool_token_amount =
+        PreciseNumber::new(after.pool_token_supply - minted_fee_amount).unwrap();
+    let max_value_per_pool_token = swap_curve
+        .calculator
+        .normalized_value(after.token_a_amount, after.token_b_amount)
+        .unwrap()
+        .checked_add(&value_tolerance(swap_curve))
+        .unwrap()
+        .checked_div(&pool_token_amount)
+        .unwrap();
+    assert!(min_value_per_pool_token.less_than_or_equal(&max_value_per_pool_token));
+
+    // the fees dilute all pool tokens from now on
+    *min_value_per_pool_token = min_value_per_pool_token
+        .checked_mul(&pool_token_amount)
+        .unwrap()
+        .checked_div(&PreciseNumber::new(after.pool_token_supply).unwrap())
+        .unwrap();
+}
+
+/// Swaps on the stable curve must not lower its invariant.  The invariant
+/// changes by more than one token for each token of the smaller side of an
+/// unbalanced pool, so swaps are allowed to round by one token on each side.
+fn check_stable_invariant(stable_curve: &StableCurve, before: &PoolSnapshot, after: &PoolSnapshot) {
+    let invariant_before = stable_curve
+        .invariant(before.token_a_amount, before.token_b_amount)
+        .unwrap();
+    let invariant_after = stable_curve
+        .invariant(after.token_a_amount + 1, after.token_b_amount + 1)
+        .unwrap();
+    assert!(invariant_before <= invariant_after);
+}
+
+/// Every pool token minted is held by one of the pool token accounts
+fn check_pool_token_supply(
+    token_swap: &NativeTokenSwap,
+    pool_accounts: &HashMap<AccountId, NativeAccountData>,
+) {
+    let pool_token_amount = pool_accounts.values().map(get_token_balance).sum::<u64>()
+        + get_token_balance(&token_swap.pool_token_account)
+        + get_token_balance(&token_swap.pool_fee_account);
+    assert_eq!(
+        get_mint_supply(&token_swap.pool_mint_account),
+        pool_token_amount
+    );
+}
+
+/// Tokens A and B only ever move between the users and the pool
+fn check_token_totals(
+    token_swap: &NativeTokenSwap,
+    token_a_accounts: &HashMap<AccountId, NativeAccountData>,
+    token_b_accounts: &HashMap<AccountId, NativeAccountData>,
+    total_token_a: u64,
+    total_token_b: u64,
+) {
+    let token_a_amount = token_a_accounts
+        .values()
+        .map(get_token_balance)
+        .sum::<u64>()
+        + get_token_balance(&token_swap.token_a_account);
+    assert_eq!(total_token_a, token_a_amount);
+    let token_b_amount = token_b_accounts
+        .values()
+        .map(get_token_balance)
+        .sum::<u64>()
+        + get_token_balance(&token_swap.token_b_account);
+    assert_eq!(total_token_b, token_b_amount);
 }
 
 fn get_total_token_a_amount(fuzz_instructions: &[FuzzInstruction]) -> u64 {
@@ -420,6 +835,7 @@ fn get_total_token_a_amount(fuzz_instructions: &[FuzzInstruction]) -> u64 {
                 TradeDirection::AtoB => token_a_ids.insert(token_account_id),
                 _ => false,
             },
+            FuzzInstruction::WithdrawOwnerFees { token_a_id, .. } => token_a_ids.insert(token_a_id),
         };
     }
     (token_a_ids.len() as u64) * INITIAL_USER_TOKEN_A_AMOUNT
@@ -452,6 +868,7 @@ fn get_total_token_b_amount(fuzz_instructions: &[FuzzInstruction]) -> u64 {
                 TradeDirection::BtoA => token_b_ids.insert(token_account_id),
                 _ => false,
             },
+            FuzzInstruction::WithdrawOwnerFees { token_b_id, .. } => token_b_ids.insert(token_b_id),
         };
     }
     (token_b_ids.len() as u64) * INITIAL_USER_TOKEN_B_AMOUNT
@@ -2,7 +2,7 @@ use crate::native_account_data::NativeAccountData;
 
 use solana_program::{
     account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction,
-    program_error::ProgramError, program_stubs, pubkey::Pubkey,
+    program_error::ProgramError, program_stubs, pubkey::Pubkey, sysvar::clock::Clock,
 };
 
 struct TestSyscallStubs {}
@@ -42,6 +42,13 @@ impl program_stubs::SyscallStubs for TestSyscallStubs {
             &instruction.data,
         )
     }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe {
+            *(var_addr as *mut Clock) = Clock::default();
+        }
+        solana_program::entrypoint::SUCCESS
+    }
 }
 
 fn test_syscall_stubs() {
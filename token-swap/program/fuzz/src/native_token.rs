@@ -40,6 +40,11 @@ pub fn get_token_balance(account_data: &NativeAccountData) -> u64 {
     account.amount
 }
 
+pub fn get_mint_supply(account_data: &NativeAccountData) -> u64 {
+    let mint = Mint::unpack(&account_data.data).unwrap();
+    mint.supply
+}
+
 pub fn transfer(
     from_account: &mut NativeAccountData,
     to_account: &mut NativeAccountData,
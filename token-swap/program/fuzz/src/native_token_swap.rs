@@ -17,6 +17,7 @@ use spl_token::instruction::approve;
 
 use solana_program::{bpf_loader, entrypoint::ProgramResult, pubkey::Pubkey, system_program};
 
+#[derive(Clone)]
 pub struct NativeTokenSwap {
     pub user_account: NativeAccountData,
     pub nonce: u8,
@@ -86,6 +87,7 @@ impl NativeTokenSwap {
             &pool_mint_account.key,
             &pool_fee_account.key,
             &pool_token_account.key,
+            None,
             nonce,
             fees.clone(),
             swap_curve.clone(),
@@ -149,6 +151,7 @@ impl NativeTokenSwap {
         &mut self,
         token_a_account: &mut NativeAccountData,
         token_b_account: &mut NativeAccountData,
+        host_fee: bool,
         instruction: Swap,
     ) -> ProgramResult {
         let mut user_transfer_account = NativeAccountData::new(0, system_program::id());
@@ -182,33 +185,38 @@ impl NativeTokenSwap {
             &token_b_account.key,
             &self.pool_mint_account.key,
             &self.pool_fee_account.key,
-            Some(&self.pool_token_account.key),
+            if host_fee {
+                Some(&self.pool_token_account.key)
+            } else {
+                None
+            },
             instruction,
         )
         .unwrap();
 
-        do_process_instruction(
-            swap_instruction,
-            &[
-                self.swap_account.as_account_info(),
-                self.authority_account.as_account_info(),
-                user_transfer_account.as_account_info(),
-                token_a_account.as_account_info(),
-                self.token_a_account.as_account_info(),
-                self.token_b_account.as_account_info(),
-                token_b_account.as_account_info(),
-                self.pool_mint_account.as_account_info(),
-                self.pool_fee_account.as_account_info(),
-                self.token_program_account.as_account_info(),
-                self.pool_token_account.as_account_info(),
-            ],
-        )
+        let mut account_infos = vec![
+            self.swap_account.as_account_info(),
+            self.authority_account.as_account_info(),
+            user_transfer_account.as_account_info(),
+            token_a_account.as_account_info(),
+            self.token_a_account.as_account_info(),
+            self.token_b_account.as_account_info(),
+            token_b_account.as_account_info(),
+            self.pool_mint_account.as_account_info(),
+            self.pool_fee_account.as_account_info(),
+            self.token_program_account.as_account_info(),
+        ];
+        if host_fee {
+            account_infos.push(self.pool_token_account.as_account_info());
+        }
+        do_process_instruction(swap_instruction, &account_infos)
     }
 
     pub fn swap_b_to_a(
         &mut self,
         token_b_account: &mut NativeAccountData,
         token_a_account: &mut NativeAccountData,
+        host_fee: bool,
         instruction: Swap,
     ) -> ProgramResult {
         let mut user_transfer_account = NativeAccountData::new(0, system_program::id());
@@ -243,27 +251,31 @@ impl NativeTokenSwap {
             &token_a_account.key,
             &self.pool_mint_account.key,
             &self.pool_fee_account.key,
-            Some(&self.pool_token_account.key),
+            if host_fee {
+                Some(&self.pool_token_account.key)
+            } else {
+                None
+            },
             instruction,
         )
         .unwrap();
 
-        do_process_instruction(
-            swap_instruction,
-            &[
-                self.swap_account.as_account_info(),
-                self.authority_account.as_account_info(),
-                user_transfer_account.as_account_info(),
-                token_b_account.as_account_info(),
-                self.token_b_account.as_account_info(),
-                self.token_a_account.as_account_info(),
-                token_a_account.as_account_info(),
-                self.pool_mint_account.as_account_info(),
-                self.pool_fee_account.as_account_info(),
-                self.token_program_account.as_account_info(),
-                self.pool_token_account.as_account_info(),
-            ],
-        )
+        let mut account_infos = vec![
+            self.swap_account.as_account_info(),
+            self.authority_account.as_account_info(),
+            user_transfer_account.as_account_info(),
+            token_b_account.as_account_info(),
+            self.token_b_account.as_account_info(),
+            self.token_a_account.as_account_info(),
+            token_a_account.as_account_info(),
+            self.pool_mint_account.as_account_info(),
+            self.pool_fee_account.as_account_info(),
+            self.token_program_account.as_account_info(),
+        ];
+        if host_fee {
+            account_infos.push(self.pool_token_account.as_account_info());
+        }
+        do_process_instruction(swap_instruction, &account_infos)
     }
 
     pub fn deposit_all_token_types(
@@ -418,6 +430,24 @@ impl NativeTokenSwap {
         )
     }
 
+    pub fn withdraw_owner_fees(
+        &mut self,
+        token_a_account: &mut NativeAccountData,
+        token_b_account: &mut NativeAccountData,
+        instruction: WithdrawAllTokenTypes,
+    ) -> ProgramResult {
+        // withdrawing from the pool fee account itself skips the withdraw fee
+        let mut pool_fee_account = self.pool_fee_account.clone();
+        let result = self.withdraw_all_token_types(
+            &mut pool_fee_account,
+            token_a_account,
+            token_b_account,
+            instruction,
+        );
+        self.pool_fee_account = pool_fee_account;
+        result
+    }
+
     pub fn deposit_single_token_type_exact_amount_in(
         &mut self,
         source_token_account: &mut NativeAccountData,
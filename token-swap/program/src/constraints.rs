@@ -50,6 +50,18 @@ impl<'a> SwapConstraints<'a> {
             Err(SwapError::InvalidFee.into())
         }
     }
+
+    /// Checks that the provided trade fee matches the constraints, for pools
+    /// such as concentrated liquidity pools that only charge a trade fee
+    pub fn validate_trade_fee(&self, fees: &Fees) -> Result<(), ProgramError> {
+        if self.fees.trade_fee_numerator == fees.trade_fee_numerator
+            && self.fees.trade_fee_denominator == fees.trade_fee_denominator
+        {
+            Ok(())
+        } else {
+            Err(SwapError::InvalidFee.into())
+        }
+    }
 }
 
 #[cfg(feature = "production")]
@@ -157,6 +169,17 @@ mod tests {
         );
         fees.owner_trade_fee_denominator = owner_trade_fee_denominator;
 
+        // only the trade fee is checked for concentrated liquidity pools
+        let mut fees = valid_fees.clone();
+        fees.owner_trade_fee_numerator = 0;
+        fees.host_fee_numerator = 0;
+        constraints.validate_trade_fee(&fees).unwrap();
+        fees.trade_fee_numerator = trade_fee_numerator - 1;
+        assert_eq!(
+            Err(SwapError::InvalidFee.into()),
+            constraints.validate_trade_fee(&fees),
+        );
+
         let swap_curve = SwapCurve {
             curve_type: CurveType::ConstantPrice,
             calculator: Box::new(calculator),
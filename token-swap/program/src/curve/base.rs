@@ -1,10 +1,13 @@
 //! Base curve implementation
 
 use solana_program::{
+    clock::UnixTimestamp,
     program_error::ProgramError,
     program_pack::{Pack, Sealed},
 };
 
+use crate::error::SwapError;
+
 use crate::curve::{
     calculator::{CurveCalculator, RoundDirection, SwapWithoutFeesResult, TradeDirection},
     constant_price::ConstantPriceCurve,
@@ -12,6 +15,7 @@ use crate::curve::{
     fees::Fees,
     offset::OffsetCurve,
     stable::StableCurve,
+    weighted::WeightedCurve,
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use std::convert::{TryFrom, TryInto};
@@ -29,6 +33,9 @@ pub enum CurveType {
     Stable,
     /// Offset curve, like Uniswap, but the token B side has a faked offset
     Offset,
+    /// Balancer-style weighted curve,
+    /// invariant = token_a_amount ^ weight_a * token_b_amount ^ weight_b
+    Weighted,
 }
 
 /// Encodes all results of swapping from a source token to a destination token
@@ -61,6 +68,24 @@ pub struct SwapCurve {
 }
 
 impl SwapCurve {
+    /// Fix the parameters of the curve that move over time at their value at
+    /// `timestamp`, as a stable curve ramping its amplifier.  Calculators
+    /// never read the clock, so ramping curves must be fixed before use.
+    pub fn fix_at(&mut self, timestamp: UnixTimestamp) -> Result<(), ProgramError> {
+        if self.curve_type == CurveType::Stable {
+            let mut packed_calculator = [0u8; Self::LEN - 1];
+            self.calculator.pack_into_slice(&mut packed_calculator);
+            let curve = StableCurve::unpack_from_slice(&packed_calculator)?;
+            if curve.ramp_stop_ts != 0 {
+                let amp = curve
+                    .amp_at(timestamp)
+                    .ok_or(SwapError::CalculationFailure)?;
+                self.calculator = Box::new(StableCurve::new(amp));
+            }
+        }
+        Ok(())
+    }
+
     /// Subtract fees and calculate how much destination token will be provided
     /// given an amount of source token.
     pub fn swap(
@@ -100,6 +125,45 @@ impl SwapCurve {
         })
     }
 
+    /// Calculate how much source token, including fees, must be provided to
+    /// get exactly the given amount of destination token.
+    pub fn swap_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        if destination_amount > swap_destination_amount {
+            return None;
+        }
+        let SwapWithoutFeesResult {
+            source_amount_swapped: source_amount_less_fees,
+            destination_amount_swapped,
+        } = self.calculator.swap_without_fees_exact_out(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_direction,
+        )?;
+
+        // credit the fees on top of the amount swapped
+        let source_amount_swapped = fees.pre_trading_fee_amount(source_amount_less_fees)?;
+        let trade_fee = fees.trading_fee(source_amount_swapped)?;
+        let owner_fee = fees.owner_trading_fee(source_amount_swapped)?;
+
+        Some(SwapResult {
+            new_swap_source_amount: swap_source_amount.checked_add(source_amount_swapped)?,
+            new_swap_destination_amount: swap_destination_amount
+                .checked_sub(destination_amount_swapped)?,
+            source_amount_swapped,
+            destination_amount_swapped,
+            trade_fee,
+            owner_fee,
+        })
+    }
+
     /// Get the amount of pool tokens for the given amount of token A or B
     #[allow(clippy::too_many_arguments)]
     pub fn trading_tokens_to_pool_tokens(
@@ -194,6 +258,7 @@ impl Pack for SwapCurve {
                 }
                 CurveType::Stable => Box::new(StableCurve::unpack_from_slice(calculator)?),
                 CurveType::Offset => Box::new(OffsetCurve::unpack_from_slice(calculator)?),
+                CurveType::Weighted => Box::new(WeightedCurve::unpack_from_slice(calculator)?),
             },
         })
     }
@@ -224,6 +289,7 @@ impl TryFrom<u8> for CurveType {
             1 => Ok(CurveType::ConstantPrice),
             2 => Ok(CurveType::Stable),
             3 => Ok(CurveType::Offset),
+            4 => Ok(CurveType::Weighted),
             _ => Err(ProgramError::InvalidAccountData),
         }
     }
@@ -368,4 +434,81 @@ mod tests {
         assert_eq!(result.destination_amount_swapped, 4545);
         assert_eq!(result.new_swap_destination_amount, 45455);
     }
+
+    #[test]
+    fn constant_product_exact_out_with_fees() {
+        let swap_source_amount: u128 = 1_000;
+        let swap_destination_amount: u128 = 50_000;
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        for destination_amount in [1, 100, 4_545, 25_000, 49_999].iter() {
+            let result = swap_curve
+                .swap_exact_out(
+                    *destination_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    TradeDirection::AtoB,
+                    &fees,
+                )
+                .unwrap();
+            assert_eq!(result.destination_amount_swapped, *destination_amount);
+            assert_eq!(
+                result.new_swap_source_amount,
+                swap_source_amount + result.source_amount_swapped
+            );
+            assert_eq!(
+                result.new_swap_destination_amount,
+                swap_destination_amount - destination_amount
+            );
+
+            // the same trade forwards gives at least the requested amount
+            let forward = swap_curve
+                .swap(
+                    result.source_amount_swapped,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    TradeDirection::AtoB,
+                    &fees,
+                )
+                .unwrap();
+            assert!(forward.destination_amount_swapped >= *destination_amount);
+            assert_eq!(forward.trade_fee, result.trade_fee);
+            assert_eq!(forward.owner_fee, result.owner_fee);
+
+            // and one less source token is not enough
+            let short = swap_curve.swap(
+                result.source_amount_swapped - 1,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+                &fees,
+            );
+            if let Some(short) = short {
+                assert!(short.destination_amount_swapped < *destination_amount);
+            }
+        }
+
+        // the whole destination can never be bought
+        assert!(swap_curve
+            .swap_exact_out(
+                swap_destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+                &fees,
+            )
+            .is_none());
+    }
 }
@@ -54,6 +54,13 @@ impl TradeDirection {
     }
 }
 
+/// Trades sell token A unless they say otherwise
+impl Default for TradeDirection {
+    fn default() -> Self {
+        TradeDirection::AtoB
+    }
+}
+
 /// Encodes all results of swapping from a source token to a destination token
 #[derive(Debug, PartialEq)]
 pub struct SwapWithoutFeesResult {
@@ -72,6 +79,73 @@ pub struct TradingTokenResult {
     pub token_b_amount: u128,
 }
 
+/// Find the smallest source amount for which the curve provides at least the
+/// destination amount, starting from an estimate, for curves whose
+/// exact-output inverse can only be approximated.
+///
+/// The amount is moved away from the estimate in doubling steps until it
+/// crosses the smallest one, which is then narrowed down by bisection, so an
+/// estimate off by a few tokens only costs a few swap calculations.  The
+/// search never goes above the largest source amount accepted by the curve.
+pub fn minimum_source_amount(
+    curve: &dyn CurveCalculator,
+    estimate: u128,
+    max_source_amount: u128,
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+    trade_direction: TradeDirection,
+) -> Option<u128> {
+    let provides_enough = |source_amount: u128| {
+        source_amount > 0
+            && curve
+                .swap_without_fees(
+                    source_amount,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    trade_direction,
+                )
+                .map_or(false, |result| {
+                    result.destination_amount_swapped >= destination_amount
+                })
+    };
+    let (mut not_enough, mut enough) = if provides_enough(estimate) {
+        let mut enough = estimate;
+        let mut step = 1u128;
+        loop {
+            let source_amount = estimate.saturating_sub(step);
+            if !provides_enough(source_amount) {
+                break (source_amount, enough);
+            }
+            enough = source_amount;
+            step = step.checked_mul(2)?;
+        }
+    } else {
+        let mut not_enough = estimate;
+        let mut step = 1u128;
+        loop {
+            if not_enough >= max_source_amount {
+                return None;
+            }
+            let source_amount = std::cmp::min(estimate.saturating_add(step), max_source_amount);
+            if provides_enough(source_amount) {
+                break (not_enough, source_amount);
+            }
+            not_enough = source_amount;
+            step = step.checked_mul(2)?;
+        }
+    };
+    while enough.checked_sub(not_enough)? > 1 {
+        let middle = not_enough.checked_add(enough.checked_sub(not_enough)? / 2)?;
+        if provides_enough(middle) {
+            enough = middle;
+        } else {
+            not_enough = middle;
+        }
+    }
+    Some(enough)
+}
+
 /// Trait for packing of trait objects, required because structs that implement
 /// `Pack` cannot be used as trait objects (as `dyn Pack`).
 pub trait DynPack {
@@ -91,6 +165,20 @@ pub trait CurveCalculator: Debug + DynPack {
         trade_direction: TradeDirection,
     ) -> Option<SwapWithoutFeesResult>;
 
+    /// Calculate how much source token must be provided to get exactly the
+    /// given amount of destination token, the inverse of `swap_without_fees`.
+    ///
+    /// The source amount is the smallest one for which `swap_without_fees`
+    /// provides at least the destination amount, so that rounding always
+    /// favors the pool.
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult>;
+
     /// Get the supply for a new pool
     /// The default implementation is a Balancer-style fixed initial supply
     fn new_pool_supply(&self) -> u128 {
@@ -328,6 +416,41 @@ pub mod test {
         assert!(difference <= epsilon);
     }
 
+    /// Test function checking that an exact-output swap provides exactly the
+    /// requested amount, and that swapping the source amount it asks for
+    /// provides at least that much, so the pool never loses value.
+    ///
+    /// Returns the source amount, for curves to check how close it is to the
+    /// smallest possible one.
+    pub fn check_swap_exact_out(
+        curve: &dyn CurveCalculator,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> u128 {
+        let results = curve
+            .swap_without_fees_exact_out(
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_direction,
+            )
+            .unwrap();
+        assert_eq!(results.destination_amount_swapped, destination_amount);
+        let forward_results = curve
+            .swap_without_fees(
+                results.source_amount_swapped,
+                swap_source_amount,
+                swap_destination_amount,
+                trade_direction,
+            )
+            .unwrap();
+        assert!(forward_results.destination_amount_swapped >= destination_amount);
+        assert!(forward_results.source_amount_swapped <= results.source_amount_swapped);
+        results.source_amount_swapped
+    }
+
     /// Test function checking that a deposit never reduces the value of pool
     /// tokens.
     ///
@@ -0,0 +1,1010 @@
+//! Calculations for concentrated liquidity pools, where liquidity is provided
+//! within price ranges bounded by ticks.
+//!
+//! The price of token A in token B at tick `i` is `1.0001 ^ i`, and prices are
+//! stored as their square root in Q64.64 fixed point, so that the amounts of
+//! each token held for some liquidity `L` between two prices are:
+//!
+//! ```text
+//! token A = L * (sqrt_upper - sqrt_lower) / (sqrt_upper * sqrt_lower)
+//! token B = L * (sqrt_upper - sqrt_lower)
+//! ```
+
+use {
+    crate::{
+        curve::fees::Fees,
+        error::SwapError,
+        state::{ConcentratedPool, Position, Tick},
+    },
+    spl_math::uint::U256,
+    std::convert::TryFrom,
+};
+
+/// Maximum number of initialized ticks held by a concentrated liquidity pool
+pub const MAX_TICKS_IN_CONCENTRATED_POOL: usize = 32;
+
+/// Lowest tick, with a price of `1.0001 ^ MIN_TICK`
+pub const MIN_TICK: i32 = -443_636;
+
+/// Highest tick, with a price of `1.0001 ^ MAX_TICK`
+pub const MAX_TICK: i32 = 443_636;
+
+/// Square root price at `MIN_TICK`, in Q64.64
+pub const MIN_SQRT_PRICE: u128 = 4_295_048_017;
+
+/// Square root price at `MAX_TICK`, in Q64.64
+pub const MAX_SQRT_PRICE: u128 = 79_226_673_515_401_279_992_447_579_062;
+
+/// Maximum spacing between usable ticks
+pub const MAX_TICK_SPACING: u16 = 16_384;
+
+/// `2^128 / sqrt(1.0001) ^ (2 ^ i)`, rounded, so that the square root price
+/// at a tick can be built up one bit of the tick at a time
+const TICK_FACTORS: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+];
+
+/// Divide, rounding the quotient up if requested
+fn checked_div_round(numerator: U256, denominator: U256, round_up: bool) -> Option<U256> {
+    let quotient = numerator.checked_div(denominator)?;
+    if round_up && numerator.checked_rem(denominator)? > U256::zero() {
+        quotient.checked_add(U256::one())
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Apply a signed change to some liquidity
+fn add_liquidity_delta(liquidity: u128, liquidity_delta: i128) -> Option<u128> {
+    if liquidity_delta < 0 {
+        liquidity.checked_sub(liquidity_delta.checked_neg()? as u128)
+    } else {
+        liquidity.checked_add(liquidity_delta as u128)
+    }
+}
+
+/// Square root price at the given tick, in Q64.64, rounded up
+pub fn sqrt_price_at_tick(tick: i32) -> Option<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+    let abs_tick = i64::from(tick).abs() as u32;
+    // Q128.128 value of 1 / sqrt(1.0001 ^ abs_tick)
+    let mut ratio = U256::one() << 128;
+    for (bit, factor) in TICK_FACTORS.iter().enumerate() {
+        if abs_tick & (1 << bit) != 0 {
+            ratio = ratio.checked_mul(U256::from(*factor))? >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::max_value().checked_div(ratio)?;
+    }
+    u128::try_from(checked_div_round(ratio, U256::one() << 64, true)?).ok()
+}
+
+/// Lowest tick that can bound a position in a pool with the given tick
+/// spacing, starting at the given tick.  The usable ticks are centered on the
+/// starting price, and moved back within `MIN_TICK` and `MAX_TICK` if needed.
+pub fn tick_lower_bound(tick_current: i32, tick_spacing: u16) -> i32 {
+    let tick_spacing = i32::from(tick_spacing);
+    let half_width = MAX_TICKS_IN_CONCENTRATED_POOL as i32 / 2 * tick_spacing;
+    let lowest = -(MIN_TICK.abs() / tick_spacing * tick_spacing);
+    let highest = MAX_TICK / tick_spacing * tick_spacing
+        - (MAX_TICKS_IN_CONCENTRATED_POOL as i32 - 1) * tick_spacing;
+    (tick_current.div_euclid(tick_spacing) * tick_spacing - half_width)
+        .max(lowest)
+        .min(highest)
+}
+
+/// Greatest tick with a square root price at or below the given one
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> Option<i32> {
+    if !(MIN_SQRT_PRICE..=MAX_SQRT_PRICE).contains(&sqrt_price) {
+        return None;
+    }
+    let mut low = MIN_TICK;
+    let mut high = MAX_TICK;
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(middle)? <= sqrt_price {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Some(low)
+}
+
+/// Amount of token A held by the liquidity between two square root prices
+pub fn amount_a_delta(
+    sqrt_price_a: u128,
+    sqrt_price_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let (sqrt_price_lower, sqrt_price_upper) = if sqrt_price_a < sqrt_price_b {
+        (sqrt_price_a, sqrt_price_b)
+    } else {
+        (sqrt_price_b, sqrt_price_a)
+    };
+    // dividing by the upper price first keeps the product within 256 bits
+    let product = U256::from(liquidity)
+        .checked_mul(U256::from(sqrt_price_upper.checked_sub(sqrt_price_lower)?))?;
+    let quotient = checked_div_round(product, U256::from(sqrt_price_upper), round_up)?;
+    let amount = checked_div_round(quotient << 64, U256::from(sqrt_price_lower), round_up)?;
+    u128::try_from(amount).ok()
+}
+
+/// Amount of token B held by the liquidity between two square root prices
+pub fn amount_b_delta(
+    sqrt_price_a: u128,
+    sqrt_price_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let sqrt_price_difference = if sqrt_price_a < sqrt_price_b {
+        sqrt_price_b.checked_sub(sqrt_price_a)?
+    } else {
+        sqrt_price_a.checked_sub(sqrt_price_b)?
+    };
+    let product = U256::from(liquidity).checked_mul(U256::from(sqrt_price_difference))?;
+    let amount = checked_div_round(product, U256::one() << 64, round_up)?;
+    u128::try_from(amount).ok()
+}
+
+/// Square root price after adding some token A, which lowers the price,
+/// rounded up to keep the pool's side of the trade
+fn next_sqrt_price_from_amount_a(sqrt_price: u128, liquidity: u128, amount: u128) -> Option<u128> {
+    if amount == 0 {
+        return Some(sqrt_price);
+    }
+    // liquidity / (liquidity / sqrt_price + amount)
+    let numerator = U256::from(liquidity) << 64;
+    let denominator = numerator
+        .checked_div(U256::from(sqrt_price))?
+        .checked_add(U256::from(amount))?;
+    u128::try_from(checked_div_round(numerator, denominator, true)?).ok()
+}
+
+/// Square root price after adding some token B, which raises the price,
+/// rounded down to keep the pool's side of the trade
+fn next_sqrt_price_from_amount_b(sqrt_price: u128, liquidity: u128, amount: u128) -> Option<u128> {
+    // sqrt_price + amount / liquidity
+    let delta = (U256::from(amount) << 64).checked_div(U256::from(liquidity))?;
+    u128::try_from(U256::from(sqrt_price).checked_add(delta)?).ok()
+}
+
+/// Result of a swap within a single price range
+#[derive(Debug, PartialEq)]
+pub struct SwapStep {
+    /// Square root price after the step
+    pub sqrt_price_next: u128,
+    /// Amount of the source token added to the pool, without fees
+    pub amount_in: u128,
+    /// Amount of the destination token taken from the pool
+    pub amount_out: u128,
+    /// Trading fee taken from the source token
+    pub fee_amount: u128,
+}
+
+/// Swap as much of the remaining source token as possible with the given
+/// liquidity, without moving the price past the target
+pub fn compute_swap_step(
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    fees: &Fees,
+) -> Option<SwapStep> {
+    let a_to_b = sqrt_price_current >= sqrt_price_target;
+    let fee_numerator = u128::from(fees.trade_fee_numerator);
+    let fee_denominator = u128::from(fees.trade_fee_denominator);
+    let amount_remaining_less_fee = if fee_numerator == 0 {
+        amount_remaining
+    } else {
+        amount_remaining
+            .checked_mul(fee_denominator.checked_sub(fee_numerator)?)?
+            .checked_div(fee_denominator)?
+    };
+
+    let amount_in_to_target = if a_to_b {
+        amount_a_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+    } else {
+        amount_b_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+    };
+    let sqrt_price_next = if amount_remaining_less_fee >= amount_in_to_target {
+        sqrt_price_target
+    } else if a_to_b {
+        next_sqrt_price_from_amount_a(sqrt_price_current, liquidity, amount_remaining_less_fee)?
+    } else {
+        next_sqrt_price_from_amount_b(sqrt_price_current, liquidity, amount_remaining_less_fee)?
+    };
+    let reached_target = sqrt_price_next == sqrt_price_target;
+
+    let (amount_in, amount_out) = if a_to_b {
+        (
+            if reached_target {
+                amount_in_to_target
+            } else {
+                amount_a_delta(sqrt_price_next, sqrt_price_current, liquidity, true)?
+            },
+            amount_b_delta(sqrt_price_next, sqrt_price_current, liquidity, false)?,
+        )
+    } else {
+        (
+            if reached_target {
+                amount_in_to_target
+            } else {
+                amount_b_delta(sqrt_price_current, sqrt_price_next, liquidity, true)?
+            },
+            amount_a_delta(sqrt_price_current, sqrt_price_next, liquidity, false)?,
+        )
+    };
+
+    // if the target was not reached, everything left over is kept as the fee
+    let fee_amount = if !reached_target {
+        amount_remaining.checked_sub(amount_in)?
+    } else if fee_numerator == 0 {
+        0
+    } else {
+        u128::try_from(checked_div_round(
+            U256::from(amount_in).checked_mul(U256::from(fee_numerator))?,
+            U256::from(fee_denominator.checked_sub(fee_numerator)?),
+            true,
+        )?)
+        .ok()?
+    };
+
+    Some(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+/// Result of a swap through a concentrated liquidity pool
+#[derive(Debug, PartialEq)]
+pub struct ConcentratedSwapResult {
+    /// Amount of the source token taken by the pool, including fees
+    pub amount_in: u128,
+    /// Amount of the destination token given by the pool
+    pub amount_out: u128,
+}
+
+/// Amounts of each token added to or removed from the pool for a change in
+/// a position's liquidity
+#[derive(Debug, PartialEq)]
+pub struct LiquidityAmounts {
+    /// Amount of token A
+    pub token_a_amount: u128,
+    /// Amount of token B
+    pub token_b_amount: u128,
+}
+
+impl Position {
+    /// Credit the fees earned by the position since the last update
+    fn update_fees(&mut self, fee_growth_inside_a: u128, fee_growth_inside_b: u128) -> Option<()> {
+        let liquidity = U256::from(self.liquidity);
+        let earned = |fee_growth_inside: u128, fee_growth_inside_last: u128| {
+            let fee_growth = fee_growth_inside.wrapping_sub(fee_growth_inside_last);
+            u64::try_from(liquidity.checked_mul(U256::from(fee_growth))? >> 64).ok()
+        };
+        self.tokens_owed_a = self
+            .tokens_owed_a
+            .checked_add(earned(fee_growth_inside_a, self.fee_growth_inside_a)?)?;
+        self.tokens_owed_b = self
+            .tokens_owed_b
+            .checked_add(earned(fee_growth_inside_b, self.fee_growth_inside_b)?)?;
+        self.fee_growth_inside_a = fee_growth_inside_a;
+        self.fee_growth_inside_b = fee_growth_inside_b;
+        Some(())
+    }
+}
+
+impl ConcentratedPool {
+    /// Initialized ticks, sorted by index
+    pub fn ticks(&self) -> &[Tick] {
+        &self.ticks[..self.tick_count as usize]
+    }
+
+    /// Check that a tick can bound a position in the pool: it must be one of
+    /// the pool's usable ticks, so that a slot is always left for it
+    pub fn validate_tick(&self, tick: i32) -> Result<(), SwapError> {
+        let tick_spacing = i32::from(self.tick_spacing);
+        let tick_upper_bound =
+            self.tick_lower_bound + (MAX_TICKS_IN_CONCENTRATED_POOL as i32 - 1) * tick_spacing;
+        if !(self.tick_lower_bound..=tick_upper_bound).contains(&tick)
+            || (tick - self.tick_lower_bound) % tick_spacing != 0
+        {
+            Err(SwapError::InvalidTick)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fee growth per unit of liquidity, in Q64.64, accumulated between two
+    /// ticks.  Both ticks must be initialized.
+    pub fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> Option<(u128, u128)> {
+        let lower = self.tick(tick_lower)?;
+        let upper = self.tick(tick_upper)?;
+        let (below_a, below_b) = if self.tick_current >= tick_lower {
+            (lower.fee_growth_outside_a, lower.fee_growth_outside_b)
+        } else {
+            (
+                self.fee_growth_global_a
+                    .wrapping_sub(lower.fee_growth_outside_a),
+                self.fee_growth_global_b
+                    .wrapping_sub(lower.fee_growth_outside_b),
+            )
+        };
+        let (above_a, above_b) = if self.tick_current < tick_upper {
+            (upper.fee_growth_outside_a, upper.fee_growth_outside_b)
+        } else {
+            (
+                self.fee_growth_global_a
+                    .wrapping_sub(upper.fee_growth_outside_a),
+                self.fee_growth_global_b
+                    .wrapping_sub(upper.fee_growth_outside_b),
+            )
+        };
+        Some((
+            self.fee_growth_global_a
+                .wrapping_sub(below_a)
+                .wrapping_sub(above_a),
+            self.fee_growth_global_b
+                .wrapping_sub(below_b)
+                .wrapping_sub(above_b),
+        ))
+    }
+
+    fn tick(&self, index: i32) -> Option<&Tick> {
+        self.ticks()
+            .binary_search_by_key(&index, |tick| tick.index)
+            .ok()
+            .map(|position| &self.ticks[position])
+    }
+
+    /// Apply a change in liquidity to the lower or upper tick of a position,
+    /// initializing or clearing the tick as needed
+    fn update_tick(
+        &mut self,
+        index: i32,
+        liquidity_delta: i128,
+        upper: bool,
+    ) -> Result<(), SwapError> {
+        let tick_count = self.tick_count as usize;
+        let position = match self.ticks().binary_search_by_key(&index, |tick| tick.index) {
+            Ok(position) => position,
+            Err(position) => {
+                if tick_count == MAX_TICKS_IN_CONCENTRATED_POOL {
+                    return Err(SwapError::TooManyTicks);
+                }
+                self.ticks[position..=tick_count].rotate_right(1);
+                // by convention, all fees so far were earned below the tick
+                // if the price is at or above it
+                let (fee_growth_outside_a, fee_growth_outside_b) = if self.tick_current >= index {
+                    (self.fee_growth_global_a, self.fee_growth_global_b)
+                } else {
+                    (0, 0)
+                };
+                self.ticks[position] = Tick {
+                    index,
+                    fee_growth_outside_a,
+                    fee_growth_outside_b,
+                    ..Tick::default()
+                };
+                self.tick_count += 1;
+                position
+            }
+        };
+
+        let tick = &mut self.ticks[position];
+        tick.liquidity_gross = add_liquidity_delta(tick.liquidity_gross, liquidity_delta)
+            .ok_or(SwapError::CalculationFailure)?;
+        tick.liquidity_net = if upper {
+            tick.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            tick.liquidity_net.checked_add(liquidity_delta)
+        }
+        .ok_or(SwapError::CalculationFailure)?;
+
+        if tick.liquidity_gross == 0 {
+            let tick_count = self.tick_count as usize;
+            self.ticks[position..tick_count].rotate_left(1);
+            self.ticks[tick_count - 1] = Tick::default();
+            self.tick_count -= 1;
+        }
+        Ok(())
+    }
+
+    /// Add liquidity to, or remove liquidity from, a position, crediting any
+    /// fees earned so far, and returning the amounts of each token to
+    /// transfer.  Amounts are rounded up when adding and down when removing.
+    ///
+    /// A position must be left either empty or with at least the pool's
+    /// minimum liquidity, so every initialized tick holds at least that much.
+    pub fn modify_position(
+        &mut self,
+        position: &mut Position,
+        liquidity_delta: i128,
+    ) -> Result<LiquidityAmounts, SwapError> {
+        let new_liquidity = add_liquidity_delta(position.liquidity, liquidity_delta)
+            .ok_or(SwapError::CalculationFailure)?;
+        if new_liquidity != 0 && new_liquidity < self.min_position_liquidity {
+            return Err(SwapError::PositionLiquidityTooLow);
+        }
+
+        if liquidity_delta > 0 {
+            self.update_tick(position.tick_lower, liquidity_delta, false)?;
+            self.update_tick(position.tick_upper, liquidity_delta, true)?;
+        }
+
+        // an empty position has no ticks to measure fees with
+        if position.liquidity > 0 || liquidity_delta > 0 {
+            let (fee_growth_inside_a, fee_growth_inside_b) = self
+                .fee_growth_inside(position.tick_lower, position.tick_upper)
+                .ok_or(SwapError::CalculationFailure)?;
+            position
+                .update_fees(fee_growth_inside_a, fee_growth_inside_b)
+                .ok_or(SwapError::CalculationFailure)?;
+        }
+        position.liquidity = new_liquidity;
+
+        if liquidity_delta < 0 {
+            self.update_tick(position.tick_lower, liquidity_delta, false)?;
+            self.update_tick(position.tick_upper, liquidity_delta, true)?;
+        }
+
+        let round_up = liquidity_delta > 0;
+        let liquidity = liquidity_delta
+            .checked_abs()
+            .ok_or(SwapError::CalculationFailure)? as u128;
+        let sqrt_price_lower =
+            sqrt_price_at_tick(position.tick_lower).ok_or(SwapError::InvalidTick)?;
+        let sqrt_price_upper =
+            sqrt_price_at_tick(position.tick_upper).ok_or(SwapError::InvalidTick)?;
+        let (token_a_amount, token_b_amount) = if self.tick_current < position.tick_lower {
+            (
+                amount_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up),
+                Some(0),
+            )
+        } else if self.tick_current < position.tick_upper {
+            self.liquidity = add_liquidity_delta(self.liquidity, liquidity_delta)
+                .ok_or(SwapError::CalculationFailure)?;
+            (
+                amount_a_delta(self.sqrt_price, sqrt_price_upper, liquidity, round_up),
+                amount_b_delta(sqrt_price_lower, self.sqrt_price, liquidity, round_up),
+            )
+        } else {
+            (
+                Some(0),
+                amount_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up),
+            )
+        };
+        Ok(LiquidityAmounts {
+            token_a_amount: token_a_amount.ok_or(SwapError::CalculationFailure)?,
+            token_b_amount: token_b_amount.ok_or(SwapError::CalculationFailure)?,
+        })
+    }
+
+    /// Move the price across an initialized tick, activating or deactivating
+    /// the liquidity of the positions bounded by it
+    fn cross_tick(&mut self, index: i32, a_to_b: bool) -> Result<(), SwapError> {
+        let position = self
+            .ticks()
+            .binary_search_by_key(&index, |tick| tick.index)
+            .map_err(|_| SwapError::CalculationFailure)?;
+        let tick = &mut self.ticks[position];
+        tick.fee_growth_outside_a = self
+            .fee_growth_global_a
+            .wrapping_sub(tick.fee_growth_outside_a);
+        tick.fee_growth_outside_b = self
+            .fee_growth_global_b
+            .wrapping_sub(tick.fee_growth_outside_b);
+        let liquidity_delta = if a_to_b {
+            tick.liquidity_net
+                .checked_neg()
+                .ok_or(SwapError::CalculationFailure)?
+        } else {
+            tick.liquidity_net
+        };
+        self.liquidity = add_liquidity_delta(self.liquidity, liquidity_delta)
+            .ok_or(SwapError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Swap an exact amount of token A for token B, or B for A, crossing as
+    /// many ticks as needed.  If the pool runs out of liquidity, only part of
+    /// the amount is swapped.
+    pub fn swap(
+        &mut self,
+        amount: u128,
+        a_to_b: bool,
+    ) -> Result<ConcentratedSwapResult, SwapError> {
+        let mut amount_remaining = amount;
+        let mut amount_out = 0u128;
+        while amount_remaining > 0 {
+            // the next initialized tick in the direction of the swap
+            let tick_current = self.tick_current;
+            let next_tick = if a_to_b {
+                self.ticks()
+                    .iter()
+                    .rev()
+                    .find(|tick| tick.index <= tick_current)
+                    .map(|tick| tick.index)
+            } else {
+                self.ticks()
+                    .iter()
+                    .find(|tick| tick.index > tick_current)
+                    .map(|tick| tick.index)
+            };
+            let sqrt_price_target = match next_tick {
+                Some(index) => sqrt_price_at_tick(index).ok_or(SwapError::CalculationFailure)?,
+                None if a_to_b => MIN_SQRT_PRICE,
+                None => MAX_SQRT_PRICE,
+            };
+            if next_tick.is_none() && sqrt_price_target == self.sqrt_price {
+                break;
+            }
+
+            let step = compute_swap_step(
+                self.sqrt_price,
+                sqrt_price_target,
+                self.liquidity,
+                amount_remaining,
+                &self.fees,
+            )
+            .ok_or(SwapError::CalculationFailure)?;
+            amount_remaining = step
+                .amount_in
+                .checked_add(step.fee_amount)
+                .and_then(|amount_used| amount_remaining.checked_sub(amount_used))
+                .ok_or(SwapError::CalculationFailure)?;
+            amount_out = amount_out
+                .checked_add(step.amount_out)
+                .ok_or(SwapError::CalculationFailure)?;
+            if self.liquidity > 0 {
+                let fee_growth = u128::try_from(
+                    (U256::from(step.fee_amount) << 64)
+                        .checked_div(U256::from(self.liquidity))
+                        .ok_or(SwapError::CalculationFailure)?,
+                )
+                .map_err(|_| SwapError::CalculationFailure)?;
+                if a_to_b {
+                    self.fee_growth_global_a = self.fee_growth_global_a.wrapping_add(fee_growth);
+                } else {
+                    self.fee_growth_global_b = self.fee_growth_global_b.wrapping_add(fee_growth);
+                }
+            }
+
+            self.sqrt_price = step.sqrt_price_next;
+            match next_tick {
+                Some(index) if step.sqrt_price_next == sqrt_price_target => {
+                    self.cross_tick(index, a_to_b)?;
+                    self.tick_current = if a_to_b { index - 1 } else { index };
+                }
+                _ => {
+                    self.tick_current =
+                        tick_at_sqrt_price(self.sqrt_price).ok_or(SwapError::CalculationFailure)?;
+                }
+            }
+        }
+
+        Ok(ConcentratedSwapResult {
+            amount_in: amount
+                .checked_sub(amount_remaining)
+                .ok_or(SwapError::CalculationFailure)?,
+            amount_out,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const TRADE_FEES: Fees = Fees {
+        trade_fee_numerator: 3,
+        trade_fee_denominator: 1000,
+        owner_trade_fee_numerator: 0,
+        owner_trade_fee_denominator: 0,
+        owner_withdraw_fee_numerator: 0,
+        owner_withdraw_fee_denominator: 0,
+        host_fee_numerator: 0,
+        host_fee_denominator: 0,
+    };
+
+    fn test_pool(tick: i32) -> ConcentratedPool {
+        ConcentratedPool {
+            is_initialized: true,
+            fees: TRADE_FEES,
+            tick_spacing: 10,
+            tick_lower_bound: tick_lower_bound(tick, 10),
+            min_position_liquidity: 1_000,
+            sqrt_price: sqrt_price_at_tick(tick).unwrap(),
+            tick_current: tick,
+            ..ConcentratedPool::default()
+        }
+    }
+
+    fn test_position(tick_lower: i32, tick_upper: i32) -> Position {
+        Position {
+            is_initialized: true,
+            tick_lower,
+            tick_upper,
+            ..Position::default()
+        }
+    }
+
+    #[test]
+    fn sqrt_price_at_ticks() {
+        assert_eq!(sqrt_price_at_tick(0), Some(1 << 64));
+        assert_eq!(sqrt_price_at_tick(1), Some(18_447_666_387_855_959_851));
+        assert_eq!(sqrt_price_at_tick(-1), Some(18_445_821_805_675_392_312));
+        assert_eq!(sqrt_price_at_tick(MIN_TICK), Some(MIN_SQRT_PRICE));
+        assert_eq!(sqrt_price_at_tick(MAX_TICK), Some(MAX_SQRT_PRICE));
+        assert_eq!(sqrt_price_at_tick(MIN_TICK - 1), None);
+        assert_eq!(sqrt_price_at_tick(MAX_TICK + 1), None);
+    }
+
+    #[test]
+    fn tick_at_sqrt_prices() {
+        for tick in [MIN_TICK, -100_000, -1, 0, 1, 12_345, MAX_TICK].iter() {
+            let sqrt_price = sqrt_price_at_tick(*tick).unwrap();
+            assert_eq!(tick_at_sqrt_price(sqrt_price), Some(*tick));
+            if *tick > MIN_TICK {
+                assert_eq!(tick_at_sqrt_price(sqrt_price - 1), Some(*tick - 1));
+            }
+        }
+        assert_eq!(tick_at_sqrt_price(MIN_SQRT_PRICE - 1), None);
+        assert_eq!(tick_at_sqrt_price(MAX_SQRT_PRICE + 1), None);
+    }
+
+    #[test]
+    fn amount_deltas() {
+        // between a price of 1 and 4, token A is L / 2 and token B is L
+        let sqrt_price_lower = 1 << 64;
+        let sqrt_price_upper = 1 << 65;
+        let liquidity = 1_000_001;
+        assert_eq!(
+            amount_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, false),
+            Some(500_000)
+        );
+        assert_eq!(
+            amount_a_delta(sqrt_price_upper, sqrt_price_lower, liquidity, true),
+            Some(500_001)
+        );
+        assert_eq!(
+            amount_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, false),
+            Some(1_000_001)
+        );
+        assert_eq!(amount_a_delta(0, sqrt_price_upper, liquidity, false), None);
+    }
+
+    #[test]
+    fn swap_step_within_range() {
+        let liquidity = 1_000_000_000;
+        let sqrt_price_current = sqrt_price_at_tick(0).unwrap();
+        let sqrt_price_target = sqrt_price_at_tick(-100).unwrap();
+
+        // not enough to reach the target, the whole amount is used
+        let step = compute_swap_step(
+            sqrt_price_current,
+            sqrt_price_target,
+            liquidity,
+            1_000_000,
+            &TRADE_FEES,
+        )
+        .unwrap();
+        assert!(step.sqrt_price_next < sqrt_price_current);
+        assert!(step.sqrt_price_next > sqrt_price_target);
+        // the fee takes whatever the rounded up input leaves behind
+        assert_eq!(step.amount_in + step.fee_amount, 1_000_000);
+        assert!(step.fee_amount >= 2_999 && step.fee_amount <= 3_000);
+        // close to a price of 1, less the price impact
+        assert!(step.amount_out < step.amount_in);
+        assert!(step.amount_out > step.amount_in * 999 / 1000);
+
+        // more than enough, the price stops at the target
+        let step = compute_swap_step(
+            sqrt_price_current,
+            sqrt_price_target,
+            liquidity,
+            100_000_000,
+            &TRADE_FEES,
+        )
+        .unwrap();
+        assert_eq!(step.sqrt_price_next, sqrt_price_target);
+        assert_eq!(
+            step.amount_in,
+            amount_a_delta(sqrt_price_target, sqrt_price_current, liquidity, true).unwrap()
+        );
+        assert!(step.amount_in + step.fee_amount < 100_000_000);
+
+        // without liquidity, the price moves straight to the target
+        let step = compute_swap_step(sqrt_price_current, sqrt_price_target, 0, 1_000, &TRADE_FEES)
+            .unwrap();
+        assert_eq!(
+            step,
+            SwapStep {
+                sqrt_price_next: sqrt_price_target,
+                amount_in: 0,
+                amount_out: 0,
+                fee_amount: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn modify_position_amounts() {
+        let mut pool = test_pool(0);
+        let liquidity = 1_000_000_000;
+
+        // in range, both tokens are needed
+        let mut in_range = test_position(-100, 100);
+        let amounts = pool.modify_position(&mut in_range, liquidity).unwrap();
+        assert!(amounts.token_a_amount > 0);
+        assert!(amounts.token_b_amount > 0);
+        assert_eq!(pool.liquidity, liquidity as u128);
+        assert_eq!(pool.ticks().len(), 2);
+
+        // above the price, only token A
+        let mut above = test_position(100, 200);
+        let amounts = pool.modify_position(&mut above, liquidity).unwrap();
+        assert!(amounts.token_a_amount > 0);
+        assert_eq!(amounts.token_b_amount, 0);
+
+        // below the price, only token B
+        let mut below = test_position(-200, -100);
+        let amounts = pool.modify_position(&mut below, liquidity).unwrap();
+        assert_eq!(amounts.token_a_amount, 0);
+        assert!(amounts.token_b_amount > 0);
+
+        // out of range positions do not change the active liquidity, and
+        // share the ticks at -100 and 100
+        assert_eq!(pool.liquidity, liquidity as u128);
+        let indexes = pool
+            .ticks()
+            .iter()
+            .map(|tick| tick.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indexes, vec![-200, -100, 100, 200]);
+
+        // removing gives back no more than was added
+        let added = pool.modify_position(&mut in_range, 0).unwrap();
+        assert_eq!(added.token_a_amount, 0);
+        let mut copy = test_position(-100, 100);
+        let added = pool.modify_position(&mut copy, liquidity).unwrap();
+        let removed = pool.modify_position(&mut copy, -liquidity).unwrap();
+        assert!(removed.token_a_amount <= added.token_a_amount);
+        assert!(removed.token_b_amount <= added.token_b_amount);
+        assert!(removed.token_a_amount + 1 >= added.token_a_amount);
+
+        // ticks are cleared once no position uses them
+        pool.modify_position(&mut in_range, -liquidity).unwrap();
+        pool.modify_position(&mut above, -liquidity).unwrap();
+        pool.modify_position(&mut below, -liquidity).unwrap();
+        assert_eq!(pool.ticks().len(), 0);
+        assert_eq!(pool.liquidity, 0);
+        assert_eq!(
+            pool.modify_position(&mut below, -1),
+            Err(SwapError::CalculationFailure)
+        );
+    }
+
+    #[test]
+    fn tick_lower_bounds() {
+        assert_eq!(tick_lower_bound(0, 10), -160);
+        assert_eq!(tick_lower_bound(-5, 10), -170);
+        assert_eq!(tick_lower_bound(MIN_TICK, 60), -443_580);
+        assert_eq!(tick_lower_bound(MAX_TICK, 60), 443_580 - 31 * 60);
+        for (tick, tick_spacing) in [(MIN_TICK, 1), (MAX_TICK, 16_384), (12_345, 7)].iter() {
+            let lower = tick_lower_bound(*tick, *tick_spacing);
+            let upper = lower + 31 * i32::from(*tick_spacing);
+            assert!(lower >= MIN_TICK && upper <= MAX_TICK);
+            assert_eq!(lower % i32::from(*tick_spacing), 0);
+        }
+    }
+
+    #[test]
+    fn ticks_cannot_run_out() {
+        let mut pool = test_pool(0);
+        assert_eq!(pool.validate_tick(-170), Err(SwapError::InvalidTick));
+        assert_eq!(pool.validate_tick(160), Err(SwapError::InvalidTick));
+        assert_eq!(pool.validate_tick(-155), Err(SwapError::InvalidTick));
+
+        // fill every slot with the usable ticks
+        let usable_ticks = (0..MAX_TICKS_IN_CONCENTRATED_POOL as i32)
+            .map(|index| pool.tick_lower_bound + index * 10)
+            .collect::<Vec<_>>();
+        for bounds in usable_ticks.chunks_exact(2) {
+            pool.validate_tick(bounds[0]).unwrap();
+            pool.validate_tick(bounds[1]).unwrap();
+            let mut position = test_position(bounds[0], bounds[1]);
+            pool.modify_position(&mut position, 1_000).unwrap();
+        }
+        assert_eq!(pool.ticks().len(), MAX_TICKS_IN_CONCENTRATED_POOL);
+
+        // any other position reuses initialized ticks
+        for (tick_lower, tick_upper) in usable_ticks
+            .iter()
+            .zip(usable_ticks.iter().skip(1))
+            .chain([(&usable_ticks[0], &usable_ticks[31])].iter().cloned())
+        {
+            let mut position = test_position(*tick_lower, *tick_upper);
+            pool.modify_position(&mut position, 1_000).unwrap();
+        }
+        assert_eq!(pool.ticks().len(), MAX_TICKS_IN_CONCENTRATED_POOL);
+
+        // a tick beyond the usable ones would need another slot
+        let mut position = test_position(-20, 170);
+        assert_eq!(
+            pool.modify_position(&mut position, 1_000),
+            Err(SwapError::TooManyTicks)
+        );
+    }
+
+    #[test]
+    fn position_liquidity_minimum() {
+        let mut pool = test_pool(0);
+        let mut position = test_position(-100, 100);
+        assert_eq!(
+            pool.modify_position(&mut position, 999),
+            Err(SwapError::PositionLiquidityTooLow)
+        );
+        assert_eq!(pool.ticks().len(), 0);
+
+        pool.modify_position(&mut position, 1_500).unwrap();
+        // dust cannot be left behind to keep the ticks initialized
+        assert_eq!(
+            pool.modify_position(&mut position, -501),
+            Err(SwapError::PositionLiquidityTooLow)
+        );
+        pool.modify_position(&mut position, -500).unwrap();
+        pool.modify_position(&mut position, -1_000).unwrap();
+        assert_eq!(position.liquidity, 0);
+        assert_eq!(pool.ticks().len(), 0);
+    }
+
+    #[test]
+    fn swap_crosses_ticks() {
+        let mut pool = test_pool(0);
+        let mut wide = test_position(-1_000, 1_000);
+        let mut narrow = test_position(-100, 100);
+        pool.modify_position(&mut wide, 1_000_000_000).unwrap();
+        pool.modify_position(&mut narrow, 4_000_000_000).unwrap();
+        assert_eq!(pool.liquidity, 5_000_000_000);
+
+        // a small swap stays within the narrow range
+        let result = pool.swap(1_000_000, true).unwrap();
+        assert_eq!(result.amount_in, 1_000_000);
+        assert!(pool.tick_current < 0 && pool.tick_current >= -100);
+        assert_eq!(pool.liquidity, 5_000_000_000);
+
+        // a larger one leaves it
+        pool.swap(50_000_000, true).unwrap();
+        assert!(pool.tick_current < -100);
+        assert_eq!(pool.liquidity, 1_000_000_000);
+
+        // and coming back activates it again
+        pool.swap(40_000_000, false).unwrap();
+        assert!(pool.tick_current >= -100);
+        assert_eq!(pool.liquidity, 5_000_000_000);
+
+        // the swap stops once all liquidity is used
+        let result = pool.swap(u64::MAX as u128, false).unwrap();
+        assert!(result.amount_in < u64::MAX as u128);
+        assert_eq!(pool.liquidity, 0);
+        assert_eq!(pool.sqrt_price, MAX_SQRT_PRICE);
+        let result = pool.swap(1_000, false).unwrap();
+        assert_eq!(result.amount_in, 0);
+        assert_eq!(result.amount_out, 0);
+    }
+
+    #[test]
+    fn fees_accrue_in_range() {
+        let mut pool = test_pool(0);
+        let mut in_range = test_position(-100, 100);
+        let mut out_of_range = test_position(100, 200);
+        pool.modify_position(&mut in_range, 1_000_000_000).unwrap();
+        pool.modify_position(&mut out_of_range, 1_000_000_000)
+            .unwrap();
+
+        pool.swap(1_000_000, true).unwrap();
+        pool.swap(1_000_000, false).unwrap();
+        pool.modify_position(&mut in_range, 0).unwrap();
+        pool.modify_position(&mut out_of_range, 0).unwrap();
+
+        // 0.3% of each swap, less rounding
+        assert!(in_range.tokens_owed_a <= 3_000 && in_range.tokens_owed_a >= 2_998);
+        assert!(in_range.tokens_owed_b <= 3_000 && in_range.tokens_owed_b >= 2_998);
+        assert_eq!(out_of_range.tokens_owed_a, 0);
+        assert_eq!(out_of_range.tokens_owed_b, 0);
+    }
+
+    proptest! {
+        #[test]
+        fn sqrt_price_increases_with_tick(tick in MIN_TICK..MAX_TICK) {
+            prop_assert!(sqrt_price_at_tick(tick).unwrap() < sqrt_price_at_tick(tick + 1).unwrap());
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn swap_round_trip_does_not_profit(
+            tick in -10_000..10_000i32,
+            liquidity in 1_000_000..u64::MAX as i128,
+            amount in 1..u64::MAX as u128,
+            a_to_b: bool,
+        ) {
+            let mut pool = test_pool(tick);
+            let mut position = test_position(-20_000, 20_000);
+            pool.modify_position(&mut position, liquidity).unwrap();
+            let result = pool.swap(amount, a_to_b).unwrap();
+            let round_trip = pool.swap(result.amount_out, !a_to_b).unwrap();
+            prop_assert!(round_trip.amount_out <= result.amount_in);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn pool_can_pay_out_all_positions(
+            tick in -10_000..10_000i32,
+            ranges in prop::collection::vec((-2_000..2_000i32, 1..2_000i32, 1_000..u64::MAX as i128), 1..5),
+            swaps in prop::collection::vec((1..u64::MAX as u128, any::<bool>()), 1..5),
+        ) {
+            let mut pool = test_pool(tick);
+            let mut balance_a = 0u128;
+            let mut balance_b = 0u128;
+            let mut positions = vec![];
+            for (tick_lower, width, liquidity) in ranges.iter() {
+                let tick_lower = tick + tick_lower * 10;
+                let mut position = test_position(tick_lower, tick_lower + width * 10);
+                let amounts = pool.modify_position(&mut position, *liquidity).unwrap();
+                balance_a += amounts.token_a_amount;
+                balance_b += amounts.token_b_amount;
+                positions.push((position, *liquidity));
+            }
+            for (amount, a_to_b) in swaps.iter() {
+                let result = pool.swap(*amount, *a_to_b).unwrap();
+                if *a_to_b {
+                    balance_a += result.amount_in;
+                    balance_b = balance_b.checked_sub(result.amount_out).unwrap();
+                } else {
+                    balance_b += result.amount_in;
+                    balance_a = balance_a.checked_sub(result.amount_out).unwrap();
+                }
+            }
+            for (position, liquidity) in positions.iter_mut() {
+                let amounts = pool.modify_position(position, -*liquidity).unwrap();
+                balance_a = balance_a
+                    .checked_sub(amounts.token_a_amount + position.tokens_owed_a as u128)
+                    .unwrap();
+                balance_b = balance_b
+                    .checked_sub(amounts.token_b_amount + position.tokens_owed_b as u128)
+                    .unwrap();
+            }
+            prop_assert_eq!(pool.liquidity, 0);
+            prop_assert_eq!(pool.ticks().len(), 0);
+        }
+    }
+}
@@ -58,6 +58,35 @@ impl CurveCalculator for ConstantPriceCurve {
         })
     }
 
+    /// Buying token A takes the ceiling of the token B needed at the price,
+    /// and buying token B takes exactly the token A needed
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        _swap_source_amount: u128,
+        _swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        let token_b_price = self.token_b_price as u128;
+        let destination_amount = map_zero_to_none(destination_amount)?;
+
+        let source_amount_swapped = match trade_direction {
+            TradeDirection::BtoA => {
+                let source_amount_swapped = destination_amount.checked_div(token_b_price)?;
+                if destination_amount.checked_rem(token_b_price)? > 0 {
+                    source_amount_swapped.checked_add(1)?
+                } else {
+                    source_amount_swapped
+                }
+            }
+            TradeDirection::AtoB => destination_amount.checked_mul(token_b_price)?,
+        };
+        Some(SwapWithoutFeesResult {
+            source_amount_swapped,
+            destination_amount_swapped: destination_amount,
+        })
+    }
+
     /// Get the amount of trading tokens for the given amount of pool tokens,
     /// provided the total trading tokens and supply of pool tokens.
     /// For the constant price curve, the total value of the pool is weighted
@@ -221,8 +250,8 @@ mod tests {
     use super::*;
     use crate::curve::calculator::{
         test::{
-            check_curve_value_from_swap, check_pool_token_conversion, total_and_intermediate,
-            CONVERSION_BASIS_POINTS_GUARANTEE,
+            check_curve_value_from_swap, check_pool_token_conversion, check_swap_exact_out,
+            total_and_intermediate, CONVERSION_BASIS_POINTS_GUARANTEE,
         },
         INITIAL_SWAP_POOL_AMOUNT,
     };
@@ -407,6 +436,69 @@ mod tests {
         }
     }
 
+    #[test]
+    fn swap_exact_out_calculation() {
+        let token_b_price = 1123513u128;
+        let curve = ConstantPriceCurve {
+            token_b_price: token_b_price as u64,
+        };
+        let token_b_amount = 500u128;
+        let token_a_amount = token_b_amount * token_b_price;
+
+        let result = curve
+            .swap_without_fees_exact_out(3, token_a_amount, token_b_amount, TradeDirection::AtoB)
+            .unwrap();
+        assert_eq!(result.source_amount_swapped, 3 * token_b_price);
+        assert_eq!(result.destination_amount_swapped, 3);
+
+        // token B can only be paid in whole tokens, so a little extra token A
+        // is bought
+        let result = curve
+            .swap_without_fees_exact_out(
+                token_b_price + 1,
+                token_b_amount,
+                token_a_amount,
+                TradeDirection::BtoA,
+            )
+            .unwrap();
+        assert_eq!(result.source_amount_swapped, 2);
+        assert_eq!(result.destination_amount_swapped, token_b_price + 1);
+
+        assert!(curve
+            .swap_without_fees_exact_out(0, token_a_amount, token_b_amount, TradeDirection::AtoB)
+            .is_none());
+    }
+
+    proptest! {
+        #[test]
+        fn swap_exact_out_is_minimal(
+            destination_amount in 1..u64::MAX,
+            swap_source_amount in 1..u64::MAX,
+            swap_destination_amount in 1..u64::MAX,
+            token_b_price in 1..u64::MAX,
+        ) {
+            let curve = ConstantPriceCurve { token_b_price };
+            for trade_direction in &[TradeDirection::AtoB, TradeDirection::BtoA] {
+                let source_amount = check_swap_exact_out(
+                    &curve,
+                    destination_amount as u128,
+                    swap_source_amount as u128,
+                    swap_destination_amount as u128,
+                    *trade_direction,
+                );
+                let smaller = curve.swap_without_fees(
+                    source_amount - 1,
+                    swap_source_amount as u128,
+                    swap_destination_amount as u128,
+                    *trade_direction,
+                );
+                if let Some(smaller) = smaller {
+                    prop_assert!(smaller.destination_amount_swapped < destination_amount as u128);
+                }
+            }
+        }
+    }
+
     proptest! {
         #[test]
         fn curve_value_does_not_decrease_from_swap_a_to_b(
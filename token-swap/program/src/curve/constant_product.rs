@@ -46,6 +46,34 @@ pub fn swap(
     })
 }
 
+/// The constant product exact-output swap calculation, the inverse of `swap`,
+/// factored out of its class for reuse.
+///
+/// The new source amount is the ceiling of the invariant divided by the new
+/// destination amount, which is the smallest source amount for which `swap`
+/// provides the destination amount.  The whole destination amount can never
+/// be taken out.
+pub fn swap_exact_out(
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<SwapWithoutFeesResult> {
+    map_zero_to_none(destination_amount)?;
+    let invariant = swap_source_amount.checked_mul(swap_destination_amount)?;
+
+    let new_swap_destination_amount =
+        map_zero_to_none(swap_destination_amount.checked_sub(destination_amount)?)?;
+    let (new_swap_source_amount, _) = invariant.checked_ceil_div(new_swap_destination_amount)?;
+
+    let source_amount_swapped =
+        map_zero_to_none(new_swap_source_amount.checked_sub(swap_source_amount)?)?;
+
+    Some(SwapWithoutFeesResult {
+        source_amount_swapped,
+        destination_amount_swapped: destination_amount,
+    })
+}
+
 /// Get the amount of trading tokens for the given amount of pool tokens,
 /// provided the total trading tokens and supply of pool tokens.
 ///
@@ -152,6 +180,21 @@ impl CurveCalculator for ConstantProductCurve {
         swap(source_amount, swap_source_amount, swap_destination_amount)
     }
 
+    /// Inverse of the constant product swap, x * y = constant
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        _trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        swap_exact_out(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+    }
+
     /// The constant product implementation is a simple ratio calculation for how many
     /// trading tokens correspond to a certain number of pool tokens
     fn pool_tokens_to_trading_tokens(
@@ -232,8 +275,8 @@ mod tests {
     use crate::curve::calculator::{
         test::{
             check_curve_value_from_swap, check_pool_token_conversion,
-            check_pool_value_from_deposit, check_pool_value_from_withdraw, total_and_intermediate,
-            CONVERSION_BASIS_POINTS_GUARANTEE,
+            check_pool_value_from_deposit, check_pool_value_from_withdraw, check_swap_exact_out,
+            total_and_intermediate, CONVERSION_BASIS_POINTS_GUARANTEE,
         },
         RoundDirection, INITIAL_SWAP_POOL_AMOUNT,
     };
@@ -366,6 +409,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn constant_product_swap_exact_out() {
+        let curve = ConstantProductCurve::default();
+
+        // the source amounts from `constant_product_swap_rounding`
+        let tests: &[(u128, u128, u128, u128)] = &[
+            (174_999, 4_000_000, 70_000_000_000, 10),
+            (6, 30_000 - 20, 10_000, 18),
+            (14, 20_000, 30_000, 10),
+            (15, 20_000 - 10, 30_000, 10),
+            (49, 60_000, 30_000, 99),
+            (48, 60_000, 30_000, 97),
+        ];
+        for (
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            expected_source_amount,
+        ) in tests.iter()
+        {
+            let result = curve
+                .swap_without_fees_exact_out(
+                    *destination_amount,
+                    *swap_source_amount,
+                    *swap_destination_amount,
+                    TradeDirection::AtoB,
+                )
+                .unwrap();
+            assert_eq!(result.source_amount_swapped, *expected_source_amount);
+            assert_eq!(result.destination_amount_swapped, *destination_amount);
+        }
+
+        // the whole destination side can't be taken
+        assert!(curve
+            .swap_without_fees_exact_out(30_000, 60_000, 30_000, TradeDirection::AtoB)
+            .is_none());
+        assert!(curve
+            .swap_without_fees_exact_out(0, 60_000, 30_000, TradeDirection::AtoB)
+            .is_none());
+    }
+
+    proptest! {
+        #[test]
+        fn swap_exact_out_is_minimal(
+            swap_source_amount in 1..u64::MAX,
+            (swap_destination_amount, destination_amount) in total_and_intermediate(),
+        ) {
+            let curve = ConstantProductCurve {};
+            let swap_source_amount = swap_source_amount as u128;
+            let swap_destination_amount = swap_destination_amount as u128;
+            let destination_amount = destination_amount as u128;
+            let source_amount = check_swap_exact_out(
+                &curve,
+                destination_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            );
+            // one token less is not enough
+            let smaller = curve.swap_without_fees(
+                source_amount - 1,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            );
+            if let Some(smaller) = smaller {
+                prop_assert!(smaller.destination_amount_swapped < destination_amount);
+            }
+        }
+    }
+
     proptest! {
         #[test]
         fn pool_token_conversion(
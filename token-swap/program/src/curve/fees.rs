@@ -6,6 +6,7 @@ use solana_program::{
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
 };
+use spl_math::uint::U256;
 use std::convert::TryFrom;
 
 /// Encapsulates all fee information and calculations for swap operations
@@ -99,6 +100,58 @@ impl Fees {
         )
     }
 
+    /// Calculate the smallest amount of trading tokens which still leaves the
+    /// given amount once the trading fee and owner trading fee are taken out,
+    /// the inverse of the fee calculation on swaps
+    pub fn pre_trading_fee_amount(&self, post_fee_amount: u128) -> Option<u128> {
+        if post_fee_amount == 0 {
+            return Some(0);
+        }
+        // fraction of the amount left after fees, with a zero fee counted as
+        // 0 / 1 since validation allows a zero denominator
+        let fraction = |numerator: u64, denominator: u64| {
+            if numerator == 0 {
+                (U256::zero(), U256::one())
+            } else {
+                (U256::from(numerator), U256::from(denominator))
+            }
+        };
+        let (trade_fee_numerator, trade_fee_denominator) =
+            fraction(self.trade_fee_numerator, self.trade_fee_denominator);
+        let (owner_fee_numerator, owner_fee_denominator) = fraction(
+            self.owner_trade_fee_numerator,
+            self.owner_trade_fee_denominator,
+        );
+        let denominator = trade_fee_denominator.checked_mul(owner_fee_denominator)?;
+        let remaining = denominator
+            .checked_sub(trade_fee_numerator.checked_mul(owner_fee_denominator)?)?
+            .checked_sub(owner_fee_numerator.checked_mul(trade_fee_denominator)?)?;
+        if remaining.is_zero() {
+            return None;
+        }
+
+        // fees are floored, so the ceiling of the exact amount is enough,
+        // except where the minimum fee of one token applies
+        let dividend = U256::from(post_fee_amount).checked_mul(denominator)?;
+        let mut quotient = dividend.checked_div(remaining)?;
+        if !dividend.checked_rem(remaining)?.is_zero() {
+            quotient = quotient.checked_add(U256::one())?;
+        }
+        let mut amount = u128::try_from(quotient).ok()?;
+        let after_fees = |amount: u128| {
+            amount
+                .checked_sub(self.trading_fee(amount)?)?
+                .checked_sub(self.owner_trading_fee(amount)?)
+        };
+        while after_fees(amount).map_or(true, |left| left < post_fee_amount) {
+            amount = amount.checked_add(1)?;
+        }
+        while after_fees(amount.checked_sub(1)?).map_or(false, |left| left >= post_fee_amount) {
+            amount = amount.checked_sub(1)?;
+        }
+        Some(amount)
+    }
+
     /// Calculate the host fee based on the owner fee, only used in production
     /// situations where a program is hosted by multiple frontends
     pub fn host_fee(&self, owner_fee: u128) -> Option<u128> {
@@ -225,4 +278,54 @@ mod tests {
         let unpacked = Fees::unpack_from_slice(&packed).unwrap();
         assert_eq!(fees, unpacked);
     }
+
+    #[test]
+    fn pre_trading_fee_amount() {
+        let all_fees = [
+            (0, 0, 0, 0),
+            (1, 4, 0, 0),
+            (0, 0, 2, 5),
+            (1, 4, 2, 5),
+            (25, 10_000, 5, 10_000),
+            (1, 1_000_000, 1, 3),
+        ];
+        for (
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+        ) in all_fees.iter()
+        {
+            let fees = Fees {
+                trade_fee_numerator: *trade_fee_numerator,
+                trade_fee_denominator: *trade_fee_denominator,
+                owner_trade_fee_numerator: *owner_trade_fee_numerator,
+                owner_trade_fee_denominator: *owner_trade_fee_denominator,
+                ..Fees::default()
+            };
+            let after_fees = |amount: u128| {
+                amount
+                    .checked_sub(fees.trading_fee(amount).unwrap())
+                    .and_then(|left| left.checked_sub(fees.owner_trading_fee(amount).unwrap()))
+            };
+            for post_fee_amount in (0..2_000).chain(u64::MAX as u128 - 10..u64::MAX as u128) {
+                let amount = fees.pre_trading_fee_amount(post_fee_amount).unwrap();
+                // enough once the fees are taken out, and one less is not
+                assert!(after_fees(amount).unwrap() >= post_fee_amount);
+                if post_fee_amount > 0 {
+                    assert!(after_fees(amount - 1).map_or(true, |left| left < post_fee_amount));
+                }
+            }
+        }
+
+        // fees taking everything leave nothing to swap
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 2,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 2,
+            ..Fees::default()
+        };
+        assert_eq!(fees.pre_trading_fee_amount(100), None);
+    }
 }
@@ -2,9 +2,12 @@
 
 pub mod base;
 pub mod calculator;
+pub mod concentrated;
 pub mod constant_price;
 pub mod constant_product;
 pub mod fees;
 pub mod math;
+pub mod multi_token;
 pub mod offset;
 pub mod stable;
+pub mod weighted;
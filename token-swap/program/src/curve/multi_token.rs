@@ -0,0 +1,772 @@
+//! Calculations for pools holding more than two tokens.
+
+use {
+    crate::{
+        curve::{
+            base::{CurveType, SwapCurve, SwapResult},
+            calculator::{
+                map_zero_to_none, CurveCalculator, RoundDirection, SwapWithoutFeesResult,
+                TradeDirection,
+            },
+            constant_product,
+            fees::Fees,
+            stable::StableCurve,
+            weighted::{WeightedCurve, MAX_WEIGHT_RATIO},
+        },
+        error::SwapError,
+    },
+    solana_program::program_pack::Pack,
+    spl_math::{precise_number::PreciseNumber, uint::U256},
+    std::convert::TryFrom,
+};
+
+/// Minimum number of tokens in a multi-token pool, since two-token pools use
+/// the original swap layout
+pub const MIN_TOKENS_IN_MULTI_TOKEN_POOL: usize = 3;
+
+/// Maximum number of tokens in a multi-token pool
+pub const MAX_TOKENS_IN_MULTI_TOKEN_POOL: usize = 8;
+
+/// Curve used by a multi-token pool, derived from the pool's `SwapCurve` and
+/// the weights of its tokens
+#[derive(Clone, Debug, PartialEq)]
+pub enum MultiTokenCurve {
+    /// Constant product across all tokens, behaving as a weighted curve where
+    /// every token has the same weight
+    ConstantProduct,
+    /// The curve.fi invariant across all tokens
+    Stable {
+        /// Amplifier constant
+        amp: u64,
+    },
+    /// Balancer-style weighted curve across all tokens
+    Weighted {
+        /// Weight of each token, relative to the others
+        weights: Vec<u64>,
+    },
+}
+
+impl MultiTokenCurve {
+    /// Create the multi-token curve for a swap curve and token weights.
+    ///
+    /// Weighted curves must use the first two weights as the weights of their
+    /// calculator, so that the swap curve stays valid between the first two
+    /// tokens.  All other curves must have zero weights.
+    pub fn new(swap_curve: &SwapCurve, weights: &[u64]) -> Result<Self, SwapError> {
+        // The calculator is a trait object, so its parameters are read back
+        // from its packed representation
+        let mut packed_curve = [0u8; SwapCurve::LEN];
+        swap_curve.pack_into_slice(&mut packed_curve);
+        let calculator = &packed_curve[1..];
+        match swap_curve.curve_type {
+            CurveType::ConstantProduct | CurveType::Stable => {
+                if weights.iter().any(|weight| *weight != 0) {
+                    return Err(SwapError::InvalidCurve);
+                }
+                if swap_curve.curve_type == CurveType::ConstantProduct {
+                    Ok(Self::ConstantProduct)
+                } else {
+                    let curve = StableCurve::unpack_from_slice(calculator)
+                        .map_err(|_| SwapError::InvalidCurve)?;
+                    // A ramping amplifier is only supported on two-token swaps
+                    if curve.ramp_stop_ts != 0 {
+                        return Err(SwapError::InvalidCurve);
+                    }
+                    Ok(Self::Stable { amp: curve.amp })
+                }
+            }
+            CurveType::Weighted => {
+                let curve = WeightedCurve::unpack_from_slice(calculator)
+                    .map_err(|_| SwapError::InvalidCurve)?;
+                if weights.get(0) != Some(&curve.token_a_weight)
+                    || weights.get(1) != Some(&curve.token_b_weight)
+                {
+                    return Err(SwapError::InvalidCurve);
+                }
+                Ok(Self::Weighted {
+                    weights: weights.to_vec(),
+                })
+            }
+            _ => Err(SwapError::UnsupportedCurveType),
+        }
+    }
+
+    /// Validate that the curve has no invalid parameters
+    pub fn validate(&self) -> Result<(), SwapError> {
+        match self {
+            Self::ConstantProduct => Ok(()),
+            Self::Stable { amp } => {
+                if *amp == 0 {
+                    Err(SwapError::InvalidCurve)
+                } else {
+                    Ok(())
+                }
+            }
+            Self::Weighted { weights } => {
+                let min_weight = weights.iter().min().copied().unwrap_or(0) as u128;
+                let max_weight = weights.iter().max().copied().unwrap_or(0) as u128;
+                if min_weight == 0 || max_weight > min_weight * MAX_WEIGHT_RATIO as u128 {
+                    Err(SwapError::InvalidCurve)
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Weight of the given token and the total weight of all tokens
+    fn weight_and_total(&self, index: usize, token_count: usize) -> Option<(u128, u128)> {
+        match self {
+            Self::Weighted { weights } => Some((
+                *weights.get(index)? as u128,
+                weights
+                    .iter()
+                    .try_fold(0u128, |total, weight| total.checked_add(*weight as u128))?,
+            )),
+            _ => Some((1, token_count as u128)),
+        }
+    }
+
+    /// Calculate how much of the destination token will be provided given an
+    /// amount of the source token, with `balances` holding the pool's amount
+    /// of every token.
+    pub fn swap_without_fees(
+        &self,
+        source_index: usize,
+        destination_index: usize,
+        source_amount: u128,
+        balances: &[u128],
+    ) -> Option<SwapWithoutFeesResult> {
+        if source_index == destination_index {
+            return None;
+        }
+        let swap_source_amount = *balances.get(source_index)?;
+        let swap_destination_amount = *balances.get(destination_index)?;
+        match self {
+            Self::ConstantProduct => {
+                constant_product::swap(source_amount, swap_source_amount, swap_destination_amount)
+            }
+            Self::Weighted { weights } => WeightedCurve {
+                token_a_weight: *weights.get(source_index)?,
+                token_b_weight: *weights.get(destination_index)?,
+            }
+            .swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            ),
+            Self::Stable { amp } => {
+                let leverage = amp.checked_mul(balances.len() as u64)?;
+                let d_val = compute_d(leverage, balances)?;
+                let mut new_balances = balances.to_vec();
+                new_balances[source_index] = swap_source_amount.checked_add(source_amount)?;
+                let new_destination_amount = compute_new_destination_amount(
+                    leverage,
+                    destination_index,
+                    &new_balances,
+                    d_val,
+                )?;
+                // Subtract one to round in favor of the pool, as curve.fi does
+                let destination_amount_swapped = map_zero_to_none(
+                    swap_destination_amount
+                        .checked_sub(new_destination_amount)?
+                        .checked_sub(1)?,
+                )?;
+                Some(SwapWithoutFeesResult {
+                    source_amount_swapped: source_amount,
+                    destination_amount_swapped,
+                })
+            }
+        }
+    }
+
+    /// Subtract fees and calculate how much destination token will be provided
+    /// given an amount of source token.
+    pub fn swap(
+        &self,
+        source_index: usize,
+        destination_index: usize,
+        source_amount: u128,
+        balances: &[u128],
+        fees: &Fees,
+    ) -> Option<SwapResult> {
+        // debit the fee to calculate the amount swapped
+        let trade_fee = fees.trading_fee(source_amount)?;
+        let owner_fee = fees.owner_trading_fee(source_amount)?;
+
+        let total_fees = trade_fee.checked_add(owner_fee)?;
+        let source_amount_less_fees = source_amount.checked_sub(total_fees)?;
+
+        let SwapWithoutFeesResult {
+            source_amount_swapped,
+            destination_amount_swapped,
+        } = self.swap_without_fees(
+            source_index,
+            destination_index,
+            source_amount_less_fees,
+            balances,
+        )?;
+
+        let source_amount_swapped = source_amount_swapped.checked_add(total_fees)?;
+        Some(SwapResult {
+            new_swap_source_amount: balances[source_index].checked_add(source_amount_swapped)?,
+            new_swap_destination_amount: balances[destination_index]
+                .checked_sub(destination_amount_swapped)?,
+            source_amount_swapped,
+            destination_amount_swapped,
+            trade_fee,
+            owner_fee,
+        })
+    }
+
+    /// Get the amount of pool tokens for the given amount of one token.
+    ///
+    /// The trading fee is charged on the share of the source amount that is
+    /// implicitly swapped for the other tokens, which is the share of the pool
+    /// not made up by the source token.
+    pub fn trading_tokens_to_pool_tokens(
+        &self,
+        index: usize,
+        source_amount: u128,
+        balances: &[u128],
+        pool_supply: u128,
+        round_direction: RoundDirection,
+        fees: &Fees,
+    ) -> Option<u128> {
+        if source_amount == 0 {
+            return Some(0);
+        }
+        let (weight, total_weight) = self.weight_and_total(index, balances.len())?;
+        let swapped_amount = std::cmp::max(
+            1,
+            source_amount
+                .checked_mul(total_weight.checked_sub(weight)?)?
+                .checked_div(total_weight)?,
+        );
+        let trade_fee = fees.trading_fee(swapped_amount)?;
+        let source_amount = source_amount.checked_sub(trade_fee)?;
+        let swap_source_amount = *balances.get(index)?;
+
+        match self {
+            Self::Stable { amp } => {
+                let leverage = amp.checked_mul(balances.len() as u64)?;
+                let d_val = compute_d(leverage, balances)?;
+                let mut new_balances = balances.to_vec();
+                new_balances[index] = swap_source_amount.checked_add(source_amount)?;
+                let new_d_val = compute_d(leverage, &new_balances)?;
+                let pool_tokens = PreciseNumber::new(pool_supply)?
+                    .checked_mul(&PreciseNumber::new(new_d_val.checked_sub(d_val)?)?)?
+                    .checked_div(&PreciseNumber::new(d_val)?)?;
+                match round_direction {
+                    RoundDirection::Floor => pool_tokens.floor()?.to_imprecise(),
+                    RoundDirection::Ceiling => pool_tokens.ceiling()?.to_imprecise(),
+                }
+            }
+            // The weighted formula only depends on the weight of the source
+            // token against the total, so the rest of the pool is represented
+            // as the other side of a two-token weighted curve
+            _ => WeightedCurve {
+                token_a_weight: u64::try_from(weight).ok()?,
+                token_b_weight: u64::try_from(total_weight.checked_sub(weight)?).ok()?,
+            }
+            .trading_tokens_to_pool_tokens(
+                source_amount,
+                swap_source_amount,
+                0,
+                pool_supply,
+                TradeDirection::AtoB,
+                round_direction,
+            ),
+        }
+    }
+
+    /// Calculates the total normalized value of the curve given the balances
+    /// of all tokens, with the dimension of `tokens ^ 1`.
+    ///
+    /// This is the invariant `D` for the stable curve, and the weighted
+    /// geometric mean of the balances for the other curves.
+    pub fn normalized_value(&self, balances: &[u128]) -> Option<PreciseNumber> {
+        match self {
+            Self::Stable { amp } => {
+                let leverage = amp.checked_mul(balances.len() as u64)?;
+                PreciseNumber::new(compute_d(leverage, balances)?)
+            }
+            _ => {
+                if balances.iter().any(|balance| *balance == 0) {
+                    return PreciseNumber::new(0);
+                }
+                let mut weighted_ln_sum = PreciseNumber::new(0)?;
+                for (index, balance) in balances.iter().enumerate() {
+                    let (weight, total_weight) = self.weight_and_total(index, balances.len())?;
+                    let normalized_weight = PreciseNumber::new(weight)?
+                        .checked_div(&PreciseNumber::new(total_weight)?)?;
+                    weighted_ln_sum = weighted_ln_sum.checked_add(
+                        &PreciseNumber::new(*balance)?
+                            .checked_ln()?
+                            .checked_mul(&normalized_weight)?,
+                    )?;
+                }
+                weighted_ln_sum.checked_exp()
+            }
+        }
+    }
+}
+
+/// Get the amount of each token for the given amount of pool tokens, provided
+/// the balances of all tokens and the supply of pool tokens.
+///
+/// Just like the two-token curves, this is a simple ratio for every token.
+pub fn pool_tokens_to_trading_tokens(
+    pool_tokens: u128,
+    pool_token_supply: u128,
+    balances: &[u128],
+    round_direction: RoundDirection,
+) -> Option<Vec<u128>> {
+    balances
+        .iter()
+        .map(|balance| {
+            let product = pool_tokens.checked_mul(*balance)?;
+            let amount = product.checked_div(pool_token_supply)?;
+            match round_direction {
+                RoundDirection::Floor => Some(amount),
+                RoundDirection::Ceiling => {
+                    // Avoid the ceiling on tiny amounts, so that they are
+                    // rejected later in processing
+                    if product.checked_rem(pool_token_supply)? > 0 && amount > 0 {
+                        amount.checked_add(1)
+                    } else {
+                        Some(amount)
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns true if values differ not more than by 1
+fn almost_equal(a: &U256, b: &U256) -> bool {
+    if a > b {
+        *a - *b <= U256::one()
+    } else {
+        *b - *a <= U256::one()
+    }
+}
+
+/// Divide, rounding up
+fn ceil_div(numerator: U256, denominator: U256) -> Option<U256> {
+    numerator
+        .checked_add(denominator.checked_sub(U256::one())?)?
+        .checked_div(denominator)
+}
+
+/// Compute the stable swap invariant (D) for any number of tokens
+/// Equation:
+/// A * sum(x_i) * n**n + D = A * D * n**n + D**(n+1) / (n**n * prod(x_i))
+fn compute_d(leverage: u64, balances: &[u128]) -> Option<u128> {
+    let n_coins = U256::from(balances.len());
+    let sum_x = balances
+        .iter()
+        .try_fold(0u128, |sum, balance| sum.checked_add(*balance))?;
+    if sum_x == 0 {
+        return Some(0);
+    }
+    let balances_times_coins = balances
+        .iter()
+        .map(|balance| U256::from(*balance).checked_mul(n_coins))
+        .collect::<Option<Vec<U256>>>()?;
+    let leverage = U256::from(leverage);
+    let sum_x = U256::from(sum_x);
+
+    // Newton's method to approximate D
+    let mut d_val = sum_x;
+    for _ in 0..64 {
+        let mut d_product = d_val;
+        for balance_times_coins in balances_times_coins.iter() {
+            d_product = d_product
+                .checked_mul(d_val)?
+                .checked_div(*balance_times_coins)?;
+        }
+        let d_previous = d_val;
+        // d = (leverage * sum_x + d_p * n_coins) * d / ((leverage - 1) * d + (n_coins + 1) * d_p)
+        let numerator = leverage
+            .checked_mul(sum_x)?
+            .checked_add(d_product.checked_mul(n_coins)?)?
+            .checked_mul(d_val)?;
+        let denominator = leverage
+            .checked_sub(U256::one())?
+            .checked_mul(d_val)?
+            .checked_add(n_coins.checked_add(U256::one())?.checked_mul(d_product)?)?;
+        d_val = numerator.checked_div(denominator)?;
+        // Equality with the precision of 1
+        if almost_equal(&d_val, &d_previous) {
+            break;
+        }
+    }
+    u128::try_from(d_val).ok()
+}
+
+/// Compute the new amount of the destination token, given the balances of all
+/// tokens after the source amount has been added.
+/// Solve for y:
+/// y**2 + y * (sum' - (A*n**n - 1) * D / (A * n**n)) = D ** (n + 1) / (n ** (2 * n) * prod' * A)
+/// y**2 + b*y = c
+fn compute_new_destination_amount(
+    leverage: u64,
+    destination_index: usize,
+    new_balances: &[u128],
+    d_val: u128,
+) -> Option<u128> {
+    let n_coins = U256::from(new_balances.len());
+    let leverage = U256::from(leverage);
+    let d_val = U256::from(d_val);
+
+    // sum' and prod' exclude the destination token
+    let mut sum = U256::zero();
+    let mut c = d_val;
+    for (index, balance) in new_balances.iter().enumerate() {
+        if index != destination_index {
+            let balance = U256::from(*balance);
+            sum = sum.checked_add(balance)?;
+            c = ceil_div(c.checked_mul(d_val)?, balance.checked_mul(n_coins)?)?;
+        }
+    }
+    // c =  D ** (n + 1) / (n ** (2 * n) * prod' * A), rounded up so that the
+    // new destination amount is never underestimated
+    c = ceil_div(c.checked_mul(d_val)?, leverage.checked_mul(n_coins)?)?;
+    // b = sum' - (A*n**n - 1) * D / (A * n**n)
+    let b = sum.checked_add(d_val.checked_div(leverage)?)?;
+
+    // Solve for y by approximating: y**2 + b*y = c
+    let mut y = d_val;
+    for _ in 0..64 {
+        let y_previous = y;
+        y = y.checked_mul(y)?.checked_add(c)?.checked_div(
+            y.checked_mul(U256::from(2))?
+                .checked_add(b)?
+                .checked_sub(d_val)?,
+        )?;
+        if almost_equal(&y, &y_previous) {
+            break;
+        }
+    }
+    u128::try_from(y).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{
+        calculator::INITIAL_SWAP_POOL_AMOUNT, constant_product::ConstantProductCurve,
+        offset::OffsetCurve,
+    };
+    use proptest::prelude::*;
+    use sim::StableSwapModel;
+
+    const NO_FEES: Fees = Fees {
+        trade_fee_numerator: 0,
+        trade_fee_denominator: 1,
+        owner_trade_fee_numerator: 0,
+        owner_trade_fee_denominator: 1,
+        owner_withdraw_fee_numerator: 0,
+        owner_withdraw_fee_denominator: 1,
+        host_fee_numerator: 0,
+        host_fee_denominator: 1,
+    };
+
+    fn weighted_swap_curve(token_a_weight: u64, token_b_weight: u64) -> SwapCurve {
+        SwapCurve {
+            curve_type: CurveType::Weighted,
+            calculator: Box::new(WeightedCurve {
+                token_a_weight,
+                token_b_weight,
+            }),
+        }
+    }
+
+    #[test]
+    fn new_from_swap_curve() {
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        assert_eq!(
+            MultiTokenCurve::new(&swap_curve, &[0, 0, 0]),
+            Ok(MultiTokenCurve::ConstantProduct)
+        );
+        assert_eq!(
+            MultiTokenCurve::new(&swap_curve, &[0, 1, 0]),
+            Err(SwapError::InvalidCurve)
+        );
+
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::Stable,
+            calculator: Box::new(StableCurve::new(85)),
+        };
+        assert_eq!(
+            MultiTokenCurve::new(&swap_curve, &[0, 0, 0, 0]),
+            Ok(MultiTokenCurve::Stable { amp: 85 })
+        );
+
+        let swap_curve = weighted_swap_curve(50, 30);
+        assert_eq!(
+            MultiTokenCurve::new(&swap_curve, &[50, 30, 20]),
+            Ok(MultiTokenCurve::Weighted {
+                weights: vec![50, 30, 20]
+            })
+        );
+        assert_eq!(
+            MultiTokenCurve::new(&swap_curve, &[30, 50, 20]),
+            Err(SwapError::InvalidCurve)
+        );
+
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::Offset,
+            calculator: Box::new(OffsetCurve {
+                token_b_offset: 1_000,
+            }),
+        };
+        assert_eq!(
+            MultiTokenCurve::new(&swap_curve, &[0, 0, 0]),
+            Err(SwapError::UnsupportedCurveType)
+        );
+    }
+
+    #[test]
+    fn validate_curve() {
+        assert_eq!(MultiTokenCurve::ConstantProduct.validate(), Ok(()));
+        assert_eq!(MultiTokenCurve::Stable { amp: 1 }.validate(), Ok(()));
+        assert_eq!(
+            MultiTokenCurve::Stable { amp: 0 }.validate(),
+            Err(SwapError::InvalidCurve)
+        );
+        let valid = [vec![1, 1, 1], vec![49, 1, 25], vec![60, 20, 10, 10]];
+        for weights in valid.iter() {
+            let curve = MultiTokenCurve::Weighted {
+                weights: weights.clone(),
+            };
+            assert_eq!(curve.validate(), Ok(()));
+        }
+        let invalid = [vec![1, 1, 0], vec![50, 1, 25], vec![u64::MAX, 1, 1]];
+        for weights in invalid.iter() {
+            let curve = MultiTokenCurve::Weighted {
+                weights: weights.clone(),
+            };
+            assert_eq!(curve.validate(), Err(SwapError::InvalidCurve));
+        }
+    }
+
+    #[test]
+    fn trading_token_conversion() {
+        let results =
+            pool_tokens_to_trading_tokens(5, 10, &[2, 49, 100], RoundDirection::Ceiling).unwrap();
+        assert_eq!(results, vec![1, 25, 50]);
+        let results =
+            pool_tokens_to_trading_tokens(5, 10, &[2, 49, 100], RoundDirection::Floor).unwrap();
+        assert_eq!(results, vec![1, 24, 50]);
+        assert!(
+            pool_tokens_to_trading_tokens(5, 10, &[1, u128::MAX, 1], RoundDirection::Floor)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn weighted_swap_calculation() {
+        let curve = MultiTokenCurve::Weighted {
+            weights: vec![40, 10, 50],
+        };
+        let balances = [1000, 1000, 1000];
+        // 1000 * (1 - (1000 / 1100) ^ 4) = 316.98654
+        let result = curve.swap_without_fees(0, 1, 100, &balances).unwrap();
+        assert_eq!(result.destination_amount_swapped, 316);
+        // 1000 * (1 - (1000 / 1100) ^ (4 / 5)) = 73.41141
+        let result = curve.swap_without_fees(0, 2, 100, &balances).unwrap();
+        assert_eq!(result.destination_amount_swapped, 73);
+        assert!(curve.swap_without_fees(1, 1, 100, &balances).is_none());
+        assert!(curve.swap_without_fees(0, 3, 100, &balances).is_none());
+    }
+
+    #[test]
+    fn single_token_deposit() {
+        // each token is a third of the pool, so depositing 1/3 of one token
+        // gives supply * ((4 / 3) ^ (1 / 3) - 1) = 100_642_416.30 pool tokens
+        let balances = [3_000_000, 3_000_000, 3_000_000];
+        let pool_tokens = MultiTokenCurve::ConstantProduct
+            .trading_tokens_to_pool_tokens(
+                1,
+                1_000_000,
+                &balances,
+                INITIAL_SWAP_POOL_AMOUNT,
+                RoundDirection::Floor,
+                &NO_FEES,
+            )
+            .unwrap();
+        assert_eq!(pool_tokens, 100_642_416);
+
+        // the stable curve gives almost exactly the share of the deposit in
+        // a balanced pool
+        let pool_tokens = MultiTokenCurve::Stable { amp: 100 }
+            .trading_tokens_to_pool_tokens(
+                1,
+                30_000,
+                &balances,
+                INITIAL_SWAP_POOL_AMOUNT,
+                RoundDirection::Floor,
+                &NO_FEES,
+            )
+            .unwrap();
+        assert!(pool_tokens < INITIAL_SWAP_POOL_AMOUNT / 300);
+        assert!(pool_tokens > INITIAL_SWAP_POOL_AMOUNT / 300 * 999 / 1000);
+    }
+
+    proptest! {
+        #[test]
+        fn stable_swap_matches_model(
+            base in 100..1_000_000_000_000_000u128,
+            ratios in prop::collection::vec(1..100u128, 3..=8),
+            source_amount in 100..100_000_000_000u128,
+            amp in 1..150u64,
+        ) {
+            // the model itself breaks down on extremely imbalanced pools
+            let balances = ratios.iter().map(|ratio| base * ratio).collect::<Vec<_>>();
+            prop_assume!(source_amount < balances[0]);
+            let curve = MultiTokenCurve::Stable { amp };
+            let destination_index = balances.len() - 1;
+            let model = StableSwapModel::new(amp.into(), balances.clone(), balances.len() as u8);
+
+            let result = curve.swap_without_fees(0, destination_index, source_amount, &balances);
+            let sim_result = model.sim_exchange(0, destination_index as u128, source_amount);
+            let destination_amount_swapped = result.map_or(0, |r| r.destination_amount_swapped);
+
+            // one less for rounding in favor of the pool, with the precision of
+            // 1, and up to one more for rounding up each of the balances in `c`
+            let diff = sim_result as i128 - destination_amount_swapped as i128;
+            prop_assert!((0..=balances.len() as i128).contains(&diff), "result={}, sim_result={}", destination_amount_swapped, sim_result);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn curve_value_does_not_decrease_from_swap(
+            balances in prop::collection::vec(1_000_000..u64::MAX as u128, 3..=8),
+            weights in prop::collection::vec(1..MAX_WEIGHT_RATIO, 8),
+            source_amount in 1..u64::MAX as u128,
+        ) {
+            let weights = weights[..balances.len()].to_vec();
+            let destination_index = balances.len() - 1;
+            let source_amount = source_amount % (balances[0] / 2) + 1;
+
+            // compare the invariant before and after as a ratio, which is
+            // close to 1 and needs no more than floating point precision
+            for curve in [MultiTokenCurve::ConstantProduct, MultiTokenCurve::Weighted { weights: weights.clone() }].iter() {
+                if let Some(result) = curve.swap_without_fees(0, destination_index, source_amount, &balances) {
+                    let (source_weight, total_weight) = curve.weight_and_total(0, balances.len()).unwrap();
+                    let (destination_weight, _) = curve.weight_and_total(destination_index, balances.len()).unwrap();
+                    let source_ratio = (balances[0] + source_amount) as f64 / balances[0] as f64;
+                    let destination_ratio = (balances[destination_index] - result.destination_amount_swapped) as f64
+                        / balances[destination_index] as f64;
+                    let invariant_ratio = source_ratio.powf(source_weight as f64 / total_weight as f64)
+                        * destination_ratio.powf(destination_weight as f64 / total_weight as f64);
+                    prop_assert!(invariant_ratio >= 1.0 - 1e-14);
+                }
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn curve_value_per_pool_token_does_not_decrease_from_deposit(
+            balances in prop::collection::vec(1_000_000..u64::MAX as u128, 3..=8),
+            weights in prop::collection::vec(1..MAX_WEIGHT_RATIO, 8),
+            source_amount in 1..u64::MAX as u128,
+            pool_supply in INITIAL_SWAP_POOL_AMOUNT..u64::MAX as u128,
+        ) {
+            let weights = weights[..balances.len()].to_vec();
+            let source_amount = source_amount % (balances[0] / 2) + 1;
+            let mut new_balances = balances.clone();
+            new_balances[0] += source_amount;
+
+            // compare the value per pool token before and after as a ratio,
+            // which is close to 1 and needs no more than floating point
+            // precision, allowing for the approximation in
+            // `checked_pow_fraction`
+            let pool_token_ratio = |pool_tokens: u128| {
+                (pool_supply + pool_tokens) as f64 / pool_supply as f64
+            };
+            let curves = [
+                MultiTokenCurve::ConstantProduct,
+                MultiTokenCurve::Weighted { weights },
+            ];
+            for curve in curves.iter() {
+                let pool_tokens = curve.trading_tokens_to_pool_tokens(
+                    0,
+                    source_amount,
+                    &balances,
+                    pool_supply,
+                    RoundDirection::Floor,
+                    &NO_FEES,
+                ).unwrap();
+                let (weight, total_weight) = curve.weight_and_total(0, balances.len()).unwrap();
+                let value_ratio = (new_balances[0] as f64 / balances[0] as f64)
+                    .powf(weight as f64 / total_weight as f64);
+                prop_assert!(value_ratio >= pool_token_ratio(pool_tokens) * (1.0 - 1e-10));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn stable_curve_value_does_not_decrease_from_swap(
+            base in 1_000_000..1_000_000_000_000_000u128,
+            ratios in prop::collection::vec(1..100u128, 3..=8),
+            source_amount in 1..u64::MAX as u128,
+            amp in 1..150u64,
+        ) {
+            // the stable curve is meant for pools of similarly priced tokens,
+            // so balances stay within a factor of 100 of each other
+            let balances = ratios.iter().map(|ratio| base * ratio).collect::<Vec<_>>();
+            let destination_index = balances.len() - 1;
+            let source_amount = source_amount % (balances[0] / 2) + 1;
+            let curve = MultiTokenCurve::Stable { amp };
+            if let Some(result) = curve.swap_without_fees(0, destination_index, source_amount, &balances) {
+                let mut new_balances = balances.clone();
+                new_balances[0] += source_amount;
+                new_balances[destination_index] -= result.destination_amount_swapped;
+                let previous_value = curve.normalized_value(&balances).unwrap();
+                let new_value = curve.normalized_value(&new_balances).unwrap();
+                prop_assert!(new_value.greater_than_or_equal(&previous_value));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn stable_curve_value_per_pool_token_does_not_decrease_from_deposit(
+            base in 1_000_000..1_000_000_000_000_000u128,
+            ratios in prop::collection::vec(1..100u128, 3..=8),
+            source_amount in 1..u64::MAX as u128,
+            pool_supply in INITIAL_SWAP_POOL_AMOUNT..u64::MAX as u128,
+            amp in 1..150u64,
+        ) {
+            let balances = ratios.iter().map(|ratio| base * ratio).collect::<Vec<_>>();
+            let source_amount = source_amount % (balances[0] / 2) + 1;
+            let mut new_balances = balances.clone();
+            new_balances[0] += source_amount;
+
+            let curve = MultiTokenCurve::Stable { amp };
+            let pool_tokens = curve.trading_tokens_to_pool_tokens(
+                0,
+                source_amount,
+                &balances,
+                pool_supply,
+                RoundDirection::Floor,
+                &NO_FEES,
+            ).unwrap();
+            let previous_value = curve.normalized_value(&balances).unwrap().to_imprecise().unwrap();
+            let new_value = curve.normalized_value(&new_balances).unwrap().to_imprecise().unwrap();
+            let value_ratio = new_value as f64 / previous_value as f64;
+            let pool_token_ratio = (pool_supply + pool_tokens) as f64 / pool_supply as f64;
+            prop_assert!(value_ratio >= pool_token_ratio * (1.0 - 1e-14));
+        }
+    }
+}
@@ -8,7 +8,7 @@ use {
                 TradingTokenResult,
             },
             constant_product::{
-                normalized_value, pool_tokens_to_trading_tokens, swap,
+                normalized_value, pool_tokens_to_trading_tokens, swap, swap_exact_out,
                 trading_tokens_to_pool_tokens,
             },
         },
@@ -56,6 +56,34 @@ impl CurveCalculator for OffsetCurve {
         swap(source_amount, swap_source_amount, swap_destination_amount)
     }
 
+    /// Inverse of the constant product swap with the offset.  Only tokens
+    /// actually held by the pool can be taken out, never the offset.
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        if destination_amount >= swap_destination_amount {
+            return None;
+        }
+        let token_b_offset = self.token_b_offset as u128;
+        let swap_source_amount = match trade_direction {
+            TradeDirection::AtoB => swap_source_amount,
+            TradeDirection::BtoA => swap_source_amount.checked_add(token_b_offset)?,
+        };
+        let swap_destination_amount = match trade_direction {
+            TradeDirection::AtoB => swap_destination_amount.checked_add(token_b_offset)?,
+            TradeDirection::BtoA => swap_destination_amount,
+        };
+        swap_exact_out(
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+        )
+    }
+
     /// The conversion for the offset curve needs to take into account the
     /// offset
     fn pool_tokens_to_trading_tokens(
@@ -171,8 +199,8 @@ mod tests {
     use crate::curve::calculator::{
         test::{
             check_curve_value_from_swap, check_pool_token_conversion,
-            check_pool_value_from_deposit, check_pool_value_from_withdraw, total_and_intermediate,
-            CONVERSION_BASIS_POINTS_GUARANTEE,
+            check_pool_value_from_deposit, check_pool_value_from_withdraw, check_swap_exact_out,
+            total_and_intermediate, CONVERSION_BASIS_POINTS_GUARANTEE,
         },
         INITIAL_SWAP_POOL_AMOUNT,
     };
@@ -287,6 +315,54 @@ mod tests {
         assert_eq!(result.destination_amount_swapped, 499);
     }
 
+    #[test]
+    fn swap_exact_out_offset() {
+        let swap_source_amount: u128 = 10_000_000;
+        let swap_destination_amount: u128 = 1_000;
+        let token_b_offset = u64::MAX;
+        let curve = OffsetCurve { token_b_offset };
+
+        // the offset makes token B cheap, but can't be taken out
+        let result = curve
+            .swap_without_fees_exact_out(
+                999,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            )
+            .unwrap();
+        assert_eq!(result.source_amount_swapped, 1);
+        assert_eq!(result.destination_amount_swapped, 999);
+        assert!(curve
+            .swap_without_fees_exact_out(
+                1_000,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            )
+            .is_none());
+
+        // and makes token A expensive
+        let result = curve
+            .swap_without_fees_exact_out(
+                499,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::BtoA,
+            )
+            .unwrap();
+        assert!(result.source_amount_swapped <= 18_373_104_376_818_475_561);
+        let forward = curve
+            .swap_without_fees(
+                result.source_amount_swapped,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::BtoA,
+            )
+            .unwrap();
+        assert_eq!(forward.destination_amount_swapped, 499);
+    }
+
     prop_compose! {
         pub fn values_sum_within_u64()(total in 1..u64::MAX)
                         (amount in 1..total, total in Just(total))
@@ -409,6 +485,54 @@ mod tests {
         }
     }
 
+    proptest! {
+        #[test]
+        fn swap_exact_out_a_to_b(
+            swap_source_amount in 1..u64::MAX,
+            (swap_destination_amount, destination_amount) in total_and_intermediate(),
+            token_b_offset in 1..u64::MAX,
+        ) {
+            let curve = OffsetCurve { token_b_offset };
+            let swap_source_amount = swap_source_amount as u128;
+            let swap_destination_amount = swap_destination_amount as u128;
+            let token_b_offset = token_b_offset as u128;
+
+            // The invariant needs to fit in a u128
+            prop_assume!(!(swap_destination_amount + token_b_offset).overflowing_mul(swap_source_amount).1);
+            check_swap_exact_out(
+                &curve,
+                destination_amount as u128,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn swap_exact_out_b_to_a(
+            swap_source_amount in 1..u64::MAX,
+            (swap_destination_amount, destination_amount) in total_and_intermediate(),
+            token_b_offset in 1..u64::MAX,
+        ) {
+            let curve = OffsetCurve { token_b_offset };
+            let swap_source_amount = swap_source_amount as u128;
+            let swap_destination_amount = swap_destination_amount as u128;
+            let token_b_offset = token_b_offset as u128;
+
+            // The invariant needs to fit in a u128
+            prop_assume!(!(swap_source_amount + token_b_offset).overflowing_mul(swap_destination_amount).1);
+            check_swap_exact_out(
+                &curve,
+                destination_amount as u128,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::BtoA,
+            );
+        }
+    }
+
     proptest! {
         #[test]
         fn curve_value_does_not_decrease_from_swap_b_to_a(
@@ -2,26 +2,38 @@
 
 use crate::error::SwapError;
 use solana_program::{
+    clock::UnixTimestamp,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
 };
 
 use crate::curve::{
     calculator::{
-        CurveCalculator, DynPack, RoundDirection, SwapWithoutFeesResult, TradeDirection,
-        TradingTokenResult,
+        map_zero_to_none, minimum_source_amount, CurveCalculator, DynPack, RoundDirection,
+        SwapWithoutFeesResult, TradeDirection, TradingTokenResult,
     },
     constant_product::{
         normalized_value, pool_tokens_to_trading_tokens, trading_tokens_to_pool_tokens,
     },
 };
-use arrayref::{array_mut_ref, array_ref};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use spl_math::{precise_number::PreciseNumber, uint::U256};
 use std::convert::TryFrom;
 
 const N_COINS: u8 = 2;
 const N_COINS_SQUARED: u8 = 4;
 
+/// Minimum amplification coefficient
+pub const MIN_AMP: u64 = 1;
+/// Maximum amplification coefficient
+pub const MAX_AMP: u64 = 1_000_000;
+/// Maximum factor by which a single ramp may raise or lower the amplification
+/// coefficient
+pub const MAX_AMP_CHANGE: u64 = 10;
+/// Minimum duration of a ramp, and minimum time between the starts of two
+/// ramps, in seconds
+pub const MIN_RAMP_DURATION: UnixTimestamp = 86_400;
+
 /// Returns self to the power of b
 fn checked_u8_power(a: &U256, b: u8) -> Option<U256> {
     let mut result = *a;
@@ -50,10 +62,112 @@ fn almost_equal(a: &U256, b: &U256) -> Option<bool> {
 }
 
 /// StableCurve struct implementing CurveCalculator
+///
+/// The amplifier moves linearly from `amp` to `target_amp` between
+/// `ramp_start_ts` and `ramp_stop_ts`.  A curve that has never been ramped has
+/// a `ramp_stop_ts` of 0 and always uses `amp`.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct StableCurve {
-    /// Amplifier constant
+    /// Amplifier constant, or amplifier at the start of the ramp
     pub amp: u64,
+    /// Amplifier at the end of the ramp
+    pub target_amp: u64,
+    /// Unix timestamp at which the ramp starts
+    pub ramp_start_ts: UnixTimestamp,
+    /// Unix timestamp at which the ramp stops
+    pub ramp_stop_ts: UnixTimestamp,
+}
+
+impl StableCurve {
+    /// Create a stable curve with a constant amplifier
+    pub const fn new(amp: u64) -> Self {
+        Self {
+            amp,
+            target_amp: 0,
+            ramp_start_ts: 0,
+            ramp_stop_ts: 0,
+        }
+    }
+
+    /// Amplifier in effect at the given time
+    pub fn amp_at(&self, timestamp: UnixTimestamp) -> Option<u64> {
+        if self.ramp_stop_ts == 0 || timestamp <= self.ramp_start_ts {
+            Some(self.amp)
+        } else if timestamp >= self.ramp_stop_ts {
+            Some(self.target_amp)
+        } else {
+            let elapsed = i128::from(timestamp.checked_sub(self.ramp_start_ts)?);
+            let duration = i128::from(self.ramp_stop_ts.checked_sub(self.ramp_start_ts)?);
+            let amp = i128::from(self.amp);
+            let change = i128::from(self.target_amp).checked_sub(amp)?;
+            let amp = amp.checked_add(change.checked_mul(elapsed)?.checked_div(duration)?)?;
+            u64::try_from(amp).ok()
+        }
+    }
+
+    /// Leverage of a constant amplifier.  The amplifier of a curve that has
+    /// been ramped depends on the time, so the curve must first be fixed at a
+    /// timestamp with `SwapCurve::fix_at`.
+    fn leverage(&self) -> Option<u64> {
+        if self.ramp_stop_ts != 0 {
+            return None;
+        }
+        self.amp.checked_mul(N_COINS as u64)
+    }
+
+    /// Start ramping from the current amplifier to `target_amp`, reaching it at
+    /// `stop_ramp_ts`
+    pub fn ramp(
+        &self,
+        target_amp: u64,
+        now: UnixTimestamp,
+        stop_ramp_ts: UnixTimestamp,
+    ) -> Result<Self, SwapError> {
+        if self.ramp_stop_ts != 0 && now < self.ramp_start_ts.saturating_add(MIN_RAMP_DURATION) {
+            return Err(SwapError::InvalidRamp);
+        }
+        if stop_ramp_ts < now.saturating_add(MIN_RAMP_DURATION) {
+            return Err(SwapError::InvalidRamp);
+        }
+        if !(MIN_AMP..=MAX_AMP).contains(&target_amp) {
+            return Err(SwapError::InvalidRamp);
+        }
+        let amp = self.amp_at(now).ok_or(SwapError::CalculationFailure)?;
+        if target_amp > amp.saturating_mul(MAX_AMP_CHANGE)
+            || target_amp.saturating_mul(MAX_AMP_CHANGE) < amp
+        {
+            return Err(SwapError::InvalidRamp);
+        }
+        Ok(Self {
+            amp,
+            target_amp,
+            ramp_start_ts: now,
+            ramp_stop_ts: stop_ramp_ts,
+        })
+    }
+
+    /// Stop ramping, keeping the amplifier in effect at `now`
+    pub fn stop_ramp(&self, now: UnixTimestamp) -> Result<Self, SwapError> {
+        if self.ramp_stop_ts == 0 {
+            Ok(self.clone())
+        } else {
+            let amp = self.amp_at(now).ok_or(SwapError::CalculationFailure)?;
+            Ok(Self {
+                amp,
+                target_amp: amp,
+                ramp_start_ts: self.ramp_start_ts.min(now),
+                ramp_stop_ts: now,
+            })
+        }
+    }
+
+    /// Stable swap invariant (D) of the given amounts, for a constant
+    /// amplifier.  Swaps never lower it by more than the rounding of the
+    /// amounts swapped.
+    pub fn invariant(&self, swap_token_a_amount: u128, swap_token_b_amount: u128) -> Option<u128> {
+        let leverage = self.leverage()?;
+        compute_d(leverage, swap_token_a_amount, swap_token_b_amount)
+    }
 }
 
 /// d = (leverage * sum_x + d_product * n_coins) * initial_d / ((leverage - 1) * initial_d + (n_coins + 1) * d_product)
@@ -150,7 +264,7 @@ impl CurveCalculator for StableCurve {
         swap_destination_amount: u128,
         _trade_direction: TradeDirection,
     ) -> Option<SwapWithoutFeesResult> {
-        let leverage = self.amp.checked_mul(N_COINS as u64)?;
+        let leverage = self.leverage()?;
 
         let new_source_amount = swap_source_amount.checked_add(source_amount)?;
         let new_destination_amount = compute_new_destination_amount(
@@ -167,6 +281,44 @@ impl CurveCalculator for StableCurve {
         })
     }
 
+    /// The invariant is symmetric, so the new source amount is solved from
+    /// the new destination amount the same way as the other way around.
+    /// Since both are approximations, the result is corrected against
+    /// `swap_without_fees`.
+    fn swap_without_fees_exact_out(
+        &self,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+        trade_direction: TradeDirection,
+    ) -> Option<SwapWithoutFeesResult> {
+        let leverage = self.leverage()?;
+        let destination_amount = map_zero_to_none(destination_amount)?;
+
+        let new_destination_amount =
+            map_zero_to_none(swap_destination_amount.checked_sub(destination_amount)?)?;
+        let new_source_amount = compute_new_destination_amount(
+            leverage,
+            new_destination_amount,
+            compute_d(leverage, swap_source_amount, swap_destination_amount)?,
+        )?;
+        let estimate = new_source_amount.saturating_sub(swap_source_amount);
+
+        let source_amount_swapped = minimum_source_amount(
+            self,
+            estimate,
+            u128::MAX,
+            destination_amount,
+            swap_source_amount,
+            swap_destination_amount,
+            trade_direction,
+        )?;
+        Some(SwapWithoutFeesResult {
+            source_amount_swapped,
+            destination_amount_swapped: destination_amount,
+        })
+    }
+
     fn pool_tokens_to_trading_tokens(
         &self,
         pool_tokens: u128,
@@ -213,7 +365,15 @@ impl CurveCalculator for StableCurve {
     }
 
     fn validate(&self) -> Result<(), SwapError> {
-        // TODO are all amps valid?
+        if !(MIN_AMP..=MAX_AMP).contains(&self.amp) {
+            return Err(SwapError::InvalidCurve);
+        }
+        if self.ramp_stop_ts != 0
+            && (!(MIN_AMP..=MAX_AMP).contains(&self.target_amp)
+                || self.ramp_start_ts > self.ramp_stop_ts)
+        {
+            return Err(SwapError::InvalidCurve);
+        }
         Ok(())
     }
 }
@@ -226,40 +386,69 @@ impl IsInitialized for StableCurve {
 }
 impl Sealed for StableCurve {}
 impl Pack for StableCurve {
-    const LEN: usize = 8;
+    const LEN: usize = 32;
     fn pack_into_slice(&self, output: &mut [u8]) {
         (self as &dyn DynPack).pack_into_slice(output);
     }
 
     fn unpack_from_slice(input: &[u8]) -> Result<StableCurve, ProgramError> {
-        let amp = array_ref![input, 0, 8];
+        let input = array_ref![input, 0, 32];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (amp, target_amp, ramp_start_ts, ramp_stop_ts) = array_refs![input, 8, 8, 8, 8];
         Ok(Self {
             amp: u64::from_le_bytes(*amp),
+            target_amp: u64::from_le_bytes(*target_amp),
+            ramp_start_ts: i64::from_le_bytes(*ramp_start_ts),
+            ramp_stop_ts: i64::from_le_bytes(*ramp_stop_ts),
         })
     }
 }
 
 impl DynPack for StableCurve {
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let amp = array_mut_ref![output, 0, 8];
+        let output = array_mut_ref![output, 0, 32];
+        let (amp, target_amp, ramp_start_ts, ramp_stop_ts) = mut_array_refs![output, 8, 8, 8, 8];
         *amp = self.amp.to_le_bytes();
+        *target_amp = self.target_amp.to_le_bytes();
+        *ramp_start_ts = self.ramp_start_ts.to_le_bytes();
+        *ramp_stop_ts = self.ramp_stop_ts.to_le_bytes();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::curve::calculator::{RoundDirection, INITIAL_SWAP_POOL_AMOUNT};
+    use crate::curve::{
+        base::{CurveType, SwapCurve},
+        calculator::{test::check_swap_exact_out, RoundDirection, INITIAL_SWAP_POOL_AMOUNT},
+    };
     use proptest::prelude::*;
     use sim::StableSwapModel;
 
     #[test]
     fn initial_pool_amount() {
         let amp = 1;
-        let calculator = StableCurve { amp };
+        let calculator = StableCurve::new(amp);
         assert_eq!(calculator.new_pool_supply(), INITIAL_SWAP_POOL_AMOUNT);
     }
 
+    #[test]
+    fn invariant() {
+        let curve = StableCurve::new(100);
+        // a balanced pool is worth the sum of its tokens
+        assert_eq!(curve.invariant(1_000_000, 1_000_000), Some(2_000_000));
+        let result = curve
+            .swap_without_fees(10_000, 1_000_000, 1_000_000, TradeDirection::AtoB)
+            .unwrap();
+        let invariant = curve
+            .invariant(
+                1_010_000 + 1,
+                1_000_000 - result.destination_amount_swapped + 1,
+            )
+            .unwrap();
+        assert!(invariant >= 2_000_000);
+    }
+
     fn check_pool_token_rate(
         token_a: u128,
         token_b: u128,
@@ -269,7 +458,7 @@ mod tests {
         expected_b: u128,
     ) {
         let amp = 1;
-        let calculator = StableCurve { amp };
+        let calculator = StableCurve::new(amp);
         let results = calculator
             .pool_tokens_to_trading_tokens(
                 deposit,
@@ -293,7 +482,7 @@ mod tests {
     #[test]
     fn fail_trading_token_conversion() {
         let amp = 1;
-        let calculator = StableCurve { amp };
+        let calculator = StableCurve::new(amp);
         let results =
             calculator.pool_tokens_to_trading_tokens(5, 10, u128::MAX, 0, RoundDirection::Floor);
         assert!(results.is_none());
@@ -312,7 +501,7 @@ mod tests {
         ) {
             prop_assume!(source_amount < swap_source_amount);
 
-            let curve = StableCurve { amp };
+            let curve = StableCurve::new(amp);
 
             let model: StableSwapModel = StableSwapModel::new(
                 curve.amp.into(),
@@ -346,10 +535,49 @@ mod tests {
         }
     }
 
+    proptest! {
+        #[test]
+        fn swap_exact_out_inverts_swap(
+            swap_source_amount in 100..1_000_000_000_000_000_000u128,
+            swap_destination_amount in 100..1_000_000_000_000_000_000u128,
+            source_amount in 100..100_000_000_000u128,
+            amp in 1..150u64
+        ) {
+            prop_assume!(source_amount < swap_source_amount);
+
+            let curve = StableCurve::new(amp);
+            let result = curve.swap_without_fees(
+                source_amount,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            ).unwrap();
+            prop_assume!(result.destination_amount_swapped > 0);
+
+            // asking for the output of a swap costs at most its input
+            let exact_out_source_amount = check_swap_exact_out(
+                &curve,
+                result.destination_amount_swapped,
+                swap_source_amount,
+                swap_destination_amount,
+                TradeDirection::AtoB,
+            );
+            prop_assert!(exact_out_source_amount <= source_amount);
+        }
+    }
+
     #[test]
     fn pack_curve() {
         let amp = 1;
-        let curve = StableCurve { amp };
+        let target_amp = 10;
+        let ramp_start_ts: i64 = 1_000;
+        let ramp_stop_ts: i64 = 100_000;
+        let curve = StableCurve {
+            amp,
+            target_amp,
+            ramp_start_ts,
+            ramp_stop_ts,
+        };
 
         let mut packed = [0u8; StableCurve::LEN];
         Pack::pack_into_slice(&curve, &mut packed[..]);
@@ -358,7 +586,139 @@ mod tests {
 
         let mut packed = vec![];
         packed.extend_from_slice(&amp.to_le_bytes());
+        packed.extend_from_slice(&target_amp.to_le_bytes());
+        packed.extend_from_slice(&ramp_start_ts.to_le_bytes());
+        packed.extend_from_slice(&ramp_stop_ts.to_le_bytes());
         let unpacked = StableCurve::unpack(&packed).unwrap();
         assert_eq!(curve, unpacked);
     }
+
+    #[test]
+    fn amp_ramps_linearly() {
+        let curve = StableCurve::new(100);
+        assert_eq!(curve.amp_at(-1), Some(100));
+        assert_eq!(curve.amp_at(i64::MAX), Some(100));
+        assert_eq!(curve.leverage(), Some(200));
+
+        let up = curve
+            .ramp(200, 1_000, 1_000 + 4 * MIN_RAMP_DURATION)
+            .unwrap();
+        assert_eq!(up.amp_at(0), Some(100));
+        assert_eq!(up.amp_at(1_000), Some(100));
+        assert_eq!(up.amp_at(1_000 + MIN_RAMP_DURATION), Some(125));
+        assert_eq!(up.amp_at(1_000 + 2 * MIN_RAMP_DURATION), Some(150));
+        assert_eq!(up.amp_at(1_000 + 4 * MIN_RAMP_DURATION), Some(200));
+        assert_eq!(up.amp_at(i64::MAX), Some(200));
+
+        let down = curve.ramp(20, 0, 2 * MIN_RAMP_DURATION).unwrap();
+        assert_eq!(down.amp_at(MIN_RAMP_DURATION), Some(60));
+        assert_eq!(down.amp_at(2 * MIN_RAMP_DURATION), Some(20));
+
+        let stopped = up.stop_ramp(1_000 + 2 * MIN_RAMP_DURATION).unwrap();
+        assert_eq!(stopped.amp_at(0), Some(150));
+        assert_eq!(stopped.amp_at(1_000 + 2 * MIN_RAMP_DURATION), Some(150));
+        assert_eq!(stopped.amp_at(i64::MAX), Some(150));
+        assert_eq!(stopped.validate(), Ok(()));
+        assert_eq!(curve.stop_ramp(5), Ok(curve.clone()));
+    }
+
+    #[test]
+    fn ramped_curve_is_fixed_before_use() {
+        let up = StableCurve::new(100)
+            .ramp(200, 1_000, 1_000 + 4 * MIN_RAMP_DURATION)
+            .unwrap();
+        assert_eq!(up.leverage(), None);
+        assert_eq!(
+            up.swap_without_fees(1_000, 100_000, 100_000, TradeDirection::AtoB),
+            None
+        );
+
+        let mut swap_curve = SwapCurve {
+            curve_type: CurveType::Stable,
+            calculator: Box::new(up),
+        };
+        swap_curve.fix_at(1_000 + 2 * MIN_RAMP_DURATION).unwrap();
+        assert_eq!(
+            swap_curve,
+            SwapCurve {
+                curve_type: CurveType::Stable,
+                calculator: Box::new(StableCurve::new(150)),
+            }
+        );
+        assert!(swap_curve
+            .calculator
+            .swap_without_fees(1_000, 100_000, 100_000, TradeDirection::AtoB)
+            .is_some());
+
+        // curves that were never ramped are left as they are
+        let mut swap_curve = SwapCurve {
+            curve_type: CurveType::Stable,
+            calculator: Box::new(StableCurve::new(100)),
+        };
+        swap_curve.fix_at(i64::MAX).unwrap();
+        assert_eq!(
+            swap_curve
+                .calculator
+                .swap_without_fees(1_000, 100_000, 100_000, TradeDirection::AtoB),
+            StableCurve::new(100).swap_without_fees(1_000, 100_000, 100_000, TradeDirection::AtoB)
+        );
+    }
+
+    #[test]
+    fn ramp_bounds() {
+        let curve = StableCurve::new(100);
+        let now = 1_000;
+        let stop = now + MIN_RAMP_DURATION;
+        assert!(curve.ramp(1_000, now, stop).is_ok());
+        assert!(curve.ramp(10, now, stop).is_ok());
+        assert_eq!(curve.ramp(1_001, now, stop), Err(SwapError::InvalidRamp));
+        assert_eq!(curve.ramp(9, now, stop), Err(SwapError::InvalidRamp));
+        assert_eq!(curve.ramp(200, now, stop - 1), Err(SwapError::InvalidRamp));
+        assert_eq!(
+            StableCurve::new(MAX_AMP).ramp(MAX_AMP + 1, now, stop),
+            Err(SwapError::InvalidRamp)
+        );
+        assert_eq!(
+            StableCurve::new(MIN_AMP).ramp(0, now, stop),
+            Err(SwapError::InvalidRamp)
+        );
+
+        // the next ramp must start at least a day after the last one
+        let ramping = curve.ramp(200, now, stop).unwrap();
+        assert_eq!(
+            ramping.ramp(300, stop - 1, stop + MIN_RAMP_DURATION),
+            Err(SwapError::InvalidRamp)
+        );
+        let stopped = ramping.stop_ramp(now + 1).unwrap();
+        assert_eq!(
+            stopped.ramp(300, stop - 1, stop + MIN_RAMP_DURATION),
+            Err(SwapError::InvalidRamp)
+        );
+        let next = ramping.ramp(300, stop, stop + MIN_RAMP_DURATION).unwrap();
+        assert_eq!(next.amp, 200);
+    }
+
+    #[test]
+    fn validate_amp() {
+        assert_eq!(StableCurve::new(0).validate(), Err(SwapError::InvalidCurve));
+        assert_eq!(
+            StableCurve::new(MAX_AMP + 1).validate(),
+            Err(SwapError::InvalidCurve)
+        );
+        assert_eq!(StableCurve::new(MAX_AMP).validate(), Ok(()));
+        let curve = StableCurve {
+            amp: 100,
+            target_amp: 0,
+            ramp_start_ts: 0,
+            ramp_stop_ts: 10,
+        };
+        assert_eq!(curve.validate(), Err(SwapError::InvalidCurve));
+        let curve = StableCurve {
+            amp: 100,
+            target_amp: 200,
+            ramp_start_ts: 20,
+            ramp_stop_ts: 10,
+        };
+        assert_eq!(curve.validate(), Err(SwapError::InvalidCurve));
+    }
 }
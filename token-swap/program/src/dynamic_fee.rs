@@ -0,0 +1,230 @@
+//! Trade fees of two-token swaps following the recent volatility of their
+//! price
+
+use crate::{curve::fees::Fees, error::SwapError, oracle::spot_price, state::DynamicFee};
+use spl_math::uint::U256;
+use std::convert::TryFrom;
+
+/// Basis points in a price movement of 100%
+const BASIS_POINTS: u64 = 10_000;
+
+impl DynamicFee {
+    /// Whether the trade fee follows the volatility, instead of being the
+    /// fixed trade fee of the swap
+    pub fn is_enabled(&self) -> bool {
+        self.max_volatility > 0
+    }
+
+    /// Checks that the trade fee stays valid for the other fees of the swap
+    /// at any volatility
+    pub fn validate(&self, fees: &Fees) -> Result<(), SwapError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if self.min_trade_fee_numerator > self.max_trade_fee_numerator
+            || self.volatility_period == 0
+        {
+            return Err(SwapError::InvalidFee);
+        }
+        self.fees_with_trade_fee(fees, self.min_trade_fee_numerator)
+            .validate()?;
+        self.fees_with_trade_fee(fees, self.max_trade_fee_numerator)
+            .validate()
+    }
+
+    /// Volatility as of the given slot.  Each slot without trades removes
+    /// `1 / volatility_period` of the volatility, which is gone once a full
+    /// period passes without trades.
+    pub fn decay(&self, slot: u64) -> Self {
+        let mut decayed = *self;
+        if slot <= self.last_update_slot {
+            return decayed;
+        }
+        let elapsed = slot - self.last_update_slot;
+        decayed.volatility = if elapsed >= self.volatility_period {
+            0
+        } else {
+            let remaining = u128::from(self.volatility)
+                * u128::from(self.volatility_period - elapsed)
+                / u128::from(self.volatility_period);
+            u64::try_from(remaining).unwrap_or(u64::MAX)
+        };
+        decayed.last_update_slot = slot;
+        decayed
+    }
+
+    /// Adds the movement of the price of token A from a trade to the
+    /// volatility, in basis points of the price before the trade
+    pub fn record_trade(
+        &mut self,
+        token_a_amount_before: u128,
+        token_b_amount_before: u128,
+        token_a_amount_after: u128,
+        token_b_amount_after: u128,
+    ) {
+        let price_before = spot_price(token_a_amount_before, token_b_amount_before);
+        let price_after = spot_price(token_a_amount_after, token_b_amount_after);
+        if let (Some(price_before), Some(price_after)) = (price_before, price_after) {
+            if price_before == 0 {
+                return;
+            }
+            let movement = if price_after > price_before {
+                price_after - price_before
+            } else {
+                price_before - price_after
+            };
+            let movement =
+                U256::from(movement) * U256::from(BASIS_POINTS) / U256::from(price_before);
+            let movement = u128::try_from(movement)
+                .ok()
+                .and_then(|movement| u64::try_from(movement).ok())
+                .unwrap_or(u64::MAX);
+            self.volatility = self.volatility.saturating_add(movement);
+        }
+    }
+
+    /// Trade fee numerator for the current volatility, rising linearly from
+    /// the minimum without volatility to the maximum at `max_volatility`
+    pub fn trade_fee_numerator(&self) -> u64 {
+        if !self.is_enabled() {
+            return self.min_trade_fee_numerator;
+        }
+        let volatility = u128::from(self.volatility.min(self.max_volatility));
+        let range = u128::from(
+            self.max_trade_fee_numerator
+                .saturating_sub(self.min_trade_fee_numerator),
+        );
+        let increase = range * volatility / u128::from(self.max_volatility);
+        self.min_trade_fee_numerator
+            .saturating_add(u64::try_from(increase).unwrap_or(u64::MAX))
+    }
+
+    /// Fees charged by the swap, with the trade fee for the current
+    /// volatility if it is enabled
+    pub fn fees(&self, fees: &Fees) -> Fees {
+        if self.is_enabled() {
+            self.fees_with_trade_fee(fees, self.trade_fee_numerator())
+        } else {
+            fees.clone()
+        }
+    }
+
+    fn fees_with_trade_fee(&self, fees: &Fees, trade_fee_numerator: u64) -> Fees {
+        Fees {
+            trade_fee_numerator,
+            ..fees.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn dynamic_fee() -> DynamicFee {
+        DynamicFee {
+            min_trade_fee_numerator: 10,
+            max_trade_fee_numerator: 110,
+            max_volatility: 5_000,
+            volatility_period: 100,
+            volatility: 0,
+            last_update_slot: 0,
+        }
+    }
+
+    fn fees() -> Fees {
+        Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        }
+    }
+
+    #[test]
+    fn trade_fee_follows_volatility() {
+        let mut dynamic_fee = dynamic_fee();
+        assert_eq!(dynamic_fee.fees(&fees()).trade_fee_numerator, 10);
+
+        // a 12.5% price movement is 1250 basis points, a quarter of the way
+        // to the maximum fee
+        dynamic_fee.record_trade(1_000_000, 1_000_000, 1_000_000, 1_125_000);
+        assert_eq!(dynamic_fee.volatility, 1_250);
+        assert_eq!(dynamic_fee.trade_fee_numerator(), 35);
+        // moving back counts as well
+        dynamic_fee.record_trade(1_000_000, 1_125_000, 1_000_000, 1_000_000);
+        assert_eq!(dynamic_fee.volatility, 1_250 + 1_111);
+        let charged = dynamic_fee.fees(&fees());
+        assert_eq!(charged.trade_fee_numerator, 57);
+        assert_eq!(charged.owner_trade_fee_numerator, 5);
+
+        // the fee stops at its maximum
+        dynamic_fee.record_trade(1_000_000, 1_000_000, 1_000_000, 2_000_000);
+        assert_eq!(dynamic_fee.trade_fee_numerator(), 110);
+
+        // a swap without a dynamic fee charges its own trade fee
+        assert_eq!(DynamicFee::default().fees(&fees()).trade_fee_numerator, 25);
+    }
+
+    #[test]
+    fn volatility_decays() {
+        let mut dynamic_fee = dynamic_fee();
+        dynamic_fee.volatility = 400;
+        dynamic_fee.last_update_slot = 10;
+        assert_eq!(dynamic_fee.decay(5), dynamic_fee);
+        assert_eq!(dynamic_fee.decay(10), dynamic_fee);
+        let decayed = dynamic_fee.decay(35);
+        assert_eq!(decayed.volatility, 300);
+        assert_eq!(decayed.last_update_slot, 35);
+        assert_eq!(decayed.decay(85).volatility, 150);
+        assert_eq!(dynamic_fee.decay(110).volatility, 0);
+        assert_eq!(dynamic_fee.decay(u64::MAX).volatility, 0);
+    }
+
+    #[test]
+    fn validate_dynamic_fee() {
+        assert_eq!(DynamicFee::default().validate(&fees()), Ok(()));
+        assert_eq!(dynamic_fee().validate(&fees()), Ok(()));
+
+        let mut invalid = dynamic_fee();
+        invalid.min_trade_fee_numerator = 111;
+        assert_eq!(invalid.validate(&fees()), Err(SwapError::InvalidFee));
+        let mut invalid = dynamic_fee();
+        invalid.volatility_period = 0;
+        assert_eq!(invalid.validate(&fees()), Err(SwapError::InvalidFee));
+        let mut invalid = dynamic_fee();
+        invalid.max_trade_fee_numerator = 10_000;
+        assert_eq!(invalid.validate(&fees()), Err(SwapError::InvalidFee));
+    }
+
+    proptest! {
+        #[test]
+        fn trade_fee_within_bounds(
+            min_trade_fee_numerator in 0..u64::MAX,
+            max_trade_fee_numerator in 0..u64::MAX,
+            max_volatility in 1..u64::MAX,
+            volatility in 0..u64::MAX,
+        ) {
+            prop_assume!(min_trade_fee_numerator <= max_trade_fee_numerator);
+            let dynamic_fee = DynamicFee {
+                min_trade_fee_numerator,
+                max_trade_fee_numerator,
+                max_volatility,
+                volatility_period: 1,
+                volatility,
+                last_update_slot: 0,
+            };
+            let trade_fee_numerator = dynamic_fee.trade_fee_numerator();
+            prop_assert!(trade_fee_numerator >= min_trade_fee_numerator);
+            prop_assert!(trade_fee_numerator <= max_trade_fee_numerator);
+            if volatility >= max_volatility {
+                prop_assert_eq!(trade_fee_numerator, max_trade_fee_numerator);
+            }
+        }
+    }
+}
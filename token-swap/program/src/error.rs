@@ -91,6 +91,61 @@ pub enum SwapError {
     /// The operation cannot be performed on the given curve
     #[error("The operation cannot be performed on the given curve")]
     UnsupportedCurveOperation,
+    /// The number of tokens is not supported, or does not match the swap
+    #[error("The number of tokens is not supported, or does not match the swap")]
+    IncorrectTokenCount,
+    /// The tick is out of range, or not a multiple of the tick spacing
+    #[error("The tick is out of range, or not a multiple of the tick spacing")]
+    InvalidTick,
+    /// The pool cannot hold any more initialized ticks
+    #[error("The pool cannot hold any more initialized ticks")]
+    TooManyTicks,
+    /// The position does not belong to the swap or to the given owner
+    #[error("The position does not belong to the swap or to the given owner")]
+    IncorrectPosition,
+    /// The swap has no admin, or the given admin authority does not match
+    #[error("The swap has no admin, or the given admin authority does not match")]
+    InvalidAdmin,
+    /// The amplifier ramp is too short, too steep, out of bounds, or too soon after the last one
+    #[error(
+        "The amplifier ramp is too short, too steep, out of bounds, or too soon after the last one"
+    )]
+    InvalidRamp,
+    /// The operation is not allowed by the status of the pool
+    #[error("The operation is not allowed by the status of the pool")]
+    PoolPaused,
+    /// The flash loan was not repaid with its fees
+    #[error("The flash loan was not repaid with its fees")]
+    FlashLoanNotRepaid,
+    /// The farm or stake does not belong to the swap, farm, or owner given
+    #[error("The farm or stake does not belong to the swap, farm, or owner given")]
+    IncorrectFarm,
+    /// The farm cannot pay any more reward tokens
+    #[error("The farm cannot pay any more reward tokens")]
+    TooManyFarmRewards,
+    /// The amount is greater than the pool tokens staked
+    #[error("The amount is greater than the pool tokens staked")]
+    InsufficientStake,
+    /// The swap has no allowlist, or the given allowlist authority does not match
+    #[error("The swap has no allowlist, or the given allowlist authority does not match")]
+    InvalidAllowlistAuthority,
+    /// The user is not a member of the allowlist of the swap
+    #[error("The user is not a member of the allowlist of the swap")]
+    NotAllowlisted,
+    /// The limit order does not belong to the swap or owner given, or its accounts do not match
+    #[error(
+        "The limit order does not belong to the swap or owner given, or its accounts do not match"
+    )]
+    IncorrectLimitOrder,
+    /// The price of the swap has not crossed the level of the limit order
+    #[error("The price of the swap has not crossed the level of the limit order")]
+    LimitOrderNotTriggered,
+    /// The fee split does not belong to the swap, or its shares or recipients are invalid
+    #[error("The fee split does not belong to the swap, or its shares or recipients are invalid")]
+    InvalidFeeSplit,
+    /// The position would hold less than the minimum liquidity of the pool
+    #[error("The position would hold less than the minimum liquidity of the pool")]
+    PositionLiquidityTooLow,
 }
 impl From<SwapError> for ProgramError {
     fn from(e: SwapError) -> Self {
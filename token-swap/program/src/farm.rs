@@ -0,0 +1,223 @@
+//! Liquidity-mining farms, paying rewards per slot to the owners of staked pool
+//! tokens of two-token swaps
+
+use crate::{
+    error::SwapError,
+    state::{Farm, FarmReward, FarmStake},
+};
+use solana_program::pubkey::Pubkey;
+use spl_math::uint::U256;
+use std::convert::TryFrom;
+
+/// Largest number of reward tokens paid by a farm
+pub const MAX_FARM_REWARDS: usize = 4;
+
+impl Farm {
+    /// Reward tokens paid by the farm
+    pub fn active_rewards(&self) -> &[FarmReward] {
+        &self.rewards[..self.reward_count as usize]
+    }
+
+    /// Adds a reward token, paid from the given vault from the last update on
+    pub fn add_reward(&mut self, vault: Pubkey, reward_per_slot: u64) -> Result<(), SwapError> {
+        let reward = self
+            .rewards
+            .get_mut(self.reward_count as usize)
+            .ok_or(SwapError::TooManyFarmRewards)?;
+        *reward = FarmReward {
+            vault,
+            reward_per_slot,
+            reward_per_share: 0,
+        };
+        self.reward_count += 1;
+        Ok(())
+    }
+
+    /// Accrues the rewards paid since the last update to the staked pool
+    /// tokens.  Rewards of slots where nothing is staked are not paid out.
+    ///
+    /// The rewards per share wrap around on overflow, like the fee growth of
+    /// concentrated liquidity pools, so only their differences are used.
+    pub fn update(&mut self, slot: u64) {
+        if slot <= self.last_update_slot {
+            return;
+        }
+        if self.total_staked > 0 {
+            let slots = U256::from(slot - self.last_update_slot);
+            let total_staked = U256::from(self.total_staked);
+            for reward in self.rewards[..self.reward_count as usize].iter_mut() {
+                let growth = ((U256::from(reward.reward_per_slot) * slots) << 64) / total_staked;
+                let growth = u128::try_from(growth).unwrap_or(u128::MAX);
+                reward.reward_per_share = reward.reward_per_share.wrapping_add(growth);
+            }
+        }
+        self.last_update_slot = slot;
+    }
+
+    /// Credits a stake with the rewards it earned since its last update
+    pub fn credit_rewards(&self, stake: &mut FarmStake) {
+        for (index, reward) in self.active_rewards().iter().enumerate() {
+            let growth = reward
+                .reward_per_share
+                .wrapping_sub(stake.reward_per_share_paid[index]);
+            let earned = (U256::from(stake.amount) * U256::from(growth)) >> 64;
+            let earned = u128::try_from(earned)
+                .ok()
+                .and_then(|earned| u64::try_from(earned).ok())
+                .unwrap_or(u64::MAX);
+            stake.rewards_owed[index] = stake.rewards_owed[index].saturating_add(earned);
+            stake.reward_per_share_paid[index] = reward.reward_per_share;
+        }
+    }
+
+    /// Adds pool tokens to a stake, after crediting its rewards
+    pub fn stake(&mut self, stake: &mut FarmStake, amount: u64) -> Result<(), SwapError> {
+        self.credit_rewards(stake);
+        stake.amount = stake
+            .amount
+            .checked_add(amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        self.total_staked = self
+            .total_staked
+            .checked_add(amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// Removes pool tokens from a stake, after crediting its rewards
+    pub fn unstake(&mut self, stake: &mut FarmStake, amount: u64) -> Result<(), SwapError> {
+        self.credit_rewards(stake);
+        stake.amount = stake
+            .amount
+            .checked_sub(amount)
+            .ok_or(SwapError::InsufficientStake)?;
+        self.total_staked = self
+            .total_staked
+            .checked_sub(amount)
+            .ok_or(SwapError::CalculationFailure)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn farm_with_reward(reward_per_slot: u64) -> Farm {
+        let mut farm = Farm {
+            is_initialized: true,
+            ..Farm::default()
+        };
+        farm.add_reward(Pubkey::new_unique(), reward_per_slot)
+            .unwrap();
+        farm
+    }
+
+    #[test]
+    fn rewards_split_by_stake() {
+        let mut farm = farm_with_reward(100);
+        let mut stake_1 = FarmStake::default();
+        let mut stake_2 = FarmStake::default();
+        farm.stake(&mut stake_1, 1_000).unwrap();
+        farm.stake(&mut stake_2, 3_000).unwrap();
+
+        farm.update(10);
+        farm.credit_rewards(&mut stake_1);
+        farm.credit_rewards(&mut stake_2);
+        assert_eq!(stake_1.rewards_owed[0], 250);
+        assert_eq!(stake_2.rewards_owed[0], 750);
+
+        // unstaking stops the rewards of a stake from the update on
+        farm.update(20);
+        farm.unstake(&mut stake_2, 3_000).unwrap();
+        assert_eq!(stake_2.rewards_owed[0], 1_500);
+        farm.update(30);
+        farm.credit_rewards(&mut stake_1);
+        farm.credit_rewards(&mut stake_2);
+        assert_eq!(stake_1.rewards_owed[0], 250 + 250 + 1_000);
+        assert_eq!(stake_2.rewards_owed[0], 1_500);
+        assert_eq!(
+            farm.unstake(&mut stake_2, 1),
+            Err(SwapError::InsufficientStake)
+        );
+    }
+
+    #[test]
+    fn rewards_not_paid_without_stake() {
+        let mut farm = farm_with_reward(100);
+        farm.update(10);
+        let mut stake = FarmStake::default();
+        farm.stake(&mut stake, 1_024).unwrap();
+        farm.update(15);
+        farm.credit_rewards(&mut stake);
+        assert_eq!(stake.rewards_owed[0], 500);
+
+        // rewards follow the rate in effect at each update
+        farm.rewards[0].reward_per_slot = 10;
+        farm.update(20);
+        farm.credit_rewards(&mut stake);
+        assert_eq!(stake.rewards_owed[0], 550);
+    }
+
+    #[test]
+    fn added_rewards_start_at_last_update() {
+        let mut farm = farm_with_reward(100);
+        let mut stake = FarmStake::default();
+        farm.stake(&mut stake, 1_024).unwrap();
+        farm.update(10);
+        farm.add_reward(Pubkey::new_unique(), 7).unwrap();
+        farm.update(20);
+        farm.credit_rewards(&mut stake);
+        assert_eq!(stake.rewards_owed[..2], [2_000, 70]);
+
+        for _ in 2..MAX_FARM_REWARDS {
+            farm.add_reward(Pubkey::new_unique(), 1).unwrap();
+        }
+        assert_eq!(
+            farm.add_reward(Pubkey::new_unique(), 1),
+            Err(SwapError::TooManyFarmRewards)
+        );
+    }
+
+    #[test]
+    fn reward_per_share_wraps() {
+        let mut farm = farm_with_reward(1_000);
+        farm.rewards[0].reward_per_share = u128::MAX - (1 << 64);
+        let mut stake = FarmStake::default();
+        farm.stake(&mut stake, 1).unwrap();
+        farm.update(10);
+        farm.credit_rewards(&mut stake);
+        assert_eq!(stake.rewards_owed[0], 10_000);
+    }
+
+    proptest! {
+        #[test]
+        fn rewards_paid_do_not_exceed_rate(
+            reward_per_slot in 0..u32::MAX as u64,
+            stakes in prop::collection::vec(1..u64::MAX / 16, 1..8),
+            slots in prop::collection::vec(1..1_000u64, 1..8),
+        ) {
+            let mut farm = farm_with_reward(reward_per_slot);
+            let mut farm_stakes = stakes.iter().map(|_| FarmStake::default()).collect::<Vec<_>>();
+            let mut slot = 0;
+            for (farm_stake, amount) in farm_stakes.iter_mut().zip(stakes.iter()) {
+                farm.stake(farm_stake, *amount).unwrap();
+            }
+            for elapsed in slots.iter() {
+                slot += elapsed;
+                farm.update(slot);
+            }
+            let mut total_owed = 0u128;
+            for farm_stake in farm_stakes.iter_mut() {
+                farm.credit_rewards(farm_stake);
+                total_owed += farm_stake.rewards_owed[0] as u128;
+            }
+            let total_paid = reward_per_slot as u128 * slot as u128;
+            prop_assert!(total_owed <= total_paid);
+            // each stake and update loses less than one token to rounding
+            let rounding = (stakes.len() * (slots.len() + 1)) as u128;
+            prop_assert!(total_owed + rounding >= total_paid);
+        }
+    }
+}
@@ -2,15 +2,17 @@
 
 #![allow(clippy::too_many_arguments)]
 
-use crate::curve::{base::SwapCurve, fees::Fees};
+use crate::curve::{base::SwapCurve, calculator::TradeDirection, fees::Fees};
 use crate::error::SwapError;
+use crate::state::{LimitOrderKind, PoolStatus};
 use solana_program::{
+    clock::UnixTimestamp,
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     program_pack::Pack,
     pubkey::Pubkey,
 };
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::mem::size_of;
 
 #[cfg(feature = "fuzz")]
@@ -29,6 +31,104 @@ pub struct Initialize {
     pub swap_curve: SwapCurve,
 }
 
+/// UpdateFees instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateFees {
+    /// new swap fees
+    pub fees: Fees,
+}
+
+/// RampA instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RampA {
+    /// Amplifier to reach at the end of the ramp
+    pub target_amp: u64,
+    /// Unix timestamp at which the ramp stops
+    pub stop_ramp_ts: UnixTimestamp,
+}
+
+/// SetPoolStatus instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetPoolStatus {
+    /// New status of the pool
+    pub status: PoolStatus,
+}
+
+/// SetDynamicFee instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetDynamicFee {
+    /// Trade fee numerator charged without volatility
+    pub min_trade_fee_numerator: u64,
+    /// Trade fee numerator charged from `max_volatility` on
+    pub max_trade_fee_numerator: u64,
+    /// Volatility, in basis points of price movement, at which the maximum
+    /// trade fee is reached.  Zero goes back to the fixed trade fee.
+    pub max_volatility: u64,
+    /// Number of slots over which the volatility decays to zero
+    pub volatility_period: u64,
+}
+
+/// SetFlashLoanFee instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetFlashLoanFee {
+    /// Fee numerator paid back to the pool on top of the loan
+    pub trade_fee_numerator: u64,
+    /// Fee denominator paid back to the pool on top of the loan
+    pub trade_fee_denominator: u64,
+    /// Owner fee numerator, minted as pool tokens to the pool fee account
+    pub owner_trade_fee_numerator: u64,
+    /// Owner fee denominator, minted as pool tokens to the pool fee account
+    pub owner_trade_fee_denominator: u64,
+}
+
+/// SetAllowlist instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetAllowlist {
+    /// Whether swaps, and not only deposits, require a membership
+    pub gate_swaps: bool,
+}
+
+/// MigrateSwap instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MigrateSwap {
+    /// nonce used to create the program address of the new swap authority
+    pub nonce: u8,
+}
+
+/// CreateLimitOrder instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateLimitOrder {
+    /// nonce used to create the program address of the order authority
+    pub nonce: u8,
+    /// Direction of the swap filling the order, `AtoB` to sell token A
+    pub trade_direction: TradeDirection,
+    /// Condition under which the order fills
+    pub kind: LimitOrderKind,
+    /// Amount of tokens to sell, moved into the order escrow
+    pub amount_in: u64,
+    /// Amount of tokens bought for all of `amount_in` at the limit price
+    pub trigger_amount_out: u64,
+    /// Least amount of tokens bought the order fills for
+    pub minimum_amount_out: u64,
+}
+
+/// SetFeeSplit instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetFeeSplit {
+    /// Share of the owner fees paid to each recipient, in basis points
+    /// adding up to 10_000
+    pub shares: Vec<u16>,
+}
+
 /// Swap instruction data
 #[cfg_attr(feature = "fuzz", derive(Arbitrary))]
 #[repr(C)]
@@ -40,6 +140,117 @@ pub struct Swap {
     pub minimum_amount_out: u64,
 }
 
+/// SwapExactOut instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapExactOut {
+    /// DESTINATION amount to receive, input from SOURCE is based on the exchange rate
+    pub amount_out: u64,
+    /// Maximum amount of SOURCE token to transfer, prevents excessive slippage
+    pub maximum_amount_in: u64,
+}
+
+/// FlashLoan instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlashLoan {
+    /// Amount of token A or B to lend out
+    pub amount: u64,
+}
+
+/// Operation priced by the Quote instruction
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuoteOperation {
+    /// Swap an amount of the source token
+    Swap,
+    /// Swap for an amount of the destination token
+    SwapExactOut,
+    /// Deposit both tokens for an amount of pool tokens
+    DepositAllTokenTypes,
+    /// Withdraw both tokens for an amount of pool tokens
+    WithdrawAllTokenTypes,
+    /// Deposit an amount of one token
+    DepositSingleTokenTypeExactAmountIn,
+    /// Withdraw an amount of one token
+    WithdrawSingleTokenTypeExactAmountOut,
+}
+
+impl TryFrom<u8> for QuoteOperation {
+    type Error = ProgramError;
+
+    fn try_from(operation: u8) -> Result<Self, Self::Error> {
+        match operation {
+            0 => Ok(QuoteOperation::Swap),
+            1 => Ok(QuoteOperation::SwapExactOut),
+            2 => Ok(QuoteOperation::DepositAllTokenTypes),
+            3 => Ok(QuoteOperation::WithdrawAllTokenTypes),
+            4 => Ok(QuoteOperation::DepositSingleTokenTypeExactAmountIn),
+            5 => Ok(QuoteOperation::WithdrawSingleTokenTypeExactAmountOut),
+            _ => Err(SwapError::InvalidInstruction.into()),
+        }
+    }
+}
+
+/// Quote instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+    /// Operation to quote
+    pub operation: QuoteOperation,
+    /// Amount given to the operation: the source or destination token amount
+    /// of swaps and single token deposits and withdrawals, or the pool token
+    /// amount of deposits and withdrawals of all token types
+    pub amount: u64,
+    /// Direction of swaps, or `AtoB` to deposit or withdraw only token A and
+    /// `BtoA` for only token B
+    pub trade_direction: TradeDirection,
+}
+
+/// InitializeFarm instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct InitializeFarm {
+    /// nonce used to create the program address of the farm authority
+    pub nonce: u8,
+}
+
+/// AddFarmReward instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AddFarmReward {
+    /// Amount of the reward token paid to all stakers per slot
+    pub reward_per_slot: u64,
+}
+
+/// SetFarmRewardRate instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetFarmRewardRate {
+    /// Index of the reward in the farm
+    pub reward_index: u8,
+    /// New amount of the reward token paid to all stakers per slot
+    pub reward_per_slot: u64,
+}
+
+/// Stake instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stake {
+    /// Amount of pool tokens to stake
+    pub amount: u64,
+}
+
+/// Unstake instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unstake {
+    /// Amount of pool tokens to unstake
+    pub amount: u64,
+}
+
 /// DepositAllTokenTypes instruction data
 #[cfg_attr(feature = "fuzz", derive(Arbitrary))]
 #[repr(C)]
@@ -92,6 +303,104 @@ pub struct WithdrawSingleTokenTypeExactAmountOut {
     pub maximum_pool_token_amount: u64,
 }
 
+/// InitializeMultiToken instruction data
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct InitializeMultiToken {
+    /// nonce used to create valid program address
+    pub nonce: u8,
+    /// all swap fees
+    pub fees: Fees,
+    /// swap curve info for pool, including CurveType and anything
+    /// else that may be required
+    pub swap_curve: SwapCurve,
+    /// weight of each token, in the order of the token accounts, which must
+    /// be zero for all curves except the weighted curve
+    pub weights: Vec<u64>,
+}
+
+/// DepositAllMultiTokenTypes instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepositAllMultiTokenTypes {
+    /// Pool token amount to transfer. Token amounts are set by the current
+    /// exchange rate and size of the pool
+    pub pool_token_amount: u64,
+    /// Maximum amount of each token to deposit, in the order of the swap's
+    /// tokens, prevents excessive slippage
+    pub maximum_token_amounts: Vec<u64>,
+}
+
+/// WithdrawAllMultiTokenTypes instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct WithdrawAllMultiTokenTypes {
+    /// Amount of pool tokens to burn. User receives an output of each token
+    /// based on the percentage of the pool tokens that are returned.
+    pub pool_token_amount: u64,
+    /// Minimum amount of each token to receive, in the order of the swap's
+    /// tokens, prevents excessive slippage
+    pub minimum_token_amounts: Vec<u64>,
+}
+
+/// InitializeConcentrated instruction data
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct InitializeConcentrated {
+    /// nonce used to create valid program address
+    pub nonce: u8,
+    /// all swap fees, only the trade fee may be set
+    pub fees: Fees,
+    /// spacing between the 32 ticks around the starting price that can bound
+    /// a position
+    pub tick_spacing: u16,
+    /// least liquidity a non-empty position may hold
+    pub min_position_liquidity: u128,
+    /// square root of the starting price of token A in token B, in Q64.64
+    pub initial_sqrt_price: u128,
+}
+
+/// OpenPosition instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenPosition {
+    /// Lower tick of the price range, must be a multiple of the tick spacing
+    pub tick_lower: i32,
+    /// Upper tick of the price range, must be a multiple of the tick spacing
+    pub tick_upper: i32,
+}
+
+/// IncreaseLiquidity instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IncreaseLiquidity {
+    /// Liquidity to add to the position. Token A and B amounts are set by the
+    /// current price and the position's price range
+    pub liquidity: u128,
+    /// Maximum token A amount to deposit, prevents excessive slippage
+    pub maximum_token_a_amount: u64,
+    /// Maximum token B amount to deposit, prevents excessive slippage
+    pub maximum_token_b_amount: u64,
+}
+
+/// DecreaseLiquidity instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecreaseLiquidity {
+    /// Liquidity to remove from the position. Token A and B amounts are set
+    /// by the current price and the position's price range
+    pub liquidity: u128,
+    /// Minimum amount of token A to receive, prevents excessive slippage
+    pub minimum_token_a_amount: u64,
+    /// Minimum amount of token B to receive, prevents excessive slippage
+    pub minimum_token_b_amount: u64,
+}
+
 /// Instructions supported by the token swap program.
 #[repr(C)]
 #[derive(Debug, PartialEq)]
@@ -108,11 +417,15 @@ pub enum SwapInstruction {
     ///   6. `[writable]` Pool Token Account to deposit the initial pool token
     ///   supply.  Must be empty, not owned by swap authority.
     ///   7. '[]` Token program id
+    ///   8. `[optional, signer]` Admin authority, allowed to update the fees
+    ///   and fee account.  Without it, the swap has no admin.  Swap accounts
+    ///   of the size used before the swap extension was added have no room
+    ///   for an admin, and are rejected with one.
     Initialize(Initialize),
 
     ///   Swap the tokens in the pool.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
     ///   2. `[]` user transfer authority
     ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
@@ -123,13 +436,17 @@ pub enum SwapInstruction {
     ///   8. `[writable]` Fee account, to receive trading fees
     ///   9. '[]` Token program id
     ///   10 `[optional, writable]` Host fee account to receive additional trading fees
+    ///   11 `[optional]` Allowlist membership of the user transfer authority,
+    ///   required if the swap gates swaps.  It is owned by the swap program,
+    ///   and may directly follow the token program id without a host fee
+    ///   account.
     Swap(Swap),
 
     ///   Deposit both types of tokens into the pool.  The output is a "pool"
     ///   token representing ownership in the pool. Inputs are converted to
     ///   the current ratio.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
     ///   2. `[]` user transfer authority
     ///   3. `[writable]` token_a user transfer authority can transfer amount,
@@ -139,13 +456,15 @@ pub enum SwapInstruction {
     ///   7. `[writable]` Pool MINT account, swap authority is the owner.
     ///   8. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
     ///   9. '[]` Token program id
+    ///   10 `[optional]` Allowlist membership of the user transfer authority,
+    ///   required if the swap has an allowlist
     DepositAllTokenTypes(DepositAllTokenTypes),
 
     ///   Withdraw both types of tokens from the pool at the current ratio, given
     ///   pool tokens.  The pool tokens are burned in exchange for an equivalent
     ///   amount of token A and B.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
     ///   2. `[]` user transfer authority
     ///   3. `[writable]` Pool mint account, swap authority is the owner
@@ -162,7 +481,7 @@ pub enum SwapInstruction {
     ///   representing ownership into the pool. Input token is converted as if
     ///   a swap and deposit all token types were performed.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
     ///   2. `[]` user transfer authority
     ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
@@ -171,12 +490,14 @@ pub enum SwapInstruction {
     ///   6. `[writable]` Pool MINT account, swap authority is the owner.
     ///   7. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
     ///   8. '[]` Token program id
+    ///   9. `[optional]` Allowlist membership of the user transfer authority,
+    ///   required if the swap has an allowlist
     DepositSingleTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn),
 
     ///   Withdraw one token type from the pool at the current ratio given the
     ///   exact amount out expected.
     ///
-    ///   0. `[]` Token-swap
+    ///   0. `[writable]` Token-swap
     ///   1. `[]` swap authority
     ///   2. `[]` user transfer authority
     ///   3. `[writable]` Pool mint account, swap authority is the owner
@@ -187,191 +508,2394 @@ pub enum SwapInstruction {
     ///   8. `[writable]` Fee account, to receive withdrawal fees
     ///   9. '[]` Token program id
     WithdrawSingleTokenTypeExactAmountOut(WithdrawSingleTokenTypeExactAmountOut),
-}
 
-impl SwapInstruction {
-    /// Unpacks a byte buffer into a [SwapInstruction](enum.SwapInstruction.html).
-    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&tag, rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
-        Ok(match tag {
-            0 => {
-                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
-                if rest.len() >= Fees::LEN {
-                    let (fees, rest) = rest.split_at(Fees::LEN);
-                    let fees = Fees::unpack_unchecked(fees)?;
-                    let swap_curve = SwapCurve::unpack_unchecked(rest)?;
-                    Self::Initialize(Initialize {
-                        nonce,
-                        fees,
-                        swap_curve,
-                    })
-                } else {
-                    return Err(SwapError::InvalidInstruction.into());
-                }
-            }
-            1 => {
-                let (amount_in, rest) = Self::unpack_u64(rest)?;
-                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
-                Self::Swap(Swap {
-                    amount_in,
-                    minimum_amount_out,
-                })
-            }
-            2 => {
-                let (pool_token_amount, rest) = Self::unpack_u64(rest)?;
-                let (maximum_token_a_amount, rest) = Self::unpack_u64(rest)?;
-                let (maximum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
-                Self::DepositAllTokenTypes(DepositAllTokenTypes {
-                    pool_token_amount,
-                    maximum_token_a_amount,
-                    maximum_token_b_amount,
-                })
-            }
-            3 => {
-                let (pool_token_amount, rest) = Self::unpack_u64(rest)?;
-                let (minimum_token_a_amount, rest) = Self::unpack_u64(rest)?;
-                let (minimum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
-                Self::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
-                    pool_token_amount,
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                })
-            }
-            4 => {
-                let (source_token_amount, rest) = Self::unpack_u64(rest)?;
-                let (minimum_pool_token_amount, _rest) = Self::unpack_u64(rest)?;
-                Self::DepositSingleTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn {
-                    source_token_amount,
-                    minimum_pool_token_amount,
-                })
-            }
-            5 => {
-                let (destination_token_amount, rest) = Self::unpack_u64(rest)?;
-                let (maximum_pool_token_amount, _rest) = Self::unpack_u64(rest)?;
-                Self::WithdrawSingleTokenTypeExactAmountOut(WithdrawSingleTokenTypeExactAmountOut {
-                    destination_token_amount,
-                    maximum_pool_token_amount,
-                })
-            }
-            _ => return Err(SwapError::InvalidInstruction.into()),
-        })
-    }
+    ///   Initializes a new swap holding more than two tokens
+    ///
+    ///   0. `[writable, signer]` New Token-swap to create, sized for the
+    ///   multi-token layout.
+    ///   1. `[]` swap authority derived from `create_program_address(&[Token-swap account])`
+    ///   2. `[writable]` Pool Token Mint. Must be empty, owned by swap authority.
+    ///   3. `[]` Pool Token Account to deposit trading and withdraw fees.
+    ///   Must be empty, not owned by swap authority
+    ///   4. `[writable]` Pool Token Account to deposit the initial pool token
+    ///   supply.  Must be empty, not owned by swap authority.
+    ///   5. '[]` Token program id
+    ///   6. ..6+N `[]` token Accounts, one for each weight. Must be non zero,
+    ///   owned by swap authority.
+    ///   6+N. `[optional, signer]` Admin authority, allowed to set the status
+    ///   of the swap.  Without it, the swap has no admin.
+    InitializeMultiToken(InitializeMultiToken),
 
-    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
-        if input.len() >= 8 {
-            let (amount, rest) = input.split_at(8);
-            let amount = amount
-                .get(..8)
-                .and_then(|slice| slice.try_into().ok())
-                .map(u64::from_le_bytes)
-                .ok_or(SwapError::InvalidInstruction)?;
-            Ok((amount, rest))
-        } else {
-            Err(SwapError::InvalidInstruction.into())
-        }
-    }
+    ///   Swap between any two tokens of a multi-token pool.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` SOURCE Account, amount is transferable by user transfer authority,
+    ///   4. `[writable]` DESTINATION Account assigned to USER as the owner.
+    ///   5. `[writable]` Pool token mint, to generate trading fees
+    ///   6. `[writable]` Fee account, to receive trading fees
+    ///   7. '[]` Token program id
+    ///   8. ..8+N `[writable]` Swap token accounts, in the order of the swap's tokens
+    ///   8+N `[optional, writable]` Host fee account to receive additional trading fees
+    SwapMultiToken(Swap),
 
-    /// Packs a [SwapInstruction](enum.SwapInstruction.html) into a byte buffer.
-    pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
-        match &*self {
-            Self::Initialize(Initialize {
-                nonce,
-                fees,
-                swap_curve,
-            }) => {
-                buf.push(0);
-                buf.push(*nonce);
-                let mut fees_slice = [0u8; Fees::LEN];
-                Pack::pack_into_slice(fees, &mut fees_slice[..]);
-                buf.extend_from_slice(&fees_slice);
-                let mut swap_curve_slice = [0u8; SwapCurve::LEN];
-                Pack::pack_into_slice(swap_curve, &mut swap_curve_slice[..]);
-                buf.extend_from_slice(&swap_curve_slice);
-            }
-            Self::Swap(Swap {
-                amount_in,
-                minimum_amount_out,
-            }) => {
-                buf.push(1);
-                buf.extend_from_slice(&amount_in.to_le_bytes());
-                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+    ///   Deposit all types of tokens into a multi-token pool.  The output is a
+    ///   "pool" token representing ownership in the pool. Inputs are converted
+    ///   to the current ratio.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` Pool MINT account, swap authority is the owner.
+    ///   4. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
+    ///   5. '[]` Token program id
+    ///   6. ..6+N `[writable]` Swap token accounts to deposit into, in the order of the swap's tokens
+    ///   6+N. ..6+2N `[writable]` User token accounts, user transfer authority can transfer amount
+    DepositAllMultiTokenTypes(DepositAllMultiTokenTypes),
+
+    ///   Withdraw all types of tokens from a multi-token pool at the current
+    ///   ratio, given pool tokens.  The pool tokens are burned in exchange for
+    ///   an equivalent amount of each token.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` Pool mint account, swap authority is the owner
+    ///   4. `[writable]` SOURCE Pool account, amount is transferable by user transfer authority.
+    ///   5. `[writable]` Fee account, to receive withdrawal fees
+    ///   6. '[]` Token program id
+    ///   7. ..7+N `[writable]` Swap token accounts to withdraw FROM, in the order of the swap's tokens
+    ///   7+N. ..7+2N `[writable]` User token accounts to credit
+    WithdrawAllMultiTokenTypes(WithdrawAllMultiTokenTypes),
+
+    ///   Deposit one type of tokens into a multi-token pool.  The output is a
+    ///   "pool" token representing ownership into the pool. Input token is
+    ///   converted as if a swap and deposit all token types were performed.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` SOURCE Account, amount is transferable by user transfer authority,
+    ///   4. `[writable]` Pool MINT account, swap authority is the owner.
+    ///   5. `[writable]` Pool Account to deposit the generated tokens, user is the owner.
+    ///   6. '[]` Token program id
+    ///   7. ..7+N `[writable]` Swap token accounts, in the order of the swap's tokens
+    DepositSingleMultiTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn),
+
+    ///   Withdraw one token type from a multi-token pool at the current ratio
+    ///   given the exact amount out expected.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` Pool mint account, swap authority is the owner
+    ///   4. `[writable]` SOURCE Pool account, amount is transferable by user transfer authority.
+    ///   5. `[writable]` User Account to credit
+    ///   6. `[writable]` Fee account, to receive withdrawal fees
+    ///   7. '[]` Token program id
+    ///   8. ..8+N `[writable]` Swap token accounts, in the order of the swap's tokens
+    WithdrawSingleMultiTokenTypeExactAmountOut(WithdrawSingleTokenTypeExactAmountOut),
+
+    ///   Initializes a new concentrated liquidity pool, where liquidity is
+    ///   provided within price ranges through positions instead of pool tokens.
+    ///
+    ///   0. `[writable, signer]` New concentrated liquidity pool to create, with
+    ///   space for the concentrated layout.
+    ///   1. `[]` swap authority derived from `create_program_address(&[Token-swap account])`
+    ///   2. `[]` token_a Account. Owned by swap authority.
+    ///   3. `[]` token_b Account. Owned by swap authority.
+    ///   4. '[]` Token program id
+    ///   5. `[optional, signer]` Admin authority, allowed to set the status
+    ///   of the pool.  Without it, the pool has no admin.
+    InitializeConcentrated(InitializeConcentrated),
+
+    ///   Opens an empty position within a price range of a concentrated
+    ///   liquidity pool.  Both ends of the range must be among the pool's
+    ///   usable ticks, set around the starting price when it is initialized.
+    ///
+    ///   0. `[]` Concentrated liquidity pool
+    ///   1. `[writable]` New position account, owned by the swap program
+    ///   2. `[signer]` Position owner
+    OpenPosition(OpenPosition),
+
+    ///   Adds liquidity to a position, depositing token A and token B as
+    ///   required by the current price.
+    ///
+    ///   0. `[writable]` Concentrated liquidity pool
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` Position
+    ///   3. `[signer]` Position owner
+    ///   4. `[]` user transfer authority
+    ///   5. `[writable]` token_a user transfer authority can transfer amount,
+    ///   6. `[writable]` token_b user transfer authority can transfer amount,
+    ///   7. `[writable]` token_a Base Account to deposit into.
+    ///   8. `[writable]` token_b Base Account to deposit into.
+    ///   9. '[]` Token program id
+    IncreaseLiquidity(IncreaseLiquidity),
+
+    ///   Removes liquidity from a position, withdrawing token A and token B at
+    ///   the current price.  Earned fees stay in the position until collected.
+    ///
+    ///   0. `[writable]` Concentrated liquidity pool
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` Position
+    ///   3. `[signer]` Position owner
+    ///   4. `[writable]` token_a Swap Account to withdraw FROM.
+    ///   5. `[writable]` token_b Swap Account to withdraw FROM.
+    ///   6. `[writable]` token_a user Account to credit.
+    ///   7. `[writable]` token_b user Account to credit.
+    ///   8. '[]` Token program id
+    DecreaseLiquidity(DecreaseLiquidity),
+
+    ///   Collects the trading fees earned by a position.
+    ///
+    ///   0. `[writable]` Concentrated liquidity pool
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` Position
+    ///   3. `[signer]` Position owner
+    ///   4. `[writable]` token_a Swap Account to withdraw FROM.
+    ///   5. `[writable]` token_b Swap Account to withdraw FROM.
+    ///   6. `[writable]` token_a user Account to credit.
+    ///   7. `[writable]` token_b user Account to credit.
+    ///   8. '[]` Token program id
+    CollectFees,
+
+    ///   Swap the tokens in a concentrated liquidity pool.  If the pool runs
+    ///   out of liquidity, only part of the amount is swapped.
+    ///
+    ///   0. `[writable]` Concentrated liquidity pool
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   4. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   5. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   6. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   7. '[]` Token program id
+    SwapConcentrated(Swap),
+
+    ///   Swap the tokens in the pool, receiving an exact amount of the
+    ///   DESTINATION token.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` user transfer authority
+    ///   3. `[writable]` token_(A|B) SOURCE Account, amount is transferable by user transfer authority,
+    ///   4. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   5. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   6. `[writable]` token_(A|B) DESTINATION Account assigned to USER as the owner.
+    ///   7. `[writable]` Pool token mint, to generate trading fees
+    ///   8. `[writable]` Fee account, to receive trading fees
+    ///   9. '[]` Token program id
+    ///   10 `[optional, writable]` Host fee account to receive additional trading fees
+    ///   11 `[optional]` Allowlist membership of the user transfer authority,
+    ///   required if the swap gates swaps.  It is owned by the swap program,
+    ///   and may directly follow the token program id without a host fee
+    ///   account.
+    SwapExactOut(SwapExactOut),
+
+    ///   Swap the tokens through a route of pools, where the DESTINATION token
+    ///   of each pool is the SOURCE token of the next one.  Intermediate
+    ///   tokens move directly between the pools, and the slippage check only
+    ///   applies to the amount received from the last pool.  The route fails
+    ///   if any pool cannot swap the whole amount it is given, because of
+    ///   rounding in the curve.  Pools gating swaps
+    ///   to their allowlist cannot be part of a route.
+    ///
+    ///   0. `[]` user transfer authority
+    ///   1. `[writable]` SOURCE Account, amount is transferable by user transfer authority,
+    ///   2. `[writable]` DESTINATION Account assigned to USER as the owner.
+    ///   3. '[]` Token program id
+    ///   4. ..4+6N the accounts of each of the N pools of the route, in order:
+    ///      0. `[writable]` Token-swap
+    ///      1. `[]` swap authority
+    ///      2. `[writable]` token_(A|B) Base Account to swap INTO.
+    ///      3. `[writable]` token_(A|B) Base Account to swap FROM.
+    ///      4. `[writable]` Pool token mint, to generate trading fees
+    ///      5. `[writable]` Fee account, to receive trading fees
+    RouteSwap(Swap),
+
+    ///   Update the fees of a swap.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    UpdateFees(UpdateFees),
+
+    ///   Change the pool token account receiving the fees of a swap.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[]` New Pool Token Account to deposit trading and withdraw fees.
+    ///   Must not be owned by swap authority.
+    SetFeeAccount,
+
+    ///   Transfer the admin of a swap of any version to a new authority.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[]` New admin authority
+    SetAdminAuthority,
+
+    ///   Start moving the amplifier of a stable curve swap linearly from its
+    ///   current value to a target value, reached at the given timestamp.
+    ///   The ramp must last at least a day, start at least a day after the
+    ///   previous one, and change the amplifier by at most a factor of 10.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    RampA(RampA),
+
+    ///   Stop the amplifier ramp of a stable curve swap, keeping its current
+    ///   value.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    StopRampA,
+
+    ///   Set the status of a swap of any version.  Pausing swaps also rejects
+    ///   all deposits, and withdrawals of a single token type, while new
+    ///   limit orders and farm stakes are still allowed.  A withdraw-only
+    ///   pool also rejects those.  Withdrawals of all token types, and
+    ///   removing liquidity from concentrated positions, are always allowed.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    SetPoolStatus(SetPoolStatus),
+
+    ///   Lend token A or B out of the pool for the length of the instruction.
+    ///   After transferring the amount to the DESTINATION account, the
+    ///   receiver program is invoked with the instruction created by
+    ///   `receive_flash_loan`, and must transfer the amount plus the flash
+    ///   loan fee back into the swap account.  The fee is set by the admin
+    ///   with `SetFlashLoanFee`, and starts at the trade and owner trade fees
+    ///   of the swap.  Pools created before the flash loan fee charge those
+    ///   trade fees.  The trade part stays in the pool, and the owner part is
+    ///   minted as pool tokens to the fee account, as for swaps.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` token_a Base Account
+    ///   3. `[writable]` token_b Base Account
+    ///   4. `[writable]` token_(A|B) DESTINATION Account, receiving the loan
+    ///   5. `[writable]` Pool token mint, to generate owner fees
+    ///   6. `[writable]` Fee account, to receive owner fees
+    ///   7. `[]` Token program id
+    ///   8. `[]` Flash loan receiver program id
+    ///   9. ..9+N `[]` N accounts passed on to the receiver program
+    FlashLoan(FlashLoan),
+
+    ///   Quote a swap, deposit or withdrawal without performing it, for
+    ///   example through a simulated transaction.  The quote is logged as
+    ///   `Quote: token_a_amount=.., token_b_amount=.., pool_token_amount=..,
+    ///   trade_fee=.., owner_fee=.., host_fee=.., price_after=..`, with the
+    ///   fields of `quote::QuoteResult`, and a `price_after` of 0 if the swap
+    ///   would no longer hold any token A.  Fails as the operation would if
+    ///   it is not allowed by the pool status.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` token_a Base Account
+    ///   2. `[]` token_b Base Account
+    ///   3. `[]` Pool token mint
+    Quote(Quote),
+
+    ///   Create a liquidity-mining farm for the pool tokens of a two-token
+    ///   swap.  Stakers of pool tokens earn the reward tokens added to the
+    ///   farm, in proportion to their stake.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[writable]` New farm account, owned by the swap program
+    ///   3. `[]` Farm authority derived from `create_program_address(&[Farm account])`
+    ///   4. `[]` Stake vault, a pool token account owned by the farm authority
+    InitializeFarm(InitializeFarm),
+
+    ///   Add a reward token to a farm, paid per slot from a vault from now on.
+    ///   Rewards are only paid out as far as the vault is funded.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[writable]` Farm
+    ///   3. `[]` Reward vault, a token account owned by the farm authority
+    AddFarmReward(AddFarmReward),
+
+    ///   Set the amount of a reward token paid per slot by a farm, after
+    ///   accruing the rewards at the previous rate.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[writable]` Farm
+    SetFarmRewardRate(SetFarmRewardRate),
+
+    ///   Stake pool tokens in a farm, initializing the stake account on
+    ///   first use.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[writable]` Farm
+    ///   2. `[writable]` Stake account, owned by the swap program
+    ///   3. `[signer]` Stake owner
+    ///   4. `[signer]` user transfer authority
+    ///   5. `[writable]` Pool token account, amount is transferable by user transfer authority
+    ///   6. `[writable]` Stake vault
+    ///   7. `[]` Token program id
+    Stake(Stake),
+
+    ///   Unstake pool tokens from a farm.  Rewards earned so far stay owed to
+    ///   the stake until claimed.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` Farm authority
+    ///   2. `[writable]` Farm
+    ///   3. `[writable]` Stake account
+    ///   4. `[signer]` Stake owner
+    ///   5. `[writable]` Stake vault
+    ///   6. `[writable]` Pool token account to credit
+    ///   7. `[]` Token program id
+    Unstake(Unstake),
+
+    ///   Claim the rewards earned by a stake.  Rewards a vault cannot pay out
+    ///   yet stay owed to the stake.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` Farm authority
+    ///   2. `[writable]` Farm
+    ///   3. `[writable]` Stake account
+    ///   4. `[signer]` Stake owner
+    ///   5. `[]` Token program id
+    ///   6. ..6+2N `[writable]` N pairs of reward vault and reward token
+    ///      account to credit, one for each reward of the farm in order
+    ClaimFarmRewards,
+
+    ///   Set the dynamic trade fee of a swap, which rises with the recent
+    ///   volatility of its price from a minimum to a maximum numerator over
+    ///   the trade fee denominator.  The volatility adds up the price
+    ///   movement of every trade, in basis points, and decays over the
+    ///   given number of slots.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    SetDynamicFee(SetDynamicFee),
+
+    ///   Set the allowlist of a swap.  With an allowlist authority, deposits
+    ///   require the user transfer authority to be a member of the
+    ///   allowlist, and so do swaps if they are gated.  Withdrawals are
+    ///   always allowed.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[]` New allowlist authority, or the default pubkey to remove the
+    ///   allowlist
+    SetAllowlist(SetAllowlist),
+
+    ///   Add a member to the allowlist of a swap.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[signer]` Allowlist authority
+    ///   2. `[writable]` New membership account, owned by the swap program
+    ///   3. `[]` Member, allowed to deposit as the user transfer authority
+    AddAllowlistMember,
+
+    ///   Remove a member from the allowlist of a swap.  The membership account
+    ///   can then be used for another member.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[signer]` Allowlist authority
+    ///   2. `[writable]` Membership account
+    RemoveAllowlistMember,
+
+    ///   Migrate a two-token swap created before the swap extension was
+    ///   added to a new swap account of the latest size, since accounts
+    ///   cannot grow.  The swap keeps its token accounts, pool mint, fees
+    ///   and curve, which are handed over to the new swap authority, and the
+    ///   extension is initialized as for a new swap, with the given admin
+    ///   if any.  The old swap is marked retired: it can no longer move the
+    ///   tokens of the pool or be initialized again, but its farms can still
+    ///   be unstaked and claimed, and its limit orders cancelled.  Without an admin, the old swap has no
+    ///   allowlist or fee split to carry over, so the new swap starts open to
+    ///   everyone and without a fee split.  The swap is migrated by the owner
+    ///   of the pool fee account, or the owner of the swap constraints if
+    ///   any.
+    ///
+    ///   0. `[writable]` Old Token-swap
+    ///   1. `[]` Old swap authority
+    ///   2. `[writable]` New Token-swap, owned by the swap program
+    ///   3. `[]` New swap authority derived from `create_program_address(&[New Token-swap account])`
+    ///   4. `[writable]` token_a Account
+    ///   5. `[writable]` token_b Account
+    ///   6. `[writable]` Pool Token Mint
+    ///   7. '[]` Token program id
+    ///   8. `[]` Pool fee account
+    ///   9. `[signer]` Migration authority
+    ///   10. `[optional, signer]` Admin of the new swap
+    MigrateSwap(MigrateSwap),
+
+    ///   Create a limit order selling one token of a two-token swap to the
+    ///   pool once its price crosses a level.  The tokens to sell are moved
+    ///   into an escrow until the order is filled or cancelled.  Pools gating
+    ///   swaps cannot fill limit orders.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[writable]` New limit order, owned by the swap program
+    ///   2. `[]` Order authority derived from `create_program_address(&[Limit order account])`
+    ///   3. `[signer]` Order owner
+    ///   4. `[signer]` user transfer authority
+    ///   5. `[writable]` Source account, amount is transferable by user transfer authority
+    ///   6. `[writable]` Order escrow, a token account of the token sold owned by the order authority
+    ///   7. `[]` Destination account of the token bought, credited when the order fills
+    ///   8. `[]` Token program id
+    CreateLimitOrder(CreateLimitOrder),
+
+    ///   Fill a limit order by selling its escrow to the pool, if the price of
+    ///   the swap has crossed its level.  Anyone may fill an order.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[writable]` Limit order
+    ///   3. `[]` Order authority
+    ///   4. `[writable]` Order escrow
+    ///   5. `[writable]` token_(A|B) Base Account to swap INTO.  Must be the SOURCE token.
+    ///   6. `[writable]` token_(A|B) Base Account to swap FROM.  Must be the DESTINATION token.
+    ///   7. `[writable]` Order destination
+    ///   8. `[writable]` Pool token mint, to generate trading fees
+    ///   9. `[writable]` Fee account, to receive trading fees
+    ///   10. `[]` Token program id
+    FillLimitOrder,
+
+    ///   Cancel a limit order, returning its escrow to the owner.  The order
+    ///   account can then be used for another order.  Orders on a swap that
+    ///   was migrated can no longer fill, but can still be cancelled.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[writable]` Limit order
+    ///   2. `[]` Order authority
+    ///   3. `[signer]` Order owner
+    ///   4. `[writable]` Order escrow
+    ///   5. `[writable]` Token account to refund
+    ///   6. `[]` Token program id
+    CancelLimitOrder,
+
+    ///   Set how the owner fees of a two-token swap are split among up to 4
+    ///   recipients, creating the fee split on first use.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[signer]` Admin authority
+    ///   2. `[writable]` Fee split, owned by the swap program
+    ///   3. ..3+2N `[]` N pairs of token A and token B accounts of the
+    ///      recipients, one for each share in order
+    SetFeeSplit(SetFeeSplit),
+
+    ///   Withdraw all pool tokens of the pool fee account into tokens A and
+    ///   B, and pay each recipient of a fee split its share.  Rounding leaves
+    ///   the rest in the pool.
+    ///
+    ///   0. `[]` Token-swap
+    ///   1. `[]` swap authority
+    ///   2. `[]` Fee split
+    ///   3. `[signer]` Owner or delegate of the pool fee account, or the swap
+    ///      authority if the pool tokens are delegated to it
+    ///   4. `[writable]` Pool fee account
+    ///   5. `[writable]` Pool token mint
+    ///   6. `[writable]` token_a Swap Account
+    ///   7. `[writable]` token_b Swap Account
+    ///   8. `[]` Token program id
+    ///   9. ..9+2N `[writable]` N pairs of token A and token B accounts of the
+    ///      recipients, in the order of the fee split
+    DistributeFees,
+
+    ///   Set the fee charged on flash loans of a two-token swap, as a trade
+    ///   fee kept by the pool and an owner fee minted to the pool fee
+    ///   account.
+    ///
+    ///   0. `[writable]` Token-swap
+    ///   1. `[signer]` Admin authority
+    SetFlashLoanFee(SetFlashLoanFee),
+}
+
+impl SwapInstruction {
+    /// Unpacks a byte buffer into a [SwapInstruction](enum.SwapInstruction.html).
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
+        Ok(match tag {
+            0 => {
+                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                if rest.len() >= Fees::LEN {
+                    let (fees, rest) = rest.split_at(Fees::LEN);
+                    let fees = Fees::unpack_unchecked(fees)?;
+                    let swap_curve = SwapCurve::unpack_unchecked(rest)?;
+                    Self::Initialize(Initialize {
+                        nonce,
+                        fees,
+                        swap_curve,
+                    })
+                } else {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
             }
-            Self::DepositAllTokenTypes(DepositAllTokenTypes {
-                pool_token_amount,
-                maximum_token_a_amount,
-                maximum_token_b_amount,
-            }) => {
-                buf.push(2);
-                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
-                buf.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
-                buf.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+            1 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::Swap(Swap {
+                    amount_in,
+                    minimum_amount_out,
+                })
             }
-            Self::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
-                pool_token_amount,
-                minimum_token_a_amount,
-                minimum_token_b_amount,
-            }) => {
-                buf.push(3);
-                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
-                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
-                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+            2 => {
+                let (pool_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_token_a_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DepositAllTokenTypes(DepositAllTokenTypes {
+                    pool_token_amount,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                })
             }
-            Self::DepositSingleTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn {
-                source_token_amount,
-                minimum_pool_token_amount,
-            }) => {
-                buf.push(4);
-                buf.extend_from_slice(&source_token_amount.to_le_bytes());
-                buf.extend_from_slice(&minimum_pool_token_amount.to_le_bytes());
+            3 => {
+                let (pool_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_token_a_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
+                    pool_token_amount,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                })
             }
-            Self::WithdrawSingleTokenTypeExactAmountOut(
-                WithdrawSingleTokenTypeExactAmountOut {
+            4 => {
+                let (source_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_pool_token_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DepositSingleTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                })
+            }
+            5 => {
+                let (destination_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_pool_token_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::WithdrawSingleTokenTypeExactAmountOut(WithdrawSingleTokenTypeExactAmountOut {
                     destination_token_amount,
                     maximum_pool_token_amount,
-                },
-            ) => {
-                buf.push(5);
-                buf.extend_from_slice(&destination_token_amount.to_le_bytes());
-                buf.extend_from_slice(&maximum_pool_token_amount.to_le_bytes());
+                })
             }
-        }
-        buf
+            6 => {
+                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                if rest.len() >= Fees::LEN + SwapCurve::LEN {
+                    let (fees, rest) = rest.split_at(Fees::LEN);
+                    let fees = Fees::unpack_unchecked(fees)?;
+                    let (swap_curve, rest) = rest.split_at(SwapCurve::LEN);
+                    let swap_curve = SwapCurve::unpack_unchecked(swap_curve)?;
+                    let (weights, _rest) = Self::unpack_u64_vec(rest)?;
+                    Self::InitializeMultiToken(InitializeMultiToken {
+                        nonce,
+                        fees,
+                        swap_curve,
+                        weights,
+                    })
+                } else {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+            }
+            7 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::SwapMultiToken(Swap {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            8 => {
+                let (pool_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_token_amounts, _rest) = Self::unpack_u64_vec(rest)?;
+                Self::DepositAllMultiTokenTypes(DepositAllMultiTokenTypes {
+                    pool_token_amount,
+                    maximum_token_amounts,
+                })
+            }
+            9 => {
+                let (pool_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_token_amounts, _rest) = Self::unpack_u64_vec(rest)?;
+                Self::WithdrawAllMultiTokenTypes(WithdrawAllMultiTokenTypes {
+                    pool_token_amount,
+                    minimum_token_amounts,
+                })
+            }
+            10 => {
+                let (source_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_pool_token_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DepositSingleMultiTokenTypeExactAmountIn(
+                    DepositSingleTokenTypeExactAmountIn {
+                        source_token_amount,
+                        minimum_pool_token_amount,
+                    },
+                )
+            }
+            11 => {
+                let (destination_token_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_pool_token_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::WithdrawSingleMultiTokenTypeExactAmountOut(
+                    WithdrawSingleTokenTypeExactAmountOut {
+                        destination_token_amount,
+                        maximum_pool_token_amount,
+                    },
+                )
+            }
+            12 => {
+                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                if rest.len() >= Fees::LEN {
+                    let (fees, rest) = rest.split_at(Fees::LEN);
+                    let fees = Fees::unpack_unchecked(fees)?;
+                    let (tick_spacing, rest) = Self::unpack_u16(rest)?;
+                    let (min_position_liquidity, rest) = Self::unpack_u128(rest)?;
+                    let (initial_sqrt_price, _rest) = Self::unpack_u128(rest)?;
+                    Self::InitializeConcentrated(InitializeConcentrated {
+                        nonce,
+                        fees,
+                        tick_spacing,
+                        min_position_liquidity,
+                        initial_sqrt_price,
+                    })
+                } else {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+            }
+            13 => {
+                let (tick_lower, rest) = Self::unpack_i32(rest)?;
+                let (tick_upper, _rest) = Self::unpack_i32(rest)?;
+                Self::OpenPosition(OpenPosition {
+                    tick_lower,
+                    tick_upper,
+                })
+            }
+            14 => {
+                let (liquidity, rest) = Self::unpack_u128(rest)?;
+                let (maximum_token_a_amount, rest) = Self::unpack_u64(rest)?;
+                let (maximum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::IncreaseLiquidity(IncreaseLiquidity {
+                    liquidity,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                })
+            }
+            15 => {
+                let (liquidity, rest) = Self::unpack_u128(rest)?;
+                let (minimum_token_a_amount, rest) = Self::unpack_u64(rest)?;
+                let (minimum_token_b_amount, _rest) = Self::unpack_u64(rest)?;
+                Self::DecreaseLiquidity(DecreaseLiquidity {
+                    liquidity,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                })
+            }
+            16 => Self::CollectFees,
+            17 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::SwapConcentrated(Swap {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            18 => {
+                let (amount_out, rest) = Self::unpack_u64(rest)?;
+                let (maximum_amount_in, _rest) = Self::unpack_u64(rest)?;
+                Self::SwapExactOut(SwapExactOut {
+                    amount_out,
+                    maximum_amount_in,
+                })
+            }
+            19 => {
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::RouteSwap(Swap {
+                    amount_in,
+                    minimum_amount_out,
+                })
+            }
+            20 => {
+                if rest.len() >= Fees::LEN {
+                    let fees = Fees::unpack_unchecked(&rest[..Fees::LEN])?;
+                    Self::UpdateFees(UpdateFees { fees })
+                } else {
+                    return Err(SwapError::InvalidInstruction.into());
+                }
+            }
+            21 => Self::SetFeeAccount,
+            22 => Self::SetAdminAuthority,
+            23 => {
+                let (target_amp, rest) = Self::unpack_u64(rest)?;
+                let (stop_ramp_ts, _rest) = Self::unpack_i64(rest)?;
+                Self::RampA(RampA {
+                    target_amp,
+                    stop_ramp_ts,
+                })
+            }
+            24 => Self::StopRampA,
+            25 => {
+                let (&status, _rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                let status = status
+                    .try_into()
+                    .map_err(|_| SwapError::InvalidInstruction)?;
+                Self::SetPoolStatus(SetPoolStatus { status })
+            }
+            26 => {
+                let (amount, _rest) = Self::unpack_u64(rest)?;
+                Self::FlashLoan(FlashLoan { amount })
+            }
+            27 => {
+                let (&operation, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                let operation = QuoteOperation::try_from(operation)?;
+                let (amount, rest) = Self::unpack_u64(rest)?;
+                let trade_direction = match rest.first() {
+                    Some(0) => TradeDirection::AtoB,
+                    Some(1) => TradeDirection::BtoA,
+                    _ => return Err(SwapError::InvalidInstruction.into()),
+                };
+                Self::Quote(Quote {
+                    operation,
+                    amount,
+                    trade_direction,
+                })
+            }
+            28 => {
+                let (&nonce, _rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                Self::InitializeFarm(InitializeFarm { nonce })
+            }
+            29 => {
+                let (reward_per_slot, _rest) = Self::unpack_u64(rest)?;
+                Self::AddFarmReward(AddFarmReward { reward_per_slot })
+            }
+            30 => {
+                let (&reward_index, rest) =
+                    rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                let (reward_per_slot, _rest) = Self::unpack_u64(rest)?;
+                Self::SetFarmRewardRate(SetFarmRewardRate {
+                    reward_index,
+                    reward_per_slot,
+                })
+            }
+            31 => {
+                let (amount, _rest) = Self::unpack_u64(rest)?;
+                Self::Stake(Stake { amount })
+            }
+            32 => {
+                let (amount, _rest) = Self::unpack_u64(rest)?;
+                Self::Unstake(Unstake { amount })
+            }
+            33 => Self::ClaimFarmRewards,
+            34 => {
+                let (min_trade_fee_numerator, rest) = Self::unpack_u64(rest)?;
+                let (max_trade_fee_numerator, rest) = Self::unpack_u64(rest)?;
+                let (max_volatility, rest) = Self::unpack_u64(rest)?;
+                let (volatility_period, _rest) = Self::unpack_u64(rest)?;
+                Self::SetDynamicFee(SetDynamicFee {
+                    min_trade_fee_numerator,
+                    max_trade_fee_numerator,
+                    max_volatility,
+                    volatility_period,
+                })
+            }
+            35 => {
+                let gate_swaps = match rest.first() {
+                    Some(0) => false,
+                    Some(1) => true,
+                    _ => return Err(SwapError::InvalidInstruction.into()),
+                };
+                Self::SetAllowlist(SetAllowlist { gate_swaps })
+            }
+            36 => Self::AddAllowlistMember,
+            37 => Self::RemoveAllowlistMember,
+            38 => {
+                let (&nonce, _rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                Self::MigrateSwap(MigrateSwap { nonce })
+            }
+            39 => {
+                let (&nonce, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                let (trade_direction, rest) = match rest.split_first() {
+                    Some((0, rest)) => (TradeDirection::AtoB, rest),
+                    Some((1, rest)) => (TradeDirection::BtoA, rest),
+                    _ => return Err(SwapError::InvalidInstruction.into()),
+                };
+                let (&kind, rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                let kind = kind.try_into().map_err(|_| SwapError::InvalidInstruction)?;
+                let (amount_in, rest) = Self::unpack_u64(rest)?;
+                let (trigger_amount_out, rest) = Self::unpack_u64(rest)?;
+                let (minimum_amount_out, _rest) = Self::unpack_u64(rest)?;
+                Self::CreateLimitOrder(CreateLimitOrder {
+                    nonce,
+                    trade_direction,
+                    kind,
+                    amount_in,
+                    trigger_amount_out,
+                    minimum_amount_out,
+                })
+            }
+            40 => Self::FillLimitOrder,
+            41 => Self::CancelLimitOrder,
+            42 => {
+                let (&count, mut rest) = rest.split_first().ok_or(SwapError::InvalidInstruction)?;
+                let mut shares = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    if rest.len() < 2 {
+                        return Err(SwapError::InvalidInstruction.into());
+                    }
+                    let (share, remaining) = rest.split_at(2);
+                    shares.push(u16::from_le_bytes([share[0], share[1]]));
+                    rest = remaining;
+                }
+                Self::SetFeeSplit(SetFeeSplit { shares })
+            }
+            43 => Self::DistributeFees,
+            44 => {
+                let (trade_fee_numerator, rest) = Self::unpack_u64(rest)?;
+                let (trade_fee_denominator, rest) = Self::unpack_u64(rest)?;
+                let (owner_trade_fee_numerator, rest) = Self::unpack_u64(rest)?;
+                let (owner_trade_fee_denominator, _rest) = Self::unpack_u64(rest)?;
+                Self::SetFlashLoanFee(SetFlashLoanFee {
+                    trade_fee_numerator,
+                    trade_fee_denominator,
+                    owner_trade_fee_numerator,
+                    owner_trade_fee_denominator,
+                })
+            }
+            _ => return Err(SwapError::InvalidInstruction.into()),
+        })
+    }
+
+    fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+        if input.len() >= 8 {
+            let (amount, rest) = input.split_at(8);
+            let amount = amount
+                .get(..8)
+                .and_then(|slice| slice.try_into().ok())
+                .map(u64::from_le_bytes)
+                .ok_or(SwapError::InvalidInstruction)?;
+            Ok((amount, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_i64(input: &[u8]) -> Result<(i64, &[u8]), ProgramError> {
+        if input.len() >= 8 {
+            let (value, rest) = input.split_at(8);
+            let value = value
+                .try_into()
+                .map(i64::from_le_bytes)
+                .map_err(|_| SwapError::InvalidInstruction)?;
+            Ok((value, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        if input.len() >= 2 {
+            let (value, rest) = input.split_at(2);
+            let value = value
+                .try_into()
+                .map(u16::from_le_bytes)
+                .map_err(|_| SwapError::InvalidInstruction)?;
+            Ok((value, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_i32(input: &[u8]) -> Result<(i32, &[u8]), ProgramError> {
+        if input.len() >= 4 {
+            let (value, rest) = input.split_at(4);
+            let value = value
+                .try_into()
+                .map(i32::from_le_bytes)
+                .map_err(|_| SwapError::InvalidInstruction)?;
+            Ok((value, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+
+    fn unpack_u128(input: &[u8]) -> Result<(u128, &[u8]), ProgramError> {
+        if input.len() >= 16 {
+            let (value, rest) = input.split_at(16);
+            let value = value
+                .try_into()
+                .map(u128::from_le_bytes)
+                .map_err(|_| SwapError::InvalidInstruction)?;
+            Ok((value, rest))
+        } else {
+            Err(SwapError::InvalidInstruction.into())
+        }
+    }
+
+    /// Unpacks a list of u64 values, prefixed by their count as a single byte
+    fn unpack_u64_vec(input: &[u8]) -> Result<(Vec<u64>, &[u8]), ProgramError> {
+        let (&count, mut rest) = input.split_first().ok_or(SwapError::InvalidInstruction)?;
+        let mut values = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (value, remaining) = Self::unpack_u64(rest)?;
+            values.push(value);
+            rest = remaining;
+        }
+        Ok((values, rest))
+    }
+
+    /// Packs a list of u64 values, prefixed by their count as a single byte
+    fn pack_u64_vec(values: &[u64], buf: &mut Vec<u8>) {
+        buf.push(values.len() as u8);
+        for value in values {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// Packs a [SwapInstruction](enum.SwapInstruction.html) into a byte buffer.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match &*self {
+            Self::Initialize(Initialize {
+                nonce,
+                fees,
+                swap_curve,
+            }) => {
+                buf.push(0);
+                buf.push(*nonce);
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+                let mut swap_curve_slice = [0u8; SwapCurve::LEN];
+                Pack::pack_into_slice(swap_curve, &mut swap_curve_slice[..]);
+                buf.extend_from_slice(&swap_curve_slice);
+            }
+            Self::Swap(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(1);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::DepositAllTokenTypes(DepositAllTokenTypes {
+                pool_token_amount,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                buf.push(2);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+            }
+            Self::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
+                pool_token_amount,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                buf.push(3);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+            }
+            Self::DepositSingleTokenTypeExactAmountIn(DepositSingleTokenTypeExactAmountIn {
+                source_token_amount,
+                minimum_pool_token_amount,
+            }) => {
+                buf.push(4);
+                buf.extend_from_slice(&source_token_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_pool_token_amount.to_le_bytes());
+            }
+            Self::WithdrawSingleTokenTypeExactAmountOut(
+                WithdrawSingleTokenTypeExactAmountOut {
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                },
+            ) => {
+                buf.push(5);
+                buf.extend_from_slice(&destination_token_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_pool_token_amount.to_le_bytes());
+            }
+            Self::InitializeMultiToken(InitializeMultiToken {
+                nonce,
+                fees,
+                swap_curve,
+                weights,
+            }) => {
+                buf.push(6);
+                buf.push(*nonce);
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+                let mut swap_curve_slice = [0u8; SwapCurve::LEN];
+                Pack::pack_into_slice(swap_curve, &mut swap_curve_slice[..]);
+                buf.extend_from_slice(&swap_curve_slice);
+                Self::pack_u64_vec(weights, &mut buf);
+            }
+            Self::SwapMultiToken(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(7);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::DepositAllMultiTokenTypes(DepositAllMultiTokenTypes {
+                pool_token_amount,
+                maximum_token_amounts,
+            }) => {
+                buf.push(8);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                Self::pack_u64_vec(maximum_token_amounts, &mut buf);
+            }
+            Self::WithdrawAllMultiTokenTypes(WithdrawAllMultiTokenTypes {
+                pool_token_amount,
+                minimum_token_amounts,
+            }) => {
+                buf.push(9);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                Self::pack_u64_vec(minimum_token_amounts, &mut buf);
+            }
+            Self::DepositSingleMultiTokenTypeExactAmountIn(
+                DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                },
+            ) => {
+                buf.push(10);
+                buf.extend_from_slice(&source_token_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_pool_token_amount.to_le_bytes());
+            }
+            Self::WithdrawSingleMultiTokenTypeExactAmountOut(
+                WithdrawSingleTokenTypeExactAmountOut {
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                },
+            ) => {
+                buf.push(11);
+                buf.extend_from_slice(&destination_token_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_pool_token_amount.to_le_bytes());
+            }
+            Self::InitializeConcentrated(InitializeConcentrated {
+                nonce,
+                fees,
+                tick_spacing,
+                min_position_liquidity,
+                initial_sqrt_price,
+            }) => {
+                buf.push(12);
+                buf.push(*nonce);
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+                buf.extend_from_slice(&tick_spacing.to_le_bytes());
+                buf.extend_from_slice(&min_position_liquidity.to_le_bytes());
+                buf.extend_from_slice(&initial_sqrt_price.to_le_bytes());
+            }
+            Self::OpenPosition(OpenPosition {
+                tick_lower,
+                tick_upper,
+            }) => {
+                buf.push(13);
+                buf.extend_from_slice(&tick_lower.to_le_bytes());
+                buf.extend_from_slice(&tick_upper.to_le_bytes());
+            }
+            Self::IncreaseLiquidity(IncreaseLiquidity {
+                liquidity,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                buf.push(14);
+                buf.extend_from_slice(&liquidity.to_le_bytes());
+                buf.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+            }
+            Self::DecreaseLiquidity(DecreaseLiquidity {
+                liquidity,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                buf.push(15);
+                buf.extend_from_slice(&liquidity.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+                buf.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+            }
+            Self::CollectFees => {
+                buf.push(16);
+            }
+            Self::SwapConcentrated(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(17);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::SwapExactOut(SwapExactOut {
+                amount_out,
+                maximum_amount_in,
+            }) => {
+                buf.push(18);
+                buf.extend_from_slice(&amount_out.to_le_bytes());
+                buf.extend_from_slice(&maximum_amount_in.to_le_bytes());
+            }
+            Self::RouteSwap(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                buf.push(19);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::UpdateFees(UpdateFees { fees }) => {
+                buf.push(20);
+                let mut fees_slice = [0u8; Fees::LEN];
+                Pack::pack_into_slice(fees, &mut fees_slice[..]);
+                buf.extend_from_slice(&fees_slice);
+            }
+            Self::SetFeeAccount => {
+                buf.push(21);
+            }
+            Self::SetAdminAuthority => {
+                buf.push(22);
+            }
+            Self::RampA(RampA {
+                target_amp,
+                stop_ramp_ts,
+            }) => {
+                buf.push(23);
+                buf.extend_from_slice(&target_amp.to_le_bytes());
+                buf.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+            }
+            Self::StopRampA => {
+                buf.push(24);
+            }
+            Self::SetPoolStatus(SetPoolStatus { status }) => {
+                buf.push(25);
+                buf.push(*status as u8);
+            }
+            Self::FlashLoan(FlashLoan { amount }) => {
+                buf.push(26);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::Quote(Quote {
+                operation,
+                amount,
+                trade_direction,
+            }) => {
+                buf.push(27);
+                buf.push(*operation as u8);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.push(*trade_direction as u8);
+            }
+            Self::InitializeFarm(InitializeFarm { nonce }) => {
+                buf.push(28);
+                buf.push(*nonce);
+            }
+            Self::AddFarmReward(AddFarmReward { reward_per_slot }) => {
+                buf.push(29);
+                buf.extend_from_slice(&reward_per_slot.to_le_bytes());
+            }
+            Self::SetFarmRewardRate(SetFarmRewardRate {
+                reward_index,
+                reward_per_slot,
+            }) => {
+                buf.push(30);
+                buf.push(*reward_index);
+                buf.extend_from_slice(&reward_per_slot.to_le_bytes());
+            }
+            Self::Stake(Stake { amount }) => {
+                buf.push(31);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::Unstake(Unstake { amount }) => {
+                buf.push(32);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::ClaimFarmRewards => {
+                buf.push(33);
+            }
+            Self::SetDynamicFee(SetDynamicFee {
+                min_trade_fee_numerator,
+                max_trade_fee_numerator,
+                max_volatility,
+                volatility_period,
+            }) => {
+                buf.push(34);
+                buf.extend_from_slice(&min_trade_fee_numerator.to_le_bytes());
+                buf.extend_from_slice(&max_trade_fee_numerator.to_le_bytes());
+                buf.extend_from_slice(&max_volatility.to_le_bytes());
+                buf.extend_from_slice(&volatility_period.to_le_bytes());
+            }
+            Self::SetAllowlist(SetAllowlist { gate_swaps }) => {
+                buf.push(35);
+                buf.push(*gate_swaps as u8);
+            }
+            Self::AddAllowlistMember => {
+                buf.push(36);
+            }
+            Self::RemoveAllowlistMember => {
+                buf.push(37);
+            }
+            Self::MigrateSwap(MigrateSwap { nonce }) => {
+                buf.push(38);
+                buf.push(*nonce);
+            }
+            Self::CreateLimitOrder(CreateLimitOrder {
+                nonce,
+                trade_direction,
+                kind,
+                amount_in,
+                trigger_amount_out,
+                minimum_amount_out,
+            }) => {
+                buf.push(39);
+                buf.push(*nonce);
+                buf.push(*trade_direction as u8);
+                buf.push(*kind as u8);
+                buf.extend_from_slice(&amount_in.to_le_bytes());
+                buf.extend_from_slice(&trigger_amount_out.to_le_bytes());
+                buf.extend_from_slice(&minimum_amount_out.to_le_bytes());
+            }
+            Self::FillLimitOrder => {
+                buf.push(40);
+            }
+            Self::CancelLimitOrder => {
+                buf.push(41);
+            }
+            Self::SetFeeSplit(SetFeeSplit { shares }) => {
+                buf.push(42);
+                buf.push(shares.len() as u8);
+                for share in shares {
+                    buf.extend_from_slice(&share.to_le_bytes());
+                }
+            }
+            Self::DistributeFees => {
+                buf.push(43);
+            }
+            Self::SetFlashLoanFee(SetFlashLoanFee {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+            }) => {
+                buf.push(44);
+                buf.extend_from_slice(&trade_fee_numerator.to_le_bytes());
+                buf.extend_from_slice(&trade_fee_denominator.to_le_bytes());
+                buf.extend_from_slice(&owner_trade_fee_numerator.to_le_bytes());
+                buf.extend_from_slice(&owner_trade_fee_denominator.to_le_bytes());
+            }
+        }
+        buf
+    }
+}
+
+/// Creates an 'initialize' instruction.
+pub fn initialize(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    fee_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    admin_pubkey: Option<&Pubkey>,
+    nonce: u8,
+    fees: Fees,
+    swap_curve: SwapCurve,
+) -> Result<Instruction, ProgramError> {
+    let init_data = SwapInstruction::Initialize(Initialize {
+        nonce,
+        fees,
+        swap_curve,
+    });
+    let data = init_data.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*token_a_pubkey, false),
+        AccountMeta::new_readonly(*token_b_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*fee_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    if let Some(admin_pubkey) = admin_pubkey {
+        accounts.push(AccountMeta::new_readonly(*admin_pubkey, true));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'update_fees' instruction.
+pub fn update_fees(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    fees: Fees,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::UpdateFees(UpdateFees { fees }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_fee_account' instruction.
+pub fn set_fee_account(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    fee_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetFeeAccount.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*fee_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_admin_authority' instruction.
+pub fn set_admin_authority(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    new_admin_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetAdminAuthority.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*new_admin_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'ramp_a' instruction.
+pub fn ramp_a(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    target_amp: u64,
+    stop_ramp_ts: UnixTimestamp,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::RampA(RampA {
+        target_amp,
+        stop_ramp_ts,
+    })
+    .pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'stop_ramp_a' instruction.
+pub fn stop_ramp_a(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::StopRampA.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_pool_status' instruction.
+pub fn set_pool_status(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    status: PoolStatus,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetPoolStatus(SetPoolStatus { status }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_dynamic_fee' instruction.
+pub fn set_dynamic_fee(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    instruction: SetDynamicFee,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetDynamicFee(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_allowlist' instruction.
+pub fn set_allowlist(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    allowlist_authority_pubkey: &Pubkey,
+    gate_swaps: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetAllowlist(SetAllowlist { gate_swaps }).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new_readonly(*allowlist_authority_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'add_allowlist_member' instruction.
+pub fn add_allowlist_member(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    allowlist_authority_pubkey: &Pubkey,
+    membership_pubkey: &Pubkey,
+    member_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::AddAllowlistMember.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*allowlist_authority_pubkey, true),
+        AccountMeta::new(*membership_pubkey, false),
+        AccountMeta::new_readonly(*member_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'remove_allowlist_member' instruction.
+pub fn remove_allowlist_member(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    allowlist_authority_pubkey: &Pubkey,
+    membership_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::RemoveAllowlistMember.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*allowlist_authority_pubkey, true),
+        AccountMeta::new(*membership_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Appends the allowlist membership of the user transfer authority to a
+/// 'swap', 'swap_exact_out', 'deposit_all_token_types' or
+/// 'deposit_single_token_type_exact_amount_in' instruction, for swaps with an
+/// allowlist.
+pub fn with_allowlist_membership(
+    mut instruction: Instruction,
+    membership_pubkey: &Pubkey,
+) -> Instruction {
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(*membership_pubkey, false));
+    instruction
+}
+
+/// Creates a 'migrate_swap' instruction.
+pub fn migrate_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    new_swap_pubkey: &Pubkey,
+    new_authority_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    migration_authority_pubkey: &Pubkey,
+    admin_pubkey: Option<&Pubkey>,
+    nonce: u8,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::MigrateSwap(MigrateSwap { nonce }).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*new_swap_pubkey, false),
+        AccountMeta::new_readonly(*new_authority_pubkey, false),
+        AccountMeta::new(*token_a_pubkey, false),
+        AccountMeta::new(*token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*migration_authority_pubkey, true),
+    ];
+    if let Some(admin_pubkey) = admin_pubkey {
+        accounts.push(AccountMeta::new_readonly(*admin_pubkey, true));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'create_limit_order' instruction.
+pub fn create_limit_order(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    order_pubkey: &Pubkey,
+    order_authority_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    escrow_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    instruction: CreateLimitOrder,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::CreateLimitOrder(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*order_pubkey, false),
+        AccountMeta::new_readonly(*order_authority_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*escrow_pubkey, false),
+        AccountMeta::new_readonly(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'fill_limit_order' instruction.
+pub fn fill_limit_order(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    order_pubkey: &Pubkey,
+    order_authority_pubkey: &Pubkey,
+    escrow_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::FillLimitOrder.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*order_pubkey, false),
+        AccountMeta::new_readonly(*order_authority_pubkey, false),
+        AccountMeta::new(*escrow_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'cancel_limit_order' instruction.
+pub fn cancel_limit_order(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    order_pubkey: &Pubkey,
+    order_authority_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    escrow_pubkey: &Pubkey,
+    refund_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::CancelLimitOrder.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*order_pubkey, false),
+        AccountMeta::new_readonly(*order_authority_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new(*escrow_pubkey, false),
+        AccountMeta::new(*refund_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_fee_split' instruction, given the token A and token B
+/// accounts and share of every recipient.
+pub fn set_fee_split(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    fee_split_pubkey: &Pubkey,
+    recipients: &[(Pubkey, Pubkey, u16)],
+) -> Result<Instruction, ProgramError> {
+    let shares = recipients.iter().map(|(_, _, share)| *share).collect();
+    let data = SwapInstruction::SetFeeSplit(SetFeeSplit { shares }).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*fee_split_pubkey, false),
+    ];
+    for (token_a_pubkey, token_b_pubkey, _) in recipients {
+        accounts.push(AccountMeta::new_readonly(*token_a_pubkey, false));
+        accounts.push(AccountMeta::new_readonly(*token_b_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'distribute_fees' instruction, given the token A and token B
+/// accounts of every recipient of the fee split.
+pub fn distribute_fees(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    fee_split_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    recipients: &[(Pubkey, Pubkey)],
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::DistributeFees.pack();
+
+    // the swap authority signs for itself when the pool tokens are
+    // delegated to it
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*fee_split_pubkey, false),
+        AccountMeta::new_readonly(
+            *user_transfer_authority_pubkey,
+            user_transfer_authority_pubkey != authority_pubkey,
+        ),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    for (token_a_pubkey, token_b_pubkey) in recipients {
+        accounts.push(AccountMeta::new(*token_a_pubkey, false));
+        accounts.push(AccountMeta::new(*token_b_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_flash_loan_fee' instruction.
+pub fn set_flash_loan_fee(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    instruction: SetFlashLoanFee,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetFlashLoanFee(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'flash_loan' instruction.
+pub fn flash_loan(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    receiver_program_id: &Pubkey,
+    receiver_accounts: &[AccountMeta],
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::FlashLoan(FlashLoan { amount }).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*receiver_program_id, false),
+    ];
+    accounts.extend_from_slice(receiver_accounts);
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates the instruction invoked on a flash loan receiver program, with the
+/// data `[0, amount, fee]`, the amounts as little-endian u64s.
+///
+///   0. `[writable]` token_(A|B) DESTINATION Account, holding the loan
+///   1. `[writable]` token_(A|B) Base Account to repay the amount plus fee into
+///   2. `[]` Token program id
+///   3. ..3+N `[]` N accounts given to the flash loan instruction
+pub fn receive_flash_loan(
+    receiver_program_id: &Pubkey,
+    destination_pubkey: &Pubkey,
+    swap_token_pubkey: &Pubkey,
+    token_program_id: &Pubkey,
+    receiver_accounts: &[AccountMeta],
+    amount: u64,
+    fee: u64,
+) -> Instruction {
+    let mut data = Vec::with_capacity(1 + 2 * size_of::<u64>());
+    data.push(0);
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&fee.to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*swap_token_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    accounts.extend_from_slice(receiver_accounts);
+
+    Instruction {
+        program_id: *receiver_program_id,
+        accounts,
+        data,
+    }
+}
+
+/// Creates a 'quote' instruction.
+pub fn quote(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    quote: Quote,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Quote(quote).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*swap_token_a_pubkey, false),
+        AccountMeta::new_readonly(*swap_token_b_pubkey, false),
+        AccountMeta::new_readonly(*pool_mint_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'initialize_farm' instruction.
+pub fn initialize_farm(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    farm_pubkey: &Pubkey,
+    farm_authority_pubkey: &Pubkey,
+    stake_vault_pubkey: &Pubkey,
+    instruction: InitializeFarm,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::InitializeFarm(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*farm_pubkey, false),
+        AccountMeta::new_readonly(*farm_authority_pubkey, false),
+        AccountMeta::new_readonly(*stake_vault_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'add_farm_reward' instruction.
+pub fn add_farm_reward(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    farm_pubkey: &Pubkey,
+    reward_vault_pubkey: &Pubkey,
+    instruction: AddFarmReward,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::AddFarmReward(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*farm_pubkey, false),
+        AccountMeta::new_readonly(*reward_vault_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_farm_reward_rate' instruction.
+pub fn set_farm_reward_rate(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    admin_pubkey: &Pubkey,
+    farm_pubkey: &Pubkey,
+    instruction: SetFarmRewardRate,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SetFarmRewardRate(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*admin_pubkey, true),
+        AccountMeta::new(*farm_pubkey, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'stake' instruction.
+pub fn stake(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    farm_pubkey: &Pubkey,
+    stake_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    stake_vault_pubkey: &Pubkey,
+    instruction: Stake,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Stake(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*farm_pubkey, false),
+        AccountMeta::new(*stake_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*stake_vault_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'unstake' instruction.
+pub fn unstake(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    farm_authority_pubkey: &Pubkey,
+    farm_pubkey: &Pubkey,
+    stake_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    stake_vault_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    instruction: Unstake,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Unstake(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*farm_authority_pubkey, false),
+        AccountMeta::new(*farm_pubkey, false),
+        AccountMeta::new(*stake_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new(*stake_vault_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'claim_farm_rewards' instruction, with the pairs of reward
+/// vault and destination accounts in the order of the farm rewards.
+pub fn claim_farm_rewards(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    farm_authority_pubkey: &Pubkey,
+    farm_pubkey: &Pubkey,
+    stake_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    reward_pubkeys: &[(Pubkey, Pubkey)],
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::ClaimFarmRewards.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*farm_authority_pubkey, false),
+        AccountMeta::new(*farm_pubkey, false),
+        AccountMeta::new(*stake_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    for (vault_pubkey, destination_pubkey) in reward_pubkeys {
+        accounts.push(AccountMeta::new(*vault_pubkey, false));
+        accounts.push(AccountMeta::new(*destination_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'deposit_all_token_types' instruction.
+pub fn deposit_all_token_types(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    deposit_token_a_pubkey: &Pubkey,
+    deposit_token_b_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    instruction: DepositAllTokenTypes,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::DepositAllTokenTypes(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*deposit_token_a_pubkey, false),
+        AccountMeta::new(*deposit_token_b_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'withdraw_all_token_types' instruction.
+pub fn withdraw_all_token_types(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    destination_token_a_pubkey: &Pubkey,
+    destination_token_b_pubkey: &Pubkey,
+    instruction: WithdrawAllTokenTypes,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::WithdrawAllTokenTypes(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_token_a_pubkey, false),
+        AccountMeta::new(*destination_token_b_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'deposit_single_token_type_exact_amount_in' instruction.
+pub fn deposit_single_token_type_exact_amount_in(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_token_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    instruction: DepositSingleTokenTypeExactAmountIn,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::DepositSingleTokenTypeExactAmountIn(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_token_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'withdraw_single_token_type_exact_amount_out' instruction.
+pub fn withdraw_single_token_type_exact_amount_out(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    pool_token_source_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    instruction: WithdrawSingleTokenTypeExactAmountOut,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_token_source_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap' instruction.
+pub fn swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    host_fee_pubkey: Option<&Pubkey>,
+    instruction: Swap,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::Swap(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    if let Some(host_fee_pubkey) = host_fee_pubkey {
+        accounts.push(AccountMeta::new(*host_fee_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap_exact_out' instruction.
+pub fn swap_exact_out(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    host_fee_pubkey: Option<&Pubkey>,
+    instruction: SwapExactOut,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SwapExactOut(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    if let Some(host_fee_pubkey) = host_fee_pubkey {
+        accounts.push(AccountMeta::new(*host_fee_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Accounts of one pool in a routed swap
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteHop {
+    /// Token-swap
+    pub swap_pubkey: Pubkey,
+    /// Swap authority
+    pub authority_pubkey: Pubkey,
+    /// Swap token account to swap INTO
+    pub swap_source_pubkey: Pubkey,
+    /// Swap token account to swap FROM
+    pub swap_destination_pubkey: Pubkey,
+    /// Pool token mint, to generate trading fees
+    pub pool_mint_pubkey: Pubkey,
+    /// Fee account, to receive trading fees
+    pub pool_fee_pubkey: Pubkey,
+}
+
+/// Creates a 'route_swap' instruction.
+pub fn route_swap(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    hops: &[RouteHop],
+    instruction: Swap,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::RouteSwap(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    for hop in hops {
+        accounts.extend_from_slice(&[
+            AccountMeta::new(hop.swap_pubkey, false),
+            AccountMeta::new_readonly(hop.authority_pubkey, false),
+            AccountMeta::new(hop.swap_source_pubkey, false),
+            AccountMeta::new(hop.swap_destination_pubkey, false),
+            AccountMeta::new(hop.pool_mint_pubkey, false),
+            AccountMeta::new(hop.pool_fee_pubkey, false),
+        ]);
     }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
 }
 
-/// Creates an 'initialize' instruction.
-pub fn initialize(
+/// Creates an 'initialize_multi_token' instruction.
+pub fn initialize_multi_token(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
-    token_a_pubkey: &Pubkey,
-    token_b_pubkey: &Pubkey,
+    token_pubkeys: &[Pubkey],
     pool_pubkey: &Pubkey,
     fee_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
+    admin_pubkey: Option<&Pubkey>,
     nonce: u8,
     fees: Fees,
     swap_curve: SwapCurve,
+    weights: Vec<u64>,
 ) -> Result<Instruction, ProgramError> {
-    let init_data = SwapInstruction::Initialize(Initialize {
+    let init_data = SwapInstruction::InitializeMultiToken(InitializeMultiToken {
         nonce,
         fees,
         swap_curve,
+        weights,
     });
     let data = init_data.pack();
 
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new(*swap_pubkey, true),
         AccountMeta::new_readonly(*authority_pubkey, false),
-        AccountMeta::new_readonly(*token_a_pubkey, false),
-        AccountMeta::new_readonly(*token_b_pubkey, false),
         AccountMeta::new(*pool_pubkey, false),
         AccountMeta::new_readonly(*fee_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
+    accounts.extend(
+        token_pubkeys
+            .iter()
+            .map(|pubkey| AccountMeta::new_readonly(*pubkey, false)),
+    );
+    if let Some(admin_pubkey) = admin_pubkey {
+        accounts.push(AccountMeta::new_readonly(*admin_pubkey, true));
+    }
 
     Ok(Instruction {
         program_id: *program_id,
@@ -380,35 +2904,78 @@ pub fn initialize(
     })
 }
 
-/// Creates a 'deposit_all_token_types' instruction.
-pub fn deposit_all_token_types(
+/// Creates a 'swap_multi_token' instruction.
+pub fn swap_multi_token(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    swap_token_pubkeys: &[Pubkey],
+    host_fee_pubkey: Option<&Pubkey>,
+    instruction: Swap,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::SwapMultiToken(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_fee_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    accounts.extend(
+        swap_token_pubkeys
+            .iter()
+            .map(|pubkey| AccountMeta::new(*pubkey, false)),
+    );
+    if let Some(host_fee_pubkey) = host_fee_pubkey {
+        accounts.push(AccountMeta::new(*host_fee_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'deposit_all_multi_token_types' instruction.
+pub fn deposit_all_multi_token_types(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
-    deposit_token_a_pubkey: &Pubkey,
-    deposit_token_b_pubkey: &Pubkey,
-    swap_token_a_pubkey: &Pubkey,
-    swap_token_b_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
-    instruction: DepositAllTokenTypes,
+    swap_token_pubkeys: &[Pubkey],
+    deposit_token_pubkeys: &[Pubkey],
+    instruction: DepositAllMultiTokenTypes,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::DepositAllTokenTypes(instruction).pack();
+    let data = SwapInstruction::DepositAllMultiTokenTypes(instruction).pack();
 
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new_readonly(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
-        AccountMeta::new(*deposit_token_a_pubkey, false),
-        AccountMeta::new(*deposit_token_b_pubkey, false),
-        AccountMeta::new(*swap_token_a_pubkey, false),
-        AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
+    accounts.extend(
+        swap_token_pubkeys
+            .iter()
+            .chain(deposit_token_pubkeys.iter())
+            .map(|pubkey| AccountMeta::new(*pubkey, false)),
+    );
 
     Ok(Instruction {
         program_id: *program_id,
@@ -417,8 +2984,8 @@ pub fn deposit_all_token_types(
     })
 }
 
-/// Creates a 'withdraw_all_token_types' instruction.
-pub fn withdraw_all_token_types(
+/// Creates a 'withdraw_all_multi_token_types' instruction.
+pub fn withdraw_all_multi_token_types(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
@@ -427,27 +2994,27 @@ pub fn withdraw_all_token_types(
     pool_mint_pubkey: &Pubkey,
     fee_account_pubkey: &Pubkey,
     source_pubkey: &Pubkey,
-    swap_token_a_pubkey: &Pubkey,
-    swap_token_b_pubkey: &Pubkey,
-    destination_token_a_pubkey: &Pubkey,
-    destination_token_b_pubkey: &Pubkey,
-    instruction: WithdrawAllTokenTypes,
+    swap_token_pubkeys: &[Pubkey],
+    destination_token_pubkeys: &[Pubkey],
+    instruction: WithdrawAllMultiTokenTypes,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::WithdrawAllTokenTypes(instruction).pack();
+    let data = SwapInstruction::WithdrawAllMultiTokenTypes(instruction).pack();
 
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new_readonly(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*source_pubkey, false),
-        AccountMeta::new(*swap_token_a_pubkey, false),
-        AccountMeta::new(*swap_token_b_pubkey, false),
-        AccountMeta::new(*destination_token_a_pubkey, false),
-        AccountMeta::new(*destination_token_b_pubkey, false),
         AccountMeta::new(*fee_account_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
+    accounts.extend(
+        swap_token_pubkeys
+            .iter()
+            .chain(destination_token_pubkeys.iter())
+            .map(|pubkey| AccountMeta::new(*pubkey, false)),
+    );
 
     Ok(Instruction {
         program_id: *program_id,
@@ -456,33 +3023,35 @@ pub fn withdraw_all_token_types(
     })
 }
 
-/// Creates a 'deposit_single_token_type_exact_amount_in' instruction.
-pub fn deposit_single_token_type_exact_amount_in(
+/// Creates a 'deposit_single_multi_token_type_exact_amount_in' instruction.
+pub fn deposit_single_multi_token_type_exact_amount_in(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
     authority_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
     source_token_pubkey: &Pubkey,
-    swap_token_a_pubkey: &Pubkey,
-    swap_token_b_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
+    swap_token_pubkeys: &[Pubkey],
     instruction: DepositSingleTokenTypeExactAmountIn,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::DepositSingleTokenTypeExactAmountIn(instruction).pack();
+    let data = SwapInstruction::DepositSingleMultiTokenTypeExactAmountIn(instruction).pack();
 
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new_readonly(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
         AccountMeta::new(*source_token_pubkey, false),
-        AccountMeta::new(*swap_token_a_pubkey, false),
-        AccountMeta::new(*swap_token_b_pubkey, false),
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
+    accounts.extend(
+        swap_token_pubkeys
+            .iter()
+            .map(|pubkey| AccountMeta::new(*pubkey, false)),
+    );
 
     Ok(Instruction {
         program_id: *program_id,
@@ -491,8 +3060,8 @@ pub fn deposit_single_token_type_exact_amount_in(
     })
 }
 
-/// Creates a 'withdraw_single_token_type_exact_amount_out' instruction.
-pub fn withdraw_single_token_type_exact_amount_out(
+/// Creates a 'withdraw_single_multi_token_type_exact_amount_out' instruction.
+pub fn withdraw_single_multi_token_type_exact_amount_out(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
@@ -501,23 +3070,229 @@ pub fn withdraw_single_token_type_exact_amount_out(
     pool_mint_pubkey: &Pubkey,
     fee_account_pubkey: &Pubkey,
     pool_token_source_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    swap_token_pubkeys: &[Pubkey],
+    instruction: WithdrawSingleTokenTypeExactAmountOut,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::WithdrawSingleMultiTokenTypeExactAmountOut(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*pool_mint_pubkey, false),
+        AccountMeta::new(*pool_token_source_pubkey, false),
+        AccountMeta::new(*destination_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    accounts.extend(
+        swap_token_pubkeys
+            .iter()
+            .map(|pubkey| AccountMeta::new(*pubkey, false)),
+    );
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'initialize_concentrated' instruction.
+pub fn initialize_concentrated(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    token_a_pubkey: &Pubkey,
+    token_b_pubkey: &Pubkey,
+    admin_pubkey: Option<&Pubkey>,
+    nonce: u8,
+    fees: Fees,
+    tick_spacing: u16,
+    min_position_liquidity: u128,
+    initial_sqrt_price: u128,
+) -> Result<Instruction, ProgramError> {
+    let init_data = SwapInstruction::InitializeConcentrated(InitializeConcentrated {
+        nonce,
+        fees,
+        tick_spacing,
+        min_position_liquidity,
+        initial_sqrt_price,
+    });
+    let data = init_data.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*swap_pubkey, true),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new_readonly(*token_a_pubkey, false),
+        AccountMeta::new_readonly(*token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+    if let Some(admin_pubkey) = admin_pubkey {
+        accounts.push(AccountMeta::new_readonly(*admin_pubkey, true));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'open_position' instruction.
+pub fn open_position(
+    program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    instruction: OpenPosition,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::OpenPosition(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*position_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'increase_liquidity' instruction.
+pub fn increase_liquidity(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    deposit_token_a_pubkey: &Pubkey,
+    deposit_token_b_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    instruction: IncreaseLiquidity,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::IncreaseLiquidity(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*position_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new(*deposit_token_a_pubkey, false),
+        AccountMeta::new(*deposit_token_b_pubkey, false),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'decrease_liquidity' instruction.
+pub fn decrease_liquidity(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
+    swap_token_a_pubkey: &Pubkey,
+    swap_token_b_pubkey: &Pubkey,
+    destination_token_a_pubkey: &Pubkey,
+    destination_token_b_pubkey: &Pubkey,
+    instruction: DecreaseLiquidity,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::DecreaseLiquidity(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*position_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_token_a_pubkey, false),
+        AccountMeta::new(*destination_token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'collect_fees' instruction.
+pub fn collect_fees(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    position_pubkey: &Pubkey,
+    owner_pubkey: &Pubkey,
     swap_token_a_pubkey: &Pubkey,
     swap_token_b_pubkey: &Pubkey,
+    destination_token_a_pubkey: &Pubkey,
+    destination_token_b_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = SwapInstruction::CollectFees.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*swap_pubkey, false),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*position_pubkey, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+        AccountMeta::new(*swap_token_a_pubkey, false),
+        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*destination_token_a_pubkey, false),
+        AccountMeta::new(*destination_token_b_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'swap_concentrated' instruction.
+pub fn swap_concentrated(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
     destination_pubkey: &Pubkey,
-    instruction: WithdrawSingleTokenTypeExactAmountOut,
+    instruction: Swap,
 ) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(instruction).pack();
+    let data = SwapInstruction::SwapConcentrated(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
+        AccountMeta::new(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
         AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
-        AccountMeta::new(*pool_mint_pubkey, false),
-        AccountMeta::new(*pool_token_source_pubkey, false),
-        AccountMeta::new(*swap_token_a_pubkey, false),
-        AccountMeta::new(*swap_token_b_pubkey, false),
+        AccountMeta::new(*source_pubkey, false),
+        AccountMeta::new(*swap_source_pubkey, false),
+        AccountMeta::new(*swap_destination_pubkey, false),
         AccountMeta::new(*destination_pubkey, false),
-        AccountMeta::new(*fee_account_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
     ];
 
@@ -528,125 +3303,601 @@ pub fn withdraw_single_token_type_exact_amount_out(
     })
 }
 
-/// Creates a 'swap' instruction.
-pub fn swap(
-    program_id: &Pubkey,
-    token_program_id: &Pubkey,
-    swap_pubkey: &Pubkey,
-    authority_pubkey: &Pubkey,
-    user_transfer_authority_pubkey: &Pubkey,
-    source_pubkey: &Pubkey,
-    swap_source_pubkey: &Pubkey,
-    swap_destination_pubkey: &Pubkey,
-    destination_pubkey: &Pubkey,
-    pool_mint_pubkey: &Pubkey,
-    pool_fee_pubkey: &Pubkey,
-    host_fee_pubkey: Option<&Pubkey>,
-    instruction: Swap,
-) -> Result<Instruction, ProgramError> {
-    let data = SwapInstruction::Swap(instruction).pack();
+/// Unpacks a reference from a bytes buffer.
+/// TODO actually pack / unpack instead of relying on normal memory layout.
+pub fn unpack<T>(input: &[u8]) -> Result<&T, ProgramError> {
+    if input.len() < size_of::<u8>() + size_of::<T>() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    #[allow(clippy::cast_ptr_alignment)]
+    let val: &T = unsafe { &*(&input[1] as *const u8 as *const T) };
+    Ok(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::curve::{base::CurveType, stable::StableCurve};
+
+    #[test]
+    fn pack_intialize() {
+        let trade_fee_numerator: u64 = 1;
+        let trade_fee_denominator: u64 = 4;
+        let owner_trade_fee_numerator: u64 = 2;
+        let owner_trade_fee_denominator: u64 = 5;
+        let owner_withdraw_fee_numerator: u64 = 1;
+        let owner_withdraw_fee_denominator: u64 = 3;
+        let host_fee_numerator: u64 = 5;
+        let host_fee_denominator: u64 = 20;
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
+        let nonce: u8 = 255;
+        let amp: u64 = 1;
+        let curve_type = CurveType::Stable;
+        let calculator = Box::new(StableCurve::new(amp));
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator,
+        };
+        let check = SwapInstruction::Initialize(Initialize {
+            nonce,
+            fees,
+            swap_curve,
+        });
+        let packed = check.pack();
+        let mut expect = vec![0u8, nonce];
+        expect.extend_from_slice(&trade_fee_numerator.to_le_bytes());
+        expect.extend_from_slice(&trade_fee_denominator.to_le_bytes());
+        expect.extend_from_slice(&owner_trade_fee_numerator.to_le_bytes());
+        expect.extend_from_slice(&owner_trade_fee_denominator.to_le_bytes());
+        expect.extend_from_slice(&owner_withdraw_fee_numerator.to_le_bytes());
+        expect.extend_from_slice(&owner_withdraw_fee_denominator.to_le_bytes());
+        expect.extend_from_slice(&host_fee_numerator.to_le_bytes());
+        expect.extend_from_slice(&host_fee_denominator.to_le_bytes());
+        expect.push(curve_type as u8);
+        expect.extend_from_slice(&amp.to_le_bytes());
+        expect.extend_from_slice(&[0u8; 24]);
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    #[test]
+    fn pack_swap() {
+        let amount_in: u64 = 2;
+        let minimum_amount_out: u64 = 10;
+        let check = SwapInstruction::Swap(Swap {
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![1];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    #[test]
+    fn pack_swap_exact_out() {
+        let amount_out: u64 = 7;
+        let maximum_amount_in: u64 = 20;
+        let check = SwapInstruction::SwapExactOut(SwapExactOut {
+            amount_out,
+            maximum_amount_in,
+        });
+        let packed = check.pack();
+        let mut expect = vec![18];
+        expect.extend_from_slice(&amount_out.to_le_bytes());
+        expect.extend_from_slice(&maximum_amount_in.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    #[test]
+    fn pack_route_swap() {
+        let amount_in: u64 = 3;
+        let minimum_amount_out: u64 = 12;
+        let check = SwapInstruction::RouteSwap(Swap {
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![19];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    #[test]
+    fn pack_admin_instructions() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 4,
+            owner_trade_fee_numerator: 2,
+            owner_trade_fee_denominator: 5,
+            owner_withdraw_fee_numerator: 1,
+            owner_withdraw_fee_denominator: 3,
+            host_fee_numerator: 5,
+            host_fee_denominator: 20,
+        };
+        let check = SwapInstruction::UpdateFees(UpdateFees { fees: fees.clone() });
+        let packed = check.pack();
+        let mut expect = vec![20u8];
+        let mut fees_slice = [0u8; Fees::LEN];
+        fees.pack_into_slice(&mut fees_slice);
+        expect.extend_from_slice(&fees_slice);
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..Fees::LEN]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = SwapInstruction::SetFeeAccount;
+        let packed = check.pack();
+        assert_eq!(packed, vec![21u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+
+        let check = SwapInstruction::SetAdminAuthority;
+        let packed = check.pack();
+        assert_eq!(packed, vec![22u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+    }
+
+    #[test]
+    fn pack_ramp_a() {
+        let target_amp: u64 = 200;
+        let stop_ramp_ts: i64 = -5;
+        let check = SwapInstruction::RampA(RampA {
+            target_amp,
+            stop_ramp_ts,
+        });
+        let packed = check.pack();
+        let mut expect = vec![23u8];
+        expect.extend_from_slice(&target_amp.to_le_bytes());
+        expect.extend_from_slice(&stop_ramp_ts.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..12]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = SwapInstruction::StopRampA;
+        let packed = check.pack();
+        assert_eq!(packed, vec![24u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+    }
+
+    #[test]
+    fn pack_flash_loan() {
+        let amount: u64 = 1_000_000;
+        let check = SwapInstruction::FlashLoan(FlashLoan { amount });
+        let packed = check.pack();
+        let mut expect = vec![26u8];
+        expect.extend_from_slice(&amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..8]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn pack_quote() {
+        let amount: u64 = 1_000;
+        let check = SwapInstruction::Quote(Quote {
+            operation: QuoteOperation::WithdrawSingleTokenTypeExactAmountOut,
+            amount,
+            trade_direction: TradeDirection::BtoA,
+        });
+        let packed = check.pack();
+        let mut expect = vec![27u8, 5];
+        expect.extend_from_slice(&amount.to_le_bytes());
+        expect.push(1);
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let mut invalid = expect.clone();
+        invalid[1] = 6;
+        assert_eq!(
+            SwapInstruction::unpack(&invalid),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        let mut invalid = expect.clone();
+        invalid[10] = 2;
+        assert_eq!(
+            SwapInstruction::unpack(&invalid),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..10]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn pack_farm_instructions() {
+        let nonce: u8 = 254;
+        let check = SwapInstruction::InitializeFarm(InitializeFarm { nonce });
+        let packed = check.pack();
+        assert_eq!(packed, vec![28u8, nonce]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+        assert_eq!(
+            SwapInstruction::unpack(&[28u8]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let reward_per_slot: u64 = 50;
+        let check = SwapInstruction::AddFarmReward(AddFarmReward { reward_per_slot });
+        let packed = check.pack();
+        let mut expect = vec![29u8];
+        expect.extend_from_slice(&reward_per_slot.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapInstruction::unpack(&expect).unwrap(), check);
+
+        let reward_index: u8 = 2;
+        let check = SwapInstruction::SetFarmRewardRate(SetFarmRewardRate {
+            reward_index,
+            reward_per_slot,
+        });
+        let packed = check.pack();
+        let mut expect = vec![30u8, reward_index];
+        expect.extend_from_slice(&reward_per_slot.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapInstruction::unpack(&expect).unwrap(), check);
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..9]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let amount: u64 = 1_000;
+        let check = SwapInstruction::Stake(Stake { amount });
+        let packed = check.pack();
+        let mut expect = vec![31u8];
+        expect.extend_from_slice(&amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapInstruction::unpack(&expect).unwrap(), check);
+
+        let check = SwapInstruction::Unstake(Unstake { amount });
+        let packed = check.pack();
+        let mut expect = vec![32u8];
+        expect.extend_from_slice(&amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapInstruction::unpack(&expect).unwrap(), check);
+
+        let check = SwapInstruction::ClaimFarmRewards;
+        let packed = check.pack();
+        assert_eq!(packed, vec![33u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+    }
+
+    #[test]
+    fn pack_set_dynamic_fee() {
+        let check = SwapInstruction::SetDynamicFee(SetDynamicFee {
+            min_trade_fee_numerator: 5,
+            max_trade_fee_numerator: 100,
+            max_volatility: 500,
+            volatility_period: 150,
+        });
+        let packed = check.pack();
+        let mut expect = vec![34u8];
+        expect.extend_from_slice(&5u64.to_le_bytes());
+        expect.extend_from_slice(&100u64.to_le_bytes());
+        expect.extend_from_slice(&500u64.to_le_bytes());
+        expect.extend_from_slice(&150u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..32]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn pack_set_flash_loan_fee() {
+        let check = SwapInstruction::SetFlashLoanFee(SetFlashLoanFee {
+            trade_fee_numerator: 9,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 10_000,
+        });
+        let packed = check.pack();
+        let mut expect = vec![44u8];
+        expect.extend_from_slice(&9u64.to_le_bytes());
+        expect.extend_from_slice(&10_000u64.to_le_bytes());
+        expect.extend_from_slice(&1u64.to_le_bytes());
+        expect.extend_from_slice(&10_000u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+        assert_eq!(
+            SwapInstruction::unpack(&expect[..32]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn pack_allowlist_instructions() {
+        let check = SwapInstruction::SetAllowlist(SetAllowlist { gate_swaps: true });
+        let packed = check.pack();
+        assert_eq!(packed, vec![35u8, 1]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+        let check = SwapInstruction::SetAllowlist(SetAllowlist { gate_swaps: false });
+        assert_eq!(check.pack(), vec![35u8, 0]);
+        assert_eq!(
+            SwapInstruction::unpack(&[35u8, 2]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&[35u8]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = SwapInstruction::AddAllowlistMember;
+        assert_eq!(check.pack(), vec![36u8]);
+        assert_eq!(SwapInstruction::unpack(&[36u8]).unwrap(), check);
+        let check = SwapInstruction::RemoveAllowlistMember;
+        assert_eq!(check.pack(), vec![37u8]);
+        assert_eq!(SwapInstruction::unpack(&[37u8]).unwrap(), check);
+    }
+
+    #[test]
+    fn pack_migrate_swap() {
+        let check = SwapInstruction::MigrateSwap(MigrateSwap { nonce: 253 });
+        let packed = check.pack();
+        assert_eq!(packed, vec![38u8, 253]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+        assert_eq!(
+            SwapInstruction::unpack(&[38u8]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn pack_limit_order_instructions() {
+        let check = SwapInstruction::CreateLimitOrder(CreateLimitOrder {
+            nonce: 253,
+            trade_direction: TradeDirection::BtoA,
+            kind: LimitOrderKind::StopLoss,
+            amount_in: 1_000,
+            trigger_amount_out: 900,
+            minimum_amount_out: 800,
+        });
+        let packed = check.pack();
+        let mut expect = vec![39u8, 253, 1, 1];
+        expect.extend_from_slice(&1_000u64.to_le_bytes());
+        expect.extend_from_slice(&900u64.to_le_bytes());
+        expect.extend_from_slice(&800u64.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+
+        let mut invalid = packed.clone();
+        invalid[3] = 2;
+        assert_eq!(
+            SwapInstruction::unpack(&invalid),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&packed[..packed.len() - 1]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = SwapInstruction::FillLimitOrder;
+        let packed = check.pack();
+        assert_eq!(packed, vec![40u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+
+        let check = SwapInstruction::CancelLimitOrder;
+        let packed = check.pack();
+        assert_eq!(packed, vec![41u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+    }
+
+    #[test]
+    fn pack_fee_split_instructions() {
+        let check = SwapInstruction::SetFeeSplit(SetFeeSplit {
+            shares: vec![7_000, 3_000],
+        });
+        let packed = check.pack();
+        let mut expect = vec![42u8, 2];
+        expect.extend_from_slice(&7_000u16.to_le_bytes());
+        expect.extend_from_slice(&3_000u16.to_le_bytes());
+        assert_eq!(packed, expect);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+        assert_eq!(
+            SwapInstruction::unpack(&packed[..packed.len() - 1]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&[42u8]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+
+        let check = SwapInstruction::DistributeFees;
+        let packed = check.pack();
+        assert_eq!(packed, vec![43u8]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
+    }
+
+    #[test]
+    fn pack_set_pool_status() {
+        let check = SwapInstruction::SetPoolStatus(SetPoolStatus {
+            status: PoolStatus::SwapsPaused,
+        });
+        let packed = check.pack();
+        assert_eq!(packed, vec![25u8, 1]);
+        assert_eq!(SwapInstruction::unpack(&packed).unwrap(), check);
 
-    let mut accounts = vec![
-        AccountMeta::new_readonly(*swap_pubkey, false),
-        AccountMeta::new_readonly(*authority_pubkey, false),
-        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
-        AccountMeta::new(*source_pubkey, false),
-        AccountMeta::new(*swap_source_pubkey, false),
-        AccountMeta::new(*swap_destination_pubkey, false),
-        AccountMeta::new(*destination_pubkey, false),
-        AccountMeta::new(*pool_mint_pubkey, false),
-        AccountMeta::new(*pool_fee_pubkey, false),
-        AccountMeta::new_readonly(*token_program_id, false),
-    ];
-    if let Some(host_fee_pubkey) = host_fee_pubkey {
-        accounts.push(AccountMeta::new(*host_fee_pubkey, false));
+        assert_eq!(
+            SwapInstruction::unpack(&[25u8]),
+            Err(SwapError::InvalidInstruction.into())
+        );
+        assert_eq!(
+            SwapInstruction::unpack(&[25u8, 3]),
+            Err(SwapError::InvalidInstruction.into())
+        );
     }
 
-    Ok(Instruction {
-        program_id: *program_id,
-        accounts,
-        data,
-    })
-}
+    #[test]
+    fn pack_deposit() {
+        let pool_token_amount: u64 = 5;
+        let maximum_token_a_amount: u64 = 10;
+        let maximum_token_b_amount: u64 = 20;
+        let check = SwapInstruction::DepositAllTokenTypes(DepositAllTokenTypes {
+            pool_token_amount,
+            maximum_token_a_amount,
+            maximum_token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![2];
+        expect.extend_from_slice(&pool_token_amount.to_le_bytes());
+        expect.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
 
-/// Unpacks a reference from a bytes buffer.
-/// TODO actually pack / unpack instead of relying on normal memory layout.
-pub fn unpack<T>(input: &[u8]) -> Result<&T, ProgramError> {
-    if input.len() < size_of::<u8>() + size_of::<T>() {
-        return Err(ProgramError::InvalidAccountData);
+    #[test]
+    fn pack_withdraw() {
+        let pool_token_amount: u64 = 1212438012089;
+        let minimum_token_a_amount: u64 = 102198761982612;
+        let minimum_token_b_amount: u64 = 2011239855213;
+        let check = SwapInstruction::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
+            pool_token_amount,
+            minimum_token_a_amount,
+            minimum_token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![3];
+        expect.extend_from_slice(&pool_token_amount.to_le_bytes());
+        expect.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
     }
-    #[allow(clippy::cast_ptr_alignment)]
-    let val: &T = unsafe { &*(&input[1] as *const u8 as *const T) };
-    Ok(val)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn pack_deposit_one_exact_in() {
+        let source_token_amount: u64 = 10;
+        let minimum_pool_token_amount: u64 = 5;
+        let check = SwapInstruction::DepositSingleTokenTypeExactAmountIn(
+            DepositSingleTokenTypeExactAmountIn {
+                source_token_amount,
+                minimum_pool_token_amount,
+            },
+        );
+        let packed = check.pack();
+        let mut expect = vec![4];
+        expect.extend_from_slice(&source_token_amount.to_le_bytes());
+        expect.extend_from_slice(&minimum_pool_token_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
 
-    use crate::curve::{base::CurveType, stable::StableCurve};
+    #[test]
+    fn pack_withdraw_one_exact_out() {
+        let destination_token_amount: u64 = 102198761982612;
+        let maximum_pool_token_amount: u64 = 1212438012089;
+        let check = SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(
+            WithdrawSingleTokenTypeExactAmountOut {
+                destination_token_amount,
+                maximum_pool_token_amount,
+            },
+        );
+        let packed = check.pack();
+        let mut expect = vec![5];
+        expect.extend_from_slice(&destination_token_amount.to_le_bytes());
+        expect.extend_from_slice(&maximum_pool_token_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
 
     #[test]
-    fn pack_intialize() {
-        let trade_fee_numerator: u64 = 1;
-        let trade_fee_denominator: u64 = 4;
-        let owner_trade_fee_numerator: u64 = 2;
-        let owner_trade_fee_denominator: u64 = 5;
-        let owner_withdraw_fee_numerator: u64 = 1;
-        let owner_withdraw_fee_denominator: u64 = 3;
-        let host_fee_numerator: u64 = 5;
-        let host_fee_denominator: u64 = 20;
+    fn pack_initialize_multi_token() {
         let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 4,
+            owner_trade_fee_numerator: 2,
+            owner_trade_fee_denominator: 5,
+            owner_withdraw_fee_numerator: 1,
+            owner_withdraw_fee_denominator: 3,
+            host_fee_numerator: 5,
+            host_fee_denominator: 20,
         };
         let nonce: u8 = 255;
-        let amp: u64 = 1;
+        let amp: u64 = 100;
         let curve_type = CurveType::Stable;
-        let calculator = Box::new(StableCurve { amp });
+        let calculator = Box::new(StableCurve::new(amp));
         let swap_curve = SwapCurve {
             curve_type,
             calculator,
         };
-        let check = SwapInstruction::Initialize(Initialize {
+        let weights = vec![0u64; 3];
+        let check = SwapInstruction::InitializeMultiToken(InitializeMultiToken {
             nonce,
-            fees,
+            fees: fees.clone(),
             swap_curve,
+            weights: weights.clone(),
         });
         let packed = check.pack();
-        let mut expect = vec![0u8, nonce];
-        expect.extend_from_slice(&trade_fee_numerator.to_le_bytes());
-        expect.extend_from_slice(&trade_fee_denominator.to_le_bytes());
-        expect.extend_from_slice(&owner_trade_fee_numerator.to_le_bytes());
-        expect.extend_from_slice(&owner_trade_fee_denominator.to_le_bytes());
-        expect.extend_from_slice(&owner_withdraw_fee_numerator.to_le_bytes());
-        expect.extend_from_slice(&owner_withdraw_fee_denominator.to_le_bytes());
-        expect.extend_from_slice(&host_fee_numerator.to_le_bytes());
-        expect.extend_from_slice(&host_fee_denominator.to_le_bytes());
+        let mut expect = vec![6u8, nonce];
+        let mut fees_slice = [0u8; Fees::LEN];
+        Pack::pack_into_slice(&fees, &mut fees_slice[..]);
+        expect.extend_from_slice(&fees_slice);
         expect.push(curve_type as u8);
         expect.extend_from_slice(&amp.to_le_bytes());
         expect.extend_from_slice(&[0u8; 24]);
+        expect.push(weights.len() as u8);
+        for weight in weights.iter() {
+            expect.extend_from_slice(&weight.to_le_bytes());
+        }
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
+
+        // missing weights
+        let truncated = &expect[..expect.len() - 8];
+        assert_eq!(
+            SwapInstruction::unpack(truncated),
+            Err(SwapError::InvalidInstruction.into())
+        );
     }
 
     #[test]
-    fn pack_swap() {
+    fn pack_swap_multi_token() {
         let amount_in: u64 = 2;
         let minimum_amount_out: u64 = 10;
-        let check = SwapInstruction::Swap(Swap {
+        let check = SwapInstruction::SwapMultiToken(Swap {
             amount_in,
             minimum_amount_out,
         });
         let packed = check.pack();
-        let mut expect = vec![1];
+        let mut expect = vec![7];
         expect.extend_from_slice(&amount_in.to_le_bytes());
         expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
         assert_eq!(packed, expect);
@@ -655,80 +3906,192 @@ mod tests {
     }
 
     #[test]
-    fn pack_deposit() {
+    fn pack_deposit_multi_token() {
         let pool_token_amount: u64 = 5;
-        let maximum_token_a_amount: u64 = 10;
-        let maximum_token_b_amount: u64 = 20;
-        let check = SwapInstruction::DepositAllTokenTypes(DepositAllTokenTypes {
+        let maximum_token_amounts = vec![10u64, 20, 30, 40];
+        let check = SwapInstruction::DepositAllMultiTokenTypes(DepositAllMultiTokenTypes {
             pool_token_amount,
-            maximum_token_a_amount,
-            maximum_token_b_amount,
+            maximum_token_amounts: maximum_token_amounts.clone(),
         });
         let packed = check.pack();
-        let mut expect = vec![2];
+        let mut expect = vec![8];
         expect.extend_from_slice(&pool_token_amount.to_le_bytes());
-        expect.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
-        expect.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+        expect.push(4);
+        for amount in maximum_token_amounts.iter() {
+            expect.extend_from_slice(&amount.to_le_bytes());
+        }
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
     }
 
     #[test]
-    fn pack_withdraw() {
+    fn pack_withdraw_multi_token() {
         let pool_token_amount: u64 = 1212438012089;
-        let minimum_token_a_amount: u64 = 102198761982612;
-        let minimum_token_b_amount: u64 = 2011239855213;
-        let check = SwapInstruction::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
+        let minimum_token_amounts = vec![102198761982612u64, 2011239855213, 1];
+        let check = SwapInstruction::WithdrawAllMultiTokenTypes(WithdrawAllMultiTokenTypes {
             pool_token_amount,
-            minimum_token_a_amount,
-            minimum_token_b_amount,
+            minimum_token_amounts: minimum_token_amounts.clone(),
         });
         let packed = check.pack();
-        let mut expect = vec![3];
+        let mut expect = vec![9];
         expect.extend_from_slice(&pool_token_amount.to_le_bytes());
-        expect.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
-        expect.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+        expect.push(3);
+        for amount in minimum_token_amounts.iter() {
+            expect.extend_from_slice(&amount.to_le_bytes());
+        }
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
     }
 
     #[test]
-    fn pack_deposit_one_exact_in() {
+    fn pack_single_multi_token() {
         let source_token_amount: u64 = 10;
         let minimum_pool_token_amount: u64 = 5;
-        let check = SwapInstruction::DepositSingleTokenTypeExactAmountIn(
+        let check = SwapInstruction::DepositSingleMultiTokenTypeExactAmountIn(
             DepositSingleTokenTypeExactAmountIn {
                 source_token_amount,
                 minimum_pool_token_amount,
             },
         );
         let packed = check.pack();
-        let mut expect = vec![4];
+        let mut expect = vec![10];
         expect.extend_from_slice(&source_token_amount.to_le_bytes());
         expect.extend_from_slice(&minimum_pool_token_amount.to_le_bytes());
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
-    }
 
-    #[test]
-    fn pack_withdraw_one_exact_out() {
         let destination_token_amount: u64 = 102198761982612;
         let maximum_pool_token_amount: u64 = 1212438012089;
-        let check = SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(
+        let check = SwapInstruction::WithdrawSingleMultiTokenTypeExactAmountOut(
             WithdrawSingleTokenTypeExactAmountOut {
                 destination_token_amount,
                 maximum_pool_token_amount,
             },
         );
         let packed = check.pack();
-        let mut expect = vec![5];
+        let mut expect = vec![11];
         expect.extend_from_slice(&destination_token_amount.to_le_bytes());
         expect.extend_from_slice(&maximum_pool_token_amount.to_le_bytes());
         assert_eq!(packed, expect);
         let unpacked = SwapInstruction::unpack(&expect).unwrap();
         assert_eq!(unpacked, check);
     }
+
+    #[test]
+    fn pack_initialize_concentrated() {
+        let fees = Fees {
+            trade_fee_numerator: 3,
+            trade_fee_denominator: 1000,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 0,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let nonce: u8 = 255;
+        let tick_spacing: u16 = 60;
+        let min_position_liquidity: u128 = 1_000_000;
+        let initial_sqrt_price: u128 = 1 << 64;
+        let check = SwapInstruction::InitializeConcentrated(InitializeConcentrated {
+            nonce,
+            fees: fees.clone(),
+            tick_spacing,
+            min_position_liquidity,
+            initial_sqrt_price,
+        });
+        let packed = check.pack();
+        let mut expect = vec![12u8, nonce];
+        let mut fees_slice = [0u8; Fees::LEN];
+        Pack::pack_into_slice(&fees, &mut fees_slice[..]);
+        expect.extend_from_slice(&fees_slice);
+        expect.extend_from_slice(&tick_spacing.to_le_bytes());
+        expect.extend_from_slice(&min_position_liquidity.to_le_bytes());
+        expect.extend_from_slice(&initial_sqrt_price.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let truncated = &expect[..expect.len() - 1];
+        assert_eq!(
+            SwapInstruction::unpack(truncated),
+            Err(SwapError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn pack_positions() {
+        let tick_lower: i32 = -120;
+        let tick_upper: i32 = 60;
+        let check = SwapInstruction::OpenPosition(OpenPosition {
+            tick_lower,
+            tick_upper,
+        });
+        let packed = check.pack();
+        let mut expect = vec![13];
+        expect.extend_from_slice(&tick_lower.to_le_bytes());
+        expect.extend_from_slice(&tick_upper.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let liquidity: u128 = 1_000_000_000_000_000_000_000;
+        let maximum_token_a_amount: u64 = 10;
+        let maximum_token_b_amount: u64 = 20;
+        let check = SwapInstruction::IncreaseLiquidity(IncreaseLiquidity {
+            liquidity,
+            maximum_token_a_amount,
+            maximum_token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![14];
+        expect.extend_from_slice(&liquidity.to_le_bytes());
+        expect.extend_from_slice(&maximum_token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&maximum_token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let minimum_token_a_amount: u64 = 1;
+        let minimum_token_b_amount: u64 = 2;
+        let check = SwapInstruction::DecreaseLiquidity(DecreaseLiquidity {
+            liquidity,
+            minimum_token_a_amount,
+            minimum_token_b_amount,
+        });
+        let packed = check.pack();
+        let mut expect = vec![15];
+        expect.extend_from_slice(&liquidity.to_le_bytes());
+        expect.extend_from_slice(&minimum_token_a_amount.to_le_bytes());
+        expect.extend_from_slice(&minimum_token_b_amount.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+
+        let check = SwapInstruction::CollectFees;
+        let packed = check.pack();
+        assert_eq!(packed, vec![16]);
+        let unpacked = SwapInstruction::unpack(&packed).unwrap();
+        assert_eq!(unpacked, check);
+    }
+
+    #[test]
+    fn pack_swap_concentrated() {
+        let amount_in: u64 = 2;
+        let minimum_amount_out: u64 = 10;
+        let check = SwapInstruction::SwapConcentrated(Swap {
+            amount_in,
+            minimum_amount_out,
+        });
+        let packed = check.pack();
+        let mut expect = vec![17];
+        expect.extend_from_slice(&amount_in.to_le_bytes());
+        expect.extend_from_slice(&minimum_amount_out.to_le_bytes());
+        assert_eq!(packed, expect);
+        let unpacked = SwapInstruction::unpack(&expect).unwrap();
+        assert_eq!(unpacked, check);
+    }
 }
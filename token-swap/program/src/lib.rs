@@ -4,9 +4,13 @@
 
 pub mod constraints;
 pub mod curve;
+pub mod dynamic_fee;
 pub mod error;
+pub mod farm;
 pub mod instruction;
+pub mod oracle;
 pub mod processor;
+pub mod quote;
 pub mod state;
 
 #[cfg(not(feature = "no-entrypoint"))]
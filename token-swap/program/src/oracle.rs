@@ -0,0 +1,154 @@
+//! Time-weighted average prices from the price accumulator of a swap
+
+use crate::state::PriceAccumulator;
+use spl_math::uint::U256;
+use std::convert::TryFrom;
+
+/// Average prices over a range of slots, in Q64.64
+#[derive(Debug, PartialEq)]
+pub struct AveragePrices {
+    /// Average price of token A in token B
+    pub price_a: u128,
+    /// Average price of token B in token A
+    pub price_b: u128,
+}
+
+/// Price of one token in the other given their reserves, in Q64.64,
+/// saturating at `u128::MAX`.  There is no price if the token is not held.
+pub fn spot_price(token_amount: u128, other_token_amount: u128) -> Option<u128> {
+    if token_amount == 0 {
+        return None;
+    }
+    let price = (U256::from(other_token_amount) << 64) / U256::from(token_amount);
+    Some(u128::try_from(price).unwrap_or(u128::MAX))
+}
+
+impl PriceAccumulator {
+    /// Start accumulating prices at the given slot
+    pub fn new(slot: u64) -> Self {
+        Self {
+            last_update_slot: slot,
+            ..Self::default()
+        }
+    }
+
+    /// Accumulated prices as of the given slot, assuming the reserves have
+    /// been held since the last update.  Used both to update the accumulator
+    /// before the first action in a slot, and by readers to observe the
+    /// prices at the current slot, since the accumulator is only updated
+    /// when the swap is used.
+    pub fn observe(&self, slot: u64, token_a_amount: u128, token_b_amount: u128) -> Self {
+        let mut observed = *self;
+        if slot <= self.last_update_slot {
+            return observed;
+        }
+        let elapsed = u128::from(slot - self.last_update_slot);
+        if let (Some(price_a), Some(price_b)) = (
+            spot_price(token_a_amount, token_b_amount),
+            spot_price(token_b_amount, token_a_amount),
+        ) {
+            observed.price_a_cumulative = self
+                .price_a_cumulative
+                .wrapping_add(price_a.wrapping_mul(elapsed));
+            observed.price_b_cumulative = self
+                .price_b_cumulative
+                .wrapping_add(price_b.wrapping_mul(elapsed));
+        }
+        observed.last_update_slot = slot;
+        observed
+    }
+}
+
+/// Time-weighted average prices between two observations of the same swap,
+/// or `None` if the later observation is not from a later slot.
+///
+/// The accumulators wrap on overflow, so the result is only correct if the
+/// average price multiplied by the number of slots between the observations
+/// fits in a u128.
+pub fn twap(earlier: &PriceAccumulator, later: &PriceAccumulator) -> Option<AveragePrices> {
+    let elapsed = u128::from(
+        later
+            .last_update_slot
+            .checked_sub(earlier.last_update_slot)?,
+    );
+    if elapsed == 0 {
+        return None;
+    }
+    Some(AveragePrices {
+        price_a: later
+            .price_a_cumulative
+            .wrapping_sub(earlier.price_a_cumulative)
+            / elapsed,
+        price_b: later
+            .price_b_cumulative
+            .wrapping_sub(earlier.price_b_cumulative)
+            / elapsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE: u128 = 1 << 64;
+
+    #[test]
+    fn spot_prices() {
+        assert_eq!(spot_price(1_000, 1_000), Some(ONE));
+        assert_eq!(spot_price(1_000, 2_000), Some(2 * ONE));
+        assert_eq!(spot_price(2_000, 1_000), Some(ONE / 2));
+        assert_eq!(spot_price(0, 1_000), None);
+        assert_eq!(spot_price(1_000, 0), Some(0));
+        assert_eq!(spot_price(1, u128::MAX), Some(u128::MAX));
+    }
+
+    #[test]
+    fn observe_accumulates_per_slot() {
+        let accumulator = PriceAccumulator::new(10);
+
+        // nothing accumulates within the same slot
+        assert_eq!(accumulator.observe(10, 1_000, 2_000), accumulator);
+
+        let observed = accumulator.observe(13, 1_000, 2_000);
+        assert_eq!(observed.price_a_cumulative, 3 * 2 * ONE);
+        assert_eq!(observed.price_b_cumulative, 3 * ONE / 2);
+        assert_eq!(observed.last_update_slot, 13);
+
+        // without reserves, only the slot moves
+        let empty = observed.observe(20, 0, 2_000);
+        assert_eq!(empty.price_a_cumulative, observed.price_a_cumulative);
+        assert_eq!(empty.last_update_slot, 20);
+
+        // the accumulators wrap around
+        let full = PriceAccumulator {
+            price_a_cumulative: u128::MAX,
+            price_b_cumulative: 0,
+            last_update_slot: 0,
+        };
+        let wrapped = full.observe(1, 1_000, 1_000);
+        assert_eq!(wrapped.price_a_cumulative, ONE - 1);
+        assert_eq!(twap(&full, &wrapped).unwrap().price_a, ONE);
+    }
+
+    #[test]
+    fn twap_between_observations() {
+        // price of 2 for 10 slots, then 4 for 30 slots
+        let start = PriceAccumulator::new(100);
+        let middle = start.observe(110, 1_000, 2_000);
+        let end = middle.observe(140, 1_000, 4_000);
+
+        assert_eq!(
+            twap(&start, &middle),
+            Some(AveragePrices {
+                price_a: 2 * ONE,
+                price_b: ONE / 2,
+            })
+        );
+        let average = twap(&start, &end).unwrap();
+        assert_eq!(average.price_a, (2 * 10 + 4 * 30) * ONE / 40);
+        assert_eq!(average.price_b, (10 * ONE / 2 + 30 * ONE / 4) / 40);
+
+        assert_eq!(twap(&end, &end), None);
+        assert_eq!(twap(&end, &start), None);
+    }
+}
@@ -3,30 +3,51 @@
 use crate::constraints::{SwapConstraints, SWAP_CONSTRAINTS};
 use crate::{
     curve::{
-        base::SwapCurve,
+        base::{CurveType, SwapCurve, SwapResult},
         calculator::{RoundDirection, TradeDirection},
+        concentrated::{tick_at_sqrt_price, tick_lower_bound, MAX_TICK_SPACING},
         fees::Fees,
+        multi_token::{
+            pool_tokens_to_trading_tokens, MultiTokenCurve, MAX_TOKENS_IN_MULTI_TOKEN_POOL,
+            MIN_TOKENS_IN_MULTI_TOKEN_POOL,
+        },
+        stable::StableCurve,
     },
     error::SwapError,
     instruction::{
-        DepositAllTokenTypes, DepositSingleTokenTypeExactAmountIn, Initialize, Swap,
-        SwapInstruction, WithdrawAllTokenTypes, WithdrawSingleTokenTypeExactAmountOut,
+        receive_flash_loan, AddFarmReward, CreateLimitOrder, DecreaseLiquidity,
+        DepositAllMultiTokenTypes, DepositAllTokenTypes, DepositSingleTokenTypeExactAmountIn,
+        FlashLoan, IncreaseLiquidity, Initialize, InitializeConcentrated, InitializeFarm,
+        InitializeMultiToken, MigrateSwap, OpenPosition, Quote, QuoteOperation, RampA,
+        SetAllowlist, SetDynamicFee, SetFarmRewardRate, SetFeeSplit, SetFlashLoanFee,
+        SetPoolStatus, Stake, Swap, SwapExactOut, SwapInstruction, Unstake, UpdateFees,
+        WithdrawAllMultiTokenTypes, WithdrawAllTokenTypes, WithdrawSingleTokenTypeExactAmountOut,
+    },
+    quote::{self, PoolReserves},
+    state::{
+        AllowlistMember, ConcentratedPool, DynamicFee, Farm, FarmStake, FeeRecipient, FeeSplit,
+        FlashLoanFee, LimitOrder, LimitOrderKind, PoolStatus, Position, PriceAccumulator,
+        SwapAdmin, SwapAllowlist, SwapExtension, SwapState, SwapToken, SwapV1, SwapV2, SwapVersion,
+        FEE_SPLIT_DENOMINATOR, MAX_FEE_RECIPIENTS,
     },
-    state::{SwapState, SwapV1, SwapVersion},
 };
 use num_traits::FromPrimitive;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
     decode_error::DecodeError,
     entrypoint::ProgramResult,
+    instruction::AccountMeta,
     msg,
-    program::invoke_signed,
+    program::{invoke, invoke_signed},
     program_error::{PrintProgramError, ProgramError},
     program_option::COption,
     program_pack::Pack,
     pubkey::Pubkey,
+    sysvar::{clock::Clock, Sysvar},
 };
-use std::convert::TryInto;
+use spl_token::instruction::AuthorityType;
+use std::convert::{TryFrom, TryInto};
 
 /// Program state handler.
 pub struct Processor {}
@@ -122,6 +143,31 @@ impl Processor {
         invoke_signed(&ix, &[mint, destination, authority, token_program], signers)
     }
 
+    /// Issue a spl_token `SetAuthority` instruction.
+    pub fn token_set_authority<'a>(
+        swap: &Pubkey,
+        token_program: AccountInfo<'a>,
+        owned: AccountInfo<'a>,
+        authority: AccountInfo<'a>,
+        nonce: u8,
+        new_authority: &Pubkey,
+        authority_type: AuthorityType,
+    ) -> Result<(), ProgramError> {
+        let swap_bytes = swap.to_bytes();
+        let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
+        let signers = &[&authority_signature_seeds[..]];
+        let ix = spl_token::instruction::set_authority(
+            token_program.key,
+            owned.key,
+            Some(new_authority),
+            authority_type,
+            authority.key,
+            &[],
+        )?;
+
+        invoke_signed(&ix, &[owned, authority, token_program], signers)
+    }
+
     /// Issue a spl_token `Transfer` instruction.
     pub fn token_transfer<'a>(
         swap: &Pubkey,
@@ -150,6 +196,89 @@ impl Processor {
         )
     }
 
+    /// Issue a spl_token `Transfer` instruction signed by a user's own
+    /// transfer authority, for moving tokens into accounts other than the
+    /// pool's.  The swap authority never signs these.
+    pub fn user_token_transfer<'a>(
+        token_program: AccountInfo<'a>,
+        source: AccountInfo<'a>,
+        destination: AccountInfo<'a>,
+        authority: AccountInfo<'a>,
+        amount: u64,
+    ) -> Result<(), ProgramError> {
+        let ix = spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            authority.key,
+            &[],
+            amount,
+        )?;
+        invoke(&ix, &[source, destination, authority, token_program])
+    }
+
+    /// Issue a spl_token `Transfer` instruction into one of the pool's token
+    /// accounts, returning how much its balance actually grew by.  Tokens
+    /// that take a fee on transfer deliver less than `amount`.
+    pub fn token_transfer_received<'a>(
+        swap: &Pubkey,
+        token_program: AccountInfo<'a>,
+        source: AccountInfo<'a>,
+        destination: AccountInfo<'a>,
+        authority: AccountInfo<'a>,
+        nonce: u8,
+        amount: u64,
+    ) -> Result<u64, ProgramError> {
+        let before = Self::unpack_token_account(&destination, token_program.key)?.amount;
+        Self::token_transfer(
+            swap,
+            token_program.clone(),
+            source,
+            destination.clone(),
+            authority,
+            nonce,
+            amount,
+        )?;
+        let after = Self::unpack_token_account(&destination, token_program.key)?.amount;
+        Ok(after.saturating_sub(before))
+    }
+
+    /// Pool tokens to mint for a deposit of every pool token, given
+    /// `(received, amount, maximum)` for each: only the share of the deposit
+    /// that reached the pool is minted, and the price paid per pool token
+    /// must still be within each maximum.
+    fn pool_tokens_received(
+        pool_token_amount: u128,
+        deposits: &[(u64, u64, u64)],
+    ) -> Result<u64, ProgramError> {
+        let mut minted_amount = pool_token_amount;
+        for (received, amount, _) in deposits.iter() {
+            if received < amount {
+                let scaled_amount = pool_token_amount
+                    .checked_mul(to_u128(*received)?)
+                    .ok_or(SwapError::CalculationFailure)?
+                    .checked_div(to_u128(*amount)?)
+                    .ok_or(SwapError::CalculationFailure)?;
+                minted_amount = minted_amount.min(scaled_amount);
+            }
+        }
+        if minted_amount == 0 {
+            return Err(SwapError::ZeroTradingTokens.into());
+        }
+        for (_, amount, maximum) in deposits.iter() {
+            let paid = to_u128(*amount)?
+                .checked_mul(pool_token_amount)
+                .ok_or(SwapError::CalculationFailure)?;
+            let allowed = to_u128(*maximum)?
+                .checked_mul(minted_amount)
+                .ok_or(SwapError::CalculationFailure)?;
+            if paid > allowed {
+                return Err(SwapError::ExceededSlippage.into());
+            }
+        }
+        Ok(to_u64(minted_amount)?)
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn check_accounts(
         token_swap: &dyn SwapState,
@@ -202,6 +331,65 @@ impl Processor {
         Ok(())
     }
 
+    /// Accumulates the prices given by the reserves held before the first
+    /// action of the slot, for swaps with space for the price accumulator
+    fn update_price_accumulator(
+        swap_account_info: &AccountInfo,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) -> ProgramResult {
+        let accumulator = SwapVersion::unpack_price_accumulator(&swap_account_info.data.borrow())?;
+        if let Some(accumulator) = accumulator {
+            let slot = Clock::get()?.slot;
+            if slot > accumulator.last_update_slot {
+                let accumulator =
+                    accumulator.observe(slot, to_u128(token_a_amount)?, to_u128(token_b_amount)?);
+                SwapVersion::pack_price_accumulator(
+                    accumulator,
+                    &mut swap_account_info.data.borrow_mut(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fees charged by a two-token swap, with the trade fee for its current
+    /// volatility if it has a dynamic fee
+    fn current_fees(swap_account_info: &AccountInfo, fees: &Fees) -> Result<Fees, ProgramError> {
+        match SwapVersion::unpack_dynamic_fee(&swap_account_info.data.borrow())? {
+            Some(dynamic_fee) if dynamic_fee.is_enabled() => {
+                Ok(dynamic_fee.decay(Clock::get()?.slot).fees(fees))
+            }
+            _ => Ok(fees.clone()),
+        }
+    }
+
+    /// Adds the price movement of a trade to the volatility of swaps with a
+    /// dynamic fee
+    fn update_volatility(
+        swap_account_info: &AccountInfo,
+        reserves_before: (u128, u128),
+        reserves_after: (u128, u128),
+    ) -> ProgramResult {
+        let dynamic_fee = SwapVersion::unpack_dynamic_fee(&swap_account_info.data.borrow())?;
+        if let Some(dynamic_fee) = dynamic_fee {
+            if dynamic_fee.is_enabled() {
+                let mut dynamic_fee = dynamic_fee.decay(Clock::get()?.slot);
+                dynamic_fee.record_trade(
+                    reserves_before.0,
+                    reserves_before.1,
+                    reserves_after.0,
+                    reserves_after.1,
+                );
+                SwapVersion::pack_dynamic_fee(
+                    dynamic_fee,
+                    &mut swap_account_info.data.borrow_mut(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     /// Processes an [Initialize](enum.Instruction.html).
     pub fn process_initialize(
         program_id: &Pubkey,
@@ -220,11 +408,22 @@ impl Processor {
         let fee_account_info = next_account_info(account_info_iter)?;
         let destination_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter).ok();
 
         let token_program_id = *token_program_info.key;
         if SwapVersion::is_initialized(&swap_info.data.borrow()) {
             return Err(SwapError::AlreadyInUse.into());
         }
+        // the admin must agree to take control of the swap, and be stored in
+        // its extension
+        if let Some(admin_info) = admin_info {
+            if !admin_info.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if swap_info.data_len() == SwapVersion::LEGACY_LEN {
+                return Err(SwapError::InvalidAdmin.into());
+            }
+        }
 
         if *authority_info.key != Self::authority_id(program_id, swap_info.key, nonce)? {
             return Err(SwapError::InvalidProgramAddress.into());
@@ -305,6 +504,7 @@ impl Processor {
             to_u64(initial_amount)?,
         )?;
 
+        let flash_loan_fee = FlashLoanFee::from_trade_fees(&fees);
         let obj = SwapVersion::SwapV1(SwapV1 {
             is_initialized: true,
             nonce,
@@ -319,6 +519,19 @@ impl Processor {
             swap_curve,
         });
         SwapVersion::pack(obj, &mut swap_info.data.borrow_mut())?;
+        if SwapVersion::unpack_extension(&swap_info.data.borrow())?.is_some() {
+            let extension = SwapExtension {
+                price_accumulator: PriceAccumulator::new(Clock::get()?.slot),
+                admin: SwapAdmin {
+                    admin_authority: admin_info.map_or_else(Pubkey::default, |info| *info.key),
+                },
+                pool_status: PoolStatus::Active,
+                dynamic_fee: DynamicFee::default(),
+                allowlist: SwapAllowlist::default(),
+                flash_loan_fee,
+            };
+            SwapVersion::pack_extension(extension, &mut swap_info.data.borrow_mut())?;
+        }
         Ok(())
     }
 
@@ -329,6 +542,197 @@ impl Processor {
         minimum_amount_out: u64,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
+        Self::process_swap_with(
+            program_id,
+            minimum_amount_out,
+            accounts,
+            |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                let result = swap_curve
+                    .swap(
+                        to_u128(amount_in)?,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_direction,
+                        fees,
+                    )
+                    .ok_or(SwapError::ZeroTradingTokens)?;
+                if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
+                    return Err(SwapError::ExceededSlippage.into());
+                }
+                Ok(result)
+            },
+        )
+    }
+
+    /// Processes a [SwapExactOut](enum.Instruction.html).
+    pub fn process_swap_exact_out(
+        program_id: &Pubkey,
+        amount_out: u64,
+        maximum_amount_in: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        Self::process_swap_with(
+            program_id,
+            amount_out,
+            accounts,
+            |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                let result = swap_curve
+                    .swap_exact_out(
+                        to_u128(amount_out)?,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_direction,
+                        fees,
+                    )
+                    .ok_or(SwapError::ZeroTradingTokens)?;
+                if result.source_amount_swapped > to_u128(maximum_amount_in)? {
+                    return Err(SwapError::ExceededSlippage.into());
+                }
+                Ok(result)
+            },
+        )
+    }
+
+    /// Processes a [RouteSwap](enum.Instruction.html).
+    pub fn process_route_swap(
+        program_id: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        // the tokens received from the previous pool are held by it until
+        // they are moved into the next pool, or to the user at the end
+        let mut previous_hop: Option<(&AccountInfo, &AccountInfo, &AccountInfo, u8)> = None;
+        let mut amount = amount_in;
+        while !account_info_iter.as_slice().is_empty() {
+            let swap_info = next_account_info(account_info_iter)?;
+            let authority_info = next_account_info(account_info_iter)?;
+            let swap_source_info = next_account_info(account_info_iter)?;
+            let swap_destination_info = next_account_info(account_info_iter)?;
+            let pool_mint_info = next_account_info(account_info_iter)?;
+            let pool_fee_account_info = next_account_info(account_info_iter)?;
+            if let Some((previous_swap_info, _, _, _)) = previous_hop {
+                if previous_swap_info.key == swap_info.key {
+                    return Err(SwapError::InvalidInput.into());
+                }
+            }
+
+            let (result, nonce) = Self::swap_in_pool(
+                program_id,
+                swap_info,
+                authority_info,
+                swap_source_info,
+                swap_destination_info,
+                pool_mint_info,
+                pool_fee_account_info,
+                token_program_info,
+                None,
+                |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                    let result = swap_curve
+                        .swap(
+                            to_u128(amount)?,
+                            swap_source_amount,
+                            swap_destination_amount,
+                            trade_direction,
+                            fees,
+                        )
+                        .ok_or(SwapError::ZeroTradingTokens)?;
+                    // whatever a pool could not swap would be stranded in the
+                    // previous one, so every hop must swap its whole amount
+                    if result.source_amount_swapped < to_u128(amount)? {
+                        return Err(SwapError::ExceededSlippage.into());
+                    }
+                    Ok(result)
+                },
+                |amount, nonce| match previous_hop {
+                    None => {
+                        if swap_source_info.key == source_info.key {
+                            return Err(SwapError::InvalidInput.into());
+                        }
+                        Self::token_transfer_received(
+                            swap_info.key,
+                            token_program_info.clone(),
+                            source_info.clone(),
+                            swap_source_info.clone(),
+                            user_transfer_authority_info.clone(),
+                            nonce,
+                            amount,
+                        )
+                    }
+                    Some((
+                        previous_swap_info,
+                        previous_authority_info,
+                        previous_swap_destination_info,
+                        previous_nonce,
+                    )) => Self::token_transfer_received(
+                        previous_swap_info.key,
+                        token_program_info.clone(),
+                        previous_swap_destination_info.clone(),
+                        swap_source_info.clone(),
+                        previous_authority_info.clone(),
+                        previous_nonce,
+                        amount,
+                    ),
+                },
+            )?;
+            // routes cannot carry allowlist memberships, so pools gating
+            // swaps cannot be part of them
+            Self::check_allowlist(
+                program_id,
+                swap_info,
+                user_transfer_authority_info,
+                None,
+                true,
+            )?;
+
+            amount = to_u64(result.destination_amount_swapped)?;
+            previous_hop = Some((swap_info, authority_info, swap_destination_info, nonce));
+        }
+
+        if amount < minimum_amount_out {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        let (swap_info, authority_info, swap_destination_info, nonce) =
+            previous_hop.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if swap_destination_info.key == destination_info.key {
+            return Err(SwapError::InvalidInput.into());
+        }
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            nonce,
+            amount,
+        )
+    }
+
+    /// Moves the tokens of a swap between the user and the pool, given a
+    /// function to calculate the swap from the curve, fees, swap source and
+    /// destination amounts, and trade direction, and the least destination
+    /// amount the user accepts.
+    fn process_swap_with<F>(
+        program_id: &Pubkey,
+        minimum_amount_out: u64,
+        accounts: &[AccountInfo],
+        calculate_swap: F,
+    ) -> ProgramResult
+    where
+        F: FnOnce(
+            &SwapCurve,
+            &Fees,
+            u128,
+            u128,
+            TradeDirection,
+        ) -> Result<SwapResult, ProgramError>,
+    {
         let account_info_iter = &mut accounts.iter();
         let swap_info = next_account_info(account_info_iter)?;
         let authority_info = next_account_info(account_info_iter)?;
@@ -340,11 +744,108 @@ impl Processor {
         let pool_mint_info = next_account_info(account_info_iter)?;
         let pool_fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        // the allowlist membership is owned by the swap program, which tells
+        // it apart from the host fee account
+        let (host_fee_account_info, membership_info) =
+            match next_account_info(account_info_iter).ok() {
+                Some(info) if info.owner == program_id => (None, Some(info)),
+                host_fee_account_info => (
+                    host_fee_account_info,
+                    next_account_info(account_info_iter).ok(),
+                ),
+            };
+
+        let (result, nonce) = Self::swap_in_pool(
+            program_id,
+            swap_info,
+            authority_info,
+            swap_source_info,
+            swap_destination_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            host_fee_account_info,
+            calculate_swap,
+            |amount, nonce| {
+                if swap_source_info.key == source_info.key {
+                    return Err(SwapError::InvalidInput.into());
+                }
+                Self::token_transfer_received(
+                    swap_info.key,
+                    token_program_info.clone(),
+                    source_info.clone(),
+                    swap_source_info.clone(),
+                    user_transfer_authority_info.clone(),
+                    nonce,
+                    amount,
+                )
+            },
+        )?;
+        Self::check_allowlist(
+            program_id,
+            swap_info,
+            user_transfer_authority_info,
+            membership_info,
+            true,
+        )?;
+        // a swap priced again from a short transfer in pays out less
+        if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        if swap_destination_info.key == destination_info.key {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            nonce,
+            to_u64(result.destination_amount_swapped)?,
+        )?;
 
+        Ok(())
+    }
+
+    /// Checks the accounts of a swap through one pool, calculates the swap,
+    /// moves the source tokens in through `transfer_in`, given the amount and
+    /// the nonce of the swap authority, and mints the owner fee, returning the
+    /// result along with the nonce.  If the pool receives less than the swap
+    /// asked for, the swap is priced again from the amount received.  Moving
+    /// the tokens out of the pool is left to the caller.
+    #[allow(clippy::too_many_arguments)]
+    fn swap_in_pool<'a, F, T>(
+        program_id: &Pubkey,
+        swap_info: &AccountInfo<'a>,
+        authority_info: &AccountInfo<'a>,
+        swap_source_info: &AccountInfo<'a>,
+        swap_destination_info: &AccountInfo<'a>,
+        pool_mint_info: &AccountInfo<'a>,
+        pool_fee_account_info: &AccountInfo<'a>,
+        token_program_info: &AccountInfo<'a>,
+        host_fee_account_info: Option<&AccountInfo<'a>>,
+        calculate_swap: F,
+        transfer_in: T,
+    ) -> Result<(SwapResult, u8), ProgramError>
+    where
+        F: FnOnce(
+            &SwapCurve,
+            &Fees,
+            u128,
+            u128,
+            TradeDirection,
+        ) -> Result<SwapResult, ProgramError>,
+        T: FnOnce(u64, u8) -> Result<u64, ProgramError>,
+    {
         if swap_info.owner != program_id {
             return Err(ProgramError::IncorrectProgramId);
         }
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        if !Self::pool_status(swap_info)?.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
+        }
 
         if *authority_info.key != Self::authority_id(program_id, swap_info.key, token_swap.nonce())?
         {
@@ -363,12 +864,6 @@ impl Processor {
         if *swap_source_info.key == *swap_destination_info.key {
             return Err(SwapError::InvalidInput.into());
         }
-        if swap_source_info.key == source_info.key {
-            return Err(SwapError::InvalidInput.into());
-        }
-        if swap_destination_info.key == destination_info.key {
-            return Err(SwapError::InvalidInput.into());
-        }
         if *pool_mint_info.key != *token_swap.pool_mint() {
             return Err(SwapError::IncorrectPoolMint.into());
         }
@@ -390,18 +885,34 @@ impl Processor {
         } else {
             TradeDirection::BtoA
         };
-        let result = token_swap
-            .swap_curve()
-            .swap(
-                to_u128(amount_in)?,
-                to_u128(source_account.amount)?,
-                to_u128(dest_account.amount)?,
-                trade_direction,
-                token_swap.fees(),
-            )
-            .ok_or(SwapError::ZeroTradingTokens)?;
-        if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
-            return Err(SwapError::ExceededSlippage.into());
+        let (token_a_amount, token_b_amount) = match trade_direction {
+            TradeDirection::AtoB => (source_account.amount, dest_account.amount),
+            TradeDirection::BtoA => (dest_account.amount, source_account.amount),
+        };
+        Self::update_price_accumulator(swap_info, token_a_amount, token_b_amount)?;
+        let fees = Self::current_fees(swap_info, token_swap.fees())?;
+        let mut result = calculate_swap(
+            token_swap.swap_curve(),
+            &fees,
+            to_u128(source_account.amount)?,
+            to_u128(dest_account.amount)?,
+            trade_direction,
+        )?;
+        let received = to_u128(transfer_in(
+            to_u64(result.source_amount_swapped)?,
+            token_swap.nonce(),
+        )?)?;
+        if received < result.source_amount_swapped {
+            result = token_swap
+                .swap_curve()
+                .swap(
+                    received,
+                    to_u128(source_account.amount)?,
+                    to_u128(dest_account.amount)?,
+                    trade_direction,
+                    &fees,
+                )
+                .ok_or(SwapError::ZeroTradingTokens)?;
         }
 
         let (swap_token_a_amount, swap_token_b_amount) = match trade_direction {
@@ -414,15 +925,10 @@ impl Processor {
                 result.new_swap_source_amount,
             ),
         };
-
-        Self::token_transfer(
-            swap_info.key,
-            token_program_info.clone(),
-            source_info.clone(),
-            swap_source_info.clone(),
-            user_transfer_authority_info.clone(),
-            token_swap.nonce(),
-            to_u64(result.source_amount_swapped)?,
+        Self::update_volatility(
+            swap_info,
+            (to_u128(token_a_amount)?, to_u128(token_b_amount)?),
+            (swap_token_a_amount, swap_token_b_amount),
         )?;
 
         let mut pool_token_amount = token_swap
@@ -434,13 +940,12 @@ impl Processor {
                 to_u128(pool_mint.supply)?,
                 trade_direction,
                 RoundDirection::Ceiling,
-                token_swap.fees(),
+                &fees,
             )
             .ok_or(SwapError::FeeCalculationFailure)?;
 
         if pool_token_amount > 0 {
-            // Allow error to fall through
-            if let Ok(host_fee_account_info) = next_account_info(account_info_iter) {
+            if let Some(host_fee_account_info) = host_fee_account_info {
                 let host_fee_account = Self::unpack_token_account(
                     host_fee_account_info,
                     token_swap.token_program_id(),
@@ -448,8 +953,7 @@ impl Processor {
                 if *pool_mint_info.key != host_fee_account.mint {
                     return Err(SwapError::IncorrectPoolMint.into());
                 }
-                let host_fee = token_swap
-                    .fees()
+                let host_fee = fees
                     .host_fee(pool_token_amount)
                     .ok_or(SwapError::FeeCalculationFailure)?;
                 if host_fee > 0 {
@@ -478,61 +982,311 @@ impl Processor {
             )?;
         }
 
-        Self::token_transfer(
-            swap_info.key,
-            token_program_info.clone(),
-            swap_destination_info.clone(),
-            destination_info.clone(),
-            authority_info.clone(),
-            token_swap.nonce(),
-            to_u64(result.destination_amount_swapped)?,
-        )?;
-
-        Ok(())
+        Ok((result, token_swap.nonce()))
     }
 
-    /// Processes an [DepositAllTokenTypes](enum.Instruction.html).
-    pub fn process_deposit_all_token_types(
+    /// Processes a [FlashLoan](enum.Instruction.html).
+    pub fn process_flash_loan(
         program_id: &Pubkey,
-        pool_token_amount: u64,
-        maximum_token_a_amount: u64,
-        maximum_token_b_amount: u64,
+        amount: u64,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let swap_info = next_account_info(account_info_iter)?;
         let authority_info = next_account_info(account_info_iter)?;
-        let user_transfer_authority_info = next_account_info(account_info_iter)?;
-        let source_a_info = next_account_info(account_info_iter)?;
-        let source_b_info = next_account_info(account_info_iter)?;
-        let token_a_info = next_account_info(account_info_iter)?;
-        let token_b_info = next_account_info(account_info_iter)?;
+        let swap_token_a_info = next_account_info(account_info_iter)?;
+        let swap_token_b_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
         let pool_mint_info = next_account_info(account_info_iter)?;
-        let dest_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        let receiver_program_info = next_account_info(account_info_iter)?;
+        let receiver_account_infos = account_info_iter.as_slice();
 
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
-        let calculator = &token_swap.swap_curve().calculator;
-        if !calculator.allows_deposits() {
-            return Err(SwapError::UnsupportedCurveOperation.into());
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        if !Self::pool_status(swap_info)?.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
         }
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
-            token_a_info,
-            token_b_info,
+            swap_token_a_info,
+            swap_token_b_info,
             pool_mint_info,
             token_program_info,
-            Some(source_a_info),
-            Some(source_b_info),
+            Some(destination_info),
+            Some(destination_info),
+            Some(pool_fee_account_info),
+        )?;
+        if receiver_program_info.key == program_id {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        let destination_account =
+            Self::unpack_token_account(destination_info, &token_swap.token_program_id())?;
+        let swap_token_a =
+            Self::unpack_token_account(swap_token_a_info, &token_swap.token_program_id())?;
+        let swap_token_b =
+            Self::unpack_token_account(swap_token_b_info, &token_swap.token_program_id())?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id())?;
+
+        // the loaned token is treated as the source of a swap
+        let (trade_direction, swap_token_info, reserve) =
+            if destination_account.mint == swap_token_a.mint {
+                (TradeDirection::AtoB, swap_token_a_info, swap_token_a.amount)
+            } else if destination_account.mint == swap_token_b.mint {
+                (TradeDirection::BtoA, swap_token_b_info, swap_token_b.amount)
+            } else {
+                return Err(SwapError::IncorrectSwapAccount.into());
+            };
+        if amount == 0 || amount > reserve {
+            return Err(SwapError::InvalidInput.into());
+        }
+        Self::update_price_accumulator(swap_info, swap_token_a.amount, swap_token_b.amount)?;
+
+        let fees = &match SwapVersion::unpack_flash_loan_fee(&swap_info.data.borrow())? {
+            Some(flash_loan_fee) => flash_loan_fee.fees(),
+            None => Self::current_fees(swap_info, token_swap.fees())?,
+        };
+        let trade_fee = fees
+            .trading_fee(to_u128(amount)?)
+            .ok_or(SwapError::FeeCalculationFailure)?;
+        let owner_fee = fees
+            .owner_trading_fee(to_u128(amount)?)
+            .ok_or(SwapError::FeeCalculationFailure)?;
+        let fee = to_u64(
+            trade_fee
+                .checked_add(owner_fee)
+                .ok_or(SwapError::FeeCalculationFailure)?,
+        )?;
+
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_token_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce(),
+            amount,
+        )?;
+
+        let receiver_accounts = receiver_account_infos
+            .iter()
+            .map(|info| AccountMeta {
+                pubkey: *info.key,
+                is_signer: info.is_signer,
+                is_writable: info.is_writable,
+            })
+            .collect::<Vec<_>>();
+        let instruction = receive_flash_loan(
+            receiver_program_info.key,
+            destination_info.key,
+            swap_token_info.key,
+            token_program_info.key,
+            &receiver_accounts,
+            amount,
+            fee,
+        );
+        let mut instruction_account_infos = vec![
+            destination_info.clone(),
+            swap_token_info.clone(),
+            token_program_info.clone(),
+            receiver_program_info.clone(),
+        ];
+        instruction_account_infos.extend_from_slice(receiver_account_infos);
+        invoke(&instruction, &instruction_account_infos)?;
+
+        let repaid = Self::unpack_token_account(swap_token_info, &token_swap.token_program_id())?;
+        let expected = reserve
+            .checked_add(fee)
+            .ok_or(SwapError::FeeCalculationFailure)?;
+        if repaid.amount < expected {
+            return Err(SwapError::FlashLoanNotRepaid.into());
+        }
+
+        let (swap_token_a_amount, swap_token_b_amount) = match trade_direction {
+            TradeDirection::AtoB => (repaid.amount, swap_token_b.amount),
+            TradeDirection::BtoA => (swap_token_a.amount, repaid.amount),
+        };
+        let pool_token_amount = token_swap
+            .swap_curve()
+            .trading_tokens_to_pool_tokens(
+                owner_fee,
+                to_u128(swap_token_a_amount)?,
+                to_u128(swap_token_b_amount)?,
+                to_u128(pool_mint.supply)?,
+                trade_direction,
+                RoundDirection::Ceiling,
+                fees,
+            )
+            .ok_or(SwapError::FeeCalculationFailure)?;
+        if pool_token_amount > 0 {
+            Self::token_mint_to(
+                swap_info.key,
+                token_program_info.clone(),
+                pool_mint_info.clone(),
+                pool_fee_account_info.clone(),
+                authority_info.clone(),
+                token_swap.nonce(),
+                to_u64(pool_token_amount)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Processes a [Quote](enum.Instruction.html).
+    pub fn process_quote(
+        program_id: &Pubkey,
+        operation: QuoteOperation,
+        amount: u64,
+        trade_direction: TradeDirection,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let swap_token_a_info = next_account_info(account_info_iter)?;
+        let swap_token_b_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        if *swap_token_a_info.key != *token_swap.token_a_account() {
+            return Err(SwapError::IncorrectSwapAccount.into());
+        }
+        if *swap_token_b_info.key != *token_swap.token_b_account() {
+            return Err(SwapError::IncorrectSwapAccount.into());
+        }
+        if *pool_mint_info.key != *token_swap.pool_mint() {
+            return Err(SwapError::IncorrectPoolMint.into());
+        }
+        let status = Self::pool_status(swap_info)?;
+        let allowed = match operation {
+            QuoteOperation::WithdrawAllTokenTypes => true,
+            QuoteOperation::DepositAllTokenTypes => status.allows_deposits(),
+            _ => status.allows_swaps(),
+        };
+        if !allowed {
+            return Err(SwapError::PoolPaused.into());
+        }
+
+        let token_program_id = token_swap.token_program_id();
+        let swap_token_a = Self::unpack_token_account(swap_token_a_info, token_program_id)?;
+        let swap_token_b = Self::unpack_token_account(swap_token_b_info, token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, token_program_id)?;
+        let reserves = PoolReserves {
+            token_a_amount: to_u128(swap_token_a.amount)?,
+            token_b_amount: to_u128(swap_token_b.amount)?,
+            pool_supply: to_u128(pool_mint.supply)?,
+        };
+
+        let swap_curve = token_swap.swap_curve();
+        let fees = &Self::current_fees(swap_info, token_swap.fees())?;
+        let amount = to_u128(amount)?;
+        let result = match operation {
+            QuoteOperation::Swap => {
+                quote::swap(swap_curve, fees, &reserves, amount, trade_direction)
+            }
+            QuoteOperation::SwapExactOut => {
+                quote::swap_exact_out(swap_curve, fees, &reserves, amount, trade_direction)
+            }
+            QuoteOperation::DepositAllTokenTypes => {
+                quote::deposit_all_token_types(swap_curve, &reserves, amount)
+            }
+            QuoteOperation::WithdrawAllTokenTypes => {
+                quote::withdraw_all_token_types(swap_curve, fees, &reserves, amount)
+            }
+            QuoteOperation::DepositSingleTokenTypeExactAmountIn => {
+                quote::deposit_single_token_type_exact_amount_in(
+                    swap_curve,
+                    fees,
+                    &reserves,
+                    amount,
+                    trade_direction,
+                )
+            }
+            QuoteOperation::WithdrawSingleTokenTypeExactAmountOut => {
+                quote::withdraw_single_token_type_exact_amount_out(
+                    swap_curve,
+                    fees,
+                    &reserves,
+                    amount,
+                    trade_direction,
+                )
+            }
+        }
+        .ok_or(SwapError::CalculationFailure)?;
+
+        msg!(
+            "Quote: token_a_amount={}, token_b_amount={}, pool_token_amount={}, trade_fee={}, owner_fee={}, host_fee={}, price_after={}",
+            result.token_a_amount,
+            result.token_b_amount,
+            result.pool_token_amount,
+            result.trade_fee,
+            result.owner_fee,
+            result.host_fee,
+            result.price_after.unwrap_or(0)
+        );
+        Ok(())
+    }
+
+    /// Processes an [DepositAllTokenTypes](enum.Instruction.html).
+    pub fn process_deposit_all_token_types(
+        program_id: &Pubkey,
+        pool_token_amount: u64,
+        maximum_token_a_amount: u64,
+        maximum_token_b_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_a_info = next_account_info(account_info_iter)?;
+        let source_b_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let dest_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let membership_info = next_account_info(account_info_iter).ok();
+
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        if !Self::pool_status(swap_info)?.allows_deposits() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        let calculator = &token_swap.swap_curve().calculator;
+        if !calculator.allows_deposits() {
+            return Err(SwapError::UnsupportedCurveOperation.into());
+        }
+        Self::check_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            pool_mint_info,
+            token_program_info,
+            Some(source_a_info),
+            Some(source_b_info),
             None,
         )?;
+        Self::check_allowlist(
+            program_id,
+            swap_info,
+            user_transfer_authority_info,
+            membership_info,
+            false,
+        )?;
 
         let token_a = Self::unpack_token_account(token_a_info, &token_swap.token_program_id())?;
         let token_b = Self::unpack_token_account(token_b_info, &token_swap.token_program_id())?;
         let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id())?;
+        Self::update_price_accumulator(swap_info, token_a.amount, token_b.amount)?;
         let pool_token_amount = to_u128(pool_token_amount)?;
         let pool_mint_supply = to_u128(pool_mint.supply)?;
 
@@ -560,9 +1314,7 @@ impl Processor {
             return Err(SwapError::ZeroTradingTokens.into());
         }
 
-        let pool_token_amount = to_u64(pool_token_amount)?;
-
-        Self::token_transfer(
+        let received_a = Self::token_transfer_received(
             swap_info.key,
             token_program_info.clone(),
             source_a_info.clone(),
@@ -571,7 +1323,7 @@ impl Processor {
             token_swap.nonce(),
             token_a_amount,
         )?;
-        Self::token_transfer(
+        let received_b = Self::token_transfer_received(
             swap_info.key,
             token_program_info.clone(),
             source_b_info.clone(),
@@ -580,6 +1332,14 @@ impl Processor {
             token_swap.nonce(),
             token_b_amount,
         )?;
+        let pool_token_amount = Self::pool_tokens_received(
+            pool_token_amount,
+            &[
+                (received_a, token_a_amount, maximum_token_a_amount),
+                (received_b, token_b_amount, maximum_token_b_amount),
+            ],
+        )?;
+
         Self::token_mint_to(
             swap_info.key,
             token_program_info.clone(),
@@ -614,9 +1374,9 @@ impl Processor {
         let pool_fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
 
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -632,6 +1392,7 @@ impl Processor {
         let token_a = Self::unpack_token_account(token_a_info, token_swap.token_program_id())?;
         let token_b = Self::unpack_token_account(token_b_info, token_swap.token_program_id())?;
         let pool_mint = Self::unpack_mint(pool_mint_info, token_swap.token_program_id())?;
+        Self::update_price_accumulator(swap_info, token_a.amount, token_b.amount)?;
 
         let calculator = &token_swap.swap_curve().calculator;
 
@@ -737,8 +1498,12 @@ impl Processor {
         let pool_mint_info = next_account_info(account_info_iter)?;
         let destination_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        let membership_info = next_account_info(account_info_iter).ok();
 
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        if !Self::pool_status(swap_info)?.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
+        }
         let source_account =
             Self::unpack_token_account(source_info, &token_swap.token_program_id())?;
         let swap_token_a =
@@ -760,7 +1525,7 @@ impl Processor {
         };
 
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -772,55 +1537,82 @@ impl Processor {
             source_b_info,
             None,
         )?;
+        Self::check_allowlist(
+            program_id,
+            swap_info,
+            user_transfer_authority_info,
+            membership_info,
+            false,
+        )?;
 
+        Self::update_price_accumulator(swap_info, swap_token_a.amount, swap_token_b.amount)?;
         let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id())?;
         let pool_mint_supply = to_u128(pool_mint.supply)?;
-
-        let pool_token_amount = token_swap
-            .swap_curve()
-            .trading_tokens_to_pool_tokens(
-                to_u128(source_token_amount)?,
-                to_u128(swap_token_a.amount)?,
-                to_u128(swap_token_b.amount)?,
-                pool_mint_supply,
-                trade_direction,
-                RoundDirection::Floor,
-                token_swap.fees(),
-            )
-            .ok_or(SwapError::ZeroTradingTokens)?;
-
-        let pool_token_amount = to_u64(pool_token_amount)?;
-        if pool_token_amount < minimum_pool_token_amount {
-            return Err(SwapError::ExceededSlippage.into());
-        }
-        if pool_token_amount == 0 {
-            return Err(SwapError::ZeroTradingTokens.into());
-        }
-
-        match trade_direction {
-            TradeDirection::AtoB => {
-                Self::token_transfer(
-                    swap_info.key,
-                    token_program_info.clone(),
-                    source_info.clone(),
-                    swap_token_a_info.clone(),
-                    user_transfer_authority_info.clone(),
-                    token_swap.nonce(),
-                    source_token_amount,
-                )?;
+        let swap_token_a_amount = to_u128(swap_token_a.amount)?;
+        let swap_token_b_amount = to_u128(swap_token_b.amount)?;
+        let fees = Self::current_fees(swap_info, token_swap.fees())?;
+        let pool_tokens_for = |source_amount: u128| -> Result<u64, ProgramError> {
+            let pool_token_amount = token_swap
+                .swap_curve()
+                .trading_tokens_to_pool_tokens(
+                    source_amount,
+                    swap_token_a_amount,
+                    swap_token_b_amount,
+                    pool_mint_supply,
+                    trade_direction,
+                    RoundDirection::Floor,
+                    &fees,
+                )
+                .ok_or(SwapError::ZeroTradingTokens)?;
+            let pool_token_amount = to_u64(pool_token_amount)?;
+            if pool_token_amount < minimum_pool_token_amount {
+                return Err(SwapError::ExceededSlippage.into());
             }
-            TradeDirection::BtoA => {
-                Self::token_transfer(
-                    swap_info.key,
-                    token_program_info.clone(),
-                    source_info.clone(),
-                    swap_token_b_info.clone(),
-                    user_transfer_authority_info.clone(),
-                    token_swap.nonce(),
-                    source_token_amount,
-                )?;
+            if pool_token_amount == 0 {
+                return Err(SwapError::ZeroTradingTokens.into());
             }
+            Ok(pool_token_amount)
+        };
+        let mut pool_token_amount = pool_tokens_for(to_u128(source_token_amount)?)?;
+
+        let swap_token_info = match trade_direction {
+            TradeDirection::AtoB => swap_token_a_info,
+            TradeDirection::BtoA => swap_token_b_info,
+        };
+        let received = Self::token_transfer_received(
+            swap_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_token_info.clone(),
+            user_transfer_authority_info.clone(),
+            token_swap.nonce(),
+            source_token_amount,
+        )?;
+        let source_amount = to_u128(received)?;
+        if received < source_token_amount {
+            pool_token_amount = pool_tokens_for(source_amount)?;
         }
+
+        let reserves_after = match trade_direction {
+            TradeDirection::AtoB => (
+                swap_token_a_amount
+                    .checked_add(source_amount)
+                    .ok_or(SwapError::CalculationFailure)?,
+                swap_token_b_amount,
+            ),
+            TradeDirection::BtoA => (
+                swap_token_a_amount,
+                swap_token_b_amount
+                    .checked_add(source_amount)
+                    .ok_or(SwapError::CalculationFailure)?,
+            ),
+        };
+        Self::update_volatility(
+            swap_info,
+            (swap_token_a_amount, swap_token_b_amount),
+            reserves_after,
+        )?;
+
         Self::token_mint_to(
             swap_info.key,
             token_program_info.clone(),
@@ -853,7 +1645,10 @@ impl Processor {
         let pool_fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
 
-        let token_swap = SwapVersion::unpack(&swap_info.data.borrow())?;
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        if !Self::pool_status(swap_info)?.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
+        }
         let destination_account =
             Self::unpack_token_account(destination_info, &token_swap.token_program_id())?;
         let swap_token_a =
@@ -874,7 +1669,7 @@ impl Processor {
             TradeDirection::BtoA => (None, Some(destination_info)),
         };
         Self::check_accounts(
-            token_swap.as_ref(),
+            &token_swap,
             program_id,
             swap_info,
             authority_info,
@@ -887,6 +1682,7 @@ impl Processor {
             Some(pool_fee_account_info),
         )?;
 
+        Self::update_price_accumulator(swap_info, swap_token_a.amount, swap_token_b.amount)?;
         let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id())?;
         let pool_mint_supply = to_u128(pool_mint.supply)?;
         let (swap_token_a_amount, swap_token_b_amount) = match trade_direction {
@@ -919,9 +1715,14 @@ impl Processor {
                 pool_mint_supply,
                 trade_direction,
                 RoundDirection::Ceiling,
-                token_swap.fees(),
+                &Self::current_fees(swap_info, token_swap.fees())?,
             )
             .ok_or(SwapError::ZeroTradingTokens)?;
+        Self::update_volatility(
+            swap_info,
+            (to_u128(swap_token_a.amount)?, to_u128(swap_token_b.amount)?),
+            (swap_token_a_amount, swap_token_b_amount),
+        )?;
 
         let withdraw_fee: u128 = if *pool_fee_account_info.key == *source_info.key {
             // withdrawing from the fee account, don't assess withdraw fee
@@ -992,1851 +1793,7059 @@ impl Processor {
         Ok(())
     }
 
-    /// Processes an [Instruction](enum.Instruction.html).
-    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
-        Self::process_with_constraints(program_id, accounts, input, &SWAP_CONSTRAINTS)
-    }
-
-    /// Processes an instruction given extra constraint
-    pub fn process_with_constraints(
+    #[allow(clippy::too_many_arguments)]
+    fn check_multi_token_accounts(
+        token_swap: &SwapV2,
         program_id: &Pubkey,
-        accounts: &[AccountInfo],
-        input: &[u8],
-        swap_constraints: &Option<SwapConstraints>,
+        swap_account_info: &AccountInfo,
+        authority_info: &AccountInfo,
+        swap_token_infos: &[AccountInfo],
+        pool_mint_info: &AccountInfo,
+        token_program_info: &AccountInfo,
+        user_token_infos: &[&AccountInfo],
+        pool_fee_account_info: Option<&AccountInfo>,
     ) -> ProgramResult {
-        let instruction = SwapInstruction::unpack(input)?;
-        match instruction {
-            SwapInstruction::Initialize(Initialize {
-                nonce,
-                fees,
-                swap_curve,
-            }) => {
-                msg!("Instruction: Init");
-                Self::process_initialize(
-                    program_id,
-                    nonce,
-                    fees,
-                    swap_curve,
-                    accounts,
-                    swap_constraints,
-                )
-            }
-            SwapInstruction::Swap(Swap {
-                amount_in,
-                minimum_amount_out,
-            }) => {
-                msg!("Instruction: Swap");
-                Self::process_swap(program_id, amount_in, minimum_amount_out, accounts)
-            }
-            SwapInstruction::DepositAllTokenTypes(DepositAllTokenTypes {
-                pool_token_amount,
-                maximum_token_a_amount,
-                maximum_token_b_amount,
-            }) => {
-                msg!("Instruction: DepositAllTokenTypes");
-                Self::process_deposit_all_token_types(
-                    program_id,
-                    pool_token_amount,
-                    maximum_token_a_amount,
-                    maximum_token_b_amount,
-                    accounts,
-                )
-            }
-            SwapInstruction::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
-                pool_token_amount,
-                minimum_token_a_amount,
-                minimum_token_b_amount,
-            }) => {
-                msg!("Instruction: WithdrawAllTokenTypes");
-                Self::process_withdraw_all_token_types(
-                    program_id,
-                    pool_token_amount,
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                    accounts,
-                )
-            }
-            SwapInstruction::DepositSingleTokenTypeExactAmountIn(
-                DepositSingleTokenTypeExactAmountIn {
-                    source_token_amount,
-                    minimum_pool_token_amount,
-                },
-            ) => {
-                msg!("Instruction: DepositSingleTokenTypeExactAmountIn");
-                Self::process_deposit_single_token_type_exact_amount_in(
-                    program_id,
-                    source_token_amount,
-                    minimum_pool_token_amount,
-                    accounts,
-                )
-            }
-            SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(
-                WithdrawSingleTokenTypeExactAmountOut {
-                    destination_token_amount,
-                    maximum_pool_token_amount,
-                },
-            ) => {
-                msg!("Instruction: WithdrawSingleTokenTypeExactAmountOut");
-                Self::process_withdraw_single_token_type_exact_amount_out(
-                    program_id,
-                    destination_token_amount,
-                    maximum_pool_token_amount,
-                    accounts,
-                )
-            }
+        if swap_account_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
-    }
-}
-
-impl PrintProgramError for SwapError {
-    fn print<E>(&self)
-    where
-        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
-    {
-        match self {
-            SwapError::AlreadyInUse => msg!("Error: Swap account already in use"),
-            SwapError::InvalidProgramAddress => {
-                msg!("Error: Invalid program address generated from nonce and key")
-            }
-            SwapError::InvalidOwner => {
-                msg!("Error: The input account owner is not the program address")
-            }
-            SwapError::InvalidOutputOwner => {
-                msg!("Error: Output pool account owner cannot be the program address")
-            }
-            SwapError::ExpectedMint => msg!("Error: Deserialized account is not an SPL Token mint"),
-            SwapError::ExpectedAccount => {
-                msg!("Error: Deserialized account is not an SPL Token account")
-            }
-            SwapError::EmptySupply => msg!("Error: Input token account empty"),
-            SwapError::InvalidSupply => msg!("Error: Pool token mint has a non-zero supply"),
-            SwapError::RepeatedMint => msg!("Error: Swap input token accounts have the same mint"),
-            SwapError::InvalidDelegate => msg!("Error: Token account has a delegate"),
-            SwapError::InvalidInput => msg!("Error: InvalidInput"),
-            SwapError::IncorrectSwapAccount => {
-                msg!("Error: Address of the provided swap token account is incorrect")
-            }
-            SwapError::IncorrectPoolMint => {
-                msg!("Error: Address of the provided pool token mint is incorrect")
-            }
-            SwapError::InvalidOutput => msg!("Error: InvalidOutput"),
-            SwapError::CalculationFailure => msg!("Error: CalculationFailure"),
-            SwapError::InvalidInstruction => msg!("Error: InvalidInstruction"),
-            SwapError::ExceededSlippage => {
-                msg!("Error: Swap instruction exceeds desired slippage limit")
-            }
-            SwapError::InvalidCloseAuthority => msg!("Error: Token account has a close authority"),
-            SwapError::InvalidFreezeAuthority => {
-                msg!("Error: Pool token mint has a freeze authority")
-            }
-            SwapError::IncorrectFeeAccount => msg!("Error: Pool fee token account incorrect"),
-            SwapError::ZeroTradingTokens => {
-                msg!("Error: Given pool token amount results in zero trading tokens")
-            }
-            SwapError::FeeCalculationFailure => msg!(
-                "Error: The fee calculation failed due to overflow, underflow, or unexpected 0"
-            ),
-            SwapError::ConversionFailure => msg!("Error: Conversion to or from u64 failed."),
-            SwapError::InvalidFee => {
-                msg!("Error: The provided fee does not match the program owner's constraints")
-            }
-            SwapError::IncorrectTokenProgramId => {
-                msg!("Error: The provided token program does not match the token program expected by the swap")
-            }
-            SwapError::UnsupportedCurveType => {
-                msg!("Error: The provided curve type is not supported by the program owner")
+        if *authority_info.key
+            != Self::authority_id(program_id, swap_account_info.key, token_swap.nonce)?
+        {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        for (swap_token_info, token) in swap_token_infos.iter().zip(token_swap.tokens()) {
+            if *swap_token_info.key != token.account {
+                return Err(SwapError::IncorrectSwapAccount.into());
             }
-            SwapError::InvalidCurve => {
-                msg!("Error: The provided curve parameters are invalid")
+        }
+        if *pool_mint_info.key != token_swap.pool_mint {
+            return Err(SwapError::IncorrectPoolMint.into());
+        }
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        for user_token_info in user_token_infos.iter() {
+            if swap_token_infos
+                .iter()
+                .any(|swap_token_info| swap_token_info.key == user_token_info.key)
+            {
+                return Err(SwapError::InvalidInput.into());
             }
-            SwapError::UnsupportedCurveOperation => {
-                msg!("Error: The operation cannot be performed on the given curve")
+        }
+        if let Some(pool_fee_account_info) = pool_fee_account_info {
+            if *pool_fee_account_info.key != token_swap.pool_fee_account {
+                return Err(SwapError::IncorrectFeeAccount.into());
             }
         }
+        Ok(())
     }
-}
-
-fn to_u128(val: u64) -> Result<u128, SwapError> {
-    val.try_into().map_err(|_| SwapError::ConversionFailure)
-}
 
-fn to_u64(val: u128) -> Result<u64, SwapError> {
-    val.try_into().map_err(|_| SwapError::ConversionFailure)
-}
+    /// Unpacks the amount held by each of the swap's token accounts
+    fn unpack_swap_balances(
+        swap_token_infos: &[AccountInfo],
+        token_program_id: &Pubkey,
+    ) -> Result<Vec<u128>, ProgramError> {
+        swap_token_infos
+            .iter()
+            .map(|swap_token_info| {
+                let swap_token = Self::unpack_token_account(swap_token_info, token_program_id)?;
+                Ok(to_u128(swap_token.amount)?)
+            })
+            .collect()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{
-        curve::calculator::{CurveCalculator, INITIAL_SWAP_POOL_AMOUNT},
-        curve::{
-            base::CurveType, constant_price::ConstantPriceCurve,
-            constant_product::ConstantProductCurve, offset::OffsetCurve,
-        },
-        instruction::{
-            deposit_all_token_types, deposit_single_token_type_exact_amount_in, initialize, swap,
-            withdraw_all_token_types, withdraw_single_token_type_exact_amount_out,
-        },
-    };
-    use solana_program::{instruction::Instruction, program_stubs, rent::Rent};
-    use solana_sdk::account::{create_account, create_is_signer_account_infos, Account};
-    use spl_token::{
-        error::TokenError,
-        instruction::{
-            approve, initialize_account, initialize_mint, mint_to, revoke, set_authority,
-            AuthorityType,
-        },
-    };
+    /// Unpacks a two-token swap to trade against, with a ramping amplifier
+    /// fixed at its value at the current time
+    fn unpack_trading_swap(swap_info: &AccountInfo) -> Result<SwapV1, ProgramError> {
+        let mut token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        token_swap.swap_curve.fix_at(Clock::get()?.unix_timestamp)?;
+        Ok(token_swap)
+    }
 
-    // Test program id for the swap program.
-    const SWAP_PROGRAM_ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
-    // Test program id for the token program.
-    const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([1u8; 32]);
+    /// Status of a two-token swap, where pools created before the status was
+    /// added are always active
+    fn pool_status(swap_info: &AccountInfo) -> Result<PoolStatus, ProgramError> {
+        Ok(SwapVersion::unpack_pool_status(&swap_info.data.borrow())?.unwrap_or_default())
+    }
 
-    struct TestSyscallStubs {}
-    impl program_stubs::SyscallStubs for TestSyscallStubs {
-        fn sol_invoke_signed(
-            &self,
-            instruction: &Instruction,
-            account_infos: &[AccountInfo],
-            signers_seeds: &[&[&[u8]]],
-        ) -> ProgramResult {
-            msg!("TestSyscallStubs::sol_invoke_signed()");
+    /// Checks that the admin authority of the swap signed the instruction
+    fn check_admin(swap_info: &AccountInfo, admin_info: &AccountInfo) -> ProgramResult {
+        let admin =
+            SwapVersion::unpack_admin(&swap_info.data.borrow())?.ok_or(SwapError::InvalidAdmin)?;
+        if admin.admin_authority == Pubkey::default() || admin.admin_authority != *admin_info.key {
+            return Err(SwapError::InvalidAdmin.into());
+        }
+        if !admin_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Ok(())
+    }
 
-            let mut new_account_infos = vec![];
+    /// Checks that the allowlist authority of the swap signed the instruction
+    fn check_allowlist_authority(
+        swap_info: &AccountInfo,
+        allowlist_authority_info: &AccountInfo,
+    ) -> ProgramResult {
+        let allowlist = SwapVersion::unpack_allowlist(&swap_info.data.borrow())?
+            .ok_or(SwapError::InvalidAllowlistAuthority)?;
+        if !allowlist.is_enabled() || allowlist.allowlist_authority != *allowlist_authority_info.key
+        {
+            return Err(SwapError::InvalidAllowlistAuthority.into());
+        }
+        if !allowlist_authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Ok(())
+    }
 
-            // mimic check for token program in accounts
-            if !account_infos.iter().any(|x| *x.key == TOKEN_PROGRAM_ID) {
-                return Err(ProgramError::InvalidAccountData);
-            }
+    /// Checks that the user transfer authority of a deposit, or of a swap if
+    /// the swap gates swaps, signed and holds a membership in the allowlist
+    /// of the swap.  Pools created before the allowlist are open to everyone.
+    fn check_allowlist(
+        program_id: &Pubkey,
+        swap_info: &AccountInfo,
+        user_transfer_authority_info: &AccountInfo,
+        membership_info: Option<&AccountInfo>,
+        is_swap: bool,
+    ) -> ProgramResult {
+        let allowlist = match SwapVersion::unpack_allowlist(&swap_info.data.borrow())? {
+            Some(allowlist) if allowlist.is_enabled() => allowlist,
+            _ => return Ok(()),
+        };
+        if is_swap && !allowlist.gate_swaps {
+            return Ok(());
+        }
+        let membership_info = membership_info.ok_or(SwapError::NotAllowlisted)?;
+        if membership_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let membership = AllowlistMember::unpack_unchecked(&membership_info.data.borrow())?;
+        if !membership.is_initialized
+            || membership.swap != *swap_info.key
+            || membership.member != *user_transfer_authority_info.key
+        {
+            return Err(SwapError::NotAllowlisted.into());
+        }
+        if !user_transfer_authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Ok(())
+    }
 
-            for meta in instruction.accounts.iter() {
-                for account_info in account_infos.iter() {
-                    if meta.pubkey == *account_info.key {
-                        let mut new_account_info = account_info.clone();
-                        for seeds in signers_seeds.iter() {
-                            let signer =
-                                Pubkey::create_program_address(&seeds, &SWAP_PROGRAM_ID).unwrap();
-                            if *account_info.key == signer {
-                                new_account_info.is_signer = true;
-                            }
-                        }
-                        new_account_infos.push(new_account_info);
-                    }
-                }
-            }
+    /// Processes an [UpdateFees](enum.Instruction.html).
+    pub fn process_update_fees(
+        program_id: &Pubkey,
+        fees: Fees,
+        accounts: &[AccountInfo],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
 
-            spl_token::processor::Processor::process(
-                &instruction.program_id,
-                &new_account_infos,
-                &instruction.data,
-            )
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
-    }
+        let mut token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
 
-    fn test_syscall_stubs() {
-        use std::sync::Once;
-        static ONCE: Once = Once::new();
+        if let Some(swap_constraints) = swap_constraints {
+            swap_constraints.validate_fees(&fees)?;
+        }
+        fees.validate()?;
+        if let Some(dynamic_fee) = SwapVersion::unpack_dynamic_fee(&swap_info.data.borrow())? {
+            dynamic_fee.validate(&fees)?;
+        }
 
-        ONCE.call_once(|| {
-            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs {}));
-        });
+        token_swap.fees = fees;
+        SwapVersion::pack(
+            SwapVersion::SwapV1(token_swap),
+            &mut swap_info.data.borrow_mut(),
+        )
     }
 
-    struct SwapAccountInfo {
-        nonce: u8,
-        authority_key: Pubkey,
-        fees: Fees,
-        swap_curve: SwapCurve,
-        swap_key: Pubkey,
-        swap_account: Account,
-        pool_mint_key: Pubkey,
-        pool_mint_account: Account,
-        pool_fee_key: Pubkey,
-        pool_fee_account: Account,
-        pool_token_key: Pubkey,
-        pool_token_account: Account,
-        token_a_key: Pubkey,
-        token_a_account: Account,
-        token_a_mint_key: Pubkey,
-        token_a_mint_account: Account,
-        token_b_key: Pubkey,
-        token_b_account: Account,
-        token_b_mint_key: Pubkey,
-        token_b_mint_account: Account,
-    }
-
-    impl SwapAccountInfo {
-        pub fn new(
-            user_key: &Pubkey,
-            fees: Fees,
-            swap_curve: SwapCurve,
-            token_a_amount: u64,
-            token_b_amount: u64,
-        ) -> Self {
-            let swap_key = Pubkey::new_unique();
-            let swap_account = Account::new(0, SwapVersion::LATEST_LEN, &SWAP_PROGRAM_ID);
-            let (authority_key, nonce) =
-                Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+    /// Processes a [SetFeeAccount](enum.Instruction.html).
+    pub fn process_set_fee_account(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let fee_account_info = next_account_info(account_info_iter)?;
 
-            let (pool_mint_key, mut pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &authority_key, None);
-            let (pool_token_key, pool_token_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &pool_mint_key,
-                &mut pool_mint_account,
-                &authority_key,
-                &user_key,
-                0,
-            );
-            let (pool_fee_key, pool_fee_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &pool_mint_key,
-                &mut pool_mint_account,
-                &authority_key,
-                &user_key,
-                0,
-            );
-            let (token_a_mint_key, mut token_a_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
-            let (token_a_key, token_a_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &token_a_mint_key,
-                &mut token_a_mint_account,
-                &user_key,
-                &authority_key,
-                token_a_amount,
-            );
-            let (token_b_mint_key, mut token_b_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
-            let (token_b_key, token_b_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &token_b_mint_key,
-                &mut token_b_mint_account,
-                &user_key,
-                &authority_key,
-                token_b_amount,
-            );
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
 
-            SwapAccountInfo {
-                nonce,
-                authority_key,
-                fees,
-                swap_curve,
-                swap_key,
-                swap_account,
-                pool_mint_key,
-                pool_mint_account,
-                pool_fee_key,
-                pool_fee_account,
-                pool_token_key,
-                pool_token_account,
-                token_a_key,
-                token_a_account,
-                token_a_mint_key,
-                token_a_mint_account,
-                token_b_key,
-                token_b_account,
-                token_b_mint_key,
-                token_b_mint_account,
+        let fee_account =
+            Self::unpack_token_account(fee_account_info, &token_swap.token_program_id)?;
+        if fee_account.mint != token_swap.pool_mint {
+            return Err(SwapError::IncorrectPoolMint.into());
+        }
+        if fee_account.owner == Self::authority_id(program_id, swap_info.key, token_swap.nonce)? {
+            return Err(SwapError::InvalidOutputOwner.into());
+        }
+        if let Some(swap_constraints) = swap_constraints {
+            let owner_key = swap_constraints
+                .owner_key
+                .parse::<Pubkey>()
+                .map_err(|_| SwapError::InvalidOwner)?;
+            if fee_account.owner != owner_key {
+                return Err(SwapError::InvalidOwner.into());
             }
         }
 
-        pub fn initialize_swap(&mut self) -> ProgramResult {
-            do_process_instruction(
-                initialize(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &self.swap_key,
-                    &self.authority_key,
-                    &self.token_a_key,
-                    &self.token_b_key,
-                    &self.pool_mint_key,
-                    &self.pool_fee_key,
-                    &self.pool_token_key,
-                    self.nonce,
-                    self.fees.clone(),
-                    self.swap_curve.clone(),
-                )
-                .unwrap(),
-                vec![
-                    &mut self.swap_account,
-                    &mut Account::default(),
-                    &mut self.token_a_account,
-                    &mut self.token_b_account,
-                    &mut self.pool_mint_account,
-                    &mut self.pool_fee_account,
-                    &mut self.pool_token_account,
-                    &mut Account::default(),
-                ],
-            )
+        token_swap.pool_fee_account = *fee_account_info.key;
+        SwapVersion::pack(
+            SwapVersion::SwapV1(token_swap),
+            &mut swap_info.data.borrow_mut(),
+        )
+    }
+
+    /// Processes a [SetAdminAuthority](enum.Instruction.html).
+    pub fn process_set_admin_authority(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let new_admin_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
+        // the admin of a swap of any version can be handed over
+        Self::check_admin(swap_info, admin_info)?;
 
-        pub fn setup_token_accounts(
-            &mut self,
-            mint_owner: &Pubkey,
-            account_owner: &Pubkey,
-            a_amount: u64,
-            b_amount: u64,
-            pool_amount: u64,
-        ) -> (Pubkey, Account, Pubkey, Account, Pubkey, Account) {
-            let (token_a_key, token_a_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &self.token_a_mint_key,
-                &mut self.token_a_mint_account,
-                &mint_owner,
-                &account_owner,
-                a_amount,
-            );
-            let (token_b_key, token_b_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &self.token_b_mint_key,
-                &mut self.token_b_mint_account,
-                &mint_owner,
-                &account_owner,
-                b_amount,
-            );
-            let (pool_key, pool_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &self.pool_mint_key,
-                &mut self.pool_mint_account,
-                &self.authority_key,
-                &account_owner,
-                pool_amount,
-            );
-            (
-                token_a_key,
-                token_a_account,
-                token_b_key,
-                token_b_account,
-                pool_key,
-                pool_account,
-            )
+        SwapVersion::pack_admin(
+            SwapAdmin {
+                admin_authority: *new_admin_info.key,
+            },
+            &mut swap_info.data.borrow_mut(),
+        )
+    }
+
+    /// Replaces the stable curve of an admin-controlled swap with the curve
+    /// returned by `update`, given the current unix timestamp
+    fn update_stable_curve<F>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        update: F,
+    ) -> ProgramResult
+    where
+        F: FnOnce(&StableCurve, UnixTimestamp) -> Result<StableCurve, SwapError>,
+    {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
+        let mut token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
 
-        fn get_token_account(&self, account_key: &Pubkey) -> &Account {
-            if *account_key == self.token_a_key {
-                return &self.token_a_account;
-            } else if *account_key == self.token_b_key {
-                return &self.token_b_account;
-            }
-            panic!("Could not find matching swap token account");
+        if token_swap.swap_curve.curve_type != CurveType::Stable {
+            return Err(SwapError::UnsupportedCurveType.into());
         }
+        let mut packed_curve = [0u8; SwapCurve::LEN];
+        token_swap.swap_curve.pack_into_slice(&mut packed_curve);
+        let curve = StableCurve::unpack_from_slice(&packed_curve[1..])?;
 
-        fn set_token_account(&mut self, account_key: &Pubkey, account: Account) {
-            if *account_key == self.token_a_key {
-                self.token_a_account = account;
-                return;
-            } else if *account_key == self.token_b_key {
-                self.token_b_account = account;
-                return;
-            }
-            panic!("Could not find matching swap token account");
+        let curve = update(&curve, Clock::get()?.unix_timestamp)?;
+        token_swap.swap_curve.calculator = Box::new(curve);
+        SwapVersion::pack(
+            SwapVersion::SwapV1(token_swap),
+            &mut swap_info.data.borrow_mut(),
+        )
+    }
+
+    /// Processes a [RampA](enum.Instruction.html).
+    pub fn process_ramp_a(
+        program_id: &Pubkey,
+        target_amp: u64,
+        stop_ramp_ts: UnixTimestamp,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        Self::update_stable_curve(program_id, accounts, |curve, now| {
+            curve.ramp(target_amp, now, stop_ramp_ts)
+        })
+    }
+
+    /// Processes a [StopRampA](enum.Instruction.html).
+    pub fn process_stop_ramp_a(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        Self::update_stable_curve(program_id, accounts, |curve, now| curve.stop_ramp(now))
+    }
+
+    /// Processes a [SetPoolStatus](enum.Instruction.html).
+    pub fn process_set_pool_status(
+        program_id: &Pubkey,
+        status: PoolStatus,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
+        // swaps of any version can be paused
+        Self::check_admin(swap_info, admin_info)?;
 
-        #[allow(clippy::too_many_arguments)]
-        pub fn swap(
-            &mut self,
-            user_key: &Pubkey,
-            user_source_key: &Pubkey,
-            mut user_source_account: &mut Account,
-            swap_source_key: &Pubkey,
-            swap_destination_key: &Pubkey,
-            user_destination_key: &Pubkey,
-            mut user_destination_account: &mut Account,
-            amount_in: u64,
-            minimum_amount_out: u64,
-        ) -> ProgramResult {
-            let user_transfer_key = Pubkey::new_unique();
-            // approve moving from user source account
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &user_source_key,
-                    &user_transfer_key,
-                    &user_key,
-                    &[],
-                    amount_in,
-                )
-                .unwrap(),
-                vec![
-                    &mut user_source_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
-            )
-            .unwrap();
+        SwapVersion::pack_pool_status(status, &mut swap_info.data.borrow_mut())
+    }
 
-            let mut swap_source_account = self.get_token_account(swap_source_key).clone();
-            let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+    /// Processes a [SetDynamicFee](enum.Instruction.html).
+    pub fn process_set_dynamic_fee(
+        program_id: &Pubkey,
+        instruction: SetDynamicFee,
+        accounts: &[AccountInfo],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
 
-            // perform the swap
-            do_process_instruction(
-                swap(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &self.swap_key,
-                    &self.authority_key,
-                    &user_transfer_key,
-                    &user_source_key,
-                    &swap_source_key,
-                    &swap_destination_key,
-                    &user_destination_key,
-                    &self.pool_mint_key,
-                    &self.pool_fee_key,
-                    None,
-                    Swap {
-                        amount_in,
-                        minimum_amount_out,
-                    },
-                )
-                .unwrap(),
-                vec![
-                    &mut self.swap_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                    &mut user_source_account,
-                    &mut swap_source_account,
-                    &mut swap_destination_account,
-                    &mut user_destination_account,
-                    &mut self.pool_mint_account,
-                    &mut self.pool_fee_account,
-                    &mut Account::default(),
-                ],
-            )?;
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
+        let dynamic_fee = SwapVersion::unpack_dynamic_fee(&swap_info.data.borrow())?
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        // the volatility recorded so far is kept
+        let dynamic_fee = DynamicFee {
+            min_trade_fee_numerator: instruction.min_trade_fee_numerator,
+            max_trade_fee_numerator: instruction.max_trade_fee_numerator,
+            max_volatility: instruction.max_volatility,
+            volatility_period: instruction.volatility_period,
+            ..dynamic_fee.decay(Clock::get()?.slot)
+        };
+        if let Some(swap_constraints) = swap_constraints {
+            if dynamic_fee.is_enabled() {
+                for trade_fee_numerator in &[
+                    dynamic_fee.min_trade_fee_numerator,
+                    dynamic_fee.max_trade_fee_numerator,
+                ] {
+                    swap_constraints.validate_fees(&Fees {
+                        trade_fee_numerator: *trade_fee_numerator,
+                        ..token_swap.fees.clone()
+                    })?;
+                }
+            }
+        }
+        dynamic_fee.validate(&token_swap.fees)?;
 
-            self.set_token_account(swap_source_key, swap_source_account);
-            self.set_token_account(swap_destination_key, swap_destination_account);
+        SwapVersion::pack_dynamic_fee(dynamic_fee, &mut swap_info.data.borrow_mut())
+    }
 
-            Ok(())
+    /// Processes a [SetFlashLoanFee](enum.Instruction.html).
+    pub fn process_set_flash_loan_fee(
+        program_id: &Pubkey,
+        instruction: SetFlashLoanFee,
+        accounts: &[AccountInfo],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
+        SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
+        SwapVersion::unpack_flash_loan_fee(&swap_info.data.borrow())?
+            .ok_or(ProgramError::InvalidAccountData)?;
 
-        #[allow(clippy::too_many_arguments)]
-        pub fn deposit_all_token_types(
-            &mut self,
-            depositor_key: &Pubkey,
-            depositor_token_a_key: &Pubkey,
-            mut depositor_token_a_account: &mut Account,
-            depositor_token_b_key: &Pubkey,
-            mut depositor_token_b_account: &mut Account,
-            depositor_pool_key: &Pubkey,
-            mut depositor_pool_account: &mut Account,
-            pool_token_amount: u64,
-            maximum_token_a_amount: u64,
-            maximum_token_b_amount: u64,
-        ) -> ProgramResult {
-            let user_transfer_authority = Pubkey::new_unique();
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &depositor_token_a_key,
-                    &user_transfer_authority,
-                    &depositor_key,
-                    &[],
-                    maximum_token_a_amount,
-                )
-                .unwrap(),
-                vec![
-                    &mut depositor_token_a_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
-            )
-            .unwrap();
+        let flash_loan_fee = FlashLoanFee {
+            trade_fee_numerator: instruction.trade_fee_numerator,
+            trade_fee_denominator: instruction.trade_fee_denominator,
+            owner_trade_fee_numerator: instruction.owner_trade_fee_numerator,
+            owner_trade_fee_denominator: instruction.owner_trade_fee_denominator,
+        };
+        // the owner of the program keeps its share of flash loans
+        if let Some(swap_constraints) = swap_constraints {
+            swap_constraints.validate_fees(&Fees {
+                owner_trade_fee_numerator: flash_loan_fee.owner_trade_fee_numerator,
+                owner_trade_fee_denominator: flash_loan_fee.owner_trade_fee_denominator,
+                ..swap_constraints.fees.clone()
+            })?;
+        }
+        flash_loan_fee.fees().validate()?;
 
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &depositor_token_b_key,
-                    &user_transfer_authority,
-                    &depositor_key,
-                    &[],
-                    maximum_token_b_amount,
-                )
-                .unwrap(),
-                vec![
-                    &mut depositor_token_b_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
-            )
-            .unwrap();
+        SwapVersion::pack_flash_loan_fee(flash_loan_fee, &mut swap_info.data.borrow_mut())
+    }
 
-            do_process_instruction(
-                deposit_all_token_types(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &self.swap_key,
-                    &self.authority_key,
-                    &user_transfer_authority,
-                    &depositor_token_a_key,
-                    &depositor_token_b_key,
-                    &self.token_a_key,
-                    &self.token_b_key,
-                    &self.pool_mint_key,
-                    &depositor_pool_key,
-                    DepositAllTokenTypes {
-                        pool_token_amount,
-                        maximum_token_a_amount,
-                        maximum_token_b_amount,
-                    },
-                )
-                .unwrap(),
-                vec![
-                    &mut self.swap_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                    &mut depositor_token_a_account,
-                    &mut depositor_token_b_account,
-                    &mut self.token_a_account,
-                    &mut self.token_b_account,
-                    &mut self.pool_mint_account,
-                    &mut depositor_pool_account,
-                    &mut Account::default(),
-                ],
-            )
+    /// Processes a [SetAllowlist](enum.Instruction.html).
+    pub fn process_set_allowlist(
+        program_id: &Pubkey,
+        gate_swaps: bool,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let allowlist_authority_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
+        SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
 
-        #[allow(clippy::too_many_arguments)]
-        pub fn withdraw_all_token_types(
-            &mut self,
-            user_key: &Pubkey,
-            pool_key: &Pubkey,
-            mut pool_account: &mut Account,
-            token_a_key: &Pubkey,
-            mut token_a_account: &mut Account,
-            token_b_key: &Pubkey,
-            mut token_b_account: &mut Account,
-            pool_token_amount: u64,
-            minimum_token_a_amount: u64,
-            minimum_token_b_amount: u64,
-        ) -> ProgramResult {
-            let user_transfer_authority_key = Pubkey::new_unique();
-            // approve user transfer authority to take out pool tokens
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &pool_key,
-                    &user_transfer_authority_key,
-                    &user_key,
-                    &[],
-                    pool_token_amount,
-                )
-                .unwrap(),
-                vec![
-                    &mut pool_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
-            )
-            .unwrap();
+        SwapVersion::pack_allowlist(
+            SwapAllowlist {
+                allowlist_authority: *allowlist_authority_info.key,
+                gate_swaps,
+            },
+            &mut swap_info.data.borrow_mut(),
+        )
+    }
 
-            // withdraw token a and b correctly
-            do_process_instruction(
-                withdraw_all_token_types(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &self.swap_key,
-                    &self.authority_key,
-                    &user_transfer_authority_key,
-                    &self.pool_mint_key,
-                    &self.pool_fee_key,
-                    &pool_key,
-                    &self.token_a_key,
-                    &self.token_b_key,
-                    &token_a_key,
-                    &token_b_key,
-                    WithdrawAllTokenTypes {
-                        pool_token_amount,
-                        minimum_token_a_amount,
-                        minimum_token_b_amount,
-                    },
-                )
-                .unwrap(),
-                vec![
-                    &mut self.swap_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                    &mut self.pool_mint_account,
-                    &mut pool_account,
-                    &mut self.token_a_account,
-                    &mut self.token_b_account,
-                    &mut token_a_account,
-                    &mut token_b_account,
-                    &mut self.pool_fee_account,
-                    &mut Account::default(),
-                ],
-            )
+    /// Processes an [AddAllowlistMember](enum.Instruction.html).
+    pub fn process_add_allowlist_member(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let allowlist_authority_info = next_account_info(account_info_iter)?;
+        let membership_info = next_account_info(account_info_iter)?;
+        let member_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id || membership_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_allowlist_authority(swap_info, allowlist_authority_info)?;
+        let membership = AllowlistMember::unpack_unchecked(&membership_info.data.borrow())?;
+        if membership.is_initialized {
+            return Err(SwapError::AlreadyInUse.into());
         }
 
-        #[allow(clippy::too_many_arguments)]
-        pub fn deposit_single_token_type_exact_amount_in(
-            &mut self,
-            depositor_key: &Pubkey,
-            deposit_account_key: &Pubkey,
-            mut deposit_token_account: &mut Account,
-            deposit_pool_key: &Pubkey,
-            mut deposit_pool_account: &mut Account,
-            source_token_amount: u64,
-            minimum_pool_token_amount: u64,
-        ) -> ProgramResult {
-            let user_transfer_authority_key = Pubkey::new_unique();
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &deposit_account_key,
-                    &user_transfer_authority_key,
-                    &depositor_key,
-                    &[],
-                    source_token_amount,
-                )
-                .unwrap(),
-                vec![
-                    &mut deposit_token_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
-            )
-            .unwrap();
-
-            do_process_instruction(
-                deposit_single_token_type_exact_amount_in(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &self.swap_key,
-                    &self.authority_key,
-                    &user_transfer_authority_key,
-                    &deposit_account_key,
-                    &self.token_a_key,
-                    &self.token_b_key,
-                    &self.pool_mint_key,
-                    &deposit_pool_key,
-                    DepositSingleTokenTypeExactAmountIn {
-                        source_token_amount,
-                        minimum_pool_token_amount,
-                    },
-                )
-                .unwrap(),
-                vec![
-                    &mut self.swap_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                    &mut deposit_token_account,
-                    &mut self.token_a_account,
-                    &mut self.token_b_account,
-                    &mut self.pool_mint_account,
-                    &mut deposit_pool_account,
-                    &mut Account::default(),
-                ],
-            )
-        }
+        let obj = AllowlistMember {
+            is_initialized: true,
+            swap: *swap_info.key,
+            member: *member_info.key,
+        };
+        AllowlistMember::pack(obj, &mut membership_info.data.borrow_mut())?;
+        Ok(())
+    }
 
-        #[allow(clippy::too_many_arguments)]
-        pub fn withdraw_single_token_type_exact_amount_out(
-            &mut self,
-            user_key: &Pubkey,
-            pool_key: &Pubkey,
-            mut pool_account: &mut Account,
-            destination_key: &Pubkey,
-            mut destination_account: &mut Account,
-            destination_token_amount: u64,
-            maximum_pool_token_amount: u64,
-        ) -> ProgramResult {
-            let user_transfer_authority_key = Pubkey::new_unique();
-            // approve user transfer authority to take out pool tokens
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &pool_key,
-                    &user_transfer_authority_key,
-                    &user_key,
-                    &[],
-                    maximum_pool_token_amount,
-                )
-                .unwrap(),
-                vec![
-                    &mut pool_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
-            )
-            .unwrap();
+    /// Processes a [RemoveAllowlistMember](enum.Instruction.html).
+    pub fn process_remove_allowlist_member(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let allowlist_authority_info = next_account_info(account_info_iter)?;
+        let membership_info = next_account_info(account_info_iter)?;
 
-            do_process_instruction(
-                withdraw_single_token_type_exact_amount_out(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &self.swap_key,
-                    &self.authority_key,
-                    &user_transfer_authority_key,
-                    &self.pool_mint_key,
-                    &self.pool_fee_key,
-                    &pool_key,
-                    &self.token_a_key,
-                    &self.token_b_key,
-                    &destination_key,
-                    WithdrawSingleTokenTypeExactAmountOut {
-                        destination_token_amount,
-                        maximum_pool_token_amount,
-                    },
-                )
-                .unwrap(),
-                vec![
-                    &mut self.swap_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                    &mut self.pool_mint_account,
-                    &mut pool_account,
-                    &mut self.token_a_account,
-                    &mut self.token_b_account,
-                    &mut destination_account,
-                    &mut self.pool_fee_account,
-                    &mut Account::default(),
-                ],
-            )
+        if swap_info.owner != program_id || membership_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_allowlist_authority(swap_info, allowlist_authority_info)?;
+        let membership = AllowlistMember::unpack(&membership_info.data.borrow())?;
+        if membership.swap != *swap_info.key {
+            return Err(SwapError::IncorrectSwapAccount.into());
         }
-    }
-
-    fn mint_minimum_balance() -> u64 {
-        Rent::default().minimum_balance(spl_token::state::Mint::get_packed_len())
-    }
 
-    fn account_minimum_balance() -> u64 {
-        Rent::default().minimum_balance(spl_token::state::Account::get_packed_len())
+        AllowlistMember::pack(
+            AllowlistMember::default(),
+            &mut membership_info.data.borrow_mut(),
+        )?;
+        Ok(())
     }
 
-    fn do_process_instruction_with_fee_constraints(
-        instruction: Instruction,
-        accounts: Vec<&mut Account>,
+    /// Processes a [MigrateSwap](enum.Instruction.html).
+    pub fn process_migrate_swap(
+        program_id: &Pubkey,
+        nonce: u8,
+        accounts: &[AccountInfo],
         swap_constraints: &Option<SwapConstraints>,
     ) -> ProgramResult {
-        test_syscall_stubs();
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let new_swap_info = next_account_info(account_info_iter)?;
+        let new_authority_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let migration_authority_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter).ok();
 
-        // approximate the logic in the actual runtime which runs the instruction
-        // and only updates accounts if the instruction is successful
-        let mut account_clones = accounts.iter().map(|x| (*x).clone()).collect::<Vec<_>>();
-        let mut meta = instruction
-            .accounts
-            .iter()
-            .zip(account_clones.iter_mut())
-            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
-            .collect::<Vec<_>>();
-        let mut account_infos = create_is_signer_account_infos(&mut meta);
-        let res = if instruction.program_id == SWAP_PROGRAM_ID {
-            Processor::process_with_constraints(
-                &instruction.program_id,
-                &account_infos,
-                &instruction.data,
-                swap_constraints,
-            )
-        } else {
-            spl_token::processor::Processor::process(
-                &instruction.program_id,
-                &account_infos,
-                &instruction.data,
-            )
+        if swap_info.owner != program_id || new_swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        // only swaps without an extension have anything to migrate, and the
+        // new swap must hold all of the latest state
+        if swap_info.data_len() != SwapVersion::LEGACY_LEN
+            || new_swap_info.data_len() != SwapVersion::LATEST_LEN
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // swaps without an extension have no admin, so they are migrated by
+        // the owner of their fees, or by the owner of the program when it is
+        // constrained
+        if *pool_fee_account_info.key != token_swap.pool_fee_account {
+            return Err(SwapError::IncorrectFeeAccount.into());
+        }
+        let pool_fee_account =
+            Self::unpack_token_account(pool_fee_account_info, &token_swap.token_program_id)?;
+        let constraints_owner = match swap_constraints {
+            Some(swap_constraints) => Some(
+                swap_constraints
+                    .owner_key
+                    .parse::<Pubkey>()
+                    .map_err(|_| SwapError::InvalidOwner)?,
+            ),
+            None => None,
         };
+        if *migration_authority_info.key != pool_fee_account.owner
+            && Some(*migration_authority_info.key) != constraints_owner
+        {
+            return Err(SwapError::InvalidOwner.into());
+        }
+        if !migration_authority_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        // the admin must agree to take control of the new swap
+        if admin_info.map_or(false, |info| !info.is_signer) {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if SwapVersion::is_initialized(&new_swap_info.data.borrow()) {
+            return Err(SwapError::AlreadyInUse.into());
+        }
+        if *authority_info.key != Self::authority_id(program_id, swap_info.key, token_swap.nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *new_authority_info.key != Self::authority_id(program_id, new_swap_info.key, nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *token_a_info.key != token_swap.token_a || *token_b_info.key != token_swap.token_b {
+            return Err(SwapError::IncorrectSwapAccount.into());
+        }
+        if *pool_mint_info.key != token_swap.pool_mint {
+            return Err(SwapError::IncorrectPoolMint.into());
+        }
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        // a swap that was already migrated no longer holds its tokens
+        let token_a = Self::unpack_token_account(token_a_info, &token_swap.token_program_id)?;
+        let token_b = Self::unpack_token_account(token_b_info, &token_swap.token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+        if token_a.owner != *authority_info.key
+            || token_b.owner != *authority_info.key
+            || pool_mint.mint_authority != COption::Some(*authority_info.key)
+        {
+            return Err(SwapError::InvalidOwner.into());
+        }
 
-        if res.is_ok() {
-            let mut account_metas = instruction
-                .accounts
-                .iter()
-                .zip(accounts)
-                .map(|(account_meta, account)| (&account_meta.pubkey, account))
-                .collect::<Vec<_>>();
-            for account_info in account_infos.iter_mut() {
-                for account_meta in account_metas.iter_mut() {
-                    if account_info.key == account_meta.0 {
-                        let account = &mut account_meta.1;
-                        account.owner = *account_info.owner;
-                        account.lamports = **account_info.lamports.borrow();
-                        account.data = account_info.data.borrow().to_vec();
-                    }
-                }
-            }
+        for (owned_info, authority_type) in [
+            (token_a_info, AuthorityType::AccountOwner),
+            (token_b_info, AuthorityType::AccountOwner),
+            (pool_mint_info, AuthorityType::MintTokens),
+        ]
+        .iter()
+        {
+            Self::token_set_authority(
+                swap_info.key,
+                token_program_info.clone(),
+                (*owned_info).clone(),
+                authority_info.clone(),
+                token_swap.nonce,
+                new_authority_info.key,
+                authority_type.clone(),
+            )?;
         }
-        res
+
+        let mut old_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        // without an admin there is no allowlist or fee split to carry over,
+        // but the new swap can be given one
+        let extension = SwapExtension {
+            price_accumulator: PriceAccumulator::new(Clock::get()?.slot),
+            admin: SwapAdmin {
+                admin_authority: admin_info.map_or_else(Pubkey::default, |info| *info.key),
+            },
+            flash_loan_fee: FlashLoanFee::from_trade_fees(&token_swap.fees),
+            ..SwapExtension::default()
+        };
+        token_swap.nonce = nonce;
+
+        let mut new_data = new_swap_info.data.borrow_mut();
+        SwapVersion::pack(SwapVersion::SwapV1(token_swap), &mut new_data)?;
+        SwapVersion::pack_extension(extension, &mut new_data)?;
+
+        // the old swap can no longer be used, migrated or initialized again,
+        // but its farms can still be unstaked and claimed, and its limit
+        // orders cancelled
+        old_swap.is_initialized = false;
+        let mut old_data = swap_info.data.borrow_mut();
+        SwapVersion::pack(SwapVersion::SwapV1(old_swap), &mut old_data)?;
+        SwapVersion::retire(&mut old_data)
     }
 
-    fn do_process_instruction(
-        instruction: Instruction,
-        accounts: Vec<&mut Account>,
-    ) -> ProgramResult {
-        do_process_instruction_with_fee_constraints(instruction, accounts, &SWAP_CONSTRAINTS)
+    /// Unpacks a limit order, checking that it is filled against the given
+    /// swap
+    fn unpack_limit_order(
+        program_id: &Pubkey,
+        swap_info: &AccountInfo,
+        order_info: &AccountInfo,
+    ) -> Result<LimitOrder, ProgramError> {
+        if order_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let order = LimitOrder::unpack(&order_info.data.borrow())?;
+        if order.swap != *swap_info.key {
+            return Err(SwapError::IncorrectLimitOrder.into());
+        }
+        Ok(order)
     }
 
-    fn mint_token(
+    /// Processes a [CreateLimitOrder](enum.Instruction.html).
+    pub fn process_create_limit_order(
         program_id: &Pubkey,
-        mint_key: &Pubkey,
-        mut mint_account: &mut Account,
-        mint_authority_key: &Pubkey,
-        account_owner_key: &Pubkey,
-        amount: u64,
-    ) -> (Pubkey, Account) {
-        let account_key = Pubkey::new_unique();
-        let mut account_account = Account::new(
-            account_minimum_balance(),
-            spl_token::state::Account::get_packed_len(),
-            &program_id,
-        );
-        let mut mint_authority_account = Account::default();
-        let mut rent_sysvar_account = create_account(&Rent::free(), 1);
+        instruction: CreateLimitOrder,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let order_info = next_account_info(account_info_iter)?;
+        let order_authority_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let escrow_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
 
-        do_process_instruction(
-            initialize_account(&program_id, &account_key, &mint_key, account_owner_key).unwrap(),
-            vec![
-                &mut account_account,
-                &mut mint_account,
-                &mut mint_authority_account,
-                &mut rent_sysvar_account,
-            ],
-        )
-        .unwrap();
+        if swap_info.owner != program_id || order_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        if !Self::pool_status(swap_info)?.allows_orders() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        if !owner_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if LimitOrder::unpack_unchecked(&order_info.data.borrow())?.is_initialized {
+            return Err(SwapError::AlreadyInUse.into());
+        }
+        if *order_authority_info.key
+            != Self::authority_id(program_id, order_info.key, instruction.nonce)?
+        {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        Self::check_allowlist(program_id, swap_info, owner_info, None, true)?;
 
-        if amount > 0 {
-            do_process_instruction(
-                mint_to(
-                    &program_id,
-                    &mint_key,
-                    &account_key,
-                    &mint_authority_key,
-                    &[],
-                    amount,
-                )
-                .unwrap(),
-                vec![
-                    &mut mint_account,
-                    &mut account_account,
-                    &mut mint_authority_account,
-                ],
-            )
-            .unwrap();
+        let (source_mint, destination_mint) = match instruction.trade_direction {
+            TradeDirection::AtoB => (token_swap.token_a_mint, token_swap.token_b_mint),
+            TradeDirection::BtoA => (token_swap.token_b_mint, token_swap.token_a_mint),
+        };
+        let escrow = Self::unpack_token_account(escrow_info, &token_swap.token_program_id)?;
+        if escrow.owner != *order_authority_info.key {
+            return Err(SwapError::InvalidOwner.into());
+        }
+        if escrow.delegate.is_some() {
+            return Err(SwapError::InvalidDelegate.into());
+        }
+        if escrow.close_authority.is_some() {
+            return Err(SwapError::InvalidCloseAuthority.into());
+        }
+        let destination =
+            Self::unpack_token_account(destination_info, &token_swap.token_program_id)?;
+        if escrow.mint != source_mint || destination.mint != destination_mint {
+            return Err(SwapError::IncorrectLimitOrder.into());
+        }
+        if instruction.amount_in == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+        if *source_info.key == token_swap.token_a || *source_info.key == token_swap.token_b {
+            return Err(SwapError::InvalidInput.into());
+        }
+        // a stop loss can never fill below its floor
+        if instruction.kind == LimitOrderKind::StopLoss
+            && instruction.minimum_amount_out > instruction.trigger_amount_out
+        {
+            return Err(SwapError::InvalidInput.into());
         }
 
-        (account_key, account_account)
+        // the escrow belongs to the order, so the deposit must be signed by
+        // the user rather than the swap authority
+        Self::user_token_transfer(
+            token_program_info.clone(),
+            source_info.clone(),
+            escrow_info.clone(),
+            user_transfer_authority_info.clone(),
+            instruction.amount_in,
+        )?;
+        let received = Self::unpack_token_account(escrow_info, &token_swap.token_program_id)?
+            .amount
+            .saturating_sub(escrow.amount);
+        let mut order = LimitOrder {
+            is_initialized: true,
+            nonce: instruction.nonce,
+            swap: *swap_info.key,
+            owner: *owner_info.key,
+            escrow: *escrow_info.key,
+            destination: *destination_info.key,
+            trade_direction: instruction.trade_direction,
+            kind: instruction.kind,
+            amount_in: instruction.amount_in,
+            trigger_amount_out: instruction.trigger_amount_out,
+            minimum_amount_out: instruction.minimum_amount_out,
+        };
+        if received < instruction.amount_in {
+            order
+                .resize(received)
+                .ok_or(SwapError::CalculationFailure)?;
+        }
+        LimitOrder::pack(order, &mut order_info.data.borrow_mut())?;
+        Ok(())
     }
 
-    fn create_mint(
+    /// Processes a [FillLimitOrder](enum.Instruction.html).
+    pub fn process_fill_limit_order(
         program_id: &Pubkey,
-        authority_key: &Pubkey,
-        freeze_authority: Option<&Pubkey>,
-    ) -> (Pubkey, Account) {
-        let mint_key = Pubkey::new_unique();
-        let mut mint_account = Account::new(
-            mint_minimum_balance(),
-            spl_token::state::Mint::get_packed_len(),
-            &program_id,
-        );
-        let mut rent_sysvar_account = create_account(&Rent::free(), 1);
-
-        do_process_instruction(
-            initialize_mint(&program_id, &mint_key, authority_key, freeze_authority, 2).unwrap(),
-            vec![&mut mint_account, &mut rent_sysvar_account],
-        )
-        .unwrap();
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let order_info = next_account_info(account_info_iter)?;
+        let order_authority_info = next_account_info(account_info_iter)?;
+        let escrow_info = next_account_info(account_info_iter)?;
+        let swap_source_info = next_account_info(account_info_iter)?;
+        let swap_destination_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
 
-        (mint_key, mint_account)
-    }
+        let mut order = Self::unpack_limit_order(program_id, swap_info, order_info)?;
+        if *order_authority_info.key != Self::authority_id(program_id, order_info.key, order.nonce)?
+        {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *escrow_info.key != order.escrow || *destination_info.key != order.destination {
+            return Err(SwapError::IncorrectLimitOrder.into());
+        }
 
-    #[test]
-    fn test_token_program_id_error() {
-        test_syscall_stubs();
-        let swap_key = Pubkey::new_unique();
-        let mut mint = (Pubkey::new_unique(), Account::default());
-        let mut destination = (Pubkey::new_unique(), Account::default());
-        let token_program = (TOKEN_PROGRAM_ID, Account::default());
-        let (authority_key, nonce) =
-            Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
-        let mut authority = (authority_key, Account::default());
-        let swap_bytes = swap_key.to_bytes();
-        let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
-        let signers = &[&authority_signature_seeds[..]];
-        let ix = mint_to(
-            &token_program.0,
-            &mint.0,
-            &destination.0,
-            &authority.0,
-            &[],
-            10,
-        )
-        .unwrap();
-        let mint = (&mut mint).into();
-        let destination = (&mut destination).into();
-        let authority = (&mut authority).into();
+        let (result, nonce) = Self::swap_in_pool(
+            program_id,
+            swap_info,
+            authority_info,
+            swap_source_info,
+            swap_destination_info,
+            pool_mint_info,
+            pool_fee_account_info,
+            token_program_info,
+            None,
+            |swap_curve, fees, swap_source_amount, swap_destination_amount, trade_direction| {
+                if trade_direction != order.trade_direction {
+                    return Err(SwapError::IncorrectSwapAccount.into());
+                }
+                let result = swap_curve
+                    .swap(
+                        to_u128(order.amount_in)?,
+                        swap_source_amount,
+                        swap_destination_amount,
+                        trade_direction,
+                        fees,
+                    )
+                    .ok_or(SwapError::ZeroTradingTokens)?;
+                let trigger_amount_out = to_u128(order.trigger_amount_out)?;
+                let triggered = match order.kind {
+                    LimitOrderKind::TakeProfit => {
+                        result.destination_amount_swapped >= trigger_amount_out
+                    }
+                    LimitOrderKind::StopLoss => {
+                        result.destination_amount_swapped <= trigger_amount_out
+                    }
+                };
+                if !triggered {
+                    return Err(SwapError::LimitOrderNotTriggered.into());
+                }
+                Ok(result)
+            },
+            |amount, _nonce| {
+                Self::token_transfer_received(
+                    order_info.key,
+                    token_program_info.clone(),
+                    escrow_info.clone(),
+                    swap_source_info.clone(),
+                    order_authority_info.clone(),
+                    order.nonce,
+                    amount,
+                )
+            },
+        )?;
+        // like routes, fills cannot carry allowlist memberships
+        Self::check_allowlist(program_id, swap_info, order_authority_info, None, true)?;
+        if result.destination_amount_swapped < to_u128(order.minimum_amount_out)? {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            nonce,
+            to_u64(result.destination_amount_swapped)?,
+        )?;
 
-        let err = invoke_signed(&ix, &[mint, destination, authority], signers).unwrap_err();
-        assert_eq!(err, ProgramError::InvalidAccountData);
+        // curves that only swap whole multiples of their price leave the rest
+        // of the order open
+        let remaining = Self::unpack_token_account(escrow_info, token_program_info.key)?.amount;
+        if remaining == 0 {
+            order = LimitOrder::default();
+        } else {
+            order
+                .resize(remaining)
+                .ok_or(SwapError::CalculationFailure)?;
+        }
+        LimitOrder::pack(order, &mut order_info.data.borrow_mut())?;
+        Ok(())
     }
 
-    #[test]
-    fn test_initialize() {
-        let user_key = Pubkey::new_unique();
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 2;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 10;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 5;
-        let host_fee_numerator = 20;
-        let host_fee_denominator = 100;
-        let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
-        };
+    /// Processes a [CancelLimitOrder](enum.Instruction.html).
+    pub fn process_cancel_limit_order(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let order_info = next_account_info(account_info_iter)?;
+        let order_authority_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let escrow_info = next_account_info(account_info_iter)?;
+        let refund_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
 
-        let token_a_amount = 1000;
-        let token_b_amount = 2000;
-        let pool_token_amount = 10;
-        let curve_type = CurveType::ConstantProduct;
-        let swap_curve = SwapCurve {
-            curve_type,
-            calculator: Box::new(ConstantProductCurve {}),
-        };
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        // orders stay cancellable after the swap is migrated
+        let token_swap = SwapVersion::unpack_migrated_swap_v1(&swap_info.data.borrow())?;
+        let order = Self::unpack_limit_order(program_id, swap_info, order_info)?;
+        if !owner_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if order.owner != *owner_info.key || order.escrow != *escrow_info.key {
+            return Err(SwapError::IncorrectLimitOrder.into());
+        }
+        if *order_authority_info.key != Self::authority_id(program_id, order_info.key, order.nonce)?
+        {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
 
-        let mut accounts =
-            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+        let escrow = Self::unpack_token_account(escrow_info, &token_swap.token_program_id)?;
+        Self::token_transfer(
+            order_info.key,
+            token_program_info.clone(),
+            escrow_info.clone(),
+            refund_info.clone(),
+            order_authority_info.clone(),
+            order.nonce,
+            escrow.amount,
+        )?;
+        LimitOrder::pack(LimitOrder::default(), &mut order_info.data.borrow_mut())?;
+        Ok(())
+    }
 
-        // wrong nonce for authority_key
-        {
-            let old_nonce = accounts.nonce;
-            accounts.nonce = old_nonce - 1;
-            assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.initialize_swap()
-            );
-            accounts.nonce = old_nonce;
+    /// Processes a [SetFeeSplit](enum.Instruction.html).
+    pub fn process_set_fee_split(
+        program_id: &Pubkey,
+        shares: Vec<u16>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let fee_split_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id || fee_split_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
+        let fee_split = FeeSplit::unpack_unchecked(&fee_split_info.data.borrow())?;
+        if fee_split.is_initialized && fee_split.swap != *swap_info.key {
+            return Err(SwapError::InvalidFeeSplit.into());
         }
 
-        // uninitialized token a account
+        let recipient_count = shares.len();
+        if !(1..=MAX_FEE_RECIPIENTS).contains(&recipient_count)
+            || shares.iter().any(|share| *share == 0)
+            || shares.iter().map(|share| *share as u32).sum::<u32>() != FEE_SPLIT_DENOMINATOR as u32
         {
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = Account::new(0, 0, &TOKEN_PROGRAM_ID);
-            assert_eq!(
-                Err(SwapError::ExpectedAccount.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
+            return Err(SwapError::InvalidFeeSplit.into());
         }
+        let recipient_infos = next_account_infos(account_info_iter, recipient_count * 2)?;
 
-        // uninitialized token b account
+        let mut recipients = [FeeRecipient::default(); MAX_FEE_RECIPIENTS];
+        for ((recipient, share), recipient_infos) in recipients
+            .iter_mut()
+            .zip(shares)
+            .zip(recipient_infos.chunks(2))
         {
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = Account::new(0, 0, &TOKEN_PROGRAM_ID);
-            assert_eq!(
-                Err(SwapError::ExpectedAccount.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
+            let (token_a_info, token_b_info) = (&recipient_infos[0], &recipient_infos[1]);
+            if *token_a_info.key == token_swap.token_a || *token_b_info.key == token_swap.token_b {
+                return Err(SwapError::InvalidFeeSplit.into());
+            }
+            let token_a = Self::unpack_token_account(token_a_info, &token_swap.token_program_id)?;
+            let token_b = Self::unpack_token_account(token_b_info, &token_swap.token_program_id)?;
+            if token_a.mint != token_swap.token_a_mint || token_b.mint != token_swap.token_b_mint {
+                return Err(SwapError::InvalidFeeSplit.into());
+            }
+            *recipient = FeeRecipient {
+                token_a: *token_a_info.key,
+                token_b: *token_b_info.key,
+                share,
+            };
         }
 
-        // uninitialized pool mint
-        {
-            let old_account = accounts.pool_mint_account;
-            accounts.pool_mint_account = Account::new(0, 0, &TOKEN_PROGRAM_ID);
-            assert_eq!(
-                Err(SwapError::ExpectedMint.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_mint_account = old_account;
-        }
+        let obj = FeeSplit {
+            is_initialized: true,
+            swap: *swap_info.key,
+            recipient_count: recipient_count as u8,
+            recipients,
+        };
+        FeeSplit::pack(obj, &mut fee_split_info.data.borrow_mut())?;
+        Ok(())
+    }
 
-        // token A account owner is not swap authority
-        {
-            let (_token_a_key, token_a_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &user_key,
-                0,
-            );
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = token_a_account;
-            assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
+    /// Processes a [DistributeFees](enum.Instruction.html).
+    pub fn process_distribute_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let fee_split_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let token_swap = Self::unpack_trading_swap(swap_info)?;
+        Self::check_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            pool_mint_info,
+            token_program_info,
+            None,
+            None,
+            Some(pool_fee_account_info),
+        )?;
+        if fee_split_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let fee_split = FeeSplit::unpack(&fee_split_info.data.borrow())?;
+        if fee_split.swap != *swap_info.key {
+            return Err(SwapError::InvalidFeeSplit.into());
+        }
+        let recipients = fee_split.active_recipients();
+        let recipient_infos = next_account_infos(account_info_iter, recipients.len() * 2)?;
+        for (recipient, recipient_infos) in recipients.iter().zip(recipient_infos.chunks(2)) {
+            if *recipient_infos[0].key != recipient.token_a
+                || *recipient_infos[1].key != recipient.token_b
+            {
+                return Err(SwapError::InvalidFeeSplit.into());
+            }
         }
 
-        // token B account owner is not swap authority
-        {
-            let (_token_b_key, token_b_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_b_mint_key,
-                &mut accounts.token_b_mint_account,
-                &user_key,
-                &user_key,
-                0,
-            );
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_b_account;
-            assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
+        let pool_fee_account =
+            Self::unpack_token_account(pool_fee_account_info, &token_swap.token_program_id)?;
+        if pool_fee_account.amount == 0 {
+            return Err(SwapError::ZeroTradingTokens.into());
         }
+        let token_a = Self::unpack_token_account(token_a_info, &token_swap.token_program_id)?;
+        let token_b = Self::unpack_token_account(token_b_info, &token_swap.token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+        Self::update_price_accumulator(swap_info, token_a.amount, token_b.amount)?;
 
-        // pool token account owner is swap authority
-        {
-            let (_pool_token_key, pool_token_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.pool_token_account;
-            accounts.pool_token_account = pool_token_account;
-            assert_eq!(
-                Err(SwapError::InvalidOutputOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_token_account = old_account;
+        let results = token_swap
+            .swap_curve
+            .calculator
+            .pool_tokens_to_trading_tokens(
+                to_u128(pool_fee_account.amount)?,
+                to_u128(pool_mint.supply)?,
+                to_u128(token_a.amount)?,
+                to_u128(token_b.amount)?,
+                RoundDirection::Floor,
+            )
+            .ok_or(SwapError::ZeroTradingTokens)?;
+        let token_a_amount = std::cmp::min(token_a.amount, to_u64(results.token_a_amount)?);
+        let token_b_amount = std::cmp::min(token_b.amount, to_u64(results.token_b_amount)?);
+        if token_a_amount == 0 && token_b_amount == 0 {
+            return Err(SwapError::ZeroTradingTokens.into());
         }
 
-        // pool fee account owner is swap authority
-        {
-            let (_pool_fee_key, pool_fee_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.pool_fee_account;
-            accounts.pool_fee_account = pool_fee_account;
-            assert_eq!(
-                Err(SwapError::InvalidOutputOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_fee_account = old_account;
+        Self::token_burn(
+            swap_info.key,
+            token_program_info.clone(),
+            pool_fee_account_info.clone(),
+            pool_mint_info.clone(),
+            user_transfer_authority_info.clone(),
+            token_swap.nonce,
+            pool_fee_account.amount,
+        )?;
+
+        // shares are rounded down, so the dust stays with the liquidity
+        // providers
+        for (recipient, recipient_infos) in recipients.iter().zip(recipient_infos.chunks(2)) {
+            let amounts = [
+                (token_a_info, &recipient_infos[0], token_a_amount),
+                (token_b_info, &recipient_infos[1], token_b_amount),
+            ];
+            for (source_info, destination_info, amount) in amounts.iter() {
+                let share = recipient
+                    .share_of(*amount)
+                    .ok_or(SwapError::CalculationFailure)?;
+                if share > 0 {
+                    Self::token_transfer(
+                        swap_info.key,
+                        token_program_info.clone(),
+                        (*source_info).clone(),
+                        (*destination_info).clone(),
+                        authority_info.clone(),
+                        token_swap.nonce,
+                        share,
+                    )?;
+                }
+            }
         }
+        Ok(())
+    }
 
-        // pool mint authority is not swap authority
+    /// Processes an [InitializeMultiToken](enum.Instruction.html).
+    pub fn process_initialize_multi_token(
+        program_id: &Pubkey,
+        nonce: u8,
+        fees: Fees,
+        swap_curve: SwapCurve,
+        weights: Vec<u64>,
+        accounts: &[AccountInfo],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let fee_account_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let token_count = weights.len();
+        if !(MIN_TOKENS_IN_MULTI_TOKEN_POOL..=MAX_TOKENS_IN_MULTI_TOKEN_POOL).contains(&token_count)
         {
-            let (_pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
-            let old_mint = accounts.pool_mint_account;
-            accounts.pool_mint_account = pool_mint_account;
-            assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_mint_account = old_mint;
+            return Err(SwapError::IncorrectTokenCount.into());
         }
+        let token_infos = next_account_infos(account_info_iter, token_count)?;
+        let admin_info = next_account_info(account_info_iter).ok();
 
-        // pool mint token has freeze authority
-        {
-            let (_pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, Some(&user_key));
-            let old_mint = accounts.pool_mint_account;
-            accounts.pool_mint_account = pool_mint_account;
-            assert_eq!(
-                Err(SwapError::InvalidFreezeAuthority.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_mint_account = old_mint;
+        let token_program_id = *token_program_info.key;
+        if SwapVersion::is_initialized(&swap_info.data.borrow()) {
+            return Err(SwapError::AlreadyInUse.into());
+        }
+        // the admin must agree to take control of the swap
+        if admin_info.map_or(false, |info| !info.is_signer) {
+            return Err(ProgramError::MissingRequiredSignature);
         }
 
-        // token A account owned by wrong program
-        {
-            let (_token_a_key, mut token_a_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                token_a_amount,
-            );
-            token_a_account.owner = SWAP_PROGRAM_ID;
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = token_a_account;
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
+        if *authority_info.key != Self::authority_id(program_id, swap_info.key, nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        let fee_account = Self::unpack_token_account(fee_account_info, &token_program_id)?;
+        let destination = Self::unpack_token_account(destination_info, &token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_program_id)?;
+        if *authority_info.key == destination.owner {
+            return Err(SwapError::InvalidOutputOwner.into());
+        }
+        if *authority_info.key == fee_account.owner {
+            return Err(SwapError::InvalidOutputOwner.into());
+        }
+        if COption::Some(*authority_info.key) != pool_mint.mint_authority {
+            return Err(SwapError::InvalidOwner.into());
         }
 
-        // token B account owned by wrong program
-        {
-            let (_token_b_key, mut token_b_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_b_mint_key,
-                &mut accounts.token_b_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                token_b_amount,
-            );
-            token_b_account.owner = SWAP_PROGRAM_ID;
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_b_account;
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
+        let mut tokens: [SwapToken; MAX_TOKENS_IN_MULTI_TOKEN_POOL] = Default::default();
+        for (index, (token_info, weight)) in token_infos.iter().zip(weights.iter()).enumerate() {
+            let token = Self::unpack_token_account(token_info, &token_program_id)?;
+            if *authority_info.key != token.owner {
+                return Err(SwapError::InvalidOwner.into());
+            }
+            if tokens[..index]
+                .iter()
+                .any(|swap_token| swap_token.mint == token.mint)
+            {
+                return Err(SwapError::RepeatedMint.into());
+            }
+            if token.amount == 0 {
+                return Err(SwapError::EmptySupply.into());
+            }
+            if token.delegate.is_some() {
+                return Err(SwapError::InvalidDelegate.into());
+            }
+            if token.close_authority.is_some() {
+                return Err(SwapError::InvalidCloseAuthority.into());
+            }
+            tokens[index] = SwapToken {
+                account: *token_info.key,
+                mint: token.mint,
+                weight: *weight,
+            };
         }
 
-        // empty token A account
-        {
-            let (_token_a_key, token_a_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.token_a_account;
-            accounts.token_a_account = token_a_account;
-            assert_eq!(
-                Err(SwapError::EmptySupply.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_a_account = old_account;
+        if pool_mint.supply != 0 {
+            return Err(SwapError::InvalidSupply.into());
+        }
+        if pool_mint.freeze_authority.is_some() {
+            return Err(SwapError::InvalidFreezeAuthority.into());
+        }
+        if *pool_mint_info.key != fee_account.mint {
+            return Err(SwapError::IncorrectPoolMint.into());
         }
 
-        // empty token B account
-        {
-            let (_token_b_key, token_b_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_b_mint_key,
-                &mut accounts.token_b_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                0,
-            );
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_b_account;
-            assert_eq!(
-                Err(SwapError::EmptySupply.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
+        if let Some(swap_constraints) = swap_constraints {
+            let owner_key = swap_constraints
+                .owner_key
+                .parse::<Pubkey>()
+                .map_err(|_| SwapError::InvalidOwner)?;
+            if fee_account.owner != owner_key {
+                return Err(SwapError::InvalidOwner.into());
+            }
+            swap_constraints.validate_curve(&swap_curve)?;
+            swap_constraints.validate_fees(&fees)?;
         }
+        fees.validate()?;
+        swap_curve.calculator.validate()?;
+        MultiTokenCurve::new(&swap_curve, &weights)?.validate()?;
 
-        // invalid pool tokens
-        {
-            let old_mint = accounts.pool_mint_account;
-            let old_pool_account = accounts.pool_token_account;
-
-            let (_pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
-            accounts.pool_mint_account = pool_mint_account;
-
-            let (_empty_pool_token_key, empty_pool_token_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &user_key,
-                0,
-            );
-
-            let (_pool_token_key, pool_token_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.pool_mint_key,
-                &mut accounts.pool_mint_account,
-                &accounts.authority_key,
-                &user_key,
-                pool_token_amount,
-            );
+        Self::token_mint_to(
+            swap_info.key,
+            token_program_info.clone(),
+            pool_mint_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            nonce,
+            to_u64(swap_curve.calculator.new_pool_supply())?,
+        )?;
 
-            // non-empty pool token account
-            accounts.pool_token_account = pool_token_account;
-            assert_eq!(
-                Err(SwapError::InvalidSupply.into()),
-                accounts.initialize_swap()
-            );
+        let obj = SwapVersion::SwapV2(SwapV2 {
+            is_initialized: true,
+            nonce,
+            token_program_id,
+            pool_mint: *pool_mint_info.key,
+            pool_fee_account: *fee_account_info.key,
+            fees,
+            admin: SwapAdmin {
+                admin_authority: admin_info.map_or_else(Pubkey::default, |info| *info.key),
+            },
+            pool_status: PoolStatus::Active,
+            swap_curve,
+            token_count: token_count as u8,
+            tokens,
+        });
+        SwapVersion::pack(obj, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
 
-            // pool tokens already in circulation
-            accounts.pool_token_account = empty_pool_token_account;
-            assert_eq!(
-                Err(SwapError::InvalidSupply.into()),
-                accounts.initialize_swap()
-            );
+    /// Processes a [SwapMultiToken](enum.Instruction.html).
+    pub fn process_swap_multi_token(
+        program_id: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
 
-            accounts.pool_mint_account = old_mint;
-            accounts.pool_token_account = old_pool_account;
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
         }
+        let token_swap = SwapVersion::unpack_multi_token(&swap_info.data.borrow())?;
+        if !token_swap.pool_status.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        let swap_token_infos = next_account_infos(account_info_iter, token_swap.tokens().len())?;
+        Self::check_multi_token_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            swap_token_infos,
+            pool_mint_info,
+            token_program_info,
+            &[source_info, destination_info],
+            Some(pool_fee_account_info),
+        )?;
 
-        // pool fee account has wrong mint
-        {
-            let (_pool_fee_key, pool_fee_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &user_key,
-                0,
-            );
-            let old_account = accounts.pool_fee_account;
-            accounts.pool_fee_account = pool_fee_account;
-            assert_eq!(
-                Err(SwapError::IncorrectPoolMint.into()),
-                accounts.initialize_swap()
-            );
-            accounts.pool_fee_account = old_account;
+        let source_account = Self::unpack_token_account(source_info, &token_swap.token_program_id)?;
+        let destination_account =
+            Self::unpack_token_account(destination_info, &token_swap.token_program_id)?;
+        let source_index = token_swap
+            .token_index(&source_account.mint)
+            .ok_or(SwapError::IncorrectSwapAccount)?;
+        let destination_index = token_swap
+            .token_index(&destination_account.mint)
+            .ok_or(SwapError::IncorrectSwapAccount)?;
+        if source_index == destination_index {
+            return Err(SwapError::InvalidInput.into());
         }
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+        let mut balances =
+            Self::unpack_swap_balances(swap_token_infos, &token_swap.token_program_id)?;
 
-        // token A account is delegated
-        {
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_a_key,
-                    &user_key,
-                    &accounts.authority_key,
-                    &[],
-                    1,
-                )
-                .unwrap(),
-                vec![
-                    &mut accounts.token_a_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
+        let curve = token_swap.multi_token_curve()?;
+        let mut result = curve
+            .swap(
+                source_index,
+                destination_index,
+                to_u128(amount_in)?,
+                &balances,
+                &token_swap.fees,
             )
-            .unwrap();
-            assert_eq!(
-                Err(SwapError::InvalidDelegate.into()),
-                accounts.initialize_swap()
-            );
+            .ok_or(SwapError::ZeroTradingTokens)?;
+        if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
+            return Err(SwapError::ExceededSlippage.into());
+        }
 
-            do_process_instruction(
-                revoke(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_a_key,
-                    &accounts.authority_key,
-                    &[],
+        let received = to_u128(Self::token_transfer_received(
+            swap_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_token_infos[source_index].clone(),
+            user_transfer_authority_info.clone(),
+            token_swap.nonce,
+            to_u64(result.source_amount_swapped)?,
+        )?)?;
+        if received < result.source_amount_swapped {
+            result = curve
+                .swap(
+                    source_index,
+                    destination_index,
+                    received,
+                    &balances,
+                    &token_swap.fees,
                 )
-                .unwrap(),
-                vec![&mut accounts.token_a_account, &mut Account::default()],
-            )
-            .unwrap();
+                .ok_or(SwapError::ZeroTradingTokens)?;
+            // a swap priced again from a short transfer in pays out less
+            if result.destination_amount_swapped < to_u128(minimum_amount_out)? {
+                return Err(SwapError::ExceededSlippage.into());
+            }
         }
+        balances[source_index] = result.new_swap_source_amount;
+        balances[destination_index] = result.new_swap_destination_amount;
 
-        // token B account is delegated
-        {
-            do_process_instruction(
-                approve(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_b_key,
-                    &user_key,
-                    &accounts.authority_key,
-                    &[],
-                    1,
-                )
-                .unwrap(),
-                vec![
-                    &mut accounts.token_b_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                ],
+        let mut pool_token_amount = curve
+            .trading_tokens_to_pool_tokens(
+                source_index,
+                result.owner_fee,
+                &balances,
+                to_u128(pool_mint.supply)?,
+                RoundDirection::Ceiling,
+                &token_swap.fees,
             )
-            .unwrap();
-            assert_eq!(
-                Err(SwapError::InvalidDelegate.into()),
-                accounts.initialize_swap()
-            );
+            .ok_or(SwapError::FeeCalculationFailure)?;
 
-            do_process_instruction(
-                revoke(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_b_key,
-                    &accounts.authority_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_b_account, &mut Account::default()],
-            )
-            .unwrap();
+        if pool_token_amount > 0 {
+            // Allow error to fall through
+            if let Ok(host_fee_account_info) = next_account_info(account_info_iter) {
+                let host_fee_account = Self::unpack_token_account(
+                    host_fee_account_info,
+                    &token_swap.token_program_id,
+                )?;
+                if *pool_mint_info.key != host_fee_account.mint {
+                    return Err(SwapError::IncorrectPoolMint.into());
+                }
+                let host_fee = token_swap
+                    .fees
+                    .host_fee(pool_token_amount)
+                    .ok_or(SwapError::FeeCalculationFailure)?;
+                if host_fee > 0 {
+                    pool_token_amount = pool_token_amount
+                        .checked_sub(host_fee)
+                        .ok_or(SwapError::FeeCalculationFailure)?;
+                    Self::token_mint_to(
+                        swap_info.key,
+                        token_program_info.clone(),
+                        pool_mint_info.clone(),
+                        host_fee_account_info.clone(),
+                        authority_info.clone(),
+                        token_swap.nonce,
+                        to_u64(host_fee)?,
+                    )?;
+                }
+            }
+            Self::token_mint_to(
+                swap_info.key,
+                token_program_info.clone(),
+                pool_mint_info.clone(),
+                pool_fee_account_info.clone(),
+                authority_info.clone(),
+                token_swap.nonce,
+                to_u64(pool_token_amount)?,
+            )?;
         }
 
-        // token A account has close authority
-        {
-            do_process_instruction(
-                set_authority(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_a_key,
-                    Some(&user_key),
-                    AuthorityType::CloseAccount,
-                    &accounts.authority_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_a_account, &mut Account::default()],
-            )
-            .unwrap();
-            assert_eq!(
-                Err(SwapError::InvalidCloseAuthority.into()),
-                accounts.initialize_swap()
-            );
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_token_infos[destination_index].clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce,
+            to_u64(result.destination_amount_swapped)?,
+        )?;
 
-            do_process_instruction(
-                set_authority(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_a_key,
-                    None,
-                    AuthorityType::CloseAccount,
-                    &user_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_a_account, &mut Account::default()],
-            )
-            .unwrap();
-        }
+        Ok(())
+    }
 
-        // token B account has close authority
-        {
-            do_process_instruction(
-                set_authority(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_b_key,
-                    Some(&user_key),
-                    AuthorityType::CloseAccount,
-                    &accounts.authority_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_b_account, &mut Account::default()],
-            )
-            .unwrap();
-            assert_eq!(
-                Err(SwapError::InvalidCloseAuthority.into()),
-                accounts.initialize_swap()
-            );
+    /// Processes a [DepositAllMultiTokenTypes](enum.Instruction.html).
+    pub fn process_deposit_all_multi_token_types(
+        program_id: &Pubkey,
+        pool_token_amount: u64,
+        maximum_token_amounts: &[u64],
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let dest_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
 
-            do_process_instruction(
-                set_authority(
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.token_b_key,
-                    None,
-                    AuthorityType::CloseAccount,
-                    &user_key,
-                    &[],
-                )
-                .unwrap(),
-                vec![&mut accounts.token_b_account, &mut Account::default()],
-            )
-            .unwrap();
+        let token_swap = SwapVersion::unpack_multi_token(&swap_info.data.borrow())?;
+        if !token_swap.pool_status.allows_deposits() {
+            return Err(SwapError::PoolPaused.into());
         }
+        let token_count = token_swap.tokens().len();
+        if maximum_token_amounts.len() != token_count {
+            return Err(SwapError::IncorrectTokenCount.into());
+        }
+        let swap_token_infos = next_account_infos(account_info_iter, token_count)?;
+        let source_infos = next_account_infos(account_info_iter, token_count)?;
+        Self::check_multi_token_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            swap_token_infos,
+            pool_mint_info,
+            token_program_info,
+            &source_infos.iter().collect::<Vec<_>>(),
+            None,
+        )?;
 
-        // wrong token program id
+        let balances = Self::unpack_swap_balances(swap_token_infos, &token_swap.token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+        let token_amounts = pool_tokens_to_trading_tokens(
+            to_u128(pool_token_amount)?,
+            to_u128(pool_mint.supply)?,
+            &balances,
+            RoundDirection::Ceiling,
+        )
+        .ok_or(SwapError::ZeroTradingTokens)?;
+
+        for (token_amount, maximum_token_amount) in
+            token_amounts.iter().zip(maximum_token_amounts.iter())
         {
-            let wrong_program_id = Pubkey::new_unique();
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                do_process_instruction(
-                    initialize(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_program_id,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &accounts.pool_token_key,
-                        accounts.nonce,
-                        accounts.fees.clone(),
-                        accounts.swap_curve.clone(),
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut accounts.pool_token_account,
-                        &mut Account::default(),
-                    ],
-                )
-            );
+            let token_amount = to_u64(*token_amount)?;
+            if token_amount > *maximum_token_amount {
+                return Err(SwapError::ExceededSlippage.into());
+            }
+            if token_amount == 0 {
+                return Err(SwapError::ZeroTradingTokens.into());
+            }
         }
 
-        // create swap with same token A and B
+        let mut deposits = Vec::with_capacity(token_count);
+        for (((source_info, swap_token_info), token_amount), maximum_token_amount) in source_infos
+            .iter()
+            .zip(swap_token_infos.iter())
+            .zip(token_amounts.iter())
+            .zip(maximum_token_amounts.iter())
         {
-            let (_token_a_repeat_key, token_a_repeat_account) = mint_token(
-                &TOKEN_PROGRAM_ID,
-                &accounts.token_a_mint_key,
-                &mut accounts.token_a_mint_account,
-                &user_key,
-                &accounts.authority_key,
-                10,
-            );
-            let old_account = accounts.token_b_account;
-            accounts.token_b_account = token_a_repeat_account;
-            assert_eq!(
-                Err(SwapError::RepeatedMint.into()),
-                accounts.initialize_swap()
-            );
-            accounts.token_b_account = old_account;
+            let token_amount = to_u64(*token_amount)?;
+            let received = Self::token_transfer_received(
+                swap_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                swap_token_info.clone(),
+                user_transfer_authority_info.clone(),
+                token_swap.nonce,
+                token_amount,
+            )?;
+            deposits.push((received, token_amount, *maximum_token_amount));
         }
+        let pool_token_amount = Self::pool_tokens_received(to_u128(pool_token_amount)?, &deposits)?;
+        Self::token_mint_to(
+            swap_info.key,
+            token_program_info.clone(),
+            pool_mint_info.clone(),
+            dest_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce,
+            pool_token_amount,
+        )?;
 
-        // create valid swap
-        accounts.initialize_swap().unwrap();
+        Ok(())
+    }
 
-        // create invalid flat swap
-        {
-            let token_b_price = 0;
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let swap_curve = SwapCurve {
-                curve_type: CurveType::ConstantPrice,
-                calculator: Box::new(ConstantPriceCurve { token_b_price }),
-            };
-            let mut accounts =
-                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-            assert_eq!(
-                Err(SwapError::InvalidCurve.into()),
-                accounts.initialize_swap()
-            );
-        }
+    /// Processes a [WithdrawAllMultiTokenTypes](enum.Instruction.html).
+    pub fn process_withdraw_all_multi_token_types(
+        program_id: &Pubkey,
+        pool_token_amount: u64,
+        minimum_token_amounts: &[u64],
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
 
-        // create valid flat swap
-        {
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let token_b_price = 10_000;
-            let swap_curve = SwapCurve {
-                curve_type: CurveType::ConstantPrice,
-                calculator: Box::new(ConstantPriceCurve { token_b_price }),
-            };
-            let mut accounts =
-                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-            accounts.initialize_swap().unwrap();
+        let token_swap = SwapVersion::unpack_multi_token(&swap_info.data.borrow())?;
+        let token_count = token_swap.tokens().len();
+        if minimum_token_amounts.len() != token_count {
+            return Err(SwapError::IncorrectTokenCount.into());
         }
+        let swap_token_infos = next_account_infos(account_info_iter, token_count)?;
+        let destination_infos = next_account_infos(account_info_iter, token_count)?;
+        Self::check_multi_token_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            swap_token_infos,
+            pool_mint_info,
+            token_program_info,
+            &destination_infos.iter().collect::<Vec<_>>(),
+            Some(pool_fee_account_info),
+        )?;
 
-        // create invalid offset swap
+        let balances = Self::unpack_swap_balances(swap_token_infos, &token_swap.token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+
+        let withdraw_fee: u128 = if *pool_fee_account_info.key == *source_info.key {
+            // withdrawing from the fee account, don't assess withdraw fee
+            0
+        } else {
+            token_swap
+                .fees
+                .owner_withdraw_fee(to_u128(pool_token_amount)?)
+                .ok_or(SwapError::FeeCalculationFailure)?
+        };
+        let pool_token_amount = to_u128(pool_token_amount)?
+            .checked_sub(withdraw_fee)
+            .ok_or(SwapError::CalculationFailure)?;
+
+        let token_amounts = pool_tokens_to_trading_tokens(
+            pool_token_amount,
+            to_u128(pool_mint.supply)?,
+            &balances,
+            RoundDirection::Floor,
+        )
+        .ok_or(SwapError::ZeroTradingTokens)?;
+        for ((token_amount, minimum_token_amount), balance) in token_amounts
+            .iter()
+            .zip(minimum_token_amounts.iter())
+            .zip(balances.iter())
         {
-            let token_b_offset = 0;
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let swap_curve = SwapCurve {
-                curve_type: CurveType::Offset,
-                calculator: Box::new(OffsetCurve { token_b_offset }),
-            };
-            let mut accounts =
-                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-            assert_eq!(
-                Err(SwapError::InvalidCurve.into()),
-                accounts.initialize_swap()
-            );
+            let token_amount = to_u64(*token_amount)?;
+            if token_amount < *minimum_token_amount {
+                return Err(SwapError::ExceededSlippage.into());
+            }
+            if token_amount == 0 && *balance != 0 {
+                return Err(SwapError::ZeroTradingTokens.into());
+            }
         }
 
-        // create valid offset swap
-        {
-            let token_b_offset = 10;
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
+        if withdraw_fee > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                pool_fee_account_info.clone(),
+                user_transfer_authority_info.clone(),
+                token_swap.nonce,
+                to_u64(withdraw_fee)?,
+            )?;
+        }
+        Self::token_burn(
+            swap_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            pool_mint_info.clone(),
+            user_transfer_authority_info.clone(),
+            token_swap.nonce,
+            to_u64(pool_token_amount)?,
+        )?;
+
+        for ((swap_token_info, destination_info), (token_amount, balance)) in swap_token_infos
+            .iter()
+            .zip(destination_infos.iter())
+            .zip(token_amounts.iter().zip(balances.iter()))
+        {
+            let token_amount = std::cmp::min(*balance, *token_amount);
+            if token_amount > 0 {
+                Self::token_transfer(
+                    swap_info.key,
+                    token_program_info.clone(),
+                    swap_token_info.clone(),
+                    destination_info.clone(),
+                    authority_info.clone(),
+                    token_swap.nonce,
+                    to_u64(token_amount)?,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes a [DepositSingleMultiTokenTypeExactAmountIn](enum.Instruction.html).
+    pub fn process_deposit_single_multi_token_type_exact_amount_in(
+        program_id: &Pubkey,
+        source_token_amount: u64,
+        minimum_pool_token_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let token_swap = SwapVersion::unpack_multi_token(&swap_info.data.borrow())?;
+        if !token_swap.pool_status.allows_deposits() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        let swap_token_infos = next_account_infos(account_info_iter, token_swap.tokens().len())?;
+        Self::check_multi_token_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            swap_token_infos,
+            pool_mint_info,
+            token_program_info,
+            &[source_info],
+            None,
+        )?;
+
+        let source_account = Self::unpack_token_account(source_info, &token_swap.token_program_id)?;
+        let source_index = token_swap
+            .token_index(&source_account.mint)
+            .ok_or(SwapError::IncorrectSwapAccount)?;
+        let balances = Self::unpack_swap_balances(swap_token_infos, &token_swap.token_program_id)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+
+        let curve = token_swap.multi_token_curve()?;
+        let pool_tokens_for = |source_amount: u128| -> Result<u64, ProgramError> {
+            let pool_token_amount = curve
+                .trading_tokens_to_pool_tokens(
+                    source_index,
+                    source_amount,
+                    &balances,
+                    to_u128(pool_mint.supply)?,
+                    RoundDirection::Floor,
+                    &token_swap.fees,
+                )
+                .ok_or(SwapError::ZeroTradingTokens)?;
+            let pool_token_amount = to_u64(pool_token_amount)?;
+            if pool_token_amount < minimum_pool_token_amount {
+                return Err(SwapError::ExceededSlippage.into());
+            }
+            if pool_token_amount == 0 {
+                return Err(SwapError::ZeroTradingTokens.into());
+            }
+            Ok(pool_token_amount)
+        };
+        let mut pool_token_amount = pool_tokens_for(to_u128(source_token_amount)?)?;
+
+        let received = Self::token_transfer_received(
+            swap_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_token_infos[source_index].clone(),
+            user_transfer_authority_info.clone(),
+            token_swap.nonce,
+            source_token_amount,
+        )?;
+        if received < source_token_amount {
+            pool_token_amount = pool_tokens_for(to_u128(received)?)?;
+        }
+        Self::token_mint_to(
+            swap_info.key,
+            token_program_info.clone(),
+            pool_mint_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce,
+            pool_token_amount,
+        )?;
+
+        Ok(())
+    }
+
+    /// Processes a [WithdrawSingleMultiTokenTypeExactAmountOut](enum.Instruction.html).
+    pub fn process_withdraw_single_multi_token_type_exact_amount_out(
+        program_id: &Pubkey,
+        destination_token_amount: u64,
+        maximum_pool_token_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let pool_mint_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let pool_fee_account_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        let token_swap = SwapVersion::unpack_multi_token(&swap_info.data.borrow())?;
+        if !token_swap.pool_status.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        let swap_token_infos = next_account_infos(account_info_iter, token_swap.tokens().len())?;
+        Self::check_multi_token_accounts(
+            &token_swap,
+            program_id,
+            swap_info,
+            authority_info,
+            swap_token_infos,
+            pool_mint_info,
+            token_program_info,
+            &[destination_info],
+            Some(pool_fee_account_info),
+        )?;
+
+        let destination_account =
+            Self::unpack_token_account(destination_info, &token_swap.token_program_id)?;
+        let destination_index = token_swap
+            .token_index(&destination_account.mint)
+            .ok_or(SwapError::IncorrectSwapAccount)?;
+        let mut balances =
+            Self::unpack_swap_balances(swap_token_infos, &token_swap.token_program_id)?;
+        balances[destination_index] = balances[destination_index]
+            .checked_sub(to_u128(destination_token_amount)?)
+            .ok_or(SwapError::CalculationFailure)?;
+        let pool_mint = Self::unpack_mint(pool_mint_info, &token_swap.token_program_id)?;
+
+        let burn_pool_token_amount = token_swap
+            .multi_token_curve()?
+            .trading_tokens_to_pool_tokens(
+                destination_index,
+                to_u128(destination_token_amount)?,
+                &balances,
+                to_u128(pool_mint.supply)?,
+                RoundDirection::Ceiling,
+                &token_swap.fees,
+            )
+            .ok_or(SwapError::ZeroTradingTokens)?;
+
+        let withdraw_fee: u128 = if *pool_fee_account_info.key == *source_info.key {
+            // withdrawing from the fee account, don't assess withdraw fee
+            0
+        } else {
+            token_swap
+                .fees
+                .owner_withdraw_fee(burn_pool_token_amount)
+                .ok_or(SwapError::FeeCalculationFailure)?
+        };
+        let pool_token_amount = burn_pool_token_amount
+            .checked_add(withdraw_fee)
+            .ok_or(SwapError::CalculationFailure)?;
+
+        if to_u64(pool_token_amount)? > maximum_pool_token_amount {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        if pool_token_amount == 0 {
+            return Err(SwapError::ZeroTradingTokens.into());
+        }
+
+        if withdraw_fee > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                source_info.clone(),
+                pool_fee_account_info.clone(),
+                user_transfer_authority_info.clone(),
+                token_swap.nonce,
+                to_u64(withdraw_fee)?,
+            )?;
+        }
+        Self::token_burn(
+            swap_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            pool_mint_info.clone(),
+            user_transfer_authority_info.clone(),
+            token_swap.nonce,
+            to_u64(burn_pool_token_amount)?,
+        )?;
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_token_infos[destination_index].clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            token_swap.nonce,
+            destination_token_amount,
+        )?;
+
+        Ok(())
+    }
+
+    /// Checks the accounts of a concentrated liquidity pool
+    #[allow(clippy::too_many_arguments)]
+    fn check_concentrated_accounts(
+        pool: &ConcentratedPool,
+        program_id: &Pubkey,
+        swap_account_info: &AccountInfo,
+        authority_info: &AccountInfo,
+        token_a_info: &AccountInfo,
+        token_b_info: &AccountInfo,
+        token_program_info: &AccountInfo,
+        user_token_infos: &[&AccountInfo],
+    ) -> ProgramResult {
+        if swap_account_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if *authority_info.key != Self::authority_id(program_id, swap_account_info.key, pool.nonce)?
+        {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *token_a_info.key != pool.token_a {
+            return Err(SwapError::IncorrectSwapAccount.into());
+        }
+        if *token_b_info.key != pool.token_b {
+            return Err(SwapError::IncorrectSwapAccount.into());
+        }
+        if *token_program_info.key != pool.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        for user_token_info in user_token_infos.iter() {
+            if user_token_info.key == token_a_info.key || user_token_info.key == token_b_info.key {
+                return Err(SwapError::InvalidInput.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Unpacks a position of a concentrated liquidity pool, checking that it
+    /// is held by the given signing owner
+    fn unpack_position(
+        program_id: &Pubkey,
+        swap_account_info: &AccountInfo,
+        position_info: &AccountInfo,
+        owner_info: &AccountInfo,
+    ) -> Result<Position, ProgramError> {
+        if position_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if !owner_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let position = Position::unpack(&position_info.data.borrow())?;
+        if position.pool != *swap_account_info.key || position.owner != *owner_info.key {
+            return Err(SwapError::IncorrectPosition.into());
+        }
+        Ok(position)
+    }
+
+    /// Processes an [InitializeConcentrated](enum.Instruction.html).
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_initialize_concentrated(
+        program_id: &Pubkey,
+        nonce: u8,
+        fees: Fees,
+        tick_spacing: u16,
+        min_position_liquidity: u128,
+        initial_sqrt_price: u128,
+        accounts: &[AccountInfo],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter).ok();
+
+        let token_program_id = *token_program_info.key;
+        if SwapVersion::is_initialized(&swap_info.data.borrow()) {
+            return Err(SwapError::AlreadyInUse.into());
+        }
+        // the admin must agree to take control of the pool
+        if admin_info.map_or(false, |info| !info.is_signer) {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if *authority_info.key != Self::authority_id(program_id, swap_info.key, nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        let token_a = Self::unpack_token_account(token_a_info, &token_program_id)?;
+        let token_b = Self::unpack_token_account(token_b_info, &token_program_id)?;
+        if *authority_info.key != token_a.owner {
+            return Err(SwapError::InvalidOwner.into());
+        }
+        if *authority_info.key != token_b.owner {
+            return Err(SwapError::InvalidOwner.into());
+        }
+        if token_a.mint == token_b.mint {
+            return Err(SwapError::RepeatedMint.into());
+        }
+        if token_a.delegate.is_some() {
+            return Err(SwapError::InvalidDelegate.into());
+        }
+        if token_b.delegate.is_some() {
+            return Err(SwapError::InvalidDelegate.into());
+        }
+        if token_a.close_authority.is_some() {
+            return Err(SwapError::InvalidCloseAuthority.into());
+        }
+        if token_b.close_authority.is_some() {
+            return Err(SwapError::InvalidCloseAuthority.into());
+        }
+
+        // there are no pool tokens, so only the trade fee can be charged
+        if fees.owner_trade_fee_numerator != 0
+            || fees.owner_withdraw_fee_numerator != 0
+            || fees.host_fee_numerator != 0
+        {
+            return Err(SwapError::InvalidFee.into());
+        }
+        if let Some(swap_constraints) = swap_constraints {
+            swap_constraints.validate_trade_fee(&fees)?;
+        }
+        fees.validate()?;
+        if tick_spacing == 0 || tick_spacing > MAX_TICK_SPACING {
+            return Err(SwapError::InvalidTick.into());
+        }
+        if min_position_liquidity == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let tick_current = tick_at_sqrt_price(initial_sqrt_price).ok_or(SwapError::InvalidInput)?;
+
+        let obj = ConcentratedPool {
+            is_initialized: true,
+            nonce,
+            token_program_id,
+            token_a: *token_a_info.key,
+            token_b: *token_b_info.key,
+            token_a_mint: token_a.mint,
+            token_b_mint: token_b.mint,
+            fees,
+            admin: SwapAdmin {
+                admin_authority: admin_info.map_or_else(Pubkey::default, |info| *info.key),
+            },
+            tick_spacing,
+            tick_lower_bound: tick_lower_bound(tick_current, tick_spacing),
+            min_position_liquidity,
+            sqrt_price: initial_sqrt_price,
+            tick_current,
+            ..ConcentratedPool::default()
+        };
+        SwapVersion::pack_concentrated(obj, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [OpenPosition](enum.Instruction.html).
+    pub fn process_open_position(
+        program_id: &Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id || position_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if !owner_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let pool = SwapVersion::unpack_concentrated(&swap_info.data.borrow())?;
+        let position = Position::unpack_unchecked(&position_info.data.borrow())?;
+        if position.is_initialized {
+            return Err(SwapError::AlreadyInUse.into());
+        }
+        if tick_lower >= tick_upper {
+            return Err(SwapError::InvalidTick.into());
+        }
+        pool.validate_tick(tick_lower)?;
+        pool.validate_tick(tick_upper)?;
+
+        let obj = Position {
+            is_initialized: true,
+            pool: *swap_info.key,
+            owner: *owner_info.key,
+            tick_lower,
+            tick_upper,
+            ..Position::default()
+        };
+        Position::pack(obj, &mut position_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [IncreaseLiquidity](enum.Instruction.html).
+    pub fn process_increase_liquidity(
+        program_id: &Pubkey,
+        liquidity: u128,
+        maximum_token_a_amount: u64,
+        maximum_token_b_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_a_info = next_account_info(account_info_iter)?;
+        let source_b_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut pool = SwapVersion::unpack_concentrated(&swap_info.data.borrow())?;
+        if !pool.pool_status.allows_deposits() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        Self::check_concentrated_accounts(
+            &pool,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+            &[source_a_info, source_b_info],
+        )?;
+        let mut position = Self::unpack_position(program_id, swap_info, position_info, owner_info)?;
+
+        if liquidity == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let liquidity_delta =
+            i128::try_from(liquidity).map_err(|_| SwapError::ConversionFailure)?;
+        let amounts = pool.modify_position(&mut position, liquidity_delta)?;
+        let token_a_amount = to_u64(amounts.token_a_amount)?;
+        if token_a_amount > maximum_token_a_amount {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        let token_b_amount = to_u64(amounts.token_b_amount)?;
+        if token_b_amount > maximum_token_b_amount {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+
+        if token_a_amount > 0 {
+            let received = Self::token_transfer_received(
+                swap_info.key,
+                token_program_info.clone(),
+                source_a_info.clone(),
+                token_a_info.clone(),
+                user_transfer_authority_info.clone(),
+                pool.nonce,
+                token_a_amount,
+            )?;
+            if received < token_a_amount {
+                return Err(SwapError::ExceededSlippage.into());
+            }
+        }
+        if token_b_amount > 0 {
+            let received = Self::token_transfer_received(
+                swap_info.key,
+                token_program_info.clone(),
+                source_b_info.clone(),
+                token_b_info.clone(),
+                user_transfer_authority_info.clone(),
+                pool.nonce,
+                token_b_amount,
+            )?;
+            if received < token_b_amount {
+                return Err(SwapError::ExceededSlippage.into());
+            }
+        }
+
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        SwapVersion::pack_concentrated(pool, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [DecreaseLiquidity](enum.Instruction.html).
+    pub fn process_decrease_liquidity(
+        program_id: &Pubkey,
+        liquidity: u128,
+        minimum_token_a_amount: u64,
+        minimum_token_b_amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let dest_token_a_info = next_account_info(account_info_iter)?;
+        let dest_token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut pool = SwapVersion::unpack_concentrated(&swap_info.data.borrow())?;
+        Self::check_concentrated_accounts(
+            &pool,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+            &[dest_token_a_info, dest_token_b_info],
+        )?;
+        let mut position = Self::unpack_position(program_id, swap_info, position_info, owner_info)?;
+
+        if liquidity == 0 || liquidity > position.liquidity {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let liquidity_delta = i128::try_from(liquidity)
+            .map_err(|_| SwapError::ConversionFailure)?
+            .checked_neg()
+            .ok_or(SwapError::ConversionFailure)?;
+        let amounts = pool.modify_position(&mut position, liquidity_delta)?;
+        let token_a_amount = to_u64(amounts.token_a_amount)?;
+        if token_a_amount < minimum_token_a_amount {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        let token_b_amount = to_u64(amounts.token_b_amount)?;
+        if token_b_amount < minimum_token_b_amount {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+
+        if token_a_amount > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                token_a_info.clone(),
+                dest_token_a_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_a_amount,
+            )?;
+        }
+        if token_b_amount > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                token_b_info.clone(),
+                dest_token_b_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_b_amount,
+            )?;
+        }
+
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        SwapVersion::pack_concentrated(pool, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [CollectFees](enum.Instruction.html).
+    pub fn process_collect_fees(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let position_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let token_a_info = next_account_info(account_info_iter)?;
+        let token_b_info = next_account_info(account_info_iter)?;
+        let dest_token_a_info = next_account_info(account_info_iter)?;
+        let dest_token_b_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut pool = SwapVersion::unpack_concentrated(&swap_info.data.borrow())?;
+        Self::check_concentrated_accounts(
+            &pool,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+            &[dest_token_a_info, dest_token_b_info],
+        )?;
+        let mut position = Self::unpack_position(program_id, swap_info, position_info, owner_info)?;
+
+        // credit the fees earned since the last change to the position
+        pool.modify_position(&mut position, 0)?;
+        let token_a_amount = position.tokens_owed_a;
+        let token_b_amount = position.tokens_owed_b;
+        position.tokens_owed_a = 0;
+        position.tokens_owed_b = 0;
+
+        if token_a_amount > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                token_a_info.clone(),
+                dest_token_a_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_a_amount,
+            )?;
+        }
+        if token_b_amount > 0 {
+            Self::token_transfer(
+                swap_info.key,
+                token_program_info.clone(),
+                token_b_info.clone(),
+                dest_token_b_info.clone(),
+                authority_info.clone(),
+                pool.nonce,
+                token_b_amount,
+            )?;
+        }
+
+        Position::pack(position, &mut position_info.data.borrow_mut())?;
+        SwapVersion::pack_concentrated(pool, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [SwapConcentrated](enum.Instruction.html).
+    pub fn process_swap_concentrated(
+        program_id: &Pubkey,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let swap_source_info = next_account_info(account_info_iter)?;
+        let swap_destination_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mut pool = SwapVersion::unpack_concentrated(&swap_info.data.borrow())?;
+        if !pool.pool_status.allows_swaps() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        let a_to_b = *swap_source_info.key == pool.token_a;
+        let (token_a_info, token_b_info) = if a_to_b {
+            (swap_source_info, swap_destination_info)
+        } else {
+            (swap_destination_info, swap_source_info)
+        };
+        Self::check_concentrated_accounts(
+            &pool,
+            program_id,
+            swap_info,
+            authority_info,
+            token_a_info,
+            token_b_info,
+            token_program_info,
+            &[source_info, destination_info],
+        )?;
+
+        let result = pool.swap(to_u128(amount_in)?, a_to_b)?;
+        if result.amount_out == 0 {
+            return Err(SwapError::ZeroTradingTokens.into());
+        }
+        if result.amount_out < to_u128(minimum_amount_out)? {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+
+        // the pool has already moved its price by the full amount in, so a
+        // short transfer cannot be priced again and is refused instead
+        let amount_in = to_u64(result.amount_in)?;
+        let received = Self::token_transfer_received(
+            swap_info.key,
+            token_program_info.clone(),
+            source_info.clone(),
+            swap_source_info.clone(),
+            user_transfer_authority_info.clone(),
+            pool.nonce,
+            amount_in,
+        )?;
+        if received < amount_in {
+            return Err(SwapError::ExceededSlippage.into());
+        }
+        Self::token_transfer(
+            swap_info.key,
+            token_program_info.clone(),
+            swap_destination_info.clone(),
+            destination_info.clone(),
+            authority_info.clone(),
+            pool.nonce,
+            to_u64(result.amount_out)?,
+        )?;
+
+        SwapVersion::pack_concentrated(pool, &mut swap_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Checks that a token account is owned by the farm authority and cannot
+    /// be moved by anyone else, as the vaults of farms must be.  The swap
+    /// authority signs for users of the pool, so it must never own a vault.
+    fn check_farm_vault(
+        program_id: &Pubkey,
+        farm_info: &AccountInfo,
+        nonce: u8,
+        token_swap: &SwapV1,
+        vault_info: &AccountInfo,
+    ) -> Result<spl_token::state::Account, ProgramError> {
+        let vault = Self::unpack_token_account(vault_info, &token_swap.token_program_id)?;
+        if vault.owner != Self::authority_id(program_id, farm_info.key, nonce)? {
+            return Err(SwapError::InvalidOwner.into());
+        }
+        if vault.delegate.is_some() {
+            return Err(SwapError::InvalidDelegate.into());
+        }
+        if vault.close_authority.is_some() {
+            return Err(SwapError::InvalidCloseAuthority.into());
+        }
+        Ok(vault)
+    }
+
+    /// Unpacks a farm, checking that it pays rewards for the given swap
+    fn unpack_farm(
+        program_id: &Pubkey,
+        swap_info: &AccountInfo,
+        farm_info: &AccountInfo,
+    ) -> Result<Farm, ProgramError> {
+        if farm_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let farm = Farm::unpack(&farm_info.data.borrow())?;
+        if farm.swap != *swap_info.key {
+            return Err(SwapError::IncorrectFarm.into());
+        }
+        Ok(farm)
+    }
+
+    /// Unpacks a stake in a farm, checking that it is held by the given
+    /// signing owner
+    fn unpack_farm_stake(
+        program_id: &Pubkey,
+        farm_info: &AccountInfo,
+        stake_info: &AccountInfo,
+        owner_info: &AccountInfo,
+    ) -> Result<FarmStake, ProgramError> {
+        if stake_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if !owner_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let stake = FarmStake::unpack(&stake_info.data.borrow())?;
+        if stake.farm != *farm_info.key || stake.owner != *owner_info.key {
+            return Err(SwapError::IncorrectFarm.into());
+        }
+        Ok(stake)
+    }
+
+    /// Processes an [InitializeFarm](enum.Instruction.html).
+    pub fn process_initialize_farm(
+        program_id: &Pubkey,
+        nonce: u8,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let farm_info = next_account_info(account_info_iter)?;
+        let farm_authority_info = next_account_info(account_info_iter)?;
+        let stake_vault_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id || farm_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
+        let farm = Farm::unpack_unchecked(&farm_info.data.borrow())?;
+        if farm.is_initialized {
+            return Err(SwapError::AlreadyInUse.into());
+        }
+        if *farm_authority_info.key != Self::authority_id(program_id, farm_info.key, nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        let stake_vault =
+            Self::check_farm_vault(program_id, farm_info, nonce, &token_swap, stake_vault_info)?;
+        if stake_vault.mint != token_swap.pool_mint {
+            return Err(SwapError::IncorrectPoolMint.into());
+        }
+
+        let obj = Farm {
+            is_initialized: true,
+            nonce,
+            swap: *swap_info.key,
+            stake_vault: *stake_vault_info.key,
+            last_update_slot: Clock::get()?.slot,
+            ..Farm::default()
+        };
+        Farm::pack(obj, &mut farm_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [AddFarmReward](enum.Instruction.html).
+    pub fn process_add_farm_reward(
+        program_id: &Pubkey,
+        reward_per_slot: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let farm_info = next_account_info(account_info_iter)?;
+        let reward_vault_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
+        let mut farm = Self::unpack_farm(program_id, swap_info, farm_info)?;
+        // rewards must never be paid out of the pool reserves or staked pool
+        // tokens
+        if *reward_vault_info.key == token_swap.token_a
+            || *reward_vault_info.key == token_swap.token_b
+        {
+            return Err(SwapError::InvalidInput.into());
+        }
+        let reward_vault = Self::check_farm_vault(
+            program_id,
+            farm_info,
+            farm.nonce,
+            &token_swap,
+            reward_vault_info,
+        )?;
+        if reward_vault.mint == token_swap.pool_mint
+            || farm
+                .active_rewards()
+                .iter()
+                .any(|reward| reward.vault == *reward_vault_info.key)
+        {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        farm.update(Clock::get()?.slot);
+        farm.add_reward(*reward_vault_info.key, reward_per_slot)?;
+        Farm::pack(farm, &mut farm_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [SetFarmRewardRate](enum.Instruction.html).
+    pub fn process_set_farm_reward_rate(
+        program_id: &Pubkey,
+        reward_index: u8,
+        reward_per_slot: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let farm_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        Self::check_admin(swap_info, admin_info)?;
+        let mut farm = Self::unpack_farm(program_id, swap_info, farm_info)?;
+        if reward_index >= farm.reward_count {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        farm.update(Clock::get()?.slot);
+        farm.rewards[reward_index as usize].reward_per_slot = reward_per_slot;
+        Farm::pack(farm, &mut farm_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [Stake](enum.Instruction.html).
+    pub fn process_stake(
+        program_id: &Pubkey,
+        amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let farm_info = next_account_info(account_info_iter)?;
+        let stake_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let source_info = next_account_info(account_info_iter)?;
+        let stake_vault_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id || stake_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_swap_v1(&swap_info.data.borrow())?;
+        if !Self::pool_status(swap_info)?.allows_orders() {
+            return Err(SwapError::PoolPaused.into());
+        }
+        let mut farm = Self::unpack_farm(program_id, swap_info, farm_info)?;
+        if *stake_vault_info.key != farm.stake_vault {
+            return Err(SwapError::IncorrectFarm.into());
+        }
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        if !owner_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        let mut stake = FarmStake::unpack_unchecked(&stake_info.data.borrow())?;
+        if !stake.is_initialized {
+            stake = FarmStake {
+                is_initialized: true,
+                farm: *farm_info.key,
+                owner: *owner_info.key,
+                ..FarmStake::default()
+            };
+        } else if stake.farm != *farm_info.key || stake.owner != *owner_info.key {
+            return Err(SwapError::IncorrectFarm.into());
+        }
+        if amount == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        // pool tokens held by the swap authority, like those of the pool's
+        // own accounts, can never be staked
+        let source = Self::unpack_token_account(source_info, &token_swap.token_program_id)?;
+        if source.owner == Self::authority_id(program_id, swap_info.key, token_swap.nonce)? {
+            return Err(SwapError::InvalidOwner.into());
+        }
+
+        farm.update(Clock::get()?.slot);
+        farm.stake(&mut stake, amount)?;
+        Self::user_token_transfer(
+            token_program_info.clone(),
+            source_info.clone(),
+            stake_vault_info.clone(),
+            user_transfer_authority_info.clone(),
+            amount,
+        )?;
+
+        FarmStake::pack(stake, &mut stake_info.data.borrow_mut())?;
+        Farm::pack(farm, &mut farm_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [Unstake](enum.Instruction.html).
+    pub fn process_unstake(
+        program_id: &Pubkey,
+        amount: u64,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let farm_authority_info = next_account_info(account_info_iter)?;
+        let farm_info = next_account_info(account_info_iter)?;
+        let stake_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let stake_vault_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_migrated_swap_v1(&swap_info.data.borrow())?;
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        let mut farm = Self::unpack_farm(program_id, swap_info, farm_info)?;
+        if *farm_authority_info.key != Self::authority_id(program_id, farm_info.key, farm.nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        if *stake_vault_info.key != farm.stake_vault {
+            return Err(SwapError::IncorrectFarm.into());
+        }
+        let mut stake = Self::unpack_farm_stake(program_id, farm_info, stake_info, owner_info)?;
+        if amount == 0 {
+            return Err(SwapError::InvalidInput.into());
+        }
+
+        farm.update(Clock::get()?.slot);
+        farm.unstake(&mut stake, amount)?;
+        Self::token_transfer(
+            farm_info.key,
+            token_program_info.clone(),
+            stake_vault_info.clone(),
+            destination_info.clone(),
+            farm_authority_info.clone(),
+            farm.nonce,
+            amount,
+        )?;
+
+        FarmStake::pack(stake, &mut stake_info.data.borrow_mut())?;
+        Farm::pack(farm, &mut farm_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [ClaimFarmRewards](enum.Instruction.html).
+    pub fn process_claim_farm_rewards(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let swap_info = next_account_info(account_info_iter)?;
+        let farm_authority_info = next_account_info(account_info_iter)?;
+        let farm_info = next_account_info(account_info_iter)?;
+        let stake_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let reward_infos = account_info_iter.as_slice();
+
+        if swap_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let token_swap = SwapVersion::unpack_migrated_swap_v1(&swap_info.data.borrow())?;
+        if *token_program_info.key != token_swap.token_program_id {
+            return Err(SwapError::IncorrectTokenProgramId.into());
+        }
+        let mut farm = Self::unpack_farm(program_id, swap_info, farm_info)?;
+        if *farm_authority_info.key != Self::authority_id(program_id, farm_info.key, farm.nonce)? {
+            return Err(SwapError::InvalidProgramAddress.into());
+        }
+        let mut stake = Self::unpack_farm_stake(program_id, farm_info, stake_info, owner_info)?;
+        if reward_infos.len() != 2 * farm.active_rewards().len() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        farm.update(Clock::get()?.slot);
+        farm.credit_rewards(&mut stake);
+        for (index, (reward, infos)) in farm
+            .active_rewards()
+            .iter()
+            .zip(reward_infos.chunks(2))
+            .enumerate()
+        {
+            let (vault_info, destination_info) = (&infos[0], &infos[1]);
+            if *vault_info.key != reward.vault {
+                return Err(SwapError::IncorrectFarm.into());
+            }
+            // pay what the vault holds, leaving the rest owed until it is
+            // funded again
+            let vault = Self::unpack_token_account(vault_info, &token_swap.token_program_id)?;
+            let amount = std::cmp::min(stake.rewards_owed[index], vault.amount);
+            if amount > 0 {
+                Self::token_transfer(
+                    farm_info.key,
+                    token_program_info.clone(),
+                    vault_info.clone(),
+                    destination_info.clone(),
+                    farm_authority_info.clone(),
+                    farm.nonce,
+                    amount,
+                )?;
+                stake.rewards_owed[index] -= amount;
+            }
+        }
+
+        FarmStake::pack(stake, &mut stake_info.data.borrow_mut())?;
+        Farm::pack(farm, &mut farm_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes an [Instruction](enum.Instruction.html).
+    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+        Self::process_with_constraints(program_id, accounts, input, &SWAP_CONSTRAINTS)
+    }
+
+    /// Processes an instruction given extra constraint
+    pub fn process_with_constraints(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        input: &[u8],
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        let instruction = SwapInstruction::unpack(input)?;
+        match instruction {
+            SwapInstruction::Initialize(Initialize {
+                nonce,
+                fees,
+                swap_curve,
+            }) => {
+                msg!("Instruction: Init");
+                Self::process_initialize(
+                    program_id,
+                    nonce,
+                    fees,
+                    swap_curve,
+                    accounts,
+                    swap_constraints,
+                )
+            }
+            SwapInstruction::Swap(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: Swap");
+                Self::process_swap(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            SwapInstruction::SwapExactOut(SwapExactOut {
+                amount_out,
+                maximum_amount_in,
+            }) => {
+                msg!("Instruction: SwapExactOut");
+                Self::process_swap_exact_out(program_id, amount_out, maximum_amount_in, accounts)
+            }
+            SwapInstruction::RouteSwap(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: RouteSwap");
+                Self::process_route_swap(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            SwapInstruction::DepositAllTokenTypes(DepositAllTokenTypes {
+                pool_token_amount,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                msg!("Instruction: DepositAllTokenTypes");
+                Self::process_deposit_all_token_types(
+                    program_id,
+                    pool_token_amount,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::WithdrawAllTokenTypes(WithdrawAllTokenTypes {
+                pool_token_amount,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                msg!("Instruction: WithdrawAllTokenTypes");
+                Self::process_withdraw_all_token_types(
+                    program_id,
+                    pool_token_amount,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::DepositSingleTokenTypeExactAmountIn(
+                DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                },
+            ) => {
+                msg!("Instruction: DepositSingleTokenTypeExactAmountIn");
+                Self::process_deposit_single_token_type_exact_amount_in(
+                    program_id,
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::WithdrawSingleTokenTypeExactAmountOut(
+                WithdrawSingleTokenTypeExactAmountOut {
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                },
+            ) => {
+                msg!("Instruction: WithdrawSingleTokenTypeExactAmountOut");
+                Self::process_withdraw_single_token_type_exact_amount_out(
+                    program_id,
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::InitializeMultiToken(InitializeMultiToken {
+                nonce,
+                fees,
+                swap_curve,
+                weights,
+            }) => {
+                msg!("Instruction: InitializeMultiToken");
+                Self::process_initialize_multi_token(
+                    program_id,
+                    nonce,
+                    fees,
+                    swap_curve,
+                    weights,
+                    accounts,
+                    swap_constraints,
+                )
+            }
+            SwapInstruction::SwapMultiToken(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: SwapMultiToken");
+                Self::process_swap_multi_token(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            SwapInstruction::DepositAllMultiTokenTypes(DepositAllMultiTokenTypes {
+                pool_token_amount,
+                maximum_token_amounts,
+            }) => {
+                msg!("Instruction: DepositAllMultiTokenTypes");
+                Self::process_deposit_all_multi_token_types(
+                    program_id,
+                    pool_token_amount,
+                    &maximum_token_amounts,
+                    accounts,
+                )
+            }
+            SwapInstruction::WithdrawAllMultiTokenTypes(WithdrawAllMultiTokenTypes {
+                pool_token_amount,
+                minimum_token_amounts,
+            }) => {
+                msg!("Instruction: WithdrawAllMultiTokenTypes");
+                Self::process_withdraw_all_multi_token_types(
+                    program_id,
+                    pool_token_amount,
+                    &minimum_token_amounts,
+                    accounts,
+                )
+            }
+            SwapInstruction::DepositSingleMultiTokenTypeExactAmountIn(
+                DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                },
+            ) => {
+                msg!("Instruction: DepositSingleMultiTokenTypeExactAmountIn");
+                Self::process_deposit_single_multi_token_type_exact_amount_in(
+                    program_id,
+                    source_token_amount,
+                    minimum_pool_token_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::WithdrawSingleMultiTokenTypeExactAmountOut(
+                WithdrawSingleTokenTypeExactAmountOut {
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                },
+            ) => {
+                msg!("Instruction: WithdrawSingleMultiTokenTypeExactAmountOut");
+                Self::process_withdraw_single_multi_token_type_exact_amount_out(
+                    program_id,
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::InitializeConcentrated(InitializeConcentrated {
+                nonce,
+                fees,
+                tick_spacing,
+                min_position_liquidity,
+                initial_sqrt_price,
+            }) => {
+                msg!("Instruction: InitializeConcentrated");
+                Self::process_initialize_concentrated(
+                    program_id,
+                    nonce,
+                    fees,
+                    tick_spacing,
+                    min_position_liquidity,
+                    initial_sqrt_price,
+                    accounts,
+                    swap_constraints,
+                )
+            }
+            SwapInstruction::OpenPosition(OpenPosition {
+                tick_lower,
+                tick_upper,
+            }) => {
+                msg!("Instruction: OpenPosition");
+                Self::process_open_position(program_id, tick_lower, tick_upper, accounts)
+            }
+            SwapInstruction::IncreaseLiquidity(IncreaseLiquidity {
+                liquidity,
+                maximum_token_a_amount,
+                maximum_token_b_amount,
+            }) => {
+                msg!("Instruction: IncreaseLiquidity");
+                Self::process_increase_liquidity(
+                    program_id,
+                    liquidity,
+                    maximum_token_a_amount,
+                    maximum_token_b_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::DecreaseLiquidity(DecreaseLiquidity {
+                liquidity,
+                minimum_token_a_amount,
+                minimum_token_b_amount,
+            }) => {
+                msg!("Instruction: DecreaseLiquidity");
+                Self::process_decrease_liquidity(
+                    program_id,
+                    liquidity,
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                    accounts,
+                )
+            }
+            SwapInstruction::CollectFees => {
+                msg!("Instruction: CollectFees");
+                Self::process_collect_fees(program_id, accounts)
+            }
+            SwapInstruction::SwapConcentrated(Swap {
+                amount_in,
+                minimum_amount_out,
+            }) => {
+                msg!("Instruction: SwapConcentrated");
+                Self::process_swap_concentrated(program_id, amount_in, minimum_amount_out, accounts)
+            }
+            SwapInstruction::UpdateFees(UpdateFees { fees }) => {
+                msg!("Instruction: UpdateFees");
+                Self::process_update_fees(program_id, fees, accounts, swap_constraints)
+            }
+            SwapInstruction::SetFeeAccount => {
+                msg!("Instruction: SetFeeAccount");
+                Self::process_set_fee_account(program_id, accounts, swap_constraints)
+            }
+            SwapInstruction::SetAdminAuthority => {
+                msg!("Instruction: SetAdminAuthority");
+                Self::process_set_admin_authority(program_id, accounts)
+            }
+            SwapInstruction::RampA(RampA {
+                target_amp,
+                stop_ramp_ts,
+            }) => {
+                msg!("Instruction: RampA");
+                Self::process_ramp_a(program_id, target_amp, stop_ramp_ts, accounts)
+            }
+            SwapInstruction::StopRampA => {
+                msg!("Instruction: StopRampA");
+                Self::process_stop_ramp_a(program_id, accounts)
+            }
+            SwapInstruction::SetPoolStatus(SetPoolStatus { status }) => {
+                msg!("Instruction: SetPoolStatus");
+                Self::process_set_pool_status(program_id, status, accounts)
+            }
+            SwapInstruction::FlashLoan(FlashLoan { amount }) => {
+                msg!("Instruction: FlashLoan");
+                Self::process_flash_loan(program_id, amount, accounts)
+            }
+            SwapInstruction::Quote(Quote {
+                operation,
+                amount,
+                trade_direction,
+            }) => {
+                msg!("Instruction: Quote");
+                Self::process_quote(program_id, operation, amount, trade_direction, accounts)
+            }
+            SwapInstruction::InitializeFarm(InitializeFarm { nonce }) => {
+                msg!("Instruction: InitializeFarm");
+                Self::process_initialize_farm(program_id, nonce, accounts)
+            }
+            SwapInstruction::AddFarmReward(AddFarmReward { reward_per_slot }) => {
+                msg!("Instruction: AddFarmReward");
+                Self::process_add_farm_reward(program_id, reward_per_slot, accounts)
+            }
+            SwapInstruction::SetFarmRewardRate(SetFarmRewardRate {
+                reward_index,
+                reward_per_slot,
+            }) => {
+                msg!("Instruction: SetFarmRewardRate");
+                Self::process_set_farm_reward_rate(
+                    program_id,
+                    reward_index,
+                    reward_per_slot,
+                    accounts,
+                )
+            }
+            SwapInstruction::Stake(Stake { amount }) => {
+                msg!("Instruction: Stake");
+                Self::process_stake(program_id, amount, accounts)
+            }
+            SwapInstruction::Unstake(Unstake { amount }) => {
+                msg!("Instruction: Unstake");
+                Self::process_unstake(program_id, amount, accounts)
+            }
+            SwapInstruction::ClaimFarmRewards => {
+                msg!("Instruction: ClaimFarmRewards");
+                Self::process_claim_farm_rewards(program_id, accounts)
+            }
+            SwapInstruction::SetDynamicFee(instruction) => {
+                msg!("Instruction: SetDynamicFee");
+                Self::process_set_dynamic_fee(program_id, instruction, accounts, swap_constraints)
+            }
+            SwapInstruction::SetAllowlist(SetAllowlist { gate_swaps }) => {
+                msg!("Instruction: SetAllowlist");
+                Self::process_set_allowlist(program_id, gate_swaps, accounts)
+            }
+            SwapInstruction::AddAllowlistMember => {
+                msg!("Instruction: AddAllowlistMember");
+                Self::process_add_allowlist_member(program_id, accounts)
+            }
+            SwapInstruction::RemoveAllowlistMember => {
+                msg!("Instruction: RemoveAllowlistMember");
+                Self::process_remove_allowlist_member(program_id, accounts)
+            }
+            SwapInstruction::MigrateSwap(MigrateSwap { nonce }) => {
+                msg!("Instruction: MigrateSwap");
+                Self::process_migrate_swap(program_id, nonce, accounts, swap_constraints)
+            }
+            SwapInstruction::CreateLimitOrder(instruction) => {
+                msg!("Instruction: CreateLimitOrder");
+                Self::process_create_limit_order(program_id, instruction, accounts)
+            }
+            SwapInstruction::FillLimitOrder => {
+                msg!("Instruction: FillLimitOrder");
+                Self::process_fill_limit_order(program_id, accounts)
+            }
+            SwapInstruction::CancelLimitOrder => {
+                msg!("Instruction: CancelLimitOrder");
+                Self::process_cancel_limit_order(program_id, accounts)
+            }
+            SwapInstruction::SetFeeSplit(SetFeeSplit { shares }) => {
+                msg!("Instruction: SetFeeSplit");
+                Self::process_set_fee_split(program_id, shares, accounts)
+            }
+            SwapInstruction::DistributeFees => {
+                msg!("Instruction: DistributeFees");
+                Self::process_distribute_fees(program_id, accounts)
+            }
+            SwapInstruction::SetFlashLoanFee(instruction) => {
+                msg!("Instruction: SetFlashLoanFee");
+                Self::process_set_flash_loan_fee(
+                    program_id,
+                    instruction,
+                    accounts,
+                    swap_constraints,
+                )
+            }
+        }
+    }
+}
+
+impl PrintProgramError for SwapError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        match self {
+            SwapError::AlreadyInUse => msg!("Error: Swap account already in use"),
+            SwapError::InvalidProgramAddress => {
+                msg!("Error: Invalid program address generated from nonce and key")
+            }
+            SwapError::InvalidOwner => {
+                msg!("Error: The input account owner is not the program address")
+            }
+            SwapError::InvalidOutputOwner => {
+                msg!("Error: Output pool account owner cannot be the program address")
+            }
+            SwapError::ExpectedMint => msg!("Error: Deserialized account is not an SPL Token mint"),
+            SwapError::ExpectedAccount => {
+                msg!("Error: Deserialized account is not an SPL Token account")
+            }
+            SwapError::EmptySupply => msg!("Error: Input token account empty"),
+            SwapError::InvalidSupply => msg!("Error: Pool token mint has a non-zero supply"),
+            SwapError::RepeatedMint => msg!("Error: Swap input token accounts have the same mint"),
+            SwapError::InvalidDelegate => msg!("Error: Token account has a delegate"),
+            SwapError::InvalidInput => msg!("Error: InvalidInput"),
+            SwapError::IncorrectSwapAccount => {
+                msg!("Error: Address of the provided swap token account is incorrect")
+            }
+            SwapError::IncorrectPoolMint => {
+                msg!("Error: Address of the provided pool token mint is incorrect")
+            }
+            SwapError::InvalidOutput => msg!("Error: InvalidOutput"),
+            SwapError::CalculationFailure => msg!("Error: CalculationFailure"),
+            SwapError::InvalidInstruction => msg!("Error: InvalidInstruction"),
+            SwapError::ExceededSlippage => {
+                msg!("Error: Swap instruction exceeds desired slippage limit")
+            }
+            SwapError::InvalidCloseAuthority => msg!("Error: Token account has a close authority"),
+            SwapError::InvalidFreezeAuthority => {
+                msg!("Error: Pool token mint has a freeze authority")
+            }
+            SwapError::IncorrectFeeAccount => msg!("Error: Pool fee token account incorrect"),
+            SwapError::ZeroTradingTokens => {
+                msg!("Error: Given pool token amount results in zero trading tokens")
+            }
+            SwapError::FeeCalculationFailure => msg!(
+                "Error: The fee calculation failed due to overflow, underflow, or unexpected 0"
+            ),
+            SwapError::ConversionFailure => msg!("Error: Conversion to or from u64 failed."),
+            SwapError::InvalidFee => {
+                msg!("Error: The provided fee does not match the program owner's constraints")
+            }
+            SwapError::IncorrectTokenProgramId => {
+                msg!("Error: The provided token program does not match the token program expected by the swap")
+            }
+            SwapError::UnsupportedCurveType => {
+                msg!("Error: The provided curve type is not supported by the program owner")
+            }
+            SwapError::InvalidCurve => {
+                msg!("Error: The provided curve parameters are invalid")
+            }
+            SwapError::UnsupportedCurveOperation => {
+                msg!("Error: The operation cannot be performed on the given curve")
+            }
+            SwapError::IncorrectTokenCount => {
+                msg!("Error: The number of tokens is not supported, or does not match the swap")
+            }
+            SwapError::InvalidTick => {
+                msg!("Error: The tick is out of range, or not a multiple of the tick spacing")
+            }
+            SwapError::TooManyTicks => {
+                msg!("Error: The pool cannot hold any more initialized ticks")
+            }
+            SwapError::IncorrectPosition => {
+                msg!("Error: The position does not belong to the swap or to the given owner")
+            }
+            SwapError::InvalidAdmin => {
+                msg!("Error: The swap has no admin, or the given admin authority does not match")
+            }
+            SwapError::InvalidRamp => {
+                msg!("Error: The amplifier ramp is too short, too steep, out of bounds, or too soon after the last one")
+            }
+            SwapError::PoolPaused => {
+                msg!("Error: The operation is not allowed by the status of the pool")
+            }
+            SwapError::FlashLoanNotRepaid => {
+                msg!("Error: The flash loan was not repaid with its fees")
+            }
+            SwapError::IncorrectFarm => {
+                msg!("Error: The farm or stake does not belong to the swap, farm, or owner given")
+            }
+            SwapError::TooManyFarmRewards => {
+                msg!("Error: The farm cannot pay any more reward tokens")
+            }
+            SwapError::InsufficientStake => {
+                msg!("Error: The amount is greater than the pool tokens staked")
+            }
+            SwapError::InvalidAllowlistAuthority => msg!(
+                "Error: The swap has no allowlist, or the given allowlist authority does not match"
+            ),
+            SwapError::NotAllowlisted => {
+                msg!("Error: The user is not a member of the allowlist of the swap")
+            }
+            SwapError::IncorrectLimitOrder => {
+                msg!("Error: The limit order does not belong to the swap or owner given, or its accounts do not match")
+            }
+            SwapError::LimitOrderNotTriggered => {
+                msg!("Error: The price of the swap has not crossed the level of the limit order")
+            }
+            SwapError::InvalidFeeSplit => {
+                msg!("Error: The fee split does not belong to the swap, or its shares or recipients are invalid")
+            }
+            SwapError::PositionLiquidityTooLow => {
+                msg!("Error: The position would hold less than the minimum liquidity of the pool")
+            }
+        }
+    }
+}
+
+fn to_u128(val: u64) -> Result<u128, SwapError> {
+    val.try_into().map_err(|_| SwapError::ConversionFailure)
+}
+
+fn to_u64(val: u128) -> Result<u64, SwapError> {
+    val.try_into().map_err(|_| SwapError::ConversionFailure)
+}
+
+/// Takes the next `count` accounts from the iterator, for instructions with a
+/// variable number of accounts
+fn next_account_infos<'a, 'b>(
+    iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+    count: usize,
+) -> Result<&'a [AccountInfo<'b>], ProgramError> {
+    let accounts = iter.as_slice();
+    if accounts.len() < count {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let (accounts, remaining) = accounts.split_at(count);
+    *iter = remaining.iter();
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::calculator::{CurveCalculator, INITIAL_SWAP_POOL_AMOUNT},
+        curve::{
+            base::CurveType, constant_price::ConstantPriceCurve,
+            constant_product::ConstantProductCurve, offset::OffsetCurve, stable::MIN_RAMP_DURATION,
+            weighted::WeightedCurve,
+        },
+        instruction::{
+            add_allowlist_member, add_farm_reward, cancel_limit_order, claim_farm_rewards,
+            collect_fees, create_limit_order, decrease_liquidity, deposit_all_multi_token_types,
+            deposit_all_token_types, deposit_single_multi_token_type_exact_amount_in,
+            deposit_single_token_type_exact_amount_in, distribute_fees, fill_limit_order,
+            flash_loan, increase_liquidity, initialize, initialize_concentrated, initialize_farm,
+            initialize_multi_token, migrate_swap, open_position, quote, ramp_a,
+            remove_allowlist_member, route_swap, set_admin_authority, set_allowlist,
+            set_dynamic_fee, set_fee_account, set_fee_split, set_flash_loan_fee, set_pool_status,
+            stake, stop_ramp_a, swap, swap_concentrated, swap_exact_out, swap_multi_token, unstake,
+            update_fees, with_allowlist_membership, withdraw_all_multi_token_types,
+            withdraw_all_token_types, withdraw_single_multi_token_type_exact_amount_out,
+            withdraw_single_token_type_exact_amount_out, RouteHop,
+        },
+        oracle::{spot_price, twap},
+    };
+    use solana_program::{instruction::Instruction, program_stubs, rent::Rent};
+    use solana_sdk::account::{create_account, create_is_signer_account_infos, Account};
+    use spl_token::{
+        error::TokenError,
+        instruction::{
+            approve, initialize_account, initialize_mint, mint_to, revoke, set_authority,
+            AuthorityType,
+        },
+    };
+
+    // Test program id for the swap program.
+    const SWAP_PROGRAM_ID: Pubkey = Pubkey::new_from_array([2u8; 32]);
+    // Test program id for the token program.
+    const TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([1u8; 32]);
+
+    struct TestSyscallStubs {}
+    impl program_stubs::SyscallStubs for TestSyscallStubs {
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            msg!("TestSyscallStubs::sol_invoke_signed()");
+
+            let mut new_account_infos = vec![];
+
+            // mimic check for token program in accounts
+            if !account_infos.iter().any(|x| *x.key == TOKEN_PROGRAM_ID) {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            for meta in instruction.accounts.iter() {
+                for account_info in account_infos.iter() {
+                    if meta.pubkey == *account_info.key {
+                        let mut new_account_info = account_info.clone();
+                        for seeds in signers_seeds.iter() {
+                            let signer =
+                                Pubkey::create_program_address(&seeds, &SWAP_PROGRAM_ID).unwrap();
+                            if *account_info.key == signer {
+                                new_account_info.is_signer = true;
+                            }
+                        }
+                        new_account_infos.push(new_account_info);
+                    }
+                }
+            }
+
+            if instruction.program_id == FLASH_LOAN_RECEIVER_ID
+                || instruction.program_id == FLASH_LOAN_DEFAULTER_ID
+            {
+                return receive_flash_loan_stub(instruction, &new_account_infos);
+            }
+
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &new_account_infos,
+                &instruction.data,
+            )?;
+            take_test_transfer_fee(instruction, &new_account_infos)
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                slot: TEST_SLOT.with(|slot| *slot.borrow()),
+                unix_timestamp: TEST_UNIX_TIMESTAMP.with(|timestamp| *timestamp.borrow()),
+                ..Clock::default()
+            };
+            unsafe {
+                *(var_addr as *mut Clock) = clock;
+            }
+            solana_program::entrypoint::SUCCESS
+        }
+    }
+
+    thread_local! {
+        // Slot returned by the clock sysvar to the test running on this thread
+        static TEST_SLOT: std::cell::RefCell<u64> = std::cell::RefCell::new(0);
+    }
+
+    // Flash loan receiver repaying the loan with its fees out of the loaned
+    // account, with the owner of that account as the first extra account
+    const FLASH_LOAN_RECEIVER_ID: Pubkey = Pubkey::new_from_array([3u8; 32]);
+    // Flash loan receiver repaying the loan without its fees
+    const FLASH_LOAN_DEFAULTER_ID: Pubkey = Pubkey::new_from_array([4u8; 32]);
+
+    fn receive_flash_loan_stub(
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+    ) -> ProgramResult {
+        let amount = u64::from_le_bytes(instruction.data[1..9].try_into().unwrap());
+        let fee = u64::from_le_bytes(instruction.data[9..17].try_into().unwrap());
+        let repayment = if instruction.program_id == FLASH_LOAN_RECEIVER_ID {
+            amount + fee
+        } else {
+            amount
+        };
+        let transfer = spl_token::instruction::transfer(
+            &TOKEN_PROGRAM_ID,
+            account_infos[0].key,
+            account_infos[1].key,
+            account_infos[3].key,
+            &[],
+            repayment,
+        )?;
+        spl_token::processor::Processor::process(
+            &TOKEN_PROGRAM_ID,
+            &[
+                account_infos[0].clone(),
+                account_infos[1].clone(),
+                account_infos[3].clone(),
+            ],
+            &transfer.data,
+        )
+    }
+
+    fn set_test_slot(slot: u64) {
+        TEST_SLOT.with(|test_slot| *test_slot.borrow_mut() = slot);
+    }
+
+    thread_local! {
+        // Unix timestamp returned by the clock sysvar to the test running on
+        // this thread
+        static TEST_UNIX_TIMESTAMP: std::cell::RefCell<UnixTimestamp> = std::cell::RefCell::new(0);
+    }
+
+    fn set_test_unix_timestamp(unix_timestamp: UnixTimestamp) {
+        TEST_UNIX_TIMESTAMP.with(|test_timestamp| *test_timestamp.borrow_mut() = unix_timestamp);
+    }
+
+    thread_local! {
+        // Fee taken from the destination of every token transfer made by the
+        // swap program in the test running on this thread
+        static TEST_TRANSFER_FEE: std::cell::RefCell<u64> = std::cell::RefCell::new(0);
+    }
+
+    fn set_test_transfer_fee(fee: u64) {
+        TEST_TRANSFER_FEE.with(|test_fee| *test_fee.borrow_mut() = fee);
+    }
+
+    fn take_test_transfer_fee(
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+    ) -> ProgramResult {
+        let fee = TEST_TRANSFER_FEE.with(|fee| *fee.borrow());
+        if fee == 0 {
+            return Ok(());
+        }
+        if let spl_token::instruction::TokenInstruction::Transfer { amount } =
+            spl_token::instruction::TokenInstruction::unpack(&instruction.data)?
+        {
+            let mut destination =
+                spl_token::state::Account::unpack(&account_infos[1].data.borrow())?;
+            destination.amount -= fee.min(amount);
+            spl_token::state::Account::pack(destination, &mut account_infos[1].data.borrow_mut())?;
+        }
+        Ok(())
+    }
+
+    fn test_syscall_stubs() {
+        use std::sync::Once;
+        static ONCE: Once = Once::new();
+
+        ONCE.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs {}));
+        });
+    }
+
+    struct SwapAccountInfo {
+        nonce: u8,
+        authority_key: Pubkey,
+        admin_key: Pubkey,
+        fees: Fees,
+        swap_curve: SwapCurve,
+        swap_key: Pubkey,
+        swap_account: Account,
+        pool_mint_key: Pubkey,
+        pool_mint_account: Account,
+        pool_fee_key: Pubkey,
+        pool_fee_account: Account,
+        pool_token_key: Pubkey,
+        pool_token_account: Account,
+        token_a_key: Pubkey,
+        token_a_account: Account,
+        token_a_mint_key: Pubkey,
+        token_a_mint_account: Account,
+        token_b_key: Pubkey,
+        token_b_account: Account,
+        token_b_mint_key: Pubkey,
+        token_b_mint_account: Account,
+    }
+
+    impl SwapAccountInfo {
+        pub fn new(
+            user_key: &Pubkey,
+            fees: Fees,
+            swap_curve: SwapCurve,
+            token_a_amount: u64,
+            token_b_amount: u64,
+        ) -> Self {
+            let swap_key = Pubkey::new_unique();
+            let swap_account = Account::new(0, SwapVersion::LATEST_LEN, &SWAP_PROGRAM_ID);
+            let (authority_key, nonce) =
+                Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+
+            let (pool_mint_key, mut pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &authority_key, None);
+            let (pool_token_key, pool_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &pool_mint_key,
+                &mut pool_mint_account,
+                &authority_key,
+                &user_key,
+                0,
+            );
+            let (pool_fee_key, pool_fee_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &pool_mint_key,
+                &mut pool_mint_account,
+                &authority_key,
+                &user_key,
+                0,
+            );
+            let (token_a_mint_key, mut token_a_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
+            let (token_a_key, token_a_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &token_a_mint_key,
+                &mut token_a_mint_account,
+                &user_key,
+                &authority_key,
+                token_a_amount,
+            );
+            let (token_b_mint_key, mut token_b_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
+            let (token_b_key, token_b_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &token_b_mint_key,
+                &mut token_b_mint_account,
+                &user_key,
+                &authority_key,
+                token_b_amount,
+            );
+
+            SwapAccountInfo {
+                nonce,
+                authority_key,
+                admin_key: Pubkey::new_unique(),
+                fees,
+                swap_curve,
+                swap_key,
+                swap_account,
+                pool_mint_key,
+                pool_mint_account,
+                pool_fee_key,
+                pool_fee_account,
+                pool_token_key,
+                pool_token_account,
+                token_a_key,
+                token_a_account,
+                token_a_mint_key,
+                token_a_mint_account,
+                token_b_key,
+                token_b_account,
+                token_b_mint_key,
+                token_b_mint_account,
+            }
+        }
+
+        pub fn initialize_swap(&mut self) -> ProgramResult {
+            let admin_key = self.admin_key;
+            self.initialize_swap_with_admin(Some(&admin_key))
+        }
+
+        /// Initializes the swap in an account of the size used before the
+        /// extension was added, which has no room for an admin
+        pub fn initialize_legacy_swap(&mut self) -> ProgramResult {
+            self.swap_account = Account::new(0, SwapVersion::LEGACY_LEN, &SWAP_PROGRAM_ID);
+            self.initialize_swap_with_admin(None)
+        }
+
+        fn initialize_swap_with_admin(&mut self, admin_key: Option<&Pubkey>) -> ProgramResult {
+            do_process_instruction(
+                initialize(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &self.pool_fee_key,
+                    &self.pool_token_key,
+                    admin_key,
+                    self.nonce,
+                    self.fees.clone(),
+                    self.swap_curve.clone(),
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut self.pool_fee_account,
+                    &mut self.pool_token_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        }
+
+        pub fn setup_token_accounts(
+            &mut self,
+            mint_owner: &Pubkey,
+            account_owner: &Pubkey,
+            a_amount: u64,
+            b_amount: u64,
+            pool_amount: u64,
+        ) -> (Pubkey, Account, Pubkey, Account, Pubkey, Account) {
+            let (token_a_key, token_a_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &self.token_a_mint_key,
+                &mut self.token_a_mint_account,
+                &mint_owner,
+                &account_owner,
+                a_amount,
+            );
+            let (token_b_key, token_b_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &self.token_b_mint_key,
+                &mut self.token_b_mint_account,
+                &mint_owner,
+                &account_owner,
+                b_amount,
+            );
+            let (pool_key, pool_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &self.pool_mint_key,
+                &mut self.pool_mint_account,
+                &self.authority_key,
+                &account_owner,
+                pool_amount,
+            );
+            (
+                token_a_key,
+                token_a_account,
+                token_b_key,
+                token_b_account,
+                pool_key,
+                pool_account,
+            )
+        }
+
+        fn get_token_account(&self, account_key: &Pubkey) -> &Account {
+            if *account_key == self.token_a_key {
+                return &self.token_a_account;
+            } else if *account_key == self.token_b_key {
+                return &self.token_b_account;
+            }
+            panic!("Could not find matching swap token account");
+        }
+
+        fn set_token_account(&mut self, account_key: &Pubkey, account: Account) {
+            if *account_key == self.token_a_key {
+                self.token_a_account = account;
+                return;
+            } else if *account_key == self.token_b_key {
+                self.token_b_account = account;
+                return;
+            }
+            panic!("Could not find matching swap token account");
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn swap(
+            &mut self,
+            user_key: &Pubkey,
+            user_source_key: &Pubkey,
+            mut user_source_account: &mut Account,
+            swap_source_key: &Pubkey,
+            swap_destination_key: &Pubkey,
+            user_destination_key: &Pubkey,
+            mut user_destination_account: &mut Account,
+            amount_in: u64,
+            minimum_amount_out: u64,
+        ) -> ProgramResult {
+            let user_transfer_key = Pubkey::new_unique();
+            // approve moving from user source account
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &user_source_key,
+                    &user_transfer_key,
+                    &user_key,
+                    &[],
+                    amount_in,
+                )
+                .unwrap(),
+                vec![
+                    &mut user_source_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+            let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+            // perform the swap
+            do_process_instruction(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &user_transfer_key,
+                    &user_source_key,
+                    &swap_source_key,
+                    &swap_destination_key,
+                    &user_destination_key,
+                    &self.pool_mint_key,
+                    &self.pool_fee_key,
+                    None,
+                    Swap {
+                        amount_in,
+                        minimum_amount_out,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut user_source_account,
+                    &mut swap_source_account,
+                    &mut swap_destination_account,
+                    &mut user_destination_account,
+                    &mut self.pool_mint_account,
+                    &mut self.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )?;
+
+            self.set_token_account(swap_source_key, swap_source_account);
+            self.set_token_account(swap_destination_key, swap_destination_account);
+
+            Ok(())
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn swap_exact_out(
+            &mut self,
+            user_key: &Pubkey,
+            user_source_key: &Pubkey,
+            mut user_source_account: &mut Account,
+            swap_source_key: &Pubkey,
+            swap_destination_key: &Pubkey,
+            user_destination_key: &Pubkey,
+            mut user_destination_account: &mut Account,
+            amount_out: u64,
+            maximum_amount_in: u64,
+        ) -> ProgramResult {
+            let user_transfer_key = Pubkey::new_unique();
+            // approve moving from user source account
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &user_source_key,
+                    &user_transfer_key,
+                    &user_key,
+                    &[],
+                    maximum_amount_in,
+                )
+                .unwrap(),
+                vec![
+                    &mut user_source_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            let mut swap_source_account = self.get_token_account(swap_source_key).clone();
+            let mut swap_destination_account = self.get_token_account(swap_destination_key).clone();
+
+            // perform the swap
+            do_process_instruction(
+                swap_exact_out(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &user_transfer_key,
+                    &user_source_key,
+                    &swap_source_key,
+                    &swap_destination_key,
+                    &user_destination_key,
+                    &self.pool_mint_key,
+                    &self.pool_fee_key,
+                    None,
+                    SwapExactOut {
+                        amount_out,
+                        maximum_amount_in,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut user_source_account,
+                    &mut swap_source_account,
+                    &mut swap_destination_account,
+                    &mut user_destination_account,
+                    &mut self.pool_mint_account,
+                    &mut self.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )?;
+
+            self.set_token_account(swap_source_key, swap_source_account);
+            self.set_token_account(swap_destination_key, swap_destination_account);
+
+            Ok(())
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn deposit_all_token_types(
+            &mut self,
+            depositor_key: &Pubkey,
+            depositor_token_a_key: &Pubkey,
+            mut depositor_token_a_account: &mut Account,
+            depositor_token_b_key: &Pubkey,
+            mut depositor_token_b_account: &mut Account,
+            depositor_pool_key: &Pubkey,
+            mut depositor_pool_account: &mut Account,
+            pool_token_amount: u64,
+            maximum_token_a_amount: u64,
+            maximum_token_b_amount: u64,
+        ) -> ProgramResult {
+            let user_transfer_authority = Pubkey::new_unique();
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &depositor_token_a_key,
+                    &user_transfer_authority,
+                    &depositor_key,
+                    &[],
+                    maximum_token_a_amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut depositor_token_a_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &depositor_token_b_key,
+                    &user_transfer_authority,
+                    &depositor_key,
+                    &[],
+                    maximum_token_b_amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut depositor_token_b_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            do_process_instruction(
+                deposit_all_token_types(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &user_transfer_authority,
+                    &depositor_token_a_key,
+                    &depositor_token_b_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &depositor_pool_key,
+                    DepositAllTokenTypes {
+                        pool_token_amount,
+                        maximum_token_a_amount,
+                        maximum_token_b_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut depositor_token_a_account,
+                    &mut depositor_token_b_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut depositor_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn withdraw_all_token_types(
+            &mut self,
+            user_key: &Pubkey,
+            pool_key: &Pubkey,
+            mut pool_account: &mut Account,
+            token_a_key: &Pubkey,
+            mut token_a_account: &mut Account,
+            token_b_key: &Pubkey,
+            mut token_b_account: &mut Account,
+            pool_token_amount: u64,
+            minimum_token_a_amount: u64,
+            minimum_token_b_amount: u64,
+        ) -> ProgramResult {
+            let user_transfer_authority_key = Pubkey::new_unique();
+            // approve user transfer authority to take out pool tokens
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &pool_key,
+                    &user_transfer_authority_key,
+                    &user_key,
+                    &[],
+                    pool_token_amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut pool_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            // withdraw token a and b correctly
+            do_process_instruction(
+                withdraw_all_token_types(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &user_transfer_authority_key,
+                    &self.pool_mint_key,
+                    &self.pool_fee_key,
+                    &pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &token_a_key,
+                    &token_b_key,
+                    WithdrawAllTokenTypes {
+                        pool_token_amount,
+                        minimum_token_a_amount,
+                        minimum_token_b_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut self.pool_mint_account,
+                    &mut pool_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut token_a_account,
+                    &mut token_b_account,
+                    &mut self.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn deposit_single_token_type_exact_amount_in(
+            &mut self,
+            depositor_key: &Pubkey,
+            deposit_account_key: &Pubkey,
+            mut deposit_token_account: &mut Account,
+            deposit_pool_key: &Pubkey,
+            mut deposit_pool_account: &mut Account,
+            source_token_amount: u64,
+            minimum_pool_token_amount: u64,
+        ) -> ProgramResult {
+            let user_transfer_authority_key = Pubkey::new_unique();
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &deposit_account_key,
+                    &user_transfer_authority_key,
+                    &depositor_key,
+                    &[],
+                    source_token_amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut deposit_token_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            do_process_instruction(
+                deposit_single_token_type_exact_amount_in(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &user_transfer_authority_key,
+                    &deposit_account_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &self.pool_mint_key,
+                    &deposit_pool_key,
+                    DepositSingleTokenTypeExactAmountIn {
+                        source_token_amount,
+                        minimum_pool_token_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut deposit_token_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut self.pool_mint_account,
+                    &mut deposit_pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn withdraw_single_token_type_exact_amount_out(
+            &mut self,
+            user_key: &Pubkey,
+            pool_key: &Pubkey,
+            mut pool_account: &mut Account,
+            destination_key: &Pubkey,
+            mut destination_account: &mut Account,
+            destination_token_amount: u64,
+            maximum_pool_token_amount: u64,
+        ) -> ProgramResult {
+            let user_transfer_authority_key = Pubkey::new_unique();
+            // approve user transfer authority to take out pool tokens
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &pool_key,
+                    &user_transfer_authority_key,
+                    &user_key,
+                    &[],
+                    maximum_pool_token_amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut pool_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+
+            do_process_instruction(
+                withdraw_single_token_type_exact_amount_out(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &self.swap_key,
+                    &self.authority_key,
+                    &user_transfer_authority_key,
+                    &self.pool_mint_key,
+                    &self.pool_fee_key,
+                    &pool_key,
+                    &self.token_a_key,
+                    &self.token_b_key,
+                    &destination_key,
+                    WithdrawSingleTokenTypeExactAmountOut {
+                        destination_token_amount,
+                        maximum_pool_token_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut self.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut self.pool_mint_account,
+                    &mut pool_account,
+                    &mut self.token_a_account,
+                    &mut self.token_b_account,
+                    &mut destination_account,
+                    &mut self.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        }
+    }
+
+    fn mint_minimum_balance() -> u64 {
+        Rent::default().minimum_balance(spl_token::state::Mint::get_packed_len())
+    }
+
+    fn account_minimum_balance() -> u64 {
+        Rent::default().minimum_balance(spl_token::state::Account::get_packed_len())
+    }
+
+    fn do_process_instruction_with_fee_constraints(
+        instruction: Instruction,
+        accounts: Vec<&mut Account>,
+        swap_constraints: &Option<SwapConstraints>,
+    ) -> ProgramResult {
+        test_syscall_stubs();
+
+        // approximate the logic in the actual runtime which runs the instruction
+        // and only updates accounts if the instruction is successful
+        let mut account_clones = accounts.iter().map(|x| (*x).clone()).collect::<Vec<_>>();
+        let mut meta = instruction
+            .accounts
+            .iter()
+            .zip(account_clones.iter_mut())
+            .map(|(account_meta, account)| (&account_meta.pubkey, account_meta.is_signer, account))
+            .collect::<Vec<_>>();
+        let mut account_infos = create_is_signer_account_infos(&mut meta);
+        let res = if instruction.program_id == SWAP_PROGRAM_ID {
+            Processor::process_with_constraints(
+                &instruction.program_id,
+                &account_infos,
+                &instruction.data,
+                swap_constraints,
+            )
+        } else {
+            spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &account_infos,
+                &instruction.data,
+            )
+        };
+
+        if res.is_ok() {
+            let mut account_metas = instruction
+                .accounts
+                .iter()
+                .zip(accounts)
+                .map(|(account_meta, account)| (&account_meta.pubkey, account))
+                .collect::<Vec<_>>();
+            for account_info in account_infos.iter_mut() {
+                for account_meta in account_metas.iter_mut() {
+                    if account_info.key == account_meta.0 {
+                        let account = &mut account_meta.1;
+                        account.owner = *account_info.owner;
+                        account.lamports = **account_info.lamports.borrow();
+                        account.data = account_info.data.borrow().to_vec();
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    fn do_process_instruction(
+        instruction: Instruction,
+        accounts: Vec<&mut Account>,
+    ) -> ProgramResult {
+        do_process_instruction_with_fee_constraints(instruction, accounts, &SWAP_CONSTRAINTS)
+    }
+
+    fn mint_token(
+        program_id: &Pubkey,
+        mint_key: &Pubkey,
+        mut mint_account: &mut Account,
+        mint_authority_key: &Pubkey,
+        account_owner_key: &Pubkey,
+        amount: u64,
+    ) -> (Pubkey, Account) {
+        let account_key = Pubkey::new_unique();
+        let mut account_account = Account::new(
+            account_minimum_balance(),
+            spl_token::state::Account::get_packed_len(),
+            &program_id,
+        );
+        let mut mint_authority_account = Account::default();
+        let mut rent_sysvar_account = create_account(&Rent::free(), 1);
+
+        do_process_instruction(
+            initialize_account(&program_id, &account_key, &mint_key, account_owner_key).unwrap(),
+            vec![
+                &mut account_account,
+                &mut mint_account,
+                &mut mint_authority_account,
+                &mut rent_sysvar_account,
+            ],
+        )
+        .unwrap();
+
+        if amount > 0 {
+            do_process_instruction(
+                mint_to(
+                    &program_id,
+                    &mint_key,
+                    &account_key,
+                    &mint_authority_key,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+                vec![
+                    &mut mint_account,
+                    &mut account_account,
+                    &mut mint_authority_account,
+                ],
+            )
+            .unwrap();
+        }
+
+        (account_key, account_account)
+    }
+
+    fn create_mint(
+        program_id: &Pubkey,
+        authority_key: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+    ) -> (Pubkey, Account) {
+        let mint_key = Pubkey::new_unique();
+        let mut mint_account = Account::new(
+            mint_minimum_balance(),
+            spl_token::state::Mint::get_packed_len(),
+            &program_id,
+        );
+        let mut rent_sysvar_account = create_account(&Rent::free(), 1);
+
+        do_process_instruction(
+            initialize_mint(&program_id, &mint_key, authority_key, freeze_authority, 2).unwrap(),
+            vec![&mut mint_account, &mut rent_sysvar_account],
+        )
+        .unwrap();
+
+        (mint_key, mint_account)
+    }
+
+    #[test]
+    fn test_token_program_id_error() {
+        test_syscall_stubs();
+        let swap_key = Pubkey::new_unique();
+        let mut mint = (Pubkey::new_unique(), Account::default());
+        let mut destination = (Pubkey::new_unique(), Account::default());
+        let token_program = (TOKEN_PROGRAM_ID, Account::default());
+        let (authority_key, nonce) =
+            Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let mut authority = (authority_key, Account::default());
+        let swap_bytes = swap_key.to_bytes();
+        let authority_signature_seeds = [&swap_bytes[..32], &[nonce]];
+        let signers = &[&authority_signature_seeds[..]];
+        let ix = mint_to(
+            &token_program.0,
+            &mint.0,
+            &destination.0,
+            &authority.0,
+            &[],
+            10,
+        )
+        .unwrap();
+        let mint = (&mut mint).into();
+        let destination = (&mut destination).into();
+        let authority = (&mut authority).into();
+
+        let err = invoke_signed(&ix, &[mint, destination, authority], signers).unwrap_err();
+        assert_eq!(err, ProgramError::InvalidAccountData);
+    }
+
+    #[test]
+    fn test_initialize() {
+        let user_key = Pubkey::new_unique();
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 2;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 10;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 5;
+        let host_fee_numerator = 20;
+        let host_fee_denominator = 100;
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
+
+        let token_a_amount = 1000;
+        let token_b_amount = 2000;
+        let pool_token_amount = 10;
+        let curve_type = CurveType::ConstantProduct;
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+
+        // wrong nonce for authority_key
+        {
+            let old_nonce = accounts.nonce;
+            accounts.nonce = old_nonce - 1;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.initialize_swap()
+            );
+            accounts.nonce = old_nonce;
+        }
+
+        // uninitialized token a account
+        {
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = Account::new(0, 0, &TOKEN_PROGRAM_ID);
+            assert_eq!(
+                Err(SwapError::ExpectedAccount.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // uninitialized token b account
+        {
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = Account::new(0, 0, &TOKEN_PROGRAM_ID);
+            assert_eq!(
+                Err(SwapError::ExpectedAccount.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // uninitialized pool mint
+        {
+            let old_account = accounts.pool_mint_account;
+            accounts.pool_mint_account = Account::new(0, 0, &TOKEN_PROGRAM_ID);
+            assert_eq!(
+                Err(SwapError::ExpectedMint.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_mint_account = old_account;
+        }
+
+        // token A account owner is not swap authority
+        {
+            let (_token_a_key, token_a_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &user_key,
+                0,
+            );
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = token_a_account;
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // token B account owner is not swap authority
+        {
+            let (_token_b_key, token_b_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &user_key,
+                0,
+            );
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_b_account;
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // pool token account owner is swap authority
+        {
+            let (_pool_token_key, pool_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.pool_token_account;
+            accounts.pool_token_account = pool_token_account;
+            assert_eq!(
+                Err(SwapError::InvalidOutputOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_token_account = old_account;
+        }
+
+        // pool fee account owner is swap authority
+        {
+            let (_pool_fee_key, pool_fee_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.pool_fee_account;
+            accounts.pool_fee_account = pool_fee_account;
+            assert_eq!(
+                Err(SwapError::InvalidOutputOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_fee_account = old_account;
+        }
+
+        // pool mint authority is not swap authority
+        {
+            let (_pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
+            let old_mint = accounts.pool_mint_account;
+            accounts.pool_mint_account = pool_mint_account;
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_mint_account = old_mint;
+        }
+
+        // pool mint token has freeze authority
+        {
+            let (_pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, Some(&user_key));
+            let old_mint = accounts.pool_mint_account;
+            accounts.pool_mint_account = pool_mint_account;
+            assert_eq!(
+                Err(SwapError::InvalidFreezeAuthority.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_mint_account = old_mint;
+        }
+
+        // token A account owned by wrong program
+        {
+            let (_token_a_key, mut token_a_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                token_a_amount,
+            );
+            token_a_account.owner = SWAP_PROGRAM_ID;
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = token_a_account;
+            assert_eq!(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // token B account owned by wrong program
+        {
+            let (_token_b_key, mut token_b_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                token_b_amount,
+            );
+            token_b_account.owner = SWAP_PROGRAM_ID;
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_b_account;
+            assert_eq!(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // empty token A account
+        {
+            let (_token_a_key, token_a_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.token_a_account;
+            accounts.token_a_account = token_a_account;
+            assert_eq!(
+                Err(SwapError::EmptySupply.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_a_account = old_account;
+        }
+
+        // empty token B account
+        {
+            let (_token_b_key, token_b_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                0,
+            );
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_b_account;
+            assert_eq!(
+                Err(SwapError::EmptySupply.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // invalid pool tokens
+        {
+            let old_mint = accounts.pool_mint_account;
+            let old_pool_account = accounts.pool_token_account;
+
+            let (_pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
+            accounts.pool_mint_account = pool_mint_account;
+
+            let (_empty_pool_token_key, empty_pool_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &user_key,
+                0,
+            );
+
+            let (_pool_token_key, pool_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.pool_mint_key,
+                &mut accounts.pool_mint_account,
+                &accounts.authority_key,
+                &user_key,
+                pool_token_amount,
+            );
+
+            // non-empty pool token account
+            accounts.pool_token_account = pool_token_account;
+            assert_eq!(
+                Err(SwapError::InvalidSupply.into()),
+                accounts.initialize_swap()
+            );
+
+            // pool tokens already in circulation
+            accounts.pool_token_account = empty_pool_token_account;
+            assert_eq!(
+                Err(SwapError::InvalidSupply.into()),
+                accounts.initialize_swap()
+            );
+
+            accounts.pool_mint_account = old_mint;
+            accounts.pool_token_account = old_pool_account;
+        }
+
+        // pool fee account has wrong mint
+        {
+            let (_pool_fee_key, pool_fee_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &user_key,
+                0,
+            );
+            let old_account = accounts.pool_fee_account;
+            accounts.pool_fee_account = pool_fee_account;
+            assert_eq!(
+                Err(SwapError::IncorrectPoolMint.into()),
+                accounts.initialize_swap()
+            );
+            accounts.pool_fee_account = old_account;
+        }
+
+        // token A account is delegated
+        {
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_a_key,
+                    &user_key,
+                    &accounts.authority_key,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.token_a_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidDelegate.into()),
+                accounts.initialize_swap()
+            );
+
+            do_process_instruction(
+                revoke(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_a_key,
+                    &accounts.authority_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_a_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // token B account is delegated
+        {
+            do_process_instruction(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_b_key,
+                    &user_key,
+                    &accounts.authority_key,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.token_b_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+            .unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidDelegate.into()),
+                accounts.initialize_swap()
+            );
+
+            do_process_instruction(
+                revoke(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_b_key,
+                    &accounts.authority_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_b_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // token A account has close authority
+        {
+            do_process_instruction(
+                set_authority(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_a_key,
+                    Some(&user_key),
+                    AuthorityType::CloseAccount,
+                    &accounts.authority_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_a_account, &mut Account::default()],
+            )
+            .unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidCloseAuthority.into()),
+                accounts.initialize_swap()
+            );
+
+            do_process_instruction(
+                set_authority(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_a_key,
+                    None,
+                    AuthorityType::CloseAccount,
+                    &user_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_a_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // token B account has close authority
+        {
+            do_process_instruction(
+                set_authority(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_b_key,
+                    Some(&user_key),
+                    AuthorityType::CloseAccount,
+                    &accounts.authority_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_b_account, &mut Account::default()],
+            )
+            .unwrap();
+            assert_eq!(
+                Err(SwapError::InvalidCloseAuthority.into()),
+                accounts.initialize_swap()
+            );
+
+            do_process_instruction(
+                set_authority(
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.token_b_key,
+                    None,
+                    AuthorityType::CloseAccount,
+                    &user_key,
+                    &[],
+                )
+                .unwrap(),
+                vec![&mut accounts.token_b_account, &mut Account::default()],
+            )
+            .unwrap();
+        }
+
+        // wrong token program id
+        {
+            let wrong_program_id = Pubkey::new_unique();
+            assert_eq!(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                do_process_instruction(
+                    initialize(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_program_id,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &accounts.pool_token_key,
+                        None,
+                        accounts.nonce,
+                        accounts.fees.clone(),
+                        accounts.swap_curve.clone(),
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut accounts.pool_fee_account,
+                        &mut accounts.pool_token_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // create swap with same token A and B
+        {
+            let (_token_a_repeat_key, token_a_repeat_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &accounts.authority_key,
+                10,
+            );
+            let old_account = accounts.token_b_account;
+            accounts.token_b_account = token_a_repeat_account;
+            assert_eq!(
+                Err(SwapError::RepeatedMint.into()),
+                accounts.initialize_swap()
+            );
+            accounts.token_b_account = old_account;
+        }
+
+        // create valid swap
+        accounts.initialize_swap().unwrap();
+
+        // create invalid flat swap
+        {
+            let token_b_price = 0;
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::ConstantPrice,
+                calculator: Box::new(ConstantPriceCurve { token_b_price }),
+            };
+            let mut accounts =
+                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+            assert_eq!(
+                Err(SwapError::InvalidCurve.into()),
+                accounts.initialize_swap()
+            );
+        }
+
+        // create valid flat swap
+        {
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let token_b_price = 10_000;
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::ConstantPrice,
+                calculator: Box::new(ConstantPriceCurve { token_b_price }),
+            };
+            let mut accounts =
+                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+            accounts.initialize_swap().unwrap();
+        }
+
+        // create invalid offset swap
+        {
+            let token_b_offset = 0;
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::Offset,
+                calculator: Box::new(OffsetCurve { token_b_offset }),
+            };
+            let mut accounts =
+                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+            assert_eq!(
+                Err(SwapError::InvalidCurve.into()),
+                accounts.initialize_swap()
+            );
+        }
+
+        // create valid offset swap
+        {
+            let token_b_offset = 10;
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::Offset,
+                calculator: Box::new(OffsetCurve { token_b_offset }),
+            };
+            let mut accounts =
+                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+            accounts.initialize_swap().unwrap();
+        }
+
+        // create invalid weighted swap
+        {
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::Weighted,
+                calculator: Box::new(WeightedCurve {
+                    token_a_weight: 80,
+                    token_b_weight: 0,
+                }),
+            };
+            let mut accounts =
+                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+            assert_eq!(
+                Err(SwapError::InvalidCurve.into()),
+                accounts.initialize_swap()
+            );
+        }
+
+        // create valid weighted swap
+        {
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::Weighted,
+                calculator: Box::new(WeightedCurve {
+                    token_a_weight: 80,
+                    token_b_weight: 20,
+                }),
+            };
+            let mut accounts =
+                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+            accounts.initialize_swap().unwrap();
+        }
+
+        // wrong owner key in constraint
+        {
+            let new_key = Pubkey::new_unique();
+            let trade_fee_numerator = 25;
+            let trade_fee_denominator = 10000;
+            let owner_trade_fee_numerator = 5;
+            let owner_trade_fee_denominator = 10000;
+            let host_fee_numerator = 20;
+            let host_fee_denominator = 100;
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let curve = ConstantProductCurve {};
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::ConstantProduct,
+                calculator: Box::new(curve),
+            };
+            let owner_key = &new_key.to_string();
+            let valid_curve_types = &[CurveType::ConstantProduct];
+            let constraints = Some(SwapConstraints {
+                owner_key,
+                valid_curve_types,
+                fees: &fees,
+            });
+            let mut accounts = SwapAccountInfo::new(
+                &user_key,
+                fees.clone(),
+                swap_curve,
+                token_a_amount,
+                token_b_amount,
+            );
+            assert_eq!(
+                Err(SwapError::InvalidOwner.into()),
+                do_process_instruction_with_fee_constraints(
+                    initialize(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &accounts.pool_token_key,
+                        None,
+                        accounts.nonce,
+                        accounts.fees.clone(),
+                        accounts.swap_curve.clone(),
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut accounts.pool_fee_account,
+                        &mut accounts.pool_token_account,
+                        &mut Account::default(),
+                    ],
+                    &constraints,
+                )
+            );
+        }
+
+        // wrong fee in constraint
+        {
+            let trade_fee_numerator = 25;
+            let trade_fee_denominator = 10000;
+            let owner_trade_fee_numerator = 5;
+            let owner_trade_fee_denominator = 10000;
+            let host_fee_numerator = 20;
+            let host_fee_denominator = 100;
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let curve = ConstantProductCurve {};
+            let swap_curve = SwapCurve {
+                curve_type: CurveType::ConstantProduct,
+                calculator: Box::new(curve),
+            };
+            let owner_key = &user_key.to_string();
+            let valid_curve_types = &[CurveType::ConstantProduct];
+            let constraints = Some(SwapConstraints {
+                owner_key,
+                valid_curve_types,
+                fees: &fees,
+            });
+            let mut bad_fees = fees.clone();
+            bad_fees.trade_fee_numerator = trade_fee_numerator - 1;
+            let mut accounts = SwapAccountInfo::new(
+                &user_key,
+                bad_fees,
+                swap_curve,
+                token_a_amount,
+                token_b_amount,
+            );
+            assert_eq!(
+                Err(SwapError::InvalidFee.into()),
+                do_process_instruction_with_fee_constraints(
+                    initialize(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &accounts.pool_token_key,
+                        None,
+                        accounts.nonce,
+                        accounts.fees.clone(),
+                        accounts.swap_curve.clone(),
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut accounts.pool_fee_account,
+                        &mut accounts.pool_token_account,
+                        &mut Account::default(),
+                    ],
+                    &constraints,
+                )
+            );
+        }
+
+        // create valid swap with constraints
+        {
+            let trade_fee_numerator = 25;
+            let trade_fee_denominator = 10000;
+            let owner_trade_fee_numerator = 5;
+            let owner_trade_fee_denominator = 10000;
+            let host_fee_numerator = 20;
+            let host_fee_denominator = 100;
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
                 owner_trade_fee_denominator,
                 owner_withdraw_fee_numerator,
                 owner_withdraw_fee_denominator,
                 host_fee_numerator,
                 host_fee_denominator,
             };
+            let curve = ConstantProductCurve {};
             let swap_curve = SwapCurve {
-                curve_type: CurveType::Offset,
-                calculator: Box::new(OffsetCurve { token_b_offset }),
+                curve_type: CurveType::ConstantProduct,
+                calculator: Box::new(curve),
             };
-            let mut accounts =
-                SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-            accounts.initialize_swap().unwrap();
+            let owner_key = &user_key.to_string();
+            let valid_curve_types = &[CurveType::ConstantProduct];
+            let constraints = Some(SwapConstraints {
+                owner_key,
+                valid_curve_types,
+                fees: &fees,
+            });
+            let mut accounts = SwapAccountInfo::new(
+                &user_key,
+                fees.clone(),
+                swap_curve,
+                token_a_amount,
+                token_b_amount,
+            );
+            do_process_instruction_with_fee_constraints(
+                initialize(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &accounts.authority_key,
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
+                    &accounts.pool_mint_key,
+                    &accounts.pool_fee_key,
+                    &accounts.pool_token_key,
+                    None,
+                    accounts.nonce,
+                    accounts.fees,
+                    accounts.swap_curve.clone(),
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.pool_fee_account,
+                    &mut accounts.pool_token_account,
+                    &mut Account::default(),
+                ],
+                &constraints,
+            )
+            .unwrap();
+        }
+
+        // create again
+        {
+            assert_eq!(
+                Err(SwapError::AlreadyInUse.into()),
+                accounts.initialize_swap()
+            );
+        }
+        let swap_state = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
+        assert_eq!(swap_state.is_initialized(), true);
+        assert_eq!(swap_state.nonce(), accounts.nonce);
+        assert_eq!(
+            swap_state.swap_curve().curve_type,
+            accounts.swap_curve.curve_type
+        );
+        assert_eq!(*swap_state.token_a_account(), accounts.token_a_key);
+        assert_eq!(*swap_state.token_b_account(), accounts.token_b_key);
+        assert_eq!(*swap_state.pool_mint(), accounts.pool_mint_key);
+        assert_eq!(*swap_state.token_a_mint(), accounts.token_a_mint_key);
+        assert_eq!(*swap_state.token_b_mint(), accounts.token_b_mint_key);
+        assert_eq!(*swap_state.pool_fee_account(), accounts.pool_fee_key);
+        let token_a = spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, token_a_amount);
+        let token_b = spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, token_b_amount);
+        let pool_account =
+            spl_token::state::Account::unpack(&accounts.pool_token_account.data).unwrap();
+        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.supply, pool_account.amount);
+    }
+
+    #[test]
+    fn test_deposit() {
+        let user_key = Pubkey::new_unique();
+        let depositor_key = Pubkey::new_unique();
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 2;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 10;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 5;
+        let host_fee_numerator = 20;
+        let host_fee_denominator = 100;
+
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
+
+        let token_a_amount = 1000;
+        let token_b_amount = 9000;
+        let curve_type = CurveType::ConstantProduct;
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+
+        // depositing 10% of the current pool amount in token A and B means
+        // that our pool tokens will be worth 1 / 10 of the current pool amount
+        let pool_amount = INITIAL_SWAP_POOL_AMOUNT / 10;
+        let deposit_a = token_a_amount / 10;
+        let deposit_b = token_b_amount / 10;
+
+        // swap not initialized
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong owner for swap account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let old_swap_account = accounts.swap_account;
+            let mut wrong_swap_account = old_swap_account.clone();
+            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
+            accounts.swap_account = wrong_swap_account;
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+            accounts.swap_account = old_swap_account;
+        }
+
+        // wrong nonce for authority_key
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &TOKEN_PROGRAM_ID,
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
+
+        // not enough token A
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &depositor_key,
+                deposit_a / 2,
+                deposit_b,
+                0,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        // not enough token B
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &depositor_key,
+                deposit_a,
+                deposit_b / 2,
+                0,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        // wrong pool token account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let (
+                wrong_token_key,
+                mut wrong_token_account,
+                _token_b_key,
+                mut _token_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &wrong_token_key,
+                    &mut wrong_token_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        // no approval
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let user_transfer_authority_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(TokenError::OwnerMismatch.into()),
+                do_process_instruction(
+                    deposit_all_token_types(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &user_transfer_authority_key,
+                        &token_a_key,
+                        &token_b_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &pool_key,
+                        DepositAllTokenTypes {
+                            pool_token_amount: pool_amount.try_into().unwrap(),
+                            maximum_token_a_amount: deposit_a,
+                            maximum_token_b_amount: deposit_b,
+                        },
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut token_b_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // wrong token program id
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let wrong_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                do_process_instruction(
+                    deposit_all_token_types(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_key,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.authority_key,
+                        &token_a_key,
+                        &token_b_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &accounts.pool_mint_key,
+                        &pool_key,
+                        DepositAllTokenTypes {
+                            pool_token_amount: pool_amount.try_into().unwrap(),
+                            maximum_token_a_amount: deposit_a,
+                            maximum_token_b_amount: deposit_b,
+                        },
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut token_b_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+
+            let old_a_key = accounts.token_a_key;
+            let old_a_account = accounts.token_a_account;
+
+            accounts.token_a_key = token_a_key;
+            accounts.token_a_account = token_a_account.clone();
+
+            // wrong swap token a account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+
+            accounts.token_a_key = old_a_key;
+            accounts.token_a_account = old_a_account;
+
+            let old_b_key = accounts.token_b_key;
+            let old_b_account = accounts.token_b_account;
+
+            accounts.token_b_key = token_b_key;
+            accounts.token_b_account = token_b_account.clone();
+
+            // wrong swap token b account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+
+            accounts.token_b_key = old_b_key;
+            accounts.token_b_account = old_b_account;
+        }
+
+        // wrong mint
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let (pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
+            assert_eq!(
+                Err(SwapError::IncorrectPoolMint.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // deposit 1 pool token fails beacuse it equates to 0 swap tokens
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(SwapError::ZeroTradingTokens.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    1,
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        // slippage exceeded
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            // maximum A amount in too low
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a / 10,
+                    deposit_b,
+                )
+            );
+            // maximum B amount in too low
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b / 10,
+                )
+            );
+        }
+
+        // invalid input: can't use swap pool tokens as source
+        {
+            let (
+                _token_a_key,
+                _token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let swap_token_a_key = accounts.token_a_key;
+            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
+            let swap_token_b_key = accounts.token_b_key;
+            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
+            let authority_key = accounts.authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.deposit_all_token_types(
+                    &authority_key,
+                    &swap_token_a_key,
+                    &mut swap_token_a_account,
+                    &swap_token_b_key,
+                    &mut swap_token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+        }
+
+        // correctly deposit
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            accounts
+                .deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+                .unwrap();
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            assert_eq!(swap_token_a.amount, deposit_a + token_a_amount);
+            let swap_token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_token_b.amount, deposit_b + token_b_amount);
+            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, 0);
+            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, 0);
+            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+            let swap_pool_account =
+                spl_token::state::Account::unpack(&accounts.pool_token_account.data).unwrap();
+            let pool_mint =
+                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+            assert_eq!(
+                pool_mint.supply,
+                pool_account.amount + swap_pool_account.amount
+            );
+        }
+
+        // a fee on transfer is taken from the deposit
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            set_test_transfer_fee(deposit_a / 10);
+            // the maximums cover the tokens sent but not the pool tokens
+            // minted for what reaches the pool
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a,
+                    deposit_b,
+                )
+            );
+            accounts
+                .deposit_all_token_types(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount.try_into().unwrap(),
+                    deposit_a * 2,
+                    deposit_b * 2,
+                )
+                .unwrap();
+            set_test_transfer_fee(0);
+
+            // only 9 / 10 of the token A deposit reached the pool
+            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+            assert_eq!(
+                pool_account.amount,
+                u64::try_from(pool_amount * 9 / 10).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let user_key = Pubkey::new_unique();
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 2;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 10;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 5;
+        let host_fee_numerator = 7;
+        let host_fee_denominator = 100;
+
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
+
+        let token_a_amount = 1000;
+        let token_b_amount = 2000;
+        let curve_type = CurveType::ConstantProduct;
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+
+        let withdrawer_key = Pubkey::new_unique();
+        let initial_a = token_a_amount / 10;
+        let initial_b = token_b_amount / 10;
+        let initial_pool = swap_curve.calculator.new_pool_supply() / 10;
+        let withdraw_amount = initial_pool / 4;
+        let minimum_token_a_amount = initial_a / 40;
+        let minimum_token_b_amount = initial_b / 40;
+
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+
+        // swap not initialized
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong owner for swap account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            let old_swap_account = accounts.swap_account;
+            let mut wrong_swap_account = old_swap_account.clone();
+            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
+            accounts.swap_account = wrong_swap_account;
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+            accounts.swap_account = old_swap_account;
+        }
+
+        // wrong nonce for authority_key
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &TOKEN_PROGRAM_ID,
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
+
+        // not enough pool tokens
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                to_u64(withdraw_amount).unwrap() / 2u64,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount / 2,
+                    minimum_token_b_amount / 2,
+                )
+            );
+        }
+
+        // wrong token a / b accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount.try_into().unwrap(),
+            );
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+        }
+
+        // wrong pool token account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount.try_into().unwrap(),
+            );
+            let (
+                wrong_token_a_key,
+                mut wrong_token_a_account,
+                _token_b_key,
+                _token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                withdraw_amount.try_into().unwrap(),
+                initial_b,
+                withdraw_amount.try_into().unwrap(),
+            );
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &wrong_token_a_key,
+                    &mut wrong_token_a_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+        }
+
+        // wrong pool fee account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                wrong_pool_key,
+                wrong_pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount.try_into().unwrap(),
+            );
+            let (
+                _token_a_key,
+                _token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount.try_into().unwrap(),
+            );
+            let old_pool_fee_account = accounts.pool_fee_account;
+            let old_pool_fee_key = accounts.pool_fee_key;
+            accounts.pool_fee_account = wrong_pool_account;
+            accounts.pool_fee_key = wrong_pool_key;
+            assert_eq!(
+                Err(SwapError::IncorrectFeeAccount.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                ),
+            );
+            accounts.pool_fee_account = old_pool_fee_account;
+            accounts.pool_fee_key = old_pool_fee_key;
+        }
+
+        // no approval
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                0,
+                0,
+                withdraw_amount.try_into().unwrap(),
+            );
+            let user_transfer_authority_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(TokenError::OwnerMismatch.into()),
+                do_process_instruction(
+                    withdraw_all_token_types(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &user_transfer_authority_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &pool_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_a_key,
+                        &token_b_key,
+                        WithdrawAllTokenTypes {
+                            pool_token_amount: withdraw_amount.try_into().unwrap(),
+                            minimum_token_a_amount,
+                            minimum_token_b_amount,
+                        }
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_a_account,
+                        &mut token_b_account,
+                        &mut accounts.pool_fee_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // wrong token program id
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                withdraw_amount.try_into().unwrap(),
+            );
+            let wrong_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                do_process_instruction(
+                    withdraw_all_token_types(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_key,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.authority_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &pool_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_a_key,
+                        &token_b_key,
+                        WithdrawAllTokenTypes {
+                            pool_token_amount: withdraw_amount.try_into().unwrap(),
+                            minimum_token_a_amount,
+                            minimum_token_b_amount,
+                        },
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_a_account,
+                        &mut token_b_account,
+                        &mut accounts.pool_fee_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+
+            let old_a_key = accounts.token_a_key;
+            let old_a_account = accounts.token_a_account;
+
+            accounts.token_a_key = token_a_key;
+            accounts.token_a_account = token_a_account.clone();
+
+            // wrong swap token a account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+
+            accounts.token_a_key = old_a_key;
+            accounts.token_a_account = old_a_account;
+
+            let old_b_key = accounts.token_b_key;
+            let old_b_account = accounts.token_b_account;
+
+            accounts.token_b_key = token_b_key;
+            accounts.token_b_account = token_b_account.clone();
+
+            // wrong swap token b account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+
+            accounts.token_b_key = old_b_key;
+            accounts.token_b_account = old_b_account;
+        }
+
+        // wrong mint
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+            let (pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
+            assert_eq!(
+                Err(SwapError::IncorrectPoolMint.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // withdrawing 1 pool token fails because it equates to 0 output tokens
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+            assert_eq!(
+                Err(SwapError::ZeroTradingTokens.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    1,
+                    0,
+                    0,
+                )
+            );
+        }
+
+        // slippage exceeded
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+            // minimum A amount out too high
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount * 10,
+                    minimum_token_b_amount,
+                )
+            );
+            // minimum B amount out too high
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount * 10,
+                )
+            );
+        }
+
+        // invalid input: can't use swap pool tokens as destination
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+            let swap_token_a_key = accounts.token_a_key;
+            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &swap_token_a_key,
+                    &mut swap_token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+            let swap_token_b_key = accounts.token_b_key;
+            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_b_key,
+                    &mut swap_token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+            );
+        }
+
+        // correct withdrawal
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+
+            accounts
+                .withdraw_all_token_types(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    withdraw_amount.try_into().unwrap(),
+                    minimum_token_a_amount,
+                    minimum_token_b_amount,
+                )
+                .unwrap();
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            let swap_token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            let pool_mint =
+                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+            let withdraw_fee = accounts.fees.owner_withdraw_fee(withdraw_amount).unwrap();
+            let results = accounts
+                .swap_curve
+                .calculator
+                .pool_tokens_to_trading_tokens(
+                    withdraw_amount - withdraw_fee,
+                    pool_mint.supply.try_into().unwrap(),
+                    swap_token_a.amount.try_into().unwrap(),
+                    swap_token_b.amount.try_into().unwrap(),
+                    RoundDirection::Floor,
+                )
+                .unwrap();
+            assert_eq!(
+                swap_token_a.amount,
+                token_a_amount - to_u64(results.token_a_amount).unwrap()
+            );
+            assert_eq!(
+                swap_token_b.amount,
+                token_b_amount - to_u64(results.token_b_amount).unwrap()
+            );
+            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+            assert_eq!(
+                token_a.amount,
+                initial_a + to_u64(results.token_a_amount).unwrap()
+            );
+            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+            assert_eq!(
+                token_b.amount,
+                initial_b + to_u64(results.token_b_amount).unwrap()
+            );
+            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+            assert_eq!(
+                pool_account.amount,
+                to_u64(initial_pool - withdraw_amount).unwrap()
+            );
+            let fee_account =
+                spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+            assert_eq!(
+                fee_account.amount,
+                TryInto::<u64>::try_into(withdraw_fee).unwrap()
+            );
+        }
+
+        // correct withdrawal from fee account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, 0);
+
+            let pool_fee_key = accounts.pool_fee_key;
+            let mut pool_fee_account = accounts.pool_fee_account.clone();
+            let fee_account = spl_token::state::Account::unpack(&pool_fee_account.data).unwrap();
+            let pool_fee_amount = fee_account.amount;
+
+            accounts
+                .withdraw_all_token_types(
+                    &user_key,
+                    &pool_fee_key,
+                    &mut pool_fee_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    pool_fee_amount,
+                    0,
+                    0,
+                )
+                .unwrap();
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            let swap_token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            let pool_mint =
+                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+            let results = accounts
+                .swap_curve
+                .calculator
+                .pool_tokens_to_trading_tokens(
+                    pool_fee_amount.try_into().unwrap(),
+                    pool_mint.supply.try_into().unwrap(),
+                    swap_token_a.amount.try_into().unwrap(),
+                    swap_token_b.amount.try_into().unwrap(),
+                    RoundDirection::Floor,
+                )
+                .unwrap();
+            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+            assert_eq!(
+                token_a.amount,
+                TryInto::<u64>::try_into(results.token_a_amount).unwrap()
+            );
+            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+            assert_eq!(
+                token_b.amount,
+                TryInto::<u64>::try_into(results.token_b_amount).unwrap()
+            );
         }
+    }
 
-        // wrong owner key in constraint
+    #[test]
+    fn test_deposit_one_exact_in() {
+        let user_key = Pubkey::new_unique();
+        let depositor_key = Pubkey::new_unique();
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 2;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 10;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 5;
+        let host_fee_numerator = 20;
+        let host_fee_denominator = 100;
+
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
+
+        let token_a_amount = 1000;
+        let token_b_amount = 9000;
+        let curve_type = CurveType::ConstantProduct;
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+
+        let deposit_a = token_a_amount / 10;
+        let deposit_b = token_b_amount / 10;
+        let pool_amount = to_u64(INITIAL_SWAP_POOL_AMOUNT / 100).unwrap();
+
+        // swap not initialized
         {
-            let new_key = Pubkey::new_unique();
-            let trade_fee_numerator = 25;
-            let trade_fee_denominator = 10000;
-            let owner_trade_fee_numerator = 5;
-            let owner_trade_fee_denominator = 10000;
-            let host_fee_numerator = 20;
-            let host_fee_denominator = 100;
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let curve = ConstantProductCurve {};
-            let swap_curve = SwapCurve {
-                curve_type: CurveType::ConstantProduct,
-                calculator: Box::new(curve),
-            };
-            let owner_key = &new_key.to_string();
-            let valid_curve_types = &[CurveType::ConstantProduct];
-            let constraints = Some(SwapConstraints {
-                owner_key,
-                valid_curve_types,
-                fees: &fees,
-            });
-            let mut accounts = SwapAccountInfo::new(
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong owner for swap account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let old_swap_account = accounts.swap_account;
+            let mut wrong_swap_account = old_swap_account.clone();
+            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
+            accounts.swap_account = wrong_swap_account;
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+            accounts.swap_account = old_swap_account;
+        }
+
+        // wrong nonce for authority_key
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &TOKEN_PROGRAM_ID,
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
+
+        // not enough token A / B
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
                 &user_key,
-                fees.clone(),
-                swap_curve,
-                token_a_amount,
-                token_b_amount,
+                &depositor_key,
+                deposit_a / 2,
+                deposit_b / 2,
+                0,
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    0,
+                )
+            );
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_b,
+                    0,
+                )
+            );
+        }
+
+        // wrong pool token account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                mut _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            assert_eq!(
+                Err(TokenError::MintMismatch.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    deposit_a,
+                    pool_amount,
+                )
             );
+        }
+
+        // no approval
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let user_transfer_authority_key = Pubkey::new_unique();
             assert_eq!(
-                Err(SwapError::InvalidOwner.into()),
-                do_process_instruction_with_fee_constraints(
-                    initialize(
+                Err(TokenError::OwnerMismatch.into()),
+                do_process_instruction(
+                    deposit_single_token_type_exact_amount_in(
                         &SWAP_PROGRAM_ID,
                         &TOKEN_PROGRAM_ID,
                         &accounts.swap_key,
                         &accounts.authority_key,
+                        &user_transfer_authority_key,
+                        &token_a_key,
                         &accounts.token_a_key,
                         &accounts.token_b_key,
                         &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &accounts.pool_token_key,
-                        accounts.nonce,
-                        accounts.fees.clone(),
-                        accounts.swap_curve.clone(),
+                        &pool_key,
+                        DepositSingleTokenTypeExactAmountIn {
+                            source_token_amount: deposit_a,
+                            minimum_pool_token_amount: pool_amount,
+                        },
                     )
                     .unwrap(),
                     vec![
                         &mut accounts.swap_account,
                         &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
                         &mut accounts.token_a_account,
                         &mut accounts.token_b_account,
                         &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut accounts.pool_token_account,
+                        &mut pool_account,
                         &mut Account::default(),
                     ],
-                    &constraints,
                 )
             );
         }
 
-        // wrong fee in constraint
+        // wrong token program id
         {
-            let trade_fee_numerator = 25;
-            let trade_fee_denominator = 10000;
-            let owner_trade_fee_numerator = 5;
-            let owner_trade_fee_denominator = 10000;
-            let host_fee_numerator = 20;
-            let host_fee_denominator = 100;
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let curve = ConstantProductCurve {};
-            let swap_curve = SwapCurve {
-                curve_type: CurveType::ConstantProduct,
-                calculator: Box::new(curve),
-            };
-            let owner_key = &user_key.to_string();
-            let valid_curve_types = &[CurveType::ConstantProduct];
-            let constraints = Some(SwapConstraints {
-                owner_key,
-                valid_curve_types,
-                fees: &fees,
-            });
-            let mut bad_fees = fees.clone();
-            bad_fees.trade_fee_numerator = trade_fee_numerator - 1;
-            let mut accounts = SwapAccountInfo::new(
-                &user_key,
-                bad_fees,
-                swap_curve,
-                token_a_amount,
-                token_b_amount,
-            );
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let wrong_key = Pubkey::new_unique();
             assert_eq!(
-                Err(SwapError::InvalidFee.into()),
-                do_process_instruction_with_fee_constraints(
-                    initialize(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                do_process_instruction(
+                    deposit_single_token_type_exact_amount_in(
                         &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
+                        &wrong_key,
                         &accounts.swap_key,
                         &accounts.authority_key,
+                        &accounts.authority_key,
+                        &token_a_key,
                         &accounts.token_a_key,
                         &accounts.token_b_key,
                         &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &accounts.pool_token_key,
-                        accounts.nonce,
-                        accounts.fees.clone(),
-                        accounts.swap_curve.clone(),
+                        &pool_key,
+                        DepositSingleTokenTypeExactAmountIn {
+                            source_token_amount: deposit_a,
+                            minimum_pool_token_amount: pool_amount,
+                        },
                     )
                     .unwrap(),
                     vec![
                         &mut accounts.swap_account,
                         &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
                         &mut accounts.token_a_account,
                         &mut accounts.token_b_account,
                         &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut accounts.pool_token_account,
+                        &mut pool_account,
                         &mut Account::default(),
                     ],
-                    &constraints,
                 )
             );
         }
 
-        // create valid swap with constraints
+        // wrong swap token accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+
+            let old_a_key = accounts.token_a_key;
+            let old_a_account = accounts.token_a_account;
+
+            accounts.token_a_key = token_a_key;
+            accounts.token_a_account = token_a_account.clone();
+
+            // wrong swap token a account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+
+            accounts.token_a_key = old_a_key;
+            accounts.token_a_account = old_a_account;
+
+            let old_b_key = accounts.token_b_key;
+            let old_b_account = accounts.token_b_account;
+
+            accounts.token_b_key = token_b_key;
+            accounts.token_b_account = token_b_account;
+
+            // wrong swap token b account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+
+            accounts.token_b_key = old_b_key;
+            accounts.token_b_account = old_b_account;
+        }
+
+        // wrong mint
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let (pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
+            assert_eq!(
+                Err(SwapError::IncorrectPoolMint.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // slippage exceeded
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            // minimum pool amount too high
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a / 10,
+                    pool_amount,
+                )
+            );
+            // minimum pool amount too high
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_b / 10,
+                    pool_amount,
+                )
+            );
+        }
+
+        // invalid input: can't use swap pool tokens as source
+        {
+            let (
+                _token_a_key,
+                _token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let swap_token_a_key = accounts.token_a_key;
+            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
+            let swap_token_b_key = accounts.token_b_key;
+            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
+            let authority_key = accounts.authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &authority_key,
+                    &swap_token_a_key,
+                    &mut swap_token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
+                )
+            );
+            assert_eq!(
+                Err(SwapError::InvalidInput.into()),
+                accounts.deposit_single_token_type_exact_amount_in(
+                    &authority_key,
+                    &swap_token_b_key,
+                    &mut swap_token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_b,
+                    pool_amount,
+                )
+            );
+        }
+
+        // correctly deposit
         {
-            let trade_fee_numerator = 25;
-            let trade_fee_denominator = 10000;
-            let owner_trade_fee_numerator = 5;
-            let owner_trade_fee_denominator = 10000;
-            let host_fee_numerator = 20;
-            let host_fee_denominator = 100;
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let curve = ConstantProductCurve {};
-            let swap_curve = SwapCurve {
-                curve_type: CurveType::ConstantProduct,
-                calculator: Box::new(curve),
-            };
-            let owner_key = &user_key.to_string();
-            let valid_curve_types = &[CurveType::ConstantProduct];
-            let constraints = Some(SwapConstraints {
-                owner_key,
-                valid_curve_types,
-                fees: &fees,
-            });
-            let mut accounts = SwapAccountInfo::new(
-                &user_key,
-                fees.clone(),
-                swap_curve,
-                token_a_amount,
-                token_b_amount,
-            );
-            do_process_instruction_with_fee_constraints(
-                initialize(
-                    &SWAP_PROGRAM_ID,
-                    &TOKEN_PROGRAM_ID,
-                    &accounts.swap_key,
-                    &accounts.authority_key,
-                    &accounts.token_a_key,
-                    &accounts.token_b_key,
-                    &accounts.pool_mint_key,
-                    &accounts.pool_fee_key,
-                    &accounts.pool_token_key,
-                    accounts.nonce,
-                    accounts.fees,
-                    accounts.swap_curve.clone(),
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            accounts
+                .deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_a,
+                    pool_amount,
                 )
-                .unwrap(),
-                vec![
-                    &mut accounts.swap_account,
-                    &mut Account::default(),
-                    &mut accounts.token_a_account,
-                    &mut accounts.token_b_account,
-                    &mut accounts.pool_mint_account,
-                    &mut accounts.pool_fee_account,
-                    &mut accounts.pool_token_account,
-                    &mut Account::default(),
-                ],
-                &constraints,
-            )
-            .unwrap();
-        }
+                .unwrap();
 
-        // create again
-        {
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            assert_eq!(swap_token_a.amount, deposit_a + token_a_amount);
+
+            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, 0);
+
+            accounts
+                .deposit_single_token_type_exact_amount_in(
+                    &depositor_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    &pool_key,
+                    &mut pool_account,
+                    deposit_b,
+                    pool_amount,
+                )
+                .unwrap();
+            let swap_token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            assert_eq!(swap_token_b.amount, deposit_b + token_b_amount);
+
+            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+            assert_eq!(token_b.amount, 0);
+
+            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+            let swap_pool_account =
+                spl_token::state::Account::unpack(&accounts.pool_token_account.data).unwrap();
+            let pool_mint =
+                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
             assert_eq!(
-                Err(SwapError::AlreadyInUse.into()),
-                accounts.initialize_swap()
+                pool_mint.supply,
+                pool_account.amount + swap_pool_account.amount
             );
         }
-        let swap_state = SwapVersion::unpack(&accounts.swap_account.data).unwrap();
-        assert_eq!(swap_state.is_initialized(), true);
-        assert_eq!(swap_state.nonce(), accounts.nonce);
-        assert_eq!(
-            swap_state.swap_curve().curve_type,
-            accounts.swap_curve.curve_type
-        );
-        assert_eq!(*swap_state.token_a_account(), accounts.token_a_key);
-        assert_eq!(*swap_state.token_b_account(), accounts.token_b_key);
-        assert_eq!(*swap_state.pool_mint(), accounts.pool_mint_key);
-        assert_eq!(*swap_state.token_a_mint(), accounts.token_a_mint_key);
-        assert_eq!(*swap_state.token_b_mint(), accounts.token_b_mint_key);
-        assert_eq!(*swap_state.pool_fee_account(), accounts.pool_fee_key);
-        let token_a = spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-        assert_eq!(token_a.amount, token_a_amount);
-        let token_b = spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-        assert_eq!(token_b.amount, token_b_amount);
-        let pool_account =
-            spl_token::state::Account::unpack(&accounts.pool_token_account.data).unwrap();
-        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-        assert_eq!(pool_mint.supply, pool_account.amount);
     }
 
     #[test]
-    fn test_deposit() {
+    fn test_withdraw_one_exact_out() {
         let user_key = Pubkey::new_unique();
-        let depositor_key = Pubkey::new_unique();
         let trade_fee_numerator = 1;
         let trade_fee_denominator = 2;
         let owner_trade_fee_numerator = 1;
         let owner_trade_fee_denominator = 10;
         let owner_withdraw_fee_numerator = 1;
         let owner_withdraw_fee_denominator = 5;
-        let host_fee_numerator = 20;
+        let host_fee_numerator = 7;
         let host_fee_denominator = 100;
 
         let fees = Fees {
@@ -2850,85 +8859,330 @@ mod tests {
             host_fee_denominator,
         };
 
-        let token_a_amount = 1000;
-        let token_b_amount = 9000;
+        let token_a_amount = 100_000;
+        let token_b_amount = 200_000;
         let curve_type = CurveType::ConstantProduct;
         let swap_curve = SwapCurve {
             curve_type,
             calculator: Box::new(ConstantProductCurve {}),
         };
 
+        let withdrawer_key = Pubkey::new_unique();
+        let initial_a = token_a_amount / 10;
+        let initial_b = token_b_amount / 10;
+        let initial_pool = swap_curve.calculator.new_pool_supply() / 10;
+        let maximum_pool_token_amount = to_u64(initial_pool / 4).unwrap();
+        let destination_a_amount = initial_a / 40;
+        let destination_b_amount = initial_b / 40;
+
         let mut accounts =
             SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
 
-        // depositing 10% of the current pool amount in token A and B means
-        // that our pool tokens will be worth 1 / 10 of the current pool amount
-        let pool_amount = INITIAL_SWAP_POOL_AMOUNT / 10;
-        let deposit_a = token_a_amount / 10;
-        let deposit_b = token_b_amount / 10;
+        // swap not initialized
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(ProgramError::UninitializedAccount),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
+                )
+            );
+        }
+
+        accounts.initialize_swap().unwrap();
+
+        // wrong owner for swap account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            let old_swap_account = accounts.swap_account;
+            let mut wrong_swap_account = old_swap_account.clone();
+            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
+            accounts.swap_account = wrong_swap_account;
+            assert_eq!(
+                Err(ProgramError::IncorrectProgramId),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
+                )
+            );
+            accounts.swap_account = old_swap_account;
+        }
+
+        // wrong nonce for authority_key
+        {
+            let (
+                _token_a_key,
+                _token_a_account,
+                token_b_key,
+                mut token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+            let old_authority = accounts.authority_key;
+            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
+                &[&accounts.swap_key.to_bytes()[..]],
+                &TOKEN_PROGRAM_ID,
+            );
+            accounts.authority_key = bad_authority_key;
+            assert_eq!(
+                Err(SwapError::InvalidProgramAddress.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_b_key,
+                    &mut token_b_account,
+                    destination_b_amount,
+                    maximum_pool_token_amount,
+                )
+            );
+            accounts.authority_key = old_authority;
+        }
 
-        // swap not initialized
+        // not enough pool tokens
         {
             let (
-                token_a_key,
-                mut token_a_account,
+                _token_a_key,
+                _token_a_account,
                 token_b_key,
                 mut token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                maximum_pool_token_amount / 1000,
+            );
             assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                    &token_b_key,
+                    &mut token_b_account,
+                    destination_b_amount,
+                    maximum_pool_token_amount,
                 )
             );
         }
 
-        accounts.initialize_swap().unwrap();
-
-        // wrong owner for swap account
+        // wrong pool token account
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let old_swap_account = accounts.swap_account;
-            let mut wrong_swap_account = old_swap_account.clone();
-            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
-            accounts.swap_account = wrong_swap_account;
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                maximum_pool_token_amount,
+                initial_b,
+                maximum_pool_token_amount,
+            );
             assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
+                Err(TokenError::MintMismatch.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
+                    destination_b_amount,
+                    maximum_pool_token_amount,
+                )
+            );
+        }
+
+        // wrong pool fee account
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                wrong_pool_key,
+                wrong_pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                maximum_pool_token_amount,
+            );
+            let (
+                _token_a_key,
+                _token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                maximum_pool_token_amount,
+            );
+            let old_pool_fee_account = accounts.pool_fee_account;
+            let old_pool_fee_key = accounts.pool_fee_key;
+            accounts.pool_fee_account = wrong_pool_account;
+            accounts.pool_fee_key = wrong_pool_key;
+            assert_eq!(
+                Err(SwapError::IncorrectFeeAccount.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                    &token_a_key,
+                    &mut token_a_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
                 )
             );
-            accounts.swap_account = old_swap_account;
+            accounts.pool_fee_account = old_pool_fee_account;
+            accounts.pool_fee_key = old_pool_fee_key;
         }
 
-        // wrong nonce for authority_key
+        // no approval
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                0,
+                0,
+                maximum_pool_token_amount,
+            );
+            let user_transfer_authority_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(TokenError::OwnerMismatch.into()),
+                do_process_instruction(
+                    withdraw_single_token_type_exact_amount_out(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &user_transfer_authority_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &pool_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_a_key,
+                        WithdrawSingleTokenTypeExactAmountOut {
+                            destination_token_amount: destination_a_amount,
+                            maximum_pool_token_amount,
+                        }
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_a_account,
+                        &mut accounts.pool_fee_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // wrong token program id
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                maximum_pool_token_amount,
+            );
+            let wrong_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(SwapError::IncorrectTokenProgramId.into()),
+                do_process_instruction(
+                    withdraw_single_token_type_exact_amount_out(
+                        &SWAP_PROGRAM_ID,
+                        &wrong_key,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.authority_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        &pool_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_a_key,
+                        WithdrawSingleTokenTypeExactAmountOut {
+                            destination_token_amount: destination_a_amount,
+                            maximum_pool_token_amount,
+                        }
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut accounts.pool_mint_account,
+                        &mut pool_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_a_account,
+                        &mut accounts.pool_fee_account,
+                        &mut Account::default(),
+                    ],
+                )
+            );
+        }
+
+        // wrong swap token accounts
         {
             let (
                 token_a_key,
@@ -2937,65 +9191,102 @@ mod tests {
                 mut token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
-                &TOKEN_PROGRAM_ID,
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
             );
-            accounts.authority_key = bad_authority_key;
+
+            let old_a_key = accounts.token_a_key;
+            let old_a_account = accounts.token_a_account;
+
+            accounts.token_a_key = token_a_key;
+            accounts.token_a_account = token_a_account.clone();
+
+            // wrong swap token a account
             assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
                     &token_a_key,
                     &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
+                )
+            );
+
+            accounts.token_a_key = old_a_key;
+            accounts.token_a_account = old_a_account;
+
+            let old_b_key = accounts.token_b_key;
+            let old_b_account = accounts.token_b_account;
+
+            accounts.token_b_key = token_b_key;
+            accounts.token_b_account = token_b_account.clone();
+
+            // wrong swap token b account
+            assert_eq!(
+                Err(SwapError::IncorrectSwapAccount.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                    &token_b_key,
+                    &mut token_b_account,
+                    destination_b_amount,
+                    maximum_pool_token_amount,
                 )
             );
-            accounts.authority_key = old_authority;
+
+            accounts.token_b_key = old_b_key;
+            accounts.token_b_account = old_b_account;
         }
 
-        // not enough token A
+        // wrong mint
         {
             let (
                 token_a_key,
                 mut token_a_account,
-                token_b_key,
-                mut token_b_account,
+                _token_b_key,
+                _token_b_account,
                 pool_key,
                 mut pool_account,
             ) = accounts.setup_token_accounts(
                 &user_key,
-                &depositor_key,
-                deposit_a / 2,
-                deposit_b,
-                0,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
             );
+            let (pool_mint_key, pool_mint_account) =
+                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
+            let old_pool_key = accounts.pool_mint_key;
+            let old_pool_account = accounts.pool_mint_account;
+            accounts.pool_mint_key = pool_mint_key;
+            accounts.pool_mint_account = pool_mint_account;
+
             assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
+                Err(SwapError::IncorrectPoolMint.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                    &token_a_key,
+                    &mut token_a_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
                 )
             );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
         }
 
-        // not enough token B
+        // slippage exceeded
         {
             let (
                 token_a_key,
@@ -3006,451 +9297,659 @@ mod tests {
                 mut pool_account,
             ) = accounts.setup_token_accounts(
                 &user_key,
-                &depositor_key,
-                deposit_a,
-                deposit_b / 2,
-                0,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                maximum_pool_token_amount,
             );
+
+            // maximum pool token amount too low
             assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
                     &token_a_key,
                     &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount / 1000,
+                )
+            );
+            assert_eq!(
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                    &token_b_key,
+                    &mut token_b_account,
+                    destination_b_amount,
+                    maximum_pool_token_amount / 1000,
                 )
             );
         }
 
-        // wrong swap token accounts
+        // invalid input: can't use swap pool tokens as destination
         {
             let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
+                _token_a_key,
+                _token_a_account,
+                _token_b_key,
+                _token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                maximum_pool_token_amount,
+            );
+            let swap_token_a_key = accounts.token_a_key;
+            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
             assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &token_a_key,
-                    &mut token_a_account,
+                Err(SwapError::InvalidInput.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
                     &pool_key,
                     &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                    &swap_token_a_key,
+                    &mut swap_token_a_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
                 )
             );
-        }
-
-        // wrong pool token account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let (
-                wrong_token_key,
-                mut wrong_token_account,
-                _token_b_key,
-                mut _token_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+            let swap_token_b_key = accounts.token_b_key;
+            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
             assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &wrong_token_key,
-                    &mut wrong_token_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
+                Err(SwapError::InvalidInput.into()),
+                accounts.withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &swap_token_b_key,
+                    &mut swap_token_b_account,
+                    destination_b_amount,
+                    maximum_pool_token_amount,
                 )
             );
         }
 
-        // no approval
+        // correct withdrawal
         {
             let (
                 token_a_key,
                 mut token_a_account,
-                token_b_key,
-                mut token_b_account,
+                _token_b_key,
+                _token_b_account,
                 pool_key,
                 mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let user_transfer_authority_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(TokenError::OwnerMismatch.into()),
-                do_process_instruction(
-                    deposit_all_token_types(
-                        &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &user_transfer_authority_key,
-                        &token_a_key,
-                        &token_b_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        DepositAllTokenTypes {
-                            pool_token_amount: pool_amount.try_into().unwrap(),
-                            maximum_token_a_amount: deposit_a,
-                            maximum_token_b_amount: deposit_b,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut token_b_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                    ],
+            ) = accounts.setup_token_accounts(
+                &user_key,
+                &withdrawer_key,
+                initial_a,
+                initial_b,
+                initial_pool.try_into().unwrap(),
+            );
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            let swap_token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            let pool_mint =
+                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+
+            let pool_token_amount = accounts
+                .swap_curve
+                .trading_tokens_to_pool_tokens(
+                    destination_a_amount.try_into().unwrap(),
+                    (swap_token_a.amount - destination_a_amount)
+                        .try_into()
+                        .unwrap(),
+                    swap_token_b.amount.try_into().unwrap(),
+                    pool_mint.supply.try_into().unwrap(),
+                    TradeDirection::AtoB,
+                    RoundDirection::Ceiling,
+                    &accounts.fees,
+                )
+                .unwrap();
+            let withdraw_fee = accounts.fees.owner_withdraw_fee(pool_token_amount).unwrap();
+
+            accounts
+                .withdraw_single_token_type_exact_amount_out(
+                    &withdrawer_key,
+                    &pool_key,
+                    &mut pool_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    destination_a_amount,
+                    maximum_pool_token_amount,
                 )
+                .unwrap();
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+
+            assert_eq!(swap_token_a.amount, token_a_amount - destination_a_amount);
+            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a + destination_a_amount);
+
+            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+            assert_eq!(
+                pool_account.amount,
+                to_u64(initial_pool - pool_token_amount - withdraw_fee).unwrap()
             );
+            let fee_account =
+                spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+            assert_eq!(fee_account.amount, to_u64(withdraw_fee).unwrap());
         }
 
-        // wrong token program id
+        // correct withdrawal from fee account
         {
             let (
                 token_a_key,
                 mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let wrong_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                do_process_instruction(
-                    deposit_all_token_types(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_key,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &accounts.authority_key,
-                        &token_a_key,
-                        &token_b_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        DepositAllTokenTypes {
-                            pool_token_amount: pool_amount.try_into().unwrap(),
-                            maximum_token_a_amount: deposit_a,
-                            maximum_token_b_amount: deposit_b,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut token_b_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                    ],
+                _token_b_key,
+                _token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+
+            let fee_a_amount = 2;
+            let pool_fee_key = accounts.pool_fee_key;
+            let mut pool_fee_account = accounts.pool_fee_account.clone();
+            let fee_account = spl_token::state::Account::unpack(&pool_fee_account.data).unwrap();
+            let pool_fee_amount = fee_account.amount;
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+
+            let token_a_amount = swap_token_a.amount;
+            accounts
+                .withdraw_single_token_type_exact_amount_out(
+                    &user_key,
+                    &pool_fee_key,
+                    &mut pool_fee_account,
+                    &token_a_key,
+                    &mut token_a_account,
+                    fee_a_amount,
+                    pool_fee_amount,
                 )
-            );
+                .unwrap();
+
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+
+            assert_eq!(swap_token_a.amount, token_a_amount - fee_a_amount);
+            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+            assert_eq!(token_a.amount, initial_a + fee_a_amount);
         }
+    }
+
+    fn check_valid_swap_curve(
+        fees: Fees,
+        curve_type: CurveType,
+        calculator: Box<dyn CurveCalculator>,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    ) {
+        let user_key = Pubkey::new_unique();
+        let swapper_key = Pubkey::new_unique();
+
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator,
+        };
+
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            fees.clone(),
+            swap_curve.clone(),
+            token_a_amount,
+            token_b_amount,
+        );
+        let initial_a = token_a_amount / 5;
+        let initial_b = token_b_amount / 5;
+        accounts.initialize_swap().unwrap();
+
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+        // swap one way
+        let a_to_b_amount = initial_a / 10;
+        let minimum_token_b_amount = 0;
+        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        let initial_supply = pool_mint.supply;
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                a_to_b_amount,
+                minimum_token_b_amount,
+            )
+            .unwrap();
+
+        let results = swap_curve
+            .swap(
+                a_to_b_amount.try_into().unwrap(),
+                token_a_amount.try_into().unwrap(),
+                token_b_amount.try_into().unwrap(),
+                TradeDirection::AtoB,
+                &fees,
+            )
+            .unwrap();
+
+        let swap_token_a =
+            spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+        let token_a_amount = swap_token_a.amount;
+        assert_eq!(
+            token_a_amount,
+            TryInto::<u64>::try_into(results.new_swap_source_amount).unwrap()
+        );
+        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, initial_a - a_to_b_amount);
+
+        let swap_token_b =
+            spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+        let token_b_amount = swap_token_b.amount;
+        assert_eq!(
+            token_b_amount,
+            TryInto::<u64>::try_into(results.new_swap_destination_amount).unwrap()
+        );
+        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        assert_eq!(
+            token_b.amount,
+            initial_b + to_u64(results.destination_amount_swapped).unwrap()
+        );
+
+        let first_fee = swap_curve
+            .trading_tokens_to_pool_tokens(
+                results.owner_fee,
+                token_a_amount.try_into().unwrap(),
+                token_b_amount.try_into().unwrap(),
+                initial_supply.try_into().unwrap(),
+                TradeDirection::AtoB,
+                RoundDirection::Ceiling,
+                &fees,
+            )
+            .unwrap();
+        let fee_account =
+            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        assert_eq!(
+            fee_account.amount,
+            TryInto::<u64>::try_into(first_fee).unwrap()
+        );
+
+        let first_swap_amount = results.destination_amount_swapped;
+
+        // swap the other way
+        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        let initial_supply = pool_mint.supply;
+
+        let b_to_a_amount = initial_b / 10;
+        let minimum_a_amount = 0;
+        accounts
+            .swap(
+                &swapper_key,
+                &token_b_key,
+                &mut token_b_account,
+                &swap_token_b_key,
+                &swap_token_a_key,
+                &token_a_key,
+                &mut token_a_account,
+                b_to_a_amount,
+                minimum_a_amount,
+            )
+            .unwrap();
+
+        let results = swap_curve
+            .swap(
+                b_to_a_amount.try_into().unwrap(),
+                token_b_amount.try_into().unwrap(),
+                token_a_amount.try_into().unwrap(),
+                TradeDirection::BtoA,
+                &fees,
+            )
+            .unwrap();
+
+        let swap_token_a =
+            spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+        let token_a_amount = swap_token_a.amount;
+        assert_eq!(
+            token_a_amount,
+            TryInto::<u64>::try_into(results.new_swap_destination_amount).unwrap()
+        );
+        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+        assert_eq!(
+            token_a.amount,
+            initial_a - a_to_b_amount + to_u64(results.destination_amount_swapped).unwrap()
+        );
 
-        // wrong swap token accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+        let swap_token_b =
+            spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+        let token_b_amount = swap_token_b.amount;
+        assert_eq!(
+            token_b_amount,
+            TryInto::<u64>::try_into(results.new_swap_source_amount).unwrap()
+        );
+        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        assert_eq!(
+            token_b.amount,
+            initial_b + to_u64(first_swap_amount).unwrap()
+                - to_u64(results.source_amount_swapped).unwrap()
+        );
 
-            let old_a_key = accounts.token_a_key;
-            let old_a_account = accounts.token_a_account;
+        let second_fee = swap_curve
+            .trading_tokens_to_pool_tokens(
+                results.owner_fee,
+                token_a_amount.try_into().unwrap(),
+                token_b_amount.try_into().unwrap(),
+                initial_supply.try_into().unwrap(),
+                TradeDirection::BtoA,
+                RoundDirection::Ceiling,
+                &fees,
+            )
+            .unwrap();
+        let fee_account =
+            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        assert_eq!(fee_account.amount, to_u64(first_fee + second_fee).unwrap());
+    }
 
-            accounts.token_a_key = token_a_key;
-            accounts.token_a_account = token_a_account.clone();
+    #[test]
+    fn test_valid_swap_curves_all_fees() {
+        // All fees
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 10;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 30;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 30;
+        let host_fee_numerator = 20;
+        let host_fee_denominator = 100;
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
 
-            // wrong swap token a account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
-                )
-            );
+        let token_a_amount = 10_000_000_000;
+        let token_b_amount = 50_000_000_000;
 
-            accounts.token_a_key = old_a_key;
-            accounts.token_a_account = old_a_account;
+        check_valid_swap_curve(
+            fees.clone(),
+            CurveType::ConstantProduct,
+            Box::new(ConstantProductCurve {}),
+            token_a_amount,
+            token_b_amount,
+        );
+        let token_b_price = 1;
+        check_valid_swap_curve(
+            fees.clone(),
+            CurveType::ConstantPrice,
+            Box::new(ConstantPriceCurve { token_b_price }),
+            token_a_amount,
+            token_b_amount,
+        );
+        let token_b_offset = 10_000_000_000;
+        check_valid_swap_curve(
+            fees.clone(),
+            CurveType::Offset,
+            Box::new(OffsetCurve { token_b_offset }),
+            token_a_amount,
+            token_b_amount,
+        );
+        check_valid_swap_curve(
+            fees,
+            CurveType::Weighted,
+            Box::new(WeightedCurve {
+                token_a_weight: 80,
+                token_b_weight: 20,
+            }),
+            token_a_amount,
+            token_b_amount,
+        );
+    }
 
-            let old_b_key = accounts.token_b_key;
-            let old_b_account = accounts.token_b_account;
+    #[test]
+    fn test_valid_swap_curves_trade_fee_only() {
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 10;
+        let owner_trade_fee_numerator = 0;
+        let owner_trade_fee_denominator = 0;
+        let owner_withdraw_fee_numerator = 0;
+        let owner_withdraw_fee_denominator = 0;
+        let host_fee_numerator = 0;
+        let host_fee_denominator = 0;
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
 
-            accounts.token_b_key = token_b_key;
-            accounts.token_b_account = token_b_account.clone();
+        let token_a_amount = 10_000_000_000;
+        let token_b_amount = 50_000_000_000;
 
-            // wrong swap token b account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
-                )
-            );
+        check_valid_swap_curve(
+            fees.clone(),
+            CurveType::ConstantProduct,
+            Box::new(ConstantProductCurve {}),
+            token_a_amount,
+            token_b_amount,
+        );
+        let token_b_price = 10_000;
+        check_valid_swap_curve(
+            fees.clone(),
+            CurveType::ConstantPrice,
+            Box::new(ConstantPriceCurve { token_b_price }),
+            token_a_amount,
+            token_b_amount / token_b_price,
+        );
+        let token_b_offset = 1;
+        check_valid_swap_curve(
+            fees.clone(),
+            CurveType::Offset,
+            Box::new(OffsetCurve { token_b_offset }),
+            token_a_amount,
+            token_b_amount,
+        );
+        check_valid_swap_curve(
+            fees,
+            CurveType::Weighted,
+            Box::new(WeightedCurve {
+                token_a_weight: 20,
+                token_b_weight: 80,
+            }),
+            token_a_amount,
+            token_b_amount,
+        );
+    }
 
-            accounts.token_b_key = old_b_key;
-            accounts.token_b_account = old_b_account;
-        }
+    #[test]
+    fn test_valid_swap_with_fee_constraints() {
+        let owner_key = Pubkey::new_unique();
 
-        // wrong mint
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let (pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 10;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 30;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 30;
+        let host_fee_numerator = 10;
+        let host_fee_denominator = 100;
 
-            assert_eq!(
-                Err(SwapError::IncorrectPoolMint.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
-                )
-            );
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 5_000_000;
+
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
+
+        let curve = ConstantProductCurve {};
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(curve),
+        };
+
+        let owner_key_str = &owner_key.to_string();
+        let valid_curve_types = &[CurveType::ConstantProduct];
+        let constraints = Some(SwapConstraints {
+            owner_key: owner_key_str,
+            valid_curve_types,
+            fees: &fees,
+        });
+        let mut accounts = SwapAccountInfo::new(
+            &owner_key,
+            fees.clone(),
+            swap_curve,
+            token_a_amount,
+            token_b_amount,
+        );
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
-        }
+        // initialize swap
+        do_process_instruction_with_fee_constraints(
+            initialize(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &accounts.swap_key,
+                &accounts.authority_key,
+                &accounts.token_a_key,
+                &accounts.token_b_key,
+                &accounts.pool_mint_key,
+                &accounts.pool_fee_key,
+                &accounts.pool_token_key,
+                None,
+                accounts.nonce,
+                accounts.fees.clone(),
+                accounts.swap_curve.clone(),
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut accounts.pool_mint_account,
+                &mut accounts.pool_fee_account,
+                &mut accounts.pool_token_account,
+                &mut Account::default(),
+            ],
+            &constraints,
+        )
+        .unwrap();
 
-        // deposit 1 pool token fails beacuse it equates to 0 swap tokens
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            assert_eq!(
-                Err(SwapError::ZeroTradingTokens.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    1,
-                    deposit_a,
-                    deposit_b,
-                )
-            );
-        }
+        let authority_key = accounts.authority_key;
 
-        // slippage exceeded
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            // maximum A amount in too low
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a / 10,
-                    deposit_b,
-                )
-            );
-            // maximum B amount in too low
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b / 10,
-                )
-            );
-        }
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(
+            &owner_key,
+            &authority_key,
+            token_a_amount,
+            token_b_amount,
+            0,
+        );
 
-        // invalid input: can't use swap pool tokens as source
-        {
-            let (
-                _token_a_key,
-                _token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let swap_token_a_key = accounts.token_a_key;
-            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
-            let swap_token_b_key = accounts.token_b_key;
-            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
-            let authority_key = accounts.authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.deposit_all_token_types(
-                    &authority_key,
-                    &swap_token_a_key,
-                    &mut swap_token_a_account,
-                    &swap_token_b_key,
-                    &mut swap_token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
-                )
-            );
-        }
+        let amount_in = token_a_amount / 2;
+        let minimum_amount_out = 0;
 
-        // correctly deposit
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            accounts
-                .deposit_all_token_types(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount.try_into().unwrap(),
-                    deposit_a,
-                    deposit_b,
-                )
-                .unwrap();
+        // perform the swap
+        do_process_instruction_with_fee_constraints(
+            swap(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &accounts.swap_key,
+                &accounts.authority_key,
+                &accounts.authority_key,
+                &token_a_key,
+                &accounts.token_a_key,
+                &accounts.token_b_key,
+                &token_b_key,
+                &accounts.pool_mint_key,
+                &accounts.pool_fee_key,
+                Some(&pool_key),
+                Swap {
+                    amount_in,
+                    minimum_amount_out,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut token_a_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut token_b_account,
+                &mut accounts.pool_mint_account,
+                &mut accounts.pool_fee_account,
+                &mut Account::default(),
+                &mut pool_account,
+            ],
+            &constraints,
+        )
+        .unwrap();
 
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-            assert_eq!(swap_token_a.amount, deposit_a + token_a_amount);
-            let swap_token_b =
-                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-            assert_eq!(swap_token_b.amount, deposit_b + token_b_amount);
-            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, 0);
-            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
-            assert_eq!(token_b.amount, 0);
-            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
-            let swap_pool_account =
-                spl_token::state::Account::unpack(&accounts.pool_token_account.data).unwrap();
-            let pool_mint =
-                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-            assert_eq!(
-                pool_mint.supply,
-                pool_account.amount + swap_pool_account.amount
-            );
-        }
+        // check that fees were taken in the host fee account
+        let host_fee_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+        let owner_fee_account =
+            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        let total_fee = owner_fee_account.amount * host_fee_denominator
+            / (host_fee_denominator - host_fee_numerator);
+        assert_eq!(
+            total_fee,
+            host_fee_account.amount + owner_fee_account.amount
+        );
     }
 
     #[test]
-    fn test_withdraw() {
+    fn test_invalid_swap() {
         let user_key = Pubkey::new_unique();
+        let swapper_key = Pubkey::new_unique();
         let trade_fee_numerator = 1;
-        let trade_fee_denominator = 2;
+        let trade_fee_denominator = 4;
         let owner_trade_fee_numerator = 1;
         let owner_trade_fee_denominator = 10;
         let owner_withdraw_fee_numerator = 1;
         let owner_withdraw_fee_denominator = 5;
-        let host_fee_numerator = 7;
+        let host_fee_numerator = 9;
         let host_fee_denominator = 100;
-
         let fees = Fees {
             trade_fee_numerator,
             trade_fee_denominator,
@@ -3463,24 +9962,22 @@ mod tests {
         };
 
         let token_a_amount = 1000;
-        let token_b_amount = 2000;
+        let token_b_amount = 5000;
         let curve_type = CurveType::ConstantProduct;
         let swap_curve = SwapCurve {
             curve_type,
             calculator: Box::new(ConstantProductCurve {}),
         };
-
-        let withdrawer_key = Pubkey::new_unique();
-        let initial_a = token_a_amount / 10;
-        let initial_b = token_b_amount / 10;
-        let initial_pool = swap_curve.calculator.new_pool_supply() / 10;
-        let withdraw_amount = initial_pool / 4;
-        let minimum_token_a_amount = initial_a / 40;
-        let minimum_token_b_amount = initial_b / 40;
-
         let mut accounts =
             SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
 
+        let initial_a = token_a_amount / 5;
+        let initial_b = token_b_amount / 5;
+        let minimum_token_b_amount = initial_b / 2;
+
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+
         // swap not initialized
         {
             let (
@@ -3488,21 +9985,20 @@ mod tests {
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             assert_eq!(
                 Err(ProgramError::UninitializedAccount),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
@@ -3510,48 +10006,47 @@ mod tests {
 
         accounts.initialize_swap().unwrap();
 
-        // wrong owner for swap account
+        // wrong swap account program id
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             let old_swap_account = accounts.swap_account;
             let mut wrong_swap_account = old_swap_account.clone();
             wrong_swap_account.owner = TOKEN_PROGRAM_ID;
             accounts.swap_account = wrong_swap_account;
             assert_eq!(
                 Err(ProgramError::IncorrectProgramId),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
             accounts.swap_account = old_swap_account;
         }
 
-        // wrong nonce for authority_key
+        // wrong nonce
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             let old_authority = accounts.authority_key;
             let (bad_authority_key, _nonce) = Pubkey::find_program_address(
                 &[&accounts.swap_key.to_bytes()[..]],
@@ -3560,89 +10055,22 @@ mod tests {
             accounts.authority_key = bad_authority_key;
             assert_eq!(
                 Err(SwapError::InvalidProgramAddress.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
             accounts.authority_key = old_authority;
         }
 
-        // not enough pool tokens
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                to_u64(withdraw_amount).unwrap() / 2u64,
-            );
-            assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount / 2,
-                    minimum_token_b_amount / 2,
-                )
-            );
-        }
-
-        // wrong token a / b accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount.try_into().unwrap(),
-            );
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                )
-            );
-        }
-
-        // wrong pool token account
+        // wrong token program id
         {
             let (
                 token_a_key,
@@ -3651,191 +10079,102 @@ mod tests {
                 mut token_b_account,
                 _pool_key,
                 _pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount.try_into().unwrap(),
-            );
-            let (
-                wrong_token_a_key,
-                mut wrong_token_a_account,
-                _token_b_key,
-                _token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                withdraw_amount.try_into().unwrap(),
-                initial_b,
-                withdraw_amount.try_into().unwrap(),
-            );
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &wrong_token_a_key,
-                    &mut wrong_token_a_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                )
-            );
-        }
-
-        // wrong pool fee account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                wrong_pool_key,
-                wrong_pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount.try_into().unwrap(),
-            );
-            let (
-                _token_a_key,
-                _token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount.try_into().unwrap(),
-            );
-            let old_pool_fee_account = accounts.pool_fee_account;
-            let old_pool_fee_key = accounts.pool_fee_key;
-            accounts.pool_fee_account = wrong_pool_account;
-            accounts.pool_fee_key = wrong_pool_key;
-            assert_eq!(
-                Err(SwapError::IncorrectFeeAccount.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                ),
-            );
-            accounts.pool_fee_account = old_pool_fee_account;
-            accounts.pool_fee_key = old_pool_fee_key;
-        }
-
-        // no approval
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                0,
-                0,
-                withdraw_amount.try_into().unwrap(),
-            );
-            let user_transfer_authority_key = Pubkey::new_unique();
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let wrong_program_id = Pubkey::new_unique();
             assert_eq!(
-                Err(TokenError::OwnerMismatch.into()),
+                Err(SwapError::IncorrectTokenProgramId.into()),
                 do_process_instruction(
-                    withdraw_all_token_types(
+                    swap(
                         &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
+                        &wrong_program_id,
                         &accounts.swap_key,
                         &accounts.authority_key,
-                        &user_transfer_authority_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &pool_key,
+                        &accounts.authority_key,
+                        &token_a_key,
                         &accounts.token_a_key,
                         &accounts.token_b_key,
-                        &token_a_key,
                         &token_b_key,
-                        WithdrawAllTokenTypes {
-                            pool_token_amount: withdraw_amount.try_into().unwrap(),
-                            minimum_token_a_amount,
-                            minimum_token_b_amount,
-                        }
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        None,
+                        Swap {
+                            amount_in: initial_a,
+                            minimum_amount_out: minimum_token_b_amount,
+                        },
                     )
                     .unwrap(),
                     vec![
                         &mut accounts.swap_account,
                         &mut Account::default(),
                         &mut Account::default(),
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
+                        &mut token_a_account,
                         &mut accounts.token_a_account,
                         &mut accounts.token_b_account,
-                        &mut token_a_account,
                         &mut token_b_account,
+                        &mut accounts.pool_mint_account,
                         &mut accounts.pool_fee_account,
                         &mut Account::default(),
                     ],
-                )
+                ),
             );
         }
 
-        // wrong token program id
+        // not enough token a to swap
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                withdraw_amount.try_into().unwrap(),
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(TokenError::InsufficientFunds.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a * 2,
+                    minimum_token_b_amount * 2,
+                )
             );
-            let wrong_key = Pubkey::new_unique();
+        }
+
+        // wrong swap token A / B accounts
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let user_transfer_key = Pubkey::new_unique();
             assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
+                Err(SwapError::IncorrectSwapAccount.into()),
                 do_process_instruction(
-                    withdraw_all_token_types(
+                    swap(
                         &SWAP_PROGRAM_ID,
-                        &wrong_key,
+                        &TOKEN_PROGRAM_ID,
                         &accounts.swap_key,
                         &accounts.authority_key,
-                        &accounts.authority_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &pool_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
+                        &user_transfer_key,
+                        &token_a_key,
                         &token_a_key,
                         &token_b_key,
-                        WithdrawAllTokenTypes {
-                            pool_token_amount: withdraw_amount.try_into().unwrap(),
-                            minimum_token_a_amount,
-                            minimum_token_b_amount,
+                        &token_b_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        None,
+                        Swap {
+                            amount_in: initial_a,
+                            minimum_amount_out: minimum_token_b_amount,
                         },
                     )
                     .unwrap(),
@@ -3843,105 +10182,80 @@ mod tests {
                         &mut accounts.swap_account,
                         &mut Account::default(),
                         &mut Account::default(),
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
+                        &mut token_a_account.clone(),
                         &mut token_a_account,
+                        &mut token_b_account.clone(),
                         &mut token_b_account,
+                        &mut accounts.pool_mint_account,
                         &mut accounts.pool_fee_account,
                         &mut Account::default(),
                     ],
-                )
+                ),
             );
         }
 
-        // wrong swap token accounts
+        // wrong user token A / B accounts
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
-
-            let old_a_key = accounts.token_a_key;
-            let old_a_account = accounts.token_a_account;
-
-            accounts.token_a_key = token_a_key;
-            accounts.token_a_account = token_a_account.clone();
-
-            // wrong swap token a account
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
+                Err(TokenError::MintMismatch.into()),
+                accounts.swap(
+                    &swapper_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
+        }
 
-            accounts.token_a_key = old_a_key;
-            accounts.token_a_account = old_a_account;
-
-            let old_b_key = accounts.token_b_key;
-            let old_b_account = accounts.token_b_account;
-
-            accounts.token_b_key = token_b_key;
-            accounts.token_b_account = token_b_account.clone();
-
-            // wrong swap token b account
+        // swap from a to a
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                _token_b_key,
+                _token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                Err(SwapError::InvalidInput.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account.clone(),
+                    &swap_token_a_key,
+                    &swap_token_a_key,
                     &token_a_key,
                     &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
-
-            accounts.token_b_key = old_b_key;
-            accounts.token_b_account = old_b_account;
         }
 
-        // wrong mint
+        // incorrect mint provided
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             let (pool_mint_key, pool_mint_account) =
                 create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
             let old_pool_key = accounts.pool_mint_key;
@@ -3951,314 +10265,502 @@ mod tests {
 
             assert_eq!(
                 Err(SwapError::IncorrectPoolMint.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
+                    minimum_token_b_amount,
+                )
+            );
+
+            accounts.pool_mint_key = old_pool_key;
+            accounts.pool_mint_account = old_pool_account;
+        }
+
+        // incorrect fee account provided
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                wrong_pool_key,
+                wrong_pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let old_pool_fee_account = accounts.pool_fee_account;
+            let old_pool_fee_key = accounts.pool_fee_key;
+            accounts.pool_fee_account = wrong_pool_account;
+            accounts.pool_fee_key = wrong_pool_key;
+            assert_eq!(
+                Err(SwapError::IncorrectFeeAccount.into()),
+                accounts.swap(
+                    &swapper_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
-
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
+            accounts.pool_fee_account = old_pool_fee_account;
+            accounts.pool_fee_key = old_pool_fee_key;
+        }
+
+        // no approval
+        {
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            let user_transfer_key = Pubkey::new_unique();
+            assert_eq!(
+                Err(TokenError::OwnerMismatch.into()),
+                do_process_instruction(
+                    swap(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &user_transfer_key,
+                        &token_a_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_b_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        None,
+                        Swap {
+                            amount_in: initial_a,
+                            minimum_amount_out: minimum_token_b_amount,
+                        },
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut accounts.pool_fee_account,
+                        &mut Account::default(),
+                    ],
+                ),
+            );
         }
 
-        // withdrawing 1 pool token fails because it equates to 0 output tokens
+        // output token value 0
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             assert_eq!(
                 Err(SwapError::ZeroTradingTokens.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_b_key,
                     &mut token_b_account,
+                    &swap_token_b_key,
+                    &swap_token_a_key,
+                    &token_a_key,
+                    &mut token_a_account,
+                    1,
                     1,
-                    0,
-                    0,
                 )
             );
         }
 
-        // slippage exceeded
+        // slippage exceeded: minimum out amount too high
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
-            // minimum A amount out too high
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount * 10,
-                    minimum_token_b_amount,
-                )
-            );
-            // minimum B amount out too high
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             assert_eq!(
                 Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
-                    minimum_token_b_amount * 10,
+                    initial_a,
+                    minimum_token_b_amount * 2,
                 )
             );
         }
 
-        // invalid input: can't use swap pool tokens as destination
+        // invalid input: can't use swap pool as user source / dest
         {
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
-            let swap_token_a_key = accounts.token_a_key;
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
             let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
+            let authority_key = accounts.authority_key;
             assert_eq!(
                 Err(SwapError::InvalidInput.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &authority_key,
                     &swap_token_a_key,
                     &mut swap_token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &token_b_key,
                     &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
-            let swap_token_b_key = accounts.token_b_key;
             let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
             assert_eq!(
                 Err(SwapError::InvalidInput.into()),
-                accounts.withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+                accounts.swap(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
                     &swap_token_b_key,
                     &mut swap_token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
+                    initial_a,
                     minimum_token_b_amount,
                 )
             );
         }
 
-        // correct withdrawal
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
+        // still correct: constraint specified, no host fee account
+        {
+            let authority_key = accounts.authority_key;
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &authority_key, initial_a, initial_b, 0);
+            let owner_key = &swapper_key.to_string();
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let constraints = Some(SwapConstraints {
+                owner_key,
+                valid_curve_types: &[],
+                fees: &fees,
+            });
+            do_process_instruction_with_fee_constraints(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &accounts.authority_key,
+                    &accounts.authority_key,
+                    &token_a_key,
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
+                    &token_b_key,
+                    &accounts.pool_mint_key,
+                    &accounts.pool_fee_key,
+                    None,
+                    Swap {
+                        amount_in: initial_a,
+                        minimum_amount_out: minimum_token_b_amount,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut token_a_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+                &constraints,
+            )
+            .unwrap();
+        }
+
+        // invalid mint for host fee account
+        {
+            let authority_key = accounts.authority_key;
+            let (
+                token_a_key,
+                mut token_a_account,
+                token_b_key,
+                mut token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &authority_key, initial_a, initial_b, 0);
+            let (
+                bad_token_a_key,
+                mut bad_token_a_account,
+                _token_b_key,
+                mut _token_b_account,
+                _pool_key,
+                _pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &authority_key, initial_a, initial_b, 0);
+            let owner_key = &swapper_key.to_string();
+            let fees = Fees {
+                trade_fee_numerator,
+                trade_fee_denominator,
+                owner_trade_fee_numerator,
+                owner_trade_fee_denominator,
+                owner_withdraw_fee_numerator,
+                owner_withdraw_fee_denominator,
+                host_fee_numerator,
+                host_fee_denominator,
+            };
+            let constraints = Some(SwapConstraints {
+                owner_key,
+                valid_curve_types: &[],
+                fees: &fees,
+            });
+            assert_eq!(
+                Err(SwapError::IncorrectPoolMint.into()),
+                do_process_instruction_with_fee_constraints(
+                    swap(
+                        &SWAP_PROGRAM_ID,
+                        &TOKEN_PROGRAM_ID,
+                        &accounts.swap_key,
+                        &accounts.authority_key,
+                        &accounts.authority_key,
+                        &token_a_key,
+                        &accounts.token_a_key,
+                        &accounts.token_b_key,
+                        &token_b_key,
+                        &accounts.pool_mint_key,
+                        &accounts.pool_fee_key,
+                        Some(&bad_token_a_key),
+                        Swap {
+                            amount_in: initial_a,
+                            minimum_amount_out: 0,
+                        },
+                    )
+                    .unwrap(),
+                    vec![
+                        &mut accounts.swap_account,
+                        &mut Account::default(),
+                        &mut Account::default(),
+                        &mut token_a_account,
+                        &mut accounts.token_a_account,
+                        &mut accounts.token_b_account,
+                        &mut token_b_account,
+                        &mut accounts.pool_mint_account,
+                        &mut accounts.pool_fee_account,
+                        &mut Account::default(),
+                        &mut bad_token_a_account,
+                    ],
+                    &constraints,
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn test_overdraw_offset_curve() {
+        let trade_fee_numerator = 1;
+        let trade_fee_denominator = 10;
+        let owner_trade_fee_numerator = 1;
+        let owner_trade_fee_denominator = 30;
+        let owner_withdraw_fee_numerator = 1;
+        let owner_withdraw_fee_denominator = 30;
+        let host_fee_numerator = 10;
+        let host_fee_denominator = 100;
 
-            accounts
-                .withdraw_all_token_types(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    withdraw_amount.try_into().unwrap(),
-                    minimum_token_a_amount,
-                    minimum_token_b_amount,
-                )
-                .unwrap();
+        let token_a_amount = 1_000_000_000;
+        let token_b_amount = 0;
+        let fees = Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator,
+            owner_withdraw_fee_denominator,
+            host_fee_numerator,
+            host_fee_denominator,
+        };
 
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-            let swap_token_b =
-                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-            let pool_mint =
-                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-            let withdraw_fee = accounts.fees.owner_withdraw_fee(withdraw_amount).unwrap();
-            let results = accounts
-                .swap_curve
-                .calculator
-                .pool_tokens_to_trading_tokens(
-                    withdraw_amount - withdraw_fee,
-                    pool_mint.supply.try_into().unwrap(),
-                    swap_token_a.amount.try_into().unwrap(),
-                    swap_token_b.amount.try_into().unwrap(),
-                    RoundDirection::Floor,
-                )
-                .unwrap();
-            assert_eq!(
-                swap_token_a.amount,
-                token_a_amount - to_u64(results.token_a_amount).unwrap()
-            );
-            assert_eq!(
-                swap_token_b.amount,
-                token_b_amount - to_u64(results.token_b_amount).unwrap()
-            );
-            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-            assert_eq!(
-                token_a.amount,
-                initial_a + to_u64(results.token_a_amount).unwrap()
-            );
-            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
-            assert_eq!(
-                token_b.amount,
-                initial_b + to_u64(results.token_b_amount).unwrap()
-            );
-            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
-            assert_eq!(
-                pool_account.amount,
-                to_u64(initial_pool - withdraw_amount).unwrap()
-            );
-            let fee_account =
-                spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
-            assert_eq!(
-                fee_account.amount,
-                TryInto::<u64>::try_into(withdraw_fee).unwrap()
-            );
-        }
+        let token_b_offset = 2_000_000;
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::Offset,
+            calculator: Box::new(OffsetCurve { token_b_offset }),
+        };
+        let user_key = Pubkey::new_unique();
+        let swapper_key = Pubkey::new_unique();
 
-        // correct withdrawal from fee account
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+
+        accounts.initialize_swap().unwrap();
+
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let initial_a = 500_000;
+        let initial_b = 1_000;
+
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+
+        // swap a to b way, fails, there's no liquidity
+        let a_to_b_amount = initial_a;
+        let minimum_token_b_amount = 0;
+
+        assert_eq!(
+            Err(SwapError::ZeroTradingTokens.into()),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                a_to_b_amount,
+                minimum_token_b_amount,
+            )
+        );
+
+        // swap b to a, succeeds at offset price
+        let b_to_a_amount = initial_b;
+        let minimum_token_a_amount = 0;
+        accounts
+            .swap(
+                &swapper_key,
+                &token_b_key,
+                &mut token_b_account,
+                &swap_token_b_key,
+                &swap_token_a_key,
+                &token_a_key,
+                &mut token_a_account,
+                b_to_a_amount,
+                minimum_token_a_amount,
+            )
+            .unwrap();
+
+        // try a to b again, succeeds due to new liquidity
+        accounts
+            .swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                a_to_b_amount,
+                minimum_token_b_amount,
+            )
+            .unwrap();
+
+        // try a to b again, fails due to no more liquidity
+        assert_eq!(
+            Err(SwapError::ZeroTradingTokens.into()),
+            accounts.swap(
+                &swapper_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                a_to_b_amount,
+                minimum_token_b_amount,
+            )
+        );
+
+        // Try to deposit, fails because deposits are not allowed for offset
+        // curve swaps
         {
+            let initial_a = 100;
+            let initial_b = 100;
+            let pool_amount = 100;
             let (
                 token_a_key,
                 mut token_a_account,
                 token_b_key,
                 mut token_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, 0);
-
-            let pool_fee_key = accounts.pool_fee_key;
-            let mut pool_fee_account = accounts.pool_fee_account.clone();
-            let fee_account = spl_token::state::Account::unpack(&pool_fee_account.data).unwrap();
-            let pool_fee_amount = fee_account.amount;
-
-            accounts
-                .withdraw_all_token_types(
-                    &user_key,
-                    &pool_fee_key,
-                    &mut pool_fee_account,
+                pool_key,
+                mut pool_account,
+            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+            assert_eq!(
+                Err(SwapError::UnsupportedCurveOperation.into()),
+                accounts.deposit_all_token_types(
+                    &swapper_key,
                     &token_a_key,
                     &mut token_a_account,
                     &token_b_key,
                     &mut token_b_account,
-                    pool_fee_amount,
-                    0,
-                    0,
-                )
-                .unwrap();
-
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-            let swap_token_b =
-                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-            let pool_mint =
-                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-            let results = accounts
-                .swap_curve
-                .calculator
-                .pool_tokens_to_trading_tokens(
-                    pool_fee_amount.try_into().unwrap(),
-                    pool_mint.supply.try_into().unwrap(),
-                    swap_token_a.amount.try_into().unwrap(),
-                    swap_token_b.amount.try_into().unwrap(),
-                    RoundDirection::Floor,
+                    &pool_key,
+                    &mut pool_account,
+                    pool_amount,
+                    initial_a,
+                    initial_b,
                 )
-                .unwrap();
-            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-            assert_eq!(
-                token_a.amount,
-                TryInto::<u64>::try_into(results.token_a_amount).unwrap()
-            );
-            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
-            assert_eq!(
-                token_b.amount,
-                TryInto::<u64>::try_into(results.token_b_amount).unwrap()
             );
         }
     }
 
     #[test]
-    fn test_deposit_one_exact_in() {
-        let user_key = Pubkey::new_unique();
-        let depositor_key = Pubkey::new_unique();
+    fn test_withdraw_all_offset_curve() {
         let trade_fee_numerator = 1;
-        let trade_fee_denominator = 2;
+        let trade_fee_denominator = 10;
         let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 10;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 5;
-        let host_fee_numerator = 20;
+        let owner_trade_fee_denominator = 30;
+        let owner_withdraw_fee_numerator = 0;
+        let owner_withdraw_fee_denominator = 30;
+        let host_fee_numerator = 10;
         let host_fee_denominator = 100;
 
+        let token_a_amount = 1_000_000_000;
+        let token_b_amount = 10;
         let fees = Fees {
             trade_fee_numerator,
             trade_fee_denominator,
@@ -4270,2464 +10772,5094 @@ mod tests {
             host_fee_denominator,
         };
 
-        let token_a_amount = 1000;
-        let token_b_amount = 9000;
-        let curve_type = CurveType::ConstantProduct;
+        let token_b_offset = 2_000_000;
         let swap_curve = SwapCurve {
-            curve_type,
-            calculator: Box::new(ConstantProductCurve {}),
+            curve_type: CurveType::Offset,
+            calculator: Box::new(OffsetCurve { token_b_offset }),
         };
+        let total_pool = swap_curve.calculator.new_pool_supply();
+        let user_key = Pubkey::new_unique();
+        let withdrawer_key = Pubkey::new_unique();
 
         let mut accounts =
             SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
 
-        let deposit_a = token_a_amount / 10;
-        let deposit_b = token_b_amount / 10;
-        let pool_amount = to_u64(INITIAL_SWAP_POOL_AMOUNT / 100).unwrap();
+        accounts.initialize_swap().unwrap();
 
-        // swap not initialized
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
-                )
-            );
-        }
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, 0);
+
+        let pool_key = accounts.pool_token_key;
+        let mut pool_account = accounts.pool_token_account.clone();
+
+        // WithdrawAllTokenTypes takes all tokens for A and B.
+        // The curve's calculation for token B will say to transfer
+        // `token_b_offset + token_b_amount`, but only `token_b_amount` will be
+        // moved.
+        accounts
+            .withdraw_all_token_types(
+                &user_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                total_pool.try_into().unwrap(),
+                0,
+                0,
+            )
+            .unwrap();
+
+        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, token_a_amount);
+        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount, token_b_amount);
+        let swap_token_a =
+            spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, 0);
+        let swap_token_b =
+            spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+        assert_eq!(swap_token_b.amount, 0);
+    }
+
+    #[test]
+    fn test_price_accumulator() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 0,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 2_000_000;
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+
+        set_test_slot(100);
+        accounts.initialize_swap().unwrap();
+        let initial = SwapVersion::unpack_price_accumulator(&accounts.swap_account.data)
+            .unwrap()
+            .unwrap();
+        assert_eq!(initial, PriceAccumulator::new(100));
+
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 100_000, 0);
+        let swap_reserves = |accounts: &SwapAccountInfo| {
+            let token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            let token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            (token_a.amount as u128, token_b.amount as u128)
+        };
+
+        // the first swap of the slot accumulates the initial price for 10 slots
+        set_test_slot(110);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                50_000,
+                0,
+            )
+            .unwrap();
+        let after_swap = SwapVersion::unpack_price_accumulator(&accounts.swap_account.data)
+            .unwrap()
+            .unwrap();
+        assert_eq!(after_swap.last_update_slot, 110);
+        assert_eq!(after_swap.price_a_cumulative, 10 * 2 * (1 << 64));
+        assert_eq!(after_swap.price_b_cumulative, 10 * (1 << 63));
+
+        // later actions in the same slot do not change the accumulator
+        accounts
+            .swap(
+                &user_key,
+                &token_b_key,
+                &mut token_b_account,
+                &swap_token_b_key,
+                &swap_token_a_key,
+                &token_a_key,
+                &mut token_a_account,
+                10_000,
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            SwapVersion::unpack_price_accumulator(&accounts.swap_account.data),
+            Ok(Some(after_swap))
+        );
+
+        // a withdrawal 30 slots later accumulates the price after the swaps
+        set_test_slot(140);
+        let (token_a_reserve, token_b_reserve) = swap_reserves(&accounts);
+        let pool_key = accounts.pool_token_key;
+        let mut pool_account = accounts.pool_token_account.clone();
+        accounts
+            .withdraw_all_token_types(
+                &user_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                10_000_000,
+                0,
+                0,
+            )
+            .unwrap();
+        let after_withdraw = SwapVersion::unpack_price_accumulator(&accounts.swap_account.data)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            after_withdraw,
+            after_swap.observe(140, token_a_reserve, token_b_reserve)
+        );
+
+        let price_a = spot_price(token_a_reserve, token_b_reserve).unwrap();
+        let average = twap(&initial, &after_withdraw).unwrap();
+        assert_eq!(average.price_a, (10 * 2 * (1 << 64) + 30 * price_a) / 40);
+        assert_eq!(twap(&after_swap, &after_withdraw).unwrap().price_a, price_a);
+        assert_eq!(twap(&after_withdraw, &after_swap), None);
+
+        // swaps created before the extension was added work without an
+        // accumulator
+        let fees = accounts.fees.clone();
+        let swap_curve = accounts.swap_curve.clone();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+        accounts.initialize_legacy_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 100_000, 0);
+        set_test_slot(150);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                50_000,
+                0,
+            )
+            .unwrap();
+        assert_eq!(accounts.swap_account.data.len(), SwapVersion::LEGACY_LEN);
+        assert_eq!(
+            SwapVersion::unpack_price_accumulator(&accounts.swap_account.data),
+            Ok(None)
+        );
+    }
 
+    #[test]
+    fn test_swap_exact_out() {
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 5_000_000;
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
         accounts.initialize_swap().unwrap();
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 1_000_000, 1_000_000, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let amount_out = 50_000;
 
-        // wrong owner for swap account
-        {
+        for trade_direction in [TradeDirection::AtoB, TradeDirection::BtoA].iter() {
             let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let old_swap_account = accounts.swap_account;
-            let mut wrong_swap_account = old_swap_account.clone();
-            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
-            accounts.swap_account = wrong_swap_account;
-            assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
+                source_key,
+                source_account,
+                swap_source_key,
+                swap_destination_key,
+                destination_key,
+                destination_account,
+            ) = match trade_direction {
+                TradeDirection::AtoB => (
+                    token_a_key,
                     &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
-                )
-            );
-            accounts.swap_account = old_swap_account;
-        }
-
-        // wrong nonce for authority_key
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
-                &TOKEN_PROGRAM_ID,
-            );
-            accounts.authority_key = bad_authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
+                    swap_token_a_key,
+                    swap_token_b_key,
+                    token_b_key,
+                    &mut token_b_account,
+                ),
+                TradeDirection::BtoA => (
+                    token_b_key,
+                    &mut token_b_account,
+                    swap_token_b_key,
+                    swap_token_a_key,
+                    token_a_key,
                     &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
+                ),
+            };
+            // the reserves left by the swap in the other direction
+            let swap_source_amount = spl_token::state::Account::unpack(
+                &accounts.get_token_account(&swap_source_key).data,
+            )
+            .unwrap()
+            .amount as u128;
+            let swap_destination_amount = spl_token::state::Account::unpack(
+                &accounts.get_token_account(&swap_destination_key).data,
+            )
+            .unwrap()
+            .amount as u128;
+            let expected = accounts
+                .swap_curve
+                .swap_exact_out(
+                    amount_out as u128,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    *trade_direction,
+                    &accounts.fees,
                 )
-            );
-            accounts.authority_key = old_authority;
-        }
+                .unwrap();
+            let amount_in = expected.source_amount_swapped as u64;
 
-        // not enough token A / B
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &depositor_key,
-                deposit_a / 2,
-                deposit_b / 2,
-                0,
-            );
+            // paying one token less than needed fails
             assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    0,
+                Err(SwapError::ExceededSlippage.into()),
+                accounts.swap_exact_out(
+                    &user_key,
+                    &source_key,
+                    source_account,
+                    &swap_source_key,
+                    &swap_destination_key,
+                    &destination_key,
+                    destination_account,
+                    amount_out,
+                    amount_in - 1,
                 )
             );
+
+            // the whole swap balance can never be bought
             assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_b,
-                    0,
+                Err(SwapError::ZeroTradingTokens.into()),
+                accounts.swap_exact_out(
+                    &user_key,
+                    &source_key,
+                    source_account,
+                    &swap_source_key,
+                    &swap_destination_key,
+                    &destination_key,
+                    destination_account,
+                    swap_destination_amount as u64,
+                    u64::MAX,
                 )
             );
-        }
 
-        // wrong pool token account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                mut _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    deposit_a,
-                    pool_amount,
+            let initial_source = spl_token::state::Account::unpack(&source_account.data)
+                .unwrap()
+                .amount;
+            let initial_destination = spl_token::state::Account::unpack(&destination_account.data)
+                .unwrap()
+                .amount;
+            let initial_fee = spl_token::state::Account::unpack(&accounts.pool_fee_account.data)
+                .unwrap()
+                .amount;
+            accounts
+                .swap_exact_out(
+                    &user_key,
+                    &source_key,
+                    source_account,
+                    &swap_source_key,
+                    &swap_destination_key,
+                    &destination_key,
+                    destination_account,
+                    amount_out,
+                    amount_in,
                 )
-            );
-        }
+                .unwrap();
 
-        // no approval
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let user_transfer_authority_key = Pubkey::new_unique();
+            let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+            assert_eq!(source.amount, initial_source - amount_in);
+            let destination = spl_token::state::Account::unpack(&destination_account.data).unwrap();
+            assert_eq!(destination.amount, initial_destination + amount_out);
+            let swap_source = spl_token::state::Account::unpack(
+                &accounts.get_token_account(&swap_source_key).data,
+            )
+            .unwrap();
+            assert_eq!(swap_source.amount as u128, expected.new_swap_source_amount);
+            let swap_destination = spl_token::state::Account::unpack(
+                &accounts.get_token_account(&swap_destination_key).data,
+            )
+            .unwrap();
             assert_eq!(
-                Err(TokenError::OwnerMismatch.into()),
-                do_process_instruction(
-                    deposit_single_token_type_exact_amount_in(
-                        &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &user_transfer_authority_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        DepositSingleTokenTypeExactAmountIn {
-                            source_token_amount: deposit_a,
-                            minimum_pool_token_amount: pool_amount,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                    ],
-                )
+                swap_destination.amount as u128,
+                expected.new_swap_destination_amount
             );
-        }
 
-        // wrong token program id
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let wrong_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                do_process_instruction(
-                    deposit_single_token_type_exact_amount_in(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_key,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &accounts.authority_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &accounts.pool_mint_key,
-                        &pool_key,
-                        DepositSingleTokenTypeExactAmountIn {
-                            source_token_amount: deposit_a,
-                            minimum_pool_token_amount: pool_amount,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut Account::default(),
-                    ],
+            // the owner fee is collected as with a regular swap
+            let fee = spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+            assert!(fee.amount > initial_fee);
+
+            // a regular swap of the same amount gives at least as much
+            let forward = accounts
+                .swap_curve
+                .swap(
+                    expected.source_amount_swapped,
+                    swap_source_amount,
+                    swap_destination_amount,
+                    *trade_direction,
+                    &accounts.fees,
                 )
-            );
+                .unwrap();
+            assert!(forward.destination_amount_swapped >= amount_out as u128);
         }
+    }
 
-        // wrong swap token accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
+    #[test]
+    fn test_route_swap() {
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
 
-            let old_a_key = accounts.token_a_key;
-            let old_a_account = accounts.token_a_account;
+        // route A -> B -> C, where token B of the first pool is token A of
+        // the second one
+        let mut first = SwapAccountInfo::new(
+            &user_key,
+            fees.clone(),
+            swap_curve.clone(),
+            1_000_000,
+            2_000_000,
+        );
+        first.initialize_swap().unwrap();
+        let mut second = SwapAccountInfo::new(&user_key, fees, swap_curve, 3_000_000, 1_000_000);
+        let (token_b_key, token_b_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &first.token_b_mint_key,
+            &mut first.token_b_mint_account,
+            &user_key,
+            &second.authority_key,
+            3_000_000,
+        );
+        second.token_a_key = token_b_key;
+        second.token_a_account = token_b_account;
+        second.token_a_mint_key = first.token_b_mint_key;
+        second.token_a_mint_account = first.token_b_mint_account.clone();
+        second.initialize_swap().unwrap();
+
+        let (source_key, mut source_account, _, _, _, _) =
+            first.setup_token_accounts(&user_key, &user_key, 100_000, 0, 0);
+        let (_, _, destination_key, mut destination_account, _, _) =
+            second.setup_token_accounts(&user_key, &user_key, 0, 0, 0);
+
+        // the second pool swaps the whole amount out of the first one
+        let amount_in = 50_002;
+        let first_result = first
+            .swap_curve
+            .swap(
+                amount_in as u128,
+                1_000_000,
+                2_000_000,
+                TradeDirection::AtoB,
+                &first.fees,
+            )
+            .unwrap();
+        let second_result = second
+            .swap_curve
+            .swap(
+                first_result.destination_amount_swapped,
+                3_000_000,
+                1_000_000,
+                TradeDirection::AtoB,
+                &second.fees,
+            )
+            .unwrap();
+        assert_eq!(
+            second_result.source_amount_swapped,
+            first_result.destination_amount_swapped
+        );
+        let amount_out = second_result.destination_amount_swapped as u64;
+
+        let hops = [
+            RouteHop {
+                swap_pubkey: first.swap_key,
+                authority_pubkey: first.authority_key,
+                swap_source_pubkey: first.token_a_key,
+                swap_destination_pubkey: first.token_b_key,
+                pool_mint_pubkey: first.pool_mint_key,
+                pool_fee_pubkey: first.pool_fee_key,
+            },
+            RouteHop {
+                swap_pubkey: second.swap_key,
+                authority_pubkey: second.authority_key,
+                swap_source_pubkey: second.token_a_key,
+                swap_destination_pubkey: second.token_b_key,
+                pool_mint_pubkey: second.pool_mint_key,
+                pool_fee_pubkey: second.pool_fee_key,
+            },
+        ];
+        let user_transfer_key = Pubkey::new_unique();
+        do_process_instruction(
+            approve(
+                &TOKEN_PROGRAM_ID,
+                &source_key,
+                &user_transfer_key,
+                &user_key,
+                &[],
+                amount_in,
+            )
+            .unwrap(),
+            vec![
+                &mut source_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-            accounts.token_a_key = token_a_key;
-            accounts.token_a_account = token_a_account.clone();
+        // the slippage check applies to the end of the route
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction(
+                route_swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &user_transfer_key,
+                    &source_key,
+                    &destination_key,
+                    &hops,
+                    Swap {
+                        amount_in,
+                        minimum_amount_out: amount_out + 1,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut destination_account,
+                    &mut Account::default(),
+                    &mut first.swap_account,
+                    &mut Account::default(),
+                    &mut first.token_a_account,
+                    &mut first.token_b_account,
+                    &mut first.pool_mint_account,
+                    &mut first.pool_fee_account,
+                    &mut second.swap_account,
+                    &mut Account::default(),
+                    &mut second.token_a_account,
+                    &mut second.token_b_account,
+                    &mut second.pool_mint_account,
+                    &mut second.pool_fee_account,
+                ],
+            )
+        );
 
-            // wrong swap token a account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
+        // a route leaving part of the intermediate tokens unswapped fails
+        // rather than strand them in the first pool
+        let stranding_amount_in = 50_000;
+        let first_stranding_result = first
+            .swap_curve
+            .swap(
+                stranding_amount_in as u128,
+                1_000_000,
+                2_000_000,
+                TradeDirection::AtoB,
+                &first.fees,
+            )
+            .unwrap();
+        let second_stranding_result = second
+            .swap_curve
+            .swap(
+                first_stranding_result.destination_amount_swapped,
+                3_000_000,
+                1_000_000,
+                TradeDirection::AtoB,
+                &second.fees,
+            )
+            .unwrap();
+        assert!(
+            second_stranding_result.source_amount_swapped
+                < first_stranding_result.destination_amount_swapped
+        );
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction(
+                route_swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &user_transfer_key,
+                    &source_key,
+                    &destination_key,
+                    &hops,
+                    Swap {
+                        amount_in: stranding_amount_in,
+                        minimum_amount_out: 0,
+                    },
                 )
-            );
+                .unwrap(),
+                vec![
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut destination_account,
+                    &mut Account::default(),
+                    &mut first.swap_account,
+                    &mut Account::default(),
+                    &mut first.token_a_account,
+                    &mut first.token_b_account,
+                    &mut first.pool_mint_account,
+                    &mut first.pool_fee_account,
+                    &mut second.swap_account,
+                    &mut Account::default(),
+                    &mut second.token_a_account,
+                    &mut second.token_b_account,
+                    &mut second.pool_mint_account,
+                    &mut second.pool_fee_account,
+                ],
+            )
+        );
 
-            accounts.token_a_key = old_a_key;
-            accounts.token_a_account = old_a_account;
+        // going through the same pool twice in a row is not a route
+        let mut first_swap_account = first.swap_account.clone();
+        let mut first_token_a_account = first.token_a_account.clone();
+        let mut first_token_b_account = first.token_b_account.clone();
+        let mut first_pool_mint_account = first.pool_mint_account.clone();
+        let mut first_pool_fee_account = first.pool_fee_account.clone();
+        let reverse = RouteHop {
+            swap_source_pubkey: first.token_b_key,
+            swap_destination_pubkey: first.token_a_key,
+            ..hops[0].clone()
+        };
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction(
+                route_swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &user_transfer_key,
+                    &source_key,
+                    &destination_key,
+                    &[hops[0].clone(), reverse],
+                    Swap {
+                        amount_in,
+                        minimum_amount_out: 0,
+                    },
+                )
+                .unwrap(),
+                vec![
+                    &mut Account::default(),
+                    &mut source_account,
+                    &mut destination_account,
+                    &mut Account::default(),
+                    &mut first.swap_account,
+                    &mut Account::default(),
+                    &mut first.token_a_account,
+                    &mut first.token_b_account,
+                    &mut first.pool_mint_account,
+                    &mut first.pool_fee_account,
+                    &mut first_swap_account,
+                    &mut Account::default(),
+                    &mut first_token_b_account,
+                    &mut first_token_a_account,
+                    &mut first_pool_mint_account,
+                    &mut first_pool_fee_account,
+                ],
+            )
+        );
 
-            let old_b_key = accounts.token_b_key;
-            let old_b_account = accounts.token_b_account;
+        do_process_instruction(
+            route_swap(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &user_transfer_key,
+                &source_key,
+                &destination_key,
+                &hops,
+                Swap {
+                    amount_in,
+                    minimum_amount_out: amount_out,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut Account::default(),
+                &mut source_account,
+                &mut destination_account,
+                &mut Account::default(),
+                &mut first.swap_account,
+                &mut Account::default(),
+                &mut first.token_a_account,
+                &mut first.token_b_account,
+                &mut first.pool_mint_account,
+                &mut first.pool_fee_account,
+                &mut second.swap_account,
+                &mut Account::default(),
+                &mut second.token_a_account,
+                &mut second.token_b_account,
+                &mut second.pool_mint_account,
+                &mut second.pool_fee_account,
+            ],
+        )
+        .unwrap();
 
-            accounts.token_b_key = token_b_key;
-            accounts.token_b_account = token_b_account;
+        let source = spl_token::state::Account::unpack(&source_account.data).unwrap();
+        assert_eq!(source.amount, 100_000 - amount_in);
+        let destination = spl_token::state::Account::unpack(&destination_account.data).unwrap();
+        assert_eq!(destination.amount, amount_out);
 
-            // wrong swap token b account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
-                )
-            );
+        // the intermediate tokens all moved straight from one pool to the
+        // other
+        let first_token_b = spl_token::state::Account::unpack(&first.token_b_account.data).unwrap();
+        assert_eq!(
+            first_token_b.amount as u128,
+            first_result.new_swap_destination_amount
+        );
+        let second_token_a =
+            spl_token::state::Account::unpack(&second.token_a_account.data).unwrap();
+        assert_eq!(
+            second_token_a.amount as u128,
+            second_result.new_swap_source_amount
+        );
+        let second_token_b =
+            spl_token::state::Account::unpack(&second.token_b_account.data).unwrap();
+        assert_eq!(
+            second_token_b.amount as u128,
+            second_result.new_swap_destination_amount
+        );
 
-            accounts.token_b_key = old_b_key;
-            accounts.token_b_account = old_b_account;
-        }
+        // both pools collected their owner fees
+        let first_fee = spl_token::state::Account::unpack(&first.pool_fee_account.data).unwrap();
+        assert!(first_fee.amount > 0);
+        let second_fee = spl_token::state::Account::unpack(&second.pool_fee_account.data).unwrap();
+        assert!(second_fee.amount > 0);
+    }
 
-        // wrong mint
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let (pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
+    #[test]
+    fn test_admin_instructions() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 1_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 2_000_000;
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
 
-            assert_eq!(
-                Err(SwapError::IncorrectPoolMint.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
-                )
-            );
+        // the admin must sign to take control of the swap
+        let mut instruction = initialize(
+            &SWAP_PROGRAM_ID,
+            &TOKEN_PROGRAM_ID,
+            &accounts.swap_key,
+            &accounts.authority_key,
+            &accounts.token_a_key,
+            &accounts.token_b_key,
+            &accounts.pool_mint_key,
+            &accounts.pool_fee_key,
+            &accounts.pool_token_key,
+            Some(&accounts.admin_key),
+            accounts.nonce,
+            accounts.fees.clone(),
+            accounts.swap_curve.clone(),
+        )
+        .unwrap();
+        instruction.accounts[8].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.pool_fee_account,
+                    &mut accounts.pool_token_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
-        }
+        accounts.initialize_swap().unwrap();
+        let admin_key = accounts.admin_key;
+        assert_eq!(
+            SwapVersion::unpack_admin(&accounts.swap_account.data),
+            Ok(Some(SwapAdmin {
+                admin_authority: admin_key
+            }))
+        );
 
-        // slippage exceeded
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            // minimum pool amount too high
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a / 10,
-                    pool_amount,
-                )
-            );
-            // minimum pool amount too high
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_b / 10,
-                    pool_amount,
+        let new_fees = Fees {
+            trade_fee_numerator: 3,
+            trade_fee_denominator: 1_000,
+            owner_trade_fee_numerator: 2,
+            owner_trade_fee_denominator: 1_000,
+            owner_withdraw_fee_numerator: 1,
+            owner_withdraw_fee_denominator: 1_000,
+            host_fee_numerator: 20,
+            host_fee_denominator: 100,
+        };
+
+        // only the admin can update the fees
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                update_fees(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &wrong_key,
+                    new_fees.clone()
                 )
-            );
-        }
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        let mut instruction = update_fees(
+            &SWAP_PROGRAM_ID,
+            &accounts.swap_key,
+            &admin_key,
+            new_fees.clone(),
+        )
+        .unwrap();
+        instruction.accounts[1].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(
+                instruction,
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
 
-        // invalid input: can't use swap pool tokens as source
-        {
-            let (
-                _token_a_key,
-                _token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            let swap_token_a_key = accounts.token_a_key;
-            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
-            let swap_token_b_key = accounts.token_b_key;
-            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
-            let authority_key = accounts.authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &authority_key,
-                    &swap_token_a_key,
-                    &mut swap_token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
+        // the fees are still validated
+        let mut invalid_fees = new_fees.clone();
+        invalid_fees.trade_fee_numerator = invalid_fees.trade_fee_denominator;
+        assert_eq!(
+            Err(SwapError::InvalidFee.into()),
+            do_process_instruction(
+                update_fees(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    invalid_fees
                 )
-            );
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.deposit_single_token_type_exact_amount_in(
-                    &authority_key,
-                    &swap_token_b_key,
-                    &mut swap_token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_b,
-                    pool_amount,
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+
+        // and must match the constraints when there are some
+        let owner_key = &user_key.to_string();
+        let valid_curve_types = &[CurveType::ConstantProduct];
+        let constraints = Some(SwapConstraints {
+            owner_key,
+            valid_curve_types,
+            fees: &accounts.fees,
+        });
+        assert_eq!(
+            Err(SwapError::InvalidFee.into()),
+            do_process_instruction_with_fee_constraints(
+                update_fees(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    new_fees.clone()
                 )
-            );
-        }
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+                &constraints,
+            )
+        );
 
-        // correctly deposit
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &depositor_key, deposit_a, deposit_b, 0);
-            accounts
-                .deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_a_key,
+        do_process_instruction(
+            update_fees(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                new_fees.clone(),
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        let token_swap = SwapVersion::unpack_swap_v1(&accounts.swap_account.data).unwrap();
+        assert_eq!(token_swap.fees, new_fees);
+
+        // swaps use the new fees
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                10_000,
+                0,
+            )
+            .unwrap();
+        let expected = accounts
+            .swap_curve
+            .swap(
+                10_000,
+                token_a_amount as u128,
+                token_b_amount as u128,
+                TradeDirection::AtoB,
+                &new_fees,
+            )
+            .unwrap();
+        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount as u128, expected.destination_amount_swapped);
+
+        // the new fee account must hold pool tokens
+        let (_, _, _, _, new_fee_key, mut new_fee_account) =
+            accounts.setup_token_accounts(&user_key, &user_key, 0, 0, 0);
+        assert_eq!(
+            Err(SwapError::IncorrectPoolMint.into()),
+            do_process_instruction(
+                set_fee_account(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    &token_a_key
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
                     &mut token_a_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_a,
-                    pool_amount,
+                ],
+            )
+        );
+        // and must not be owned by the swap authority
+        let authority_key = accounts.authority_key;
+        let (_, _, _, _, authority_fee_key, mut authority_fee_account) =
+            accounts.setup_token_accounts(&user_key, &authority_key, 0, 0, 0);
+        assert_eq!(
+            Err(SwapError::InvalidOutputOwner.into()),
+            do_process_instruction(
+                set_fee_account(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    &authority_fee_key
                 )
-                .unwrap();
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut authority_fee_account,
+                ],
+            )
+        );
+        // and must be owned by the program owner when there are constraints
+        let other_owner_key = &Pubkey::new_unique().to_string();
+        let constraints = Some(SwapConstraints {
+            owner_key: other_owner_key,
+            valid_curve_types,
+            fees: &new_fees,
+        });
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            do_process_instruction_with_fee_constraints(
+                set_fee_account(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    &new_fee_key
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_fee_account,
+                ],
+                &constraints,
+            )
+        );
 
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-            assert_eq!(swap_token_a.amount, deposit_a + token_a_amount);
+        do_process_instruction(
+            set_fee_account(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                &new_fee_key,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut new_fee_account,
+            ],
+        )
+        .unwrap();
+        let token_swap = SwapVersion::unpack_swap_v1(&accounts.swap_account.data).unwrap();
+        assert_eq!(token_swap.pool_fee_account, new_fee_key);
 
-            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, 0);
+        // swaps now need the new fee account
+        assert_eq!(
+            Err(SwapError::IncorrectFeeAccount.into()),
+            accounts.swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                10_000,
+                0,
+            )
+        );
+        accounts.pool_fee_key = new_fee_key;
+        accounts.pool_fee_account = new_fee_account;
+        accounts
+            .swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                10_000,
+                0,
+            )
+            .unwrap();
+        let fee_account =
+            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        assert!(fee_account.amount > 0);
 
-            accounts
-                .deposit_single_token_type_exact_amount_in(
-                    &depositor_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    deposit_b,
-                    pool_amount,
+        // transfer the admin, the previous one can no longer update the swap
+        let new_admin_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_admin_authority(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &new_admin_key,
+                    &new_admin_key
                 )
-                .unwrap();
-            let swap_token_b =
-                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-            assert_eq!(swap_token_b.amount, deposit_b + token_b_amount);
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        do_process_instruction(
+            set_admin_authority(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                &new_admin_key,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                update_fees(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    new_fees.clone()
+                )
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        do_process_instruction(
+            update_fees(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &new_admin_key,
+                accounts.fees.clone(),
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
 
-            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
-            assert_eq!(token_b.amount, 0);
+        // giving the admin to the default pubkey leaves the swap without one
+        do_process_instruction(
+            set_admin_authority(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &new_admin_key,
+                &Pubkey::default(),
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                update_fees(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &Pubkey::default(),
+                    new_fees,
+                )
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
 
-            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
-            let swap_pool_account =
-                spl_token::state::Account::unpack(&accounts.pool_token_account.data).unwrap();
-            let pool_mint =
-                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-            assert_eq!(
-                pool_mint.supply,
-                pool_account.amount + swap_pool_account.amount
-            );
-        }
+        // swaps created before the extension was added have no admin, and
+        // have no room to be given one
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            accounts.fees.clone(),
+            accounts.swap_curve.clone(),
+            token_a_amount,
+            token_b_amount,
+        );
+        accounts.swap_account = Account::new(0, SwapVersion::LEGACY_LEN, &SWAP_PROGRAM_ID);
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            accounts.initialize_swap()
+        );
+        accounts.initialize_legacy_swap().unwrap();
+        assert_eq!(
+            SwapVersion::unpack_admin(&accounts.swap_account.data),
+            Ok(None)
+        );
+        let admin_key = accounts.admin_key;
+        let fees = accounts.fees.clone();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                update_fees(&SWAP_PROGRAM_ID, &accounts.swap_key, &admin_key, fees).unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
     }
 
     #[test]
-    fn test_withdraw_one_exact_out() {
-        let user_key = Pubkey::new_unique();
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 2;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 10;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 5;
-        let host_fee_numerator = 7;
-        let host_fee_denominator = 100;
-
+    fn test_ramp_a() {
         let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
+            trade_fee_numerator: 0,
+            trade_fee_denominator: 1,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 1,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 1,
+            host_fee_numerator: 0,
+            host_fee_denominator: 1,
         };
-
-        let token_a_amount = 100_000;
-        let token_b_amount = 200_000;
-        let curve_type = CurveType::ConstantProduct;
         let swap_curve = SwapCurve {
-            curve_type,
-            calculator: Box::new(ConstantProductCurve {}),
+            curve_type: CurveType::Stable,
+            calculator: Box::new(StableCurve::new(100)),
         };
-
-        let withdrawer_key = Pubkey::new_unique();
-        let initial_a = token_a_amount / 10;
-        let initial_b = token_b_amount / 10;
-        let initial_pool = swap_curve.calculator.new_pool_supply() / 10;
-        let maximum_pool_token_amount = to_u64(initial_pool / 4).unwrap();
-        let destination_a_amount = initial_a / 40;
-        let destination_b_amount = initial_b / 40;
-
+        let user_key = Pubkey::new_unique();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 2_000_000;
         let mut accounts =
             SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-
-        // swap not initialized
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
-                )
-            );
-        }
-
         accounts.initialize_swap().unwrap();
+        let admin_key = accounts.admin_key;
+        let start = 1_000;
+        let stop = start + 2 * MIN_RAMP_DURATION;
+        set_test_unix_timestamp(start);
 
-        // wrong owner for swap account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
-            let old_swap_account = accounts.swap_account;
-            let mut wrong_swap_account = old_swap_account.clone();
-            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
-            accounts.swap_account = wrong_swap_account;
-            assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
-                )
-            );
-            accounts.swap_account = old_swap_account;
-        }
+        // only the admin can ramp
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                ramp_a(&SWAP_PROGRAM_ID, &accounts.swap_key, &wrong_key, 200, stop).unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
 
-        // wrong nonce for authority_key
-        {
-            let (
-                _token_a_key,
-                _token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
-                &TOKEN_PROGRAM_ID,
-            );
-            accounts.authority_key = bad_authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    destination_b_amount,
-                    maximum_pool_token_amount,
+        // the ramp must not be too short
+        assert_eq!(
+            Err(SwapError::InvalidRamp.into()),
+            do_process_instruction(
+                ramp_a(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    200,
+                    start + MIN_RAMP_DURATION - 1
                 )
-            );
-            accounts.authority_key = old_authority;
-        }
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
 
-        // not enough pool tokens
-        {
-            let (
-                _token_a_key,
-                _token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+        do_process_instruction(
+            ramp_a(&SWAP_PROGRAM_ID, &accounts.swap_key, &admin_key, 200, stop).unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        let token_swap = SwapVersion::unpack_swap_v1(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            token_swap.swap_curve,
+            SwapCurve {
+                curve_type: CurveType::Stable,
+                calculator: Box::new(StableCurve {
+                    amp: 100,
+                    target_amp: 200,
+                    ramp_start_ts: start,
+                    ramp_stop_ts: stop,
+                }),
+            }
+        );
+
+        // halfway through the ramp, swaps use the amplifier in between
+        set_test_unix_timestamp(start + MIN_RAMP_DURATION);
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            _pool_key,
+            _pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        accounts
+            .swap(
                 &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                maximum_pool_token_amount / 1000,
-            );
-            assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    destination_b_amount,
-                    maximum_pool_token_amount,
-                )
-            );
-        }
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                100_000,
+                0,
+            )
+            .unwrap();
+        let expected = StableCurve::new(150)
+            .swap_without_fees(
+                100_000,
+                token_a_amount as u128,
+                token_b_amount as u128,
+                TradeDirection::AtoB,
+            )
+            .unwrap();
+        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        assert_eq!(token_b.amount as u128, expected.destination_amount_swapped);
+
+        // stopping keeps the current amplifier
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                stop_ramp_a(&SWAP_PROGRAM_ID, &accounts.swap_key, &wrong_key).unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        do_process_instruction(
+            stop_ramp_a(&SWAP_PROGRAM_ID, &accounts.swap_key, &admin_key).unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        let token_swap = SwapVersion::unpack_swap_v1(&accounts.swap_account.data).unwrap();
+        assert_eq!(
+            token_swap.swap_curve,
+            SwapCurve {
+                curve_type: CurveType::Stable,
+                calculator: Box::new(StableCurve {
+                    amp: 150,
+                    target_amp: 150,
+                    ramp_start_ts: start,
+                    ramp_stop_ts: start + MIN_RAMP_DURATION,
+                }),
+            }
+        );
+
+        // the next ramp can only start a day after the last one
+        set_test_unix_timestamp(start + MIN_RAMP_DURATION - 1);
+        assert_eq!(
+            Err(SwapError::InvalidRamp.into()),
+            do_process_instruction(
+                ramp_a(&SWAP_PROGRAM_ID, &accounts.swap_key, &admin_key, 100, stop).unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+
+        // only stable swaps can be ramped
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let mut accounts = SwapAccountInfo::new(
+            &user_key,
+            accounts.fees.clone(),
+            swap_curve,
+            token_a_amount,
+            token_b_amount,
+        );
+        accounts.initialize_swap().unwrap();
+        let admin_key = accounts.admin_key;
+        assert_eq!(
+            Err(SwapError::UnsupportedCurveType.into()),
+            do_process_instruction(
+                ramp_a(&SWAP_PROGRAM_ID, &accounts.swap_key, &admin_key, 200, stop).unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+    }
 
-        // wrong pool token account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                maximum_pool_token_amount,
-                initial_b,
-                maximum_pool_token_amount,
-            );
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    destination_b_amount,
-                    maximum_pool_token_amount,
-                )
-            );
-        }
+    #[test]
+    fn test_pool_status() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 1,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 1,
+            host_fee_numerator: 0,
+            host_fee_denominator: 1,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 2_000_000);
+        accounts.initialize_swap().unwrap();
+        let admin_key = accounts.admin_key;
+        assert_eq!(
+            SwapVersion::unpack_pool_status(&accounts.swap_account.data),
+            Ok(Some(PoolStatus::Active))
+        );
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 100_000, 10_000);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
 
-        // wrong pool fee account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                wrong_pool_key,
-                wrong_pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                maximum_pool_token_amount,
-            );
-            let (
-                _token_a_key,
-                _token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                maximum_pool_token_amount,
-            );
-            let old_pool_fee_account = accounts.pool_fee_account;
-            let old_pool_fee_key = accounts.pool_fee_key;
-            accounts.pool_fee_account = wrong_pool_account;
-            accounts.pool_fee_key = wrong_pool_key;
-            assert_eq!(
-                Err(SwapError::IncorrectFeeAccount.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
+        // only the admin can set the status
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_pool_status(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &wrong_key,
+                    PoolStatus::SwapsPaused
                 )
-            );
-            accounts.pool_fee_account = old_pool_fee_account;
-            accounts.pool_fee_key = old_pool_fee_key;
-        }
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
 
-        // no approval
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+        // paused swaps reject anything trading against the pool
+        do_process_instruction(
+            set_pool_status(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                PoolStatus::SwapsPaused,
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            accounts.swap(
                 &user_key,
-                &withdrawer_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
                 0,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            accounts.deposit_single_token_type_exact_amount_in(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &pool_key,
+                &mut pool_account,
+                1_000,
                 0,
-                maximum_pool_token_amount,
-            );
-            let user_transfer_authority_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(TokenError::OwnerMismatch.into()),
-                do_process_instruction(
-                    withdraw_single_token_type_exact_amount_out(
-                        &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &user_transfer_authority_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &pool_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_a_key,
-                        WithdrawSingleTokenTypeExactAmountOut {
-                            destination_token_amount: destination_a_amount,
-                            maximum_pool_token_amount,
-                        }
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_a_account,
-                        &mut accounts.pool_fee_account,
-                        &mut Account::default(),
-                    ],
-                )
-            );
-        }
-
-        // wrong token program id
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+            )
+        );
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            accounts.withdraw_single_token_type_exact_amount_out(
                 &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                maximum_pool_token_amount,
-            );
-            let wrong_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                do_process_instruction(
-                    withdraw_single_token_type_exact_amount_out(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_key,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &accounts.authority_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        &pool_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_a_key,
-                        WithdrawSingleTokenTypeExactAmountOut {
-                            destination_token_amount: destination_a_amount,
-                            maximum_pool_token_amount,
-                        }
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut accounts.pool_mint_account,
-                        &mut pool_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_a_account,
-                        &mut accounts.pool_fee_account,
-                        &mut Account::default(),
-                    ],
-                )
-            );
-        }
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                1_000,
+                u64::MAX,
+            )
+        );
+        // as well as any deposit
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            accounts.deposit_all_token_types(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                10_000,
+                u64::MAX,
+                u64::MAX,
+            )
+        );
 
-        // wrong swap token accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+        // withdraw-only pools also reject deposits, but never trap liquidity
+        do_process_instruction(
+            set_pool_status(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                PoolStatus::WithdrawOnly,
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            accounts.deposit_all_token_types(
                 &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                10_000,
+                u64::MAX,
+                u64::MAX,
+            )
+        );
+        accounts
+            .withdraw_all_token_types(
+                &user_key,
+                &pool_key,
+                &mut pool_account,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                10_000,
+                0,
+                0,
+            )
+            .unwrap();
 
-            let old_a_key = accounts.token_a_key;
-            let old_a_account = accounts.token_a_account;
+        // and reactivated pools can trade again
+        do_process_instruction(
+            set_pool_status(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                PoolStatus::Active,
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        accounts
+            .swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
+            )
+            .unwrap();
+    }
 
-            accounts.token_a_key = token_a_key;
-            accounts.token_a_account = token_a_account.clone();
+    #[test]
+    fn test_flash_loan() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 1_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 1,
+            host_fee_numerator: 0,
+            host_fee_denominator: 1,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let token_a_amount = 1_000_000;
+        let token_b_amount = 2_000_000;
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+        accounts.initialize_swap().unwrap();
+        let (token_a_key, mut token_a_account, _, _, _, _) =
+            accounts.setup_token_accounts(&user_key, &user_key, 10_000, 0, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let loan = 100_000;
+        let trade_fee = 1_000;
+        let owner_fee = 100;
 
-            // wrong swap token a account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
+        let flash_loan_instruction = |receiver_program_id: &Pubkey| {
+            flash_loan(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &accounts.swap_key,
+                &accounts.authority_key,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_a_key,
+                &accounts.pool_mint_key,
+                &accounts.pool_fee_key,
+                receiver_program_id,
+                &[AccountMeta::new_readonly(user_key, true)],
+                loan,
+            )
+            .unwrap()
+        };
+
+        // the loan must be repaid with its fees
+        let defaulter_instruction = flash_loan_instruction(&FLASH_LOAN_DEFAULTER_ID);
+        let receiver_instruction = flash_loan_instruction(&FLASH_LOAN_RECEIVER_ID);
+        let second_receiver_instruction = flash_loan_instruction(&FLASH_LOAN_RECEIVER_ID);
+        let swap_program_instruction = flash_loan_instruction(&SWAP_PROGRAM_ID);
+        let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
+        let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
+        assert_eq!(
+            Err(SwapError::FlashLoanNotRepaid.into()),
+            do_process_instruction(
+                defaulter_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut swap_token_a_account,
+                    &mut swap_token_b_account,
                     &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
-                )
-            );
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
 
-            accounts.token_a_key = old_a_key;
-            accounts.token_a_account = old_a_account;
+        // the swap program cannot receive its own loans
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction(
+                swap_program_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut swap_token_a_account,
+                    &mut swap_token_b_account,
+                    &mut token_a_account,
+                    &mut accounts.pool_mint_account,
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
 
-            let old_b_key = accounts.token_b_key;
-            let old_b_account = accounts.token_b_account;
+        do_process_instruction(
+            receiver_instruction,
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut swap_token_a_account,
+                &mut swap_token_b_account,
+                &mut token_a_account,
+                &mut accounts.pool_mint_account,
+                &mut accounts.pool_fee_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-            accounts.token_b_key = token_b_key;
-            accounts.token_b_account = token_b_account.clone();
+        // the trading fee stays in the pool, and the owner fee is minted as
+        // pool tokens
+        let swap_token_a = spl_token::state::Account::unpack(&swap_token_a_account.data).unwrap();
+        assert_eq!(swap_token_a.amount, token_a_amount + trade_fee + owner_fee);
+        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
+        assert_eq!(token_a.amount, 10_000 - trade_fee - owner_fee);
+        let pool_fee_account =
+            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        let expected_pool_fee = accounts
+            .swap_curve
+            .trading_tokens_to_pool_tokens(
+                owner_fee as u128,
+                swap_token_a.amount as u128,
+                token_b_amount as u128,
+                INITIAL_SWAP_POOL_AMOUNT,
+                TradeDirection::AtoB,
+                RoundDirection::Ceiling,
+                &accounts.fees,
+            )
+            .unwrap();
+        assert!(expected_pool_fee > 0);
+        assert_eq!(pool_fee_account.amount as u128, expected_pool_fee);
 
-            // wrong swap token b account
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    destination_b_amount,
-                    maximum_pool_token_amount,
+        // new swaps charge their trade fees on flash loans until the admin
+        // sets a flash loan fee
+        assert_eq!(
+            SwapVersion::unpack_flash_loan_fee(&accounts.swap_account.data),
+            Ok(Some(FlashLoanFee::from_trade_fees(&accounts.fees)))
+        );
+        let flash_loan_fee = SetFlashLoanFee {
+            trade_fee_numerator: 5,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 0,
+        };
+        let admin_key = accounts.admin_key;
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_flash_loan_fee(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &wrong_key,
+                    flash_loan_fee.clone(),
                 )
-            );
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidFee.into()),
+            do_process_instruction(
+                set_flash_loan_fee(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &admin_key,
+                    SetFlashLoanFee {
+                        trade_fee_numerator: 2,
+                        trade_fee_denominator: 1,
+                        ..flash_loan_fee.clone()
+                    },
+                )
+                .unwrap(),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        do_process_instruction(
+            set_flash_loan_fee(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                flash_loan_fee,
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+
+        // the next loan only pays the flash loan fee, and mints nothing to
+        // the owner
+        let flash_trade_fee = 50;
+        do_process_instruction(
+            second_receiver_instruction,
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut swap_token_a_account,
+                &mut swap_token_b_account,
+                &mut token_a_account,
+                &mut accounts.pool_mint_account,
+                &mut accounts.pool_fee_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let swap_token_a = spl_token::state::Account::unpack(&swap_token_a_account.data).unwrap();
+        assert_eq!(
+            swap_token_a.amount,
+            token_a_amount + trade_fee + owner_fee + flash_trade_fee
+        );
+        let pool_fee_account =
+            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        assert_eq!(pool_fee_account.amount as u128, expected_pool_fee);
+    }
+
+    #[test]
+    fn test_quote() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 1_000,
+            owner_withdraw_fee_numerator: 1,
+            owner_withdraw_fee_denominator: 100,
+            host_fee_numerator: 0,
+            host_fee_denominator: 1,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 2_000_000);
+        accounts.initialize_swap().unwrap();
+        let admin_key = accounts.admin_key;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 100_000, 0);
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let quote_instruction = |accounts: &SwapAccountInfo, operation, amount| {
+            quote(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &accounts.token_a_key,
+                &accounts.token_b_key,
+                &accounts.pool_mint_key,
+                Quote {
+                    operation,
+                    amount,
+                    trade_direction: TradeDirection::AtoB,
+                },
+            )
+            .unwrap()
+        };
+        let reserves = |accounts: &SwapAccountInfo| {
+            let swap_token_a =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+            let swap_token_b =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
+            let pool_mint =
+                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+            PoolReserves {
+                token_a_amount: swap_token_a.amount as u128,
+                token_b_amount: swap_token_b.amount as u128,
+                pool_supply: pool_mint.supply as u128,
+            }
+        };
 
-            accounts.token_b_key = old_b_key;
-            accounts.token_b_account = old_b_account;
-        }
+        // the quoting instruction checks the swap accounts
+        let mut wrong_instruction = quote_instruction(&accounts, QuoteOperation::Swap, 10_000);
+        wrong_instruction.accounts[1].pubkey = accounts.token_b_key;
+        wrong_instruction.accounts[2].pubkey = accounts.token_a_key;
+        let mut wrong_token_account = accounts.token_b_account.clone();
+        assert_eq!(
+            Err(SwapError::IncorrectSwapAccount.into()),
+            do_process_instruction(
+                wrong_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut wrong_token_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.pool_mint_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            quote_instruction(&accounts, QuoteOperation::Swap, 10_000),
+            vec![
+                &mut accounts.swap_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut accounts.pool_mint_account,
+            ],
+        )
+        .unwrap();
 
-        // wrong mint
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+        // quoted swaps match the swap performed
+        let expected = quote::swap(
+            &accounts.swap_curve,
+            &accounts.fees,
+            &reserves(&accounts),
+            10_000,
+            TradeDirection::AtoB,
+        )
+        .unwrap();
+        assert!(expected.trade_fee > 0);
+        assert!(expected.owner_fee > 0);
+        accounts
+            .swap(
                 &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
-            let (pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
-
-            assert_eq!(
-                Err(SwapError::IncorrectPoolMint.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
-                )
-            );
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                10_000,
+                0,
+            )
+            .unwrap();
+        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        assert_eq!(expected.token_a_amount, 10_000);
+        assert_eq!(token_b.amount as u128, 100_000 + expected.token_b_amount);
+        let pool_fee = spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+        assert_eq!(pool_fee.amount as u128, expected.pool_token_amount);
+        assert_eq!(reserves(&accounts), expected.reserves_after);
+
+        // quoted single token deposits match the deposit performed
+        let expected = quote::deposit_single_token_type_exact_amount_in(
+            &accounts.swap_curve,
+            &accounts.fees,
+            &reserves(&accounts),
+            5_000,
+            TradeDirection::AtoB,
+        )
+        .unwrap();
+        accounts
+            .deposit_single_token_type_exact_amount_in(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &pool_key,
+                &mut pool_account,
+                5_000,
+                0,
+            )
+            .unwrap();
+        let pool = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+        assert_eq!(pool.amount as u128, expected.pool_token_amount);
+        assert_eq!(reserves(&accounts), expected.reserves_after);
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
-        }
+        // quotes fail like the operations when the pool is paused
+        do_process_instruction(
+            set_pool_status(
+                &SWAP_PROGRAM_ID,
+                &accounts.swap_key,
+                &admin_key,
+                PoolStatus::SwapsPaused,
+            )
+            .unwrap(),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction(
+                quote_instruction(&accounts, QuoteOperation::Swap, 10_000),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            quote_instruction(&accounts, QuoteOperation::WithdrawAllTokenTypes, 1_000),
+            vec![
+                &mut accounts.swap_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut accounts.pool_mint_account,
+            ],
+        )
+        .unwrap();
+    }
 
-        // slippage exceeded
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                maximum_pool_token_amount,
-            );
+    #[test]
+    fn test_farm() {
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 1_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts =
+            SwapAccountInfo::new(&user_key, fees, swap_curve, 10_000_000, 20_000_000);
+        accounts.initialize_swap().unwrap();
+        let swap_key = accounts.swap_key;
+        let admin_key = accounts.admin_key;
+        let authority_key = accounts.authority_key;
+        let (
+            token_a_key,
+            mut token_a_account,
+            token_b_key,
+            mut token_b_account,
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 0, 0, 1_000);
+        let farm_key = Pubkey::new_unique();
+        let mut farm_account = Account::new(0, Farm::get_packed_len(), &SWAP_PROGRAM_ID);
+        let (farm_authority_key, farm_nonce) =
+            Pubkey::find_program_address(&[&farm_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let (stake_vault_key, mut stake_vault_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.pool_mint_key,
+            &mut accounts.pool_mint_account,
+            &authority_key,
+            &farm_authority_key,
+            0,
+        );
+        let (swap_owned_vault_key, mut swap_owned_vault_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.pool_mint_key,
+            &mut accounts.pool_mint_account,
+            &authority_key,
+            &authority_key,
+            0,
+        );
+        let (reward_mint_key, mut reward_mint_account) =
+            create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
+        let (reward_vault_key, mut reward_vault_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &reward_mint_key,
+            &mut reward_mint_account,
+            &user_key,
+            &farm_authority_key,
+            1_000,
+        );
+        let (reward_key, mut reward_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &reward_mint_key,
+            &mut reward_mint_account,
+            &user_key,
+            &user_key,
+            0,
+        );
+        let stake_key = Pubkey::new_unique();
+        let mut stake_account = Account::new(0, FarmStake::get_packed_len(), &SWAP_PROGRAM_ID);
+        set_test_slot(100);
 
-            // maximum pool token amount too low
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_a_key,
-                    &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount / 1000,
+        // only the admin can create a farm
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                initialize_farm(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &wrong_key,
+                    &farm_key,
+                    &farm_authority_key,
+                    &stake_vault_key,
+                    InitializeFarm { nonce: farm_nonce },
                 )
-            );
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    destination_b_amount,
-                    maximum_pool_token_amount / 1000,
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut farm_account,
+                    &mut Account::default(),
+                    &mut stake_vault_account,
+                ],
+            )
+        );
+        // the vaults of farms are owned by the farm authority, since the swap
+        // authority signs for users of the pool
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            do_process_instruction(
+                initialize_farm(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &admin_key,
+                    &farm_key,
+                    &authority_key,
+                    &stake_vault_key,
+                    InitializeFarm { nonce: farm_nonce },
                 )
-            );
-        }
-
-        // invalid input: can't use swap pool tokens as destination
-        {
-            let (
-                _token_a_key,
-                _token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
-                &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                maximum_pool_token_amount,
-            );
-            let swap_token_a_key = accounts.token_a_key;
-            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &swap_token_a_key,
-                    &mut swap_token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut farm_account,
+                    &mut Account::default(),
+                    &mut stake_vault_account,
+                ],
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            do_process_instruction(
+                initialize_farm(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &admin_key,
+                    &farm_key,
+                    &farm_authority_key,
+                    &swap_owned_vault_key,
+                    InitializeFarm { nonce: farm_nonce },
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut farm_account,
+                    &mut Account::default(),
+                    &mut swap_owned_vault_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            initialize_farm(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &admin_key,
+                &farm_key,
+                &farm_authority_key,
+                &stake_vault_key,
+                InitializeFarm { nonce: farm_nonce },
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut farm_account,
+                &mut Account::default(),
+                &mut stake_vault_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            do_process_instruction(
+                initialize_farm(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &admin_key,
+                    &farm_key,
+                    &farm_authority_key,
+                    &stake_vault_key,
+                    InitializeFarm { nonce: farm_nonce },
                 )
-            );
-            let swap_token_b_key = accounts.token_b_key;
-            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
-                    &swap_token_b_key,
-                    &mut swap_token_b_account,
-                    destination_b_amount,
-                    maximum_pool_token_amount,
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut farm_account,
+                    &mut Account::default(),
+                    &mut stake_vault_account,
+                ],
+            )
+        );
+
+        // rewards cannot be paid out of the pool reserves
+        let swap_token_a_key = accounts.token_a_key;
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction(
+                add_farm_reward(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &admin_key,
+                    &farm_key,
+                    &swap_token_a_key,
+                    AddFarmReward {
+                        reward_per_slot: 10
+                    },
                 )
-            );
-        }
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut farm_account,
+                    &mut accounts.token_a_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            add_farm_reward(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &admin_key,
+                &farm_key,
+                &reward_vault_key,
+                AddFarmReward {
+                    reward_per_slot: 10,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut farm_account,
+                &mut reward_vault_account,
+            ],
+        )
+        .unwrap();
 
-        // correct withdrawal
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(
+        let stake_instruction = |amount| {
+            stake(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &farm_key,
+                &stake_key,
                 &user_key,
-                &withdrawer_key,
-                initial_a,
-                initial_b,
-                initial_pool.try_into().unwrap(),
-            );
+                &user_key,
+                &pool_key,
+                &stake_vault_key,
+                Stake { amount },
+            )
+            .unwrap()
+        };
+        let unstake_instruction = |amount| {
+            unstake(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &farm_authority_key,
+                &farm_key,
+                &stake_key,
+                &user_key,
+                &stake_vault_key,
+                &pool_key,
+                Unstake { amount },
+            )
+            .unwrap()
+        };
+        let claim_instruction = claim_farm_rewards(
+            &SWAP_PROGRAM_ID,
+            &TOKEN_PROGRAM_ID,
+            &swap_key,
+            &farm_authority_key,
+            &farm_key,
+            &stake_key,
+            &user_key,
+            &[(reward_vault_key, reward_key)],
+        )
+        .unwrap();
 
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-            let swap_token_b =
-                spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-            let pool_mint =
-                spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        // the swap authority cannot stake the pool tokens it holds
+        let mut swap_owned_stake_instruction = stake(
+            &SWAP_PROGRAM_ID,
+            &TOKEN_PROGRAM_ID,
+            &swap_key,
+            &farm_key,
+            &stake_key,
+            &user_key,
+            &authority_key,
+            &swap_owned_vault_key,
+            &stake_vault_key,
+            Stake { amount: 400 },
+        )
+        .unwrap();
+        swap_owned_stake_instruction.accounts[4].is_signer = false;
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            do_process_instruction(
+                swap_owned_stake_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut farm_account,
+                    &mut stake_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut swap_owned_vault_account,
+                    &mut stake_vault_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
 
-            let pool_token_amount = accounts
-                .swap_curve
-                .trading_tokens_to_pool_tokens(
-                    destination_a_amount.try_into().unwrap(),
-                    (swap_token_a.amount - destination_a_amount)
-                        .try_into()
-                        .unwrap(),
-                    swap_token_b.amount.try_into().unwrap(),
-                    pool_mint.supply.try_into().unwrap(),
-                    TradeDirection::AtoB,
-                    RoundDirection::Ceiling,
-                    &accounts.fees,
-                )
-                .unwrap();
-            let withdraw_fee = accounts.fees.owner_withdraw_fee(pool_token_amount).unwrap();
+        do_process_instruction(
+            stake_instruction(400),
+            vec![
+                &mut accounts.swap_account,
+                &mut farm_account,
+                &mut stake_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut pool_account,
+                &mut stake_vault_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-            accounts
-                .withdraw_single_token_type_exact_amount_out(
-                    &withdrawer_key,
-                    &pool_key,
-                    &mut pool_account,
+        // the swap authority cannot move the staked pool tokens on behalf of
+        // a user of the pool
+        assert_eq!(
+            Err(TokenError::OwnerMismatch.into()),
+            do_process_instruction(
+                withdraw_all_token_types(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &authority_key,
+                    &accounts.pool_mint_key,
+                    &accounts.pool_fee_key,
+                    &stake_vault_key,
+                    &accounts.token_a_key,
+                    &accounts.token_b_key,
                     &token_a_key,
-                    &mut token_a_account,
-                    destination_a_amount,
-                    maximum_pool_token_amount,
+                    &token_b_key,
+                    WithdrawAllTokenTypes {
+                        pool_token_amount: 400,
+                        minimum_token_a_amount: 0,
+                        minimum_token_b_amount: 0,
+                    },
                 )
-                .unwrap();
-
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-
-            assert_eq!(swap_token_a.amount, token_a_amount - destination_a_amount);
-            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, initial_a + destination_a_amount);
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut accounts.pool_mint_account,
+                    &mut stake_vault_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut token_a_account,
+                    &mut token_b_account,
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        let stake_vault = spl_token::state::Account::unpack(&stake_vault_account.data).unwrap();
+        assert_eq!(stake_vault.amount, 400);
 
-            let pool_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
-            assert_eq!(
-                pool_account.amount,
-                to_u64(initial_pool - pool_token_amount - withdraw_fee).unwrap()
-            );
-            let fee_account =
-                spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
-            assert_eq!(fee_account.amount, to_u64(withdraw_fee).unwrap());
-        }
+        // the stake earns all the rewards paid while it is the only one
+        set_test_slot(150);
+        do_process_instruction(
+            unstake_instruction(144),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut farm_account,
+                &mut stake_account,
+                &mut Account::default(),
+                &mut stake_vault_account,
+                &mut pool_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        let farm_stake = FarmStake::unpack(&stake_account.data).unwrap();
+        assert_eq!(farm_stake.amount, 256);
+        assert_eq!(farm_stake.rewards_owed[0], 500);
+        let stake_vault = spl_token::state::Account::unpack(&stake_vault_account.data).unwrap();
+        assert_eq!(stake_vault.amount, 256);
+        assert_eq!(
+            Err(SwapError::InsufficientStake.into()),
+            do_process_instruction(
+                unstake_instruction(257),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut farm_account,
+                    &mut stake_account,
+                    &mut Account::default(),
+                    &mut stake_vault_account,
+                    &mut pool_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
 
-        // correct withdrawal from fee account
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, initial_a, initial_b, 0);
+        // claims pay out what the vault holds, and keep the rest owed
+        set_test_slot(200);
+        do_process_instruction(
+            claim_instruction.clone(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut farm_account,
+                &mut stake_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reward_vault_account,
+                &mut reward_account,
+            ],
+        )
+        .unwrap();
+        let reward = spl_token::state::Account::unpack(&reward_account.data).unwrap();
+        assert_eq!(reward.amount, 1_000);
+        set_test_slot(250);
+        do_process_instruction(
+            claim_instruction,
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut farm_account,
+                &mut stake_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut reward_vault_account,
+                &mut reward_account,
+            ],
+        )
+        .unwrap();
+        let farm_stake = FarmStake::unpack(&stake_account.data).unwrap();
+        assert_eq!(farm_stake.rewards_owed[0], 500);
+        let reward = spl_token::state::Account::unpack(&reward_account.data).unwrap();
+        assert_eq!(reward.amount, 1_000);
+    }
 
-            let fee_a_amount = 2;
-            let pool_fee_key = accounts.pool_fee_key;
-            let mut pool_fee_account = accounts.pool_fee_account.clone();
-            let fee_account = spl_token::state::Account::unpack(&pool_fee_account.data).unwrap();
-            let pool_fee_amount = fee_account.amount;
+    #[test]
+    fn test_dynamic_fee() {
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 0,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 1_000_000);
+        accounts.initialize_swap().unwrap();
+        let swap_key = accounts.swap_key;
+        let admin_key = accounts.admin_key;
+        let swap_token_a_key = accounts.token_a_key;
+        let swap_token_b_key = accounts.token_b_key;
+        let (token_a_key, mut token_a_account, token_b_key, mut token_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &user_key, 100_000, 0, 0);
+        assert_eq!(
+            SwapVersion::unpack_dynamic_fee(&accounts.swap_account.data),
+            Ok(Some(DynamicFee::default()))
+        );
+        set_test_slot(10);
 
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
+        let set_dynamic_fee_instruction = |admin_key: &Pubkey, max_trade_fee_numerator| {
+            set_dynamic_fee(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                admin_key,
+                SetDynamicFee {
+                    min_trade_fee_numerator: 10,
+                    max_trade_fee_numerator,
+                    max_volatility: 1_000,
+                    volatility_period: 100,
+                },
+            )
+            .unwrap()
+        };
 
-            let token_a_amount = swap_token_a.amount;
+        // only the admin can set a dynamic fee
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_dynamic_fee_instruction(&wrong_key, 1_000),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        // and the maximum fee must still be valid
+        assert_eq!(
+            Err(SwapError::InvalidFee.into()),
+            do_process_instruction(
+                set_dynamic_fee_instruction(&admin_key, 10_000),
+                vec![&mut accounts.swap_account, &mut Account::default()],
+            )
+        );
+        do_process_instruction(
+            set_dynamic_fee_instruction(&admin_key, 1_000),
+            vec![&mut accounts.swap_account, &mut Account::default()],
+        )
+        .unwrap();
+
+        // each swap pays the trade fee for the volatility before it
+        let mut charged = vec![];
+        for (slot, amount_in) in [(10, 10_000), (10, 10_000), (60, 5_000), (200, 5_000)].iter() {
+            set_test_slot(*slot);
+            let dynamic_fee = SwapVersion::unpack_dynamic_fee(&accounts.swap_account.data)
+                .unwrap()
+                .unwrap()
+                .decay(*slot);
+            let fees = dynamic_fee.fees(&accounts.fees);
+            charged.push(fees.trade_fee_numerator);
+
+            let swap_token_a_amount =
+                spl_token::state::Account::unpack(&accounts.token_a_account.data)
+                    .unwrap()
+                    .amount as u128;
+            let swap_token_b_amount =
+                spl_token::state::Account::unpack(&accounts.token_b_account.data)
+                    .unwrap()
+                    .amount as u128;
+            let expected = accounts
+                .swap_curve
+                .swap(
+                    *amount_in as u128,
+                    swap_token_a_amount,
+                    swap_token_b_amount,
+                    TradeDirection::AtoB,
+                    &fees,
+                )
+                .unwrap();
+            let token_b_before = spl_token::state::Account::unpack(&token_b_account.data)
+                .unwrap()
+                .amount;
             accounts
-                .withdraw_single_token_type_exact_amount_out(
+                .swap(
                     &user_key,
-                    &pool_fee_key,
-                    &mut pool_fee_account,
                     &token_a_key,
                     &mut token_a_account,
-                    fee_a_amount,
-                    pool_fee_amount,
+                    &swap_token_a_key,
+                    &swap_token_b_key,
+                    &token_b_key,
+                    &mut token_b_account,
+                    *amount_in,
+                    0,
                 )
                 .unwrap();
+            let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+            assert_eq!(
+                (token_b.amount - token_b_before) as u128,
+                expected.destination_amount_swapped
+            );
 
-            let swap_token_a =
-                spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-
-            assert_eq!(swap_token_a.amount, token_a_amount - fee_a_amount);
-            let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-            assert_eq!(token_a.amount, initial_a + fee_a_amount);
+            let mut recorded = dynamic_fee;
+            recorded.record_trade(
+                swap_token_a_amount,
+                swap_token_b_amount,
+                expected.new_swap_source_amount,
+                expected.new_swap_destination_amount,
+            );
+            assert_eq!(
+                SwapVersion::unpack_dynamic_fee(&accounts.swap_account.data),
+                Ok(Some(recorded))
+            );
         }
-    }
 
-    fn check_valid_swap_curve(
-        fees: Fees,
-        curve_type: CurveType,
-        calculator: Box<dyn CurveCalculator>,
-        token_a_amount: u64,
-        token_b_amount: u64,
-    ) {
-        let user_key = Pubkey::new_unique();
-        let swapper_key = Pubkey::new_unique();
+        // the fee rises after the first swap, falls as the volatility decays,
+        // and is back to the minimum once a full period passes
+        assert_eq!(charged[0], 10);
+        assert!(charged[1] > charged[0]);
+        assert!(charged[2] < charged[1]);
+        assert!(charged[2] > charged[0]);
+        assert_eq!(charged[3], 10);
+    }
 
+    #[test]
+    fn test_allowlist() {
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 0,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
         let swap_curve = SwapCurve {
-            curve_type,
-            calculator,
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
         };
-
-        let mut accounts = SwapAccountInfo::new(
-            &user_key,
-            fees.clone(),
-            swap_curve.clone(),
-            token_a_amount,
-            token_b_amount,
-        );
-        let initial_a = token_a_amount / 5;
-        let initial_b = token_b_amount / 5;
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 1_000_000);
         accounts.initialize_swap().unwrap();
-
+        let swap_key = accounts.swap_key;
+        let admin_key = accounts.admin_key;
+        let authority_key = accounts.authority_key;
         let swap_token_a_key = accounts.token_a_key;
         let swap_token_b_key = accounts.token_b_key;
-
+        let pool_mint_key = accounts.pool_mint_key;
+        let pool_fee_key = accounts.pool_fee_key;
         let (
             token_a_key,
             mut token_a_account,
             token_b_key,
             mut token_b_account,
-            _pool_key,
-            _pool_account,
-        ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-        // swap one way
-        let a_to_b_amount = initial_a / 10;
-        let minimum_token_b_amount = 0;
-        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-        let initial_supply = pool_mint.supply;
-        accounts
-            .swap(
-                &swapper_key,
-                &token_a_key,
-                &mut token_a_account,
-                &swap_token_a_key,
-                &swap_token_b_key,
-                &token_b_key,
-                &mut token_b_account,
-                a_to_b_amount,
-                minimum_token_b_amount,
-            )
-            .unwrap();
-
-        let results = swap_curve
-            .swap(
-                a_to_b_amount.try_into().unwrap(),
-                token_a_amount.try_into().unwrap(),
-                token_b_amount.try_into().unwrap(),
-                TradeDirection::AtoB,
-                &fees,
-            )
-            .unwrap();
+            pool_key,
+            mut pool_account,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 100_000, 100_000, 0);
+        assert_eq!(
+            SwapVersion::unpack_allowlist(&accounts.swap_account.data),
+            Ok(Some(SwapAllowlist::default()))
+        );
 
-        let swap_token_a =
-            spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-        let token_a_amount = swap_token_a.amount;
+        // only the admin can set the allowlist
+        let allowlist_key = Pubkey::new_unique();
+        let wrong_key = Pubkey::new_unique();
         assert_eq!(
-            token_a_amount,
-            TryInto::<u64>::try_into(results.new_swap_source_amount).unwrap()
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_allowlist(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &wrong_key,
+                    &allowlist_key,
+                    false
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
         );
-        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-        assert_eq!(token_a.amount, initial_a - a_to_b_amount);
+        do_process_instruction(
+            set_allowlist(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &admin_key,
+                &allowlist_key,
+                false,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-        let swap_token_b =
-            spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-        let token_b_amount = swap_token_b.amount;
+        // and only the allowlist authority can add members
+        let membership_key = Pubkey::new_unique();
+        let mut membership_account = Account::new(0, AllowlistMember::LEN, &SWAP_PROGRAM_ID);
         assert_eq!(
-            token_b_amount,
-            TryInto::<u64>::try_into(results.new_swap_destination_amount).unwrap()
+            Err(SwapError::InvalidAllowlistAuthority.into()),
+            do_process_instruction(
+                add_allowlist_member(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &admin_key,
+                    &membership_key,
+                    &user_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut membership_account,
+                    &mut Account::default(),
+                ],
+            )
         );
-        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+        do_process_instruction(
+            add_allowlist_member(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &allowlist_key,
+                &membership_key,
+                &user_key,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut membership_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
         assert_eq!(
-            token_b.amount,
-            initial_b + to_u64(results.destination_amount_swapped).unwrap()
+            Err(SwapError::AlreadyInUse.into()),
+            do_process_instruction(
+                add_allowlist_member(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    &allowlist_key,
+                    &membership_key,
+                    &user_key,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut membership_account,
+                    &mut Account::default(),
+                ],
+            )
         );
+        let other_membership_key = Pubkey::new_unique();
+        let mut other_membership_account = Account::new(0, AllowlistMember::LEN, &SWAP_PROGRAM_ID);
+        do_process_instruction(
+            add_allowlist_member(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &allowlist_key,
+                &other_membership_key,
+                &wrong_key,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut other_membership_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
 
-        let first_fee = swap_curve
-            .trading_tokens_to_pool_tokens(
-                results.owner_fee,
-                token_a_amount.try_into().unwrap(),
-                token_b_amount.try_into().unwrap(),
-                initial_supply.try_into().unwrap(),
-                TradeDirection::AtoB,
-                RoundDirection::Ceiling,
-                &fees,
+        // deposits need the membership of the user transfer authority
+        let deposit_instruction = || {
+            deposit_all_token_types(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_key,
+                &token_a_key,
+                &token_b_key,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &pool_mint_key,
+                &pool_key,
+                DepositAllTokenTypes {
+                    pool_token_amount: 1_000_000,
+                    maximum_token_a_amount: 1_000,
+                    maximum_token_b_amount: 1_000,
+                },
             )
-            .unwrap();
-        let fee_account =
-            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
+            .unwrap()
+        };
+        assert_eq!(
+            Err(SwapError::NotAllowlisted.into()),
+            accounts.deposit_all_token_types(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                1_000_000,
+                1_000,
+                1_000,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::NotAllowlisted.into()),
+            do_process_instruction(
+                with_allowlist_membership(deposit_instruction(), &other_membership_key),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut token_a_account,
+                    &mut token_b_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut pool_account,
+                    &mut Account::default(),
+                    &mut other_membership_account,
+                ],
+            )
+        );
+        do_process_instruction(
+            with_allowlist_membership(deposit_instruction(), &membership_key),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut token_a_account,
+                &mut token_b_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut accounts.pool_mint_account,
+                &mut pool_account,
+                &mut Account::default(),
+                &mut membership_account,
+            ],
+        )
+        .unwrap();
+        let pool = spl_token::state::Account::unpack(&pool_account.data).unwrap();
+        assert_eq!(pool.amount, 1_000_000);
+
+        let deposit_single_instruction = deposit_single_token_type_exact_amount_in(
+            &SWAP_PROGRAM_ID,
+            &TOKEN_PROGRAM_ID,
+            &swap_key,
+            &authority_key,
+            &user_key,
+            &token_a_key,
+            &swap_token_a_key,
+            &swap_token_b_key,
+            &pool_mint_key,
+            &pool_key,
+            DepositSingleTokenTypeExactAmountIn {
+                source_token_amount: 1_000,
+                minimum_pool_token_amount: 0,
+            },
+        )
+        .unwrap();
         assert_eq!(
-            fee_account.amount,
-            TryInto::<u64>::try_into(first_fee).unwrap()
+            Err(SwapError::NotAllowlisted.into()),
+            accounts.deposit_single_token_type_exact_amount_in(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &pool_key,
+                &mut pool_account,
+                1_000,
+                0,
+            )
         );
+        do_process_instruction(
+            with_allowlist_membership(deposit_single_instruction, &membership_key),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut token_a_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut accounts.pool_mint_account,
+                &mut pool_account,
+                &mut Account::default(),
+                &mut membership_account,
+            ],
+        )
+        .unwrap();
 
-        let first_swap_amount = results.destination_amount_swapped;
-
-        // swap the other way
-        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
-        let initial_supply = pool_mint.supply;
-
-        let b_to_a_amount = initial_b / 10;
-        let minimum_a_amount = 0;
+        // swaps are open to everyone until they are gated
         accounts
             .swap(
-                &swapper_key,
-                &token_b_key,
-                &mut token_b_account,
-                &swap_token_b_key,
-                &swap_token_a_key,
+                &user_key,
                 &token_a_key,
                 &mut token_a_account,
-                b_to_a_amount,
-                minimum_a_amount,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
             )
             .unwrap();
-
-        let results = swap_curve
-            .swap(
-                b_to_a_amount.try_into().unwrap(),
-                token_b_amount.try_into().unwrap(),
-                token_a_amount.try_into().unwrap(),
-                TradeDirection::BtoA,
-                &fees,
+        do_process_instruction(
+            set_allowlist(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &admin_key,
+                &allowlist_key,
+                true,
             )
-            .unwrap();
-
-        let swap_token_a =
-            spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-        let token_a_amount = swap_token_a.amount;
-        assert_eq!(
-            token_a_amount,
-            TryInto::<u64>::try_into(results.new_swap_destination_amount).unwrap()
-        );
-        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-        assert_eq!(
-            token_a.amount,
-            initial_a - a_to_b_amount + to_u64(results.destination_amount_swapped).unwrap()
-        );
-
-        let swap_token_b =
-            spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-        let token_b_amount = swap_token_b.amount;
-        assert_eq!(
-            token_b_amount,
-            TryInto::<u64>::try_into(results.new_swap_source_amount).unwrap()
-        );
-        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
         assert_eq!(
-            token_b.amount,
-            initial_b + to_u64(first_swap_amount).unwrap()
-                - to_u64(results.source_amount_swapped).unwrap()
-        );
-
-        let second_fee = swap_curve
-            .trading_tokens_to_pool_tokens(
-                results.owner_fee,
-                token_a_amount.try_into().unwrap(),
-                token_b_amount.try_into().unwrap(),
-                initial_supply.try_into().unwrap(),
-                TradeDirection::BtoA,
-                RoundDirection::Ceiling,
-                &fees,
+            Err(SwapError::NotAllowlisted.into()),
+            accounts.swap(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &swap_token_a_key,
+                &swap_token_b_key,
+                &token_b_key,
+                &mut token_b_account,
+                1_000,
+                0,
             )
-            .unwrap();
-        let fee_account =
-            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
-        assert_eq!(fee_account.amount, to_u64(first_fee + second_fee).unwrap());
-    }
-
-    #[test]
-    fn test_valid_swap_curves_all_fees() {
-        // All fees
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 10;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 30;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 30;
-        let host_fee_numerator = 20;
-        let host_fee_denominator = 100;
-        let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
-        };
-
-        let token_a_amount = 10_000_000_000;
-        let token_b_amount = 50_000_000_000;
-
-        check_valid_swap_curve(
-            fees.clone(),
-            CurveType::ConstantProduct,
-            Box::new(ConstantProductCurve {}),
-            token_a_amount,
-            token_b_amount,
-        );
-        let token_b_price = 1;
-        check_valid_swap_curve(
-            fees.clone(),
-            CurveType::ConstantPrice,
-            Box::new(ConstantPriceCurve { token_b_price }),
-            token_a_amount,
-            token_b_amount,
-        );
-        let token_b_offset = 10_000_000_000;
-        check_valid_swap_curve(
-            fees,
-            CurveType::Offset,
-            Box::new(OffsetCurve { token_b_offset }),
-            token_a_amount,
-            token_b_amount,
         );
-    }
-
-    #[test]
-    fn test_valid_swap_curves_trade_fee_only() {
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 10;
-        let owner_trade_fee_numerator = 0;
-        let owner_trade_fee_denominator = 0;
-        let owner_withdraw_fee_numerator = 0;
-        let owner_withdraw_fee_denominator = 0;
-        let host_fee_numerator = 0;
-        let host_fee_denominator = 0;
-        let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
-        };
-
-        let token_a_amount = 10_000_000_000;
-        let token_b_amount = 50_000_000_000;
+        let swap_instruction = swap(
+            &SWAP_PROGRAM_ID,
+            &TOKEN_PROGRAM_ID,
+            &swap_key,
+            &authority_key,
+            &user_key,
+            &token_a_key,
+            &swap_token_a_key,
+            &swap_token_b_key,
+            &token_b_key,
+            &pool_mint_key,
+            &pool_fee_key,
+            None,
+            Swap {
+                amount_in: 1_000,
+                minimum_amount_out: 0,
+            },
+        )
+        .unwrap();
+        do_process_instruction(
+            with_allowlist_membership(swap_instruction, &membership_key),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut token_a_account,
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut token_b_account,
+                &mut accounts.pool_mint_account,
+                &mut accounts.pool_fee_account,
+                &mut Account::default(),
+                &mut membership_account,
+            ],
+        )
+        .unwrap();
 
-        check_valid_swap_curve(
-            fees.clone(),
-            CurveType::ConstantProduct,
-            Box::new(ConstantProductCurve {}),
-            token_a_amount,
-            token_b_amount,
-        );
-        let token_b_price = 10_000;
-        check_valid_swap_curve(
-            fees.clone(),
-            CurveType::ConstantPrice,
-            Box::new(ConstantPriceCurve { token_b_price }),
-            token_a_amount,
-            token_b_amount / token_b_price,
-        );
-        let token_b_offset = 1;
-        check_valid_swap_curve(
-            fees,
-            CurveType::Offset,
-            Box::new(OffsetCurve { token_b_offset }),
-            token_a_amount,
-            token_b_amount,
+        // removed members can no longer deposit
+        do_process_instruction(
+            remove_allowlist_member(&SWAP_PROGRAM_ID, &swap_key, &allowlist_key, &membership_key)
+                .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut membership_account,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::NotAllowlisted.into()),
+            do_process_instruction(
+                with_allowlist_membership(deposit_instruction(), &membership_key),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                    &mut token_a_account,
+                    &mut token_b_account,
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut pool_account,
+                    &mut Account::default(),
+                    &mut membership_account,
+                ],
+            )
         );
+
+        // removing the allowlist opens the pool again
+        do_process_instruction(
+            set_allowlist(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &admin_key,
+                &Pubkey::default(),
+                false,
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        accounts
+            .deposit_all_token_types(
+                &user_key,
+                &token_a_key,
+                &mut token_a_account,
+                &token_b_key,
+                &mut token_b_account,
+                &pool_key,
+                &mut pool_account,
+                1_000_000,
+                2_000,
+                2_000,
+            )
+            .unwrap();
     }
 
     #[test]
-    fn test_valid_swap_with_fee_constraints() {
-        let owner_key = Pubkey::new_unique();
-
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 10;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 30;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 30;
-        let host_fee_numerator = 10;
-        let host_fee_denominator = 100;
-
-        let token_a_amount = 1_000_000;
-        let token_b_amount = 5_000_000;
-
+    fn test_migrate_swap() {
         let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
         };
-
-        let curve = ConstantProductCurve {};
         let swap_curve = SwapCurve {
             curve_type: CurveType::ConstantProduct,
-            calculator: Box::new(curve),
+            calculator: Box::new(ConstantProductCurve {}),
         };
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 1_000_000);
+        // a swap created before the extension was added
+        accounts.initialize_legacy_swap().unwrap();
+        assert_eq!(
+            SwapVersion::unpack_extension(&accounts.swap_account.data),
+            Ok(None)
+        );
+        let old_swap = SwapVersion::unpack_swap_v1(&accounts.swap_account.data).unwrap();
+        set_test_slot(50);
 
-        let owner_key_str = &owner_key.to_string();
-        let valid_curve_types = &[CurveType::ConstantProduct];
-        let constraints = Some(SwapConstraints {
-            owner_key: owner_key_str,
-            valid_curve_types,
-            fees: &fees,
-        });
-        let mut accounts = SwapAccountInfo::new(
-            &owner_key,
-            fees.clone(),
-            swap_curve,
-            token_a_amount,
-            token_b_amount,
+        // without an admin, the old swap holds no allowlist or fee split
+        // that would stay keyed to it
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_allowlist(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &user_key,
+                    &user_key,
+                    true,
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction(
+                set_fee_split(
+                    &SWAP_PROGRAM_ID,
+                    &accounts.swap_key,
+                    &user_key,
+                    &Pubkey::new_unique(),
+                    &[],
+                )
+                .unwrap(),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::new(0, FeeSplit::LEN, &SWAP_PROGRAM_ID),
+                ],
+            )
         );
 
-        // initialize swap
-        do_process_instruction_with_fee_constraints(
-            initialize(
+        // an order left open on the old swap
+        let (
+            order_user_a_key,
+            mut order_user_a_account,
+            order_user_b_key,
+            mut order_user_b_account,
+            _,
+            _,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 10_000, 0, 0);
+        let order_key = Pubkey::new_unique();
+        let mut order_account = Account::new(0, LimitOrder::LEN, &SWAP_PROGRAM_ID);
+        let (order_authority_key, order_nonce) =
+            Pubkey::find_program_address(&[&order_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let (escrow_key, mut escrow_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &order_authority_key,
+            0,
+        );
+        let user_transfer_key = Pubkey::new_unique();
+        do_process_instruction(
+            approve(
+                &TOKEN_PROGRAM_ID,
+                &order_user_a_key,
+                &user_transfer_key,
+                &user_key,
+                &[],
+                10_000,
+            )
+            .unwrap(),
+            vec![
+                &mut order_user_a_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+        do_process_instruction(
+            create_limit_order(
                 &SWAP_PROGRAM_ID,
                 &TOKEN_PROGRAM_ID,
                 &accounts.swap_key,
-                &accounts.authority_key,
-                &accounts.token_a_key,
-                &accounts.token_b_key,
-                &accounts.pool_mint_key,
-                &accounts.pool_fee_key,
-                &accounts.pool_token_key,
-                accounts.nonce,
-                accounts.fees.clone(),
-                accounts.swap_curve.clone(),
+                &order_key,
+                &order_authority_key,
+                &user_key,
+                &user_transfer_key,
+                &order_user_a_key,
+                &escrow_key,
+                &order_user_b_key,
+                CreateLimitOrder {
+                    nonce: order_nonce,
+                    trade_direction: TradeDirection::AtoB,
+                    kind: LimitOrderKind::TakeProfit,
+                    amount_in: 10_000,
+                    trigger_amount_out: 20_000,
+                    minimum_amount_out: 20_000,
+                },
+            )
+            .unwrap(),
+            vec![
+                &mut accounts.swap_account,
+                &mut order_account,
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut Account::default(),
+                &mut order_user_a_account,
+                &mut escrow_account,
+                &mut order_user_b_account,
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        let new_swap_key = Pubkey::new_unique();
+        let mut new_swap_account = Account::new(0, SwapVersion::LATEST_LEN, &SWAP_PROGRAM_ID);
+        let (new_authority_key, new_nonce) =
+            Pubkey::find_program_address(&[&new_swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let swap_key = accounts.swap_key;
+        let authority_key = accounts.authority_key;
+        let token_a_key = accounts.token_a_key;
+        let token_b_key = accounts.token_b_key;
+        let pool_mint_key = accounts.pool_mint_key;
+        let pool_fee_key = accounts.pool_fee_key;
+        let migrate_instruction = |new_swap_key: &Pubkey,
+                                   new_authority_key: &Pubkey,
+                                   migration_authority_key: &Pubkey,
+                                   admin_key: Option<&Pubkey>,
+                                   nonce| {
+            migrate_swap(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                new_swap_key,
+                new_authority_key,
+                &token_a_key,
+                &token_b_key,
+                &pool_mint_key,
+                &pool_fee_key,
+                migration_authority_key,
+                admin_key,
+                nonce,
+            )
+            .unwrap()
+        };
+
+        // swaps without an admin are migrated by the owner of their fees
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            do_process_instruction(
+                migrate_instruction(
+                    &new_swap_key,
+                    &new_authority_key,
+                    &Pubkey::new_unique(),
+                    None,
+                    new_nonce
+                ),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // the new authority must belong to the new swap
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            do_process_instruction(
+                migrate_instruction(
+                    &new_swap_key,
+                    &new_authority_key,
+                    &user_key,
+                    None,
+                    new_nonce.wrapping_sub(1)
+                ),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // the admin of the new swap must agree to take control of it
+        let admin_key = Pubkey::new_unique();
+        let mut unsigned_instruction = migrate_instruction(
+            &new_swap_key,
+            &new_authority_key,
+            &user_key,
+            Some(&admin_key),
+            new_nonce,
+        );
+        unsigned_instruction.accounts[10].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(
+                unsigned_instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                    &mut Account::default(),
+                ],
+            )
+        );
+        do_process_instruction(
+            migrate_instruction(
+                &new_swap_key,
+                &new_authority_key,
+                &user_key,
+                Some(&admin_key),
+                new_nonce,
+            ),
+            vec![
+                &mut accounts.swap_account,
+                &mut Account::default(),
+                &mut new_swap_account,
+                &mut Account::default(),
+                &mut accounts.token_a_account,
+                &mut accounts.token_b_account,
+                &mut accounts.pool_mint_account,
+                &mut Account::default(),
+                &mut accounts.pool_fee_account,
+                &mut Account::default(),
+                &mut Account::default(),
+            ],
+        )
+        .unwrap();
+
+        // the new swap keeps the state of the old one, holds its tokens, and
+        // starts its extension as a new swap with the given admin
+        let new_swap = SwapVersion::unpack_swap_v1(&new_swap_account.data).unwrap();
+        assert_eq!(
+            new_swap,
+            SwapV1 {
+                nonce: new_nonce,
+                ..old_swap
+            }
+        );
+        assert_eq!(
+            SwapVersion::unpack_extension(&new_swap_account.data),
+            Ok(Some(SwapExtension {
+                price_accumulator: PriceAccumulator::new(50),
+                admin: SwapAdmin {
+                    admin_authority: admin_key,
+                },
+                flash_loan_fee: FlashLoanFee::from_trade_fees(&accounts.fees),
+                ..SwapExtension::default()
+            }))
+        );
+        for token_account in [&accounts.token_a_account, &accounts.token_b_account].iter() {
+            let token_account = spl_token::state::Account::unpack(&token_account.data).unwrap();
+            assert_eq!(token_account.owner, new_authority_key);
+        }
+        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.mint_authority, COption::Some(new_authority_key));
+
+        // the old swap is retired, and cannot be initialized or migrated
+        // again
+        assert!(SwapVersion::is_initialized(&accounts.swap_account.data));
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            accounts.initialize_swap()
+        );
+        let retired_swap =
+            SwapVersion::unpack_migrated_swap_v1(&accounts.swap_account.data).unwrap();
+        assert_eq!(retired_swap.pool_mint, pool_mint_key);
+        let other_swap_key = Pubkey::new_unique();
+        let (other_authority_key, other_nonce) =
+            Pubkey::find_program_address(&[&other_swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            do_process_instruction(
+                migrate_instruction(
+                    &other_swap_key,
+                    &other_authority_key,
+                    &user_key,
+                    None,
+                    other_nonce
+                ),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut Account::new(0, SwapVersion::LATEST_LEN, &SWAP_PROGRAM_ID),
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+
+        // orders on the old swap can still be cancelled
+        do_process_instruction(
+            cancel_limit_order(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &order_key,
+                &order_authority_key,
+                &user_key,
+                &escrow_key,
+                &order_user_a_key,
             )
             .unwrap(),
             vec![
                 &mut accounts.swap_account,
+                &mut order_account,
                 &mut Account::default(),
-                &mut accounts.token_a_account,
-                &mut accounts.token_b_account,
-                &mut accounts.pool_mint_account,
-                &mut accounts.pool_fee_account,
-                &mut accounts.pool_token_account,
+                &mut Account::default(),
+                &mut escrow_account,
+                &mut order_user_a_account,
                 &mut Account::default(),
             ],
-            &constraints,
         )
         .unwrap();
-
-        let authority_key = accounts.authority_key;
-
-        let (
-            token_a_key,
-            mut token_a_account,
-            token_b_key,
-            mut token_b_account,
-            pool_key,
-            mut pool_account,
-        ) = accounts.setup_token_accounts(
-            &owner_key,
-            &authority_key,
-            token_a_amount,
-            token_b_amount,
-            0,
+        assert!(
+            !LimitOrder::unpack_unchecked(&order_account.data)
+                .unwrap()
+                .is_initialized
         );
+        let refund = spl_token::state::Account::unpack(&order_user_a_account.data).unwrap();
+        assert_eq!(refund.amount, 10_000);
+
+        // and the pool is used through the new swap
+        accounts.swap_key = new_swap_key;
+        accounts.swap_account = new_swap_account;
+        accounts.authority_key = new_authority_key;
+        accounts.nonce = new_nonce;
+        let (
+            user_token_a_key,
+            mut user_token_a_account,
+            user_token_b_key,
+            mut user_token_b_account,
+            _,
+            _,
+        ) = accounts.setup_token_accounts(&user_key, &user_key, 10_000, 0, 0);
+        accounts
+            .swap(
+                &user_key,
+                &user_token_a_key,
+                &mut user_token_a_account,
+                &token_a_key,
+                &token_b_key,
+                &user_token_b_key,
+                &mut user_token_b_account,
+                10_000,
+                1,
+            )
+            .unwrap();
+        let user_token_b = spl_token::state::Account::unpack(&user_token_b_account.data).unwrap();
+        assert!(user_token_b.amount > 0);
+    }
 
-        let amount_in = token_a_amount / 2;
-        let minimum_amount_out = 0;
-
-        // perform the swap
-        do_process_instruction_with_fee_constraints(
-            swap(
+    #[test]
+    fn test_migrate_swap_authority() {
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 1_000_000);
+        // a swap created before the extension was added
+        accounts.initialize_legacy_swap().unwrap();
+
+        let new_swap_key = Pubkey::new_unique();
+        let mut new_swap_account = Account::new(0, SwapVersion::LATEST_LEN, &SWAP_PROGRAM_ID);
+        let (new_authority_key, new_nonce) =
+            Pubkey::find_program_address(&[&new_swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let swap_key = accounts.swap_key;
+        let authority_key = accounts.authority_key;
+        let token_a_key = accounts.token_a_key;
+        let token_b_key = accounts.token_b_key;
+        let pool_mint_key = accounts.pool_mint_key;
+        let migrate_instruction = |pool_fee_key: &Pubkey, migration_authority_key: &Pubkey| {
+            migrate_swap(
                 &SWAP_PROGRAM_ID,
                 &TOKEN_PROGRAM_ID,
-                &accounts.swap_key,
-                &accounts.authority_key,
-                &accounts.authority_key,
+                &swap_key,
+                &authority_key,
+                &new_swap_key,
+                &new_authority_key,
                 &token_a_key,
-                &accounts.token_a_key,
-                &accounts.token_b_key,
                 &token_b_key,
-                &accounts.pool_mint_key,
-                &accounts.pool_fee_key,
-                Some(&pool_key),
-                Swap {
-                    amount_in,
-                    minimum_amount_out,
-                },
+                &pool_mint_key,
+                pool_fee_key,
+                migration_authority_key,
+                None,
+                new_nonce,
             )
-            .unwrap(),
+            .unwrap()
+        };
+
+        // any other signer cannot migrate it
+        let pool_fee_key = accounts.pool_fee_key;
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidOwner.into()),
+            do_process_instruction(
+                migrate_instruction(&pool_fee_key, &wrong_key),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // even with a fee account of its own
+        let (wrong_fee_key, mut wrong_fee_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.pool_mint_key,
+            &mut accounts.pool_mint_account,
+            &accounts.authority_key,
+            &wrong_key,
+            0,
+        );
+        assert_eq!(
+            Err(SwapError::IncorrectFeeAccount.into()),
+            do_process_instruction(
+                migrate_instruction(&wrong_fee_key, &wrong_key),
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut wrong_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        // the owner of the fees must sign
+        let mut instruction = migrate_instruction(&pool_fee_key, &user_key);
+        instruction.accounts[9].is_signer = false;
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction(
+                instruction,
+                vec![
+                    &mut accounts.swap_account,
+                    &mut Account::default(),
+                    &mut new_swap_account,
+                    &mut Account::default(),
+                    &mut accounts.token_a_account,
+                    &mut accounts.token_b_account,
+                    &mut accounts.pool_mint_account,
+                    &mut Account::default(),
+                    &mut accounts.pool_fee_account,
+                    &mut Account::default(),
+                ],
+            )
+        );
+        assert!(SwapVersion::is_initialized(&accounts.swap_account.data));
+
+        do_process_instruction(
+            migrate_instruction(&pool_fee_key, &user_key),
             vec![
                 &mut accounts.swap_account,
                 &mut Account::default(),
+                &mut new_swap_account,
                 &mut Account::default(),
-                &mut token_a_account,
                 &mut accounts.token_a_account,
                 &mut accounts.token_b_account,
-                &mut token_b_account,
                 &mut accounts.pool_mint_account,
+                &mut Account::default(),
                 &mut accounts.pool_fee_account,
                 &mut Account::default(),
-                &mut pool_account,
             ],
-            &constraints,
         )
         .unwrap();
-
-        // check that fees were taken in the host fee account
-        let host_fee_account = spl_token::state::Account::unpack(&pool_account.data).unwrap();
-        let owner_fee_account =
-            spl_token::state::Account::unpack(&accounts.pool_fee_account.data).unwrap();
-        let total_fee = owner_fee_account.amount * host_fee_denominator
-            / (host_fee_denominator - host_fee_numerator);
+        assert!(SwapVersion::unpack_swap_v1(&accounts.swap_account.data).is_err());
+        assert!(SwapVersion::is_initialized(&new_swap_account.data));
         assert_eq!(
-            total_fee,
-            host_fee_account.amount + owner_fee_account.amount
+            SwapVersion::unpack_admin(&new_swap_account.data),
+            Ok(Some(SwapAdmin::default()))
         );
+        let pool_mint = spl_token::state::Mint::unpack(&accounts.pool_mint_account.data).unwrap();
+        assert_eq!(pool_mint.mint_authority, COption::Some(new_authority_key));
     }
 
     #[test]
-    fn test_invalid_swap() {
+    fn test_limit_order() {
+        let fees = Fees {
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 5,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        };
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        };
         let user_key = Pubkey::new_unique();
-        let swapper_key = Pubkey::new_unique();
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 4;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 10;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 5;
-        let host_fee_numerator = 9;
-        let host_fee_denominator = 100;
+        let user_transfer_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 1_000_000);
+        accounts.initialize_swap().unwrap();
+        let (user_a_key, user_a_account, user_b_key, user_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &user_key, 10_000, 100_000, 0);
+
+        let order_key = Pubkey::new_unique();
+        let (order_authority_key, order_nonce) =
+            Pubkey::find_program_address(&[&order_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let (escrow_key, escrow_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &order_authority_key,
+            0,
+        );
+        let (proceeds_key, proceeds_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.token_b_mint_key,
+            &mut accounts.token_b_mint_account,
+            &user_key,
+            &user_key,
+            0,
+        );
+        let swap_key = accounts.swap_key;
+        let authority_key = accounts.authority_key;
+        let token_a_key = accounts.token_a_key;
+        let token_b_key = accounts.token_b_key;
+        let pool_mint_key = accounts.pool_mint_key;
+        let pool_fee_key = accounts.pool_fee_key;
+        let mut keyed_accounts = vec![
+            (swap_key, accounts.swap_account.clone()),
+            (token_a_key, accounts.token_a_account.clone()),
+            (token_b_key, accounts.token_b_account.clone()),
+            (pool_mint_key, accounts.pool_mint_account.clone()),
+            (pool_fee_key, accounts.pool_fee_account.clone()),
+            (user_a_key, user_a_account),
+            (user_b_key, user_b_account),
+            (
+                order_key,
+                Account::new(0, LimitOrder::LEN, &SWAP_PROGRAM_ID),
+            ),
+            (escrow_key, escrow_account),
+            (proceeds_key, proceeds_account),
+        ];
+        for key in [user_a_key, user_b_key].iter() {
+            do_process_instruction_with_accounts(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    key,
+                    &user_transfer_key,
+                    &user_key,
+                    &[],
+                    u64::MAX,
+                )
+                .unwrap(),
+                &mut keyed_accounts,
+            )
+            .unwrap();
+        }
+
+        let create_instruction = |kind, trigger_amount_out, minimum_amount_out, nonce| {
+            create_limit_order(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &order_key,
+                &order_authority_key,
+                &user_key,
+                &user_transfer_key,
+                &user_a_key,
+                &escrow_key,
+                &proceeds_key,
+                CreateLimitOrder {
+                    nonce,
+                    trade_direction: TradeDirection::AtoB,
+                    kind,
+                    amount_in: 10_000,
+                    trigger_amount_out,
+                    minimum_amount_out,
+                },
+            )
+            .unwrap()
+        };
+        let fill_instruction = |swap_source_key: &Pubkey, swap_destination_key: &Pubkey| {
+            fill_limit_order(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &order_key,
+                &order_authority_key,
+                &escrow_key,
+                swap_source_key,
+                swap_destination_key,
+                &proceeds_key,
+                &pool_mint_key,
+                &pool_fee_key,
+            )
+            .unwrap()
+        };
+        let cancel_instruction = |owner_key: &Pubkey| {
+            cancel_limit_order(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &order_key,
+                &order_authority_key,
+                owner_key,
+                &escrow_key,
+                &user_a_key,
+            )
+            .unwrap()
+        };
+        let order_initialized = |accounts: &[(Pubkey, Account)]| {
+            let (_, account) = accounts.iter().find(|(k, _)| *k == order_key).unwrap();
+            LimitOrder::unpack_unchecked(&account.data)
+                .unwrap()
+                .is_initialized
+        };
+
+        // the order authority must belong to the order
+        assert_eq!(
+            Err(SwapError::InvalidProgramAddress.into()),
+            do_process_instruction_with_accounts(
+                create_instruction(
+                    LimitOrderKind::TakeProfit,
+                    10_500,
+                    10_500,
+                    order_nonce.wrapping_sub(1)
+                ),
+                &mut keyed_accounts,
+            )
+        );
+        // a stop loss cannot fill below its floor
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction_with_accounts(
+                create_instruction(LimitOrderKind::StopLoss, 5_000, 6_000, order_nonce),
+                &mut keyed_accounts,
+            )
+        );
+
+        // the swap authority cannot be used to move the reserves, or any
+        // other tokens it holds, into an order
+        let steal_instruction = |source_key: &Pubkey| {
+            let mut instruction = create_limit_order(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &order_key,
+                &order_authority_key,
+                &user_key,
+                &authority_key,
+                source_key,
+                &escrow_key,
+                &proceeds_key,
+                CreateLimitOrder {
+                    nonce: order_nonce,
+                    trade_direction: TradeDirection::AtoB,
+                    kind: LimitOrderKind::TakeProfit,
+                    amount_in: 10_000,
+                    trigger_amount_out: 0,
+                    minimum_amount_out: 0,
+                },
+            )
+            .unwrap();
+            // only the program can sign for its authority
+            instruction.accounts[4].is_signer = false;
+            instruction
+        };
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction_with_accounts(
+                steal_instruction(&token_a_key),
+                &mut keyed_accounts,
+            )
+        );
+        let (authority_a_key, authority_a_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &accounts.token_a_mint_key,
+            &mut accounts.token_a_mint_account,
+            &user_key,
+            &authority_key,
+            10_000,
+        );
+        keyed_accounts.push((authority_a_key, authority_a_account));
+        assert_eq!(
+            Err(ProgramError::MissingRequiredSignature),
+            do_process_instruction_with_accounts(
+                steal_instruction(&authority_a_key),
+                &mut keyed_accounts,
+            )
+        );
+        assert_eq!(token_amount(&keyed_accounts, &authority_a_key), 10_000);
+        assert_eq!(token_amount(&keyed_accounts, &escrow_key), 0);
+        assert!(!order_initialized(&keyed_accounts));
+
+        // withdraw-only pools take no new orders
+        let admin_key = accounts.admin_key;
+        let set_status_instruction =
+            |status| set_pool_status(&SWAP_PROGRAM_ID, &swap_key, &admin_key, status).unwrap();
+        do_process_instruction_with_accounts(
+            set_status_instruction(PoolStatus::WithdrawOnly),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction_with_accounts(
+                create_instruction(LimitOrderKind::TakeProfit, 10_500, 10_500, order_nonce),
+                &mut keyed_accounts,
+            )
+        );
+        do_process_instruction_with_accounts(
+            set_status_instruction(PoolStatus::Active),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+        do_process_instruction_with_accounts(
+            create_instruction(LimitOrderKind::TakeProfit, 10_500, 10_500, order_nonce),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+        assert_eq!(token_amount(&keyed_accounts, &escrow_key), 10_000);
+        assert_eq!(token_amount(&keyed_accounts, &user_a_key), 0);
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            do_process_instruction_with_accounts(
+                create_instruction(LimitOrderKind::TakeProfit, 10_500, 10_500, order_nonce),
+                &mut keyed_accounts,
+            )
+        );
+
+        // selling 10_000 A only returns about 9_871 B for now
+        assert_eq!(
+            Err(SwapError::LimitOrderNotTriggered.into()),
+            do_process_instruction_with_accounts(
+                fill_instruction(&token_a_key, &token_b_key),
+                &mut keyed_accounts,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::IncorrectSwapAccount.into()),
+            do_process_instruction_with_accounts(
+                fill_instruction(&token_b_key, &token_a_key),
+                &mut keyed_accounts,
+            )
+        );
+
+        // buying A raises its price past the level of the order
+        do_process_instruction_with_accounts(
+            swap(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &user_b_key,
+                &token_b_key,
+                &token_a_key,
+                &user_a_key,
+                &pool_mint_key,
+                &pool_fee_key,
+                None,
+                Swap {
+                    amount_in: 100_000,
+                    minimum_amount_out: 0,
+                },
+            )
+            .unwrap(),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+        do_process_instruction_with_accounts(
+            fill_instruction(&token_a_key, &token_b_key),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+        assert!(token_amount(&keyed_accounts, &proceeds_key) >= 10_500);
+        assert_eq!(token_amount(&keyed_accounts, &escrow_key), 0);
+        assert!(!order_initialized(&keyed_accounts));
+
+        // the order account can then hold a stop loss, which the owner
+        // cancels before it fills
+        let user_a_amount = token_amount(&keyed_accounts, &user_a_key);
+        do_process_instruction_with_accounts(
+            create_instruction(LimitOrderKind::StopLoss, 5_000, 1_000, order_nonce),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::LimitOrderNotTriggered.into()),
+            do_process_instruction_with_accounts(
+                fill_instruction(&token_a_key, &token_b_key),
+                &mut keyed_accounts,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::IncorrectLimitOrder.into()),
+            do_process_instruction_with_accounts(
+                cancel_instruction(&user_transfer_key),
+                &mut keyed_accounts,
+            )
+        );
+        do_process_instruction_with_accounts(cancel_instruction(&user_key), &mut keyed_accounts)
+            .unwrap();
+        assert_eq!(token_amount(&keyed_accounts, &user_a_key), user_a_amount);
+        assert_eq!(token_amount(&keyed_accounts, &escrow_key), 0);
+        assert!(!order_initialized(&keyed_accounts));
+    }
+
+    #[test]
+    fn test_distribute_fees() {
         let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
+            trade_fee_numerator: 25,
+            trade_fee_denominator: 10_000,
+            owner_trade_fee_numerator: 50,
+            owner_trade_fee_denominator: 10_000,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
         };
-
-        let token_a_amount = 1000;
-        let token_b_amount = 5000;
-        let curve_type = CurveType::ConstantProduct;
         let swap_curve = SwapCurve {
-            curve_type,
+            curve_type: CurveType::ConstantProduct,
             calculator: Box::new(ConstantProductCurve {}),
         };
-        let mut accounts =
-            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-
-        let initial_a = token_a_amount / 5;
-        let initial_b = token_b_amount / 5;
-        let minimum_token_b_amount = initial_b / 2;
-
-        let swap_token_a_key = accounts.token_a_key;
-        let swap_token_b_key = accounts.token_b_key;
-
-        // swap not initialized
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(ProgramError::UninitializedAccount),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
-                )
-            );
-        }
-
+        let user_key = Pubkey::new_unique();
+        let mut accounts = SwapAccountInfo::new(&user_key, fees, swap_curve, 1_000_000, 1_000_000);
         accounts.initialize_swap().unwrap();
+        let swap_key = accounts.swap_key;
+        let authority_key = accounts.authority_key;
+        let admin_key = accounts.admin_key;
+        let token_a_key = accounts.token_a_key;
+        let token_b_key = accounts.token_b_key;
+        let pool_mint_key = accounts.pool_mint_key;
+        let pool_fee_key = accounts.pool_fee_key;
+
+        // trade both ways so that the pool fee account holds owner fees
+        let (user_a_key, mut user_a_account, user_b_key, mut user_b_account, _, _) =
+            accounts.setup_token_accounts(&user_key, &user_key, 100_000, 100_000, 0);
+        accounts
+            .swap(
+                &user_key,
+                &user_a_key,
+                &mut user_a_account,
+                &token_a_key,
+                &token_b_key,
+                &user_b_key,
+                &mut user_b_account,
+                100_000,
+                0,
+            )
+            .unwrap();
+        accounts
+            .swap(
+                &user_key,
+                &user_b_key,
+                &mut user_b_account,
+                &token_b_key,
+                &token_a_key,
+                &user_a_key,
+                &mut user_a_account,
+                50_000,
+                0,
+            )
+            .unwrap();
 
-        // wrong swap account program id
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let old_swap_account = accounts.swap_account;
-            let mut wrong_swap_account = old_swap_account.clone();
-            wrong_swap_account.owner = TOKEN_PROGRAM_ID;
-            accounts.swap_account = wrong_swap_account;
-            assert_eq!(
-                Err(ProgramError::IncorrectProgramId),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
-                )
-            );
-            accounts.swap_account = old_swap_account;
-        }
-
-        // wrong nonce
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let old_authority = accounts.authority_key;
-            let (bad_authority_key, _nonce) = Pubkey::find_program_address(
-                &[&accounts.swap_key.to_bytes()[..]],
+        let mut recipients = vec![];
+        let mut keyed_accounts = vec![];
+        for _ in 0..2 {
+            let recipient_key = Pubkey::new_unique();
+            let (recipient_a_key, recipient_a_account) = mint_token(
                 &TOKEN_PROGRAM_ID,
+                &accounts.token_a_mint_key,
+                &mut accounts.token_a_mint_account,
+                &user_key,
+                &recipient_key,
+                0,
             );
-            accounts.authority_key = bad_authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidProgramAddress.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
-                )
-            );
-            accounts.authority_key = old_authority;
-        }
-
-        // wrong token program id
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let wrong_program_id = Pubkey::new_unique();
-            assert_eq!(
-                Err(SwapError::IncorrectTokenProgramId.into()),
-                do_process_instruction(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &wrong_program_id,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &accounts.authority_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_b_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        None,
-                        Swap {
-                            amount_in: initial_a,
-                            minimum_amount_out: minimum_token_b_amount,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut Account::default(),
-                    ],
-                ),
-            );
-        }
-
-        // not enough token a to swap
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(TokenError::InsufficientFunds.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a * 2,
-                    minimum_token_b_amount * 2,
-                )
-            );
-        }
-
-        // wrong swap token A / B accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let user_transfer_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(SwapError::IncorrectSwapAccount.into()),
-                do_process_instruction(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &user_transfer_key,
-                        &token_a_key,
-                        &token_a_key,
-                        &token_b_key,
-                        &token_b_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        None,
-                        Swap {
-                            amount_in: initial_a,
-                            minimum_amount_out: minimum_token_b_amount,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account.clone(),
-                        &mut token_a_account,
-                        &mut token_b_account.clone(),
-                        &mut token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut Account::default(),
-                    ],
-                ),
-            );
-        }
-
-        // wrong user token A / B accounts
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(TokenError::MintMismatch.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    initial_a,
-                    minimum_token_b_amount,
-                )
+            let (recipient_b_key, recipient_b_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &accounts.token_b_mint_key,
+                &mut accounts.token_b_mint_account,
+                &user_key,
+                &recipient_key,
+                0,
             );
-        }
+            recipients.push((recipient_a_key, recipient_b_key));
+            keyed_accounts.push((recipient_a_key, recipient_a_account));
+            keyed_accounts.push((recipient_b_key, recipient_b_account));
+        }
+        let fee_split_key = Pubkey::new_unique();
+        keyed_accounts.extend(vec![
+            (swap_key, accounts.swap_account.clone()),
+            (token_a_key, accounts.token_a_account.clone()),
+            (token_b_key, accounts.token_b_account.clone()),
+            (pool_mint_key, accounts.pool_mint_account.clone()),
+            (pool_fee_key, accounts.pool_fee_account.clone()),
+            (
+                fee_split_key,
+                Account::new(0, FeeSplit::LEN, &SWAP_PROGRAM_ID),
+            ),
+        ]);
 
-        // swap from a to a
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                _token_b_key,
-                _token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account.clone(),
-                    &swap_token_a_key,
-                    &swap_token_a_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    initial_a,
-                    minimum_token_b_amount,
+        let set_fee_split_instruction =
+            |admin_key: &Pubkey, recipients: &[(Pubkey, Pubkey, u16)]| {
+                set_fee_split(
+                    &SWAP_PROGRAM_ID,
+                    &swap_key,
+                    admin_key,
+                    &fee_split_key,
+                    recipients,
                 )
-            );
-        }
-
-        // incorrect mint provided
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let (pool_mint_key, pool_mint_account) =
-                create_mint(&TOKEN_PROGRAM_ID, &accounts.authority_key, None);
-            let old_pool_key = accounts.pool_mint_key;
-            let old_pool_account = accounts.pool_mint_account;
-            accounts.pool_mint_key = pool_mint_key;
-            accounts.pool_mint_account = pool_mint_account;
-
-            assert_eq!(
-                Err(SwapError::IncorrectPoolMint.into()),
-                accounts.swap(
-                    &swapper_key,
+                .unwrap()
+            };
+        let distribute_fees_instruction =
+            |user_transfer_key: &Pubkey, recipients: &[(Pubkey, Pubkey)]| {
+                distribute_fees(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &fee_split_key,
+                    user_transfer_key,
+                    &pool_fee_key,
+                    &pool_mint_key,
                     &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
                     &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
+                    recipients,
                 )
-            );
+                .unwrap()
+            };
+        let (first_a_key, first_b_key) = recipients[0];
+        let (second_a_key, second_b_key) = recipients[1];
 
-            accounts.pool_mint_key = old_pool_key;
-            accounts.pool_mint_account = old_pool_account;
-        }
+        // only the admin can split the fees
+        let wrong_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::InvalidAdmin.into()),
+            do_process_instruction_with_accounts(
+                set_fee_split_instruction(
+                    &wrong_key,
+                    &[
+                        (first_a_key, first_b_key, 7_000),
+                        (second_a_key, second_b_key, 3_000)
+                    ]
+                ),
+                &mut keyed_accounts,
+            )
+        );
+        // the shares must add up to the whole fee
+        assert_eq!(
+            Err(SwapError::InvalidFeeSplit.into()),
+            do_process_instruction_with_accounts(
+                set_fee_split_instruction(
+                    &admin_key,
+                    &[
+                        (first_a_key, first_b_key, 7_000),
+                        (second_a_key, second_b_key, 2_000)
+                    ]
+                ),
+                &mut keyed_accounts,
+            )
+        );
+        // recipients hold the tokens of the pool, in order
+        assert_eq!(
+            Err(SwapError::InvalidFeeSplit.into()),
+            do_process_instruction_with_accounts(
+                set_fee_split_instruction(&admin_key, &[(first_b_key, first_a_key, 10_000)]),
+                &mut keyed_accounts,
+            )
+        );
+        // and can't be the pool itself
+        assert_eq!(
+            Err(SwapError::InvalidFeeSplit.into()),
+            do_process_instruction_with_accounts(
+                set_fee_split_instruction(&admin_key, &[(token_a_key, first_b_key, 10_000)]),
+                &mut keyed_accounts,
+            )
+        );
+        do_process_instruction_with_accounts(
+            set_fee_split_instruction(
+                &admin_key,
+                &[
+                    (first_a_key, first_b_key, 7_000),
+                    (second_a_key, second_b_key, 3_000),
+                ],
+            ),
+            &mut keyed_accounts,
+        )
+        .unwrap();
 
-        // incorrect fee account provided
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                wrong_pool_key,
-                wrong_pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let old_pool_fee_account = accounts.pool_fee_account;
-            let old_pool_fee_key = accounts.pool_fee_key;
-            accounts.pool_fee_account = wrong_pool_account;
-            accounts.pool_fee_key = wrong_pool_key;
-            assert_eq!(
-                Err(SwapError::IncorrectFeeAccount.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
-                )
-            );
-            accounts.pool_fee_account = old_pool_fee_account;
-            accounts.pool_fee_key = old_pool_fee_key;
+        // the recipients must be given in the order of the split
+        assert_eq!(
+            Err(SwapError::InvalidFeeSplit.into()),
+            do_process_instruction_with_accounts(
+                distribute_fees_instruction(
+                    &user_key,
+                    &[(second_a_key, second_b_key), (first_a_key, first_b_key)]
+                ),
+                &mut keyed_accounts,
+            )
+        );
+        // and only the owner of the pool fee account can withdraw its tokens
+        assert_eq!(
+            Err(TokenError::OwnerMismatch.into()),
+            do_process_instruction_with_accounts(
+                distribute_fees_instruction(&wrong_key, &recipients),
+                &mut keyed_accounts,
+            )
+        );
+
+        let pool_fee_amount = token_amount(&keyed_accounts, &pool_fee_key);
+        assert!(pool_fee_amount > 0);
+        let pool_mint_supply = {
+            let (_, account) = keyed_accounts
+                .iter()
+                .find(|(k, _)| *k == pool_mint_key)
+                .unwrap();
+            spl_token::state::Mint::unpack(&account.data)
+                .unwrap()
+                .supply
+        };
+        let swap_token_a_amount = token_amount(&keyed_accounts, &token_a_key);
+        let swap_token_b_amount = token_amount(&keyed_accounts, &token_b_key);
+        let results = accounts
+            .swap_curve
+            .calculator
+            .pool_tokens_to_trading_tokens(
+                pool_fee_amount as u128,
+                pool_mint_supply as u128,
+                swap_token_a_amount as u128,
+                swap_token_b_amount as u128,
+                RoundDirection::Floor,
+            )
+            .unwrap();
+        let (token_a_amount, token_b_amount) =
+            (results.token_a_amount as u64, results.token_b_amount as u64);
+        do_process_instruction_with_accounts(
+            distribute_fees_instruction(&user_key, &recipients),
+            &mut keyed_accounts,
+        )
+        .unwrap();
+
+        // the shares round down, leaving the rest in the pool
+        assert_eq!(token_amount(&keyed_accounts, &pool_fee_key), 0);
+        assert_eq!(
+            token_amount(&keyed_accounts, &first_a_key),
+            token_a_amount * 7_000 / 10_000
+        );
+        assert_eq!(
+            token_amount(&keyed_accounts, &first_b_key),
+            token_b_amount * 7_000 / 10_000
+        );
+        assert_eq!(
+            token_amount(&keyed_accounts, &second_a_key),
+            token_a_amount * 3_000 / 10_000
+        );
+        assert_eq!(
+            token_amount(&keyed_accounts, &second_b_key),
+            token_b_amount * 3_000 / 10_000
+        );
+        assert_eq!(
+            token_amount(&keyed_accounts, &token_a_key),
+            swap_token_a_amount - token_a_amount * 7_000 / 10_000 - token_a_amount * 3_000 / 10_000
+        );
+
+        // with the fees paid out there is nothing left to distribute
+        assert_eq!(
+            Err(SwapError::ZeroTradingTokens.into()),
+            do_process_instruction_with_accounts(
+                distribute_fees_instruction(&user_key, &recipients),
+                &mut keyed_accounts,
+            )
+        );
+    }
+
+    fn do_process_instruction_with_accounts(
+        instruction: Instruction,
+        accounts: &mut [(Pubkey, Account)],
+    ) -> ProgramResult {
+        // accounts not found in the list, like signers, are given an empty
+        // account, as in the single swap tests
+        let mut defaults = vec![Account::default(); instruction.accounts.len()];
+        let mut ordered = defaults.iter_mut().collect::<Vec<_>>();
+        for (key, account) in accounts.iter_mut() {
+            if let Some(index) = instruction
+                .accounts
+                .iter()
+                .position(|account_meta| account_meta.pubkey == *key)
+            {
+                ordered[index] = account;
+            }
         }
+        do_process_instruction(instruction, ordered)
+    }
 
-        // no approval
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let user_transfer_key = Pubkey::new_unique();
-            assert_eq!(
-                Err(TokenError::OwnerMismatch.into()),
-                do_process_instruction(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &user_transfer_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_b_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        None,
-                        Swap {
-                            amount_in: initial_a,
-                            minimum_amount_out: minimum_token_b_amount,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut Account::default(),
-                    ],
-                ),
+    fn token_amount(accounts: &[(Pubkey, Account)], key: &Pubkey) -> u64 {
+        let (_, account) = accounts.iter().find(|(k, _)| k == key).unwrap();
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+
+    #[test]
+    fn test_multi_token_pool() {
+        let user_key = Pubkey::new_unique();
+        let user_transfer_key = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let swap_key = Pubkey::new_unique();
+        let (authority_key, nonce) =
+            Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
+        let fees = Fees {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 100,
+            owner_trade_fee_numerator: 1,
+            owner_trade_fee_denominator: 100,
+            owner_withdraw_fee_numerator: 1,
+            owner_withdraw_fee_denominator: 100,
+            host_fee_numerator: 20,
+            host_fee_denominator: 100,
+        };
+        let weights = vec![20, 30, 50];
+        let swap_curve = || SwapCurve {
+            curve_type: CurveType::Weighted,
+            calculator: Box::new(WeightedCurve {
+                token_a_weight: weights[0],
+                token_b_weight: weights[1],
+            }),
+        };
+        let swap_token_amount = 1_000_000;
+        let user_token_amount = 1_000_000;
+
+        let (pool_mint_key, mut pool_mint_account) =
+            create_mint(&TOKEN_PROGRAM_ID, &authority_key, None);
+        let (pool_fee_key, pool_fee_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &pool_mint_key,
+            &mut pool_mint_account,
+            &authority_key,
+            &user_key,
+            0,
+        );
+        let (pool_token_key, pool_token_account) = mint_token(
+            &TOKEN_PROGRAM_ID,
+            &pool_mint_key,
+            &mut pool_mint_account,
+            &authority_key,
+            &user_key,
+            0,
+        );
+        let mut accounts = vec![
+            (
+                swap_key,
+                Account::new(0, SwapVersion::MULTI_TOKEN_LEN, &SWAP_PROGRAM_ID),
+            ),
+            (pool_fee_key, pool_fee_account),
+            (pool_token_key, pool_token_account),
+        ];
+        let mut swap_token_keys = vec![];
+        let mut user_token_keys = vec![];
+        for _ in 0..weights.len() {
+            let (mint_key, mut mint_account) = create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
+            let (swap_token_key, swap_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &mint_key,
+                &mut mint_account,
+                &user_key,
+                &authority_key,
+                swap_token_amount,
+            );
+            let (user_token_key, user_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &mint_key,
+                &mut mint_account,
+                &user_key,
+                &user_key,
+                user_token_amount,
             );
+            accounts.push((swap_token_key, swap_token_account));
+            accounts.push((user_token_key, user_token_account));
+            swap_token_keys.push(swap_token_key);
+            user_token_keys.push(user_token_key);
         }
+        accounts.push((pool_mint_key, pool_mint_account));
 
-        // output token value 0
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::ZeroTradingTokens.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &swap_token_b_key,
-                    &swap_token_a_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    1,
-                    1,
+        let approve_user_transfer = |accounts: &mut [(Pubkey, Account)], key: &Pubkey| {
+            do_process_instruction_with_accounts(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    key,
+                    &user_transfer_key,
+                    &user_key,
+                    &[],
+                    u64::MAX,
                 )
-            );
-        }
+                .unwrap(),
+                accounts,
+            )
+            .unwrap();
+        };
 
-        // slippage exceeded: minimum out amount too high
+        // two tokens is not a multi-token pool
+        assert_eq!(
+            Err(SwapError::IncorrectTokenCount.into()),
+            do_process_instruction_with_accounts(
+                initialize_multi_token(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &swap_token_keys[..2],
+                    &pool_mint_key,
+                    &pool_fee_key,
+                    &pool_token_key,
+                    None,
+                    nonce,
+                    fees.clone(),
+                    swap_curve(),
+                    weights[..2].to_vec(),
+                )
+                .unwrap(),
+                &mut accounts,
+            )
+        );
+
+        // weights must match the curve
+        assert_eq!(
+            Err(SwapError::InvalidCurve.into()),
+            do_process_instruction_with_accounts(
+                initialize_multi_token(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &swap_token_keys,
+                    &pool_mint_key,
+                    &pool_fee_key,
+                    &pool_token_key,
+                    None,
+                    nonce,
+                    fees.clone(),
+                    swap_curve(),
+                    vec![30, 20, 50],
+                )
+                .unwrap(),
+                &mut accounts,
+            )
+        );
+
+        let initialize = || {
+            initialize_multi_token(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &swap_token_keys,
+                &pool_mint_key,
+                &pool_fee_key,
+                &pool_token_key,
+                Some(&admin_key),
+                nonce,
+                fees.clone(),
+                swap_curve(),
+                weights.clone(),
+            )
+            .unwrap()
+        };
+        do_process_instruction_with_accounts(initialize(), &mut accounts).unwrap();
+        assert_eq!(
+            token_amount(&accounts, &pool_token_key),
+            INITIAL_SWAP_POOL_AMOUNT as u64
+        );
+        let swap_state = SwapVersion::unpack_multi_token(&accounts[0].1.data).unwrap();
+        assert_eq!(swap_state.tokens().len(), weights.len());
+        assert_eq!(swap_state.admin.admin_authority, admin_key);
+        assert_eq!(swap_state.pool_status, PoolStatus::Active);
+        for (token, (swap_token_key, weight)) in swap_state
+            .tokens()
+            .iter()
+            .zip(swap_token_keys.iter().zip(weights.iter()))
         {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::ExceededSlippage.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount * 2,
+            assert_eq!(token.account, *swap_token_key);
+            assert_eq!(token.weight, *weight);
+        }
+
+        // already initialized
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            do_process_instruction_with_accounts(initialize(), &mut accounts)
+        );
+
+        // two-token instructions cannot use a multi-token pool
+        assert_eq!(
+            Err(ProgramError::InvalidAccountData),
+            do_process_instruction_with_accounts(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &user_transfer_key,
+                    &user_token_keys[0],
+                    &swap_token_keys[0],
+                    &swap_token_keys[1],
+                    &user_token_keys[1],
+                    &pool_mint_key,
+                    &pool_fee_key,
+                    None,
+                    Swap {
+                        amount_in: 1_000,
+                        minimum_amount_out: 0,
+                    },
                 )
-            );
+                .unwrap(),
+                &mut accounts,
+            )
+        );
+
+        for user_token_key in user_token_keys.iter() {
+            approve_user_transfer(&mut accounts, user_token_key);
         }
+        approve_user_transfer(&mut accounts, &pool_token_key);
 
-        // invalid input: can't use swap pool as user source / dest
-        {
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            let mut swap_token_a_account = accounts.get_token_account(&swap_token_a_key).clone();
-            let authority_key = accounts.authority_key;
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.swap(
+        // swap between tokens 0 and 2, weights 20 and 50
+        let amount_in = 100_000;
+        let swap_instruction = |minimum_amount_out| {
+            swap_multi_token(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &user_token_keys[0],
+                &user_token_keys[2],
+                &pool_mint_key,
+                &pool_fee_key,
+                &swap_token_keys,
+                None,
+                Swap {
+                    amount_in,
+                    minimum_amount_out,
+                },
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(swap_instruction(amount_in), &mut accounts)
+        );
+        do_process_instruction_with_accounts(swap_instruction(0), &mut accounts).unwrap();
+        let amount_out = token_amount(&accounts, &user_token_keys[2]) - user_token_amount;
+        assert_eq!(
+            token_amount(&accounts, &user_token_keys[0]),
+            user_token_amount - amount_in
+        );
+        assert_eq!(
+            token_amount(&accounts, &swap_token_keys[0]),
+            swap_token_amount + amount_in
+        );
+        assert_eq!(
+            token_amount(&accounts, &swap_token_keys[2]),
+            swap_token_amount - amount_out
+        );
+        assert_eq!(
+            token_amount(&accounts, &swap_token_keys[1]),
+            swap_token_amount
+        );
+        // (1 - (1_000_000 / 1_098_000) ^ (20 / 50)) * 1_000_000
+        assert!(amount_out > 36_000 && amount_out < 37_000);
+        assert!(token_amount(&accounts, &pool_fee_key) > 0);
+
+        // the destination must hold one of the pool's tokens
+        assert_eq!(
+            Err(SwapError::IncorrectSwapAccount.into()),
+            do_process_instruction_with_accounts(
+                swap_multi_token(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
                     &authority_key,
-                    &swap_token_a_key,
-                    &mut swap_token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &token_b_key,
-                    &mut token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
+                    &user_transfer_key,
+                    &user_token_keys[1],
+                    &pool_token_key,
+                    &pool_mint_key,
+                    &pool_fee_key,
+                    &swap_token_keys,
+                    None,
+                    Swap {
+                        amount_in,
+                        minimum_amount_out: 0,
+                    },
                 )
-            );
-            let mut swap_token_b_account = accounts.get_token_account(&swap_token_b_key).clone();
-            assert_eq!(
-                Err(SwapError::InvalidInput.into()),
-                accounts.swap(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &swap_token_a_key,
-                    &swap_token_b_key,
-                    &swap_token_b_key,
-                    &mut swap_token_b_account,
-                    initial_a,
-                    minimum_token_b_amount,
+                .unwrap(),
+                &mut accounts,
+            )
+        );
+
+        // deposit and withdraw all tokens
+        let pool_token_amount = INITIAL_SWAP_POOL_AMOUNT as u64 / 10;
+        assert_eq!(
+            Err(SwapError::IncorrectTokenCount.into()),
+            do_process_instruction_with_accounts(
+                deposit_all_multi_token_types(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &user_transfer_key,
+                    &pool_mint_key,
+                    &pool_token_key,
+                    &swap_token_keys,
+                    &user_token_keys,
+                    DepositAllMultiTokenTypes {
+                        pool_token_amount,
+                        maximum_token_amounts: vec![u64::MAX; 2],
+                    },
                 )
-            );
+                .unwrap(),
+                &mut accounts,
+            )
+        );
+        let swap_balances = swap_token_keys
+            .iter()
+            .map(|key| token_amount(&accounts, key))
+            .collect::<Vec<_>>();
+        do_process_instruction_with_accounts(
+            deposit_all_multi_token_types(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &pool_mint_key,
+                &pool_token_key,
+                &swap_token_keys,
+                &user_token_keys,
+                DepositAllMultiTokenTypes {
+                    pool_token_amount,
+                    maximum_token_amounts: vec![u64::MAX; 3],
+                },
+            )
+            .unwrap(),
+            &mut accounts,
+        )
+        .unwrap();
+        for (swap_token_key, swap_balance) in swap_token_keys.iter().zip(swap_balances.iter()) {
+            // the pool supply also includes the fees from the swap, so each
+            // deposit is slightly less than a tenth of the balance
+            let deposited = token_amount(&accounts, swap_token_key) - swap_balance;
+            assert!(deposited > 0 && deposited <= swap_balance / 10 + 1);
+        }
+        assert_eq!(
+            token_amount(&accounts, &pool_token_key),
+            INITIAL_SWAP_POOL_AMOUNT as u64 + pool_token_amount
+        );
+
+        let swap_balances = swap_token_keys
+            .iter()
+            .map(|key| token_amount(&accounts, key))
+            .collect::<Vec<_>>();
+        let withdraw_all = |minimum_token_amount| {
+            withdraw_all_multi_token_types(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &pool_mint_key,
+                &pool_fee_key,
+                &pool_token_key,
+                &swap_token_keys,
+                &user_token_keys,
+                WithdrawAllMultiTokenTypes {
+                    pool_token_amount,
+                    minimum_token_amounts: vec![minimum_token_amount; 3],
+                },
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(withdraw_all(u64::MAX), &mut accounts)
+        );
+        let pool_fee_amount = token_amount(&accounts, &pool_fee_key);
+        do_process_instruction_with_accounts(withdraw_all(0), &mut accounts).unwrap();
+        let withdraw_fee = pool_token_amount / 100;
+        assert_eq!(
+            token_amount(&accounts, &pool_fee_key),
+            pool_fee_amount + withdraw_fee
+        );
+        assert_eq!(
+            token_amount(&accounts, &pool_token_key),
+            INITIAL_SWAP_POOL_AMOUNT as u64
+        );
+        for (swap_token_key, swap_balance) in swap_token_keys.iter().zip(swap_balances.iter()) {
+            let withdrawn = swap_balance - token_amount(&accounts, swap_token_key);
+            assert!(withdrawn > 0 && withdrawn < swap_balance / 10);
         }
 
-        // still correct: constraint specified, no host fee account
-        {
-            let authority_key = accounts.authority_key;
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &authority_key, initial_a, initial_b, 0);
-            let owner_key = &swapper_key.to_string();
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let constraints = Some(SwapConstraints {
-                owner_key,
-                valid_curve_types: &[],
-                fees: &fees,
-            });
-            do_process_instruction_with_fee_constraints(
-                swap(
+        // deposit and withdraw a single token
+        let pool_token_before = token_amount(&accounts, &pool_token_key);
+        let source_token_amount = 10_000;
+        do_process_instruction_with_accounts(
+            deposit_single_multi_token_type_exact_amount_in(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &user_token_keys[1],
+                &pool_mint_key,
+                &pool_token_key,
+                &swap_token_keys,
+                DepositSingleTokenTypeExactAmountIn {
+                    source_token_amount,
+                    minimum_pool_token_amount: 1,
+                },
+            )
+            .unwrap(),
+            &mut accounts,
+        )
+        .unwrap();
+        let deposited_pool_tokens = token_amount(&accounts, &pool_token_key) - pool_token_before;
+        assert!(deposited_pool_tokens > 0);
+
+        let swap_balance = token_amount(&accounts, &swap_token_keys[1]);
+        let user_balance = token_amount(&accounts, &user_token_keys[1]);
+        let destination_token_amount = source_token_amount / 2;
+        let withdraw_single = |maximum_pool_token_amount| {
+            withdraw_single_multi_token_type_exact_amount_out(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &pool_mint_key,
+                &pool_fee_key,
+                &pool_token_key,
+                &user_token_keys[1],
+                &swap_token_keys,
+                WithdrawSingleTokenTypeExactAmountOut {
+                    destination_token_amount,
+                    maximum_pool_token_amount,
+                },
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(withdraw_single(1), &mut accounts)
+        );
+        do_process_instruction_with_accounts(withdraw_single(deposited_pool_tokens), &mut accounts)
+            .unwrap();
+        assert_eq!(
+            token_amount(&accounts, &swap_token_keys[1]),
+            swap_balance - destination_token_amount
+        );
+        assert_eq!(
+            token_amount(&accounts, &user_token_keys[1]),
+            user_balance + destination_token_amount
+        );
+        let burned_pool_tokens =
+            pool_token_before + deposited_pool_tokens - token_amount(&accounts, &pool_token_key);
+        assert!(burned_pool_tokens > deposited_pool_tokens / 2);
+        assert!(burned_pool_tokens < deposited_pool_tokens);
+
+        // a fee on transfer is taken from the amount in, and the swap is
+        // priced from what reaches the pool
+        let swap_balances = swap_token_keys
+            .iter()
+            .map(|key| to_u128(token_amount(&accounts, key)).unwrap())
+            .collect::<Vec<_>>();
+        let transfer_fee = amount_in / 10;
+        let received = amount_in - transfer_fee;
+        let expected = SwapVersion::unpack_multi_token(&accounts[0].1.data)
+            .unwrap()
+            .multi_token_curve()
+            .unwrap()
+            .swap(0, 2, to_u128(received).unwrap(), &swap_balances, &fees)
+            .unwrap();
+        set_test_transfer_fee(transfer_fee);
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(
+                swap_instruction(to_u64(expected.destination_amount_swapped).unwrap() + 1),
+                &mut accounts
+            )
+        );
+        do_process_instruction_with_accounts(swap_instruction(0), &mut accounts).unwrap();
+        set_test_transfer_fee(0);
+        assert_eq!(
+            to_u128(token_amount(&accounts, &swap_token_keys[0])).unwrap(),
+            swap_balances[0] + to_u128(received).unwrap()
+        );
+        assert_eq!(
+            to_u128(token_amount(&accounts, &swap_token_keys[2])).unwrap(),
+            swap_balances[2] - expected.destination_amount_swapped
+        );
+
+        // a paused pool rejects swaps and deposits, but not withdrawals
+        let set_status_instruction =
+            |status| set_pool_status(&SWAP_PROGRAM_ID, &swap_key, &admin_key, status).unwrap();
+        do_process_instruction_with_accounts(
+            set_status_instruction(PoolStatus::SwapsPaused),
+            &mut accounts,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction_with_accounts(swap_instruction(0), &mut accounts)
+        );
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction_with_accounts(
+                deposit_all_multi_token_types(
                     &SWAP_PROGRAM_ID,
                     &TOKEN_PROGRAM_ID,
-                    &accounts.swap_key,
-                    &accounts.authority_key,
-                    &accounts.authority_key,
-                    &token_a_key,
-                    &accounts.token_a_key,
-                    &accounts.token_b_key,
-                    &token_b_key,
-                    &accounts.pool_mint_key,
-                    &accounts.pool_fee_key,
-                    None,
-                    Swap {
-                        amount_in: initial_a,
-                        minimum_amount_out: minimum_token_b_amount,
+                    &swap_key,
+                    &authority_key,
+                    &user_transfer_key,
+                    &pool_mint_key,
+                    &pool_token_key,
+                    &swap_token_keys,
+                    &user_token_keys,
+                    DepositAllMultiTokenTypes {
+                        pool_token_amount,
+                        maximum_token_amounts: vec![u64::MAX; 3],
                     },
                 )
                 .unwrap(),
-                vec![
-                    &mut accounts.swap_account,
-                    &mut Account::default(),
-                    &mut Account::default(),
-                    &mut token_a_account,
-                    &mut accounts.token_a_account,
-                    &mut accounts.token_b_account,
-                    &mut token_b_account,
-                    &mut accounts.pool_mint_account,
-                    &mut accounts.pool_fee_account,
-                    &mut Account::default(),
-                ],
-                &constraints,
+                &mut accounts,
             )
-            .unwrap();
-        }
-
-        // invalid mint for host fee account
-        {
-            let authority_key = accounts.authority_key;
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &authority_key, initial_a, initial_b, 0);
-            let (
-                bad_token_a_key,
-                mut bad_token_a_account,
-                _token_b_key,
-                mut _token_b_account,
-                _pool_key,
-                _pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &authority_key, initial_a, initial_b, 0);
-            let owner_key = &swapper_key.to_string();
-            let fees = Fees {
-                trade_fee_numerator,
-                trade_fee_denominator,
-                owner_trade_fee_numerator,
-                owner_trade_fee_denominator,
-                owner_withdraw_fee_numerator,
-                owner_withdraw_fee_denominator,
-                host_fee_numerator,
-                host_fee_denominator,
-            };
-            let constraints = Some(SwapConstraints {
-                owner_key,
-                valid_curve_types: &[],
-                fees: &fees,
-            });
-            assert_eq!(
-                Err(SwapError::IncorrectPoolMint.into()),
-                do_process_instruction_with_fee_constraints(
-                    swap(
-                        &SWAP_PROGRAM_ID,
-                        &TOKEN_PROGRAM_ID,
-                        &accounts.swap_key,
-                        &accounts.authority_key,
-                        &accounts.authority_key,
-                        &token_a_key,
-                        &accounts.token_a_key,
-                        &accounts.token_b_key,
-                        &token_b_key,
-                        &accounts.pool_mint_key,
-                        &accounts.pool_fee_key,
-                        Some(&bad_token_a_key),
-                        Swap {
-                            amount_in: initial_a,
-                            minimum_amount_out: 0,
-                        },
-                    )
-                    .unwrap(),
-                    vec![
-                        &mut accounts.swap_account,
-                        &mut Account::default(),
-                        &mut Account::default(),
-                        &mut token_a_account,
-                        &mut accounts.token_a_account,
-                        &mut accounts.token_b_account,
-                        &mut token_b_account,
-                        &mut accounts.pool_mint_account,
-                        &mut accounts.pool_fee_account,
-                        &mut Account::default(),
-                        &mut bad_token_a_account,
-                    ],
-                    &constraints,
-                ),
-            );
-        }
+        );
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction_with_accounts(withdraw_single(u64::MAX), &mut accounts)
+        );
+        do_process_instruction_with_accounts(withdraw_all(0), &mut accounts).unwrap();
+        do_process_instruction_with_accounts(
+            set_status_instruction(PoolStatus::Active),
+            &mut accounts,
+        )
+        .unwrap();
+        do_process_instruction_with_accounts(swap_instruction(0), &mut accounts).unwrap();
     }
 
     #[test]
-    fn test_overdraw_offset_curve() {
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 10;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 30;
-        let owner_withdraw_fee_numerator = 1;
-        let owner_withdraw_fee_denominator = 30;
-        let host_fee_numerator = 10;
-        let host_fee_denominator = 100;
-
-        let token_a_amount = 1_000_000_000;
-        let token_b_amount = 0;
+    fn test_concentrated_liquidity_pool() {
+        let user_key = Pubkey::new_unique();
+        let user_transfer_key = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let owner_key = Pubkey::new_unique();
+        let swap_key = Pubkey::new_unique();
+        let position_key = Pubkey::new_unique();
+        let (authority_key, nonce) =
+            Pubkey::find_program_address(&[&swap_key.to_bytes()[..]], &SWAP_PROGRAM_ID);
         let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
-        };
-
-        let token_b_offset = 2_000_000;
-        let swap_curve = SwapCurve {
-            curve_type: CurveType::Offset,
-            calculator: Box::new(OffsetCurve { token_b_offset }),
+            trade_fee_numerator: 3,
+            trade_fee_denominator: 1000,
+            owner_trade_fee_numerator: 0,
+            owner_trade_fee_denominator: 0,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
         };
-        let user_key = Pubkey::new_unique();
-        let swapper_key = Pubkey::new_unique();
-
-        let mut accounts =
-            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
-
-        accounts.initialize_swap().unwrap();
-
-        let swap_token_a_key = accounts.token_a_key;
-        let swap_token_b_key = accounts.token_b_key;
-        let initial_a = 500_000;
-        let initial_b = 1_000;
+        let tick_spacing = 10;
+        let min_position_liquidity = 1_000_000;
+        let initial_sqrt_price = 1 << 64;
+        let user_token_amount = 1_000_000_000;
 
-        let (
-            token_a_key,
-            mut token_a_account,
-            token_b_key,
-            mut token_b_account,
-            _pool_key,
-            _pool_account,
-        ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
+        let mut accounts = vec![
+            (
+                swap_key,
+                Account::new(0, SwapVersion::CONCENTRATED_LEN, &SWAP_PROGRAM_ID),
+            ),
+            (
+                position_key,
+                Account::new(0, Position::LEN, &SWAP_PROGRAM_ID),
+            ),
+        ];
+        let mut swap_token_keys = vec![];
+        let mut user_token_keys = vec![];
+        for _ in 0..2 {
+            let (mint_key, mut mint_account) = create_mint(&TOKEN_PROGRAM_ID, &user_key, None);
+            let (swap_token_key, swap_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &mint_key,
+                &mut mint_account,
+                &user_key,
+                &authority_key,
+                0,
+            );
+            let (user_token_key, user_token_account) = mint_token(
+                &TOKEN_PROGRAM_ID,
+                &mint_key,
+                &mut mint_account,
+                &user_key,
+                &user_key,
+                user_token_amount,
+            );
+            accounts.push((swap_token_key, swap_token_account));
+            accounts.push((user_token_key, user_token_account));
+            swap_token_keys.push(swap_token_key);
+            user_token_keys.push(user_token_key);
+        }
 
-        // swap a to b way, fails, there's no liquidity
-        let a_to_b_amount = initial_a;
-        let minimum_token_b_amount = 0;
+        let initialize = |fees: Fees, tick_spacing, min_position_liquidity, initial_sqrt_price| {
+            initialize_concentrated(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &swap_token_keys[0],
+                &swap_token_keys[1],
+                Some(&admin_key),
+                nonce,
+                fees,
+                tick_spacing,
+                min_position_liquidity,
+                initial_sqrt_price,
+            )
+            .unwrap()
+        };
 
+        // only the trade fee can be charged without pool tokens
+        let mut owner_fees = fees.clone();
+        owner_fees.owner_trade_fee_numerator = 1;
+        owner_fees.owner_trade_fee_denominator = 100;
         assert_eq!(
-            Err(SwapError::ZeroTradingTokens.into()),
-            accounts.swap(
-                &swapper_key,
-                &token_a_key,
-                &mut token_a_account,
-                &swap_token_a_key,
-                &swap_token_b_key,
-                &token_b_key,
-                &mut token_b_account,
-                a_to_b_amount,
-                minimum_token_b_amount,
+            Err(SwapError::InvalidFee.into()),
+            do_process_instruction_with_accounts(
+                initialize(
+                    owner_fees,
+                    tick_spacing,
+                    min_position_liquidity,
+                    initial_sqrt_price
+                ),
+                &mut accounts,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidTick.into()),
+            do_process_instruction_with_accounts(
+                initialize(fees.clone(), 0, min_position_liquidity, initial_sqrt_price),
+                &mut accounts,
+            )
+        );
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction_with_accounts(
+                initialize(fees.clone(), tick_spacing, 0, initial_sqrt_price),
+                &mut accounts,
             )
         );
-
-        // swap b to a, succeeds at offset price
-        let b_to_a_amount = initial_b;
-        let minimum_token_a_amount = 0;
-        accounts
-            .swap(
-                &swapper_key,
-                &token_b_key,
-                &mut token_b_account,
-                &swap_token_b_key,
-                &swap_token_a_key,
-                &token_a_key,
-                &mut token_a_account,
-                b_to_a_amount,
-                minimum_token_a_amount,
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction_with_accounts(
+                initialize(fees.clone(), tick_spacing, min_position_liquidity, 0),
+                &mut accounts,
             )
-            .unwrap();
+        );
 
-        // try a to b again, succeeds due to new liquidity
-        accounts
-            .swap(
-                &swapper_key,
-                &token_a_key,
-                &mut token_a_account,
-                &swap_token_a_key,
-                &swap_token_b_key,
-                &token_b_key,
-                &mut token_b_account,
-                a_to_b_amount,
-                minimum_token_b_amount,
+        do_process_instruction_with_accounts(
+            initialize(
+                fees.clone(),
+                tick_spacing,
+                min_position_liquidity,
+                initial_sqrt_price,
+            ),
+            &mut accounts,
+        )
+        .unwrap();
+        let pool = SwapVersion::unpack_concentrated(&accounts[0].1.data).unwrap();
+        assert_eq!(pool.token_a, swap_token_keys[0]);
+        assert_eq!(pool.token_b, swap_token_keys[1]);
+        assert_eq!(pool.tick_current, 0);
+        assert_eq!(pool.liquidity, 0);
+        assert_eq!(pool.min_position_liquidity, min_position_liquidity);
+        assert_eq!(pool.admin.admin_authority, admin_key);
+        assert_eq!(pool.pool_status, PoolStatus::Active);
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            do_process_instruction_with_accounts(
+                initialize(
+                    fees.clone(),
+                    tick_spacing,
+                    min_position_liquidity,
+                    initial_sqrt_price
+                ),
+                &mut accounts,
             )
-            .unwrap();
+        );
 
-        // try a to b again, fails due to no more liquidity
+        // two-token instructions cannot use a concentrated liquidity pool
         assert_eq!(
-            Err(SwapError::ZeroTradingTokens.into()),
-            accounts.swap(
-                &swapper_key,
-                &token_a_key,
-                &mut token_a_account,
-                &swap_token_a_key,
-                &swap_token_b_key,
-                &token_b_key,
-                &mut token_b_account,
-                a_to_b_amount,
-                minimum_token_b_amount,
+            Err(ProgramError::InvalidAccountData),
+            do_process_instruction_with_accounts(
+                swap(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &user_transfer_key,
+                    &user_token_keys[0],
+                    &swap_token_keys[0],
+                    &swap_token_keys[1],
+                    &user_token_keys[1],
+                    &user_token_keys[0],
+                    &user_token_keys[1],
+                    None,
+                    Swap {
+                        amount_in: 1_000,
+                        minimum_amount_out: 0,
+                    },
+                )
+                .unwrap(),
+                &mut accounts,
             )
         );
 
-        // Try to deposit, fails because deposits are not allowed for offset
-        // curve swaps
-        {
-            let initial_a = 100;
-            let initial_b = 100;
-            let pool_amount = 100;
-            let (
-                token_a_key,
-                mut token_a_account,
-                token_b_key,
-                mut token_b_account,
-                pool_key,
-                mut pool_account,
-            ) = accounts.setup_token_accounts(&user_key, &swapper_key, initial_a, initial_b, 0);
-            assert_eq!(
-                Err(SwapError::UnsupportedCurveOperation.into()),
-                accounts.deposit_all_token_types(
-                    &swapper_key,
-                    &token_a_key,
-                    &mut token_a_account,
-                    &token_b_key,
-                    &mut token_b_account,
-                    &pool_key,
-                    &mut pool_account,
-                    pool_amount,
-                    initial_a,
-                    initial_b,
+        // open a position, bounded by ticks on the spacing
+        let open = |tick_lower, tick_upper| {
+            open_position(
+                &SWAP_PROGRAM_ID,
+                &swap_key,
+                &position_key,
+                &owner_key,
+                OpenPosition {
+                    tick_lower,
+                    tick_upper,
+                },
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            Err(SwapError::InvalidTick.into()),
+            do_process_instruction_with_accounts(open(-100, 105), &mut accounts)
+        );
+        assert_eq!(
+            Err(SwapError::InvalidTick.into()),
+            do_process_instruction_with_accounts(open(100, -100), &mut accounts)
+        );
+        // only the ticks around the starting price can be used
+        assert_eq!(
+            Err(SwapError::InvalidTick.into()),
+            do_process_instruction_with_accounts(open(-100, 200), &mut accounts)
+        );
+        do_process_instruction_with_accounts(open(-100, 100), &mut accounts).unwrap();
+        let position = Position::unpack(&accounts[1].1.data).unwrap();
+        assert_eq!(position.pool, swap_key);
+        assert_eq!(position.owner, owner_key);
+        assert_eq!(
+            Err(SwapError::AlreadyInUse.into()),
+            do_process_instruction_with_accounts(open(-100, 100), &mut accounts)
+        );
+
+        for user_token_key in user_token_keys.iter() {
+            do_process_instruction_with_accounts(
+                approve(
+                    &TOKEN_PROGRAM_ID,
+                    user_token_key,
+                    &user_transfer_key,
+                    &user_key,
+                    &[],
+                    u64::MAX,
                 )
-            );
+                .unwrap(),
+                &mut accounts,
+            )
+            .unwrap();
         }
-    }
-
-    #[test]
-    fn test_withdraw_all_offset_curve() {
-        let trade_fee_numerator = 1;
-        let trade_fee_denominator = 10;
-        let owner_trade_fee_numerator = 1;
-        let owner_trade_fee_denominator = 30;
-        let owner_withdraw_fee_numerator = 0;
-        let owner_withdraw_fee_denominator = 30;
-        let host_fee_numerator = 10;
-        let host_fee_denominator = 100;
 
-        let token_a_amount = 1_000_000_000;
-        let token_b_amount = 10;
-        let fees = Fees {
-            trade_fee_numerator,
-            trade_fee_denominator,
-            owner_trade_fee_numerator,
-            owner_trade_fee_denominator,
-            owner_withdraw_fee_numerator,
-            owner_withdraw_fee_denominator,
-            host_fee_numerator,
-            host_fee_denominator,
+        // add liquidity around the current price
+        let liquidity = 1_000_000_000;
+        let increase = |owner_key: &Pubkey, maximum_token_amount| {
+            increase_liquidity(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &position_key,
+                owner_key,
+                &user_transfer_key,
+                &user_token_keys[0],
+                &user_token_keys[1],
+                &swap_token_keys[0],
+                &swap_token_keys[1],
+                IncreaseLiquidity {
+                    liquidity,
+                    maximum_token_a_amount: maximum_token_amount,
+                    maximum_token_b_amount: maximum_token_amount,
+                },
+            )
+            .unwrap()
         };
-
-        let token_b_offset = 2_000_000;
-        let swap_curve = SwapCurve {
-            curve_type: CurveType::Offset,
-            calculator: Box::new(OffsetCurve { token_b_offset }),
+        assert_eq!(
+            Err(SwapError::IncorrectPosition.into()),
+            do_process_instruction_with_accounts(increase(&user_key, u64::MAX), &mut accounts)
+        );
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(increase(&owner_key, 1_000), &mut accounts)
+        );
+        do_process_instruction_with_accounts(increase(&owner_key, u64::MAX), &mut accounts)
+            .unwrap();
+        let deposited_a = token_amount(&accounts, &swap_token_keys[0]);
+        let deposited_b = token_amount(&accounts, &swap_token_keys[1]);
+        // L * (1 - 1.0001 ^ -50), the same for both tokens at a price of 1
+        assert!(deposited_a > 4_900_000 && deposited_a < 5_000_000);
+        assert_eq!(deposited_a, deposited_b);
+        assert_eq!(
+            token_amount(&accounts, &user_token_keys[0]),
+            user_token_amount - deposited_a
+        );
+        let pool = SwapVersion::unpack_concentrated(&accounts[0].1.data).unwrap();
+        assert_eq!(pool.liquidity, liquidity);
+        assert_eq!(pool.ticks().len(), 2);
+
+        // swap both ways
+        let amount_in = 1_000_000;
+        let swap_instruction = |a_to_b: bool, minimum_amount_out| {
+            let (source, destination) = if a_to_b { (0, 1) } else { (1, 0) };
+            swap_concentrated(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &user_transfer_key,
+                &user_token_keys[source],
+                &swap_token_keys[source],
+                &swap_token_keys[destination],
+                &user_token_keys[destination],
+                Swap {
+                    amount_in,
+                    minimum_amount_out,
+                },
+            )
+            .unwrap()
         };
-        let total_pool = swap_curve.calculator.new_pool_supply();
-        let user_key = Pubkey::new_unique();
-        let withdrawer_key = Pubkey::new_unique();
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(swap_instruction(true, amount_in), &mut accounts)
+        );
+        do_process_instruction_with_accounts(swap_instruction(true, 0), &mut accounts).unwrap();
+        let amount_out =
+            token_amount(&accounts, &user_token_keys[1]) + deposited_b - user_token_amount;
+        // 0.3% fee, then a little price impact
+        assert!(amount_out > 990_000 && amount_out < 997_000);
+        assert_eq!(
+            token_amount(&accounts, &swap_token_keys[0]),
+            deposited_a + amount_in
+        );
+        let pool = SwapVersion::unpack_concentrated(&accounts[0].1.data).unwrap();
+        assert!(pool.tick_current < 0);
+        do_process_instruction_with_accounts(swap_instruction(false, 0), &mut accounts).unwrap();
 
-        let mut accounts =
-            SwapAccountInfo::new(&user_key, fees, swap_curve, token_a_amount, token_b_amount);
+        // the pool cannot take a fee on transfer, as its price and liquidity
+        // have moved by the full amount
+        set_test_transfer_fee(1);
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(swap_instruction(true, 0), &mut accounts)
+        );
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(increase(&owner_key, u64::MAX), &mut accounts)
+        );
+        set_test_transfer_fee(0);
 
-        accounts.initialize_swap().unwrap();
+        // the swap accounts must match the pool
+        assert_eq!(
+            Err(SwapError::IncorrectSwapAccount.into()),
+            do_process_instruction_with_accounts(
+                swap_concentrated(
+                    &SWAP_PROGRAM_ID,
+                    &TOKEN_PROGRAM_ID,
+                    &swap_key,
+                    &authority_key,
+                    &user_transfer_key,
+                    &user_token_keys[0],
+                    &swap_token_keys[0],
+                    &user_token_keys[0],
+                    &user_token_keys[1],
+                    Swap {
+                        amount_in,
+                        minimum_amount_out: 0,
+                    },
+                )
+                .unwrap(),
+                &mut accounts,
+            )
+        );
 
-        let (
-            token_a_key,
-            mut token_a_account,
-            token_b_key,
-            mut token_b_account,
-            _pool_key,
-            _pool_account,
-        ) = accounts.setup_token_accounts(&user_key, &withdrawer_key, 0, 0, 0);
+        // collect the fees earned by both swaps
+        let user_a = token_amount(&accounts, &user_token_keys[0]);
+        let user_b = token_amount(&accounts, &user_token_keys[1]);
+        let collect = || {
+            collect_fees(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &position_key,
+                &owner_key,
+                &swap_token_keys[0],
+                &swap_token_keys[1],
+                &user_token_keys[0],
+                &user_token_keys[1],
+            )
+            .unwrap()
+        };
+        do_process_instruction_with_accounts(collect(), &mut accounts).unwrap();
+        let fees_a = token_amount(&accounts, &user_token_keys[0]) - user_a;
+        let fees_b = token_amount(&accounts, &user_token_keys[1]) - user_b;
+        assert!(fees_a > 2_990 && fees_a <= 3_000);
+        assert!(fees_b > 2_990 && fees_b <= 3_000);
+        let position = Position::unpack(&accounts[1].1.data).unwrap();
+        assert_eq!(position.tokens_owed_a, 0);
+        assert_eq!(position.tokens_owed_b, 0);
+
+        // nothing more to collect
+        do_process_instruction_with_accounts(collect(), &mut accounts).unwrap();
+        assert_eq!(
+            token_amount(&accounts, &user_token_keys[0]),
+            user_a + fees_a
+        );
 
-        let pool_key = accounts.pool_token_key;
-        let mut pool_account = accounts.pool_token_account.clone();
+        // a paused pool rejects swaps and new liquidity
+        let set_status_instruction =
+            |status| set_pool_status(&SWAP_PROGRAM_ID, &swap_key, &admin_key, status).unwrap();
+        do_process_instruction_with_accounts(
+            set_status_instruction(PoolStatus::SwapsPaused),
+            &mut accounts,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction_with_accounts(swap_instruction(true, 0), &mut accounts)
+        );
+        assert_eq!(
+            Err(SwapError::PoolPaused.into()),
+            do_process_instruction_with_accounts(increase(&owner_key, u64::MAX), &mut accounts)
+        );
 
-        // WithdrawAllTokenTypes takes all tokens for A and B.
-        // The curve's calculation for token B will say to transfer
-        // `token_b_offset + token_b_amount`, but only `token_b_amount` will be
-        // moved.
-        accounts
-            .withdraw_all_token_types(
-                &user_key,
-                &pool_key,
-                &mut pool_account,
-                &token_a_key,
-                &mut token_a_account,
-                &token_b_key,
-                &mut token_b_account,
-                total_pool.try_into().unwrap(),
-                0,
-                0,
+        // but all liquidity can still be removed
+        let decrease = |liquidity, minimum_token_amount| {
+            decrease_liquidity(
+                &SWAP_PROGRAM_ID,
+                &TOKEN_PROGRAM_ID,
+                &swap_key,
+                &authority_key,
+                &position_key,
+                &owner_key,
+                &swap_token_keys[0],
+                &swap_token_keys[1],
+                &user_token_keys[0],
+                &user_token_keys[1],
+                DecreaseLiquidity {
+                    liquidity,
+                    minimum_token_a_amount: minimum_token_amount,
+                    minimum_token_b_amount: minimum_token_amount,
+                },
             )
-            .unwrap();
-
-        let token_a = spl_token::state::Account::unpack(&token_a_account.data).unwrap();
-        assert_eq!(token_a.amount, token_a_amount);
-        let token_b = spl_token::state::Account::unpack(&token_b_account.data).unwrap();
-        assert_eq!(token_b.amount, token_b_amount);
-        let swap_token_a =
-            spl_token::state::Account::unpack(&accounts.token_a_account.data).unwrap();
-        assert_eq!(swap_token_a.amount, 0);
-        let swap_token_b =
-            spl_token::state::Account::unpack(&accounts.token_b_account.data).unwrap();
-        assert_eq!(swap_token_b.amount, 0);
+            .unwrap()
+        };
+        assert_eq!(
+            Err(SwapError::InvalidInput.into()),
+            do_process_instruction_with_accounts(decrease(liquidity + 1, 0), &mut accounts)
+        );
+        assert_eq!(
+            Err(SwapError::ExceededSlippage.into()),
+            do_process_instruction_with_accounts(
+                decrease(liquidity, deposited_a * 2),
+                &mut accounts
+            )
+        );
+        // dust cannot be left behind in the position
+        assert_eq!(
+            Err(SwapError::PositionLiquidityTooLow.into()),
+            do_process_instruction_with_accounts(decrease(liquidity - 1, 0), &mut accounts)
+        );
+        do_process_instruction_with_accounts(decrease(liquidity, 0), &mut accounts).unwrap();
+        let position = Position::unpack(&accounts[1].1.data).unwrap();
+        assert_eq!(position.liquidity, 0);
+        let pool = SwapVersion::unpack_concentrated(&accounts[0].1.data).unwrap();
+        assert_eq!(pool.liquidity, 0);
+        assert_eq!(pool.ticks().len(), 0);
+        // only rounding dust is left in the pool
+        assert!(token_amount(&accounts, &swap_token_keys[0]) < 10);
+        assert!(token_amount(&accounts, &swap_token_keys[1]) < 10);
+
+        // without liquidity, nothing can be swapped
+        do_process_instruction_with_accounts(
+            set_status_instruction(PoolStatus::Active),
+            &mut accounts,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(SwapError::ZeroTradingTokens.into()),
+            do_process_instruction_with_accounts(swap_instruction(true, 0), &mut accounts)
+        );
     }
 }
@@ -0,0 +1,513 @@
+//! Expected results of swaps, deposits and withdrawals on a two-token swap,
+//! computed with the same curve and fee math as the program.  A stable curve
+//! that has been ramped must first be fixed at the current time with
+//! `SwapCurve::fix_at`, as the program does.
+
+use crate::{
+    curve::{
+        base::{SwapCurve, SwapResult},
+        calculator::{RoundDirection, TradeDirection},
+        fees::Fees,
+    },
+    oracle::spot_price,
+};
+
+/// Token amounts held by a two-token swap, and the supply of its pool token
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolReserves {
+    /// Amount of token A held by the swap
+    pub token_a_amount: u128,
+    /// Amount of token B held by the swap
+    pub token_b_amount: u128,
+    /// Supply of the pool token
+    pub pool_supply: u128,
+}
+
+/// Expected result of an operation on a two-token swap
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuoteResult {
+    /// Amount of token A moved into or out of the swap
+    pub token_a_amount: u128,
+    /// Amount of token B moved into or out of the swap
+    pub token_b_amount: u128,
+    /// Amount of pool tokens minted for deposits, burned and paid as fees for
+    /// withdrawals, or minted as owner fees for swaps
+    pub pool_token_amount: u128,
+    /// Trading fee kept in the pool, in the token given to the swap
+    pub trade_fee: u128,
+    /// Owner fee, in the token given to the swap for swaps, or in pool tokens
+    /// for withdrawals
+    pub owner_fee: u128,
+    /// Part of the owner fee paid to the host if a host fee account is given,
+    /// in pool tokens
+    pub host_fee: u128,
+    /// Reserves of the swap after the operation
+    pub reserves_after: PoolReserves,
+    /// Price of token A in token B after the operation, in Q64.64, or `None`
+    /// if the swap no longer holds any token A
+    pub price_after: Option<u128>,
+}
+
+impl QuoteResult {
+    fn with_reserves_after(mut self, reserves_after: PoolReserves) -> Self {
+        self.price_after = spot_price(reserves_after.token_a_amount, reserves_after.token_b_amount);
+        self.reserves_after = reserves_after;
+        self
+    }
+}
+
+/// Orders the amounts of the source and destination tokens of a trade as
+/// token A and token B amounts
+fn to_token_amounts(
+    source_amount: u128,
+    destination_amount: u128,
+    trade_direction: TradeDirection,
+) -> (u128, u128) {
+    match trade_direction {
+        TradeDirection::AtoB => (source_amount, destination_amount),
+        TradeDirection::BtoA => (destination_amount, source_amount),
+    }
+}
+
+/// Completes a swap quote from the curve's result, minting the owner fee as
+/// pool tokens the same way as the program
+fn swap_quote(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    reserves: &PoolReserves,
+    trade_direction: TradeDirection,
+    result: &SwapResult,
+) -> Option<QuoteResult> {
+    // the reserves are ordered the same way as the amounts of a trade
+    let (swap_source_amount, swap_destination_amount) = to_token_amounts(
+        reserves.token_a_amount,
+        reserves.token_b_amount,
+        trade_direction,
+    );
+    let (token_a_amount_after, token_b_amount_after) = to_token_amounts(
+        swap_source_amount.checked_add(result.source_amount_swapped)?,
+        swap_destination_amount.checked_sub(result.destination_amount_swapped)?,
+        trade_direction,
+    );
+    let pool_token_amount = swap_curve.trading_tokens_to_pool_tokens(
+        result.owner_fee,
+        token_a_amount_after,
+        token_b_amount_after,
+        reserves.pool_supply,
+        trade_direction,
+        RoundDirection::Ceiling,
+        fees,
+    )?;
+    let (token_a_amount, token_b_amount) = to_token_amounts(
+        result.source_amount_swapped,
+        result.destination_amount_swapped,
+        trade_direction,
+    );
+    let quote = QuoteResult {
+        token_a_amount,
+        token_b_amount,
+        pool_token_amount,
+        trade_fee: result.trade_fee,
+        owner_fee: result.owner_fee,
+        host_fee: fees.host_fee(pool_token_amount)?,
+        ..QuoteResult::default()
+    };
+    Some(quote.with_reserves_after(PoolReserves {
+        token_a_amount: token_a_amount_after,
+        token_b_amount: token_b_amount_after,
+        pool_supply: reserves.pool_supply.checked_add(pool_token_amount)?,
+    }))
+}
+
+/// Quote a [Swap](../instruction/enum.SwapInstruction.html) of `amount_in`
+/// of the source token
+pub fn swap(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    reserves: &PoolReserves,
+    amount_in: u128,
+    trade_direction: TradeDirection,
+) -> Option<QuoteResult> {
+    let (swap_source_amount, swap_destination_amount) = to_token_amounts(
+        reserves.token_a_amount,
+        reserves.token_b_amount,
+        trade_direction,
+    );
+    let result = swap_curve.swap(
+        amount_in,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_direction,
+        fees,
+    )?;
+    swap_quote(swap_curve, fees, reserves, trade_direction, &result)
+}
+
+/// Quote a [SwapExactOut](../instruction/enum.SwapInstruction.html) for
+/// `amount_out` of the destination token
+pub fn swap_exact_out(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    reserves: &PoolReserves,
+    amount_out: u128,
+    trade_direction: TradeDirection,
+) -> Option<QuoteResult> {
+    let (swap_source_amount, swap_destination_amount) = to_token_amounts(
+        reserves.token_a_amount,
+        reserves.token_b_amount,
+        trade_direction,
+    );
+    let result = swap_curve.swap_exact_out(
+        amount_out,
+        swap_source_amount,
+        swap_destination_amount,
+        trade_direction,
+        fees,
+    )?;
+    swap_quote(swap_curve, fees, reserves, trade_direction, &result)
+}
+
+/// Quote a [DepositAllTokenTypes](../instruction/enum.SwapInstruction.html)
+/// for `pool_token_amount` pool tokens
+pub fn deposit_all_token_types(
+    swap_curve: &SwapCurve,
+    reserves: &PoolReserves,
+    pool_token_amount: u128,
+) -> Option<QuoteResult> {
+    let results = swap_curve.calculator.pool_tokens_to_trading_tokens(
+        pool_token_amount,
+        reserves.pool_supply,
+        reserves.token_a_amount,
+        reserves.token_b_amount,
+        RoundDirection::Ceiling,
+    )?;
+    let quote = QuoteResult {
+        token_a_amount: results.token_a_amount,
+        token_b_amount: results.token_b_amount,
+        pool_token_amount,
+        ..QuoteResult::default()
+    };
+    Some(
+        quote.with_reserves_after(PoolReserves {
+            token_a_amount: reserves
+                .token_a_amount
+                .checked_add(results.token_a_amount)?,
+            token_b_amount: reserves
+                .token_b_amount
+                .checked_add(results.token_b_amount)?,
+            pool_supply: reserves.pool_supply.checked_add(pool_token_amount)?,
+        }),
+    )
+}
+
+/// Quote a [WithdrawAllTokenTypes](../instruction/enum.SwapInstruction.html)
+/// of `pool_token_amount` pool tokens, including the owner withdraw fee, from
+/// an account other than the fee account
+pub fn withdraw_all_token_types(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    reserves: &PoolReserves,
+    pool_token_amount: u128,
+) -> Option<QuoteResult> {
+    let withdraw_fee = fees.owner_withdraw_fee(pool_token_amount)?;
+    let burn_pool_token_amount = pool_token_amount.checked_sub(withdraw_fee)?;
+    let results = swap_curve.calculator.pool_tokens_to_trading_tokens(
+        burn_pool_token_amount,
+        reserves.pool_supply,
+        reserves.token_a_amount,
+        reserves.token_b_amount,
+        RoundDirection::Floor,
+    )?;
+    let token_a_amount = std::cmp::min(reserves.token_a_amount, results.token_a_amount);
+    let token_b_amount = std::cmp::min(reserves.token_b_amount, results.token_b_amount);
+    let quote = QuoteResult {
+        token_a_amount,
+        token_b_amount,
+        pool_token_amount,
+        owner_fee: withdraw_fee,
+        ..QuoteResult::default()
+    };
+    Some(quote.with_reserves_after(PoolReserves {
+        token_a_amount: reserves.token_a_amount.checked_sub(token_a_amount)?,
+        token_b_amount: reserves.token_b_amount.checked_sub(token_b_amount)?,
+        pool_supply: reserves.pool_supply.checked_sub(burn_pool_token_amount)?,
+    }))
+}
+
+/// Quote a
+/// [DepositSingleTokenTypeExactAmountIn](../instruction/enum.SwapInstruction.html)
+/// of `source_token_amount` of token A when trading from A to B, or of token B
+/// otherwise
+pub fn deposit_single_token_type_exact_amount_in(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    reserves: &PoolReserves,
+    source_token_amount: u128,
+    trade_direction: TradeDirection,
+) -> Option<QuoteResult> {
+    let pool_token_amount = swap_curve.trading_tokens_to_pool_tokens(
+        source_token_amount,
+        reserves.token_a_amount,
+        reserves.token_b_amount,
+        reserves.pool_supply,
+        trade_direction,
+        RoundDirection::Floor,
+        fees,
+    )?;
+    // the trading fee is charged as if half of the deposit were swapped
+    let trade_fee = if source_token_amount == 0 {
+        0
+    } else {
+        fees.trading_fee(std::cmp::max(1, source_token_amount / 2))?
+    };
+    let (token_a_amount, token_b_amount) =
+        to_token_amounts(source_token_amount, 0, trade_direction);
+    let quote = QuoteResult {
+        token_a_amount,
+        token_b_amount,
+        pool_token_amount,
+        trade_fee,
+        ..QuoteResult::default()
+    };
+    Some(quote.with_reserves_after(PoolReserves {
+        token_a_amount: reserves.token_a_amount.checked_add(token_a_amount)?,
+        token_b_amount: reserves.token_b_amount.checked_add(token_b_amount)?,
+        pool_supply: reserves.pool_supply.checked_add(pool_token_amount)?,
+    }))
+}
+
+/// Quote a
+/// [WithdrawSingleTokenTypeExactAmountOut](../instruction/enum.SwapInstruction.html)
+/// of `destination_token_amount` of token A when trading from A to B, or of
+/// token B otherwise, from an account other than the fee account
+pub fn withdraw_single_token_type_exact_amount_out(
+    swap_curve: &SwapCurve,
+    fees: &Fees,
+    reserves: &PoolReserves,
+    destination_token_amount: u128,
+    trade_direction: TradeDirection,
+) -> Option<QuoteResult> {
+    let (token_a_amount, token_b_amount) =
+        to_token_amounts(destination_token_amount, 0, trade_direction);
+    let token_a_amount_after = reserves.token_a_amount.checked_sub(token_a_amount)?;
+    let token_b_amount_after = reserves.token_b_amount.checked_sub(token_b_amount)?;
+    let burn_pool_token_amount = swap_curve.trading_tokens_to_pool_tokens(
+        destination_token_amount,
+        token_a_amount_after,
+        token_b_amount_after,
+        reserves.pool_supply,
+        trade_direction,
+        RoundDirection::Ceiling,
+        fees,
+    )?;
+    let withdraw_fee = fees.owner_withdraw_fee(burn_pool_token_amount)?;
+    let trade_fee = if destination_token_amount == 0 {
+        0
+    } else {
+        fees.trading_fee(std::cmp::max(1, destination_token_amount / 2))?
+    };
+    let quote = QuoteResult {
+        token_a_amount,
+        token_b_amount,
+        pool_token_amount: burn_pool_token_amount.checked_add(withdraw_fee)?,
+        trade_fee,
+        owner_fee: withdraw_fee,
+        ..QuoteResult::default()
+    };
+    Some(quote.with_reserves_after(PoolReserves {
+        token_a_amount: token_a_amount_after,
+        token_b_amount: token_b_amount_after,
+        pool_supply: reserves.pool_supply.checked_sub(burn_pool_token_amount)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{
+        base::CurveType, constant_product::ConstantProductCurve, offset::OffsetCurve,
+    };
+
+    const FEES: Fees = Fees {
+        trade_fee_numerator: 25,
+        trade_fee_denominator: 10_000,
+        owner_trade_fee_numerator: 5,
+        owner_trade_fee_denominator: 10_000,
+        owner_withdraw_fee_numerator: 1,
+        owner_withdraw_fee_denominator: 100,
+        host_fee_numerator: 20,
+        host_fee_denominator: 100,
+    };
+
+    const RESERVES: PoolReserves = PoolReserves {
+        token_a_amount: 1_000_000,
+        token_b_amount: 2_000_000,
+        pool_supply: 1_000_000_000,
+    };
+
+    fn constant_product() -> SwapCurve {
+        SwapCurve {
+            curve_type: CurveType::ConstantProduct,
+            calculator: Box::new(ConstantProductCurve {}),
+        }
+    }
+
+    #[test]
+    fn quote_swap_matches_curve() {
+        let swap_curve = constant_product();
+        let quote = swap(&swap_curve, &FEES, &RESERVES, 10_000, TradeDirection::BtoA).unwrap();
+        let result = swap_curve
+            .swap(
+                10_000,
+                RESERVES.token_b_amount,
+                RESERVES.token_a_amount,
+                TradeDirection::BtoA,
+                &FEES,
+            )
+            .unwrap();
+        assert_eq!(quote.token_b_amount, result.source_amount_swapped);
+        assert_eq!(quote.token_a_amount, result.destination_amount_swapped);
+        assert_eq!(quote.trade_fee, result.trade_fee);
+        assert_eq!(quote.owner_fee, result.owner_fee);
+        assert!(quote.pool_token_amount > 0);
+        assert_eq!(
+            quote.host_fee,
+            FEES.host_fee(quote.pool_token_amount).unwrap()
+        );
+        assert_eq!(
+            quote.reserves_after,
+            PoolReserves {
+                token_a_amount: result.new_swap_destination_amount,
+                token_b_amount: result.new_swap_source_amount,
+                pool_supply: RESERVES.pool_supply + quote.pool_token_amount,
+            }
+        );
+        assert_eq!(
+            quote.price_after,
+            spot_price(
+                result.new_swap_destination_amount,
+                result.new_swap_source_amount
+            )
+        );
+        // buying token A makes it more expensive
+        assert!(
+            quote.price_after.unwrap()
+                > spot_price(RESERVES.token_a_amount, RESERVES.token_b_amount).unwrap()
+        );
+
+        let exact_out = swap_exact_out(
+            &swap_curve,
+            &FEES,
+            &RESERVES,
+            quote.token_a_amount,
+            TradeDirection::BtoA,
+        )
+        .unwrap();
+        assert_eq!(exact_out.token_a_amount, quote.token_a_amount);
+        assert!(exact_out.token_b_amount <= quote.token_b_amount);
+
+        assert_eq!(
+            swap(
+                &swap_curve,
+                &FEES,
+                &RESERVES,
+                u128::MAX,
+                TradeDirection::AtoB
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn quote_balanced_deposit_and_withdrawal() {
+        let swap_curve = constant_product();
+        let deposit = deposit_all_token_types(&swap_curve, &RESERVES, 10_000_000).unwrap();
+        assert_eq!(deposit.token_a_amount, 10_000);
+        assert_eq!(deposit.token_b_amount, 20_000);
+        assert_eq!(deposit.reserves_after.pool_supply, 1_010_000_000);
+        assert_eq!(deposit.price_after, Some(2 << 64));
+
+        let withdrawal =
+            withdraw_all_token_types(&swap_curve, &FEES, &RESERVES, 10_000_000).unwrap();
+        assert_eq!(withdrawal.owner_fee, 100_000);
+        assert_eq!(withdrawal.pool_token_amount, 10_000_000);
+        assert_eq!(withdrawal.token_a_amount, 9_900);
+        assert_eq!(withdrawal.token_b_amount, 19_800);
+        assert_eq!(
+            withdrawal.reserves_after,
+            PoolReserves {
+                token_a_amount: 990_100,
+                token_b_amount: 1_980_200,
+                pool_supply: 990_100_000,
+            }
+        );
+    }
+
+    #[test]
+    fn quote_single_sided_deposit_and_withdrawal() {
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::Offset,
+            calculator: Box::new(OffsetCurve {
+                token_b_offset: 1_000_000,
+            }),
+        };
+        let deposit = deposit_single_token_type_exact_amount_in(
+            &swap_curve,
+            &FEES,
+            &RESERVES,
+            10_000,
+            TradeDirection::BtoA,
+        )
+        .unwrap();
+        assert_eq!(deposit.token_a_amount, 0);
+        assert_eq!(deposit.token_b_amount, 10_000);
+        assert_eq!(deposit.trade_fee, FEES.trading_fee(5_000).unwrap());
+        assert_eq!(
+            deposit.pool_token_amount,
+            swap_curve
+                .trading_tokens_to_pool_tokens(
+                    10_000,
+                    RESERVES.token_a_amount,
+                    RESERVES.token_b_amount,
+                    RESERVES.pool_supply,
+                    TradeDirection::BtoA,
+                    RoundDirection::Floor,
+                    &FEES,
+                )
+                .unwrap()
+        );
+        assert_eq!(deposit.reserves_after.token_b_amount, 2_010_000);
+
+        let withdrawal = withdraw_single_token_type_exact_amount_out(
+            &swap_curve,
+            &FEES,
+            &RESERVES,
+            10_000,
+            TradeDirection::AtoB,
+        )
+        .unwrap();
+        assert_eq!(withdrawal.token_a_amount, 10_000);
+        assert_eq!(withdrawal.token_b_amount, 0);
+        let burn_pool_token_amount = withdrawal.pool_token_amount - withdrawal.owner_fee;
+        assert_eq!(
+            withdrawal.owner_fee,
+            FEES.owner_withdraw_fee(burn_pool_token_amount).unwrap()
+        );
+        assert_eq!(
+            withdrawal.reserves_after.pool_supply,
+            RESERVES.pool_supply - burn_pool_token_amount
+        );
+        assert_eq!(withdrawal.reserves_after.token_a_amount, 990_000);
+
+        assert_eq!(
+            withdraw_single_token_type_exact_amount_out(
+                &swap_curve,
+                &FEES,
+                &RESERVES,
+                RESERVES.token_a_amount + 1,
+                TradeDirection::AtoB,
+            ),
+            None
+        );
+    }
+}
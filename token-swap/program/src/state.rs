@@ -1,6 +1,16 @@
 //! State transition types
 
-use crate::curve::{base::SwapCurve, fees::Fees};
+use crate::{
+    curve::{
+        base::SwapCurve,
+        calculator::TradeDirection,
+        concentrated::MAX_TICKS_IN_CONCENTRATED_POOL,
+        fees::Fees,
+        multi_token::{MultiTokenCurve, MAX_TOKENS_IN_MULTI_TOKEN_POOL},
+    },
+    error::SwapError,
+    farm::MAX_FARM_REWARDS,
+};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use enum_dispatch::enum_dispatch;
 use solana_program::{
@@ -8,6 +18,7 @@ use solana_program::{
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+use std::convert::{TryFrom, TryInto};
 
 /// Trait representing access to program state across all versions
 #[enum_dispatch]
@@ -42,45 +53,291 @@ pub trait SwapState {
 /// All versions of SwapState
 #[enum_dispatch(SwapState)]
 pub enum SwapVersion {
-    /// Latest version, used for all new swaps
+    /// Latest version, used for all new two-token swaps
     SwapV1,
+    /// Multi-token version, used for all swaps with more than two tokens
+    SwapV2,
 }
 
 /// SwapVersion does not implement program_pack::Pack because there are size
 /// checks on pack and unpack that would break backwards compatibility, so
 /// special implementations are provided here
 impl SwapVersion {
-    /// Size of the latest version of the SwapState
-    pub const LATEST_LEN: usize = 1 + SwapV1::LEN; // add one for the version enum
+    /// Size of the latest version of the SwapState, including the extension
+    /// stored after it
+    pub const LATEST_LEN: usize = Self::LEGACY_LEN + SwapExtension::LEN;
+
+    /// Size of the SwapState for pools created before the extension was
+    /// added
+    pub const LEGACY_LEN: usize = 1 + SwapV1::LEN; // add one for the version enum
+
+    /// Size of the multi-token version of the SwapState
+    pub const MULTI_TOKEN_LEN: usize = 1 + SwapV2::LEN; // add one for the version enum
+
+    /// Size of a concentrated liquidity pool
+    pub const CONCENTRATED_LEN: usize = 1 + ConcentratedPool::LEN; // add one for the version enum
 
     /// Pack a swap into a byte array, based on its version
     pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
         match src {
             Self::SwapV1(swap_info) => {
                 dst[0] = 1;
-                SwapV1::pack(swap_info, &mut dst[1..])
+                let dst = dst
+                    .get_mut(1..=SwapV1::LEN)
+                    .ok_or(ProgramError::InvalidAccountData)?;
+                SwapV1::pack(swap_info, dst)
+            }
+            Self::SwapV2(swap_info) => {
+                dst[0] = 2;
+                SwapV2::pack(swap_info, &mut dst[1..])
             }
         }
     }
 
     /// Unpack the swap account based on its version, returning the result as a
     /// SwapState trait object
+    ///
+    /// Multi-token swaps and concentrated liquidity pools are rejected, since
+    /// they can only be used through their own instructions
     pub fn unpack(input: &[u8]) -> Result<Box<dyn SwapState>, ProgramError> {
         let (&version, rest) = input
             .split_first()
             .ok_or(ProgramError::InvalidAccountData)?;
         match version {
-            1 => Ok(Box::new(SwapV1::unpack(rest)?)),
+            1 => {
+                let rest = rest
+                    .get(..SwapV1::LEN)
+                    .ok_or(ProgramError::InvalidAccountData)?;
+                Ok(Box::new(SwapV1::unpack(rest)?))
+            }
+            2 | 3 | 4 => Err(ProgramError::InvalidAccountData),
+            _ => Err(ProgramError::UninitializedAccount),
+        }
+    }
+
+    /// Unpack a two-token swap account, for instructions that modify it
+    pub fn unpack_swap_v1(input: &[u8]) -> Result<SwapV1, ProgramError> {
+        let (&version, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match version {
+            1 => SwapV1::unpack(
+                rest.get(..SwapV1::LEN)
+                    .ok_or(ProgramError::InvalidAccountData)?,
+            ),
+            2 | 3 | 4 => Err(ProgramError::InvalidAccountData),
+            _ => Err(ProgramError::UninitializedAccount),
+        }
+    }
+
+    /// Unpack a two-token swap account that may have been migrated, and is
+    /// no longer initialized, for instructions that only read it
+    pub fn unpack_migrated_swap_v1(input: &[u8]) -> Result<SwapV1, ProgramError> {
+        let (&version, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match version {
+            1 | 4 => SwapV1::unpack_unchecked(
+                rest.get(..SwapV1::LEN)
+                    .ok_or(ProgramError::InvalidAccountData)?,
+            ),
+            2 | 3 => Err(ProgramError::InvalidAccountData),
+            _ => Err(ProgramError::UninitializedAccount),
+        }
+    }
+
+    /// Unpack a multi-token swap account
+    pub fn unpack_multi_token(input: &[u8]) -> Result<SwapV2, ProgramError> {
+        let (&version, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match version {
+            2 => SwapV2::unpack(rest),
+            1 | 3 | 4 => Err(ProgramError::InvalidAccountData),
+            _ => Err(ProgramError::UninitializedAccount),
+        }
+    }
+
+    /// Unpack the extension of a two-token swap, or `None` for pools created
+    /// before the extension was added
+    pub fn unpack_extension(input: &[u8]) -> Result<Option<SwapExtension>, ProgramError> {
+        if input.first() != Some(&1) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        match input.len() {
+            Self::LEGACY_LEN => Ok(None),
+            Self::LATEST_LEN => Ok(Some(SwapExtension::unpack_unchecked(
+                &input[Self::LEGACY_LEN..],
+            )?)),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Pack the extension of a two-token swap into a byte array
+    pub fn pack_extension(src: SwapExtension, dst: &mut [u8]) -> Result<(), ProgramError> {
+        let dst = dst
+            .get_mut(Self::LEGACY_LEN..Self::LATEST_LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        SwapExtension::pack(src, dst)
+    }
+
+    /// Update one part of the extension of a two-token swap in a byte array
+    fn update_extension<F: FnOnce(&mut SwapExtension)>(
+        dst: &mut [u8],
+        update: F,
+    ) -> Result<(), ProgramError> {
+        let mut extension = Self::unpack_extension(dst)?.ok_or(ProgramError::InvalidAccountData)?;
+        update(&mut extension);
+        Self::pack_extension(extension, dst)
+    }
+
+    /// Unpack the price accumulator of a two-token swap, or `None` for pools
+    /// created before the extension was added
+    pub fn unpack_price_accumulator(
+        input: &[u8],
+    ) -> Result<Option<PriceAccumulator>, ProgramError> {
+        Ok(Self::unpack_extension(input)?.map(|extension| extension.price_accumulator))
+    }
+
+    /// Pack the price accumulator of a two-token swap into a byte array
+    pub fn pack_price_accumulator(
+        src: PriceAccumulator,
+        dst: &mut [u8],
+    ) -> Result<(), ProgramError> {
+        Self::update_extension(dst, |extension| extension.price_accumulator = src)
+    }
+
+    /// Unpack the admin of a swap of any version, or `None` for two-token
+    /// pools created before the extension was added
+    pub fn unpack_admin(input: &[u8]) -> Result<Option<SwapAdmin>, ProgramError> {
+        match input.first() {
+            Some(2) => Ok(Some(Self::unpack_multi_token(input)?.admin)),
+            Some(3) => Ok(Some(Self::unpack_concentrated(input)?.admin)),
+            _ => Ok(Self::unpack_extension(input)?.map(|extension| extension.admin)),
+        }
+    }
+
+    /// Pack the admin of a swap of any version into a byte array
+    pub fn pack_admin(src: SwapAdmin, dst: &mut [u8]) -> Result<(), ProgramError> {
+        match dst.first() {
+            Some(2) => {
+                let mut swap = Self::unpack_multi_token(dst)?;
+                swap.admin = src;
+                Self::pack(Self::SwapV2(swap), dst)
+            }
+            Some(3) => {
+                let mut pool = Self::unpack_concentrated(dst)?;
+                pool.admin = src;
+                Self::pack_concentrated(pool, dst)
+            }
+            _ => Self::update_extension(dst, |extension| extension.admin = src),
+        }
+    }
+
+    /// Unpack the status of a swap of any version, or `None` for two-token
+    /// pools created before the extension was added
+    pub fn unpack_pool_status(input: &[u8]) -> Result<Option<PoolStatus>, ProgramError> {
+        match input.first() {
+            Some(2) => Ok(Some(Self::unpack_multi_token(input)?.pool_status)),
+            Some(3) => Ok(Some(Self::unpack_concentrated(input)?.pool_status)),
+            _ => Ok(Self::unpack_extension(input)?.map(|extension| extension.pool_status)),
+        }
+    }
+
+    /// Pack the status of a swap of any version into a byte array
+    pub fn pack_pool_status(src: PoolStatus, dst: &mut [u8]) -> Result<(), ProgramError> {
+        match dst.first() {
+            Some(2) => {
+                let mut swap = Self::unpack_multi_token(dst)?;
+                swap.pool_status = src;
+                Self::pack(Self::SwapV2(swap), dst)
+            }
+            Some(3) => {
+                let mut pool = Self::unpack_concentrated(dst)?;
+                pool.pool_status = src;
+                Self::pack_concentrated(pool, dst)
+            }
+            _ => Self::update_extension(dst, |extension| extension.pool_status = src),
+        }
+    }
+
+    /// Unpack the dynamic fee of a two-token swap, or `None` for pools
+    /// created before the extension was added
+    pub fn unpack_dynamic_fee(input: &[u8]) -> Result<Option<DynamicFee>, ProgramError> {
+        Ok(Self::unpack_extension(input)?.map(|extension| extension.dynamic_fee))
+    }
+
+    /// Pack the dynamic fee of a two-token swap into a byte array
+    pub fn pack_dynamic_fee(src: DynamicFee, dst: &mut [u8]) -> Result<(), ProgramError> {
+        Self::update_extension(dst, |extension| extension.dynamic_fee = src)
+    }
+
+    /// Unpack the allowlist of a two-token swap, or `None` for pools created
+    /// before the extension was added
+    pub fn unpack_allowlist(input: &[u8]) -> Result<Option<SwapAllowlist>, ProgramError> {
+        Ok(Self::unpack_extension(input)?.map(|extension| extension.allowlist))
+    }
+
+    /// Pack the allowlist of a two-token swap into a byte array
+    pub fn pack_allowlist(src: SwapAllowlist, dst: &mut [u8]) -> Result<(), ProgramError> {
+        Self::update_extension(dst, |extension| extension.allowlist = src)
+    }
+
+    /// Unpack the flash loan fee of a two-token swap, or `None` for pools
+    /// created before the extension was added
+    pub fn unpack_flash_loan_fee(input: &[u8]) -> Result<Option<FlashLoanFee>, ProgramError> {
+        Ok(Self::unpack_extension(input)?.map(|extension| extension.flash_loan_fee))
+    }
+
+    /// Pack the flash loan fee of a two-token swap into a byte array
+    pub fn pack_flash_loan_fee(src: FlashLoanFee, dst: &mut [u8]) -> Result<(), ProgramError> {
+        Self::update_extension(dst, |extension| extension.flash_loan_fee = src)
+    }
+
+    /// Mark a two-token swap that was migrated to a new account as retired,
+    /// so that it can still be read by the instructions winding it down, but
+    /// never used or initialized again
+    pub fn retire(dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.first() != Some(&1) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        dst[0] = 4;
+        Ok(())
+    }
+
+    /// Pack a concentrated liquidity pool into a byte array
+    pub fn pack_concentrated(src: ConcentratedPool, dst: &mut [u8]) -> Result<(), ProgramError> {
+        dst[0] = 3;
+        ConcentratedPool::pack(src, &mut dst[1..])
+    }
+
+    /// Unpack a concentrated liquidity pool account
+    pub fn unpack_concentrated(input: &[u8]) -> Result<ConcentratedPool, ProgramError> {
+        let (&version, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match version {
+            3 => ConcentratedPool::unpack(rest),
+            1 | 2 | 4 => Err(ProgramError::InvalidAccountData),
             _ => Err(ProgramError::UninitializedAccount),
         }
     }
 
     /// Special check to be done before any instruction processing, works for
-    /// all versions
+    /// all versions.  Retired swaps count as initialized.
     pub fn is_initialized(input: &[u8]) -> bool {
+        if input.first() == Some(&4) {
+            return true;
+        }
         match Self::unpack(input) {
             Ok(swap) => swap.is_initialized(),
-            Err(_) => false,
+            Err(_) => match Self::unpack_multi_token(input) {
+                Ok(swap) => swap.is_initialized,
+                Err(_) => match Self::unpack_concentrated(input) {
+                    Ok(pool) => pool.is_initialized,
+                    Err(_) => false,
+                },
+            },
         }
     }
 }
@@ -247,130 +504,2369 @@ impl Pack for SwapV1 {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::curve::stable::StableCurve;
+/// State of a two-token swap stored after the SwapV1 state.  Pools created
+/// before it was added have none, and can be moved to the latest layout with
+/// `MigrateSwap`.  New fields are taken from the reserved space at the end.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwapExtension {
+    /// Cumulative prices of the swap
+    pub price_accumulator: PriceAccumulator,
+    /// Admin of the swap
+    pub admin: SwapAdmin,
+    /// Status of the swap
+    pub pool_status: PoolStatus,
+    /// Trade fee following the volatility of the price
+    pub dynamic_fee: DynamicFee,
+    /// Allowlist of depositors, and optionally traders
+    pub allowlist: SwapAllowlist,
+    /// Fee charged on flash loans
+    pub flash_loan_fee: FlashLoanFee,
+}
 
-    use std::convert::TryInto;
+impl SwapExtension {
+    /// Space kept free at the end of the extension for later fields
+    pub const RESERVED_LEN: usize = 128;
+}
 
-    const TEST_FEES: Fees = Fees {
-        trade_fee_numerator: 1,
-        trade_fee_denominator: 4,
-        owner_trade_fee_numerator: 3,
-        owner_trade_fee_denominator: 10,
-        owner_withdraw_fee_numerator: 2,
-        owner_withdraw_fee_denominator: 7,
-        host_fee_numerator: 5,
-        host_fee_denominator: 20,
-    };
+impl Sealed for SwapExtension {}
 
-    const TEST_NONCE: u8 = 255;
-    const TEST_TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([1u8; 32]);
-    const TEST_TOKEN_A: Pubkey = Pubkey::new_from_array([2u8; 32]);
-    const TEST_TOKEN_B: Pubkey = Pubkey::new_from_array([3u8; 32]);
-    const TEST_POOL_MINT: Pubkey = Pubkey::new_from_array([4u8; 32]);
-    const TEST_TOKEN_A_MINT: Pubkey = Pubkey::new_from_array([5u8; 32]);
-    const TEST_TOKEN_B_MINT: Pubkey = Pubkey::new_from_array([6u8; 32]);
-    const TEST_POOL_FEE_ACCOUNT: Pubkey = Pubkey::new_from_array([7u8; 32]);
+impl Pack for SwapExtension {
+    const LEN: usize = 314;
 
-    const TEST_CURVE_TYPE: u8 = 2;
-    const TEST_AMP: u64 = 1;
-    const TEST_CURVE: StableCurve = StableCurve { amp: TEST_AMP };
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 314];
+        let (
+            price_accumulator,
+            admin,
+            pool_status,
+            dynamic_fee,
+            allowlist,
+            flash_loan_fee,
+            reserved,
+        ) = mut_array_refs![output, 40, 32, 1, 48, 33, 32, 128];
+        self.price_accumulator
+            .pack_into_slice(&mut price_accumulator[..]);
+        self.admin.pack_into_slice(&mut admin[..]);
+        self.pool_status.pack_into_slice(&mut pool_status[..]);
+        self.dynamic_fee.pack_into_slice(&mut dynamic_fee[..]);
+        self.allowlist.pack_into_slice(&mut allowlist[..]);
+        self.flash_loan_fee.pack_into_slice(&mut flash_loan_fee[..]);
+        *reserved = [0u8; Self::RESERVED_LEN];
+    }
 
-    #[test]
-    fn swap_version_pack() {
-        let curve_type = TEST_CURVE_TYPE.try_into().unwrap();
-        let calculator = Box::new(TEST_CURVE);
-        let swap_curve = SwapCurve {
-            curve_type,
-            calculator,
-        };
-        let swap_info = SwapVersion::SwapV1(SwapV1 {
-            is_initialized: true,
-            nonce: TEST_NONCE,
-            token_program_id: TEST_TOKEN_PROGRAM_ID,
-            token_a: TEST_TOKEN_A,
-            token_b: TEST_TOKEN_B,
-            pool_mint: TEST_POOL_MINT,
-            token_a_mint: TEST_TOKEN_A_MINT,
-            token_b_mint: TEST_TOKEN_B_MINT,
-            pool_fee_account: TEST_POOL_FEE_ACCOUNT,
-            fees: TEST_FEES,
-            swap_curve: swap_curve.clone(),
-        });
+    /// Unpacks a byte buffer into a [SwapExtension](struct.SwapExtension.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 314];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            price_accumulator,
+            admin,
+            pool_status,
+            dynamic_fee,
+            allowlist,
+            flash_loan_fee,
+            _reserved,
+        ) = array_refs![input, 40, 32, 1, 48, 33, 32, 128];
+        Ok(Self {
+            price_accumulator: PriceAccumulator::unpack_from_slice(price_accumulator)?,
+            admin: SwapAdmin::unpack_from_slice(admin)?,
+            pool_status: PoolStatus::unpack_from_slice(pool_status)?,
+            dynamic_fee: DynamicFee::unpack_from_slice(dynamic_fee)?,
+            allowlist: SwapAllowlist::unpack_from_slice(allowlist)?,
+            flash_loan_fee: FlashLoanFee::unpack_from_slice(flash_loan_fee)?,
+        })
+    }
+}
 
-        let mut packed = [0u8; SwapVersion::LATEST_LEN];
-        SwapVersion::pack(swap_info, &mut packed).unwrap();
-        let unpacked = SwapVersion::unpack(&packed).unwrap();
+/// Cumulative prices of a two-token swap, stored in its extension, used to
+/// compute time-weighted average prices between two observations.
+/// Updated with the reserves held before the first swap, deposit, or
+/// withdrawal in each slot.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PriceAccumulator {
+    /// Sum of the price of token A in token B, in Q64.64, for every slot
+    /// since the swap was created, wrapping on overflow
+    pub price_a_cumulative: u128,
+    /// Sum of the price of token B in token A, in Q64.64, for every slot
+    /// since the swap was created, wrapping on overflow
+    pub price_b_cumulative: u128,
+    /// Slot of the last update
+    pub last_update_slot: u64,
+}
 
-        assert_eq!(unpacked.is_initialized(), true);
-        assert_eq!(unpacked.nonce(), TEST_NONCE);
-        assert_eq!(*unpacked.token_program_id(), TEST_TOKEN_PROGRAM_ID);
-        assert_eq!(*unpacked.token_a_account(), TEST_TOKEN_A);
-        assert_eq!(*unpacked.token_b_account(), TEST_TOKEN_B);
-        assert_eq!(*unpacked.pool_mint(), TEST_POOL_MINT);
-        assert_eq!(*unpacked.token_a_mint(), TEST_TOKEN_A_MINT);
-        assert_eq!(*unpacked.token_b_mint(), TEST_TOKEN_B_MINT);
-        assert_eq!(*unpacked.pool_fee_account(), TEST_POOL_FEE_ACCOUNT);
-        assert_eq!(*unpacked.fees(), TEST_FEES);
-        assert_eq!(*unpacked.swap_curve(), swap_curve);
+impl Sealed for PriceAccumulator {}
+
+impl Pack for PriceAccumulator {
+    const LEN: usize = 40;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 40];
+        let (price_a_cumulative, price_b_cumulative, last_update_slot) =
+            mut_array_refs![output, 16, 16, 8];
+        *price_a_cumulative = self.price_a_cumulative.to_le_bytes();
+        *price_b_cumulative = self.price_b_cumulative.to_le_bytes();
+        *last_update_slot = self.last_update_slot.to_le_bytes();
     }
 
-    #[test]
-    fn swap_v1_pack() {
-        let curve_type = TEST_CURVE_TYPE.try_into().unwrap();
-        let calculator = Box::new(TEST_CURVE);
-        let swap_curve = SwapCurve {
-            curve_type,
-            calculator,
-        };
-        let swap_info = SwapV1 {
-            is_initialized: true,
-            nonce: TEST_NONCE,
-            token_program_id: TEST_TOKEN_PROGRAM_ID,
-            token_a: TEST_TOKEN_A,
-            token_b: TEST_TOKEN_B,
-            pool_mint: TEST_POOL_MINT,
-            token_a_mint: TEST_TOKEN_A_MINT,
-            token_b_mint: TEST_TOKEN_B_MINT,
-            pool_fee_account: TEST_POOL_FEE_ACCOUNT,
-            fees: TEST_FEES,
-            swap_curve,
-        };
+    /// Unpacks a byte buffer into a [PriceAccumulator](struct.PriceAccumulator.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 40];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (price_a_cumulative, price_b_cumulative, last_update_slot) =
+            array_refs![input, 16, 16, 8];
+        Ok(Self {
+            price_a_cumulative: u128::from_le_bytes(*price_a_cumulative),
+            price_b_cumulative: u128::from_le_bytes(*price_b_cumulative),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+        })
+    }
+}
 
-        let mut packed = [0u8; SwapV1::LEN];
-        SwapV1::pack_into_slice(&swap_info, &mut packed);
-        let unpacked = SwapV1::unpack(&packed).unwrap();
-        assert_eq!(swap_info, unpacked);
+/// Admin of a two-token swap, stored in its extension
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwapAdmin {
+    /// Authority allowed to update the fees and fee account of the swap, and
+    /// to transfer the admin.  The default pubkey means the swap has no admin.
+    pub admin_authority: Pubkey,
+}
 
-        let mut packed = vec![1u8, TEST_NONCE];
-        packed.extend_from_slice(&TEST_TOKEN_PROGRAM_ID.to_bytes());
-        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
-        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
-        packed.extend_from_slice(&TEST_POOL_MINT.to_bytes());
-        packed.extend_from_slice(&TEST_TOKEN_A_MINT.to_bytes());
-        packed.extend_from_slice(&TEST_TOKEN_B_MINT.to_bytes());
-        packed.extend_from_slice(&TEST_POOL_FEE_ACCOUNT.to_bytes());
-        packed.extend_from_slice(&TEST_FEES.trade_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.trade_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_denominator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.host_fee_numerator.to_le_bytes());
-        packed.extend_from_slice(&TEST_FEES.host_fee_denominator.to_le_bytes());
-        packed.push(TEST_CURVE_TYPE);
-        packed.extend_from_slice(&TEST_AMP.to_le_bytes());
-        packed.extend_from_slice(&[0u8; 24]);
-        let unpacked = SwapV1::unpack(&packed).unwrap();
-        assert_eq!(swap_info, unpacked);
+impl Sealed for SwapAdmin {}
 
-        let packed = [0u8; SwapV1::LEN];
-        let swap_info: SwapV1 = Default::default();
-        let unpack_unchecked = SwapV1::unpack_unchecked(&packed).unwrap();
-        assert_eq!(unpack_unchecked, swap_info);
-        let err = SwapV1::unpack(&packed).unwrap_err();
-        assert_eq!(err, ProgramError::UninitializedAccount);
+impl Pack for SwapAdmin {
+    const LEN: usize = 32;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 32];
+        output.copy_from_slice(self.admin_authority.as_ref());
+    }
+
+    /// Unpacks a byte buffer into a [SwapAdmin](struct.SwapAdmin.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 32];
+        Ok(Self {
+            admin_authority: Pubkey::new_from_array(*input),
+        })
+    }
+}
+
+/// Status of a swap, set by the admin, and stored in the extension of
+/// two-token swaps
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PoolStatus {
+    /// All instructions are allowed
+    Active,
+    /// Swaps, deposits, and withdrawals of a single token, which trade
+    /// against the pool, are rejected until the pool is active again.  Limit
+    /// orders and farm stakes, which wait for the pool, are still allowed.
+    SwapsPaused,
+    /// Only withdrawals of all token types, and of limit orders and farm
+    /// stakes, are allowed
+    WithdrawOnly,
+}
+
+impl PoolStatus {
+    /// Whether the status allows trading against the pool
+    pub fn allows_swaps(&self) -> bool {
+        *self == PoolStatus::Active
+    }
+
+    /// Whether the status allows depositing any tokens into the pool
+    pub fn allows_deposits(&self) -> bool {
+        *self == PoolStatus::Active
+    }
+
+    /// Whether the status allows new limit orders and farm stakes on the pool
+    pub fn allows_orders(&self) -> bool {
+        *self != PoolStatus::WithdrawOnly
+    }
+}
+
+/// Pools are active unless their admin says otherwise
+impl Default for PoolStatus {
+    fn default() -> Self {
+        PoolStatus::Active
+    }
+}
+
+impl TryFrom<u8> for PoolStatus {
+    type Error = ProgramError;
+
+    fn try_from(status: u8) -> Result<Self, Self::Error> {
+        match status {
+            0 => Ok(PoolStatus::Active),
+            1 => Ok(PoolStatus::SwapsPaused),
+            2 => Ok(PoolStatus::WithdrawOnly),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+impl Sealed for PoolStatus {}
+
+impl Pack for PoolStatus {
+    const LEN: usize = 1;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        output[0] = *self as u8;
+    }
+
+    /// Unpacks a byte buffer into a [PoolStatus](enum.PoolStatus.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        input[0].try_into()
+    }
+}
+
+/// Trade fee of a two-token swap following the recent volatility of its
+/// price, stored in its extension and set by the admin
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DynamicFee {
+    /// Trade fee numerator charged without volatility
+    pub min_trade_fee_numerator: u64,
+    /// Trade fee numerator charged from `max_volatility` on
+    pub max_trade_fee_numerator: u64,
+    /// Volatility, in basis points of price movement, at which the maximum
+    /// trade fee is reached.  Zero means the fixed trade fee of the swap is
+    /// charged.
+    pub max_volatility: u64,
+    /// Number of slots over which the volatility decays to zero
+    pub volatility_period: u64,
+    /// Price movement accumulated over recent slots, in basis points
+    pub volatility: u64,
+    /// Slot of the last update
+    pub last_update_slot: u64,
+}
+
+impl Sealed for DynamicFee {}
+
+impl Pack for DynamicFee {
+    const LEN: usize = 48;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 48];
+        let (
+            min_trade_fee_numerator,
+            max_trade_fee_numerator,
+            max_volatility,
+            volatility_period,
+            volatility,
+            last_update_slot,
+        ) = mut_array_refs![output, 8, 8, 8, 8, 8, 8];
+        *min_trade_fee_numerator = self.min_trade_fee_numerator.to_le_bytes();
+        *max_trade_fee_numerator = self.max_trade_fee_numerator.to_le_bytes();
+        *max_volatility = self.max_volatility.to_le_bytes();
+        *volatility_period = self.volatility_period.to_le_bytes();
+        *volatility = self.volatility.to_le_bytes();
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [DynamicFee](struct.DynamicFee.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 48];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            min_trade_fee_numerator,
+            max_trade_fee_numerator,
+            max_volatility,
+            volatility_period,
+            volatility,
+            last_update_slot,
+        ) = array_refs![input, 8, 8, 8, 8, 8, 8];
+        Ok(Self {
+            min_trade_fee_numerator: u64::from_le_bytes(*min_trade_fee_numerator),
+            max_trade_fee_numerator: u64::from_le_bytes(*max_trade_fee_numerator),
+            max_volatility: u64::from_le_bytes(*max_volatility),
+            volatility_period: u64::from_le_bytes(*volatility_period),
+            volatility: u64::from_le_bytes(*volatility),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+        })
+    }
+}
+
+/// Allowlist of a two-token swap, stored in its extension and set by the
+/// admin
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SwapAllowlist {
+    /// Authority allowed to add and remove members of the allowlist.  The
+    /// default pubkey means anyone may deposit into the swap.
+    pub allowlist_authority: Pubkey,
+    /// Whether swaps, and not only deposits, require a membership
+    pub gate_swaps: bool,
+}
+
+impl SwapAllowlist {
+    /// Whether deposits require a membership
+    pub fn is_enabled(&self) -> bool {
+        self.allowlist_authority != Pubkey::default()
+    }
+}
+
+impl Sealed for SwapAllowlist {}
+
+impl Pack for SwapAllowlist {
+    const LEN: usize = 33;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 33];
+        let (allowlist_authority, gate_swaps) = mut_array_refs![output, 32, 1];
+        allowlist_authority.copy_from_slice(self.allowlist_authority.as_ref());
+        gate_swaps[0] = self.gate_swaps as u8;
+    }
+
+    /// Unpacks a byte buffer into a [SwapAllowlist](struct.SwapAllowlist.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 33];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (allowlist_authority, gate_swaps) = array_refs![input, 32, 1];
+        Ok(Self {
+            allowlist_authority: Pubkey::new_from_array(*allowlist_authority),
+            gate_swaps: match gate_swaps {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+}
+
+/// Fee charged on flash loans of a two-token swap, stored in its extension
+/// and set by the admin.  New pools start with their trade fees.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlashLoanFee {
+    /// Fee numerator paid back to the pool on top of the loan
+    pub trade_fee_numerator: u64,
+    /// Fee denominator paid back to the pool on top of the loan
+    pub trade_fee_denominator: u64,
+    /// Owner fee numerator, minted as pool tokens to the pool fee account
+    pub owner_trade_fee_numerator: u64,
+    /// Owner fee denominator, minted as pool tokens to the pool fee account
+    pub owner_trade_fee_denominator: u64,
+}
+
+impl FlashLoanFee {
+    /// Flash loan fee matching the trade fees of a swap
+    pub fn from_trade_fees(fees: &Fees) -> Self {
+        Self {
+            trade_fee_numerator: fees.trade_fee_numerator,
+            trade_fee_denominator: fees.trade_fee_denominator,
+            owner_trade_fee_numerator: fees.owner_trade_fee_numerator,
+            owner_trade_fee_denominator: fees.owner_trade_fee_denominator,
+        }
+    }
+
+    /// Fees charging the flash loan fee as trade fees, and nothing else
+    pub fn fees(&self) -> Fees {
+        Fees {
+            trade_fee_numerator: self.trade_fee_numerator,
+            trade_fee_denominator: self.trade_fee_denominator,
+            owner_trade_fee_numerator: self.owner_trade_fee_numerator,
+            owner_trade_fee_denominator: self.owner_trade_fee_denominator,
+            ..Fees::default()
+        }
+    }
+}
+
+impl Sealed for FlashLoanFee {}
+
+impl Pack for FlashLoanFee {
+    const LEN: usize = 32;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 32];
+        let (
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+        ) = mut_array_refs![output, 8, 8, 8, 8];
+        *trade_fee_numerator = self.trade_fee_numerator.to_le_bytes();
+        *trade_fee_denominator = self.trade_fee_denominator.to_le_bytes();
+        *owner_trade_fee_numerator = self.owner_trade_fee_numerator.to_le_bytes();
+        *owner_trade_fee_denominator = self.owner_trade_fee_denominator.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [FlashLoanFee](struct.FlashLoanFee.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 32];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+        ) = array_refs![input, 8, 8, 8, 8];
+        Ok(Self {
+            trade_fee_numerator: u64::from_le_bytes(*trade_fee_numerator),
+            trade_fee_denominator: u64::from_le_bytes(*trade_fee_denominator),
+            owner_trade_fee_numerator: u64::from_le_bytes(*owner_trade_fee_numerator),
+            owner_trade_fee_denominator: u64::from_le_bytes(*owner_trade_fee_denominator),
+        })
+    }
+}
+
+/// Membership of a user in the allowlist of a two-token swap, held in its
+/// own account and created by the allowlist authority.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct AllowlistMember {
+    /// Initialized state, cleared when the membership is removed.
+    pub is_initialized: bool,
+    /// Swap whose allowlist holds the member
+    pub swap: Pubkey,
+    /// User allowed to deposit, and to swap if swaps are gated, as the user
+    /// transfer authority
+    pub member: Pubkey,
+}
+
+impl Sealed for AllowlistMember {}
+impl IsInitialized for AllowlistMember {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for AllowlistMember {
+    const LEN: usize = 65;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 65];
+        let (is_initialized, swap, member) = mut_array_refs![output, 1, 32, 32];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        member.copy_from_slice(self.member.as_ref());
+    }
+
+    /// Unpacks a byte buffer into a [AllowlistMember](struct.AllowlistMember.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 65];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, member) = array_refs![input, 1, 32, 32];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            member: Pubkey::new_from_array(*member),
+        })
+    }
+}
+
+/// Condition on the price of a two-token swap under which a limit order
+/// fills
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitOrderKind {
+    /// Fills once selling the order returns at least the trigger amount
+    TakeProfit,
+    /// Fills once selling the order returns at most the trigger amount
+    StopLoss,
+}
+
+/// Orders take profit unless they say otherwise
+impl Default for LimitOrderKind {
+    fn default() -> Self {
+        LimitOrderKind::TakeProfit
+    }
+}
+
+impl TryFrom<u8> for LimitOrderKind {
+    type Error = ProgramError;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(LimitOrderKind::TakeProfit),
+            1 => Ok(LimitOrderKind::StopLoss),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Order to sell one token of a two-token swap to the pool once its price
+/// crosses a level, held in its own account with the tokens to sell in an
+/// escrow controlled by the order authority.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct LimitOrder {
+    /// Initialized state, cleared when the order is filled or cancelled.
+    pub is_initialized: bool,
+    /// Bump seed used to generate the order authority
+    pub nonce: u8,
+    /// Swap the order is filled against
+    pub swap: Pubkey,
+    /// Owner allowed to cancel the order
+    pub owner: Pubkey,
+    /// Token account holding the tokens to sell, owned by the order authority
+    pub escrow: Pubkey,
+    /// Token account credited with the tokens bought when the order fills
+    pub destination: Pubkey,
+    /// Direction of the swap filling the order, `AtoB` to sell token A
+    pub trade_direction: TradeDirection,
+    /// Condition under which the order fills
+    pub kind: LimitOrderKind,
+    /// Amount of tokens to sell
+    pub amount_in: u64,
+    /// Amount of tokens bought for all of `amount_in` at the limit price
+    pub trigger_amount_out: u64,
+    /// Least amount of tokens bought the order fills for
+    pub minimum_amount_out: u64,
+}
+
+impl LimitOrder {
+    /// Scales the order to sell `amount_in` at the same price, rounding the
+    /// amounts out up
+    pub fn resize(&mut self, amount_in: u64) -> Option<()> {
+        let scale = |amount_out: u64| -> Option<u64> {
+            let amount_out = (amount_out as u128)
+                .checked_mul(amount_in as u128)?
+                .checked_add((self.amount_in as u128).checked_sub(1)?)?
+                .checked_div(self.amount_in as u128)?;
+            amount_out.try_into().ok()
+        };
+        let trigger_amount_out = scale(self.trigger_amount_out)?;
+        let minimum_amount_out = scale(self.minimum_amount_out)?;
+        self.amount_in = amount_in;
+        self.trigger_amount_out = trigger_amount_out;
+        self.minimum_amount_out = minimum_amount_out;
+        Some(())
+    }
+}
+
+impl Sealed for LimitOrder {}
+impl IsInitialized for LimitOrder {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for LimitOrder {
+    const LEN: usize = 156;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 156];
+        let (
+            is_initialized,
+            nonce,
+            swap,
+            owner,
+            escrow,
+            destination,
+            trade_direction,
+            kind,
+            amount_in,
+            trigger_amount_out,
+            minimum_amount_out,
+        ) = mut_array_refs![output, 1, 1, 32, 32, 32, 32, 1, 1, 8, 8, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        nonce[0] = self.nonce;
+        swap.copy_from_slice(self.swap.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        escrow.copy_from_slice(self.escrow.as_ref());
+        destination.copy_from_slice(self.destination.as_ref());
+        trade_direction[0] = self.trade_direction as u8;
+        kind[0] = self.kind as u8;
+        *amount_in = self.amount_in.to_le_bytes();
+        *trigger_amount_out = self.trigger_amount_out.to_le_bytes();
+        *minimum_amount_out = self.minimum_amount_out.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [LimitOrder](struct.LimitOrder.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 156];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            nonce,
+            swap,
+            owner,
+            escrow,
+            destination,
+            trade_direction,
+            kind,
+            amount_in,
+            trigger_amount_out,
+            minimum_amount_out,
+        ) = array_refs![input, 1, 1, 32, 32, 32, 32, 1, 1, 8, 8, 8];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            swap: Pubkey::new_from_array(*swap),
+            owner: Pubkey::new_from_array(*owner),
+            escrow: Pubkey::new_from_array(*escrow),
+            destination: Pubkey::new_from_array(*destination),
+            trade_direction: match trade_direction {
+                [0] => TradeDirection::AtoB,
+                [1] => TradeDirection::BtoA,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            kind: kind[0].try_into()?,
+            amount_in: u64::from_le_bytes(*amount_in),
+            trigger_amount_out: u64::from_le_bytes(*trigger_amount_out),
+            minimum_amount_out: u64::from_le_bytes(*minimum_amount_out),
+        })
+    }
+}
+
+/// Maximum number of recipients of a fee split
+pub const MAX_FEE_RECIPIENTS: usize = 4;
+
+/// Denominator of the shares of a fee split, in basis points
+pub const FEE_SPLIT_DENOMINATOR: u16 = 10_000;
+
+/// Recipient of a share of the owner fees of a two-token swap
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeeRecipient {
+    /// Token A account credited with the share
+    pub token_a: Pubkey,
+    /// Token B account credited with the share
+    pub token_b: Pubkey,
+    /// Share of the fees, in basis points
+    pub share: u16,
+}
+
+impl FeeRecipient {
+    const LEN: usize = 66;
+
+    /// Share of the given amount of tokens paid to the recipient, rounded
+    /// down
+    pub fn share_of(&self, amount: u64) -> Option<u64> {
+        let share = (amount as u128)
+            .checked_mul(self.share as u128)?
+            .checked_div(FEE_SPLIT_DENOMINATOR as u128)?;
+        share.try_into().ok()
+    }
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 66];
+        let (token_a, token_b, share) = mut_array_refs![output, 32, 32, 2];
+        token_a.copy_from_slice(self.token_a.as_ref());
+        token_b.copy_from_slice(self.token_b.as_ref());
+        *share = self.share.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Self {
+        let input = array_ref![input, 0, 66];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (token_a, token_b, share) = array_refs![input, 32, 32, 2];
+        Self {
+            token_a: Pubkey::new_from_array(*token_a),
+            token_b: Pubkey::new_from_array(*token_b),
+            share: u16::from_le_bytes(*share),
+        }
+    }
+}
+
+/// Split of the owner fees of a two-token swap among several recipients, set
+/// by the admin
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct FeeSplit {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Two-token swap whose owner fees are split
+    pub swap: Pubkey,
+    /// Number of recipients
+    pub recipient_count: u8,
+    /// Recipients, only the first `recipient_count` are used
+    pub recipients: [FeeRecipient; MAX_FEE_RECIPIENTS],
+}
+
+impl FeeSplit {
+    /// Recipients in use
+    pub fn active_recipients(&self) -> &[FeeRecipient] {
+        &self.recipients[..self.recipient_count as usize]
+    }
+}
+
+impl Sealed for FeeSplit {}
+impl IsInitialized for FeeSplit {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for FeeSplit {
+    const LEN: usize = 298;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 298];
+        let (is_initialized, swap, recipient_count, recipients) =
+            mut_array_refs![output, 1, 32, 1, 264];
+        is_initialized[0] = self.is_initialized as u8;
+        swap.copy_from_slice(self.swap.as_ref());
+        recipient_count[0] = self.recipient_count;
+        for (recipient, output) in self
+            .recipients
+            .iter()
+            .zip(recipients.chunks_exact_mut(FeeRecipient::LEN))
+        {
+            recipient.pack_into_slice(output);
+        }
+    }
+
+    /// Unpacks a byte buffer into a [FeeSplit](struct.FeeSplit.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 298];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, swap, recipient_count, recipients) = array_refs![input, 1, 32, 1, 264];
+        if recipient_count[0] as usize > MAX_FEE_RECIPIENTS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut unpacked_recipients = [FeeRecipient::default(); MAX_FEE_RECIPIENTS];
+        for (recipient, input) in unpacked_recipients
+            .iter_mut()
+            .zip(recipients.chunks_exact(FeeRecipient::LEN))
+        {
+            *recipient = FeeRecipient::unpack_from_slice(input);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            swap: Pubkey::new_from_array(*swap),
+            recipient_count: recipient_count[0],
+            recipients: unpacked_recipients,
+        })
+    }
+}
+
+/// Token held by a multi-token swap
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapToken {
+    /// Swap account holding the token
+    pub account: Pubkey,
+    /// Mint of the token
+    pub mint: Pubkey,
+    /// Weight of the token, only used by weighted curves
+    pub weight: u64,
+}
+
+impl SwapToken {
+    const LEN: usize = 72;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 72];
+        let (account, mint, weight) = mut_array_refs![output, 32, 32, 8];
+        account.copy_from_slice(self.account.as_ref());
+        mint.copy_from_slice(self.mint.as_ref());
+        *weight = self.weight.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Self {
+        let input = array_ref![input, 0, 72];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (account, mint, weight) = array_refs![input, 32, 32, 8];
+        Self {
+            account: Pubkey::new_from_array(*account),
+            mint: Pubkey::new_from_array(*mint),
+            weight: u64::from_le_bytes(*weight),
+        }
+    }
+}
+
+/// Program state for swaps holding more than two tokens.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct SwapV2 {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Nonce used in program address.
+    /// The program address is created deterministically with the nonce,
+    /// swap program id, and swap account pubkey.  This program address has
+    /// authority over the swap's token accounts and pool token mint.
+    pub nonce: u8,
+
+    /// Program ID of the tokens being exchanged.
+    pub token_program_id: Pubkey,
+
+    /// Pool tokens are issued when tokens are deposited.
+    /// Pool tokens can be withdrawn back to the original tokens.
+    pub pool_mint: Pubkey,
+
+    /// Pool token account to receive trading and / or withdrawal fees
+    pub pool_fee_account: Pubkey,
+
+    /// All fee information
+    pub fees: Fees,
+
+    /// Admin allowed to set the status of the swap
+    pub admin: SwapAdmin,
+    /// Status of the swap, set by the admin
+    pub pool_status: PoolStatus,
+
+    /// Swap curve parameters, valid between the first two tokens, and used to
+    /// build the multi-token curve for all tokens
+    pub swap_curve: SwapCurve,
+
+    /// Number of tokens held by the swap
+    pub token_count: u8,
+    /// Tokens held by the swap, only the first `token_count` are used
+    pub tokens: [SwapToken; MAX_TOKENS_IN_MULTI_TOKEN_POOL],
+}
+
+impl SwapV2 {
+    /// Tokens held by the swap
+    pub fn tokens(&self) -> &[SwapToken] {
+        &self.tokens[..self.token_count as usize]
+    }
+
+    /// Index of the token with the given mint
+    pub fn token_index(&self, mint: &Pubkey) -> Option<usize> {
+        self.tokens().iter().position(|token| token.mint == *mint)
+    }
+
+    /// Curve calculating swaps, deposits, and withdrawals across all tokens
+    pub fn multi_token_curve(&self) -> Result<MultiTokenCurve, SwapError> {
+        let weights = self
+            .tokens()
+            .iter()
+            .map(|token| token.weight)
+            .collect::<Vec<_>>();
+        MultiTokenCurve::new(&self.swap_curve, &weights)
+    }
+}
+
+impl SwapState for SwapV2 {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn nonce(&self) -> u8 {
+        self.nonce
+    }
+
+    fn token_program_id(&self) -> &Pubkey {
+        &self.token_program_id
+    }
+
+    fn token_a_account(&self) -> &Pubkey {
+        &self.tokens[0].account
+    }
+
+    fn token_b_account(&self) -> &Pubkey {
+        &self.tokens[1].account
+    }
+
+    fn pool_mint(&self) -> &Pubkey {
+        &self.pool_mint
+    }
+
+    fn token_a_mint(&self) -> &Pubkey {
+        &self.tokens[0].mint
+    }
+
+    fn token_b_mint(&self) -> &Pubkey {
+        &self.tokens[1].mint
+    }
+
+    fn pool_fee_account(&self) -> &Pubkey {
+        &self.pool_fee_account
+    }
+
+    fn fees(&self) -> &Fees {
+        &self.fees
+    }
+
+    fn swap_curve(&self) -> &SwapCurve {
+        &self.swap_curve
+    }
+}
+
+impl Sealed for SwapV2 {}
+impl IsInitialized for SwapV2 {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for SwapV2 {
+    const LEN: usize = 805;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 805];
+        let (
+            is_initialized,
+            nonce,
+            token_program_id,
+            pool_mint,
+            pool_fee_account,
+            fees,
+            admin,
+            pool_status,
+            swap_curve,
+            token_count,
+            tokens,
+        ) = mut_array_refs![output, 1, 1, 32, 32, 32, 64, 32, 1, 33, 1, 576];
+        is_initialized[0] = self.is_initialized as u8;
+        nonce[0] = self.nonce;
+        token_program_id.copy_from_slice(self.token_program_id.as_ref());
+        pool_mint.copy_from_slice(self.pool_mint.as_ref());
+        pool_fee_account.copy_from_slice(self.pool_fee_account.as_ref());
+        self.fees.pack_into_slice(&mut fees[..]);
+        self.admin.pack_into_slice(&mut admin[..]);
+        self.pool_status.pack_into_slice(&mut pool_status[..]);
+        self.swap_curve.pack_into_slice(&mut swap_curve[..]);
+        token_count[0] = self.token_count;
+        for (token, output) in self
+            .tokens
+            .iter()
+            .zip(tokens.chunks_exact_mut(SwapToken::LEN))
+        {
+            token.pack_into_slice(output);
+        }
+    }
+
+    /// Unpacks a byte buffer into a [SwapV2](struct.SwapV2.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 805];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            nonce,
+            token_program_id,
+            pool_mint,
+            pool_fee_account,
+            fees,
+            admin,
+            pool_status,
+            swap_curve,
+            token_count,
+            tokens,
+        ) = array_refs![input, 1, 1, 32, 32, 32, 64, 32, 1, 33, 1, 576];
+        if token_count[0] as usize > MAX_TOKENS_IN_MULTI_TOKEN_POOL {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut unpacked_tokens: [SwapToken; MAX_TOKENS_IN_MULTI_TOKEN_POOL] = Default::default();
+        for (token, input) in unpacked_tokens
+            .iter_mut()
+            .zip(tokens.chunks_exact(SwapToken::LEN))
+        {
+            *token = SwapToken::unpack_from_slice(input);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            token_program_id: Pubkey::new_from_array(*token_program_id),
+            pool_mint: Pubkey::new_from_array(*pool_mint),
+            pool_fee_account: Pubkey::new_from_array(*pool_fee_account),
+            fees: Fees::unpack_from_slice(fees)?,
+            admin: SwapAdmin::unpack_from_slice(admin)?,
+            pool_status: PoolStatus::unpack_from_slice(pool_status)?,
+            swap_curve: SwapCurve::unpack_from_slice(swap_curve)?,
+            token_count: token_count[0],
+            tokens: unpacked_tokens,
+        })
+    }
+}
+
+/// Initialized tick in a concentrated liquidity pool, bounding at least one
+/// position
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tick {
+    /// Index of the tick, where the price is `1.0001 ^ index`
+    pub index: i32,
+    /// Liquidity added when the price crosses the tick going up, and removed
+    /// when going down
+    pub liquidity_net: i128,
+    /// Total liquidity of all positions bounded by the tick
+    pub liquidity_gross: u128,
+    /// Fee growth per unit of liquidity in token A on the other side of the
+    /// tick from the current price, in Q64.64
+    pub fee_growth_outside_a: u128,
+    /// Fee growth per unit of liquidity in token B on the other side of the
+    /// tick from the current price, in Q64.64
+    pub fee_growth_outside_b: u128,
+}
+
+impl Tick {
+    const LEN: usize = 68;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 68];
+        let (index, liquidity_net, liquidity_gross, fee_growth_outside_a, fee_growth_outside_b) =
+            mut_array_refs![output, 4, 16, 16, 16, 16];
+        *index = self.index.to_le_bytes();
+        *liquidity_net = self.liquidity_net.to_le_bytes();
+        *liquidity_gross = self.liquidity_gross.to_le_bytes();
+        *fee_growth_outside_a = self.fee_growth_outside_a.to_le_bytes();
+        *fee_growth_outside_b = self.fee_growth_outside_b.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Self {
+        let input = array_ref![input, 0, 68];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (index, liquidity_net, liquidity_gross, fee_growth_outside_a, fee_growth_outside_b) =
+            array_refs![input, 4, 16, 16, 16, 16];
+        Self {
+            index: i32::from_le_bytes(*index),
+            liquidity_net: i128::from_le_bytes(*liquidity_net),
+            liquidity_gross: u128::from_le_bytes(*liquidity_gross),
+            fee_growth_outside_a: u128::from_le_bytes(*fee_growth_outside_a),
+            fee_growth_outside_b: u128::from_le_bytes(*fee_growth_outside_b),
+        }
+    }
+}
+
+/// Program state for concentrated liquidity pools, where liquidity is held by
+/// positions within price ranges instead of by pool tokens.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct ConcentratedPool {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Nonce used in program address.
+    /// The program address is created deterministically with the nonce,
+    /// swap program id, and swap account pubkey.  This program address has
+    /// authority over the swap's token A and token B accounts.
+    pub nonce: u8,
+
+    /// Program ID of the tokens being exchanged.
+    pub token_program_id: Pubkey,
+
+    /// Token A
+    pub token_a: Pubkey,
+    /// Token B
+    pub token_b: Pubkey,
+
+    /// Mint information for token A
+    pub token_a_mint: Pubkey,
+    /// Mint information for token B
+    pub token_b_mint: Pubkey,
+
+    /// All fee information, only the trade fee is used
+    pub fees: Fees,
+
+    /// Admin allowed to set the status of the pool
+    pub admin: SwapAdmin,
+    /// Status of the pool, set by the admin
+    pub pool_status: PoolStatus,
+
+    /// Spacing between ticks that can bound a position
+    pub tick_spacing: u16,
+    /// Lowest tick that can bound a position.  Positions are bounded by the
+    /// `MAX_TICKS_IN_CONCENTRATED_POOL` ticks starting here at `tick_spacing`
+    /// apart, so there is always a slot for every tick in use.
+    pub tick_lower_bound: i32,
+    /// Least liquidity a position may hold, unless empty
+    pub min_position_liquidity: u128,
+    /// Square root of the current price of token A in token B, in Q64.64
+    pub sqrt_price: u128,
+    /// Greatest tick at or below the current price
+    pub tick_current: i32,
+    /// Liquidity of all positions in range of the current price
+    pub liquidity: u128,
+    /// Total fee growth per unit of liquidity in token A, in Q64.64
+    pub fee_growth_global_a: u128,
+    /// Total fee growth per unit of liquidity in token B, in Q64.64
+    pub fee_growth_global_b: u128,
+
+    /// Number of initialized ticks
+    pub tick_count: u8,
+    /// Initialized ticks, sorted by index, only the first `tick_count` are used
+    pub ticks: [Tick; MAX_TICKS_IN_CONCENTRATED_POOL],
+}
+
+impl Sealed for ConcentratedPool {}
+impl IsInitialized for ConcentratedPool {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for ConcentratedPool {
+    const LEN: usize = 2526;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 2526];
+        let (
+            is_initialized,
+            nonce,
+            token_program_id,
+            token_a,
+            token_b,
+            token_a_mint,
+            token_b_mint,
+            fees,
+            admin,
+            pool_status,
+            tick_spacing,
+            tick_lower_bound,
+            min_position_liquidity,
+            sqrt_price,
+            tick_current,
+            liquidity,
+            fee_growth_global_a,
+            fee_growth_global_b,
+            tick_count,
+            ticks,
+        ) = mut_array_refs![
+            output, 1, 1, 32, 32, 32, 32, 32, 64, 32, 1, 2, 4, 16, 16, 4, 16, 16, 16, 1, 2176
+        ];
+        is_initialized[0] = self.is_initialized as u8;
+        nonce[0] = self.nonce;
+        token_program_id.copy_from_slice(self.token_program_id.as_ref());
+        token_a.copy_from_slice(self.token_a.as_ref());
+        token_b.copy_from_slice(self.token_b.as_ref());
+        token_a_mint.copy_from_slice(self.token_a_mint.as_ref());
+        token_b_mint.copy_from_slice(self.token_b_mint.as_ref());
+        self.fees.pack_into_slice(&mut fees[..]);
+        self.admin.pack_into_slice(&mut admin[..]);
+        self.pool_status.pack_into_slice(&mut pool_status[..]);
+        *tick_spacing = self.tick_spacing.to_le_bytes();
+        *tick_lower_bound = self.tick_lower_bound.to_le_bytes();
+        *min_position_liquidity = self.min_position_liquidity.to_le_bytes();
+        *sqrt_price = self.sqrt_price.to_le_bytes();
+        *tick_current = self.tick_current.to_le_bytes();
+        *liquidity = self.liquidity.to_le_bytes();
+        *fee_growth_global_a = self.fee_growth_global_a.to_le_bytes();
+        *fee_growth_global_b = self.fee_growth_global_b.to_le_bytes();
+        tick_count[0] = self.tick_count;
+        for (tick, output) in self.ticks.iter().zip(ticks.chunks_exact_mut(Tick::LEN)) {
+            tick.pack_into_slice(output);
+        }
+    }
+
+    /// Unpacks a byte buffer into a [ConcentratedPool](struct.ConcentratedPool.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 2526];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            nonce,
+            token_program_id,
+            token_a,
+            token_b,
+            token_a_mint,
+            token_b_mint,
+            fees,
+            admin,
+            pool_status,
+            tick_spacing,
+            tick_lower_bound,
+            min_position_liquidity,
+            sqrt_price,
+            tick_current,
+            liquidity,
+            fee_growth_global_a,
+            fee_growth_global_b,
+            tick_count,
+            ticks,
+        ) = array_refs![
+            input, 1, 1, 32, 32, 32, 32, 32, 64, 32, 1, 2, 4, 16, 16, 4, 16, 16, 16, 1, 2176
+        ];
+        if tick_count[0] as usize > MAX_TICKS_IN_CONCENTRATED_POOL {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut unpacked_ticks = [Tick::default(); MAX_TICKS_IN_CONCENTRATED_POOL];
+        for (tick, input) in unpacked_ticks.iter_mut().zip(ticks.chunks_exact(Tick::LEN)) {
+            *tick = Tick::unpack_from_slice(input);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            token_program_id: Pubkey::new_from_array(*token_program_id),
+            token_a: Pubkey::new_from_array(*token_a),
+            token_b: Pubkey::new_from_array(*token_b),
+            token_a_mint: Pubkey::new_from_array(*token_a_mint),
+            token_b_mint: Pubkey::new_from_array(*token_b_mint),
+            fees: Fees::unpack_from_slice(fees)?,
+            admin: SwapAdmin::unpack_from_slice(admin)?,
+            pool_status: PoolStatus::unpack_from_slice(pool_status)?,
+            tick_spacing: u16::from_le_bytes(*tick_spacing),
+            tick_lower_bound: i32::from_le_bytes(*tick_lower_bound),
+            min_position_liquidity: u128::from_le_bytes(*min_position_liquidity),
+            sqrt_price: u128::from_le_bytes(*sqrt_price),
+            tick_current: i32::from_le_bytes(*tick_current),
+            liquidity: u128::from_le_bytes(*liquidity),
+            fee_growth_global_a: u128::from_le_bytes(*fee_growth_global_a),
+            fee_growth_global_b: u128::from_le_bytes(*fee_growth_global_b),
+            tick_count: tick_count[0],
+            ticks: unpacked_ticks,
+        })
+    }
+}
+
+/// Liquidity provided to a concentrated liquidity pool within a price range,
+/// held in its own account and controlled by its owner.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct Position {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Concentrated liquidity pool holding the position
+    pub pool: Pubkey,
+    /// Owner allowed to change the position and collect its fees
+    pub owner: Pubkey,
+    /// Lower tick of the price range
+    pub tick_lower: i32,
+    /// Upper tick of the price range
+    pub tick_upper: i32,
+    /// Liquidity provided within the price range
+    pub liquidity: u128,
+    /// Fee growth per unit of liquidity in token A within the price range as
+    /// of the last update, in Q64.64
+    pub fee_growth_inside_a: u128,
+    /// Fee growth per unit of liquidity in token B within the price range as
+    /// of the last update, in Q64.64
+    pub fee_growth_inside_b: u128,
+    /// Fees in token A earned and not yet collected
+    pub tokens_owed_a: u64,
+    /// Fees in token B earned and not yet collected
+    pub tokens_owed_b: u64,
+}
+
+impl Sealed for Position {}
+impl IsInitialized for Position {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Position {
+    const LEN: usize = 137;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 137];
+        let (
+            is_initialized,
+            pool,
+            owner,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            fee_growth_inside_a,
+            fee_growth_inside_b,
+            tokens_owed_a,
+            tokens_owed_b,
+        ) = mut_array_refs![output, 1, 32, 32, 4, 4, 16, 16, 16, 8, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        pool.copy_from_slice(self.pool.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        *tick_lower = self.tick_lower.to_le_bytes();
+        *tick_upper = self.tick_upper.to_le_bytes();
+        *liquidity = self.liquidity.to_le_bytes();
+        *fee_growth_inside_a = self.fee_growth_inside_a.to_le_bytes();
+        *fee_growth_inside_b = self.fee_growth_inside_b.to_le_bytes();
+        *tokens_owed_a = self.tokens_owed_a.to_le_bytes();
+        *tokens_owed_b = self.tokens_owed_b.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [Position](struct.Position.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 137];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            pool,
+            owner,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            fee_growth_inside_a,
+            fee_growth_inside_b,
+            tokens_owed_a,
+            tokens_owed_b,
+        ) = array_refs![input, 1, 32, 32, 4, 4, 16, 16, 16, 8, 8];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            pool: Pubkey::new_from_array(*pool),
+            owner: Pubkey::new_from_array(*owner),
+            tick_lower: i32::from_le_bytes(*tick_lower),
+            tick_upper: i32::from_le_bytes(*tick_upper),
+            liquidity: u128::from_le_bytes(*liquidity),
+            fee_growth_inside_a: u128::from_le_bytes(*fee_growth_inside_a),
+            fee_growth_inside_b: u128::from_le_bytes(*fee_growth_inside_b),
+            tokens_owed_a: u64::from_le_bytes(*tokens_owed_a),
+            tokens_owed_b: u64::from_le_bytes(*tokens_owed_b),
+        })
+    }
+}
+
+/// Reward token paid by a farm to its stakers
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FarmReward {
+    /// Token account holding the rewards, owned by the farm authority
+    pub vault: Pubkey,
+    /// Amount of the reward token paid to all stakers per slot
+    pub reward_per_slot: u64,
+    /// Total rewards per staked pool token, in Q64.64
+    pub reward_per_share: u128,
+}
+
+impl FarmReward {
+    const LEN: usize = 56;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 56];
+        let (vault, reward_per_slot, reward_per_share) = mut_array_refs![output, 32, 8, 16];
+        vault.copy_from_slice(self.vault.as_ref());
+        *reward_per_slot = self.reward_per_slot.to_le_bytes();
+        *reward_per_share = self.reward_per_share.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Self {
+        let input = array_ref![input, 0, 56];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (vault, reward_per_slot, reward_per_share) = array_refs![input, 32, 8, 16];
+        Self {
+            vault: Pubkey::new_from_array(*vault),
+            reward_per_slot: u64::from_le_bytes(*reward_per_slot),
+            reward_per_share: u128::from_le_bytes(*reward_per_share),
+        }
+    }
+}
+
+/// Liquidity-mining farm of a two-token swap, paying rewards per slot to the
+/// owners of staked pool tokens
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct Farm {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Bump seed used to generate the farm authority
+    pub nonce: u8,
+    /// Two-token swap whose pool tokens are staked
+    pub swap: Pubkey,
+    /// Pool token account holding the staked pool tokens, owned by the farm
+    /// authority
+    pub stake_vault: Pubkey,
+    /// Pool tokens staked by all stakers
+    pub total_staked: u64,
+    /// Slot of the last update of the rewards per share
+    pub last_update_slot: u64,
+    /// Number of reward tokens
+    pub reward_count: u8,
+    /// Reward tokens, only the first `reward_count` are used
+    pub rewards: [FarmReward; MAX_FARM_REWARDS],
+}
+
+impl Sealed for Farm {}
+impl IsInitialized for Farm {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Farm {
+    const LEN: usize = 307;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 307];
+        let (
+            is_initialized,
+            nonce,
+            swap,
+            stake_vault,
+            total_staked,
+            last_update_slot,
+            reward_count,
+            rewards,
+        ) = mut_array_refs![output, 1, 1, 32, 32, 8, 8, 1, 224];
+        is_initialized[0] = self.is_initialized as u8;
+        nonce[0] = self.nonce;
+        swap.copy_from_slice(self.swap.as_ref());
+        stake_vault.copy_from_slice(self.stake_vault.as_ref());
+        *total_staked = self.total_staked.to_le_bytes();
+        *last_update_slot = self.last_update_slot.to_le_bytes();
+        reward_count[0] = self.reward_count;
+        for (reward, output) in self
+            .rewards
+            .iter()
+            .zip(rewards.chunks_exact_mut(FarmReward::LEN))
+        {
+            reward.pack_into_slice(output);
+        }
+    }
+
+    /// Unpacks a byte buffer into a [Farm](struct.Farm.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 307];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            nonce,
+            swap,
+            stake_vault,
+            total_staked,
+            last_update_slot,
+            reward_count,
+            rewards,
+        ) = array_refs![input, 1, 1, 32, 32, 8, 8, 1, 224];
+        if reward_count[0] as usize > MAX_FARM_REWARDS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut unpacked_rewards = [FarmReward::default(); MAX_FARM_REWARDS];
+        for (reward, input) in unpacked_rewards
+            .iter_mut()
+            .zip(rewards.chunks_exact(FarmReward::LEN))
+        {
+            *reward = FarmReward::unpack_from_slice(input);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            nonce: nonce[0],
+            swap: Pubkey::new_from_array(*swap),
+            stake_vault: Pubkey::new_from_array(*stake_vault),
+            total_staked: u64::from_le_bytes(*total_staked),
+            last_update_slot: u64::from_le_bytes(*last_update_slot),
+            reward_count: reward_count[0],
+            rewards: unpacked_rewards,
+        })
+    }
+}
+
+/// Pool tokens staked in a farm, held in its own account and controlled by
+/// its owner.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct FarmStake {
+    /// Initialized state.
+    pub is_initialized: bool,
+    /// Farm holding the staked pool tokens
+    pub farm: Pubkey,
+    /// Owner allowed to unstake the pool tokens and claim their rewards
+    pub owner: Pubkey,
+    /// Pool tokens staked
+    pub amount: u64,
+    /// Rewards per staked pool token of every reward token as of the last
+    /// update, in Q64.64
+    pub reward_per_share_paid: [u128; MAX_FARM_REWARDS],
+    /// Rewards of every reward token earned and not yet claimed
+    pub rewards_owed: [u64; MAX_FARM_REWARDS],
+}
+
+impl Sealed for FarmStake {}
+impl IsInitialized for FarmStake {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for FarmStake {
+    const LEN: usize = 169;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 169];
+        let (is_initialized, farm, owner, amount, reward_per_share_paid, rewards_owed) =
+            mut_array_refs![output, 1, 32, 32, 8, 64, 32];
+        is_initialized[0] = self.is_initialized as u8;
+        farm.copy_from_slice(self.farm.as_ref());
+        owner.copy_from_slice(self.owner.as_ref());
+        *amount = self.amount.to_le_bytes();
+        for (paid, output) in self
+            .reward_per_share_paid
+            .iter()
+            .zip(reward_per_share_paid.chunks_exact_mut(16))
+        {
+            output.copy_from_slice(&paid.to_le_bytes());
+        }
+        for (owed, output) in self
+            .rewards_owed
+            .iter()
+            .zip(rewards_owed.chunks_exact_mut(8))
+        {
+            output.copy_from_slice(&owed.to_le_bytes());
+        }
+    }
+
+    /// Unpacks a byte buffer into a [FarmStake](struct.FarmStake.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 169];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, farm, owner, amount, reward_per_share_paid, rewards_owed) =
+            array_refs![input, 1, 32, 32, 8, 64, 32];
+        let mut unpacked_reward_per_share_paid = [0u128; MAX_FARM_REWARDS];
+        for (paid, input) in unpacked_reward_per_share_paid
+            .iter_mut()
+            .zip(reward_per_share_paid.chunks_exact(16))
+        {
+            *paid = u128::from_le_bytes(*array_ref![input, 0, 16]);
+        }
+        let mut unpacked_rewards_owed = [0u64; MAX_FARM_REWARDS];
+        for (owed, input) in unpacked_rewards_owed
+            .iter_mut()
+            .zip(rewards_owed.chunks_exact(8))
+        {
+            *owed = u64::from_le_bytes(*array_ref![input, 0, 8]);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            farm: Pubkey::new_from_array(*farm),
+            owner: Pubkey::new_from_array(*owner),
+            amount: u64::from_le_bytes(*amount),
+            reward_per_share_paid: unpacked_reward_per_share_paid,
+            rewards_owed: unpacked_rewards_owed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::{base::CurveType, stable::StableCurve, weighted::WeightedCurve};
+
+    use std::convert::TryInto;
+
+    const TEST_FEES: Fees = Fees {
+        trade_fee_numerator: 1,
+        trade_fee_denominator: 4,
+        owner_trade_fee_numerator: 3,
+        owner_trade_fee_denominator: 10,
+        owner_withdraw_fee_numerator: 2,
+        owner_withdraw_fee_denominator: 7,
+        host_fee_numerator: 5,
+        host_fee_denominator: 20,
+    };
+
+    const TEST_NONCE: u8 = 255;
+    const TEST_TOKEN_PROGRAM_ID: Pubkey = Pubkey::new_from_array([1u8; 32]);
+    const TEST_TOKEN_A: Pubkey = Pubkey::new_from_array([2u8; 32]);
+    const TEST_TOKEN_B: Pubkey = Pubkey::new_from_array([3u8; 32]);
+    const TEST_POOL_MINT: Pubkey = Pubkey::new_from_array([4u8; 32]);
+    const TEST_TOKEN_A_MINT: Pubkey = Pubkey::new_from_array([5u8; 32]);
+    const TEST_TOKEN_B_MINT: Pubkey = Pubkey::new_from_array([6u8; 32]);
+    const TEST_POOL_FEE_ACCOUNT: Pubkey = Pubkey::new_from_array([7u8; 32]);
+
+    const TEST_CURVE_TYPE: u8 = 2;
+    const TEST_AMP: u64 = 1;
+    const TEST_CURVE: StableCurve = StableCurve::new(TEST_AMP);
+
+    #[test]
+    fn swap_version_pack() {
+        let curve_type = TEST_CURVE_TYPE.try_into().unwrap();
+        let calculator = Box::new(TEST_CURVE);
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator,
+        };
+        let swap_info = SwapVersion::SwapV1(SwapV1 {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            token_program_id: TEST_TOKEN_PROGRAM_ID,
+            token_a: TEST_TOKEN_A,
+            token_b: TEST_TOKEN_B,
+            pool_mint: TEST_POOL_MINT,
+            token_a_mint: TEST_TOKEN_A_MINT,
+            token_b_mint: TEST_TOKEN_B_MINT,
+            pool_fee_account: TEST_POOL_FEE_ACCOUNT,
+            fees: TEST_FEES,
+            swap_curve: swap_curve.clone(),
+        });
+
+        let mut packed = [0u8; SwapVersion::LATEST_LEN];
+        SwapVersion::pack(swap_info, &mut packed).unwrap();
+        let unpacked = SwapVersion::unpack(&packed).unwrap();
+
+        assert_eq!(unpacked.is_initialized(), true);
+        assert_eq!(unpacked.nonce(), TEST_NONCE);
+        assert_eq!(*unpacked.token_program_id(), TEST_TOKEN_PROGRAM_ID);
+        assert_eq!(*unpacked.token_a_account(), TEST_TOKEN_A);
+        assert_eq!(*unpacked.token_b_account(), TEST_TOKEN_B);
+        assert_eq!(*unpacked.pool_mint(), TEST_POOL_MINT);
+        assert_eq!(*unpacked.token_a_mint(), TEST_TOKEN_A_MINT);
+        assert_eq!(*unpacked.token_b_mint(), TEST_TOKEN_B_MINT);
+        assert_eq!(*unpacked.pool_fee_account(), TEST_POOL_FEE_ACCOUNT);
+        assert_eq!(*unpacked.fees(), TEST_FEES);
+        assert_eq!(*unpacked.swap_curve(), swap_curve);
+    }
+
+    #[test]
+    fn swap_v1_pack() {
+        let curve_type = TEST_CURVE_TYPE.try_into().unwrap();
+        let calculator = Box::new(TEST_CURVE);
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator,
+        };
+        let swap_info = SwapV1 {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            token_program_id: TEST_TOKEN_PROGRAM_ID,
+            token_a: TEST_TOKEN_A,
+            token_b: TEST_TOKEN_B,
+            pool_mint: TEST_POOL_MINT,
+            token_a_mint: TEST_TOKEN_A_MINT,
+            token_b_mint: TEST_TOKEN_B_MINT,
+            pool_fee_account: TEST_POOL_FEE_ACCOUNT,
+            fees: TEST_FEES,
+            swap_curve,
+        };
+
+        let mut packed = [0u8; SwapV1::LEN];
+        SwapV1::pack_into_slice(&swap_info, &mut packed);
+        let unpacked = SwapV1::unpack(&packed).unwrap();
+        assert_eq!(swap_info, unpacked);
+
+        let mut packed = vec![1u8, TEST_NONCE];
+        packed.extend_from_slice(&TEST_TOKEN_PROGRAM_ID.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&TEST_POOL_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_POOL_FEE_ACCOUNT.to_bytes());
+        packed.extend_from_slice(&TEST_FEES.trade_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.trade_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.host_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.host_fee_denominator.to_le_bytes());
+        packed.push(TEST_CURVE_TYPE);
+        packed.extend_from_slice(&TEST_AMP.to_le_bytes());
+        packed.extend_from_slice(&[0u8; 24]);
+        let unpacked = SwapV1::unpack(&packed).unwrap();
+        assert_eq!(swap_info, unpacked);
+
+        let packed = [0u8; SwapV1::LEN];
+        let swap_info: SwapV1 = Default::default();
+        let unpack_unchecked = SwapV1::unpack_unchecked(&packed).unwrap();
+        assert_eq!(unpack_unchecked, swap_info);
+        let err = SwapV1::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    fn test_swap_v2(swap_curve: SwapCurve) -> SwapV2 {
+        let mut tokens: [SwapToken; MAX_TOKENS_IN_MULTI_TOKEN_POOL] = Default::default();
+        for (index, token) in tokens.iter_mut().take(3).enumerate() {
+            *token = SwapToken {
+                account: Pubkey::new_from_array([10 + index as u8; 32]),
+                mint: Pubkey::new_from_array([20 + index as u8; 32]),
+                weight: 10 * (index as u64 + 1),
+            };
+        }
+        SwapV2 {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            token_program_id: TEST_TOKEN_PROGRAM_ID,
+            pool_mint: TEST_POOL_MINT,
+            pool_fee_account: TEST_POOL_FEE_ACCOUNT,
+            fees: TEST_FEES,
+            admin: SwapAdmin {
+                admin_authority: TEST_TOKEN_A,
+            },
+            pool_status: PoolStatus::SwapsPaused,
+            swap_curve,
+            token_count: 3,
+            tokens,
+        }
+    }
+
+    #[test]
+    fn swap_version_pack_multi_token() {
+        let swap_curve = SwapCurve {
+            curve_type: CurveType::Weighted,
+            calculator: Box::new(WeightedCurve {
+                token_a_weight: 10,
+                token_b_weight: 20,
+            }),
+        };
+        let swap_info = SwapVersion::SwapV2(test_swap_v2(swap_curve.clone()));
+
+        let mut packed = [0u8; SwapVersion::MULTI_TOKEN_LEN];
+        SwapVersion::pack(swap_info, &mut packed).unwrap();
+        assert!(SwapVersion::is_initialized(&packed));
+        assert_eq!(
+            SwapVersion::unpack(&packed).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+
+        let unpacked = SwapVersion::unpack_multi_token(&packed).unwrap();
+        assert_eq!(unpacked, test_swap_v2(swap_curve));
+        assert_eq!(unpacked.tokens().len(), 3);
+        assert_eq!(
+            *unpacked.token_a_account(),
+            Pubkey::new_from_array([10u8; 32])
+        );
+        assert_eq!(*unpacked.token_b_mint(), Pubkey::new_from_array([21u8; 32]));
+        assert_eq!(
+            unpacked.token_index(&Pubkey::new_from_array([22u8; 32])),
+            Some(2)
+        );
+        assert_eq!(unpacked.token_index(&TEST_POOL_MINT), None);
+        assert_eq!(
+            unpacked.multi_token_curve(),
+            Ok(MultiTokenCurve::Weighted {
+                weights: vec![10, 20, 30]
+            })
+        );
+
+        let mut packed = [0u8; SwapVersion::LATEST_LEN];
+        packed[0] = 1;
+        assert_eq!(
+            SwapVersion::unpack_multi_token(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn swap_v2_pack() {
+        let curve_type = TEST_CURVE_TYPE.try_into().unwrap();
+        let calculator = Box::new(TEST_CURVE);
+        let swap_curve = SwapCurve {
+            curve_type,
+            calculator,
+        };
+        let swap_info = test_swap_v2(swap_curve);
+
+        let mut packed = [0u8; SwapV2::LEN];
+        SwapV2::pack_into_slice(&swap_info, &mut packed);
+        let unpacked = SwapV2::unpack(&packed).unwrap();
+        assert_eq!(swap_info, unpacked);
+
+        let mut packed = vec![1u8, TEST_NONCE];
+        packed.extend_from_slice(&TEST_TOKEN_PROGRAM_ID.to_bytes());
+        packed.extend_from_slice(&TEST_POOL_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_POOL_FEE_ACCOUNT.to_bytes());
+        packed.extend_from_slice(&TEST_FEES.trade_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.trade_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.host_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.host_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.push(1);
+        packed.push(TEST_CURVE_TYPE);
+        packed.extend_from_slice(&TEST_AMP.to_le_bytes());
+        packed.extend_from_slice(&[0u8; 24]);
+        packed.push(3);
+        for index in 0..3u8 {
+            packed.extend_from_slice(&[10 + index; 32]);
+            packed.extend_from_slice(&[20 + index; 32]);
+            packed.extend_from_slice(&(10 * (index as u64 + 1)).to_le_bytes());
+        }
+        packed.extend_from_slice(&[0u8; 5 * 72]);
+        let unpacked = SwapV2::unpack(&packed).unwrap();
+        assert_eq!(swap_info, unpacked);
+
+        packed[SwapV2::LEN - 8 * 72 - 1] = MAX_TOKENS_IN_MULTI_TOKEN_POOL as u8 + 1;
+        assert_eq!(
+            SwapV2::unpack(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        let packed = [0u8; SwapV2::LEN];
+        let swap_info: SwapV2 = Default::default();
+        let unpack_unchecked = SwapV2::unpack_unchecked(&packed).unwrap();
+        assert_eq!(unpack_unchecked, swap_info);
+        let err = SwapV2::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    fn test_concentrated_pool() -> ConcentratedPool {
+        let mut ticks = [Tick::default(); MAX_TICKS_IN_CONCENTRATED_POOL];
+        ticks[0] = Tick {
+            index: -120,
+            liquidity_net: 1_000,
+            liquidity_gross: 1_000,
+            fee_growth_outside_a: 11,
+            fee_growth_outside_b: 12,
+        };
+        ticks[1] = Tick {
+            index: 60,
+            liquidity_net: -1_000,
+            liquidity_gross: 1_000,
+            fee_growth_outside_a: 0,
+            fee_growth_outside_b: 0,
+        };
+        ConcentratedPool {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            token_program_id: TEST_TOKEN_PROGRAM_ID,
+            token_a: TEST_TOKEN_A,
+            token_b: TEST_TOKEN_B,
+            token_a_mint: TEST_TOKEN_A_MINT,
+            token_b_mint: TEST_TOKEN_B_MINT,
+            fees: TEST_FEES,
+            admin: SwapAdmin {
+                admin_authority: TEST_TOKEN_A_MINT,
+            },
+            pool_status: PoolStatus::WithdrawOnly,
+            tick_spacing: 60,
+            tick_lower_bound: -960,
+            min_position_liquidity: 100,
+            sqrt_price: 1 << 64,
+            tick_current: 0,
+            liquidity: 1_000,
+            fee_growth_global_a: 21,
+            fee_growth_global_b: 22,
+            tick_count: 2,
+            ticks,
+        }
+    }
+
+    #[test]
+    fn swap_version_pack_concentrated() {
+        let mut packed = [0u8; SwapVersion::CONCENTRATED_LEN];
+        SwapVersion::pack_concentrated(test_concentrated_pool(), &mut packed).unwrap();
+        assert_eq!(packed[0], 3);
+        assert!(SwapVersion::is_initialized(&packed));
+        assert_eq!(
+            SwapVersion::unpack(&packed).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapVersion::unpack_multi_token(&packed).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+        let unpacked = SwapVersion::unpack_concentrated(&packed).unwrap();
+        assert_eq!(unpacked, test_concentrated_pool());
+
+        let mut packed = [0u8; SwapVersion::LATEST_LEN];
+        packed[0] = 1;
+        assert_eq!(
+            SwapVersion::unpack_concentrated(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+        let packed = [0u8; SwapVersion::CONCENTRATED_LEN];
+        assert!(!SwapVersion::is_initialized(&packed));
+        assert_eq!(
+            SwapVersion::unpack_concentrated(&packed).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+    }
+
+    #[test]
+    fn concentrated_pool_pack() {
+        let pool = test_concentrated_pool();
+        let mut packed = [0u8; ConcentratedPool::LEN];
+        ConcentratedPool::pack_into_slice(&pool, &mut packed);
+        let unpacked = ConcentratedPool::unpack(&packed).unwrap();
+        assert_eq!(pool, unpacked);
+
+        let mut packed = vec![1u8, TEST_NONCE];
+        packed.extend_from_slice(&TEST_TOKEN_PROGRAM_ID.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_FEES.trade_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.trade_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_trade_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.owner_withdraw_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.host_fee_numerator.to_le_bytes());
+        packed.extend_from_slice(&TEST_FEES.host_fee_denominator.to_le_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A_MINT.to_bytes());
+        packed.push(2);
+        packed.extend_from_slice(&60u16.to_le_bytes());
+        packed.extend_from_slice(&(-960i32).to_le_bytes());
+        packed.extend_from_slice(&100u128.to_le_bytes());
+        packed.extend_from_slice(&(1u128 << 64).to_le_bytes());
+        packed.extend_from_slice(&0i32.to_le_bytes());
+        packed.extend_from_slice(&1_000u128.to_le_bytes());
+        packed.extend_from_slice(&21u128.to_le_bytes());
+        packed.extend_from_slice(&22u128.to_le_bytes());
+        packed.push(2);
+        packed.extend_from_slice(&(-120i32).to_le_bytes());
+        packed.extend_from_slice(&1_000i128.to_le_bytes());
+        packed.extend_from_slice(&1_000u128.to_le_bytes());
+        packed.extend_from_slice(&11u128.to_le_bytes());
+        packed.extend_from_slice(&12u128.to_le_bytes());
+        packed.extend_from_slice(&60i32.to_le_bytes());
+        packed.extend_from_slice(&(-1_000i128).to_le_bytes());
+        packed.extend_from_slice(&1_000u128.to_le_bytes());
+        packed.extend_from_slice(&[0u8; 32]);
+        packed.extend_from_slice(&[0u8; 30 * 68]);
+        let unpacked = ConcentratedPool::unpack(&packed).unwrap();
+        assert_eq!(pool, unpacked);
+
+        packed[ConcentratedPool::LEN - 32 * 68 - 1] = MAX_TICKS_IN_CONCENTRATED_POOL as u8 + 1;
+        assert_eq!(
+            ConcentratedPool::unpack(&packed).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        let packed = [0u8; ConcentratedPool::LEN];
+        let err = ConcentratedPool::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    #[test]
+    fn position_pack() {
+        let position = Position {
+            is_initialized: true,
+            pool: TEST_TOKEN_A,
+            owner: TEST_TOKEN_B,
+            tick_lower: -120,
+            tick_upper: 60,
+            liquidity: 1_000,
+            fee_growth_inside_a: 31,
+            fee_growth_inside_b: 32,
+            tokens_owed_a: 41,
+            tokens_owed_b: 42,
+        };
+        let mut packed = [0u8; Position::LEN];
+        Position::pack_into_slice(&position, &mut packed);
+        let unpacked = Position::unpack(&packed).unwrap();
+        assert_eq!(position, unpacked);
+
+        let mut packed = vec![1u8];
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&(-120i32).to_le_bytes());
+        packed.extend_from_slice(&60i32.to_le_bytes());
+        packed.extend_from_slice(&1_000u128.to_le_bytes());
+        packed.extend_from_slice(&31u128.to_le_bytes());
+        packed.extend_from_slice(&32u128.to_le_bytes());
+        packed.extend_from_slice(&41u64.to_le_bytes());
+        packed.extend_from_slice(&42u64.to_le_bytes());
+        let unpacked = Position::unpack(&packed).unwrap();
+        assert_eq!(position, unpacked);
+
+        let packed = [0u8; Position::LEN];
+        let err = Position::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    #[test]
+    fn farm_pack() {
+        let mut farm = Farm {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            swap: TEST_TOKEN_A,
+            stake_vault: TEST_TOKEN_B,
+            total_staked: 1_000,
+            last_update_slot: 12,
+            reward_count: 1,
+            ..Farm::default()
+        };
+        farm.rewards[0] = FarmReward {
+            vault: TEST_POOL_MINT,
+            reward_per_slot: 5,
+            reward_per_share: 7 << 64,
+        };
+        let mut packed = [0u8; Farm::LEN];
+        Farm::pack_into_slice(&farm, &mut packed);
+        let unpacked = Farm::unpack(&packed).unwrap();
+        assert_eq!(farm, unpacked);
+
+        let mut packed = vec![1u8, TEST_NONCE];
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&1_000u64.to_le_bytes());
+        packed.extend_from_slice(&12u64.to_le_bytes());
+        packed.push(1);
+        packed.extend_from_slice(&TEST_POOL_MINT.to_bytes());
+        packed.extend_from_slice(&5u64.to_le_bytes());
+        packed.extend_from_slice(&(7u128 << 64).to_le_bytes());
+        packed.extend_from_slice(&[0u8; 3 * 56]);
+        let unpacked = Farm::unpack(&packed).unwrap();
+        assert_eq!(farm, unpacked);
+
+        packed[1 + 1 + 32 + 32 + 8 + 8] = MAX_FARM_REWARDS as u8 + 1;
+        let err = Farm::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::InvalidAccountData);
+
+        let packed = [0u8; Farm::LEN];
+        let err = Farm::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    #[test]
+    fn farm_stake_pack() {
+        let stake = FarmStake {
+            is_initialized: true,
+            farm: TEST_TOKEN_A,
+            owner: TEST_TOKEN_B,
+            amount: 1_000,
+            reward_per_share_paid: [1, 2, 3, 4],
+            rewards_owed: [5, 6, 7, 8],
+        };
+        let mut packed = [0u8; FarmStake::LEN];
+        FarmStake::pack_into_slice(&stake, &mut packed);
+        let unpacked = FarmStake::unpack(&packed).unwrap();
+        assert_eq!(stake, unpacked);
+
+        let mut packed = vec![1u8];
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&1_000u64.to_le_bytes());
+        for paid in 1..=4u128 {
+            packed.extend_from_slice(&paid.to_le_bytes());
+        }
+        for owed in 5..=8u64 {
+            packed.extend_from_slice(&owed.to_le_bytes());
+        }
+        let unpacked = FarmStake::unpack(&packed).unwrap();
+        assert_eq!(stake, unpacked);
+
+        let packed = [0u8; FarmStake::LEN];
+        let err = FarmStake::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    #[test]
+    fn swap_version_pack_extension() {
+        let swap_info = || SwapV1 {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            token_program_id: TEST_TOKEN_PROGRAM_ID,
+            token_a: TEST_TOKEN_A,
+            token_b: TEST_TOKEN_B,
+            pool_mint: TEST_POOL_MINT,
+            token_a_mint: TEST_TOKEN_A_MINT,
+            token_b_mint: TEST_TOKEN_B_MINT,
+            pool_fee_account: TEST_POOL_FEE_ACCOUNT,
+            fees: TEST_FEES,
+            swap_curve: SwapCurve {
+                curve_type: TEST_CURVE_TYPE.try_into().unwrap(),
+                calculator: Box::new(TEST_CURVE),
+            },
+        };
+        let extension = SwapExtension {
+            price_accumulator: PriceAccumulator {
+                price_a_cumulative: 1 << 70,
+                price_b_cumulative: u128::MAX,
+                last_update_slot: 42,
+            },
+            admin: SwapAdmin {
+                admin_authority: Pubkey::new_from_array([9u8; 32]),
+            },
+            pool_status: PoolStatus::WithdrawOnly,
+            dynamic_fee: DynamicFee {
+                min_trade_fee_numerator: 1,
+                max_trade_fee_numerator: 10,
+                max_volatility: 500,
+                volatility_period: 100,
+                volatility: 42,
+                last_update_slot: 7,
+            },
+            allowlist: SwapAllowlist {
+                allowlist_authority: Pubkey::new_from_array([8u8; 32]),
+                gate_swaps: true,
+            },
+            flash_loan_fee: FlashLoanFee {
+                trade_fee_numerator: 1,
+                trade_fee_denominator: 2,
+                owner_trade_fee_numerator: 3,
+                owner_trade_fee_denominator: 4,
+            },
+        };
+
+        let mut packed = [0u8; SwapVersion::LATEST_LEN];
+        SwapVersion::pack(SwapVersion::SwapV1(swap_info()), &mut packed).unwrap();
+        assert_eq!(
+            SwapVersion::unpack_extension(&packed),
+            Ok(Some(SwapExtension::default()))
+        );
+        SwapVersion::pack_extension(extension, &mut packed).unwrap();
+        assert_eq!(SwapVersion::unpack_extension(&packed), Ok(Some(extension)));
+        assert_eq!(SwapVersion::unpack_swap_v1(&packed), Ok(swap_info()));
+        let unpacked = SwapVersion::unpack(&packed).unwrap();
+        assert_eq!(*unpacked.pool_mint(), TEST_POOL_MINT);
+
+        let mut expect = vec![];
+        expect.extend_from_slice(&(1u128 << 70).to_le_bytes());
+        expect.extend_from_slice(&u128::MAX.to_le_bytes());
+        expect.extend_from_slice(&42u64.to_le_bytes());
+        expect.extend_from_slice(&[9u8; 32]);
+        expect.push(2);
+        for value in [1u64, 10, 500, 100, 42, 7].iter() {
+            expect.extend_from_slice(&value.to_le_bytes());
+        }
+        expect.extend_from_slice(&[8u8; 32]);
+        expect.push(1);
+        for value in [1u64, 2, 3, 4].iter() {
+            expect.extend_from_slice(&value.to_le_bytes());
+        }
+        expect.extend_from_slice(&[0u8; SwapExtension::RESERVED_LEN]);
+        assert_eq!(&packed[SwapVersion::LEGACY_LEN..], &expect[..]);
+
+        // each part can be updated without touching the others
+        let admin = SwapAdmin {
+            admin_authority: Pubkey::new_from_array([7u8; 32]),
+        };
+        SwapVersion::pack_admin(admin, &mut packed).unwrap();
+        SwapVersion::pack_pool_status(PoolStatus::SwapsPaused, &mut packed).unwrap();
+        assert_eq!(SwapVersion::unpack_admin(&packed), Ok(Some(admin)));
+        assert_eq!(
+            SwapVersion::unpack_pool_status(&packed),
+            Ok(Some(PoolStatus::SwapsPaused))
+        );
+        assert_eq!(
+            SwapVersion::unpack_extension(&packed),
+            Ok(Some(SwapExtension {
+                admin,
+                pool_status: PoolStatus::SwapsPaused,
+                ..extension
+            }))
+        );
+
+        // invalid parts are rejected
+        let status_offset = SwapVersion::LEGACY_LEN + PriceAccumulator::LEN + SwapAdmin::LEN;
+        packed[status_offset] = 3;
+        assert_eq!(
+            SwapVersion::unpack_pool_status(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+        packed[status_offset] = 0;
+        let gate_swaps_offset = status_offset + PoolStatus::LEN + DynamicFee::LEN + 32;
+        packed[gate_swaps_offset] = 2;
+        assert_eq!(
+            SwapVersion::unpack_allowlist(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // swaps created before the extension still work, without it
+        let mut packed = [0u8; SwapVersion::LEGACY_LEN];
+        SwapVersion::pack(SwapVersion::SwapV1(swap_info()), &mut packed).unwrap();
+        assert!(SwapVersion::is_initialized(&packed));
+        assert_eq!(SwapVersion::unpack_swap_v1(&packed), Ok(swap_info()));
+        assert_eq!(SwapVersion::unpack_extension(&packed), Ok(None));
+        assert_eq!(SwapVersion::unpack_price_accumulator(&packed), Ok(None));
+        assert_eq!(SwapVersion::unpack_admin(&packed), Ok(None));
+        assert_eq!(SwapVersion::unpack_pool_status(&packed), Ok(None));
+        assert_eq!(SwapVersion::unpack_dynamic_fee(&packed), Ok(None));
+        assert_eq!(SwapVersion::unpack_allowlist(&packed), Ok(None));
+        assert_eq!(SwapVersion::unpack_flash_loan_fee(&packed), Ok(None));
+        assert_eq!(
+            SwapVersion::pack_extension(extension, &mut packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapVersion::pack_admin(admin, &mut packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // any other length is not a two-token swap
+        let mut packed = [0u8; SwapVersion::LATEST_LEN - 1];
+        SwapVersion::pack(SwapVersion::SwapV1(swap_info()), &mut packed).unwrap();
+        assert_eq!(
+            SwapVersion::unpack_extension(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+        let packed = [0u8; SwapVersion::MULTI_TOKEN_LEN];
+        assert_eq!(
+            SwapVersion::unpack_extension(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            SwapVersion::unpack_swap_v1(&packed),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn pool_status_permissions() {
+        assert!(PoolStatus::Active.allows_swaps());
+        assert!(PoolStatus::Active.allows_deposits());
+        assert!(PoolStatus::Active.allows_orders());
+        assert!(!PoolStatus::SwapsPaused.allows_swaps());
+        assert!(!PoolStatus::SwapsPaused.allows_deposits());
+        assert!(PoolStatus::SwapsPaused.allows_orders());
+        assert!(!PoolStatus::WithdrawOnly.allows_swaps());
+        assert!(!PoolStatus::WithdrawOnly.allows_deposits());
+        assert!(!PoolStatus::WithdrawOnly.allows_orders());
+    }
+
+    #[test]
+    fn allowlist_enabled() {
+        assert!(!SwapAllowlist::default().is_enabled());
+        let allowlist = SwapAllowlist {
+            allowlist_authority: Pubkey::new_from_array([9u8; 32]),
+            gate_swaps: false,
+        };
+        assert!(allowlist.is_enabled());
+    }
+
+    #[test]
+    fn flash_loan_fee_from_trade_fees() {
+        let flash_loan_fee = FlashLoanFee {
+            trade_fee_numerator: 1,
+            trade_fee_denominator: 2,
+            owner_trade_fee_numerator: 3,
+            owner_trade_fee_denominator: 4,
+        };
+        let fees = flash_loan_fee.fees();
+        assert_eq!(fees.trade_fee_numerator, 1);
+        assert_eq!(fees.owner_trade_fee_denominator, 4);
+        assert_eq!(fees.host_fee_numerator, 0);
+        assert_eq!(fees.owner_withdraw_fee_numerator, 0);
+        assert_eq!(
+            FlashLoanFee::from_trade_fees(&TEST_FEES),
+            FlashLoanFee {
+                trade_fee_numerator: TEST_FEES.trade_fee_numerator,
+                trade_fee_denominator: TEST_FEES.trade_fee_denominator,
+                owner_trade_fee_numerator: TEST_FEES.owner_trade_fee_numerator,
+                owner_trade_fee_denominator: TEST_FEES.owner_trade_fee_denominator,
+            }
+        );
+    }
+
+    #[test]
+    fn allowlist_member_pack() {
+        let member = AllowlistMember {
+            is_initialized: true,
+            swap: TEST_TOKEN_A,
+            member: TEST_TOKEN_B,
+        };
+        let mut packed = [0u8; AllowlistMember::LEN];
+        AllowlistMember::pack_into_slice(&member, &mut packed);
+        let unpacked = AllowlistMember::unpack(&packed).unwrap();
+        assert_eq!(member, unpacked);
+
+        let mut packed = vec![1u8];
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        let unpacked = AllowlistMember::unpack(&packed).unwrap();
+        assert_eq!(member, unpacked);
+
+        let packed = [0u8; AllowlistMember::LEN];
+        let err = AllowlistMember::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    #[test]
+    fn limit_order_pack() {
+        let order = LimitOrder {
+            is_initialized: true,
+            nonce: TEST_NONCE,
+            swap: TEST_TOKEN_A,
+            owner: TEST_TOKEN_B,
+            escrow: TEST_TOKEN_A_MINT,
+            destination: TEST_TOKEN_B_MINT,
+            trade_direction: TradeDirection::BtoA,
+            kind: LimitOrderKind::StopLoss,
+            amount_in: 1_000,
+            trigger_amount_out: 900,
+            minimum_amount_out: 800,
+        };
+        let mut packed = [0u8; LimitOrder::LEN];
+        LimitOrder::pack_into_slice(&order, &mut packed);
+        let unpacked = LimitOrder::unpack(&packed).unwrap();
+        assert_eq!(order, unpacked);
+
+        let mut packed = vec![1u8, TEST_NONCE];
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B_MINT.to_bytes());
+        packed.push(1);
+        packed.push(1);
+        packed.extend_from_slice(&1_000u64.to_le_bytes());
+        packed.extend_from_slice(&900u64.to_le_bytes());
+        packed.extend_from_slice(&800u64.to_le_bytes());
+        let unpacked = LimitOrder::unpack(&packed).unwrap();
+        assert_eq!(order, unpacked);
+
+        packed[130] = 2;
+        let err = LimitOrder::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::InvalidAccountData);
+
+        let packed = [0u8; LimitOrder::LEN];
+        let err = LimitOrder::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+    }
+
+    #[test]
+    fn fee_split_pack() {
+        let mut recipients = [FeeRecipient::default(); MAX_FEE_RECIPIENTS];
+        recipients[0] = FeeRecipient {
+            token_a: TEST_TOKEN_A,
+            token_b: TEST_TOKEN_B,
+            share: 7_000,
+        };
+        recipients[1] = FeeRecipient {
+            token_a: TEST_TOKEN_A_MINT,
+            token_b: TEST_TOKEN_B_MINT,
+            share: 3_000,
+        };
+        let split = FeeSplit {
+            is_initialized: true,
+            swap: TEST_POOL_MINT,
+            recipient_count: 2,
+            recipients,
+        };
+        let mut packed = [0u8; FeeSplit::LEN];
+        FeeSplit::pack_into_slice(&split, &mut packed);
+        let unpacked = FeeSplit::unpack(&packed).unwrap();
+        assert_eq!(split, unpacked);
+        assert_eq!(unpacked.active_recipients(), &recipients[..2]);
+
+        let mut packed = vec![1u8];
+        packed.extend_from_slice(&TEST_POOL_MINT.to_bytes());
+        packed.push(2);
+        packed.extend_from_slice(&TEST_TOKEN_A.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B.to_bytes());
+        packed.extend_from_slice(&7_000u16.to_le_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_A_MINT.to_bytes());
+        packed.extend_from_slice(&TEST_TOKEN_B_MINT.to_bytes());
+        packed.extend_from_slice(&3_000u16.to_le_bytes());
+        packed.extend_from_slice(&[0u8; 2 * FeeRecipient::LEN]);
+        let unpacked = FeeSplit::unpack(&packed).unwrap();
+        assert_eq!(split, unpacked);
+
+        packed[33] = MAX_FEE_RECIPIENTS as u8 + 1;
+        let err = FeeSplit::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::InvalidAccountData);
+
+        let packed = [0u8; FeeSplit::LEN];
+        let err = FeeSplit::unpack(&packed).unwrap_err();
+        assert_eq!(err, ProgramError::UninitializedAccount);
+
+        assert_eq!(recipients[0].share_of(1_001), Some(700));
+        assert_eq!(
+            recipients[1].share_of(u64::MAX),
+            Some(5_534_023_222_112_865_484)
+        );
+    }
+
+    #[test]
+    fn limit_order_resize() {
+        let mut order = LimitOrder {
+            amount_in: 1_000,
+            trigger_amount_out: 900,
+            minimum_amount_out: 801,
+            ..LimitOrder::default()
+        };
+        order.resize(500).unwrap();
+        assert_eq!(order.amount_in, 500);
+        assert_eq!(order.trigger_amount_out, 450);
+        assert_eq!(order.minimum_amount_out, 401);
+
+        let mut empty = LimitOrder::default();
+        assert_eq!(empty.resize(500), None);
     }
 }
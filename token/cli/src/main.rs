@@ -3,10 +3,7 @@ use clap::{
     ArgMatches, SubCommand,
 };
 use console::Emoji;
-use solana_account_decoder::{
-    parse_token::{TokenAccountType, UiAccountState},
-    UiAccountData,
-};
+use solana_account_decoder::parse_token::{token_amount_to_ui_amount, UiAccountState};
 use solana_clap_utils::{
     fee_payer::fee_payer_arg,
     input_parsers::{pubkey_of_signer, pubkeys_of_multiple_signers, signer_of, value_of},
@@ -26,28 +23,27 @@ use solana_client::{
 use solana_remote_wallet::remote_wallet::RemoteWalletManager;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
-    instruction::Instruction,
     message::Message,
     native_token::*,
-    program_option::COption,
     program_pack::Pack,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
-    system_instruction, system_program,
     transaction::Transaction,
 };
 use spl_associated_token_account::*;
-use spl_token::{
-    self,
-    instruction::*,
-    native_mint,
-    state::{Account, Mint, Multisig},
+use spl_token::{self, instruction::*, native_mint, state::Multisig};
+use spl_token_client::{
+    token::authority_name, ProgramRpcClient, TokenClient, TokenClientError, TokenOperation,
 };
-use std::{collections::HashMap, process::exit, str::FromStr, sync::Arc};
+use std::{future::Future, process::exit, str::FromStr, sync::Arc};
+use tokio::runtime::{self, Runtime};
 
 mod sort;
 use sort::sort_and_parse_token_accounts;
 
+mod watch;
+use watch::{command_watch, WatchOutput};
+
 static WARNING: Emoji = Emoji("⚠️", "!");
 
 pub const MINT_ADDRESS_ARG: ArgConstant<'static> = ArgConstant {
@@ -145,8 +141,11 @@ fn is_multisig_minimum_signers(string: String) -> Result<(), String> {
     }
 }
 
-struct Config<'a> {
-    rpc_client: RpcClient,
+struct Config {
+    rpc_client: Arc<RpcClient>,
+    token_client: TokenClient<ProgramRpcClient>,
+    runtime: Runtime,
+    websocket_url: String,
     verbose: bool,
     owner: Pubkey,
     fee_payer: Pubkey,
@@ -155,11 +154,17 @@ struct Config<'a> {
     nonce_authority: Option<Pubkey>,
     blockhash_query: BlockhashQuery,
     sign_only: bool,
-    multisigner_pubkeys: Vec<&'a Pubkey>,
+}
+
+impl Config {
+    /// Run a token client request to completion
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
 }
 
 type Error = Box<dyn std::error::Error>;
-type CommandResult = Result<Option<(u64, Vec<Vec<Instruction>>)>, Error>;
+type CommandResult = Result<Option<TokenOperation>, Error>;
 
 fn new_throwaway_signer() -> (Option<Box<dyn Signer>>, Option<Pubkey>) {
     let keypair = Keypair::new();
@@ -167,34 +172,16 @@ fn new_throwaway_signer() -> (Option<Box<dyn Signer>>, Option<Pubkey>) {
     (Some(Box::new(keypair) as Box<dyn Signer>), Some(pubkey))
 }
 
-fn check_fee_payer_balance(config: &Config, required_balance: u64) -> Result<(), Error> {
-    let balance = config.rpc_client.get_balance(&config.fee_payer)?;
-    if balance < required_balance {
-        Err(format!(
-            "Fee payer, {}, has insufficient balance: {} required, {} available",
-            config.fee_payer,
-            lamports_to_sol(required_balance),
-            lamports_to_sol(balance)
-        )
-        .into())
-    } else {
-        Ok(())
-    }
-}
-
-fn check_owner_balance(config: &Config, required_balance: u64) -> Result<(), Error> {
-    let balance = config.rpc_client.get_balance(&config.owner)?;
-    if balance < required_balance {
-        Err(format!(
-            "Owner, {}, has insufficient balance: {} required, {} available",
-            config.owner,
-            lamports_to_sol(required_balance),
-            lamports_to_sol(balance)
-        )
-        .into())
-    } else {
-        Ok(())
-    }
+/// Point the user at the flag that resolves a token client error, if there is one
+fn cli_error(err: TokenClientError) -> Error {
+    let hint = match err {
+        TokenClientError::EmptyRecipient(_) => {
+            "Add `--allow-empty-recipient` to complete the transfer"
+        }
+        TokenClientError::RecipientNotFunded(_) => "Add `--fund-recipient` to fund their account",
+        _ => return err.into(),
+    };
+    format!("Error: {}. {}", err, hint).into()
 }
 
 type SignersOf = Vec<(Box<dyn Signer>, Pubkey)>;
@@ -217,47 +204,49 @@ pub fn signers_of(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn command_create_token(
     config: &Config,
     decimals: u8,
     token: Pubkey,
-    enable_freeze: bool,
+    mint_authority: Pubkey,
+    freeze_authority: Option<Pubkey>,
+    initial_supply: Option<(f64, Pubkey)>,
+    disable_mint: bool,
 ) -> CommandResult {
     println!("Creating token {}", token);
-
-    let minimum_balance_for_rent_exemption = if !config.sign_only {
-        config
-            .rpc_client
-            .get_minimum_balance_for_rent_exemption(Mint::LEN)?
-    } else {
-        0
-    };
-    let freeze_authority_pubkey = if enable_freeze {
-        Some(config.owner)
+    if let Some((ui_amount, recipient)) = initial_supply {
+        println!(
+            "  Minting {} tokens\n  Recipient: {}\n  Recipient associated token account: {}",
+            ui_amount,
+            recipient,
+            get_associated_token_address(&recipient, &token)
+        );
+    }
+    if disable_mint {
+        println!("  Mint authority: disabled");
     } else {
-        None
-    };
+        println!("  Mint authority: {}", mint_authority);
+    }
+    if let Some(freeze_authority) = freeze_authority {
+        println!("  Freeze authority: {}", freeze_authority);
+    }
 
-    let instructions = vec![
-        system_instruction::create_account(
-            &config.fee_payer,
-            &token,
-            minimum_balance_for_rent_exemption,
-            Mint::LEN as u64,
-            &spl_token::id(),
-        ),
-        initialize_mint(
-            &spl_token::id(),
-            &token,
-            &config.owner,
-            freeze_authority_pubkey.as_ref(),
-            decimals,
-        )?,
-    ];
-    Ok(Some((
-        minimum_balance_for_rent_exemption,
-        vec![instructions],
-    )))
+    let initial_supply = initial_supply.map(|(ui_amount, recipient)| {
+        (
+            spl_token::ui_amount_to_amount(ui_amount, decimals),
+            recipient,
+        )
+    });
+    let operation = config.block_on(config.token_client.create_token(
+        &token,
+        decimals,
+        &mint_authority,
+        freeze_authority.as_ref(),
+        initial_supply,
+        disable_mint,
+    ))?;
+    Ok(Some(operation))
 }
 
 fn command_create_account(
@@ -265,58 +254,13 @@ fn command_create_account(
     token: Pubkey,
     maybe_account: Option<Pubkey>,
 ) -> CommandResult {
-    let minimum_balance_for_rent_exemption = if !config.sign_only {
+    let (account, operation) = config.block_on(
         config
-            .rpc_client
-            .get_minimum_balance_for_rent_exemption(Account::LEN)?
-    } else {
-        0
-    };
-
-    let (account, system_account_ok, instructions) = if let Some(account) = maybe_account {
-        println!("Creating account {}", account);
-        (
-            account,
-            false,
-            vec![
-                system_instruction::create_account(
-                    &config.fee_payer,
-                    &account,
-                    minimum_balance_for_rent_exemption,
-                    Account::LEN as u64,
-                    &spl_token::id(),
-                ),
-                initialize_account(&spl_token::id(), &account, &token, &config.owner)?,
-            ],
-        )
-    } else {
-        let account = get_associated_token_address(&config.owner, &token);
-        println!("Creating account {}", account);
-        (
-            account,
-            true,
-            vec![create_associated_token_account(
-                &config.fee_payer,
-                &config.owner,
-                &token,
-            )],
-        )
-    };
-
-    if let Some(account_data) = config
-        .rpc_client
-        .get_account_with_commitment(&account, config.rpc_client.commitment())?
-        .value
-    {
-        if !(account_data.owner == system_program::id() && system_account_ok) {
-            return Err(format!("Error: Account already exists: {}", account).into());
-        }
-    }
-
-    Ok(Some((
-        minimum_balance_for_rent_exemption,
-        vec![instructions],
-    )))
+            .token_client
+            .create_account(&token, maybe_account.as_ref()),
+    )?;
+    println!("Creating account {}", account);
+    Ok(Some(operation))
 }
 
 fn command_create_multisig(
@@ -332,33 +276,12 @@ fn command_create_multisig(
         multisig
     );
 
-    let minimum_balance_for_rent_exemption = if !config.sign_only {
-        config
-            .rpc_client
-            .get_minimum_balance_for_rent_exemption(Multisig::LEN)?
-    } else {
-        0
-    };
-
-    let instructions = vec![
-        system_instruction::create_account(
-            &config.fee_payer,
-            &multisig,
-            minimum_balance_for_rent_exemption,
-            Multisig::LEN as u64,
-            &spl_token::id(),
-        ),
-        initialize_multisig(
-            &spl_token::id(),
-            &multisig,
-            multisig_members.iter().collect::<Vec<_>>().as_slice(),
-            minimum_signers,
-        )?,
-    ];
-    Ok(Some((
-        minimum_balance_for_rent_exemption,
-        vec![instructions],
-    )))
+    let operation = config.block_on(config.token_client.create_multisig(
+        &multisig,
+        minimum_signers,
+        &multisig_members,
+    ))?;
+    Ok(Some(operation))
 }
 
 fn command_authorize(
@@ -368,58 +291,13 @@ fn command_authorize(
     new_owner: Option<Pubkey>,
     force_authorize: bool,
 ) -> CommandResult {
-    let auth_str = match authority_type {
-        AuthorityType::MintTokens => "mint authority",
-        AuthorityType::FreezeAccount => "freeze authority",
-        AuthorityType::AccountOwner => "owner",
-        AuthorityType::CloseAccount => "close authority",
-    };
-    let target_account = config.rpc_client.get_account(&account)?;
-    let previous_authority = if let Ok(mint) = Mint::unpack(&target_account.data) {
-        match authority_type {
-            AuthorityType::AccountOwner | AuthorityType::CloseAccount => Err(format!(
-                "Authority type `{}` not supported for SPL Token mints",
-                auth_str
-            )),
-            AuthorityType::MintTokens => Ok(mint.mint_authority),
-            AuthorityType::FreezeAccount => Ok(mint.freeze_authority),
-        }
-    } else if let Ok(token_account) = Account::unpack(&target_account.data) {
-        let check_associated_token_account = || -> Result<(), Error> {
-            let maybe_associated_token_account =
-                get_associated_token_address(&config.owner, &token_account.mint);
-            if account == maybe_associated_token_account
-                && !force_authorize
-                && Some(config.owner) != new_owner
-            {
-                Err(
-                    format!("Error: attempting to change the `{}` of an associated token account of `--owner`", auth_str)
-                        .into(),
-                )
-            } else {
-                Ok(())
-            }
-        };
-
-        match authority_type {
-            AuthorityType::MintTokens | AuthorityType::FreezeAccount => Err(format!(
-                "Authority type `{}` not supported for SPL Token accounts",
-                auth_str
-            )),
-            AuthorityType::AccountOwner => {
-                check_associated_token_account()?;
-                Ok(COption::Some(token_account.owner))
-            }
-            AuthorityType::CloseAccount => {
-                check_associated_token_account()?;
-                Ok(COption::Some(
-                    token_account.close_authority.unwrap_or(token_account.owner),
-                ))
-            }
-        }
-    } else {
-        Err("Unsupported account data format".to_string())
-    }?;
+    let auth_str = authority_name(&authority_type);
+    let (previous_authority, operation) = config.block_on(config.token_client.authorize(
+        &account,
+        authority_type,
+        new_owner.as_ref(),
+        force_authorize,
+    ))?;
     println!(
         "Updating {}\n  Current {}: {}\n  New {}: {}",
         account,
@@ -432,16 +310,7 @@ fn command_authorize(
             .map(|pubkey| pubkey.to_string())
             .unwrap_or_else(|| "disabled".to_string())
     );
-
-    let instructions = vec![set_authority(
-        &spl_token::id(),
-        &account,
-        new_owner.as_ref(),
-        authority_type,
-        &config.owner,
-        &config.multisigner_pubkeys,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    Ok(Some(operation))
 }
 
 fn resolve_mint_info(
@@ -450,21 +319,11 @@ fn resolve_mint_info(
     mint_address: Option<Pubkey>,
     mint_decimals: Option<u8>,
 ) -> Result<(Pubkey, u8), Error> {
-    if !config.sign_only {
-        let source_account = config
-            .rpc_client
-            .get_token_account(&token_account)?
-            .ok_or_else(|| format!("Could not find token account {}", token_account))?;
-        Ok((
-            Pubkey::from_str(&source_account.mint)?,
-            source_account.token_amount.decimals,
-        ))
-    } else {
-        Ok((
-            mint_address.unwrap_or_default(),
-            mint_decimals.unwrap_or_default(),
-        ))
-    }
+    Ok(config.block_on(config.token_client.resolve_mint_info(
+        token_account,
+        mint_address,
+        mint_decimals,
+    ))?)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -479,120 +338,40 @@ fn command_transfer(
     mint_decimals: Option<u8>,
 ) -> CommandResult {
     let (mint_pubkey, decimals) = resolve_mint_info(config, &sender, mint_address, mint_decimals)?;
-    let sender_token_amount = config
-        .rpc_client
-        .get_token_account_balance(&sender)
-        .map_err(|err| {
-            format!(
-                "Error: Failed to get token balance of sender address {}: {}",
-                sender, err
-            )
-        })?;
-    let sender_balance = sender_token_amount.amount.parse::<u64>().map_err(|err| {
-        format!(
-            "Token account {} balance could not be parsed: {}",
-            sender, err
-        )
-    })?;
-    let transfer_balance = ui_amount
-        .map(|ui_amount| spl_token::ui_amount_to_amount(ui_amount, decimals))
-        .unwrap_or(sender_balance);
+    let amount = ui_amount.map(|ui_amount| spl_token::ui_amount_to_amount(ui_amount, decimals));
+
+    let transfer = config
+        .block_on(config.token_client.transfer(
+            &sender,
+            &mint_pubkey,
+            decimals,
+            amount,
+            &recipient,
+            allow_empty_recipient,
+            fund_recipient,
+        ))
+        .map_err(cli_error)?;
 
     println!(
         "Transfer {} tokens\n  Sender: {}\n  Recipient: {}",
-        spl_token::amount_to_ui_amount(transfer_balance, decimals),
+        spl_token::amount_to_ui_amount(transfer.amount, decimals),
         sender,
         recipient
     );
-
-    if transfer_balance > sender_balance {
-        return Err(format!(
-            "Error: Sender has insufficient funds, current balance is {}",
-            sender_token_amount.real_number_string_trimmed()
-        )
-        .into());
-    }
-
-    let mut instructions = vec![];
-
-    let mut recipient_token_account = recipient;
-    let mut minimum_balance_for_rent_exemption = 0;
-
-    let recipient_account_owner = config
-        .rpc_client
-        .get_account_with_commitment(&recipient, config.rpc_client.commitment())?
-        .value
-        .map(|account_data| account_data.owner);
-
-    if recipient_account_owner.is_none() && !allow_empty_recipient {
-        return Err("Error: The recipient has no balance. \
-                            Add `--allow-empty-recipient` to complete the transfer \
-                           "
-        .into());
-    }
-
-    if Some(spl_token::id()) != recipient_account_owner {
-        recipient_token_account = get_associated_token_address(&recipient, &mint_pubkey);
+    if transfer.is_associated {
         println!(
             "  Recipient associated token account: {}",
-            recipient_token_account
+            transfer.recipient_token_account
         );
-
-        let needs_funding = if let Some(recipient_token_account_data) = config
-            .rpc_client
-            .get_account_with_commitment(&recipient_token_account, config.rpc_client.commitment())?
-            .value
-        {
-            if recipient_token_account_data.owner == system_program::id() {
-                true
-            } else if recipient_token_account_data.owner == spl_token::id() {
-                false
-            } else {
-                return Err(format!("Error: Unsupported recipient address: {}", recipient).into());
-            }
-        } else {
-            true
-        };
-
-        if needs_funding {
-            if fund_recipient {
-                minimum_balance_for_rent_exemption += config
-                    .rpc_client
-                    .get_minimum_balance_for_rent_exemption(Account::LEN)?;
-                println!(
-                    "  Funding recipient: {} ({} SOL)",
-                    recipient_token_account,
-                    lamports_to_sol(minimum_balance_for_rent_exemption)
-                );
-                instructions.push(create_associated_token_account(
-                    &config.fee_payer,
-                    &recipient,
-                    &mint_pubkey,
-                ));
-            } else {
-                return Err(
-                    "Error: Recipient's associated token account does not exist. \
-                                    Add `--fund-recipient` to fund their account"
-                        .into(),
-                );
-            }
-        }
     }
-
-    instructions.push(transfer_checked(
-        &spl_token::id(),
-        &sender,
-        &mint_pubkey,
-        &recipient_token_account,
-        &config.owner,
-        &config.multisigner_pubkeys,
-        transfer_balance,
-        decimals,
-    )?);
-    Ok(Some((
-        minimum_balance_for_rent_exemption,
-        vec![instructions],
-    )))
+    if transfer.funds_recipient {
+        println!(
+            "  Funding recipient: {} ({} SOL)",
+            transfer.recipient_token_account,
+            lamports_to_sol(transfer.operation.lamports)
+        );
+    }
+    Ok(Some(transfer.operation))
 }
 
 fn command_burn(
@@ -607,16 +386,13 @@ fn command_burn(
     let (mint_pubkey, decimals) = resolve_mint_info(config, &source, mint_address, mint_decimals)?;
     let amount = spl_token::ui_amount_to_amount(ui_amount, decimals);
 
-    let instructions = vec![burn_checked(
-        &spl_token::id(),
+    let operation = config.block_on(config.token_client.burn(
         &source,
         &mint_pubkey,
-        &config.owner,
-        &config.multisigner_pubkeys,
         amount,
         decimals,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    ))?;
+    Ok(Some(operation))
 }
 
 fn command_mint(
@@ -634,16 +410,12 @@ fn command_mint(
     let (_, decimals) = resolve_mint_info(config, &recipient, None, mint_decimals)?;
     let amount = spl_token::ui_amount_to_amount(ui_amount, decimals);
 
-    let instructions = vec![mint_to_checked(
-        &spl_token::id(),
-        &token,
-        &recipient,
-        &config.owner,
-        &config.multisigner_pubkeys,
-        amount,
-        decimals,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    let operation = config.block_on(
+        config
+            .token_client
+            .mint_to(&token, &recipient, amount, decimals),
+    )?;
+    Ok(Some(operation))
 }
 
 fn command_freeze(config: &Config, account: Pubkey, mint_address: Option<Pubkey>) -> CommandResult {
@@ -651,14 +423,8 @@ fn command_freeze(config: &Config, account: Pubkey, mint_address: Option<Pubkey>
 
     println!("Freezing account: {}\n  Token: {}", account, token);
 
-    let instructions = vec![freeze_account(
-        &spl_token::id(),
-        &account,
-        &token,
-        &config.owner,
-        &config.multisigner_pubkeys,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    let operation = config.block_on(config.token_client.freeze(&account, &token))?;
+    Ok(Some(operation))
 }
 
 fn command_thaw(config: &Config, account: Pubkey, mint_address: Option<Pubkey>) -> CommandResult {
@@ -666,39 +432,16 @@ fn command_thaw(config: &Config, account: Pubkey, mint_address: Option<Pubkey>)
 
     println!("Freezing account: {}\n  Token: {}", account, token);
 
-    let instructions = vec![thaw_account(
-        &spl_token::id(),
-        &account,
-        &token,
-        &config.owner,
-        &config.multisigner_pubkeys,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    let operation = config.block_on(config.token_client.thaw(&account, &token))?;
+    Ok(Some(operation))
 }
 
 fn command_wrap(config: &Config, sol: f64, account: Pubkey) -> CommandResult {
     let lamports = sol_to_lamports(sol);
     println!("Wrapping {} SOL into {}", sol, account);
 
-    let instructions = vec![
-        system_instruction::create_account(
-            &config.owner,
-            &account,
-            lamports,
-            Account::LEN as u64,
-            &spl_token::id(),
-        ),
-        initialize_account(
-            &spl_token::id(),
-            &account,
-            &native_mint::id(),
-            &config.owner,
-        )?,
-    ];
-    if !config.sign_only {
-        check_owner_balance(config, lamports)?;
-    }
-    Ok(Some((0, vec![instructions])))
+    let operation = config.block_on(config.token_client.wrap(&account, lamports))?;
+    Ok(Some(operation))
 }
 
 fn command_unwrap(config: &Config, address: Pubkey) -> CommandResult {
@@ -711,14 +454,8 @@ fn command_unwrap(config: &Config, address: Pubkey) -> CommandResult {
     }
     println!("  Recipient: {}", &config.owner);
 
-    let instructions = vec![close_account(
-        &spl_token::id(),
-        &address,
-        &config.owner,
-        &config.owner,
-        &config.multisigner_pubkeys,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    let operation = config.block_on(config.token_client.unwrap(&address))?;
+    Ok(Some(operation))
 }
 
 fn command_approve(
@@ -737,88 +474,77 @@ fn command_approve(
     let (mint_pubkey, decimals) = resolve_mint_info(config, &account, mint_address, mint_decimals)?;
     let amount = spl_token::ui_amount_to_amount(ui_amount, decimals);
 
-    let instructions = vec![approve_checked(
-        &spl_token::id(),
+    let operation = config.block_on(config.token_client.approve(
         &account,
         &mint_pubkey,
         &delegate,
-        &config.owner,
-        &config.multisigner_pubkeys,
         amount,
         decimals,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    ))?;
+    Ok(Some(operation))
 }
 
 fn command_revoke(config: &Config, account: Pubkey, delegate: Option<Pubkey>) -> CommandResult {
-    let delegate = if !config.sign_only {
-        let source_account = config
-            .rpc_client
-            .get_token_account(&account)?
-            .ok_or_else(|| format!("Could not find token account {}", account))?;
-
-        if let Some(string) = source_account.delegate {
-            Some(Pubkey::from_str(&string)?)
-        } else {
-            None
-        }
-    } else {
-        delegate
-    };
+    let (delegate, operation) = config.block_on(config.token_client.revoke(&account, delegate))?;
+    println!(
+        "Revoking approval\n  Account: {}\n  Delegate: {}",
+        account, delegate
+    );
+    Ok(Some(operation))
+}
+
+fn command_allowances(config: &Config, delegate: Pubkey, token: Option<Pubkey>) -> CommandResult {
+    let allowances = config.block_on(config.token_client.allowances(&delegate, token.as_ref()))?;
+    if allowances.is_empty() {
+        println!("None");
+        return Ok(None);
+    }
 
-    if let Some(delegate) = delegate {
+    println!(
+        "{:<44}  {:<44}  {:<44}  Allowance",
+        "Account", "Token", "Owner"
+    );
+    println!("{}", "-".repeat(150));
+    for allowance in allowances {
         println!(
-            "Revoking approval\n  Account: {}\n  Delegate: {}",
-            account, delegate
+            "{:<44}  {:<44}  {:<44}  {}",
+            allowance.address,
+            allowance.mint,
+            allowance.owner,
+            token_amount_to_ui_amount(allowance.delegated_amount, allowance.decimals)
+                .real_number_string_trimmed()
         );
-    } else {
-        return Err(format!("No delegate on account {}", account).into());
     }
-
-    let instructions = vec![revoke(
-        &spl_token::id(),
-        &account,
-        &config.owner,
-        &config.multisigner_pubkeys,
-    )?];
-    Ok(Some((0, vec![instructions])))
+    Ok(None)
 }
 
-fn command_close(config: &Config, account: Pubkey, destination: Pubkey) -> CommandResult {
-    if !config.sign_only {
-        let source_account = config
-            .rpc_client
-            .get_token_account(&account)?
-            .ok_or_else(|| format!("Could not find token account {}", account))?;
-        let source_amount = source_account
-            .token_amount
-            .amount
-            .parse::<u64>()
-            .map_err(|err| {
-                format!(
-                    "Token account {} balance could not be parsed: {}",
-                    account, err
-                )
-            })?;
+fn command_revoke_all(
+    config: &Config,
+    delegate: Option<Pubkey>,
+    token: Option<Pubkey>,
+) -> CommandResult {
+    let (revoked, operation) = config.block_on(
+        config
+            .token_client
+            .revoke_all(delegate.as_ref(), token.as_ref()),
+    )?;
+    if revoked.is_empty() {
+        println!("Nothing to do");
+        return Ok(None);
+    }
 
-        if !source_account.is_native && source_amount > 0 {
-            return Err(format!(
-                "Account {} still has {} tokens; empty the account in order to close it.",
-                account,
-                source_account.token_amount.real_number_string_trimmed()
-            )
-            .into());
-        }
+    for (account, delegate) in revoked {
+        println!(
+            "Revoking approval\n  Account: {}\n  Delegate: {}",
+            account, delegate
+        );
     }
+    Ok(Some(operation))
+}
 
-    let instructions = vec![close_account(
-        &spl_token::id(),
-        &account,
-        &destination,
-        &config.owner,
-        &config.multisigner_pubkeys,
-    )?];
-    Ok(Some((0, vec![instructions])))
+fn command_close(config: &Config, account: Pubkey, destination: Pubkey) -> CommandResult {
+    let operation = config.block_on(config.token_client.close(&account, &destination))?;
+    Ok(Some(operation))
 }
 
 fn command_balance(config: &Config, address: Pubkey) -> CommandResult {
@@ -964,130 +690,16 @@ fn command_accounts(config: &Config, token: Option<Pubkey>) -> CommandResult {
 
 fn command_gc(config: &Config) -> CommandResult {
     println!("Fetching token accounts");
-    let accounts = config.rpc_client.get_token_accounts_by_owner(
-        &config.owner,
-        TokenAccountsFilter::ProgramId(spl_token::id()),
-    )?;
-    if accounts.is_empty() {
+    let (tokens, operation) = config.block_on(config.token_client.gc())?;
+    if tokens.is_empty() {
         println!("Nothing to do");
         return Ok(None);
     }
 
-    let minimum_balance_for_rent_exemption = if !config.sign_only {
-        config
-            .rpc_client
-            .get_minimum_balance_for_rent_exemption(Account::LEN)?
-    } else {
-        0
-    };
-
-    let mut accounts_by_token = HashMap::new();
-
-    for keyed_account in accounts {
-        if let UiAccountData::Json(parsed_account) = keyed_account.account.data {
-            if parsed_account.program == "spl-token" {
-                if let Ok(TokenAccountType::Account(ui_token_account)) =
-                    serde_json::from_value(parsed_account.parsed)
-                {
-                    let frozen = ui_token_account.state == UiAccountState::Frozen;
-
-                    let token = ui_token_account
-                        .mint
-                        .parse::<Pubkey>()
-                        .unwrap_or_else(|err| panic!("Invalid mint: {}", err));
-                    let token_account = keyed_account
-                        .pubkey
-                        .parse::<Pubkey>()
-                        .unwrap_or_else(|err| panic!("Invalid token account: {}", err));
-                    let token_amount = ui_token_account
-                        .token_amount
-                        .amount
-                        .parse::<u64>()
-                        .unwrap_or_else(|err| panic!("Invalid token amount: {}", err));
-
-                    let close_authority =
-                        ui_token_account.close_authority.map_or(config.owner, |s| {
-                            s.parse::<Pubkey>()
-                                .unwrap_or_else(|err| panic!("Invalid close authority: {}", err))
-                        });
-
-                    let entry = accounts_by_token.entry(token).or_insert_with(HashMap::new);
-                    entry.insert(
-                        token_account,
-                        (
-                            token_amount,
-                            ui_token_account.token_amount.decimals,
-                            frozen,
-                            close_authority,
-                        ),
-                    );
-                }
-            }
-        }
-    }
-
-    let mut instructions = vec![];
-    let mut lamports_needed = 0;
-
-    for (token, accounts) in accounts_by_token.into_iter() {
+    for token in tokens {
         println!("Processing token: {}", token);
-        let associated_token_account = get_associated_token_address(&config.owner, &token);
-        let total_balance: u64 = accounts.values().map(|account| account.0).sum();
-
-        if total_balance > 0 && !accounts.contains_key(&associated_token_account) {
-            // Create the associated token account
-            instructions.push(vec![create_associated_token_account(
-                &config.fee_payer,
-                &config.owner,
-                &token,
-            )]);
-            lamports_needed += minimum_balance_for_rent_exemption;
-        }
-
-        for (address, (amount, decimals, frozen, close_authority)) in accounts {
-            if address == associated_token_account {
-                // leave the associated token account alone
-                continue;
-            }
-
-            if frozen {
-                // leave frozen accounts alone
-                continue;
-            }
-
-            let mut account_instructions = vec![];
-
-            // Transfer the account balance into the associated token account
-            if amount > 0 {
-                account_instructions.push(transfer_checked(
-                    &spl_token::id(),
-                    &address,
-                    &token,
-                    &associated_token_account,
-                    &config.owner,
-                    &config.multisigner_pubkeys,
-                    amount,
-                    decimals,
-                )?);
-            }
-            // Close the account if config.owner is able to
-            if close_authority == config.owner {
-                account_instructions.push(close_account(
-                    &spl_token::id(),
-                    &address,
-                    &config.owner,
-                    &config.owner,
-                    &config.multisigner_pubkeys,
-                )?);
-            }
-
-            if !account_instructions.is_empty() {
-                instructions.push(account_instructions);
-            }
-        }
     }
-
-    Ok(Some((lamports_needed, instructions)))
+    Ok(Some(operation))
 }
 
 fn command_account_info(config: &Config, address: Pubkey) -> CommandResult {
@@ -1171,9 +783,26 @@ impl offline::ArgsConfig for SignOnlyNeedsDelegateAddress {
     }
 }
 
-fn main() {
-    let default_decimals = &format!("{}", native_mint::DECIMALS);
-    let app_matches = App::new(crate_name!())
+fn minimum_signers_help_string() -> String {
+    format!(
+        "The minimum number of signers required to allow the operation. [{} <= M <= N]",
+        MIN_SIGNERS
+    )
+}
+
+fn multisig_member_help_string() -> String {
+    format!(
+        "The public keys for each of the N signing members of this account. [{} <= N <= {}]",
+        MIN_SIGNERS, MAX_SIGNERS
+    )
+}
+
+fn app<'a, 'b>(
+    default_decimals: &'a str,
+    minimum_signers_help: &'b str,
+    multisig_member_help: &'b str,
+) -> App<'a, 'b> {
+    App::new(crate_name!())
         .about(crate_description!())
         .version(crate_version!())
         .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -1234,7 +863,7 @@ fn main() {
                         .validator(is_mint_decimals)
                         .value_name("DECIMALS")
                         .takes_value(true)
-                        .default_value(&default_decimals)
+                        .default_value(default_decimals)
                         .help("Number of base 10 digits to the right of the decimal place"),
                 )
                 .arg(
@@ -1257,6 +886,60 @@ fn main() {
                             "Enable the mint authority to freeze associated token accounts."
                         ),
                 )
+                .arg(
+                    Arg::with_name("freeze_authority")
+                        .long("freeze-authority")
+                        .validator(is_valid_pubkey)
+                        .value_name("FREEZE_AUTHORITY_ADDRESS")
+                        .takes_value(true)
+                        .conflicts_with("enable_freeze")
+                        .help(
+                            "Specify the freeze authority of the token. \
+                             [default: no freeze authority, or --owner with --enable-freeze]"
+                        ),
+                )
+                .arg(
+                    Arg::with_name("mint_authority")
+                        .long("mint-authority")
+                        .validator(is_valid_pubkey)
+                        .value_name("MINT_AUTHORITY_ADDRESS")
+                        .takes_value(true)
+                        .help(
+                            "Specify the mint authority of the token. \
+                             This may be a multisig account. \
+                             [default: --owner]"
+                        ),
+                )
+                .arg(
+                    Arg::with_name("initial_supply")
+                        .long("initial-supply")
+                        .validator(is_amount)
+                        .value_name("TOKEN_AMOUNT")
+                        .takes_value(true)
+                        .help("Amount to mint to the recipient when the token is created, in tokens"),
+                )
+                .arg(
+                    Arg::with_name("initial_supply_recipient")
+                        .long("initial-supply-recipient")
+                        .validator(is_valid_pubkey)
+                        .value_name("RECIPIENT_ADDRESS")
+                        .takes_value(true)
+                        .requires("initial_supply")
+                        .help(
+                            "The wallet address to receive the initial supply in its \
+                             associated token account [default: --owner]"
+                        ),
+                )
+                .arg(
+                    Arg::with_name("disable_mint")
+                        .long("disable-mint")
+                        .takes_value(false)
+                        .requires("initial_supply")
+                        .conflicts_with("mint_authority")
+                        .help(
+                            "Disable minting after the initial supply, fixing the total supply of the token"
+                        ),
+                )
                 .nonce_args(true)
                 .offline_args(),
         )
@@ -1297,10 +980,7 @@ fn main() {
                         .takes_value(true)
                         .index(1)
                         .required(true)
-                        .help(&format!("The minimum number of signers required \
-                            to allow the operation. [{} <= M <= N]",
-                            MIN_SIGNERS,
-                        )),
+                        .help(minimum_signers_help),
                 )
                 .arg(
                     Arg::with_name("multisig_member")
@@ -1311,10 +991,7 @@ fn main() {
                         .required(true)
                         .min_values(MIN_SIGNERS as u64)
                         .max_values(MAX_SIGNERS as u64)
-                        .help(&format!("The public keys for each of the N \
-                            signing members of this account. [{} <= N <= {}]",
-                            MIN_SIGNERS, MAX_SIGNERS,
-                        )),
+                        .help(multisig_member_help),
                 )
                 .arg(
                     Arg::with_name("address_keypair")
@@ -1670,6 +1347,48 @@ fn main() {
                 .nonce_args(true)
                 .offline_args_config(&SignOnlyNeedsDelegateAddress{}),
         )
+        .subcommand(
+            SubCommand::with_name("allowances")
+                .about("List all token accounts a delegate is approved to spend from")
+                .arg(
+                    Arg::with_name("delegate")
+                        .validator(is_valid_pubkey)
+                        .value_name("DELEGATE_ADDRESS")
+                        .takes_value(true)
+                        .index(1)
+                        .required(true)
+                        .help("The address of the delegate"),
+                )
+                .arg(
+                    Arg::with_name("token")
+                        .long("token")
+                        .validator(is_valid_pubkey)
+                        .value_name("TOKEN_ADDRESS")
+                        .takes_value(true)
+                        .help("Limit results to the given token. [Default: list accounts for all tokens]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("revoke-all")
+                .about("Revoke delegate authority from every token account owned by --owner")
+                .arg(
+                    Arg::with_name("delegate")
+                        .long("delegate")
+                        .validator(is_valid_pubkey)
+                        .value_name("DELEGATE_ADDRESS")
+                        .takes_value(true)
+                        .help("Only revoke approvals held by this delegate. [Default: revoke all delegates]"),
+                )
+                .arg(
+                    Arg::with_name("token")
+                        .long("token")
+                        .validator(is_valid_pubkey)
+                        .value_name("TOKEN_ADDRESS")
+                        .takes_value(true)
+                        .help("Only revoke approvals on accounts of the given token. [Default: all tokens]"),
+                )
+                .arg(multisig_signer_arg()),
+        )
         .subcommand(
             SubCommand::with_name("close")
                 .about("Close a token account")
@@ -1696,13 +1415,45 @@ fn main() {
         )
         .subcommand(
             SubCommand::with_name("gc")
-                .about("Cleanup unnecessary token accounts")
+                .about("Cleanup unnecessary token accounts"),
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Stream changes to a token account or mint as they happen")
+                .arg(
+                    Arg::with_name("address")
+                        .validator(is_valid_pubkey)
+                        .value_name("TOKEN_ACCOUNT_OR_TOKEN_ADDRESS")
+                        .takes_value(true)
+                        .index(1)
+                        .required(true)
+                        .help("The address of the token account or token to watch"),
+                )
+                .arg(
+                    Arg::with_name("output_format")
+                        .long("output")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["display", "json-lines"])
+                        .default_value("display")
+                        .help("Print each change as text or as one JSON object per line"),
+                ),
+        )
+}
+
+fn main() {
+    let default_decimals = format!("{}", native_mint::DECIMALS);
+    let minimum_signers_help = minimum_signers_help_string();
+    let multisig_member_help = multisig_member_help_string();
+    let app_matches = app(
+        &default_decimals,
+        &minimum_signers_help,
+        &multisig_member_help,
+    )
+    .get_matches();
 
     let mut wallet_manager = None;
     let mut bulk_signers: Vec<Option<Box<dyn Signer>>> = Vec::new();
-    let mut multisigner_ids = Vec::new();
 
     let (sub_command, sub_matches) = app_matches.subcommand();
     let matches = sub_matches.unwrap();
@@ -1718,6 +1469,12 @@ fn main() {
                 .value_of("json_rpc_url")
                 .unwrap_or(&cli_config.json_rpc_url),
         );
+        let websocket_url =
+            if matches.is_present("json_rpc_url") || cli_config.websocket_url.is_empty() {
+                solana_cli_config::Config::compute_websocket_url(&json_rpc_url)
+            } else {
+                cli_config.websocket_url.clone()
+            };
 
         let default_signer_arg_name = "owner".to_string();
         let default_signer_path = matches
@@ -1732,6 +1489,7 @@ fn main() {
         // Owner doesn't sign when using a mulitisig...
         let owner = if matches.is_present(MULTISIG_SIGNER_ARG.name)
           || sub_command == "accounts" // when calling the `accounts` command...
+          || sub_command == "allowances" // or listing a delegate's allowances...
           || (sub_command == "create-account" // or when creating an associated token account.
               && !matches.is_present("account_keypair"))
         {
@@ -1790,6 +1548,8 @@ fn main() {
         if signer.is_some() {
             bulk_signers.push(signer);
         }
+        // The fee payer is always a signer, so it is the natural default nonce authority
+        let nonce_authority = nonce_authority.or_else(|| nonce_account.map(|_| fee_payer));
 
         let blockhash_query = BlockhashQuery::new_from_matches(matches);
         let sign_only = matches.is_present(SIGN_ONLY_ARG.name);
@@ -1799,15 +1559,40 @@ fn main() {
                 eprintln!("error: {}", e);
                 exit(1);
             });
-        if let Some(multisig_signers) = multisig_signers {
+        let multisigner_ids = if let Some(multisig_signers) = multisig_signers {
             let (signers, pubkeys): (Vec<_>, Vec<_>) = multisig_signers.into_iter().unzip();
             bulk_signers.extend(signers.into_iter().map(Some));
-            multisigner_ids = pubkeys;
+            pubkeys
+        } else {
+            vec![]
+        };
+
+        let rpc_client = Arc::new(RpcClient::new_with_commitment(
+            json_rpc_url,
+            CommitmentConfig::confirmed(),
+        ));
+        let mut token_client = TokenClient::new(
+            ProgramRpcClient::new(rpc_client.clone()).with_spinner(true),
+            owner,
+            fee_payer,
+        )
+        .with_multisigners(multisigner_ids)
+        .with_offline(sign_only);
+        if let Some(nonce_account) = nonce_account {
+            token_client = token_client.with_nonce(nonce_account, nonce_authority.unwrap());
         }
-        let multisigner_pubkeys = multisigner_ids.iter().collect::<Vec<_>>();
+        let runtime = runtime::Builder::new_current_thread()
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                exit(1);
+            });
 
         Config {
-            rpc_client: RpcClient::new_with_commitment(json_rpc_url, CommitmentConfig::confirmed()),
+            rpc_client,
+            token_client,
+            runtime,
+            websocket_url,
             verbose,
             owner,
             fee_payer,
@@ -1816,7 +1601,6 @@ fn main() {
             nonce_authority,
             blockhash_query,
             sign_only,
-            multisigner_pubkeys,
         }
     };
 
@@ -1844,11 +1628,31 @@ fn main() {
             let token = token.unwrap();
             bulk_signers.push(signer);
 
+            let mint_authority =
+                pubkey_of_signer(arg_matches, "mint_authority", &mut wallet_manager)
+                    .unwrap()
+                    .unwrap_or(config.owner);
+            let freeze_authority = if arg_matches.is_present("enable_freeze") {
+                Some(config.owner)
+            } else {
+                pubkey_of_signer(arg_matches, "freeze_authority", &mut wallet_manager).unwrap()
+            };
+            let initial_supply = value_of::<f64>(&arg_matches, "initial_supply").map(|amount| {
+                let recipient =
+                    pubkey_of_signer(arg_matches, "initial_supply_recipient", &mut wallet_manager)
+                        .unwrap()
+                        .unwrap_or(config.owner);
+                (amount, recipient)
+            });
+
             command_create_token(
                 &config,
                 decimals,
                 token,
-                arg_matches.is_present("enable_freeze"),
+                mint_authority,
+                freeze_authority,
+                initial_supply,
+                arg_matches.is_present("disable_mint"),
             )
         }
         ("create-account", Some(arg_matches)) => {
@@ -2035,6 +1839,18 @@ fn main() {
                     .unwrap();
             command_revoke(&config, account, delegate_address)
         }
+        ("allowances", Some(arg_matches)) => {
+            let delegate = pubkey_of_signer(arg_matches, "delegate", &mut wallet_manager)
+                .unwrap()
+                .unwrap();
+            let token = pubkey_of_signer(arg_matches, "token", &mut wallet_manager).unwrap();
+            command_allowances(&config, delegate, token)
+        }
+        ("revoke-all", Some(arg_matches)) => {
+            let delegate = pubkey_of_signer(arg_matches, "delegate", &mut wallet_manager).unwrap();
+            let token = pubkey_of_signer(arg_matches, "token", &mut wallet_manager).unwrap();
+            command_revoke_all(&config, delegate, token)
+        }
         ("close", Some(arg_matches)) => {
             let account = pubkey_of_signer(arg_matches, "account", &mut wallet_manager)
                 .unwrap()
@@ -2073,10 +1889,24 @@ fn main() {
             command_multisig(&config, address)
         }
         ("gc", Some(_arg_matches)) => command_gc(&config),
+        ("watch", Some(arg_matches)) => {
+            let address = pubkey_of_signer(arg_matches, "address", &mut wallet_manager)
+                .unwrap()
+                .unwrap();
+            let output = match arg_matches.value_of("output_format").unwrap() {
+                "json-lines" => WatchOutput::JsonLines,
+                _ => WatchOutput::Display,
+            };
+            command_watch(&config, address, output)
+        }
         _ => unreachable!(),
     }
     .and_then(|transaction_info| {
-        if let Some((minimum_balance_for_rent_exemption, instruction_batches)) = transaction_info {
+        if let Some(TokenOperation {
+            lamports: minimum_balance_for_rent_exemption,
+            transactions: instruction_batches,
+        }) = transaction_info
+        {
             let fee_payer = Some(&config.fee_payer);
             let signer_info = config
                 .default_signer
@@ -2086,45 +1916,51 @@ fn main() {
                     exit(1);
                 });
 
-            for instructions in instruction_batches {
-                let message = if let Some(nonce_account) = config.nonce_account.as_ref() {
-                    Message::new_with_nonce(
-                        instructions,
-                        fee_payer,
-                        nonce_account,
-                        config.nonce_authority.as_ref().unwrap(),
-                    )
-                } else {
-                    Message::new(&instructions, fee_payer)
-                };
-                let (recent_blockhash, fee_calculator) = config
-                    .blockhash_query
-                    .get_blockhash_and_fee_calculator(
-                        &config.rpc_client,
-                        config.rpc_client.commitment(),
-                    )
-                    .unwrap_or_else(|e| {
-                        eprintln!("error: {}", e);
-                        exit(1);
-                    });
-
-                if !config.sign_only {
-                    check_fee_payer_balance(
-                        &config,
-                        minimum_balance_for_rent_exemption + fee_calculator.calculate_fee(&message),
-                    )?;
-                }
+            if config.nonce_account.is_some() && instruction_batches.len() > 1 {
+                return Err(format!(
+                    "This command requires {} transactions, but a nonce account can only \
+                     be used for a single transaction",
+                    instruction_batches.len()
+                )
+                .into());
+            }
 
-                let mut transaction = Transaction::new_unsigned(message);
+            if config.sign_only {
+                for instructions in instruction_batches {
+                    let message = if let Some(nonce_account) = config.nonce_account.as_ref() {
+                        Message::new_with_nonce(
+                            instructions,
+                            fee_payer,
+                            nonce_account,
+                            config.nonce_authority.as_ref().unwrap(),
+                        )
+                    } else {
+                        Message::new(&instructions, fee_payer)
+                    };
+                    let (recent_blockhash, _) = config
+                        .blockhash_query
+                        .get_blockhash_and_fee_calculator(
+                            &config.rpc_client,
+                            config.rpc_client.commitment(),
+                        )
+                        .unwrap_or_else(|e| {
+                            eprintln!("error: {}", e);
+                            exit(1);
+                        });
 
-                if config.sign_only {
+                    let mut transaction = Transaction::new_unsigned(message);
                     transaction.try_partial_sign(&signer_info.signers, recent_blockhash)?;
                     println!("{}", return_signers(&transaction, &OutputFormat::Display)?);
-                } else {
-                    transaction.try_sign(&signer_info.signers, recent_blockhash)?;
-                    let signature = config
-                        .rpc_client
-                        .send_and_confirm_transaction_with_spinner(&transaction)?;
+                }
+            } else {
+                let signatures = config.block_on(config.token_client.send(
+                    TokenOperation {
+                        lamports: minimum_balance_for_rent_exemption,
+                        transactions: instruction_batches,
+                    },
+                    &signer_info.signers,
+                ))?;
+                for signature in signatures {
                     println!("Signature: {}", signature);
                 }
             }
@@ -2136,3 +1972,94 @@ fn main() {
         exit(1);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ErrorKind;
+    use solana_sdk::hash::Hash;
+
+    fn parse(args: &[&str]) -> Result<ArgMatches<'static>, clap::Error> {
+        let minimum_signers_help = minimum_signers_help_string();
+        let multisig_member_help = multisig_member_help_string();
+        app("9", &minimum_signers_help, &multisig_member_help).get_matches_from_safe(args)
+    }
+
+    #[test]
+    fn disable_mint_requires_initial_supply_and_conflicts_with_mint_authority() {
+        let token = Pubkey::new_unique().to_string();
+        let mint_authority = Pubkey::new_unique().to_string();
+        let matches = parse(&[
+            "spl-token",
+            "create-token",
+            &token,
+            "--initial-supply",
+            "10",
+            "--disable-mint",
+        ])
+        .unwrap();
+        let create_token = matches.subcommand_matches("create-token").unwrap();
+        assert!(create_token.is_present("disable_mint"));
+        assert_eq!(create_token.value_of("initial_supply"), Some("10"));
+
+        let err = parse(&["spl-token", "create-token", &token, "--disable-mint"]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::MissingRequiredArgument);
+
+        let err = parse(&[
+            "spl-token",
+            "create-token",
+            &token,
+            "--initial-supply",
+            "10",
+            "--disable-mint",
+            "--mint-authority",
+            &mint_authority,
+        ])
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::ArgumentConflict);
+
+        assert!(parse(&[
+            "spl-token",
+            "create-token",
+            &token,
+            "--initial-supply",
+            "10",
+            "--mint-authority",
+            &mint_authority,
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn nonce_rejected_for_multi_transaction_commands() {
+        let nonce = Pubkey::new_unique().to_string();
+        let blockhash = Hash::default().to_string();
+        for sub_command in ["gc", "revoke-all"].iter() {
+            assert!(parse(&["spl-token", sub_command]).is_ok());
+            let err = parse(&[
+                "spl-token",
+                sub_command,
+                "--nonce",
+                &nonce,
+                "--blockhash",
+                &blockhash,
+            ])
+            .unwrap_err();
+            assert_eq!(err.kind, ErrorKind::UnknownArgument);
+        }
+        let matches = parse(&[
+            "spl-token",
+            "close",
+            &Pubkey::new_unique().to_string(),
+            "--nonce",
+            &nonce,
+            "--blockhash",
+            &blockhash,
+        ])
+        .unwrap();
+        assert!(matches
+            .subcommand_matches("close")
+            .unwrap()
+            .is_present(NONCE_ARG.name));
+    }
+}
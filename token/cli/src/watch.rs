@@ -0,0 +1,303 @@
+use crate::{CommandResult, Config};
+use serde_json::{json, Map, Value};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{program_option::COption, program_pack::Pack, pubkey::Pubkey};
+use spl_token::state::{Account, AccountState, Mint};
+use spl_token_client::ProgramClient;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchOutput {
+    Display,
+    JsonLines,
+}
+
+enum TokenState {
+    Account(Account),
+    Mint(Mint),
+}
+
+impl TokenState {
+    /// Decode an account's data, which is only token state when the account is
+    /// owned by the SPL Token program
+    fn unpack(owner: &Pubkey, data: &[u8]) -> Option<Self> {
+        if *owner != spl_token::id() {
+            None
+        } else if let Ok(account) = Account::unpack(data) {
+            Some(TokenState::Account(account))
+        } else if let Ok(mint) = Mint::unpack(data) {
+            Some(TokenState::Mint(mint))
+        } else {
+            None
+        }
+    }
+
+    /// Decoded fields worth reporting, as (name, value) pairs
+    fn fields(&self, decimals: u8) -> Vec<(&'static str, String)> {
+        match self {
+            TokenState::Account(account) => vec![
+                ("amount", format_amount(account.amount, decimals)),
+                ("delegate", format_optional_pubkey(&account.delegate)),
+                (
+                    "delegatedAmount",
+                    format_amount(account.delegated_amount, decimals),
+                ),
+                ("state", format_account_state(account.state).to_string()),
+                ("owner", account.owner.to_string()),
+                (
+                    "closeAuthority",
+                    format_optional_pubkey(&account.close_authority),
+                ),
+            ],
+            TokenState::Mint(mint) => vec![
+                ("supply", format_amount(mint.supply, decimals)),
+                (
+                    "mintAuthority",
+                    format_optional_pubkey(&mint.mint_authority),
+                ),
+                (
+                    "freezeAuthority",
+                    format_optional_pubkey(&mint.freeze_authority),
+                ),
+            ],
+        }
+    }
+
+    /// Fields that differ from `new`, as (name, old value, new value)
+    fn changes(&self, new: &TokenState, decimals: u8) -> Vec<(&'static str, String, String)> {
+        self.fields(decimals)
+            .into_iter()
+            .zip(new.fields(decimals))
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((field, old), (_, new))| (field, old, new))
+            .collect()
+    }
+}
+
+fn format_amount(amount: u64, decimals: u8) -> String {
+    spl_token::amount_to_ui_amount(amount, decimals).to_string()
+}
+
+fn format_optional_pubkey(pubkey: &COption<Pubkey>) -> String {
+    pubkey
+        .map(|pubkey| pubkey.to_string())
+        .unwrap_or_else(|| "none".to_string())
+}
+
+fn format_account_state(state: AccountState) -> &'static str {
+    match state {
+        AccountState::Uninitialized => "uninitialized",
+        AccountState::Initialized => "initialized",
+        AccountState::Frozen => "frozen",
+    }
+}
+
+/// Lines to print for the changes seen at `slot`
+fn format_changes(
+    output: WatchOutput,
+    address: &Pubkey,
+    slot: u64,
+    changes: &[(&'static str, String, String)],
+) -> Vec<String> {
+    match output {
+        WatchOutput::Display => changes
+            .iter()
+            .map(|(field, old, new)| format!("Slot {}: {}: {} -> {}", slot, field, old, new))
+            .collect(),
+        WatchOutput::JsonLines => {
+            let mut fields = Map::new();
+            for (field, old, new) in changes {
+                fields.insert(field.to_string(), json!({ "old": old, "new": new }));
+            }
+            vec![json!({
+                "slot": slot,
+                "address": address.to_string(),
+                "changes": Value::Object(fields),
+            })
+            .to_string()]
+        }
+    }
+}
+
+pub(crate) fn command_watch(
+    config: &Config,
+    address: Pubkey,
+    output: WatchOutput,
+) -> CommandResult {
+    let account = config
+        .block_on(config.token_client.client().get_account(&address))?
+        .ok_or_else(|| format!("Could not find account {}", address))?;
+    let mut state = TokenState::unpack(&account.owner, &account.data)
+        .ok_or_else(|| format!("{} is not an SPL Token account or mint", address))?;
+    let decimals = match &state {
+        TokenState::Account(account) => {
+            config
+                .block_on(config.token_client.get_mint(&account.mint))?
+                .decimals
+        }
+        TokenState::Mint(mint) => mint.decimals,
+    };
+
+    let (mut subscription, receiver) = PubsubClient::account_subscribe(
+        &config.websocket_url,
+        &address,
+        Some(RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(config.rpc_client.commitment()),
+            data_slice: None,
+        }),
+    )?;
+
+    if output == WatchOutput::Display {
+        println!("Watching {}", address);
+        for (field, value) in state.fields(decimals) {
+            println!("  {}: {}", field, value);
+        }
+    }
+
+    let result = loop {
+        let response = match receiver.recv() {
+            Ok(response) => response,
+            Err(err) => break Err(format!("Subscription closed: {}", err).into()),
+        };
+        let slot = response.context.slot;
+        let owner = response.value.owner.parse::<Pubkey>().unwrap_or_default();
+        let data = response.value.data.decode().unwrap_or_default();
+        let new_state = match TokenState::unpack(&owner, &data) {
+            Some(new_state) => new_state,
+            None => {
+                if output == WatchOutput::Display {
+                    println!("Slot {}: account closed", slot);
+                } else {
+                    println!(
+                        "{}",
+                        json!({ "slot": slot, "address": address.to_string(), "closed": true })
+                    );
+                }
+                break Ok(None);
+            }
+        };
+
+        let changes = state.changes(&new_state, decimals);
+        for line in format_changes(output, &address, slot, &changes) {
+            println!("{}", line);
+        }
+        state = new_state;
+    };
+
+    let _ = subscription.shutdown();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_account(amount: u64) -> Account {
+        Account {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount,
+            state: AccountState::Initialized,
+            ..Account::default()
+        }
+    }
+
+    fn pack<T: Pack>(state: T) -> Vec<u8> {
+        let mut data = vec![0; T::LEN];
+        T::pack(state, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn unpack_requires_token_program_owner() {
+        let account = pack(token_account(1));
+        let mint = pack(Mint {
+            decimals: 2,
+            is_initialized: true,
+            ..Mint::default()
+        });
+
+        assert!(matches!(
+            TokenState::unpack(&spl_token::id(), &account),
+            Some(TokenState::Account(_))
+        ));
+        assert!(matches!(
+            TokenState::unpack(&spl_token::id(), &mint),
+            Some(TokenState::Mint(_))
+        ));
+
+        let other_program = Pubkey::new_unique();
+        assert!(TokenState::unpack(&other_program, &account).is_none());
+        assert!(TokenState::unpack(&other_program, &mint).is_none());
+        assert!(TokenState::unpack(&spl_token::id(), &[0; 10]).is_none());
+    }
+
+    #[test]
+    fn changes_only_reports_differing_fields() {
+        let old = token_account(150);
+        let delegate = Pubkey::new_unique();
+        let new = Account {
+            amount: 100,
+            delegate: COption::Some(delegate),
+            delegated_amount: 25,
+            ..old
+        };
+
+        let changes = TokenState::Account(old).changes(&TokenState::Account(new), 2);
+        assert_eq!(
+            changes,
+            vec![
+                ("amount", "1.5".to_string(), "1".to_string()),
+                ("delegate", "none".to_string(), delegate.to_string()),
+                ("delegatedAmount", "0".to_string(), "0.25".to_string()),
+            ]
+        );
+
+        let frozen = TokenState::Account(Account {
+            state: AccountState::Frozen,
+            ..new
+        });
+        assert_eq!(
+            TokenState::Account(new).changes(&frozen, 2),
+            vec![("state", "initialized".to_string(), "frozen".to_string())]
+        );
+        assert!(TokenState::Account(new)
+            .changes(&TokenState::Account(new), 2)
+            .is_empty());
+    }
+
+    #[test]
+    fn format_changes_per_output() {
+        let address = Pubkey::new_unique();
+        let changes = vec![
+            ("supply", "0".to_string(), "10".to_string()),
+            ("mintAuthority", address.to_string(), "none".to_string()),
+        ];
+
+        assert_eq!(
+            format_changes(WatchOutput::Display, &address, 7, &changes),
+            vec![
+                "Slot 7: supply: 0 -> 10".to_string(),
+                format!("Slot 7: mintAuthority: {} -> none", address),
+            ]
+        );
+
+        let lines = format_changes(WatchOutput::JsonLines, &address, 7, &changes);
+        assert_eq!(lines.len(), 1);
+        let value: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "slot": 7,
+                "address": address.to_string(),
+                "changes": {
+                    "supply": { "old": "0", "new": "10" },
+                    "mintAuthority": { "old": address.to_string(), "new": "none" },
+                },
+            })
+        );
+
+        assert!(format_changes(WatchOutput::Display, &address, 7, &[]).is_empty());
+    }
+}
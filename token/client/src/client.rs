@@ -0,0 +1,220 @@
+//! Backends used to read chain state and submit transactions
+
+use crate::error::TokenClientError;
+use async_trait::async_trait;
+use serde_json::json;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::RpcAccountInfoConfig,
+    rpc_request::{RpcRequest, RpcTokenAccountsFilter},
+    rpc_response::{Response, RpcKeyedAccount},
+};
+use solana_sdk::{
+    account::Account as BaseAccount, fee_calculator::FeeCalculator, hash::Hash, pubkey::Pubkey,
+    signature::Signature, transaction::Transaction,
+};
+use std::sync::Arc;
+
+/// Everything `TokenClient` needs from the cluster. Implement this to run token
+/// operations against a custom RPC stack or an in-process test bank.
+#[async_trait]
+pub trait ProgramClient {
+    /// Fetch an account, or `None` if it does not exist
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<BaseAccount>, TokenClientError>;
+
+    /// Lamport balance of an address
+    async fn get_balance(&self, address: &Pubkey) -> Result<u64, TokenClientError>;
+
+    /// Lamports required for an account of `data_len` bytes to be rent-exempt
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> Result<u64, TokenClientError>;
+
+    /// Every SPL Token account whose owner is `owner`
+    async fn get_token_accounts_by_owner(
+        &self,
+        owner: &Pubkey,
+    ) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError>;
+
+    /// Every SPL Token account delegated to `delegate`, only for `mint` if given
+    async fn get_token_accounts_by_delegate(
+        &self,
+        delegate: &Pubkey,
+        mint: Option<&Pubkey>,
+    ) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError>;
+
+    /// A recent blockhash and the fee schedule that goes with it
+    async fn get_recent_blockhash(&self) -> Result<(Hash, FeeCalculator), TokenClientError>;
+
+    /// Submit a signed transaction and wait for it to be confirmed
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, TokenClientError>;
+}
+
+/// `ProgramClient` backed by a JSON RPC node, using the commitment of the
+/// wrapped `RpcClient`.  The blocking requests of `RpcClient` run on the tokio
+/// blocking thread pool, so this must be used from within a tokio runtime.
+pub struct ProgramRpcClient {
+    rpc_client: Arc<RpcClient>,
+    spinner: bool,
+}
+
+impl ProgramRpcClient {
+    /// Create a backend sharing `rpc_client` with the caller
+    pub fn new(rpc_client: Arc<RpcClient>) -> Self {
+        Self {
+            rpc_client,
+            spinner: false,
+        }
+    }
+
+    /// Show a progress spinner on the terminal while transactions confirm
+    pub fn with_spinner(mut self, spinner: bool) -> Self {
+        self.spinner = spinner;
+        self
+    }
+
+    /// Run a blocking request without holding up the async executor
+    async fn run<T, F>(&self, request: F) -> Result<T, TokenClientError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&RpcClient) -> Result<T, TokenClientError> + Send + 'static,
+    {
+        let rpc_client = self.rpc_client.clone();
+        tokio::task::spawn_blocking(move || request(&rpc_client))
+            .await
+            .map_err(client_error)?
+    }
+}
+
+fn client_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> TokenClientError {
+    TokenClientError::Client(Box::new(err))
+}
+
+/// Fetch token accounts with raw account data.  The `RpcClient` helpers for
+/// these requests ask for `jsonParsed` data, so the request is made directly.
+fn get_token_accounts(
+    rpc_client: &RpcClient,
+    request: RpcRequest,
+    address: &Pubkey,
+    filter: RpcTokenAccountsFilter,
+) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError> {
+    let response: Response<Vec<RpcKeyedAccount>> = rpc_client
+        .send(
+            request,
+            json!([
+                address.to_string(),
+                filter,
+                RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(rpc_client.commitment()),
+                    ..RpcAccountInfoConfig::default()
+                },
+            ]),
+        )
+        .map_err(client_error)?;
+    Ok(response
+        .value
+        .into_iter()
+        .filter_map(|keyed_account| {
+            let address = keyed_account.pubkey.parse::<Pubkey>().ok()?;
+            let account: BaseAccount = keyed_account.account.decode()?;
+            Some((address, account))
+        })
+        .collect())
+}
+
+#[async_trait]
+impl ProgramClient for ProgramRpcClient {
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<BaseAccount>, TokenClientError> {
+        let address = *address;
+        self.run(move |rpc_client| {
+            rpc_client
+                .get_account_with_commitment(&address, rpc_client.commitment())
+                .map(|response| response.value)
+                .map_err(client_error)
+        })
+        .await
+    }
+
+    async fn get_balance(&self, address: &Pubkey) -> Result<u64, TokenClientError> {
+        let address = *address;
+        self.run(move |rpc_client| rpc_client.get_balance(&address).map_err(client_error))
+            .await
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> Result<u64, TokenClientError> {
+        self.run(move |rpc_client| {
+            rpc_client
+                .get_minimum_balance_for_rent_exemption(data_len)
+                .map_err(client_error)
+        })
+        .await
+    }
+
+    async fn get_token_accounts_by_owner(
+        &self,
+        owner: &Pubkey,
+    ) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError> {
+        let owner = *owner;
+        self.run(move |rpc_client| {
+            get_token_accounts(
+                rpc_client,
+                RpcRequest::GetTokenAccountsByOwner,
+                &owner,
+                RpcTokenAccountsFilter::ProgramId(spl_token::id().to_string()),
+            )
+        })
+        .await
+    }
+
+    async fn get_token_accounts_by_delegate(
+        &self,
+        delegate: &Pubkey,
+        mint: Option<&Pubkey>,
+    ) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError> {
+        let delegate = *delegate;
+        let filter = match mint {
+            Some(mint) => RpcTokenAccountsFilter::Mint(mint.to_string()),
+            None => RpcTokenAccountsFilter::ProgramId(spl_token::id().to_string()),
+        };
+        self.run(move |rpc_client| {
+            get_token_accounts(
+                rpc_client,
+                RpcRequest::GetTokenAccountsByDelegate,
+                &delegate,
+                filter,
+            )
+        })
+        .await
+    }
+
+    async fn get_recent_blockhash(&self) -> Result<(Hash, FeeCalculator), TokenClientError> {
+        self.run(|rpc_client| rpc_client.get_recent_blockhash().map_err(client_error))
+            .await
+    }
+
+    async fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<Signature, TokenClientError> {
+        let transaction = transaction.clone();
+        let spinner = self.spinner;
+        self.run(move |rpc_client| {
+            if spinner {
+                rpc_client.send_and_confirm_transaction_with_spinner(&transaction)
+            } else {
+                rpc_client.send_and_confirm_transaction(&transaction)
+            }
+            .map_err(client_error)
+        })
+        .await
+    }
+}
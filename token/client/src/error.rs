@@ -0,0 +1,58 @@
+//! Error types
+
+use solana_sdk::{program_error::ProgramError, pubkey::Pubkey};
+use thiserror::Error;
+
+/// Errors that may be returned while building or submitting token operations
+#[derive(Debug, Error)]
+pub enum TokenClientError {
+    /// The RPC backend or signer failed
+    #[error("{0}")]
+    Client(Box<dyn std::error::Error + Send + Sync>),
+    /// An instruction could not be built
+    #[error("{0}")]
+    Program(#[from] ProgramError),
+    /// The account does not exist
+    #[error("Could not find account {0}")]
+    AccountNotFound(Pubkey),
+    /// The account exists but does not hold the expected SPL Token state
+    #[error("Unsupported account data format: {0}")]
+    InvalidAccountData(Pubkey),
+    /// The account to create already exists
+    #[error("Account already exists: {0}")]
+    AccountAlreadyExists(Pubkey),
+    /// The authority type does not apply to this kind of account
+    #[error("Authority type `{0}` not supported for SPL Token {1}")]
+    UnsupportedAuthorityType(&'static str, &'static str),
+    /// Changing this authority would take the owner's associated token account
+    /// away from them
+    #[error("Attempting to change the `{0}` of an associated token account of the owner")]
+    AssociatedTokenAccountAuthority(&'static str),
+    /// The token account balance does not cover the amount requested
+    #[error("Sender has insufficient funds, current balance is {0}")]
+    InsufficientFunds(f64),
+    /// The transfer recipient does not exist
+    #[error("The recipient {0} has no balance")]
+    EmptyRecipient(Pubkey),
+    /// The recipient's associated token account does not exist and was not funded
+    #[error("Recipient's associated token account {0} does not exist")]
+    RecipientNotFunded(Pubkey),
+    /// The recipient is owned by a program other than the system or token program
+    #[error("Unsupported recipient address: {0}")]
+    UnsupportedRecipient(Pubkey),
+    /// The account still holds tokens and cannot be closed
+    #[error("Account {0} still has {1} tokens; empty the account in order to close it.")]
+    AccountNotEmpty(Pubkey, f64),
+    /// The account has no delegate to revoke
+    #[error("No delegate on account {0}")]
+    NoDelegate(Pubkey),
+    /// A paying account cannot cover the lamports required, in SOL
+    #[error("{0}, {1}, has insufficient balance: {2} required, {3} available")]
+    InsufficientBalance(&'static str, Pubkey, f64, f64),
+    /// The nonce account is controlled by another authority
+    #[error("Nonce account {0} has authority {1}, not {2}")]
+    InvalidNonceAuthority(Pubkey, Pubkey, Pubkey),
+    /// A nonce can only be used for one transaction, but the operation needs more
+    #[error("This operation requires {0} transactions, but a nonce account can only be used for a single transaction")]
+    NonceMultipleTransactions(usize),
+}
@@ -0,0 +1,13 @@
+#![deny(missing_docs)]
+#![forbid(unsafe_code)]
+
+//! A Rust client for the SPL Token program, shared by `spl-token` and any
+//! service that needs to build and submit token transactions
+
+pub mod client;
+pub mod error;
+pub mod token;
+
+pub use client::{ProgramClient, ProgramRpcClient};
+pub use error::TokenClientError;
+pub use token::{TokenClient, TokenOperation};
@@ -0,0 +1,1605 @@
+//! Typed SPL Token operations
+
+use crate::{client::ProgramClient, error::TokenClientError};
+use solana_client::nonce_utils;
+use solana_sdk::{
+    fee_calculator::FeeCalculator,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    native_token::lamports_to_sol,
+    program_option::COption,
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+    signature::{Signature, Signers},
+    system_instruction, system_program,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+use spl_token::{
+    amount_to_ui_amount,
+    instruction::*,
+    native_mint,
+    state::{Account, AccountState, Mint, Multisig},
+};
+use std::collections::HashMap;
+
+/// Number of `Revoke` instructions packed into each `revoke_all` transaction
+const REVOKE_BATCH_SIZE: usize = 16;
+
+/// Result type for token operations
+pub type TokenResult<T> = Result<T, TokenClientError>;
+
+/// The transactions that carry out a token operation, in submission order
+#[derive(Debug, Default, PartialEq)]
+pub struct TokenOperation {
+    /// Lamports the fee payer must hold beyond transaction fees, such as rent
+    /// for the accounts being created
+    pub lamports: u64,
+    /// Instructions for each transaction
+    pub transactions: Vec<Vec<Instruction>>,
+}
+
+impl TokenOperation {
+    fn single(lamports: u64, instructions: Vec<Instruction>) -> Self {
+        Self {
+            lamports,
+            transactions: vec![instructions],
+        }
+    }
+}
+
+/// A transfer, along with how its recipient was resolved
+#[derive(Debug, PartialEq)]
+pub struct TransferOperation {
+    /// Raw amount being transferred
+    pub amount: u64,
+    /// Token account receiving the transfer
+    pub recipient_token_account: Pubkey,
+    /// True if the recipient was a wallet and its associated token account is used
+    pub is_associated: bool,
+    /// True if the recipient's associated token account is created by the transfer
+    pub funds_recipient: bool,
+    /// Transactions to submit
+    pub operation: TokenOperation,
+}
+
+/// Tokens a delegate may spend from a token account
+#[derive(Debug, PartialEq)]
+pub struct Allowance {
+    /// Token account the delegate may spend from
+    pub address: Pubkey,
+    /// Mint of the token account
+    pub mint: Pubkey,
+    /// Owner of the token account
+    pub owner: Pubkey,
+    /// Raw amount the delegate may still spend
+    pub delegated_amount: u64,
+    /// Decimals of the mint
+    pub decimals: u8,
+}
+
+/// Human-readable name of an authority type
+pub fn authority_name(authority_type: &AuthorityType) -> &'static str {
+    match authority_type {
+        AuthorityType::MintTokens => "mint authority",
+        AuthorityType::FreezeAccount => "freeze authority",
+        AuthorityType::AccountOwner => "owner",
+        AuthorityType::CloseAccount => "close authority",
+    }
+}
+
+/// Builds and submits SPL Token operations on behalf of an owner
+pub struct TokenClient<C> {
+    client: C,
+    owner: Pubkey,
+    fee_payer: Pubkey,
+    multisigners: Vec<Pubkey>,
+    offline: bool,
+    nonce: Option<(Pubkey, Pubkey)>,
+}
+
+impl<C: ProgramClient> TokenClient<C> {
+    /// Create a client acting for `owner`, with transaction fees and rent paid
+    /// by `fee_payer`
+    pub fn new(client: C, owner: Pubkey, fee_payer: Pubkey) -> Self {
+        Self {
+            client,
+            owner,
+            fee_payer,
+            multisigners: vec![],
+            offline: false,
+            nonce: None,
+        }
+    }
+
+    /// Treat `owner` as a multisig and sign with these members
+    pub fn with_multisigners(mut self, multisigners: Vec<Pubkey>) -> Self {
+        self.multisigners = multisigners;
+        self
+    }
+
+    /// Build operations for offline signing: rent and balances are not queried,
+    /// and mint details must be supplied by the caller
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Send transactions with the durable nonce stored in `nonce_account`,
+    /// advanced by `nonce_authority`, instead of a recent blockhash
+    pub fn with_nonce(mut self, nonce_account: Pubkey, nonce_authority: Pubkey) -> Self {
+        self.nonce = Some((nonce_account, nonce_authority));
+        self
+    }
+
+    /// The backend used to read state and submit transactions
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// The owner acting through this client
+    pub fn owner(&self) -> &Pubkey {
+        &self.owner
+    }
+
+    /// The account paying fees and rent
+    pub fn fee_payer(&self) -> &Pubkey {
+        &self.fee_payer
+    }
+
+    fn multisigner_refs(&self) -> Vec<&Pubkey> {
+        self.multisigners.iter().collect()
+    }
+
+    async fn minimum_balance_for_rent_exemption(&self, data_len: usize) -> TokenResult<u64> {
+        if self.offline {
+            Ok(0)
+        } else {
+            self.client
+                .get_minimum_balance_for_rent_exemption(data_len)
+                .await
+        }
+    }
+
+    async fn get_state<T: Pack + IsInitialized>(&self, address: &Pubkey) -> TokenResult<T> {
+        let account = self
+            .client
+            .get_account(address)
+            .await?
+            .ok_or(TokenClientError::AccountNotFound(*address))?;
+        T::unpack(&account.data).map_err(|_| TokenClientError::InvalidAccountData(*address))
+    }
+
+    /// Fetch and decode a mint
+    pub async fn get_mint(&self, address: &Pubkey) -> TokenResult<Mint> {
+        self.get_state(address).await
+    }
+
+    /// Fetch and decode a token account
+    pub async fn get_token_account(&self, address: &Pubkey) -> TokenResult<Account> {
+        self.get_state(address).await
+    }
+
+    /// Fetch and decode a multisig
+    pub async fn get_multisig(&self, address: &Pubkey) -> TokenResult<Multisig> {
+        self.get_state(address).await
+    }
+
+    /// Fetch the blockhash and fee schedule stored in a nonce account, which
+    /// must be advanced by `nonce_authority`
+    async fn get_nonce_data(
+        &self,
+        nonce_account: &Pubkey,
+        nonce_authority: &Pubkey,
+    ) -> TokenResult<(Hash, FeeCalculator)> {
+        let account = self
+            .client
+            .get_account(nonce_account)
+            .await?
+            .ok_or(TokenClientError::AccountNotFound(*nonce_account))?;
+        let data = nonce_utils::data_from_account(&account)
+            .map_err(|err| TokenClientError::Client(Box::new(err)))?;
+        if data.authority != *nonce_authority {
+            return Err(TokenClientError::InvalidNonceAuthority(
+                *nonce_account,
+                data.authority,
+                *nonce_authority,
+            ));
+        }
+        Ok((data.blockhash, data.fee_calculator))
+    }
+
+    /// Fail unless `address` holds at least `required` lamports
+    async fn check_balance(
+        &self,
+        name: &'static str,
+        address: &Pubkey,
+        required: u64,
+    ) -> TokenResult<()> {
+        let balance = self.client.get_balance(address).await?;
+        if balance < required {
+            Err(TokenClientError::InsufficientBalance(
+                name,
+                *address,
+                lamports_to_sol(required),
+                lamports_to_sol(balance),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fail unless the fee payer holds at least `required` lamports
+    pub async fn check_fee_payer_balance(&self, required: u64) -> TokenResult<()> {
+        self.check_balance("Fee payer", &self.fee_payer, required)
+            .await
+    }
+
+    /// Look up the mint and decimals of a token account.  Offline, the
+    /// supplied values are used instead.
+    pub async fn resolve_mint_info(
+        &self,
+        token_account: &Pubkey,
+        mint_address: Option<Pubkey>,
+        mint_decimals: Option<u8>,
+    ) -> TokenResult<(Pubkey, u8)> {
+        if !self.offline {
+            let account = self.get_token_account(token_account).await?;
+            let mint = self.get_mint(&account.mint).await?;
+            Ok((account.mint, mint.decimals))
+        } else {
+            Ok((
+                mint_address.unwrap_or_default(),
+                mint_decimals.unwrap_or_default(),
+            ))
+        }
+    }
+
+    /// Create a new mint.  If `initial_supply` is given, that raw amount is
+    /// minted to the associated token account of the given wallet.  The mint
+    /// authority then moves to `mint_authority`, or is disabled if
+    /// `disable_mint` is set, in the same transaction.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_token(
+        &self,
+        token: &Pubkey,
+        decimals: u8,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        initial_supply: Option<(u64, Pubkey)>,
+        disable_mint: bool,
+    ) -> TokenResult<TokenOperation> {
+        let mut lamports = self.minimum_balance_for_rent_exemption(Mint::LEN).await?;
+
+        // The owner holds the mint authority until the initial supply is minted,
+        // after which it is handed over (or disabled) within the same transaction
+        let initial_mint_authority = if initial_supply.is_some() || disable_mint {
+            self.owner
+        } else {
+            *mint_authority
+        };
+
+        let mut instructions = vec![
+            system_instruction::create_account(
+                &self.fee_payer,
+                token,
+                lamports,
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_mint(
+                &spl_token::id(),
+                token,
+                &initial_mint_authority,
+                freeze_authority,
+                decimals,
+            )?,
+        ];
+
+        if let Some((amount, recipient)) = initial_supply {
+            let recipient_token_account = get_associated_token_address(&recipient, token);
+            lamports += self
+                .minimum_balance_for_rent_exemption(Account::LEN)
+                .await?;
+            instructions.push(create_associated_token_account(
+                &self.fee_payer,
+                &recipient,
+                token,
+            ));
+            instructions.push(mint_to_checked(
+                &spl_token::id(),
+                token,
+                &recipient_token_account,
+                &initial_mint_authority,
+                &[],
+                amount,
+                decimals,
+            )?);
+        }
+
+        if disable_mint {
+            instructions.push(set_authority(
+                &spl_token::id(),
+                token,
+                None,
+                AuthorityType::MintTokens,
+                &initial_mint_authority,
+                &[],
+            )?);
+        } else if *mint_authority != initial_mint_authority {
+            instructions.push(set_authority(
+                &spl_token::id(),
+                token,
+                Some(mint_authority),
+                AuthorityType::MintTokens,
+                &initial_mint_authority,
+                &[],
+            )?);
+        }
+
+        Ok(TokenOperation::single(lamports, instructions))
+    }
+
+    /// Create a token account for `token`, at `account` if given or else at the
+    /// owner's associated token account address.  Returns the new account.
+    pub async fn create_account(
+        &self,
+        token: &Pubkey,
+        account: Option<&Pubkey>,
+    ) -> TokenResult<(Pubkey, TokenOperation)> {
+        let lamports = self
+            .minimum_balance_for_rent_exemption(Account::LEN)
+            .await?;
+
+        let (account, system_account_ok, instructions) = if let Some(account) = account {
+            (
+                *account,
+                false,
+                vec![
+                    system_instruction::create_account(
+                        &self.fee_payer,
+                        account,
+                        lamports,
+                        Account::LEN as u64,
+                        &spl_token::id(),
+                    ),
+                    initialize_account(&spl_token::id(), account, token, &self.owner)?,
+                ],
+            )
+        } else {
+            (
+                get_associated_token_address(&self.owner, token),
+                true,
+                vec![create_associated_token_account(
+                    &self.fee_payer,
+                    &self.owner,
+                    token,
+                )],
+            )
+        };
+
+        if let Some(account_data) = self.client.get_account(&account).await? {
+            if !(account_data.owner == system_program::id() && system_account_ok) {
+                return Err(TokenClientError::AccountAlreadyExists(account));
+            }
+        }
+
+        Ok((account, TokenOperation::single(lamports, instructions)))
+    }
+
+    /// Create an M:N multisig at `multisig`
+    pub async fn create_multisig(
+        &self,
+        multisig: &Pubkey,
+        minimum_signers: u8,
+        multisig_members: &[Pubkey],
+    ) -> TokenResult<TokenOperation> {
+        let lamports = self
+            .minimum_balance_for_rent_exemption(Multisig::LEN)
+            .await?;
+
+        let instructions = vec![
+            system_instruction::create_account(
+                &self.fee_payer,
+                multisig,
+                lamports,
+                Multisig::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_multisig(
+                &spl_token::id(),
+                multisig,
+                multisig_members.iter().collect::<Vec<_>>().as_slice(),
+                minimum_signers,
+            )?,
+        ];
+        Ok(TokenOperation::single(lamports, instructions))
+    }
+
+    /// Change an authority of a mint or token account.  Returns the authority
+    /// being replaced.  Unless `force` is set, the owner of the owner's own
+    /// associated token account cannot be changed.
+    pub async fn authorize(
+        &self,
+        account: &Pubkey,
+        authority_type: AuthorityType,
+        new_authority: Option<&Pubkey>,
+        force: bool,
+    ) -> TokenResult<(COption<Pubkey>, TokenOperation)> {
+        let auth_str = authority_name(&authority_type);
+        let target_account = self
+            .client
+            .get_account(account)
+            .await?
+            .ok_or(TokenClientError::AccountNotFound(*account))?;
+
+        let previous_authority = if let Ok(mint) = Mint::unpack(&target_account.data) {
+            match authority_type {
+                AuthorityType::AccountOwner | AuthorityType::CloseAccount => Err(
+                    TokenClientError::UnsupportedAuthorityType(auth_str, "mints"),
+                ),
+                AuthorityType::MintTokens => Ok(mint.mint_authority),
+                AuthorityType::FreezeAccount => Ok(mint.freeze_authority),
+            }
+        } else if let Ok(token_account) = Account::unpack(&target_account.data) {
+            let check_associated_token_account = || -> TokenResult<()> {
+                let maybe_associated_token_account =
+                    get_associated_token_address(&self.owner, &token_account.mint);
+                if *account == maybe_associated_token_account
+                    && !force
+                    && Some(&self.owner) != new_authority
+                {
+                    Err(TokenClientError::AssociatedTokenAccountAuthority(auth_str))
+                } else {
+                    Ok(())
+                }
+            };
+
+            match authority_type {
+                AuthorityType::MintTokens | AuthorityType::FreezeAccount => Err(
+                    TokenClientError::UnsupportedAuthorityType(auth_str, "accounts"),
+                ),
+                AuthorityType::AccountOwner => {
+                    check_associated_token_account()?;
+                    Ok(COption::Some(token_account.owner))
+                }
+                AuthorityType::CloseAccount => {
+                    check_associated_token_account()?;
+                    Ok(COption::Some(
+                        token_account.close_authority.unwrap_or(token_account.owner),
+                    ))
+                }
+            }
+        } else {
+            Err(TokenClientError::InvalidAccountData(*account))
+        }?;
+
+        let instructions = vec![set_authority(
+            &spl_token::id(),
+            account,
+            new_authority,
+            authority_type,
+            &self.owner,
+            &self.multisigner_refs(),
+        )?];
+        Ok((previous_authority, TokenOperation::single(0, instructions)))
+    }
+
+    /// Transfer `amount` raw tokens, or the entire balance if `None`, from
+    /// `sender`.  If `recipient` is not a token account it is treated as a
+    /// wallet, and the transfer goes to its associated token account, which is
+    /// created first when `fund_recipient` is set.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn transfer(
+        &self,
+        sender: &Pubkey,
+        mint: &Pubkey,
+        decimals: u8,
+        amount: Option<u64>,
+        recipient: &Pubkey,
+        allow_empty_recipient: bool,
+        fund_recipient: bool,
+    ) -> TokenResult<TransferOperation> {
+        let sender_balance = self.get_token_account(sender).await?.amount;
+        let amount = amount.unwrap_or(sender_balance);
+        if amount > sender_balance {
+            return Err(TokenClientError::InsufficientFunds(amount_to_ui_amount(
+                sender_balance,
+                decimals,
+            )));
+        }
+
+        let mut instructions = vec![];
+        let mut lamports = 0;
+        let mut recipient_token_account = *recipient;
+        let mut is_associated = false;
+        let mut funds_recipient = false;
+
+        let recipient_account_owner = self
+            .client
+            .get_account(recipient)
+            .await?
+            .map(|account_data| account_data.owner);
+
+        if recipient_account_owner.is_none() && !allow_empty_recipient {
+            return Err(TokenClientError::EmptyRecipient(*recipient));
+        }
+
+        if Some(spl_token::id()) != recipient_account_owner {
+            recipient_token_account = get_associated_token_address(recipient, mint);
+            is_associated = true;
+
+            let needs_funding = match self.client.get_account(&recipient_token_account).await? {
+                Some(account_data) if account_data.owner == system_program::id() => true,
+                Some(account_data) if account_data.owner == spl_token::id() => false,
+                Some(_) => return Err(TokenClientError::UnsupportedRecipient(*recipient)),
+                None => true,
+            };
+
+            if needs_funding {
+                if fund_recipient {
+                    lamports += self
+                        .minimum_balance_for_rent_exemption(Account::LEN)
+                        .await?;
+                    funds_recipient = true;
+                    instructions.push(create_associated_token_account(
+                        &self.fee_payer,
+                        recipient,
+                        mint,
+                    ));
+                } else {
+                    return Err(TokenClientError::RecipientNotFunded(
+                        recipient_token_account,
+                    ));
+                }
+            }
+        }
+
+        instructions.push(transfer_checked(
+            &spl_token::id(),
+            sender,
+            mint,
+            &recipient_token_account,
+            &self.owner,
+            &self.multisigner_refs(),
+            amount,
+            decimals,
+        )?);
+        Ok(TransferOperation {
+            amount,
+            recipient_token_account,
+            is_associated,
+            funds_recipient,
+            operation: TokenOperation::single(lamports, instructions),
+        })
+    }
+
+    /// Burn `amount` raw tokens from `source`
+    pub async fn burn(
+        &self,
+        source: &Pubkey,
+        mint: &Pubkey,
+        amount: u64,
+        decimals: u8,
+    ) -> TokenResult<TokenOperation> {
+        let instructions = vec![burn_checked(
+            &spl_token::id(),
+            source,
+            mint,
+            &self.owner,
+            &self.multisigner_refs(),
+            amount,
+            decimals,
+        )?];
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Mint `amount` raw tokens of `mint` into `recipient`
+    pub async fn mint_to(
+        &self,
+        mint: &Pubkey,
+        recipient: &Pubkey,
+        amount: u64,
+        decimals: u8,
+    ) -> TokenResult<TokenOperation> {
+        let instructions = vec![mint_to_checked(
+            &spl_token::id(),
+            mint,
+            recipient,
+            &self.owner,
+            &self.multisigner_refs(),
+            amount,
+            decimals,
+        )?];
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Freeze a token account of `mint`
+    pub async fn freeze(&self, account: &Pubkey, mint: &Pubkey) -> TokenResult<TokenOperation> {
+        let instructions = vec![freeze_account(
+            &spl_token::id(),
+            account,
+            mint,
+            &self.owner,
+            &self.multisigner_refs(),
+        )?];
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Thaw a frozen token account of `mint`
+    pub async fn thaw(&self, account: &Pubkey, mint: &Pubkey) -> TokenResult<TokenOperation> {
+        let instructions = vec![thaw_account(
+            &spl_token::id(),
+            account,
+            mint,
+            &self.owner,
+            &self.multisigner_refs(),
+        )?];
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Wrap `lamports` of the owner's SOL into a new native token account
+    pub async fn wrap(&self, account: &Pubkey, lamports: u64) -> TokenResult<TokenOperation> {
+        let instructions = vec![
+            system_instruction::create_account(
+                &self.owner,
+                account,
+                lamports,
+                Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            initialize_account(&spl_token::id(), account, &native_mint::id(), &self.owner)?,
+        ];
+        if !self.offline {
+            self.check_balance("Owner", &self.owner, lamports).await?;
+        }
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Close a native token account, returning its SOL to the owner
+    pub async fn unwrap(&self, account: &Pubkey) -> TokenResult<TokenOperation> {
+        self.close_unchecked(account, &self.owner)
+    }
+
+    /// Let `delegate` spend up to `amount` raw tokens from `account`
+    pub async fn approve(
+        &self,
+        account: &Pubkey,
+        mint: &Pubkey,
+        delegate: &Pubkey,
+        amount: u64,
+        decimals: u8,
+    ) -> TokenResult<TokenOperation> {
+        let instructions = vec![approve_checked(
+            &spl_token::id(),
+            account,
+            mint,
+            delegate,
+            &self.owner,
+            &self.multisigner_refs(),
+            amount,
+            decimals,
+        )?];
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Revoke the delegate of `account`.  Returns the delegate being revoked,
+    /// which is read from the account unless offline.
+    pub async fn revoke(
+        &self,
+        account: &Pubkey,
+        delegate: Option<Pubkey>,
+    ) -> TokenResult<(Pubkey, TokenOperation)> {
+        let delegate = if !self.offline {
+            match self.get_token_account(account).await?.delegate {
+                COption::Some(delegate) => Some(delegate),
+                COption::None => None,
+            }
+        } else {
+            delegate
+        };
+        let delegate = delegate.ok_or(TokenClientError::NoDelegate(*account))?;
+
+        let instructions = vec![revoke(
+            &spl_token::id(),
+            account,
+            &self.owner,
+            &self.multisigner_refs(),
+        )?];
+        Ok((delegate, TokenOperation::single(0, instructions)))
+    }
+
+    /// Revoke every delegate approval on the owner's token accounts, optionally
+    /// limited to one delegate or one mint.  Returns the (account, delegate)
+    /// pairs being revoked.
+    pub async fn revoke_all(
+        &self,
+        delegate: Option<&Pubkey>,
+        mint: Option<&Pubkey>,
+    ) -> TokenResult<(Vec<(Pubkey, Pubkey)>, TokenOperation)> {
+        let mut revoked = vec![];
+        let mut instructions = vec![];
+        for (address, account) in self.client.get_token_accounts_by_owner(&self.owner).await? {
+            let token_account = match Account::unpack(&account.data) {
+                Ok(token_account) => token_account,
+                Err(_) => continue,
+            };
+            let account_delegate = match token_account.delegate {
+                COption::Some(account_delegate) => account_delegate,
+                COption::None => continue,
+            };
+            if delegate.map_or(false, |delegate| *delegate != account_delegate)
+                || mint.map_or(false, |mint| *mint != token_account.mint)
+            {
+                continue;
+            }
+            instructions.push(revoke(
+                &spl_token::id(),
+                &address,
+                &self.owner,
+                &self.multisigner_refs(),
+            )?);
+            revoked.push((address, account_delegate));
+        }
+
+        let transactions = instructions
+            .chunks(REVOKE_BATCH_SIZE)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        Ok((
+            revoked,
+            TokenOperation {
+                lamports: 0,
+                transactions,
+            },
+        ))
+    }
+
+    /// Every allowance granted to `delegate`, optionally limited to one mint
+    pub async fn allowances(
+        &self,
+        delegate: &Pubkey,
+        mint: Option<&Pubkey>,
+    ) -> TokenResult<Vec<Allowance>> {
+        let mut decimals_by_mint = HashMap::new();
+        let mut allowances = vec![];
+        for (address, account) in self
+            .client
+            .get_token_accounts_by_delegate(delegate, mint)
+            .await?
+        {
+            if account.owner != spl_token::id() {
+                continue;
+            }
+            let token_account = match Account::unpack(&account.data) {
+                Ok(token_account) => token_account,
+                Err(_) => continue,
+            };
+            if token_account.delegate != COption::Some(*delegate) {
+                continue;
+            }
+            let decimals = match decimals_by_mint.get(&token_account.mint) {
+                Some(decimals) => *decimals,
+                None => {
+                    let decimals = self.get_mint(&token_account.mint).await?.decimals;
+                    decimals_by_mint.insert(token_account.mint, decimals);
+                    decimals
+                }
+            };
+            allowances.push(Allowance {
+                address,
+                mint: token_account.mint,
+                owner: token_account.owner,
+                delegated_amount: token_account.delegated_amount,
+                decimals,
+            });
+        }
+        Ok(allowances)
+    }
+
+    /// Close an empty token account, sending its lamports to `destination`
+    pub async fn close(
+        &self,
+        account: &Pubkey,
+        destination: &Pubkey,
+    ) -> TokenResult<TokenOperation> {
+        if !self.offline {
+            let source_account = self.get_token_account(account).await?;
+            if !source_account.is_native() && source_account.amount > 0 {
+                let mint = self.get_mint(&source_account.mint).await?;
+                return Err(TokenClientError::AccountNotEmpty(
+                    *account,
+                    amount_to_ui_amount(source_account.amount, mint.decimals),
+                ));
+            }
+        }
+        self.close_unchecked(account, destination)
+    }
+
+    fn close_unchecked(
+        &self,
+        account: &Pubkey,
+        destination: &Pubkey,
+    ) -> TokenResult<TokenOperation> {
+        let instructions = vec![close_account(
+            &spl_token::id(),
+            account,
+            destination,
+            &self.owner,
+            &self.multisigner_refs(),
+        )?];
+        Ok(TokenOperation::single(0, instructions))
+    }
+
+    /// Consolidate the owner's balances into their associated token accounts
+    /// and close the auxiliary accounts the owner can close.  Frozen accounts
+    /// are left alone.  Returns the mints that were processed.
+    pub async fn gc(&self) -> TokenResult<(Vec<Pubkey>, TokenOperation)> {
+        let accounts = self.client.get_token_accounts_by_owner(&self.owner).await?;
+        let mut mints = vec![];
+        let mut operation = TokenOperation::default();
+        if accounts.is_empty() {
+            return Ok((mints, operation));
+        }
+
+        let minimum_balance_for_rent_exemption = self
+            .minimum_balance_for_rent_exemption(Account::LEN)
+            .await?;
+
+        let mut accounts_by_token = HashMap::new();
+        for (address, account) in accounts {
+            if let Ok(token_account) = Account::unpack(&account.data) {
+                let frozen = token_account.state == AccountState::Frozen;
+                let close_authority = token_account.close_authority.unwrap_or(self.owner);
+                accounts_by_token
+                    .entry(token_account.mint)
+                    .or_insert_with(HashMap::new)
+                    .insert(address, (token_account.amount, frozen, close_authority));
+            }
+        }
+
+        for (token, accounts) in accounts_by_token.into_iter() {
+            mints.push(token);
+            let decimals = self.get_mint(&token).await?.decimals;
+            let associated_token_account = get_associated_token_address(&self.owner, &token);
+            let total_balance: u64 = accounts.values().map(|account| account.0).sum();
+
+            if total_balance > 0 && !accounts.contains_key(&associated_token_account) {
+                // Create the associated token account
+                operation
+                    .transactions
+                    .push(vec![create_associated_token_account(
+                        &self.fee_payer,
+                        &self.owner,
+                        &token,
+                    )]);
+                operation.lamports += minimum_balance_for_rent_exemption;
+            }
+
+            for (address, (amount, frozen, close_authority)) in accounts {
+                if address == associated_token_account {
+                    // leave the associated token account alone
+                    continue;
+                }
+
+                if frozen {
+                    // leave frozen accounts alone
+                    continue;
+                }
+
+                let mut account_instructions = vec![];
+
+                // Transfer the account balance into the associated token account
+                if amount > 0 {
+                    account_instructions.push(transfer_checked(
+                        &spl_token::id(),
+                        &address,
+                        &token,
+                        &associated_token_account,
+                        &self.owner,
+                        &self.multisigner_refs(),
+                        amount,
+                        decimals,
+                    )?);
+                }
+                // Close the account if the owner is able to
+                if close_authority == self.owner {
+                    account_instructions.push(close_account(
+                        &spl_token::id(),
+                        &address,
+                        &self.owner,
+                        &self.owner,
+                        &self.multisigner_refs(),
+                    )?);
+                }
+
+                if !account_instructions.is_empty() {
+                    operation.transactions.push(account_instructions);
+                }
+            }
+        }
+
+        Ok((mints, operation))
+    }
+
+    /// Sign each transaction of `operation` with a recent blockhash, or the
+    /// durable nonce if one is set, and submit it, returning the signatures in
+    /// order.  A nonce can only be used for an operation of one transaction.
+    pub async fn send<S: Signers>(
+        &self,
+        operation: TokenOperation,
+        signers: &S,
+    ) -> TokenResult<Vec<Signature>> {
+        let TokenOperation {
+            lamports,
+            transactions,
+        } = operation;
+        if self.nonce.is_some() && transactions.len() > 1 {
+            return Err(TokenClientError::NonceMultipleTransactions(
+                transactions.len(),
+            ));
+        }
+
+        let mut signatures = vec![];
+        for instructions in transactions {
+            let (message, (recent_blockhash, fee_calculator)) =
+                if let Some((nonce_account, nonce_authority)) = self.nonce.as_ref() {
+                    (
+                        Message::new_with_nonce(
+                            instructions,
+                            Some(&self.fee_payer),
+                            nonce_account,
+                            nonce_authority,
+                        ),
+                        self.get_nonce_data(nonce_account, nonce_authority).await?,
+                    )
+                } else {
+                    (
+                        Message::new(&instructions, Some(&self.fee_payer)),
+                        self.client.get_recent_blockhash().await?,
+                    )
+                };
+            self.check_fee_payer_balance(lamports + fee_calculator.calculate_fee(&message))
+                .await?;
+
+            let mut transaction = Transaction::new_unsigned(message);
+            transaction
+                .try_sign(signers, recent_blockhash)
+                .map_err(|err| TokenClientError::Client(Box::new(err)))?;
+            signatures.push(self.client.send_transaction(&transaction).await?);
+        }
+        Ok(signatures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use solana_sdk::{
+        account::Account as BaseAccount,
+        nonce::{
+            state::{Data, Versions},
+            State,
+        },
+        signature::{Keypair, Signer},
+    };
+    use std::sync::Mutex;
+
+    const RENT_PER_BYTE: u64 = 10;
+
+    /// In-memory cluster holding a fixed set of accounts
+    #[derive(Default)]
+    struct MockClient {
+        accounts: HashMap<Pubkey, BaseAccount>,
+        sent: Mutex<Vec<Transaction>>,
+    }
+
+    impl MockClient {
+        fn add_lamports(&mut self, address: Pubkey, lamports: u64) {
+            self.accounts.insert(
+                address,
+                BaseAccount::new(lamports, 0, &system_program::id()),
+            );
+        }
+
+        fn add_mint(&mut self, address: Pubkey, decimals: u8) {
+            let mut account = BaseAccount::new(0, Mint::LEN, &spl_token::id());
+            let mint = Mint {
+                decimals,
+                is_initialized: true,
+                ..Mint::default()
+            };
+            Mint::pack(mint, &mut account.data).unwrap();
+            self.accounts.insert(address, account);
+        }
+
+        fn add_token_account(&mut self, address: Pubkey, token_account: Account) {
+            let mut account = BaseAccount::new(0, Account::LEN, &spl_token::id());
+            Account::pack(token_account, &mut account.data).unwrap();
+            self.accounts.insert(address, account);
+        }
+
+        fn add_nonce_account(&mut self, address: Pubkey, authority: Pubkey, blockhash: Hash) {
+            let state = Versions::new_current(State::Initialized(Data {
+                authority,
+                blockhash,
+                fee_calculator: FeeCalculator::default(),
+            }));
+            let account = BaseAccount::new_data(1, &state, &system_program::id()).unwrap();
+            self.accounts.insert(address, account);
+        }
+    }
+
+    #[async_trait]
+    impl ProgramClient for MockClient {
+        async fn get_account(
+            &self,
+            address: &Pubkey,
+        ) -> Result<Option<BaseAccount>, TokenClientError> {
+            Ok(self.accounts.get(address).cloned())
+        }
+
+        async fn get_balance(&self, address: &Pubkey) -> Result<u64, TokenClientError> {
+            Ok(self
+                .accounts
+                .get(address)
+                .map_or(0, |account| account.lamports))
+        }
+
+        async fn get_minimum_balance_for_rent_exemption(
+            &self,
+            data_len: usize,
+        ) -> Result<u64, TokenClientError> {
+            Ok(data_len as u64 * RENT_PER_BYTE)
+        }
+
+        async fn get_token_accounts_by_owner(
+            &self,
+            owner: &Pubkey,
+        ) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError> {
+            Ok(self
+                .accounts
+                .iter()
+                .filter(|(_, account)| account.owner == spl_token::id())
+                .filter(|(_, account)| {
+                    Account::unpack(&account.data)
+                        .map_or(false, |token_account| token_account.owner == *owner)
+                })
+                .map(|(address, account)| (*address, account.clone()))
+                .collect())
+        }
+
+        async fn get_token_accounts_by_delegate(
+            &self,
+            delegate: &Pubkey,
+            mint: Option<&Pubkey>,
+        ) -> Result<Vec<(Pubkey, BaseAccount)>, TokenClientError> {
+            Ok(self
+                .accounts
+                .iter()
+                .filter(|(_, account)| account.owner == spl_token::id())
+                .filter(|(_, account)| {
+                    Account::unpack(&account.data).map_or(false, |token_account| {
+                        token_account.delegate == COption::Some(*delegate)
+                            && mint.map_or(true, |mint| token_account.mint == *mint)
+                    })
+                })
+                .map(|(address, account)| (*address, account.clone()))
+                .collect())
+        }
+
+        async fn get_recent_blockhash(&self) -> Result<(Hash, FeeCalculator), TokenClientError> {
+            Ok((Hash::default(), FeeCalculator::default()))
+        }
+
+        async fn send_transaction(
+            &self,
+            transaction: &Transaction,
+        ) -> Result<Signature, TokenClientError> {
+            self.sent.lock().unwrap().push(transaction.clone());
+            Ok(transaction.signatures[0])
+        }
+    }
+
+    fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+        Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..Account::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn create_token_with_authorities() {
+        let owner = Pubkey::new_unique();
+        let token = Pubkey::new_unique();
+        let mint_authority = Pubkey::new_unique();
+        let freeze_authority = Pubkey::new_unique();
+        let token_client = TokenClient::new(MockClient::default(), owner, owner);
+
+        let operation = token_client
+            .create_token(
+                &token,
+                2,
+                &mint_authority,
+                Some(&freeze_authority),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            operation,
+            TokenOperation::single(
+                Mint::LEN as u64 * RENT_PER_BYTE,
+                vec![
+                    system_instruction::create_account(
+                        &owner,
+                        &token,
+                        Mint::LEN as u64 * RENT_PER_BYTE,
+                        Mint::LEN as u64,
+                        &spl_token::id(),
+                    ),
+                    initialize_mint(
+                        &spl_token::id(),
+                        &token,
+                        &mint_authority,
+                        Some(&freeze_authority),
+                        2,
+                    )
+                    .unwrap(),
+                ],
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn create_token_mints_initial_supply_before_handing_over_authority() {
+        let owner = Pubkey::new_unique();
+        let token = Pubkey::new_unique();
+        let mint_authority = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let recipient_token_account = get_associated_token_address(&recipient, &token);
+        let token_client = TokenClient::new(MockClient::default(), owner, owner);
+
+        let create_and_mint = |final_authority: Option<&Pubkey>| {
+            vec![
+                system_instruction::create_account(
+                    &owner,
+                    &token,
+                    Mint::LEN as u64 * RENT_PER_BYTE,
+                    Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                initialize_mint(&spl_token::id(), &token, &owner, None, 2).unwrap(),
+                create_associated_token_account(&owner, &recipient, &token),
+                mint_to_checked(
+                    &spl_token::id(),
+                    &token,
+                    &recipient_token_account,
+                    &owner,
+                    &[],
+                    500,
+                    2,
+                )
+                .unwrap(),
+                set_authority(
+                    &spl_token::id(),
+                    &token,
+                    final_authority,
+                    AuthorityType::MintTokens,
+                    &owner,
+                    &[],
+                )
+                .unwrap(),
+            ]
+        };
+        let lamports = (Mint::LEN + Account::LEN) as u64 * RENT_PER_BYTE;
+
+        // the owner mints the supply, then passes the mint authority on
+        let operation = token_client
+            .create_token(
+                &token,
+                2,
+                &mint_authority,
+                None,
+                Some((500, recipient)),
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            operation,
+            TokenOperation::single(lamports, create_and_mint(Some(&mint_authority)))
+        );
+
+        // or disables minting altogether
+        let operation = token_client
+            .create_token(&token, 2, &owner, None, Some((500, recipient)), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            operation,
+            TokenOperation::single(lamports, create_and_mint(None))
+        );
+
+        // an owner keeping the mint authority needs no handoff
+        let operation = token_client
+            .create_token(&token, 2, &owner, None, Some((500, recipient)), false)
+            .await
+            .unwrap();
+        let mut instructions = create_and_mint(None);
+        instructions.pop();
+        assert_eq!(operation, TokenOperation::single(lamports, instructions));
+    }
+
+    #[tokio::test]
+    async fn transfer_to_wallet_without_associated_account() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let mut client = MockClient::default();
+        client.add_mint(mint, 2);
+        client.add_token_account(sender, token_account(&mint, &owner, 100));
+        client.add_lamports(recipient, 1);
+        let token_client = TokenClient::new(client, owner, owner);
+
+        // the recipient's associated token account must be funded
+        let result = token_client
+            .transfer(&sender, &mint, 2, Some(10), &recipient, false, false)
+            .await;
+        let recipient_token_account = get_associated_token_address(&recipient, &mint);
+        assert!(matches!(
+            result,
+            Err(TokenClientError::RecipientNotFunded(address)) if address == recipient_token_account
+        ));
+
+        let transfer = token_client
+            .transfer(&sender, &mint, 2, Some(10), &recipient, false, true)
+            .await
+            .unwrap();
+        assert_eq!(transfer.recipient_token_account, recipient_token_account);
+        assert!(transfer.is_associated);
+        assert!(transfer.funds_recipient);
+        assert_eq!(
+            transfer.operation,
+            TokenOperation::single(
+                Account::LEN as u64 * RENT_PER_BYTE,
+                vec![
+                    create_associated_token_account(&owner, &recipient, &mint),
+                    transfer_checked(
+                        &spl_token::id(),
+                        &sender,
+                        &mint,
+                        &recipient_token_account,
+                        &owner,
+                        &[],
+                        10,
+                        2,
+                    )
+                    .unwrap(),
+                ],
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn transfer_to_existing_associated_account() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+        let recipient_token_account = get_associated_token_address(&recipient, &mint);
+        let mut client = MockClient::default();
+        client.add_mint(mint, 2);
+        client.add_token_account(sender, token_account(&mint, &owner, 100));
+        client.add_token_account(recipient_token_account, token_account(&mint, &recipient, 0));
+        let token_client = TokenClient::new(client, owner, owner);
+
+        // the wallet itself holds no lamports, so it must be allowed to be empty
+        let result = token_client
+            .transfer(&sender, &mint, 2, None, &recipient, false, true)
+            .await;
+        assert!(matches!(
+            result,
+            Err(TokenClientError::EmptyRecipient(address)) if address == recipient
+        ));
+
+        let transfer = token_client
+            .transfer(&sender, &mint, 2, None, &recipient, true, true)
+            .await
+            .unwrap();
+        assert_eq!(transfer.amount, 100);
+        assert_eq!(transfer.recipient_token_account, recipient_token_account);
+        assert!(transfer.is_associated);
+        assert!(!transfer.funds_recipient);
+        assert_eq!(transfer.operation.lamports, 0);
+        assert_eq!(transfer.operation.transactions[0].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn wrap_and_close() {
+        let owner = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let mut client = MockClient::default();
+        client.add_lamports(owner, 1_000);
+        let token_client = TokenClient::new(client, owner, owner);
+
+        let result = token_client.wrap(&account, 1_001).await;
+        assert!(matches!(
+            result,
+            Err(TokenClientError::InsufficientBalance("Owner", address, _, _)) if address == owner
+        ));
+
+        let operation = token_client.wrap(&account, 1_000).await.unwrap();
+        assert_eq!(
+            operation,
+            TokenOperation::single(
+                0,
+                vec![
+                    system_instruction::create_account(
+                        &owner,
+                        &account,
+                        1_000,
+                        Account::LEN as u64,
+                        &spl_token::id(),
+                    ),
+                    initialize_account(&spl_token::id(), &account, &native_mint::id(), &owner)
+                        .unwrap(),
+                ],
+            )
+        );
+
+        // offline, the owner's balance is not checked
+        let token_client = token_client.with_offline(true);
+        assert!(token_client.wrap(&account, 1_001).await.is_ok());
+
+        let operation = token_client.unwrap(&account).await.unwrap();
+        assert_eq!(
+            operation,
+            TokenOperation::single(
+                0,
+                vec![close_account(&spl_token::id(), &account, &owner, &owner, &[]).unwrap()],
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn close_requires_empty_account() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let native_account = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let mut client = MockClient::default();
+        client.add_mint(mint, 2);
+        client.add_token_account(account, token_account(&mint, &owner, 150));
+        client.add_token_account(
+            native_account,
+            Account {
+                is_native: COption::Some(0),
+                ..token_account(&native_mint::id(), &owner, 150)
+            },
+        );
+        let token_client = TokenClient::new(client, owner, owner);
+
+        let result = token_client.close(&account, &destination).await;
+        assert!(matches!(
+            result,
+            Err(TokenClientError::AccountNotEmpty(address, amount))
+                if address == account && (amount - 1.5).abs() < f64::EPSILON
+        ));
+
+        // native accounts give their tokens back as lamports when closed
+        let operation = token_client
+            .close(&native_account, &destination)
+            .await
+            .unwrap();
+        assert_eq!(
+            operation,
+            TokenOperation::single(
+                0,
+                vec![
+                    close_account(&spl_token::id(), &native_account, &destination, &owner, &[],)
+                        .unwrap()
+                ],
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn revoke_all_in_batches() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let other_delegate = Pubkey::new_unique();
+        let mut client = MockClient::default();
+        let delegated_accounts = REVOKE_BATCH_SIZE + 4;
+        for _ in 0..delegated_accounts {
+            client.add_token_account(
+                Pubkey::new_unique(),
+                Account {
+                    delegate: COption::Some(delegate),
+                    ..token_account(&mint, &owner, 1)
+                },
+            );
+        }
+        client.add_token_account(
+            Pubkey::new_unique(),
+            Account {
+                delegate: COption::Some(other_delegate),
+                ..token_account(&other_mint, &owner, 1)
+            },
+        );
+        // accounts without a delegate, or of another owner, are left alone
+        client.add_token_account(Pubkey::new_unique(), token_account(&mint, &owner, 1));
+        client.add_token_account(
+            Pubkey::new_unique(),
+            Account {
+                delegate: COption::Some(delegate),
+                ..token_account(&mint, &Pubkey::new_unique(), 1)
+            },
+        );
+        let token_client = TokenClient::new(client, owner, owner);
+
+        let (revoked, operation) = token_client.revoke_all(None, None).await.unwrap();
+        assert_eq!(revoked.len(), delegated_accounts + 1);
+        assert_eq!(operation.lamports, 0);
+        assert_eq!(operation.transactions.len(), 2);
+        assert_eq!(operation.transactions[0].len(), REVOKE_BATCH_SIZE);
+        assert_eq!(operation.transactions[1].len(), 5);
+        for ((account, _), instruction) in
+            revoked.iter().zip(operation.transactions.iter().flatten())
+        {
+            assert_eq!(
+                *instruction,
+                revoke(&spl_token::id(), account, &owner, &[]).unwrap()
+            );
+        }
+
+        let (revoked, operation) = token_client
+            .revoke_all(Some(&delegate), None)
+            .await
+            .unwrap();
+        assert_eq!(revoked.len(), delegated_accounts);
+        assert!(revoked.iter().all(|(_, revoked)| *revoked == delegate));
+        assert_eq!(operation.transactions.len(), 2);
+
+        let (revoked, operation) = token_client
+            .revoke_all(None, Some(&other_mint))
+            .await
+            .unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].1, other_delegate);
+        assert_eq!(
+            operation.transactions,
+            vec![vec![
+                revoke(&spl_token::id(), &revoked[0].0, &owner, &[]).unwrap()
+            ]]
+        );
+
+        let (revoked, operation) = token_client
+            .revoke_all(Some(&delegate), Some(&other_mint))
+            .await
+            .unwrap();
+        assert!(revoked.is_empty());
+        assert_eq!(operation, TokenOperation::default());
+    }
+
+    #[tokio::test]
+    async fn allowances_of_delegate() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let other_account = Pubkey::new_unique();
+        let mut client = MockClient::default();
+        client.add_mint(mint, 2);
+        client.add_mint(other_mint, 6);
+        client.add_token_account(
+            account,
+            Account {
+                delegate: COption::Some(delegate),
+                delegated_amount: 50,
+                ..token_account(&mint, &owner, 100)
+            },
+        );
+        client.add_token_account(
+            other_account,
+            Account {
+                delegate: COption::Some(delegate),
+                delegated_amount: 7,
+                ..token_account(&other_mint, &owner, 10)
+            },
+        );
+        client.add_token_account(
+            Pubkey::new_unique(),
+            Account {
+                delegate: COption::Some(Pubkey::new_unique()),
+                delegated_amount: 1,
+                ..token_account(&mint, &owner, 1)
+            },
+        );
+        let token_client = TokenClient::new(client, owner, owner);
+
+        let allowances = token_client
+            .allowances(&delegate, Some(&mint))
+            .await
+            .unwrap();
+        assert_eq!(
+            allowances,
+            vec![Allowance {
+                address: account,
+                mint,
+                owner,
+                delegated_amount: 50,
+                decimals: 2,
+            }]
+        );
+
+        let mut allowances = token_client.allowances(&delegate, None).await.unwrap();
+        allowances.sort_by_key(|allowance| allowance.decimals);
+        assert_eq!(allowances.len(), 2);
+        assert_eq!(allowances[1].address, other_account);
+        assert_eq!(allowances[1].delegated_amount, 7);
+        assert_eq!(allowances[1].decimals, 6);
+    }
+
+    #[tokio::test]
+    async fn send_with_nonce() {
+        let payer = Keypair::new();
+        let owner = payer.pubkey();
+        let nonce_account = Pubkey::new_unique();
+        let blockhash = Hash::new_unique();
+        let account = Pubkey::new_unique();
+        let mut client = MockClient::default();
+        client.add_lamports(owner, 1_000);
+        client.add_nonce_account(nonce_account, owner, blockhash);
+        let operation = || {
+            TokenOperation::single(
+                0,
+                vec![close_account(&spl_token::id(), &account, &owner, &owner, &[]).unwrap()],
+            )
+        };
+
+        // the nonce must be advanced by its authority
+        let other_authority = Pubkey::new_unique();
+        let token_client =
+            TokenClient::new(client, owner, owner).with_nonce(nonce_account, other_authority);
+        assert!(matches!(
+            token_client.send(operation(), &[&payer]).await,
+            Err(TokenClientError::InvalidNonceAuthority(address, authority, expected))
+                if address == nonce_account && authority == owner && expected == other_authority
+        ));
+
+        let token_client = token_client.with_nonce(nonce_account, owner);
+        let mut batches = operation();
+        batches.transactions.push(batches.transactions[0].clone());
+        assert!(matches!(
+            token_client.send(batches, &[&payer]).await,
+            Err(TokenClientError::NonceMultipleTransactions(2))
+        ));
+        assert!(token_client.client().sent.lock().unwrap().is_empty());
+
+        let signatures = token_client.send(operation(), &[&payer]).await.unwrap();
+        let sent = token_client.client().sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(signatures, vec![sent[0].signatures[0]]);
+        let mut message = Message::new_with_nonce(
+            operation().transactions.remove(0),
+            Some(&owner),
+            &nonce_account,
+            &owner,
+        );
+        message.recent_blockhash = blockhash;
+        assert_eq!(sent[0].message, message);
+        sent[0].verify().unwrap();
+    }
+}